@@ -0,0 +1,90 @@
+//! Regenerates the files in `vectors/v1` from the cases below.
+//!
+//! Run with `cargo run --example generate_vectors`. Published versions must not change, so
+//! only do this while preparing a new version.
+
+use simple_smtp::{
+    canonicalization::Canonicalization::{Relaxed, Simple},
+    vectors::{self, Operation, Suite, Vector},
+};
+
+const DOT_STUFFING: &[(&str, &[u8])] = &[
+    ("empty message", b""),
+    ("no dots", b"Subject: hi\r\n\r\nhello\r\n"),
+    ("leading dot", b".hidden\r\n"),
+    ("line of a single dot", b"a\r\n.\r\nb\r\n"),
+    ("two leading dots", b"..\r\n"),
+    ("dot inside a line", b"a.b\r\n"),
+    ("no final line break", b"a\r\n.b"),
+    ("bare line feed", b"a\n.b\r\n"),
+    ("bare carriage return", b"a\r.b\r\n"),
+];
+
+const HEADERS: &[(&str, &[u8])] = &[
+    ("rfc 6376 example a", b"A: X\r\n"),
+    ("rfc 6376 example b", b"B : Y\t\r\n\tZ  \r\n"),
+    ("mixed case name", b"Subject: Hello\r\n"),
+    ("whitespace runs", b"To:  a@b.example ,\t c@d.example\r\n"),
+    ("empty value", b"X-Empty:\r\n"),
+    ("folded twice", b"Received: from a\r\n by b\r\n\tfor c\r\n"),
+];
+
+const BODIES: &[(&str, &[u8])] = &[
+    ("empty body", b""),
+    ("rfc 6376 example", b" C \r\nD \t E\r\n\r\n\r\n"),
+    ("only empty lines", b"\r\n\r\n"),
+    ("whitespace lines", b" \r\n\t\r\n"),
+    ("no final line break", b"hello"),
+    ("trailing whitespace", b"a \t\r\nb\r\n"),
+    ("empty line inside", b"a\r\n\r\nb\r\n"),
+];
+
+const ENCODED_WORDS: &[(&str, &str)] = &[
+    ("empty text", ""),
+    ("ascii", "Hello"),
+    ("latin", "Grüße aus Köln"),
+    ("cjk", "日本語の件名"),
+    ("emoji", "📬 new mail"),
+    (
+        "split into words",
+        "Ünïcödé subjects longer than one encoded word are split between characters",
+    ),
+    ("multi-byte at the split", "ää€€€€€€€€€€€€€€"),
+];
+
+fn main() -> std::io::Result<()> {
+    let dot_stuffing = DOT_STUFFING
+        .iter()
+        .map(|(name, input)| Vector::generate(name, Operation::DotStuff, input))
+        .collect::<Vec<_>>();
+
+    let mut canonicalization = Vec::new();
+    for canonicalization_mode in [Simple, Relaxed] {
+        for (name, input) in HEADERS {
+            let operation = Operation::Header(canonicalization_mode);
+            canonicalization.push(Vector::generate(name, operation, input));
+        }
+        for (name, input) in BODIES {
+            let operation = Operation::Body(canonicalization_mode);
+            canonicalization.push(Vector::generate(name, operation, input));
+        }
+    }
+
+    let encoded_words = ENCODED_WORDS
+        .iter()
+        .map(|(name, input)| Vector::generate(name, Operation::EncodedWord, input.as_bytes()))
+        .collect::<Vec<_>>();
+
+    let dir = format!("vectors/v{}", vectors::VERSION);
+    std::fs::create_dir_all(&dir)?;
+    for (suite, vectors) in [
+        (Suite::DotStuffing, dot_stuffing),
+        (Suite::Canonicalization, canonicalization),
+        (Suite::EncodedWord, encoded_words),
+    ] {
+        let path = format!("{dir}/{}", suite.file_name());
+        std::fs::write(&path, vectors::format(suite, &vectors))?;
+        println!("wrote {} vectors to {path}", vectors.len());
+    }
+    Ok(())
+}
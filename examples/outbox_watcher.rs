@@ -0,0 +1,153 @@
+//! Watches a drop directory and submits what appears in it, the classic sendmail pattern.
+//!
+//! Every `<id>.envelope` file next to an `<id>.eml` file is a message to send, in the format
+//! [`FileTransport`](simple_smtp::transport::FileTransport) writes, see
+//! [`StoredSubmission`]. Sent messages are moved to `sent/`, permanently rejected ones to
+//! `failed/`. Anything else stays, and is retried on the next scan.
+//!
+//! ```text
+//! SMTP_USER=me SMTP_PASSWORD=secret cargo run --example outbox_watcher -- outbox smtp.example.com
+//! ```
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use simple_smtp::{
+    Error,
+    envelope::{Envelope, Recipient},
+    integrations::tokio::{ClientSession, SmtpClientBuilder},
+    smtp::auth::AuthMode,
+    transport::StoredSubmission,
+};
+
+const SCAN_INTERVAL: Duration = Duration::from_secs(5);
+// files modified more recently may still be being written
+const SETTLE_TIME: Duration = Duration::from_secs(1);
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let (Some(dir), Some(host)) = (args.next(), args.next()) else {
+        anyhow::bail!("usage: outbox_watcher <directory> <relay host>");
+    };
+    let dir = PathBuf::from(dir);
+    let (user, password) = (env::var("SMTP_USER").ok(), env::var("SMTP_PASSWORD").ok());
+    let auth = match (&user, &password) {
+        (Some(username), Some(password)) => AuthMode::Plain { username, password },
+        _ => AuthMode::None,
+    };
+    for done in ["sent", "failed"] {
+        fs::create_dir_all(dir.join(done))?;
+    }
+
+    let mut interval = tokio::time::interval(SCAN_INTERVAL);
+    loop {
+        interval.tick().await;
+        let pending = pending_submissions(&dir)?;
+        if pending.is_empty() {
+            continue;
+        }
+        // one connection per batch, the relay may close idle ones anyway
+        let mut smtp = match SmtpClientBuilder::new(&host)
+            .with_auth(auth)
+            .connect()
+            .await
+        {
+            Ok(smtp) => smtp,
+            Err(e) => {
+                eprintln!("connecting to {host} failed, retrying later: {e}");
+                continue;
+            }
+        };
+        for id in pending {
+            match submit(&mut smtp, &dir, &id).await {
+                Ok(()) => move_submission(&dir, &id, "sent"),
+                Err(Submission::Unreadable(e)) => eprintln!("{id}: will retry: {e}"),
+                Err(Submission::Invalid(e)) => {
+                    eprintln!("{id}: {e:#}");
+                    move_submission(&dir, &id, "failed");
+                }
+                Err(Submission::Rejected(e)) if e.is_permanent() => {
+                    eprintln!("{id}: rejected: {e}");
+                    move_submission(&dir, &id, "failed");
+                }
+                Err(Submission::Rejected(e)) => {
+                    eprintln!("{id}: will retry: {e}");
+                    if !e.is_session_usable() {
+                        break;
+                    }
+                }
+            }
+        }
+        let _ = smtp.quit().await;
+    }
+}
+
+enum Submission {
+    /// The files can't be read right now, e.g. for lack of permissions or file handles.
+    Unreadable(io::Error),
+    /// The envelope can't be parsed, retrying won't help.
+    Invalid(anyhow::Error),
+    Rejected(Error<io::Error>),
+}
+
+async fn submit(smtp: &mut ClientSession, dir: &Path, id: &str) -> Result<(), Submission> {
+    let stored = read_submission(dir, id)?;
+    let recipients: Vec<Recipient<'_>> = stored
+        .envelope
+        .recipients
+        .iter()
+        .map(|rcpt| Recipient::new(rcpt))
+        .collect();
+    let envelope = Envelope::new(&stored.envelope.from, &recipients);
+    smtp.send_envelope(&envelope, &stored.message)
+        .await
+        .map_err(Submission::Rejected)
+}
+
+fn read_submission(dir: &Path, id: &str) -> Result<StoredSubmission, Submission> {
+    let sidecar = fs::read(dir.join(format!("{id}.envelope"))).map_err(Submission::Unreadable)?;
+    let (envelope, _) = StoredSubmission::decode_envelope(&sidecar)
+        .with_context(|| format!("reading {id}.envelope"))
+        .map_err(Submission::Invalid)?;
+    let message = fs::read(dir.join(format!("{id}.eml"))).map_err(Submission::Unreadable)?;
+    Ok(StoredSubmission { envelope, message })
+}
+
+// the ids of complete submissions, oldest first
+fn pending_submissions(dir: &Path) -> io::Result<Vec<String>> {
+    let settled = SystemTime::now() - SETTLE_TIME;
+    let mut ids = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension() != Some("envelope".as_ref()) {
+            continue;
+        }
+        let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let modified = fs::metadata(dir.join(format!("{id}.eml"))).and_then(|m| m.modified());
+        if modified.is_ok_and(|modified| modified < settled) {
+            ids.push(id.to_owned());
+        }
+    }
+    // `FileTransport` ids start with a timestamp
+    ids.sort();
+    Ok(ids)
+}
+
+// logs a failed move instead of stopping the watcher, the submission is then seen again on
+// the next scan
+fn move_submission(dir: &Path, id: &str, to: &str) {
+    for extension in ["envelope", "eml"] {
+        let name = format!("{id}.{extension}");
+        if let Err(e) = fs::rename(dir.join(&name), dir.join(to).join(&name)) {
+            eprintln!("{id}: moving {name} to {to}/ failed: {e}");
+            return;
+        }
+    }
+}
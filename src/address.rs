@@ -0,0 +1,611 @@
+//! Checking whether a mailbox address is worth sending mail to.
+//!
+//! There are three levels of confidence, each more expensive than the last:
+//! 1. [`Level::Syntax`]: the address is a valid RFC 5321 mailbox.
+//! 2. [`Level::MailHost`]: its domain has a host that accepts mail, checked through a
+//!    [`Resolver`].
+//! 3. An SMTP [`callout`]: the responsible server accepts the address in `RCPT TO`.
+//!
+//! None of these prove that a person reads the mailbox; only a confirmation mail does that.
+//!
+//! [`Mailbox`] parses an address together with its display name, as written in the `From`
+//! and `To` headers of a message, and [`AddressList`] a comma separated list of them and
+//! of named groups.
+//! `group_by_domain` sorts recipients by the domain which receives their mail, and
+//! `Normalization` tells whether two addresses reach the same mailbox, and `EmailAddress`
+//! keeps an address beyond the string it was parsed from.
+
+use core::{
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use crate::{
+    Error, ReadWrite, Smtp,
+    envelope::{Envelope, Recipient},
+    smtp::{ReplyCode, SessionState, enhanced::EnhancedCode},
+};
+
+#[cfg(feature = "alloc")]
+mod group;
+#[cfg(feature = "alloc")]
+pub use group::{DomainGroup, group_by_domain, normalize_domain};
+#[cfg(feature = "alloc")]
+mod normalization;
+#[cfg(feature = "alloc")]
+pub use normalization::Normalization;
+#[cfg(feature = "alloc")]
+mod owned;
+#[cfg(feature = "alloc")]
+pub use owned::EmailAddress;
+mod mailbox;
+pub use mailbox::{
+    AddressList, AddressListIter, DisplayName, Group, ListEntries, ListEntry, Mailbox,
+};
+
+/// Why an address isn't a valid mailbox.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxError {
+    MissingAt,
+    EmptyLocalPart,
+    /// The local part is longer than 64 octets.
+    /// <https://datatracker.ietf.org/doc/html/rfc5321#section-4.5.3.1.1>
+    LocalPartTooLong,
+    InvalidLocalPart,
+    EmptyDomain,
+    /// The domain is longer than 255 octets, or one of its labels longer than 63.
+    /// <https://datatracker.ietf.org/doc/html/rfc5321#section-4.5.3.1.2>
+    DomainTooLong,
+    InvalidDomain,
+    InvalidAddressLiteral,
+    /// The address doesn't fit in a 256 octet path, including the angle brackets.
+    /// <https://datatracker.ietf.org/doc/html/rfc5321#section-4.5.3.1.3>
+    TooLong,
+    /// The display name of a [`Mailbox`] contains something other than words and quoted
+    /// strings, or a quote isn't closed.
+    InvalidDisplayName,
+    /// A comment isn't closed, or contains a line break.
+    InvalidComment,
+    UnclosedAngleBracket,
+    /// Something other than a comment follows the address.
+    TrailingText,
+    /// A group in an [`AddressList`] isn't closed with `;`.
+    UnclosedGroup,
+    /// The address can't be converted to ASCII, as only the domain has an ASCII form.
+    LocalPartNotAscii,
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let msg = match self {
+            SyntaxError::MissingAt => "missing '@'",
+            SyntaxError::EmptyLocalPart => "empty local part",
+            SyntaxError::LocalPartTooLong => "local part too long",
+            SyntaxError::InvalidLocalPart => "invalid local part",
+            SyntaxError::EmptyDomain => "empty domain",
+            SyntaxError::DomainTooLong => "domain too long",
+            SyntaxError::InvalidDomain => "invalid domain",
+            SyntaxError::InvalidAddressLiteral => "invalid address literal",
+            SyntaxError::TooLong => "address too long",
+            SyntaxError::InvalidDisplayName => "invalid display name",
+            SyntaxError::InvalidComment => "invalid comment",
+            SyntaxError::UnclosedAngleBracket => "missing '>'",
+            SyntaxError::TrailingText => "unexpected text after the address",
+            SyntaxError::UnclosedGroup => "missing ';' after the group",
+            SyntaxError::LocalPartNotAscii => "local part is not ASCII",
+        };
+        write!(f, "Invalid address: {msg}")
+    }
+}
+
+impl core::error::Error for SyntaxError {}
+
+/// A syntactically valid mailbox, `local-part@domain`.
+///
+/// Borrows the string it was parsed from and only remembers where the `@` is, so addresses
+/// can be validated and split without `alloc`. Convert it into an `EmailAddress` to keep
+/// it beyond the string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Address<'a> {
+    raw: &'a str,
+    at: usize,
+}
+
+/// The borrowed form of an `EmailAddress`, the same type as [`Address`].
+pub type AddrRef<'a> = Address<'a>;
+
+impl<'a> Address<'a> {
+    /// Checks `address` against the `Mailbox` grammar of RFC 5321, extended with UTF-8 as
+    /// allowed by RFC 6531.
+    /// <https://datatracker.ietf.org/doc/html/rfc5321#section-4.1.2>
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use simple_smtp::address::{Address, SyntaxError};
+    ///
+    /// let address = Address::parse("\"john doe\"@example.com").unwrap();
+    /// assert_eq!(address.domain(), "example.com");
+    /// assert_eq!(Address::parse("john..doe@example.com"), Err(SyntaxError::InvalidLocalPart));
+    /// ```
+    pub fn parse(address: &'a str) -> Result<Address<'a>, SyntaxError> {
+        // the domain can't contain an '@', but a quoted local part can
+        let at = address.rfind('@').ok_or(SyntaxError::MissingAt)?;
+        let (local_part, domain) = (&address[..at], &address[at + 1..]);
+        if address.len() + 2 > 256 {
+            return Err(SyntaxError::TooLong);
+        }
+        validate_local_part(local_part)?;
+        validate_domain(domain)?;
+        Ok(Address { raw: address, at })
+    }
+
+    pub fn local_part(&self) -> &'a str {
+        &self.raw[..self.at]
+    }
+
+    pub fn domain(&self) -> &'a str {
+        &self.raw[self.at + 1..]
+    }
+
+    pub fn as_str(&self) -> &'a str {
+        self.raw
+    }
+
+    /// Returns true if the domain is an address literal like `[192.0.2.1]` rather than a
+    /// domain name.
+    pub fn is_address_literal(&self) -> bool {
+        self.domain().starts_with('[')
+    }
+
+    /// Splits the local part into the user and the detail of a subaddress, as in
+    /// `user+detail@example.com` with `+` as `separator`. The detail starts after the first
+    /// separator.
+    ///
+    /// Which separator, if any, a domain uses is up to its server. Quoted local parts and
+    /// ones starting with the separator have no detail.
+    /// <https://datatracker.ietf.org/doc/html/rfc5233#section-1>
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use simple_smtp::address::Address;
+    ///
+    /// let address = Address::parse("alice+lists+rust@example.com").unwrap();
+    /// assert_eq!(address.split_detail('+'), ("alice", Some("lists+rust")));
+    /// assert_eq!(address.split_detail('-'), ("alice+lists+rust", None));
+    /// ```
+    pub fn split_detail(&self, separator: char) -> (&'a str, Option<&'a str>) {
+        let local_part = self.local_part();
+        if local_part.starts_with('"') {
+            return (local_part, None);
+        }
+        match local_part.split_once(separator) {
+            Some((user, detail)) if !user.is_empty() => (user, Some(detail)),
+            _ => (local_part, None),
+        }
+    }
+
+    /// The detail of a subaddress, see [`split_detail`](Self::split_detail).
+    pub fn detail(&self, separator: char) -> Option<&'a str> {
+        self.split_detail(separator).1
+    }
+
+    /// Returns true if the address contains non-ASCII characters, so the server has to
+    /// support `SMTPUTF8` to accept it.
+    /// <https://datatracker.ietf.org/doc/html/rfc6531#section-3.3>
+    pub fn requires_smtputf8(&self) -> bool {
+        !self.raw.is_ascii()
+    }
+
+    /// The address with its domain converted to A-labels (punycode), so it can be sent to
+    /// a server without `SMTPUTF8`. Only the domain can be converted, an address with a
+    /// non-ASCII local part fails with [`SyntaxError::LocalPartNotAscii`].
+    /// <https://datatracker.ietf.org/doc/html/rfc5891#section-4.2>
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use simple_smtp::address::Address;
+    ///
+    /// let address = Address::parse("user@bücher.example").unwrap();
+    /// assert_eq!(address.to_ascii().unwrap(), "user@xn--bcher-kva.example");
+    /// ```
+    #[cfg(feature = "idna")]
+    pub fn to_ascii(&self) -> Result<alloc::borrow::Cow<'a, str>, SyntaxError> {
+        if self.raw.is_ascii() {
+            return Ok(self.raw.into());
+        }
+        if !self.local_part().is_ascii() {
+            return Err(SyntaxError::LocalPartNotAscii);
+        }
+        let domain =
+            idna::domain_to_ascii(self.domain()).map_err(|_| SyntaxError::InvalidDomain)?;
+        Ok(alloc::format!("{}@{domain}", self.local_part()).into())
+    }
+}
+
+impl<'a> TryFrom<&'a str> for Address<'a> {
+    type Error = SyntaxError;
+
+    /// Same as [`Address::parse`]. `FromStr` can't be implemented, as the address borrows
+    /// the string.
+    fn try_from(address: &'a str) -> Result<Self, Self::Error> {
+        Address::parse(address)
+    }
+}
+
+impl Display for Address<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.raw)
+    }
+}
+
+fn is_atext(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~".contains(c) || !c.is_ascii()
+}
+
+fn validate_local_part(local_part: &str) -> Result<(), SyntaxError> {
+    if local_part.is_empty() {
+        return Err(SyntaxError::EmptyLocalPart);
+    }
+    if local_part.len() > 64 {
+        return Err(SyntaxError::LocalPartTooLong);
+    }
+    let valid = if let Some(quoted) = local_part
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+    {
+        // Quoted-string, qtextSMTP or quoted-pairSMTP
+        let mut chars = quoted.chars();
+        let mut valid = true;
+        while let Some(c) = chars.next() {
+            valid &= match c {
+                '\\' => chars.next().is_some_and(|c| matches!(c, ' '..='~')),
+                '"' => false,
+                c => matches!(c, ' '..='~') || !c.is_ascii(),
+            };
+        }
+        valid
+    } else {
+        // Dot-string
+        local_part
+            .split('.')
+            .all(|atom| !atom.is_empty() && atom.chars().all(is_atext))
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(SyntaxError::InvalidLocalPart)
+    }
+}
+
+fn validate_domain(domain: &str) -> Result<(), SyntaxError> {
+    if domain.is_empty() {
+        return Err(SyntaxError::EmptyDomain);
+    }
+    if let Some(literal) = domain.strip_prefix('[') {
+        let literal = literal
+            .strip_suffix(']')
+            .ok_or(SyntaxError::InvalidAddressLiteral)?;
+        return match parse_literal(literal) {
+            Some(_) => Ok(()),
+            None => Err(SyntaxError::InvalidAddressLiteral),
+        };
+    }
+    if domain.len() > 255 {
+        return Err(SyntaxError::DomainTooLong);
+    }
+    for label in domain.split('.') {
+        if label.len() > 63 {
+            return Err(SyntaxError::DomainTooLong);
+        }
+        // sub-domain = Let-dig [Ldh-str], where U-labels may contain any non-ASCII character
+        let let_dig = |c: char| c.is_ascii_alphanumeric() || !c.is_ascii();
+        let valid = label.chars().next().is_some_and(let_dig)
+            && label.chars().last().is_some_and(let_dig)
+            && label.chars().all(|c| let_dig(c) || c == '-');
+        if !valid {
+            return Err(SyntaxError::InvalidDomain);
+        }
+    }
+    Ok(())
+}
+
+/// Looks up whether a domain has a host that accepts mail.
+///
+/// This crate doesn't do DNS itself, implement this on top of the resolver of your platform.
+/// Implementations should look for MX records, fall back to A/AAAA records when there are
+/// none, and treat a "null MX" as having no mail host.
+/// <https://datatracker.ietf.org/doc/html/rfc5321#section-5.1>
+/// <https://datatracker.ietf.org/doc/html/rfc7505>
+pub trait Resolver {
+    type Error: core::error::Error;
+    fn has_mail_host(&mut self, domain: &str) -> impl Future<Output = Result<bool, Self::Error>>;
+}
+
+/// How thoroughly [`validate`] checks an address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Only check the syntax, no network access.
+    Syntax,
+    /// Also check that the domain has a mail host.
+    MailHost,
+}
+
+/// The outcome of validating an address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// The address passed every check of the requested level.
+    Valid,
+    InvalidSyntax(SyntaxError),
+    /// The domain has no host that accepts mail.
+    NoMailHost,
+    /// The server permanently rejected the address during a [`callout`].
+    Rejected {
+        code: ReplyCode,
+        enhanced: Option<EnhancedCode>,
+    },
+    /// The server temporarily rejected the address during a [`callout`], e.g. because of
+    /// greylisting. Try again later, or treat the address as unverified.
+    Inconclusive {
+        code: ReplyCode,
+        enhanced: Option<EnhancedCode>,
+    },
+}
+
+impl Verdict {
+    pub fn is_valid(&self) -> bool {
+        matches!(self, Verdict::Valid)
+    }
+}
+
+/// Validates `address` up to `level`.
+///
+/// `resolver` is only used for [`Level::MailHost`]. Addresses with an address literal as
+/// domain are not looked up.
+pub async fn validate<R: Resolver>(
+    address: &str,
+    level: Level,
+    resolver: &mut R,
+) -> Result<Verdict, R::Error> {
+    let address = match Address::parse(address) {
+        Ok(address) => address,
+        Err(e) => return Ok(Verdict::InvalidSyntax(e)),
+    };
+    if level >= Level::MailHost
+        && !address.is_address_literal()
+        && !resolver.has_mail_host(address.domain()).await?
+    {
+        return Ok(Verdict::NoMailHost);
+    }
+    Ok(Verdict::Valid)
+}
+
+/// Asks the server responsible for the address whether it would accept mail for it.
+///
+/// `session` must be freshly connected to one of the domain's mail hosts. This sends
+/// `EHLO`, `MAIL FROM:<sender>`, `RCPT TO:<address>`, `RSET` and `QUIT`, without ever
+/// delivering a message. The session is closed with `RSET` and `QUIT` even if the server
+/// rejects the sender.
+///
+/// **Use sparingly.** Callouts are a well known technique of spammers harvesting addresses,
+/// so many servers rate limit, greylist or block hosts that do them, and some accept every
+/// address regardless to defeat them. A `Valid` verdict is therefore only a hint. Never
+/// call out for addresses entered by untrusted users in bulk, use a real `sender` address
+/// that accepts bounces, and cache results instead of repeating callouts.
+pub async fn callout<T: ReadWrite<Error = impl core::error::Error>>(
+    session: &mut Smtp<'_, T>,
+    ehlo_domain: &str,
+    sender: &str,
+    address: &Address<'_>,
+) -> Result<Verdict, Error<T::Error>> {
+    session.ready().await?;
+    let verdict = probe(session, ehlo_domain, sender, address).await;
+    // leave politely even if the probe failed, but report the failure rather than the
+    // cleanup's
+    let cleanup = async {
+        if !session.is_usable() {
+            // a reply is still outstanding, so the one to QUIT can't be told apart
+            return session.fast_quit().await;
+        }
+        if session.state() == SessionState::Greeted {
+            session.rset().await?;
+        }
+        session.quit().await.map(|_| ())
+    };
+    match verdict {
+        Ok(verdict) => cleanup.await.map(|()| verdict),
+        Err(e) => {
+            let _ = cleanup.await;
+            Err(e)
+        }
+    }
+}
+
+// EHLO, MAIL FROM and RCPT TO of a callout
+async fn probe<T: ReadWrite<Error = impl core::error::Error>>(
+    session: &mut Smtp<'_, T>,
+    ehlo_domain: &str,
+    sender: &str,
+    address: &Address<'_>,
+) -> Result<Verdict, Error<T::Error>> {
+    session.ehlo(ehlo_domain).await?;
+    let envelope = Envelope::new(sender, &[]);
+    session.check_envelope(&envelope)?;
+    session.mail_from(&envelope).await?;
+    match session.rcpt_to(&Recipient::new(address.as_str())).await {
+        Ok(()) => Ok(Verdict::Valid),
+        Err(Error::ServerRejected { code, enhanced, .. }) if code.is_permanent() => {
+            Ok(Verdict::Rejected { code, enhanced })
+        }
+        Err(Error::ServerRejected { code, enhanced, .. }) => {
+            Ok(Verdict::Inconclusive { code, enhanced })
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// the inside of an address literal, without the brackets
+// https://datatracker.ietf.org/doc/html/rfc5321#section-4.1.3
+fn parse_literal(literal: &str) -> Option<IpAddr> {
+    match literal.strip_prefix("IPv6:") {
+        Some(v6) => v6.parse::<Ipv6Addr>().ok().map(IpAddr::V6),
+        None => literal.parse::<Ipv4Addr>().ok().map(IpAddr::V4),
+    }
+}
+
+/// Parses a host given as an IP address rather than a name.
+///
+/// Accepts plain addresses (`192.0.2.1`, `2001:db8::1`), address literals (`[192.0.2.1]`,
+/// `[IPv6:2001:db8::1]`) and IPv6 in brackets as in URLs (`[2001:db8::1]`).
+pub fn parse_ip_host(host: &str) -> Option<IpAddr> {
+    match host.strip_prefix('[').and_then(|h| h.strip_suffix(']')) {
+        Some(inner) => {
+            parse_literal(inner).or_else(|| inner.parse::<Ipv6Addr>().ok().map(IpAddr::V6))
+        }
+        None => host.parse().ok(),
+    }
+}
+
+/// Formats an IP address as an address literal, for example to greet with `EHLO` from a
+/// host without a domain name.
+/// <https://datatracker.ietf.org/doc/html/rfc5321#section-4.1.4>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressLiteral(pub IpAddr);
+
+impl Display for AddressLiteral {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.0 {
+            IpAddr::V4(ip) => write!(f, "[{ip}]"),
+            IpAddr::V6(ip) => write!(f, "[IPv6:{ip}]"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_addresses() {
+        for address in [
+            "user@example.com",
+            "first.last+tag@sub.example.co.uk",
+            "!#$%&'*+-/=?^_`{|}~@example.com",
+            "\"john doe\"@example.com",
+            "\"quoted\\\"quote\"@example.com",
+            "\"at@sign\"@example.com",
+            "user@[192.0.2.1]",
+            "user@[IPv6:2001:db8::1]",
+            "user@localhost",
+            "user@xn--bcher-kva.example",
+            "jöran@bücher.example",
+            "user@1-2.example",
+        ] {
+            assert!(Address::parse(address).is_ok(), "{address:?} should parse");
+        }
+    }
+
+    #[test]
+    fn invalid_addresses() {
+        let long_local = format!("{}@example.com", "a".repeat(65));
+        let long_label = format!("user@{}.com", "a".repeat(64));
+        let long_address = format!("user@{}.com", vec!["a".repeat(60); 5].join("."));
+        for (address, error) in [
+            ("example.com", SyntaxError::MissingAt),
+            ("@example.com", SyntaxError::EmptyLocalPart),
+            (&long_local, SyntaxError::LocalPartTooLong),
+            (".user@example.com", SyntaxError::InvalidLocalPart),
+            ("user.@example.com", SyntaxError::InvalidLocalPart),
+            ("us..er@example.com", SyntaxError::InvalidLocalPart),
+            ("us er@example.com", SyntaxError::InvalidLocalPart),
+            ("\"unterminated@example.com", SyntaxError::InvalidLocalPart),
+            ("\"trailing\\\"@example.com", SyntaxError::InvalidLocalPart),
+            ("user@", SyntaxError::EmptyDomain),
+            (&long_label, SyntaxError::DomainTooLong),
+            (&long_address, SyntaxError::TooLong),
+            ("user@-example.com", SyntaxError::InvalidDomain),
+            ("user@example-.com", SyntaxError::InvalidDomain),
+            ("user@example..com", SyntaxError::InvalidDomain),
+            ("user@exa_mple.com", SyntaxError::InvalidDomain),
+            ("user@[192.0.2.256]", SyntaxError::InvalidAddressLiteral),
+            ("user@[IPv6:not-an-ip]", SyntaxError::InvalidAddressLiteral),
+            ("user@[192.0.2.1", SyntaxError::InvalidAddressLiteral),
+        ] {
+            assert_eq!(Address::parse(address), Err(error), "{address:?}");
+        }
+    }
+
+    #[test]
+    fn parts() {
+        let address = Address::parse("\"a@b\"@example.com").unwrap();
+        assert_eq!(address.local_part(), "\"a@b\"");
+        assert_eq!(address.domain(), "example.com");
+        assert!(!address.is_address_literal());
+        assert!(!address.requires_smtputf8());
+        assert!(
+            Address::parse("jöran@example.com")
+                .unwrap()
+                .requires_smtputf8()
+        );
+        assert!(
+            Address::parse("user@[192.0.2.1]")
+                .unwrap()
+                .is_address_literal()
+        );
+        assert_eq!(
+            Address::try_from("user@[IPv6:::1]").map(|address| address.domain()),
+            Ok("[IPv6:::1]")
+        );
+        assert_eq!(Address::try_from("user"), Err(SyntaxError::MissingAt));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn owned() {
+        let borrowed = AddrRef::parse("\"a@b\"@example.com").unwrap();
+        let owned = EmailAddress::from(borrowed);
+        assert_eq!(owned.local_part(), "\"a@b\"");
+        assert_eq!(owned.domain(), "example.com");
+        assert_eq!(owned.as_addr(), borrowed);
+        assert_eq!(owned.to_string(), "\"a@b\"@example.com");
+        assert_eq!(
+            "user@".parse::<EmailAddress>(),
+            Err(SyntaxError::EmptyDomain)
+        );
+    }
+
+    #[cfg(feature = "idna")]
+    #[test]
+    fn to_ascii() {
+        let ascii = |address| Address::parse(address).unwrap().to_ascii();
+        assert_eq!(ascii("user@example.com").unwrap(), "user@example.com");
+        assert_eq!(
+            ascii("user@BÜCHER.example").unwrap(),
+            "user@xn--bcher-kva.example"
+        );
+        assert_eq!(ascii("user@日本語.jp").unwrap(), "user@xn--wgv71a119e.jp");
+        assert_eq!(
+            ascii("jöran@example.com"),
+            Err(SyntaxError::LocalPartNotAscii)
+        );
+    }
+
+    #[test]
+    fn ip_hosts() {
+        let v4 = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let v6 = IpAddr::V6("2001:db8::1".parse().unwrap());
+        assert_eq!(parse_ip_host("192.0.2.1"), Some(v4));
+        assert_eq!(parse_ip_host("[192.0.2.1]"), Some(v4));
+        assert_eq!(parse_ip_host("2001:db8::1"), Some(v6));
+        assert_eq!(parse_ip_host("[2001:db8::1]"), Some(v6));
+        assert_eq!(parse_ip_host("[IPv6:2001:db8::1]"), Some(v6));
+        assert_eq!(parse_ip_host("mail.example.com"), None);
+        assert_eq!(parse_ip_host("[mail.example.com]"), None);
+
+        assert_eq!(AddressLiteral(v4).to_string(), "[192.0.2.1]");
+        assert_eq!(AddressLiteral(v6).to_string(), "[IPv6:2001:db8::1]");
+        assert_eq!(parse_ip_host(&AddressLiteral(v6).to_string()), Some(v6));
+    }
+}
@@ -0,0 +1,107 @@
+use alloc::{string::String, vec::Vec};
+
+/// Recipients sharing a domain, and so usually the mail server which accepts mail for them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainGroup<T> {
+    /// The domain as returned by [`normalize_domain`].
+    pub domain: String,
+    pub recipients: Vec<T>,
+}
+
+/// Groups recipients by the domain of their address, e.g. to open one connection per
+/// domain. `address` returns the address of a recipient.
+///
+/// Domains are compared after [`normalize_domain`], so `Example.COM` and `example.com` end
+/// up in the same group. Groups are in the order their domains first appear, and keep the
+/// order of their recipients. An address without an `@` is grouped under the empty domain.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::{address::group_by_domain, envelope::Recipient};
+///
+/// let recipients = [
+///     Recipient::new("a@example.com"),
+///     Recipient::new("b@example.org"),
+///     Recipient::new("c@Example.com"),
+/// ];
+/// let groups = group_by_domain(recipients, |recipient| recipient.address());
+/// assert_eq!(groups.len(), 2);
+/// assert_eq!(groups[0].domain, "example.com");
+/// assert_eq!(groups[0].recipients, [recipients[0], recipients[2]]);
+/// ```
+pub fn group_by_domain<T>(
+    recipients: impl IntoIterator<Item = T>,
+    address: impl Fn(&T) -> &str,
+) -> Vec<DomainGroup<T>> {
+    let mut groups: Vec<DomainGroup<T>> = Vec::new();
+    for recipient in recipients {
+        let addr = address(&recipient);
+        let domain = normalize_domain(addr.rfind('@').map_or("", |at| &addr[at + 1..]));
+        match groups.iter_mut().find(|group| group.domain == domain) {
+            Some(group) => group.recipients.push(recipient),
+            None => groups.push(DomainGroup {
+                domain,
+                recipients: Vec::from([recipient]),
+            }),
+        }
+    }
+    groups
+}
+
+/// The form of a domain used to compare it: lowercase, without a trailing dot. Address
+/// literals like `[192.0.2.1]` are left as they are.
+///
+/// With the `idna` feature, internationalized domains are converted to A-labels, so
+/// `bücher.example` and `xn--bcher-kva.example` are the same domain.
+pub fn normalize_domain(domain: &str) -> String {
+    if domain.starts_with('[') {
+        return domain.into();
+    }
+    let domain = domain.strip_suffix('.').unwrap_or(domain);
+    #[cfg(feature = "idna")]
+    if let Ok(ascii) = idna::domain_to_ascii(domain) {
+        return ascii;
+    }
+    if domain.is_ascii() {
+        domain.to_ascii_lowercase()
+    } else {
+        domain.to_lowercase()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_by_normalized_domain() {
+        let addresses = [
+            "a@bücher.example",
+            "b@example.com.",
+            "c@BÜCHER.example",
+            "broken",
+            "d@[192.0.2.1]",
+            "e@EXAMPLE.com",
+        ];
+        let groups = group_by_domain(addresses, |address| address);
+        let domains: Vec<_> = groups.iter().map(|group| group.domain.as_str()).collect();
+        let idn = match cfg!(feature = "idna") {
+            true => "xn--bcher-kva.example",
+            false => "bücher.example",
+        };
+        assert_eq!(domains, [idn, "example.com", "", "[192.0.2.1]"]);
+        assert_eq!(
+            groups[0].recipients,
+            ["a@bücher.example", "c@BÜCHER.example"]
+        );
+        assert_eq!(groups[1].recipients, ["b@example.com.", "e@EXAMPLE.com"]);
+    }
+
+    #[cfg(feature = "idna")]
+    #[test]
+    fn unifies_punycode() {
+        let groups = group_by_domain(["a@bücher.example", "b@xn--bcher-kva.example"], |a| a);
+        assert_eq!(groups.len(), 1);
+    }
+}
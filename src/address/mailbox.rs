@@ -0,0 +1,753 @@
+use core::{
+    fmt::{self, Display, Write},
+    ops::Range,
+};
+
+use super::{Address, SyntaxError, is_atext};
+use crate::envelope::Recipient;
+
+/// An address with an optional display name, like `Alice <alice@example.com>`.
+///
+/// Parses the `mailbox` grammar of RFC 5322: a display name of words or quoted strings
+/// followed by an address in angle brackets, or just an address. Comments are allowed
+/// wherever whitespace is and are dropped. Like [`Address`], it borrows the string it was
+/// parsed from.
+/// <https://datatracker.ietf.org/doc/html/rfc5322#section-3.4>
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::address::Mailbox;
+///
+/// let mailbox = Mailbox::parse("\"Doe, John\" (work) <john@example.com>").unwrap();
+/// assert_eq!(mailbox.address().as_str(), "john@example.com");
+/// assert_eq!(mailbox.display_name().unwrap().to_string(), "Doe, John");
+/// assert_eq!(mailbox.to_string(), "\"Doe, John\" <john@example.com>");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mailbox<'a> {
+    // the display name as written, quotes and comments included
+    name: Option<&'a str>,
+    address: Address<'a>,
+}
+
+impl<'a> Mailbox<'a> {
+    pub fn parse(mailbox: &'a str) -> Result<Mailbox<'a>, SyntaxError> {
+        let bytes = mailbox.as_bytes();
+        let start = skip_cfws(bytes, 0)?;
+        let (mut idx, mut name_end) = (start, start);
+        // the words of the display name, up to the angle bracket
+        loop {
+            match bytes.get(idx) {
+                Some(b'<') => break,
+                Some(b'"') => idx = skip_quoted(bytes, idx)?,
+                Some(_) => {
+                    let word = mailbox[idx..]
+                        .find(|c| !is_atext(c) && c != '.')
+                        .unwrap_or(mailbox.len() - idx);
+                    if word == 0 {
+                        if mailbox[idx..].contains('<') {
+                            return Err(SyntaxError::InvalidDisplayName);
+                        }
+                        return Self::parse_addr_spec(mailbox, start);
+                    }
+                    idx += word;
+                }
+                None => return Self::parse_addr_spec(mailbox, start),
+            }
+            name_end = idx;
+            idx = skip_cfws(bytes, idx)?;
+        }
+
+        let address_start = idx + 1;
+        let mut end = address_start;
+        loop {
+            match bytes.get(end) {
+                Some(b'>') => break,
+                Some(b'"') => {
+                    end = skip_quoted(bytes, end).map_err(|_| SyntaxError::InvalidLocalPart)?
+                }
+                Some(_) => end += 1,
+                None => return Err(SyntaxError::UnclosedAngleBracket),
+            }
+        }
+        let address = Address::parse(mailbox[address_start..end].trim_matches([' ', '\t']))?;
+        if skip_cfws(bytes, end + 1)? != bytes.len() {
+            return Err(SyntaxError::TrailingText);
+        }
+        // an empty quoted string is no name at all
+        let name =
+            Some(&mailbox[start..name_end]).filter(|name| NameChars::new(name).next().is_some());
+        Ok(Mailbox { name, address })
+    }
+
+    // just an address, possibly surrounded by comments
+    fn parse_addr_spec(mailbox: &'a str, start: usize) -> Result<Mailbox<'a>, SyntaxError> {
+        let bytes = mailbox.as_bytes();
+        let mut end = start;
+        while let Some(&b) = bytes.get(end) {
+            match b {
+                b'"' => end = skip_quoted(bytes, end).map_err(|_| SyntaxError::InvalidLocalPart)?,
+                b' ' | b'\t' | b'(' | b'\r' | b'\n' => break,
+                _ => end += 1,
+            }
+        }
+        let address = Address::parse(&mailbox[start..end])?;
+        if skip_cfws(bytes, end)? != bytes.len() {
+            return Err(SyntaxError::TrailingText);
+        }
+        Ok(Mailbox {
+            name: None,
+            address,
+        })
+    }
+
+    pub fn address(&self) -> Address<'a> {
+        self.address
+    }
+
+    pub fn display_name(&self) -> Option<DisplayName<'a>> {
+        self.name.map(DisplayName)
+    }
+}
+
+impl<'a> TryFrom<&'a str> for Mailbox<'a> {
+    type Error = SyntaxError;
+
+    /// Same as [`Mailbox::parse`].
+    fn try_from(mailbox: &'a str) -> Result<Self, Self::Error> {
+        Mailbox::parse(mailbox)
+    }
+}
+
+impl<'a> From<Address<'a>> for Mailbox<'a> {
+    fn from(address: Address<'a>) -> Self {
+        Mailbox {
+            name: None,
+            address,
+        }
+    }
+}
+
+/// Writes the mailbox in its shortest form, quoting the display name if it contains
+/// anything but words.
+impl Display for Mailbox<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.display_name() {
+            Some(name) => write!(f, "{} <{}>", name.quoted(), self.address),
+            None => write!(f, "{}", self.address),
+        }
+    }
+}
+
+/// The display name of a [`Mailbox`]. Formats as the name itself, without quotes and
+/// comments, and with whitespace collapsed to single spaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayName<'a>(&'a str);
+
+impl<'a> DisplayName<'a> {
+    /// The name as written, including quotes and comments.
+    pub fn as_raw(&self) -> &'a str {
+        self.0
+    }
+
+    pub fn chars(&self) -> impl Iterator<Item = char> + 'a {
+        NameChars::new(self.0)
+    }
+
+    /// Formats the name as a phrase, quoted if needed.
+    pub(crate) fn quoted(self) -> impl Display + 'a {
+        Quoted(self)
+    }
+}
+
+impl Display for DisplayName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chars().try_for_each(|c| f.write_char(c))
+    }
+}
+
+struct Quoted<'a>(DisplayName<'a>);
+
+impl Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.0;
+        if name.chars().all(|c| c == ' ' || is_atext(c)) {
+            return write!(f, "{name}");
+        }
+        f.write_char('"')?;
+        for c in name.chars() {
+            if matches!(c, '"' | '\\') {
+                f.write_char('\\')?;
+            }
+            f.write_char(c)?;
+        }
+        f.write_char('"')
+    }
+}
+
+// the characters of a display name which `Mailbox::parse` accepted
+struct NameChars<'a> {
+    chars: core::str::Chars<'a>,
+    quoted: bool,
+    // whitespace or a comment was skipped since the last character
+    space: bool,
+    pending: Option<char>,
+}
+
+impl<'a> NameChars<'a> {
+    fn new(name: &'a str) -> Self {
+        NameChars {
+            chars: name.chars(),
+            quoted: false,
+            space: false,
+            pending: None,
+        }
+    }
+}
+
+impl Iterator for NameChars<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if let Some(c) = self.pending.take() {
+            return Some(c);
+        }
+        loop {
+            let c = match (self.quoted, self.chars.next()?) {
+                (_, '\\') => self.chars.next()?,
+                (_, '"') => {
+                    self.quoted = !self.quoted;
+                    continue;
+                }
+                (true, c) => c,
+                (false, '(') => {
+                    let mut depth = 1;
+                    while depth > 0 {
+                        match self.chars.next()? {
+                            '(' => depth += 1,
+                            ')' => depth -= 1,
+                            '\\' => _ = self.chars.next(),
+                            _ => {}
+                        }
+                    }
+                    self.space = true;
+                    continue;
+                }
+                (false, ' ' | '\t') => {
+                    self.space = true;
+                    continue;
+                }
+                (false, c) => c,
+            };
+            if core::mem::take(&mut self.space) {
+                self.pending = Some(c);
+                return Some(' ');
+            }
+            return Some(c);
+        }
+    }
+}
+
+/// A comma separated list of mailboxes and groups, like the value of a `To` header field.
+///
+/// Commas in quoted display names and comments don't separate mailboxes. Empty items, as in
+/// `a@example.com, , b@example.com`, are skipped. A [`Group`] like `Team: a@example.com,
+/// b@example.com;` is expanded into its members by [`iter`](Self::iter) and
+/// [`recipients`](Self::recipients), so mail drafted to a group reaches the people in it.
+/// <https://datatracker.ietf.org/doc/html/rfc5322#section-3.4>
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::address::AddressList;
+///
+/// let to = AddressList::parse("a@example.com, Team: \"Doe, John\" <john@example.com>;").unwrap();
+/// let recipients: Vec<_> = to.recipients().collect();
+/// assert_eq!(recipients[1].address(), "john@example.com");
+/// assert_eq!(to.entries().count(), 2);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressList<'a>(&'a str);
+
+impl<'a> AddressList<'a> {
+    /// Checks every mailbox and group in `list`, returning the error of the first invalid one.
+    pub fn parse(list: &'a str) -> Result<AddressList<'a>, SyntaxError> {
+        let mut rest = Some(list);
+        while let Some(text) = rest {
+            rest = next_entry(text, true)?.1;
+        }
+        Ok(AddressList(list))
+    }
+
+    pub fn as_str(&self) -> &'a str {
+        self.0
+    }
+
+    /// The mailboxes of the list, with the members of each group in its place.
+    pub fn iter(&self) -> AddressListIter<'a> {
+        AddressListIter {
+            entries: self.entries(),
+            members: None,
+        }
+    }
+
+    /// The mailboxes and groups of the list as written.
+    pub fn entries(&self) -> ListEntries<'a> {
+        ListEntries { rest: Some(self.0) }
+    }
+
+    /// The envelope recipients for every mailbox of [`iter`](Self::iter).
+    pub fn recipients(self) -> impl Iterator<Item = Recipient<'a>> {
+        self.iter()
+            .map(|mailbox| Recipient::new(mailbox.address().as_str()))
+    }
+}
+
+impl<'a> IntoIterator for AddressList<'a> {
+    type Item = Mailbox<'a>;
+    type IntoIter = AddressListIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Display for AddressList<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// A named list of mailboxes within an [`AddressList`], like `Team: a@example.com;`.
+///
+/// The list may be empty, as in the common `undisclosed-recipients:;`, but can't contain
+/// groups itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Group<'a> {
+    name: &'a str,
+    members: AddressList<'a>,
+}
+
+impl<'a> Group<'a> {
+    pub fn display_name(&self) -> DisplayName<'a> {
+        DisplayName(self.name)
+    }
+
+    pub fn members(&self) -> AddressList<'a> {
+        self.members
+    }
+}
+
+/// A mailbox or a group of an [`AddressList`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListEntry<'a> {
+    Mailbox(Mailbox<'a>),
+    Group(Group<'a>),
+}
+
+/// The entries of an [`AddressList`], see [`AddressList::entries`].
+pub struct ListEntries<'a> {
+    rest: Option<&'a str>,
+}
+
+impl<'a> Iterator for ListEntries<'a> {
+    type Item = ListEntry<'a>;
+
+    fn next(&mut self) -> Option<ListEntry<'a>> {
+        loop {
+            let (entry, rest) =
+                next_entry(self.rest?, true).expect("checked by AddressList::parse");
+            self.rest = rest;
+            if entry.is_some() {
+                return entry;
+            }
+        }
+    }
+}
+
+/// The mailboxes of an [`AddressList`], see [`AddressList::iter`].
+pub struct AddressListIter<'a> {
+    entries: ListEntries<'a>,
+    // the rest of the group being expanded
+    members: Option<ListEntries<'a>>,
+}
+
+impl<'a> Iterator for AddressListIter<'a> {
+    type Item = Mailbox<'a>;
+
+    fn next(&mut self) -> Option<Mailbox<'a>> {
+        loop {
+            if let Some(members) = &mut self.members {
+                if let Some(ListEntry::Mailbox(mailbox)) = members.next() {
+                    return Some(mailbox);
+                }
+                self.members = None;
+            }
+            match self.entries.next()? {
+                ListEntry::Mailbox(mailbox) => return Some(mailbox),
+                ListEntry::Group(group) => self.members = Some(group.members().entries()),
+            }
+        }
+    }
+}
+
+// parses the first entry of a list, returning it unless it's blank, and the text after its
+// comma. Groups are only allowed at the top level.
+fn next_entry(
+    text: &str,
+    groups: bool,
+) -> Result<(Option<ListEntry<'_>>, Option<&str>), SyntaxError> {
+    let bytes = text.as_bytes();
+    let end = item_end(bytes, groups)?;
+    if bytes.get(end) != Some(&b':') {
+        let item = &text[..end];
+        let entry = match is_blank(item) {
+            true => None,
+            false => Some(ListEntry::Mailbox(Mailbox::parse(item)?)),
+        };
+        return Ok((entry, text.get(end + 1..)));
+    }
+
+    let name = &text[phrase(&bytes[..end])?];
+    let members_start = end + 1;
+    let members_end = members_start + group_end(&bytes[members_start..])?;
+    let members = &text[members_start..members_end];
+    let mut rest = Some(members);
+    while let Some(text) = rest {
+        rest = next_entry(text, false)?.1;
+    }
+    let after = skip_cfws(bytes, members_end + 1)?;
+    match bytes.get(after) {
+        None | Some(b',') => {}
+        Some(_) => return Err(SyntaxError::TrailingText),
+    }
+    let group = Group {
+        name,
+        members: AddressList(members),
+    };
+    Ok((Some(ListEntry::Group(group)), text.get(after + 1..)))
+}
+
+// the end of the first item of a list, at the first comma which isn't quoted or in a comment,
+// or at the colon after the name of a group
+fn item_end(bytes: &[u8], groups: bool) -> Result<usize, SyntaxError> {
+    let mut idx = 0;
+    // a colon in or after an address, e.g. in an IPv6 literal, doesn't start a group
+    let mut address = false;
+    while let Some(&b) = bytes.get(idx) {
+        match b {
+            b',' => break,
+            b':' if groups && !address => break,
+            b'"' => idx = skip_quoted(bytes, idx)?,
+            b'(' => idx = skip_comment(bytes, idx)?,
+            b'<' | b'@' => {
+                address = true;
+                idx += 1;
+            }
+            _ => idx += 1,
+        }
+    }
+    Ok(idx)
+}
+
+// the `;` closing a group
+fn group_end(bytes: &[u8]) -> Result<usize, SyntaxError> {
+    let mut idx = 0;
+    while let Some(&b) = bytes.get(idx) {
+        match b {
+            b';' => return Ok(idx),
+            b'"' => idx = skip_quoted(bytes, idx)?,
+            b'(' => idx = skip_comment(bytes, idx)?,
+            b'[' => match bytes[idx..].iter().position(|&b| b == b']') {
+                Some(len) => idx += len + 1,
+                None => return Err(SyntaxError::InvalidAddressLiteral),
+            },
+            _ => idx += 1,
+        }
+    }
+    Err(SyntaxError::UnclosedGroup)
+}
+
+// where the name of a group is, without the whitespace and comments around it: words and
+// quoted strings, like the display name of a mailbox
+fn phrase(bytes: &[u8]) -> Result<Range<usize>, SyntaxError> {
+    let start = skip_cfws(bytes, 0)?;
+    let (mut idx, mut end) = (start, start);
+    while let Some(&b) = bytes.get(idx) {
+        idx = match b {
+            b'"' => skip_quoted(bytes, idx)?,
+            _ => match bytes[idx..]
+                .iter()
+                .position(|&b| !is_atext(b as char) && b != b'.')
+            {
+                Some(0) => return Err(SyntaxError::InvalidDisplayName),
+                Some(len) => idx + len,
+                None => bytes.len(),
+            },
+        };
+        end = idx;
+        idx = skip_cfws(bytes, idx)?;
+    }
+    match end > start {
+        true => Ok(start..end),
+        false => Err(SyntaxError::InvalidDisplayName),
+    }
+}
+
+fn is_blank(item: &str) -> bool {
+    skip_cfws(item.as_bytes(), 0) == Ok(item.len())
+}
+
+fn is_control(b: u8) -> bool {
+    b.is_ascii_control() && b != b'\t'
+}
+
+// skips whitespace and comments, returning where the next token starts. The whitespace may
+// be folded, as in the fields of a received message, a line break is only allowed before a
+// space or tab.
+// https://datatracker.ietf.org/doc/html/rfc5322#section-3.2.2
+fn skip_cfws(bytes: &[u8], mut idx: usize) -> Result<usize, SyntaxError> {
+    while let Some(&b) = bytes.get(idx) {
+        match b {
+            b' ' | b'\t' => idx += 1,
+            b'(' => idx = skip_comment(bytes, idx)?,
+            b'\r' | b'\n' => match fold_len(&bytes[idx..]) {
+                Some(len) => idx += len,
+                None => break,
+            },
+            _ => break,
+        }
+    }
+    Ok(idx)
+}
+
+// the length of the line break of a fold, CRLF or LF followed by a space or tab
+fn fold_len(bytes: &[u8]) -> Option<usize> {
+    let len = match bytes {
+        [b'\r', b'\n', ..] => 2,
+        [b'\n', ..] => 1,
+        _ => return None,
+    };
+    matches!(bytes.get(len), Some(b' ' | b'\t')).then_some(len)
+}
+
+// `idx` is at the opening parenthesis, comments may be nested
+fn skip_comment(bytes: &[u8], mut idx: usize) -> Result<usize, SyntaxError> {
+    let mut depth = 0;
+    while let Some(&b) = bytes.get(idx) {
+        idx += 1;
+        match b {
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(idx);
+                }
+            }
+            b'\\' if bytes.get(idx).is_some_and(|&b| !is_control(b)) => idx += 1,
+            b if b == b'\\' || is_control(b) => return Err(SyntaxError::InvalidComment),
+            _ => {}
+        }
+    }
+    Err(SyntaxError::InvalidComment)
+}
+
+// `idx` is at the opening quote
+fn skip_quoted(bytes: &[u8], mut idx: usize) -> Result<usize, SyntaxError> {
+    idx += 1;
+    while let Some(&b) = bytes.get(idx) {
+        idx += 1;
+        match b {
+            b'"' => return Ok(idx),
+            b'\\' if bytes.get(idx).is_some_and(|&b| !is_control(b)) => idx += 1,
+            b if b == b'\\' || is_control(b) => return Err(SyntaxError::InvalidDisplayName),
+            _ => {}
+        }
+    }
+    Err(SyntaxError::InvalidDisplayName)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(mailbox: &str) -> Option<String> {
+        let mailbox = Mailbox::parse(mailbox).unwrap();
+        mailbox.display_name().map(|name| name.to_string())
+    }
+
+    #[test]
+    fn parses_display_names() {
+        assert_eq!(name("Alice <alice@example.com>").as_deref(), Some("Alice"));
+        assert_eq!(
+            name("  John  Q. Public\t<john@example.com> ").as_deref(),
+            Some("John Q. Public")
+        );
+        assert_eq!(
+            name("\"Doe, \\\"JD\\\" John\" <john@example.com>").as_deref(),
+            Some("Doe, \"JD\" John")
+        );
+        assert_eq!(
+            name("(boss) Pete (his (nested) comment) Smith <pete@example.com> (home)").as_deref(),
+            Some("Pete Smith")
+        );
+        assert_eq!(name("Jürgen <j@example.com>").as_deref(), Some("Jürgen"));
+        assert_eq!(name("<bob@example.com>"), None);
+        assert_eq!(name("\"\" <bob@example.com>"), None);
+        assert_eq!(name("bob@example.com (Bob)"), None);
+    }
+
+    #[test]
+    fn parses_addresses() {
+        for (mailbox, address) in [
+            ("bob@example.com", "bob@example.com"),
+            (" (Bob) bob@example.com ", "bob@example.com"),
+            ("Bob < bob@example.com >", "bob@example.com"),
+            ("\"a>b\" <\"a>b\"@example.com>", "\"a>b\"@example.com"),
+            ("\"john doe\"@example.com", "\"john doe\"@example.com"),
+        ] {
+            let parsed = Mailbox::parse(mailbox).unwrap();
+            assert_eq!(parsed.address().as_str(), address, "{mailbox:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_mailboxes() {
+        for (mailbox, error) in [
+            ("Bob", SyntaxError::MissingAt),
+            ("Bob <bob@example.com", SyntaxError::UnclosedAngleBracket),
+            ("Bob <bob@example.com> Smith", SyntaxError::TrailingText),
+            ("bob@example.com bob", SyntaxError::TrailingText),
+            (
+                "Doe, John <john@example.com>",
+                SyntaxError::InvalidDisplayName,
+            ),
+            ("\"Bob <bob@example.com>", SyntaxError::InvalidDisplayName),
+            (
+                "\"Bob\r\n\" <bob@example.com>",
+                SyntaxError::InvalidDisplayName,
+            ),
+            (
+                "Bob (unclosed <bob@example.com>",
+                SyntaxError::InvalidComment,
+            ),
+            ("Bob <bob@@example.com>", SyntaxError::InvalidLocalPart),
+        ] {
+            assert_eq!(Mailbox::parse(mailbox), Err(error), "{mailbox:?}");
+        }
+    }
+
+    #[test]
+    fn folded_lists() {
+        let list =
+            AddressList::parse("a@example.com,\r\n Bob\r\n\t<bob@example.com>,\n c@example.com")
+                .unwrap();
+        let addresses: Vec<_> = list.iter().map(|m| m.address().as_str()).collect();
+        assert_eq!(
+            addresses,
+            ["a@example.com", "bob@example.com", "c@example.com"]
+        );
+        // a line break which doesn't fold the line
+        assert_eq!(
+            AddressList::parse("a@example.com,\r\nBcc: b@example.com"),
+            Err(SyntaxError::InvalidDisplayName)
+        );
+        assert_eq!(
+            Mailbox::parse("a@example.com\r\n"),
+            Err(SyntaxError::TrailingText)
+        );
+    }
+
+    #[test]
+    fn splits_lists() {
+        let list = AddressList::parse(
+            "a@example.com, \"Doe, John\" <john@example.com> (a, b),, Bob <\"b,c\"@example.com>",
+        )
+        .unwrap();
+        let addresses: Vec<_> = list.iter().map(|m| m.address().as_str()).collect();
+        assert_eq!(
+            addresses,
+            ["a@example.com", "john@example.com", "\"b,c\"@example.com"]
+        );
+        assert_eq!(
+            list.into_iter().nth(1).unwrap().to_string(),
+            "\"Doe, John\" <john@example.com>"
+        );
+        assert_eq!(AddressList::parse(" ").unwrap().iter().count(), 0);
+
+        assert_eq!(
+            AddressList::parse("a@example.com, Doe, John <john@example.com>"),
+            Err(SyntaxError::MissingAt)
+        );
+        assert_eq!(
+            AddressList::parse("a@example.com, \"b@example.com"),
+            Err(SyntaxError::InvalidDisplayName)
+        );
+    }
+
+    #[test]
+    fn expands_groups() {
+        let list = AddressList::parse(
+            "a@example.com, \"The Team\" (staff): b@example.com, Bob <\"c;d\"@example.com> ; , \
+             undisclosed-recipients:;, e@[IPv6:::1]",
+        )
+        .unwrap();
+        let addresses: Vec<_> = list.recipients().map(|r| r.address()).collect();
+        assert_eq!(
+            addresses,
+            [
+                "a@example.com",
+                "b@example.com",
+                "\"c;d\"@example.com",
+                "e@[IPv6:::1]"
+            ]
+        );
+        let groups: Vec<_> = list
+            .entries()
+            .filter_map(|entry| match entry {
+                ListEntry::Group(group) => Some(group),
+                ListEntry::Mailbox(_) => None,
+            })
+            .collect();
+        assert_eq!(groups[0].display_name().to_string(), "The Team");
+        assert_eq!(groups[0].members().iter().count(), 2);
+        assert_eq!(groups[1].display_name().as_raw(), "undisclosed-recipients");
+        assert_eq!(groups[1].members().iter().count(), 0);
+
+        for (list, error) in [
+            ("Team: a@example.com", SyntaxError::UnclosedGroup),
+            (": a@example.com;", SyntaxError::InvalidDisplayName),
+            (
+                "Team: a@example.com; b@example.com",
+                SyntaxError::TrailingText,
+            ),
+            ("Team: Inner: a@example.com;;", SyntaxError::MissingAt),
+        ] {
+            assert_eq!(AddressList::parse(list), Err(error), "{list:?}");
+        }
+    }
+
+    #[test]
+    fn display_quotes_when_needed() {
+        for (mailbox, expected) in [
+            ("Alice  <alice@example.com>", "Alice <alice@example.com>"),
+            ("\"Alice\" <alice@example.com>", "Alice <alice@example.com>"),
+            (
+                "John Q. Public <j@example.com>",
+                "\"John Q. Public\" <j@example.com>",
+            ),
+            (
+                "\"a\\\\b \\\"c\\\"\" <a@example.com>",
+                "\"a\\\\b \\\"c\\\"\" <a@example.com>",
+            ),
+            ("<bob@example.com>", "bob@example.com"),
+        ] {
+            let displayed = Mailbox::parse(mailbox).unwrap().to_string();
+            assert_eq!(displayed, expected);
+            // re-parsing what was written gives the same mailbox
+            let reparsed = Mailbox::parse(&displayed).unwrap();
+            assert_eq!(reparsed.to_string(), displayed);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use alloc::{format, string::String};
+
+use super::{Address, normalize_domain};
+
+/// Rules for telling whether two addresses reach the same mailbox, e.g. to drop duplicate
+/// recipients or to match a bounce to the address it was sent to.
+///
+/// RFC 5321 leaves the local part to the receiving server, so only the domain is compared
+/// case-insensitively by default. Many servers ignore more than that, which can be enabled
+/// rule by rule. Quoted local parts are always compared as they are.
+/// <https://datatracker.ietf.org/doc/html/rfc5321#section-2.4>
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::address::{Address, Normalization};
+///
+/// let a = Address::parse("John.Doe+news@Gmail.com").unwrap();
+/// let b = Address::parse("johndoe@gmail.com").unwrap();
+/// assert!(!Normalization::default().same(&a, &b));
+/// assert!(Normalization::gmail().same(&a, &b));
+/// assert_eq!(Normalization::gmail().normalize(&a), "johndoe@gmail.com");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Normalization {
+    detail_separator: Option<char>,
+    ignore_dots: bool,
+    ignore_case: bool,
+}
+
+impl Normalization {
+    /// The rules of Gmail: the detail after `+`, dots and case are ignored.
+    pub fn gmail() -> Self {
+        Normalization::default()
+            .with_detail_separator('+')
+            .ignoring_dots()
+            .ignoring_case()
+    }
+
+    /// Drop the detail of a subaddress, see [`Address::split_detail`].
+    pub fn with_detail_separator(mut self, separator: char) -> Self {
+        self.detail_separator = Some(separator);
+        self
+    }
+
+    /// Remove the dots of the local part, so `j.doe` and `jdoe` are the same.
+    pub fn ignoring_dots(mut self) -> Self {
+        self.ignore_dots = true;
+        self
+    }
+
+    /// Compare the local part case-insensitively.
+    pub fn ignoring_case(mut self) -> Self {
+        self.ignore_case = true;
+        self
+    }
+
+    /// The form of `address` used to compare it, with the domain as returned by
+    /// [`normalize_domain`].
+    pub fn normalize(&self, address: &Address<'_>) -> String {
+        let domain = normalize_domain(address.domain());
+        let local_part = address.local_part();
+        if local_part.starts_with('"') {
+            return format!("{local_part}@{domain}");
+        }
+        let user = match self.detail_separator {
+            Some(separator) => address.split_detail(separator).0,
+            None => local_part,
+        };
+        let mut normalized = String::with_capacity(address.as_str().len());
+        for c in user.chars().filter(|&c| !(self.ignore_dots && c == '.')) {
+            match self.ignore_case {
+                true => normalized.extend(c.to_lowercase()),
+                false => normalized.push(c),
+            }
+        }
+        normalized.push('@');
+        normalized.push_str(&domain);
+        normalized
+    }
+
+    /// Returns true if `a` and `b` are the same after [normalizing](Self::normalize).
+    pub fn same(&self, a: &Address<'_>, b: &Address<'_>) -> bool {
+        self.normalize(a) == self.normalize(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(rules: Normalization, address: &str) -> String {
+        rules.normalize(&Address::parse(address).unwrap())
+    }
+
+    #[test]
+    fn applies_only_enabled_rules() {
+        let address = "J.Doe+Tag@Example.COM";
+        let rules = Normalization::default();
+        assert_eq!(normalize(rules, address), "J.Doe+Tag@example.com");
+        let rules = rules.with_detail_separator('+');
+        assert_eq!(normalize(rules, address), "J.Doe@example.com");
+        let rules = rules.ignoring_dots();
+        assert_eq!(normalize(rules, address), "JDoe@example.com");
+        let rules = rules.ignoring_case();
+        assert_eq!(normalize(rules, address), "jdoe@example.com");
+        assert_eq!(normalize(rules, "Ö.Z@example.com"), "öz@example.com");
+    }
+
+    #[test]
+    fn keeps_quoted_local_parts() {
+        let rules = Normalization::gmail();
+        assert_eq!(
+            normalize(rules, "\"A.B+c\"@Example.com"),
+            "\"A.B+c\"@example.com"
+        );
+        assert_eq!(normalize(rules, "+tag@example.com"), "+tag@example.com");
+    }
+}
@@ -0,0 +1,71 @@
+use alloc::string::{String, ToString};
+use core::{fmt::Display, str::FromStr};
+
+use super::{AddrRef, Address, SyntaxError};
+
+/// A syntactically valid mailbox which owns its string, for keeping addresses around
+/// after the text they were parsed from is gone.
+///
+/// Parse addresses as an [`AddrRef`] to validate them without `alloc`, and convert them
+/// when they have to be stored.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::address::{AddrRef, EmailAddress};
+///
+/// let address = EmailAddress::from(AddrRef::parse("user@example.com").unwrap());
+/// assert_eq!(address.domain(), "example.com");
+/// assert_eq!("user@example.com".parse::<EmailAddress>(), Ok(address));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EmailAddress {
+    raw: String,
+    at: usize,
+}
+
+impl EmailAddress {
+    /// Borrows the address, to use the methods of [`Address`].
+    pub fn as_addr(&self) -> AddrRef<'_> {
+        Address {
+            raw: &self.raw,
+            at: self.at,
+        }
+    }
+
+    pub fn local_part(&self) -> &str {
+        self.as_addr().local_part()
+    }
+
+    pub fn domain(&self) -> &str {
+        self.as_addr().domain()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+}
+
+impl From<AddrRef<'_>> for EmailAddress {
+    fn from(address: AddrRef<'_>) -> Self {
+        EmailAddress {
+            raw: address.raw.to_string(),
+            at: address.at,
+        }
+    }
+}
+
+impl FromStr for EmailAddress {
+    type Err = SyntaxError;
+
+    /// Same as [`Address::parse`].
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        Address::parse(address).map(EmailAddress::from)
+    }
+}
+
+impl Display for EmailAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
@@ -0,0 +1,411 @@
+//! ARC, which lets a forwarder vouch for the authentication results of a message it changed,
+//! e.g. a mailing list adding a footer and breaking the original DKIM signature.
+//! <https://datatracker.ietf.org/doc/html/rfc8617>
+//!
+//! Every forwarder adds an ARC set of three header fields: the authentication results it
+//! saw, a message signature like a DKIM signature, and a seal over the sets added so far.
+//! [`validate_chain`] checks the sets of a received message, and an [`ArcSealer`] adds the
+//! next set before the message is forwarded. Sealing uses the same [`SigningKey`] and
+//! canonicalization as [`DkimSigner`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! # async fn example(
+//! #     key: impl simple_smtp::dkim::SigningKey,
+//! #     verifier: impl simple_smtp::arc::Verifier,
+//! #     received: &[u8],
+//! # ) -> Result<Vec<u8>, simple_smtp::arc::ArcError> {
+//! use simple_smtp::arc::{ArcSealer, validate_chain};
+//!
+//! let status = validate_chain(received, &verifier).await;
+//! let results = format!("lists.example.org; dkim=pass; arc={}", status.as_str());
+//! let mut sealer = ArcSealer::new(key, "example.org", "lists2025");
+//! let mut forwarded = sealer.seal(received, status, &results)?;
+//! forwarded.extend_from_slice(received);
+//! # Ok(forwarded)
+//! # }
+//! ```
+
+use alloc::{format, vec, vec::Vec};
+use core::fmt::Display;
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    canonicalization::Canonicalization,
+    dkim::{Algorithm, DkimSigner, SigningKey},
+    encoding::base64,
+    message::{Field, MessageRef, ParseError, Signer},
+};
+
+/// The most ARC sets a message may carry.
+/// <https://datatracker.ietf.org/doc/html/rfc8617#section-4.2.1>
+pub const MAX_SETS: usize = 50;
+
+/// The state of the ARC chain of a message, the `cv=` tag of the next seal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainStatus {
+    /// The message has no ARC sets.
+    None,
+    /// Every seal and the latest message signature are valid.
+    Pass,
+    /// The chain is broken, or one of its signatures is invalid.
+    Fail,
+}
+
+impl ChainStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChainStatus::None => "none",
+            ChainStatus::Pass => "pass",
+            ChainStatus::Fail => "fail",
+        }
+    }
+}
+
+/// Checks the signatures of an ARC chain with the public keys of the domains which signed
+/// them.
+pub trait Verifier {
+    /// Returns true if `signature` was made by the `algorithm` key published at
+    /// `<selector>._domainkey.<domain>`, over `data`, of which `digest` is the SHA-256 hash,
+    /// as passed to [`SigningKey::sign`].
+    fn verify(
+        &self,
+        domain: &str,
+        selector: &str,
+        algorithm: Algorithm,
+        data: &[u8],
+        digest: &[u8; 32],
+        signature: &[u8],
+    ) -> impl Future<Output = bool>;
+}
+
+/// Why an [`ArcSealer`] couldn't seal a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArcError {
+    /// The ARC sets of the message are incomplete or numbered wrongly, so there is no next
+    /// instance to add.
+    InvalidChain,
+    /// The latest seal of the message has `cv=fail`, after which no more sets may be added.
+    ChainFailed,
+    /// The message already carries [`MAX_SETS`] sets.
+    TooManySets,
+    /// The status is [`ChainStatus::None`] for a message with ARC sets, or something else for
+    /// one without.
+    StatusMismatch,
+    /// The header of the message isn't a valid list of fields.
+    Malformed(ParseError),
+}
+
+impl Display for ArcError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let msg = match self {
+            ArcError::InvalidChain => "invalid ARC chain",
+            ArcError::ChainFailed => "ARC chain already failed",
+            ArcError::TooManySets => "too many ARC sets",
+            ArcError::StatusMismatch => "ARC chain status doesn't match the message",
+            ArcError::Malformed(e) => return write!(f, "malformed message: {e}"),
+        };
+        f.write_str(msg)
+    }
+}
+
+impl core::error::Error for ArcError {}
+
+/// Adds ARC sets to messages, see the [module](self) docs.
+///
+/// The message signature covers the same header fields as a [`DkimSigner`], and both it and
+/// the seal are canonicalized with [`Relaxed`](Canonicalization::Relaxed). The sealer can be
+/// reused for any number of messages.
+pub struct ArcSealer<'a, K> {
+    signer: DkimSigner<'a, K>,
+}
+
+impl<'a, K: SigningKey> ArcSealer<'a, K> {
+    /// Seals for `domain`, the `d=` tag, with the key published under `selector`, the `s=`
+    /// tag.
+    pub fn new(key: K, domain: &'a str, selector: &'a str) -> Self {
+        ArcSealer {
+            signer: DkimSigner::new(key, domain, selector),
+        }
+    }
+
+    /// The names of the header fields the message signature covers, if present. ARC-Seal
+    /// fields may not be signed.
+    pub fn with_headers(mut self, headers: &'a [&'a str]) -> Self {
+        self.signer = self.signer.with_headers(headers);
+        self
+    }
+
+    /// Adds the time of sealing, the `t=` tag, in seconds since the Unix epoch.
+    pub fn with_timestamp(mut self, timestamp: u64) -> Self {
+        self.signer = self.signer.with_timestamp(timestamp);
+        self
+    }
+
+    /// Returns the next ARC set of `message`, to write in front of it when forwarding.
+    ///
+    /// `status` is the result of [`validate_chain`] for the message as received, and
+    /// `authentication_results` the payload of an Authentication-Results field, starting with
+    /// the name of the forwarder, e.g. `lists.example.org; spf=pass smtp.mailfrom=example.com`.
+    /// <https://datatracker.ietf.org/doc/html/rfc8601#section-2.2>
+    pub fn seal(
+        &mut self,
+        message: &[u8],
+        status: ChainStatus,
+        authentication_results: &str,
+    ) -> Result<Vec<u8>, ArcError> {
+        let message = MessageRef::parse(message).map_err(ArcError::Malformed)?;
+        let fields: Vec<_> = message.fields().collect();
+        let sets = arc_sets(&fields).ok_or(ArcError::InvalidChain)?;
+        if sets
+            .last()
+            .is_some_and(|set| tag(set.seal.raw_value(), "cv") == Some("fail"))
+        {
+            return Err(ArcError::ChainFailed);
+        }
+        if sets.len() == MAX_SETS {
+            return Err(ArcError::TooManySets);
+        }
+        if sets.is_empty() != (status == ChainStatus::None) {
+            return Err(ArcError::StatusMismatch);
+        }
+        let instance = format!("i={}", sets.len() + 1);
+        // https://datatracker.ietf.org/doc/html/rfc8617#section-5.1.1
+        let results =
+            format!("ARC-Authentication-Results: {instance}; {authentication_results}\r\n");
+
+        for field in &fields {
+            let canonical = Canonicalization::Relaxed.header(field.as_raw().as_bytes());
+            self.signer.header(field.name(), &canonical);
+        }
+        self.signer
+            .body(&Canonicalization::Relaxed.body(message.body()));
+        let signature = self
+            .signer
+            .message_signature("ARC-Message-Signature", &instance);
+
+        // the seal covers every set in order, its own without the seal
+        let mut data = sealed_data(&sets);
+        data.extend(Canonicalization::Relaxed.header(results.as_bytes()));
+        data.extend(Canonicalization::Relaxed.header(&signature));
+        let cv = format!("cv={}", status.as_str());
+        let seal = self.signer.start_field("ARC-Seal", &instance, &cv);
+        let mut set = self.signer.sign_field(seal, data);
+        set.extend(signature);
+        set.extend(results.into_bytes());
+        Ok(set)
+    }
+}
+
+/// Checks the ARC sets of a received message: that they are complete and numbered in order,
+/// that every seal is valid, and that the latest message signature is.
+/// <https://datatracker.ietf.org/doc/html/rfc8617#section-5.2>
+pub async fn validate_chain(message: &[u8], verifier: &impl Verifier) -> ChainStatus {
+    let Ok(message) = MessageRef::parse(message) else {
+        return ChainStatus::Fail;
+    };
+    let fields: Vec<_> = message.fields().collect();
+    let Some(sets) = arc_sets(&fields) else {
+        return ChainStatus::Fail;
+    };
+    let Some(latest) = sets.last() else {
+        return ChainStatus::None;
+    };
+    // only the first seal has nothing to vouch for, a failed chain stays failed
+    for (index, set) in sets.iter().enumerate() {
+        let expected = if index == 0 { "none" } else { "pass" };
+        if tag(set.seal.raw_value(), "cv") != Some(expected) {
+            return ChainStatus::Fail;
+        }
+    }
+    if !verify_message_signature(&fields, message.body(), latest, verifier).await {
+        return ChainStatus::Fail;
+    }
+    for (index, set) in sets.iter().enumerate().rev() {
+        let mut data = sealed_data(&sets[..index]);
+        data.extend(Canonicalization::Relaxed.header(set.results.as_raw().as_bytes()));
+        data.extend(Canonicalization::Relaxed.header(set.signature.as_raw().as_bytes()));
+        if !verify(set.seal, Canonicalization::Relaxed, data, verifier).await {
+            return ChainStatus::Fail;
+        }
+    }
+    ChainStatus::Pass
+}
+
+async fn verify_message_signature(
+    fields: &[Field<'_>],
+    body: &[u8],
+    set: &ArcSet<'_>,
+    verifier: &impl Verifier,
+) -> bool {
+    let tags = set.signature.raw_value();
+    let Some((header, body_canonicalization)) = canonicalizations(tag(tags, "c")) else {
+        return false;
+    };
+    let body_hash = Sha256::digest(body_canonicalization.body(body));
+    if tag(tags, "bh").and_then(decode).as_deref() != Some(&body_hash[..]) {
+        return false;
+    }
+
+    // names listed more than once stand for their instances from the bottom up
+    // https://datatracker.ietf.org/doc/html/rfc6376#section-5.4.2
+    let mut signed = vec![false; fields.len()];
+    let mut data = Vec::new();
+    for name in tag(tags, "h").unwrap_or_default().split(':') {
+        let name = name.trim();
+        let field = fields
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(i, field)| !signed[*i] && field.name().eq_ignore_ascii_case(name));
+        // fields which aren't there are signed as nothing
+        if let Some((i, field)) = field {
+            signed[i] = true;
+            data.extend(header.header(field.as_raw().as_bytes()));
+        }
+    }
+    verify(set.signature, header, data, verifier).await
+}
+
+// checks the `b=` tag of `field`, which signs `data` followed by the field itself without
+// the signature
+async fn verify(
+    field: Field<'_>,
+    canonicalization: Canonicalization,
+    mut data: Vec<u8>,
+    verifier: &impl Verifier,
+) -> bool {
+    let tags = field.raw_value();
+    let algorithm = tag(tags, "a").and_then(|a| {
+        [Algorithm::RsaSha256, Algorithm::Ed25519Sha256]
+            .into_iter()
+            .find(|algorithm| algorithm.as_str() == a)
+    });
+    let (Some(algorithm), Some(domain), Some(selector), Some(signature)) = (
+        algorithm,
+        tag(tags, "d"),
+        tag(tags, "s"),
+        tag(tags, "b").and_then(decode),
+    ) else {
+        return false;
+    };
+    let canonical = canonicalization.header(&without_signature(field.as_raw().as_bytes()));
+    data.extend_from_slice(canonical.strip_suffix(b"\r\n").unwrap_or(&canonical));
+    let digest: [u8; 32] = Sha256::digest(&data).into();
+    verifier
+        .verify(domain, selector, algorithm, &data, &digest, &signature)
+        .await
+}
+
+// the canonicalized fields of complete sets, as a seal signs them
+fn sealed_data(sets: &[ArcSet<'_>]) -> Vec<u8> {
+    sets.iter()
+        .flat_map(|set| [set.results, set.signature, set.seal])
+        .flat_map(|field| Canonicalization::Relaxed.header(field.as_raw().as_bytes()))
+        .collect()
+}
+
+// the fields of one ARC set
+struct ArcSet<'m> {
+    results: Field<'m>,
+    signature: Field<'m>,
+    seal: Field<'m>,
+}
+
+// the ARC sets in order of their instance, or None unless every instance up to the latest
+// has exactly one field of each kind
+fn arc_sets<'m>(fields: &[Field<'m>]) -> Option<Vec<ArcSet<'m>>> {
+    let mut sets: Vec<[Option<Field<'m>>; 3]> = Vec::new();
+    for field in fields {
+        let kind = [
+            "ARC-Authentication-Results",
+            "ARC-Message-Signature",
+            "ARC-Seal",
+        ]
+        .iter()
+        .position(|name| field.name().eq_ignore_ascii_case(name));
+        let Some(kind) = kind else {
+            continue;
+        };
+        let instance: usize = tag(field.raw_value(), "i")?.parse().ok()?;
+        if !(1..=MAX_SETS).contains(&instance) {
+            return None;
+        }
+        if sets.len() < instance {
+            sets.resize(instance, [None; 3]);
+        }
+        let slot = &mut sets[instance - 1][kind];
+        if slot.replace(*field).is_some() {
+            return None;
+        }
+    }
+    sets.into_iter()
+        .map(|set| match set {
+            [Some(results), Some(signature), Some(seal)] => Some(ArcSet {
+                results,
+                signature,
+                seal,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+// the value of the tag `name` in a tag list, `name=value; ...`
+// https://datatracker.ietf.org/doc/html/rfc6376#section-3.2
+fn tag<'v>(tags: &'v str, name: &str) -> Option<&'v str> {
+    tags.split(';').find_map(|tag| {
+        let (tag, value) = tag.split_once('=')?;
+        (tag.trim() == name).then_some(value.trim())
+    })
+}
+
+// the header and body canonicalization of a `c=` tag, simple unless given
+fn canonicalizations(tag: Option<&str>) -> Option<(Canonicalization, Canonicalization)> {
+    let parse = |name: &str| match name {
+        "simple" => Some(Canonicalization::Simple),
+        "relaxed" => Some(Canonicalization::Relaxed),
+        _ => None,
+    };
+    let tag = tag.unwrap_or("simple");
+    match tag.split_once('/') {
+        Some((header, body)) => Some((parse(header)?, parse(body)?)),
+        None => Some((parse(tag)?, Canonicalization::Simple)),
+    }
+}
+
+// the field with the value of its `b=` tag removed, as it was signed
+// https://datatracker.ietf.org/doc/html/rfc6376#section-3.7
+fn without_signature(field: &[u8]) -> Vec<u8> {
+    let end = field.strip_suffix(b"\r\n").unwrap_or(field).len();
+    let mut start = field.iter().position(|&b| b == b':').map_or(end, |i| i + 1);
+    while start < end {
+        let tag_end = field[start..end]
+            .iter()
+            .position(|&b| b == b';')
+            .map_or(end, |i| start + i);
+        let tag = &field[start..tag_end];
+        if let Some(equals) = tag.iter().position(|&b| b == b'=')
+            && tag[..equals].trim_ascii() == b"b"
+        {
+            let mut out = field[..start + equals + 1].to_vec();
+            out.extend_from_slice(&field[tag_end..]);
+            return out;
+        }
+        start = tag_end + 1;
+    }
+    field.to_vec()
+}
+
+// base64 with folding whitespace, as in `b=` and `bh=` tags
+fn decode(value: &str) -> Option<Vec<u8>> {
+    let mut decoder = base64::Decoder::default();
+    let mut out = Vec::new();
+    for c in value.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        out.extend(decoder.push(c).ok()?);
+    }
+    decoder.finish().ok()?;
+    Some(out)
+}
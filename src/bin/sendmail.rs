@@ -0,0 +1,359 @@
+//! A sendmail replacement submitting to a relay, for cron jobs and tools which pipe their
+//! mail into `sendmail`.
+//!
+//! ```text
+//! simple-sendmail [-t] [-i] [-f sender] [--] [recipient...] < message
+//! ```
+//!
+//! `-t` adds the recipients in the To, Cc and Bcc header fields, and removes the Bcc field.
+//! Without `-i` (or `-oi`), a line with a single dot ends the message. Other sendmail options
+//! are ignored.
+//!
+//! The relay is configured through the environment:
+//!
+//! - `SMTP_RELAY`: the host, optionally with a port, e.g. `smtp.example.com:587`
+//! - `SMTP_TLS`: `starttls` (default), `implicit` or `none`
+//! - `SMTP_USER` and `SMTP_PASSWORD`: credentials, if the relay needs them
+//! - `SMTP_FROM`: the sender without `-f`, instead of the address in the From field
+//!
+//! The exit codes are those of sendmail, from `sysexits.h`.
+
+use std::{
+    env,
+    io::{self, Read},
+    process::ExitCode,
+};
+
+use simple_smtp::{
+    address::{AddressList, Mailbox},
+    envelope::{BodyType, Envelope, Recipient},
+    integrations::tokio::{SmtpClientBuilder, TlsMode},
+    message::MessageRef,
+    smtp::auth::AuthMode,
+};
+
+// sysexits.h
+const EX_USAGE: u8 = 64;
+const EX_DATAERR: u8 = 65;
+const EX_UNAVAILABLE: u8 = 69;
+const EX_TEMPFAIL: u8 = 75;
+const EX_CONFIG: u8 = 78;
+
+struct Failure(u8, String);
+
+impl Failure {
+    fn new(code: u8, message: impl Into<String>) -> Self {
+        Failure(code, message.into())
+    }
+}
+
+#[derive(Default)]
+struct Options {
+    recipients_from_headers: bool,
+    ignore_dots: bool,
+    sender: Option<String>,
+    recipients: Vec<String>,
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure(code, message)) => {
+            eprintln!("sendmail: {message}");
+            ExitCode::from(code)
+        }
+    }
+}
+
+fn run() -> Result<(), Failure> {
+    let options = parse_args(env::args().skip(1))?;
+    let mut input = Vec::new();
+    io::stdin()
+        .read_to_end(&mut input)
+        .map_err(|e| Failure::new(EX_DATAERR, format!("reading the message: {e}")))?;
+    let mut message = to_crlf(&input, options.ignore_dots);
+
+    let mut recipients = options.recipients.clone();
+    if options.recipients_from_headers {
+        let header = parse_header(&message)?;
+        for name in ["To", "Cc", "Bcc"] {
+            for value in header_values(&header, name) {
+                let list = AddressList::parse(&value)
+                    .map_err(|e| Failure::new(EX_DATAERR, format!("invalid {name} field: {e}")))?;
+                recipients.extend(
+                    list.iter()
+                        .map(|mailbox| mailbox.address().as_str().to_owned()),
+                );
+            }
+        }
+        message = remove_header(&header, "Bcc");
+    }
+    if recipients.is_empty() {
+        return Err(Failure::new(EX_USAGE, "no recipients"));
+    }
+    let sender = match options.sender.or_else(|| env::var("SMTP_FROM").ok()) {
+        Some(sender) => sender,
+        None => header_values(&parse_header(&message)?, "From")
+            .into_iter()
+            .next()
+            .and_then(|from| {
+                Some(
+                    Mailbox::parse(from.trim())
+                        .ok()?
+                        .address()
+                        .as_str()
+                        .to_owned(),
+                )
+            })
+            .ok_or_else(|| Failure::new(EX_USAGE, "no sender, use -f or a From field"))?,
+    };
+
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| Failure::new(EX_UNAVAILABLE, e.to_string()))?
+        .block_on(submit(&sender, &recipients, &message))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, Failure> {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--" => {
+                options.recipients.extend(args.by_ref());
+                break;
+            }
+            "-t" => options.recipients_from_headers = true,
+            "-i" | "-oi" => options.ignore_dots = true,
+            // the full name and the sender may also be attached to the option
+            "-f" | "-r" | "-F" | "-o" | "-B" | "-N" | "-R" | "-V" | "-X" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| Failure::new(EX_USAGE, format!("{arg} needs a value")))?;
+                if arg == "-f" || arg == "-r" {
+                    options.sender = Some(value);
+                }
+            }
+            _ if arg.starts_with("-f") || arg.starts_with("-r") => {
+                options.sender = Some(arg[2..].to_owned());
+            }
+            // delivery modes other than the default one
+            "-bs" | "-bp" | "-bd" | "-bi" | "-bt" | "-bv" | "-q" => {
+                return Err(Failure::new(EX_USAGE, format!("{arg} is not supported")));
+            }
+            _ if arg.starts_with('-') => {}
+            _ => options.recipients.extend(
+                arg.split(',')
+                    .map(str::trim)
+                    .filter(|r| !r.is_empty())
+                    .map(str::to_owned),
+            ),
+        }
+    }
+    Ok(options)
+}
+
+async fn submit(sender: &str, recipients: &[String], message: &[u8]) -> Result<(), Failure> {
+    let relay =
+        env::var("SMTP_RELAY").map_err(|_| Failure::new(EX_CONFIG, "SMTP_RELAY is not set"))?;
+    let (host, port) = match relay.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') || host.ends_with(']') => {
+            let port = port
+                .parse()
+                .map_err(|_| Failure::new(EX_CONFIG, format!("invalid port in {relay}")))?;
+            (host, Some(port))
+        }
+        _ => (relay.as_str(), None),
+    };
+    let tls = match env::var("SMTP_TLS").as_deref() {
+        Err(_) | Ok("starttls") => TlsMode::StartTls,
+        Ok("implicit") => TlsMode::Implicit,
+        Ok("none") => TlsMode::None,
+        Ok(other) => return Err(Failure::new(EX_CONFIG, format!("unknown SMTP_TLS {other}"))),
+    };
+    let (user, password) = (env::var("SMTP_USER").ok(), env::var("SMTP_PASSWORD").ok());
+    let auth = match (&user, &password) {
+        (Some(username), Some(password)) => AuthMode::Plain { username, password },
+        _ => AuthMode::None,
+    };
+
+    let mut builder = SmtpClientBuilder::new(host).with_tls(tls).with_auth(auth);
+    if let Some(port) = port {
+        builder = builder.with_port(port);
+    }
+    let mut smtp = builder.connect().await.map_err(|e| failure(&e))?;
+    let recipients: Vec<Recipient<'_>> = recipients.iter().map(|r| Recipient::new(r)).collect();
+    let mut envelope = Envelope::new(sender, &recipients);
+    if !message.is_ascii() {
+        envelope = envelope.with_body(BodyType::EightBitMime);
+    }
+    smtp.send_envelope(&envelope, message)
+        .await
+        .map_err(|e| failure(&e))?;
+    let _ = smtp.quit().await;
+    Ok(())
+}
+
+fn failure(e: &simple_smtp::Error<io::Error>) -> Failure {
+    let code = if e.is_permanent() {
+        EX_UNAVAILABLE
+    } else {
+        EX_TEMPFAIL
+    };
+    Failure::new(code, e.to_string())
+}
+
+// local mail uses bare line feeds, SMTP needs CRLF
+fn to_crlf(input: &[u8], ignore_dots: bool) -> Vec<u8> {
+    let mut message = Vec::with_capacity(input.len());
+    for line in input.split_inclusive(|&b| b == b'\n') {
+        let content = line.strip_suffix(b"\n").unwrap_or(line);
+        let content = content.strip_suffix(b"\r").unwrap_or(content);
+        if !ignore_dots && content == b"." {
+            break;
+        }
+        message.extend_from_slice(content);
+        message.extend_from_slice(b"\r\n");
+    }
+    message
+}
+
+fn parse_header(message: &[u8]) -> Result<MessageRef<'_>, Failure> {
+    MessageRef::parse(message)
+        .map_err(|e| Failure::new(EX_DATAERR, format!("reading the message: {e}")))
+}
+
+// the unfolded values of every field called `name`
+fn header_values(message: &MessageRef<'_>, name: &str) -> Vec<String> {
+    message
+        .fields_named(name)
+        .map(|field| field.value().to_string())
+        .collect()
+}
+
+fn remove_header(message: &MessageRef<'_>, name: &str) -> Vec<u8> {
+    let mut out: Vec<u8> = message
+        .fields()
+        .filter(|field| !field.name().eq_ignore_ascii_case(name))
+        .flat_map(|field| field.as_raw().bytes())
+        .collect();
+    out.extend_from_slice(&message.as_bytes()[message.header().len()..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Options, Failure> {
+        parse_args(args.iter().map(|arg| (*arg).to_owned()))
+    }
+
+    #[test]
+    fn sender_attached_or_separate() {
+        let attached = args(&["-fme@example.org", "you@example.com"]).ok().unwrap();
+        assert_eq!(attached.sender.as_deref(), Some("me@example.org"));
+        assert_eq!(attached.recipients, ["you@example.com"]);
+        let separate = args(&["-f", "me@example.org", "you@example.com"])
+            .ok()
+            .unwrap();
+        assert_eq!(separate.sender.as_deref(), Some("me@example.org"));
+        assert_eq!(separate.recipients, ["you@example.com"]);
+        let full_name = args(&["-F", "Me", "-r", "me@example.org"]).ok().unwrap();
+        assert_eq!(full_name.sender.as_deref(), Some("me@example.org"));
+        assert!(full_name.recipients.is_empty());
+        assert_eq!(args(&["-f"]).err().unwrap().0, EX_USAGE);
+    }
+
+    #[test]
+    fn options_and_recipients() {
+        let options = args(&["-t", "-oi", "-oem", "a@example.com, b@example.com", "-x"])
+            .ok()
+            .unwrap();
+        assert!(options.recipients_from_headers);
+        assert!(options.ignore_dots);
+        assert_eq!(options.recipients, ["a@example.com", "b@example.com"]);
+        assert!(!args(&[]).ok().unwrap().ignore_dots);
+        assert_eq!(args(&["-bs"]).err().unwrap().0, EX_USAGE);
+    }
+
+    #[test]
+    fn everything_after_double_dash_is_a_recipient() {
+        let options = args(&["-i", "--", "-t", "a@example.com"]).ok().unwrap();
+        assert!(options.ignore_dots);
+        assert!(!options.recipients_from_headers);
+        assert_eq!(options.recipients, ["-t", "a@example.com"]);
+    }
+
+    #[test]
+    fn lone_dot_ends_the_message() {
+        let input = b"Subject: hi\n\none\n..two\n.\nnot sent\n";
+        assert_eq!(
+            to_crlf(input, false),
+            b"Subject: hi\r\n\r\none\r\n..two\r\n"
+        );
+        // with -i the dot is message content
+        assert_eq!(
+            to_crlf(input, true),
+            b"Subject: hi\r\n\r\none\r\n..two\r\n.\r\nnot sent\r\n"
+        );
+    }
+
+    #[test]
+    fn line_endings_become_crlf() {
+        assert_eq!(to_crlf(b"a\r\nb\nc", false), b"a\r\nb\r\nc\r\n");
+        assert_eq!(to_crlf(b".\r\n", false), b"");
+        assert_eq!(to_crlf(b"", false), b"");
+    }
+
+    #[test]
+    fn folded_fields_are_unfolded() {
+        let message = parse_header(
+            b"To: a@example.com,\r\n\tb@example.com\r\nto : c@example.com\r\n\r\nTo: body\r\n",
+        )
+        .ok()
+        .unwrap();
+        assert_eq!(message.fields().count(), 2);
+        assert_eq!(
+            header_values(&message, "To"),
+            ["a@example.com,\tb@example.com", "c@example.com"]
+        );
+        assert!(header_values(&message, "Cc").is_empty());
+    }
+
+    #[test]
+    fn headers_without_body_or_before_leading_crlf() {
+        let no_body = parse_header(b"From: me@example.org\r\nTo: you@example.com\r\n")
+            .ok()
+            .unwrap();
+        assert_eq!(header_values(&no_body, "To"), ["you@example.com"]);
+        // an empty first line ends the empty header, the rest is body
+        let leading_crlf = parse_header(b"\r\nTo: you@example.com\r\n").ok().unwrap();
+        assert_eq!(leading_crlf.fields().count(), 0);
+        assert!(header_values(&leading_crlf, "To").is_empty());
+    }
+
+    fn without_bcc(message: &[u8]) -> Vec<u8> {
+        remove_header(&parse_header(message).ok().unwrap(), "Bcc")
+    }
+
+    #[test]
+    fn folded_bcc_is_removed() {
+        let message = b"To: a@example.com\r\nBcc: b@example.com,\r\n c@example.com\r\nBCC:d@example.com\r\nBcc-Note: kept\r\n\r\nBcc: in the body\r\n";
+        assert_eq!(
+            without_bcc(message),
+            b"To: a@example.com\r\nBcc-Note: kept\r\n\r\nBcc: in the body\r\n"
+        );
+    }
+
+    #[test]
+    fn bcc_is_removed_without_body() {
+        let message = b"Bcc: b@example.com\r\nTo: a@example.com\r\n";
+        assert_eq!(without_bcc(message), b"To: a@example.com\r\n");
+        let last = b"To: a@example.com\r\nBcc: b@example.com";
+        assert_eq!(without_bcc(last), b"To: a@example.com\r\n");
+        // no header at all, nothing to remove
+        let leading_crlf = b"\r\nBcc: b@example.com\r\n";
+        assert_eq!(without_bcc(leading_crlf), leading_crlf);
+    }
+}
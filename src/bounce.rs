@@ -0,0 +1,286 @@
+//! Parsing bounces, the delivery status notifications mail servers send back when a message
+//! couldn't be delivered, e.g. to process the bounces arriving in a monitored mailbox.
+//! <https://datatracker.ietf.org/doc/html/rfc3464>
+//!
+//! A notification is a `multipart/report` message with a human readable part, a
+//! `message/delivery-status` part with a block of fields for every recipient, and usually
+//! the original message. [`parse`] reads the delivery status part, the others are ignored.
+//!
+//! # Example
+//!
+//! ```
+//! use simple_smtp::bounce;
+//!
+//! let message = b"Content-Type: multipart/report; report-type=delivery-status;\r\n\
+//!     \tboundary=\"XX\"\r\n\
+//!     \r\n\
+//!     --XX\r\n\
+//!     Content-Type: message/delivery-status\r\n\
+//!     \r\n\
+//!     Reporting-MTA: dns; mx.example.com\r\n\
+//!     \r\n\
+//!     Final-Recipient: rfc822; bob@example.com\r\n\
+//!     Action: failed\r\n\
+//!     Status: 5.1.1\r\n\
+//!     Diagnostic-Code: smtp; 550 5.1.1 User unknown\r\n\
+//!     --XX--\r\n";
+//! let dsn = bounce::parse(message).unwrap();
+//! for recipient in dsn.failed() {
+//!     assert_eq!(recipient.final_recipient, "bob@example.com");
+//!     assert_eq!(recipient.status.to_string(), "5.1.1");
+//! }
+//! ```
+
+use alloc::{
+    borrow::ToOwned,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Display;
+
+use crate::message::{MessageRef, ParseError};
+use crate::smtp::enhanced::EnhancedCode;
+
+/// The contents of a delivery status notification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dsn {
+    /// The host which sent the notification, from `Reporting-MTA`, without its type.
+    pub reporting_mta: Option<String>,
+    /// The `ENVID` the message was sent with, from `Original-Envelope-Id`, see
+    /// [`Envelope::with_envid`](crate::envelope::Envelope::with_envid).
+    pub envelope_id: Option<String>,
+    /// The recipients the notification is about, in the order it lists them.
+    pub recipients: Vec<RecipientStatus>,
+}
+
+impl Dsn {
+    /// The recipients the message couldn't be delivered to.
+    pub fn failed(&self) -> impl Iterator<Item = &RecipientStatus> {
+        self.recipients
+            .iter()
+            .filter(|recipient| recipient.action == Action::Failed)
+    }
+}
+
+/// What happened to the message for one recipient.
+/// <https://datatracker.ietf.org/doc/html/rfc3464#section-2.3>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecipientStatus {
+    /// The address given in `RCPT TO`, from `Original-Recipient`, if the `ORCPT` parameter
+    /// was sent, see [`Recipient::with_orcpt`](crate::envelope::Recipient::with_orcpt).
+    pub original_recipient: Option<String>,
+    /// The address the delivery was attempted to, from `Final-Recipient`, which may differ
+    /// from the original one after forwarding.
+    pub final_recipient: String,
+    pub action: Action,
+    pub status: EnhancedCode,
+    /// The host which reported the failure, from `Remote-MTA`.
+    pub remote_mta: Option<String>,
+    /// The reply of the host which reported the failure, from `Diagnostic-Code`, without
+    /// its type, e.g. `550 5.1.1 User unknown`.
+    pub diagnostic_code: Option<String>,
+}
+
+/// The `Action` field of a recipient.
+/// <https://datatracker.ietf.org/doc/html/rfc3464#section-2.3.3>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// The message couldn't be delivered, and won't be retried.
+    Failed,
+    /// Delivery failed so far, but is still being retried.
+    Delayed,
+    Delivered,
+    /// The message was passed on to a system which doesn't send notifications.
+    Relayed,
+    /// The message was delivered, and forwarded to more recipients.
+    Expanded,
+}
+
+impl Action {
+    fn parse(value: &str) -> Option<Self> {
+        // the value may be followed by a comment
+        let action = value.split_whitespace().next()?;
+        [
+            Action::Failed,
+            Action::Delayed,
+            Action::Delivered,
+            Action::Relayed,
+            Action::Expanded,
+        ]
+        .into_iter()
+        .find(|candidate| candidate.as_str().eq_ignore_ascii_case(action))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::Failed => "failed",
+            Action::Delayed => "delayed",
+            Action::Delivered => "delivered",
+            Action::Relayed => "relayed",
+            Action::Expanded => "expanded",
+        }
+    }
+}
+
+/// Why a message couldn't be [parsed](parse) as a delivery status notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BounceError {
+    /// The message isn't a `multipart/report` with `report-type=delivery-status`.
+    NotAReport,
+    /// The report has no `message/delivery-status` part.
+    NoDeliveryStatus,
+    /// A recipient lacks a required field.
+    MissingField(&'static str),
+    /// A field has a value which isn't allowed.
+    InvalidField(&'static str),
+    /// The header of the message, or a block of the delivery status, isn't a valid list of
+    /// fields.
+    Malformed(ParseError),
+}
+
+impl Display for BounceError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BounceError::NotAReport => f.write_str("not a delivery status notification"),
+            BounceError::NoDeliveryStatus => f.write_str("no delivery status in the report"),
+            BounceError::MissingField(name) => write!(f, "missing {name} field"),
+            BounceError::InvalidField(name) => write!(f, "invalid {name} field"),
+            BounceError::Malformed(e) => write!(f, "malformed notification: {e}"),
+        }
+    }
+}
+
+impl core::error::Error for BounceError {}
+
+/// Parses a delivery status notification, the whole message as received, with CRLF or LF
+/// line endings. Its header, and those of its parts, are read as a [`MessageRef`].
+///
+/// `message/global-delivery-status` parts with internationalized addresses are read the
+/// same way. Transfer encodings aren't undone, as the delivery status is plain 7-bit text.
+/// <https://datatracker.ietf.org/doc/html/rfc6533#section-6>
+pub fn parse(message: &[u8]) -> Result<Dsn, BounceError> {
+    let message = MessageRef::parse(message).map_err(BounceError::Malformed)?;
+    let (media_type, parameters) = content_type(&message).ok_or(BounceError::NotAReport)?;
+    let report_type = parameter(&parameters, "report-type");
+    if !media_type.eq_ignore_ascii_case("multipart/report")
+        || !report_type.is_some_and(|t| t.eq_ignore_ascii_case("delivery-status"))
+    {
+        return Err(BounceError::NotAReport);
+    }
+    let boundary = parameter(&parameters, "boundary").ok_or(BounceError::NotAReport)?;
+    let status = parts(message.body(), boundary)
+        .into_iter()
+        .filter_map(|part| MessageRef::parse(part).ok())
+        .find(|part| {
+            content_type(part).is_some_and(|(media_type, _)| {
+                media_type.eq_ignore_ascii_case("message/delivery-status")
+                    || media_type.eq_ignore_ascii_case("message/global-delivery-status")
+            })
+        })
+        .ok_or(BounceError::NoDeliveryStatus)?;
+    parse_delivery_status(status.body())
+}
+
+// the per-message fields, followed by a group of fields for every recipient
+// https://datatracker.ietf.org/doc/html/rfc3464#section-2.1
+fn parse_delivery_status(status: &[u8]) -> Result<Dsn, BounceError> {
+    let mut groups = groups(status)?.into_iter();
+    let mut dsn = Dsn {
+        reporting_mta: None,
+        envelope_id: None,
+        recipients: Vec::new(),
+    };
+    if let Some(message) = groups.next() {
+        dsn.reporting_mta = field(&message, "Reporting-MTA")
+            .as_deref()
+            .map(without_type);
+        dsn.envelope_id = field(&message, "Original-Envelope-Id");
+    }
+    for group in groups {
+        let required =
+            |name: &'static str| field(&group, name).ok_or(BounceError::MissingField(name));
+        let optional = |name| field(&group, name).as_deref().map(without_type);
+        let action =
+            Action::parse(&required("Action")?).ok_or(BounceError::InvalidField("Action"))?;
+        let (status, _) = EnhancedCode::parse_prefix(&required("Status")?)
+            .ok_or(BounceError::InvalidField("Status"))?;
+        dsn.recipients.push(RecipientStatus {
+            original_recipient: optional("Original-Recipient"),
+            final_recipient: without_type(&required("Final-Recipient")?),
+            action,
+            status,
+            remote_mta: optional("Remote-MTA"),
+            diagnostic_code: optional("Diagnostic-Code"),
+        });
+    }
+    Ok(dsn)
+}
+
+// the blocks of fields separated by empty lines
+fn groups(mut rest: &[u8]) -> Result<Vec<MessageRef<'_>>, BounceError> {
+    let mut groups = Vec::new();
+    while !rest.is_empty() {
+        let group = MessageRef::parse(rest).map_err(BounceError::Malformed)?;
+        if group.fields().next().is_some() {
+            groups.push(group);
+        }
+        rest = group.body();
+    }
+    Ok(groups)
+}
+
+// the unfolded value of the first field called `name`
+fn field(group: &MessageRef<'_>, name: &str) -> Option<String> {
+    Some(group.field(name)?.value().to_string())
+}
+
+// the media type of the Content-Type field of a header, and its parameters
+fn content_type(message: &MessageRef<'_>) -> Option<(String, String)> {
+    let value = field(message, "Content-Type")?;
+    let (content_type, parameters) = value.split_once(';').unwrap_or((&value, ""));
+    Some((content_type.trim().to_owned(), parameters.to_owned()))
+}
+
+// the value of a Content-Type parameter, unquoted
+fn parameter<'p>(parameters: &'p str, name: &str) -> Option<&'p str> {
+    parameters.split(';').find_map(|parameter| {
+        let (key, value) = parameter.split_once('=')?;
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+        key.trim().eq_ignore_ascii_case(name).then_some(value)
+    })
+}
+
+// the contents between the boundary delimiter lines of a multipart body
+// https://datatracker.ietf.org/doc/html/rfc2046#section-5.1.1
+fn parts<'b>(body: &'b [u8], boundary: &str) -> Vec<&'b [u8]> {
+    let delimiter = format!("--{boundary}");
+    let mut parts = Vec::new();
+    let mut start = None;
+    let mut offset = 0;
+    for line in body.split_inclusive(|&b| b == b'\n') {
+        if let Some(rest) = line.trim_ascii_end().strip_prefix(delimiter.as_bytes())
+            && (rest.is_empty() || rest == b"--")
+        {
+            if let Some(start) = start {
+                parts.push(&body[start..offset]);
+            }
+            if rest == b"--" {
+                break;
+            }
+            start = Some(offset + line.len());
+        }
+        offset += line.len();
+    }
+    parts
+}
+
+// `rfc822; joe@example.com` without its type
+fn without_type(value: &str) -> String {
+    let value = value.split_once(';').map_or(value, |(_, value)| value);
+    value.trim().to_owned()
+}
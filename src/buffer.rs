@@ -32,6 +32,30 @@ impl DerefMut for Buffer<'_> {
         }
     }
 }
+impl Buffer<'_> {
+    /// Doubles the size of an owned buffer, up to `max` bytes, keeping its content.
+    /// Returns false if the buffer is borrowed or already at `max`.
+    pub(crate) fn grow(&mut self, max: usize) -> bool {
+        match self {
+            #[cfg(feature = "alloc")]
+            Buffer::Owned(v) => {
+                let len = v.len().saturating_mul(2).clamp(1, max.max(1));
+                if len <= v.len() {
+                    return false;
+                }
+                let mut grown = alloc::vec![0; len];
+                grown[..v.len()].copy_from_slice(v);
+                *v = grown.into_boxed_slice();
+                true
+            }
+            Buffer::Borrowed(_) => {
+                let _ = max;
+                false
+            }
+        }
+    }
+}
+
 #[cfg(feature = "alloc")]
 impl From<Vec<u8>> for Buffer<'static> {
     fn from(v: Vec<u8>) -> Self {
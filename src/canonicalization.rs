@@ -0,0 +1,256 @@
+//! The canonical forms DKIM signs, so a message still verifies after mail servers made
+//! harmless changes to it.
+//! <https://datatracker.ietf.org/doc/html/rfc6376#section-3.4>
+
+use alloc::vec::Vec;
+
+/// How much a header field or body may have been changed in transit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Canonicalization {
+    /// Tolerates nothing but empty lines added at the end of the body.
+    Simple,
+    /// Also tolerates changes to whitespace and the case of header field names.
+    Relaxed,
+}
+
+fn is_wsp(byte: u8) -> bool {
+    byte == b' ' || byte == b'\t'
+}
+
+impl Canonicalization {
+    /// Canonicalizes one header field, `name: value` including the final `\r\n`.
+    pub fn header(self, field: &[u8]) -> Vec<u8> {
+        // https://datatracker.ietf.org/doc/html/rfc6376#section-3.4.1
+        if self == Canonicalization::Simple {
+            return field.to_vec();
+        }
+        // https://datatracker.ietf.org/doc/html/rfc6376#section-3.4.2
+        let (name, value) = match field.iter().position(|&b| b == b':') {
+            Some(colon) => (&field[..colon], &field[colon + 1..]),
+            None => (field, &[][..]),
+        };
+        let mut out: Vec<u8> = name.trim_ascii_end().to_ascii_lowercase();
+        out.push(b':');
+        let mut pending_space = false;
+        for &byte in value {
+            if byte == b'\r' || byte == b'\n' || is_wsp(byte) {
+                // unfolded, and runs of whitespace reduced to one space
+                pending_space = true;
+                continue;
+            }
+            // whitespace after the colon is removed
+            if pending_space && out.last() != Some(&b':') {
+                out.push(b' ');
+            }
+            pending_space = false;
+            out.push(byte);
+        }
+        out.extend_from_slice(b"\r\n");
+        out
+    }
+
+    /// Canonicalizes a message body, everything after the empty line ending the header.
+    pub fn body(self, body: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(body.len() + 2);
+        let mut lines = body.split_inclusive(|&b| b == b'\n').peekable();
+        while let Some(line) = lines.next() {
+            let (content, ending) = match line.strip_suffix(b"\r\n") {
+                Some(content) => (content, &b"\r\n"[..]),
+                None => (line, &b""[..]),
+            };
+            match self {
+                // https://datatracker.ietf.org/doc/html/rfc6376#section-3.4.3
+                Canonicalization::Simple => out.extend_from_slice(content),
+                // https://datatracker.ietf.org/doc/html/rfc6376#section-3.4.4
+                Canonicalization::Relaxed => {
+                    let mut pending_space = false;
+                    for &byte in content {
+                        if is_wsp(byte) {
+                            pending_space = true;
+                            continue;
+                        }
+                        if pending_space {
+                            out.push(b' ');
+                        }
+                        pending_space = false;
+                        out.push(byte);
+                    }
+                }
+            }
+            // the last line is completed, as if the body ended with a line break
+            if !ending.is_empty() || lines.peek().is_none() {
+                out.extend_from_slice(b"\r\n");
+            }
+        }
+        // empty lines at the end are ignored
+        while out.ends_with(b"\r\n\r\n") {
+            out.truncate(out.len() - 2);
+        }
+        if out == b"\r\n" && self == Canonicalization::Relaxed {
+            out.clear();
+        }
+        if out.is_empty() && self == Canonicalization::Simple {
+            out.extend_from_slice(b"\r\n");
+        }
+        out
+    }
+}
+
+/// Canonicalizes a body piece by piece, with the same result as [`Canonicalization::body`],
+/// so a signer can hash a message while it is sent.
+///
+/// Empty lines are held back until something follows them, as they are dropped at the end
+/// of the body.
+pub struct BodyCanonicalizer {
+    canonicalization: Canonicalization,
+    // a carriage return at the end of the last piece, which may start a line break
+    held_cr: bool,
+    pending_space: bool,
+    // line breaks which are only written once more content follows
+    pending_line_breaks: usize,
+    // nothing but line breaks was seen so far
+    empty: bool,
+    at_line_start: bool,
+}
+
+impl BodyCanonicalizer {
+    pub fn new(canonicalization: Canonicalization) -> Self {
+        BodyCanonicalizer {
+            canonicalization,
+            held_cr: false,
+            pending_space: false,
+            pending_line_breaks: 0,
+            empty: true,
+            at_line_start: true,
+        }
+    }
+
+    /// Canonicalizes the next piece of the body, passing the output to `out`.
+    pub fn feed(&mut self, chunk: &[u8], mut out: impl FnMut(&[u8])) {
+        for &byte in chunk {
+            if core::mem::take(&mut self.held_cr) {
+                if byte == b'\n' {
+                    self.line_break();
+                    continue;
+                }
+                self.content(b'\r', &mut out);
+            }
+            if byte == b'\r' {
+                self.held_cr = true;
+            } else {
+                self.content(byte, &mut out);
+            }
+        }
+    }
+
+    /// Writes the end of the body.
+    pub fn finish(mut self, mut out: impl FnMut(&[u8])) {
+        if core::mem::take(&mut self.held_cr) {
+            self.content(b'\r', &mut out);
+        }
+        // the last line is completed, as if the body ended with a line break
+        if !self.at_line_start {
+            self.line_break();
+        }
+        // empty lines at the end are ignored, an empty body is a single line break for
+        // simple and nothing for relaxed
+        let line_break = match self.empty {
+            true => self.canonicalization == Canonicalization::Simple,
+            false => self.pending_line_breaks > 0,
+        };
+        if line_break {
+            out(b"\r\n");
+        }
+    }
+
+    fn line_break(&mut self) {
+        self.pending_space = false;
+        self.pending_line_breaks += 1;
+        self.at_line_start = true;
+    }
+
+    fn content(&mut self, byte: u8, out: &mut impl FnMut(&[u8])) {
+        self.at_line_start = false;
+        if self.canonicalization == Canonicalization::Relaxed && is_wsp(byte) {
+            self.pending_space = true;
+            return;
+        }
+        for _ in 0..core::mem::take(&mut self.pending_line_breaks) {
+            out(b"\r\n");
+        }
+        self.empty = false;
+        if core::mem::take(&mut self.pending_space) {
+            out(b" ");
+        }
+        out(&[byte]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // https://datatracker.ietf.org/doc/html/rfc6376#section-3.4.5
+    #[test]
+    fn rfc_examples() {
+        let header = [&b"A: X\r\n"[..], b"B : Y\t\r\n\tZ  \r\n"];
+        let body = b" C \r\nD \t E\r\n\r\n\r\n";
+        let relaxed: Vec<u8> = header
+            .iter()
+            .flat_map(|field| Canonicalization::Relaxed.header(field))
+            .collect();
+        assert_eq!(relaxed, b"a:X\r\nb:Y Z\r\n");
+        assert_eq!(Canonicalization::Relaxed.body(body), b" C\r\nD E\r\n");
+        assert_eq!(Canonicalization::Simple.header(header[1]), header[1]);
+        assert_eq!(Canonicalization::Simple.body(body), b" C \r\nD \t E\r\n");
+    }
+
+    fn streamed(canonicalization: Canonicalization, body: &[u8], chunk_len: usize) -> Vec<u8> {
+        let mut canonicalizer = BodyCanonicalizer::new(canonicalization);
+        let mut out = Vec::new();
+        for chunk in body.chunks(chunk_len) {
+            canonicalizer.feed(chunk, |bytes| out.extend_from_slice(bytes));
+        }
+        canonicalizer.finish(|bytes| out.extend_from_slice(bytes));
+        out
+    }
+
+    #[test]
+    fn streaming_matches_body() {
+        let bodies: [&[u8]; 12] = [
+            b"",
+            b"\r\n",
+            b"\r\n\r\n",
+            b" \r\n\t\r\n",
+            b" C \r\nD \t E\r\n\r\n\r\n",
+            b"no line break",
+            b"trailing space \r\n",
+            b"bare\nline feed \n",
+            b"bare\rcarriage return\r",
+            b"\r\r\n\r",
+            b"a\r\n\r\n\r\nb\r\n\r\n",
+            b"  \t\r\n\r\nx  y\t\r\n \r\n",
+        ];
+        for body in bodies {
+            for canonicalization in [Canonicalization::Simple, Canonicalization::Relaxed] {
+                let expected = canonicalization.body(body);
+                for chunk_len in 1..=body.len().max(1) {
+                    assert_eq!(
+                        streamed(canonicalization, body, chunk_len),
+                        expected,
+                        "{canonicalization:?} {:?} in pieces of {chunk_len}",
+                        String::from_utf8_lossy(body)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_bodies() {
+        assert_eq!(Canonicalization::Simple.body(b""), b"\r\n");
+        assert_eq!(Canonicalization::Simple.body(b"\r\n\r\n"), b"\r\n");
+        assert_eq!(Canonicalization::Relaxed.body(b""), b"");
+        assert_eq!(Canonicalization::Relaxed.body(b" \r\n\t\r\n"), b"");
+    }
+}
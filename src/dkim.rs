@@ -0,0 +1,278 @@
+//! DKIM signatures, which let receivers check that a message comes from the domain which
+//! signed it and wasn't changed on the way.
+//! <https://datatracker.ietf.org/doc/html/rfc6376>
+//!
+//! [`DkimSigner`] is a [`Signer`] for
+//! [`Smtp::send_signed_message`](crate::Smtp::send_signed_message): it hashes the message as
+//! it is written, and leaves the signature itself to a [`SigningKey`]. Any RSA or Ed25519
+//! implementation can be plugged in that way, including a key which never leaves a hardware
+//! secure element.
+//!
+//! The public key is published in a TXT record at `<selector>._domainkey.<domain>`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # async fn example(
+//! #     smtp: &mut simple_smtp::Smtp<'_, impl simple_smtp::ReadWrite<Error = std::io::Error>>,
+//! #     envelope: &simple_smtp::envelope::Envelope<'_>,
+//! #     message: &simple_smtp::message::Message<'_>,
+//! # ) -> Result<(), simple_smtp::Error<std::io::Error>> {
+//! # fn secure_element_sign(digest: &[u8; 32]) -> Vec<u8> { unimplemented!() }
+//! use simple_smtp::dkim::{Algorithm, DkimSigner, SigningKey};
+//!
+//! struct SecureElement;
+//!
+//! impl SigningKey for SecureElement {
+//!     fn algorithm(&self) -> Algorithm {
+//!         Algorithm::Ed25519Sha256
+//!     }
+//!
+//!     fn sign(&mut self, _data: &[u8], digest: &[u8; 32]) -> Vec<u8> {
+//!         secure_element_sign(digest)
+//!     }
+//! }
+//!
+//! let mut signer = DkimSigner::new(SecureElement, "example.org", "mail2025");
+//! smtp.send_signed_message(envelope, message, &mut signer).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{canonicalization::Canonicalization, encoding::base64, message::Signer};
+
+/// The algorithm of a [`SigningKey`], the `a=` tag of the signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// RSASSA-PKCS1-v1_5 with SHA-256. Keys need at least 1024 bits, 2048 are recommended.
+    /// <https://datatracker.ietf.org/doc/html/rfc8301#section-3.2>
+    RsaSha256,
+    /// Ed25519 over the SHA-256 hash of the signed data.
+    /// <https://datatracker.ietf.org/doc/html/rfc8463#section-3>
+    Ed25519Sha256,
+}
+
+impl Algorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Algorithm::RsaSha256 => "rsa-sha256",
+            Algorithm::Ed25519Sha256 => "ed25519-sha256",
+        }
+    }
+}
+
+/// The private key a [`DkimSigner`] signs with.
+pub trait SigningKey {
+    fn algorithm(&self) -> Algorithm;
+
+    /// Signs the canonicalized header fields in `data`, of which `digest` is the SHA-256
+    /// hash, and returns the raw signature.
+    ///
+    /// Libraries differ in what they take: [`RsaSha256`](Algorithm::RsaSha256) is a
+    /// signature of `data`, or of `digest` for libraries and secure elements which sign a
+    /// prehashed message. [`Ed25519Sha256`](Algorithm::Ed25519Sha256) is a plain Ed25519
+    /// signature of `digest`.
+    fn sign(&mut self, data: &[u8], digest: &[u8; 32]) -> Vec<u8>;
+}
+
+impl<K: SigningKey> SigningKey for &mut K {
+    fn algorithm(&self) -> Algorithm {
+        (**self).algorithm()
+    }
+
+    fn sign(&mut self, data: &[u8], digest: &[u8; 32]) -> Vec<u8> {
+        (**self).sign(data, digest)
+    }
+}
+
+/// The header fields [`DkimSigner`] signs unless [told otherwise](DkimSigner::with_headers),
+/// those which a receiver shows or acts on.
+pub const DEFAULT_HEADERS: &[&str] = &[
+    "From",
+    "Sender",
+    "Reply-To",
+    "To",
+    "Cc",
+    "Subject",
+    "Date",
+    "Message-ID",
+    "In-Reply-To",
+    "References",
+    "MIME-Version",
+    "Content-Type",
+    "Content-Transfer-Encoding",
+    "List-Unsubscribe",
+    "List-Unsubscribe-Post",
+];
+
+/// Signs messages with DKIM, see the [module](self) docs.
+///
+/// Both the header and the body are canonicalized with
+/// [`Relaxed`](Canonicalization::Relaxed) by default, which survives the whitespace changes
+/// some servers make. The signer can be reused for any number of messages.
+pub struct DkimSigner<'a, K> {
+    key: K,
+    domain: &'a str,
+    selector: &'a str,
+    headers: &'a [&'a str],
+    header_canonicalization: Canonicalization,
+    body_canonicalization: Canonicalization,
+    timestamp: Option<u64>,
+    // the canonicalized fields to sign of the message being written, with their names
+    fields: Vec<(String, Vec<u8>)>,
+    body_hash: Sha256,
+}
+
+impl<'a, K: SigningKey> DkimSigner<'a, K> {
+    /// Signs for `domain`, the `d=` tag, with the key published under `selector`, the `s=`
+    /// tag. The domain has to match the domain of the `From` address, or be a parent
+    /// domain of it, for the signature to count towards DMARC.
+    pub fn new(key: K, domain: &'a str, selector: &'a str) -> Self {
+        DkimSigner {
+            key,
+            domain,
+            selector,
+            headers: DEFAULT_HEADERS,
+            header_canonicalization: Canonicalization::Relaxed,
+            body_canonicalization: Canonicalization::Relaxed,
+            timestamp: None,
+            fields: Vec::new(),
+            body_hash: Sha256::new(),
+        }
+    }
+
+    /// The names of the header fields to sign, if present. `From` is required by DKIM.
+    pub fn with_headers(mut self, headers: &'a [&'a str]) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    pub fn with_canonicalization(
+        mut self,
+        header: Canonicalization,
+        body: Canonicalization,
+    ) -> Self {
+        self.header_canonicalization = header;
+        self.body_canonicalization = body;
+        self
+    }
+
+    /// Adds the time of signing, the `t=` tag, in seconds since the Unix epoch, e.g. the
+    /// [`timestamp`](crate::message::DateTime::timestamp) of a
+    /// [`Clock`](crate::message::Clock).
+    pub fn with_timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+}
+
+impl<K: SigningKey> Signer for DkimSigner<'_, K> {
+    fn header_canonicalization(&self) -> Canonicalization {
+        self.header_canonicalization
+    }
+
+    fn body_canonicalization(&self) -> Canonicalization {
+        self.body_canonicalization
+    }
+
+    fn header(&mut self, name: &str, field: &[u8]) {
+        if self.headers.iter().any(|h| h.eq_ignore_ascii_case(name)) {
+            self.fields.push((name.to_string(), field.to_vec()));
+        }
+    }
+
+    fn body(&mut self, chunk: &[u8]) {
+        self.body_hash.update(chunk);
+    }
+
+    fn signature(&mut self) -> Vec<u8> {
+        self.message_signature("DKIM-Signature", "v=1")
+    }
+}
+
+impl<K: SigningKey> DkimSigner<'_, K> {
+    // the signature of the fields and body passed so far, in a field called `name` with
+    // `first` as its first tags, shared with ARC message signatures
+    pub(crate) fn message_signature(&mut self, name: &str, first: &str) -> Vec<u8> {
+        // a name listed more than once stands for its instances from the bottom up, so the
+        // fields are signed, and listed, in reverse
+        // https://datatracker.ietf.org/doc/html/rfc6376#section-5.4.2
+        let fields = core::mem::take(&mut self.fields);
+        let names: Vec<&str> = fields.iter().rev().map(|(name, _)| name.as_str()).collect();
+        let body_hash = core::mem::take(&mut self.body_hash).finalize();
+
+        let canonicalization = format!(
+            "c={}/{}",
+            tag(self.header_canonicalization),
+            tag(self.body_canonicalization),
+        );
+        let mut field = self.start_field(name, first, &canonicalization);
+        field.push_str(&format!("\r\n\th={};", names.join(":")));
+        field.push_str(&format!("\r\n\tbh={};", encode(&body_hash)));
+        let data = fields.into_iter().rev().flat_map(|(_, f)| f).collect();
+        self.sign_field(field, data)
+    }
+
+    // `name: first; a=...; middle; d=...; s=...;` and the time of signing
+    pub(crate) fn start_field(&self, name: &str, first: &str, middle: &str) -> String {
+        let mut field = format!(
+            "{name}: {first}; a={}; {middle}; d={}; s={};",
+            self.key.algorithm().as_str(),
+            self.domain,
+            self.selector,
+        );
+        if let Some(timestamp) = self.timestamp {
+            field.push_str(&format!(" t={timestamp};"));
+        }
+        field
+    }
+
+    // completes `field` with the signature of `data`, the canonicalized fields it covers,
+    // followed by the field itself
+    pub(crate) fn sign_field(&mut self, mut field: String, mut data: Vec<u8>) -> Vec<u8> {
+        field.push_str("\r\n\tb=");
+
+        // the signature field itself is signed last, with an empty `b=` and without its
+        // final line break
+        // https://datatracker.ietf.org/doc/html/rfc6376#section-3.7
+        let canonical = self
+            .header_canonicalization
+            .header(format!("{field}\r\n").as_bytes());
+        data.extend_from_slice(canonical.strip_suffix(b"\r\n").unwrap_or(&canonical));
+        let digest: [u8; 32] = Sha256::digest(&data).into();
+        let signature = encode(&self.key.sign(&data, &digest));
+
+        // whitespace in the `b=` tag is ignored, so it can be folded after signing
+        let mut lines = signature.as_bytes().chunks(72);
+        let mut field = field.into_bytes();
+        field.extend(lines.next().unwrap_or_default());
+        for line in lines {
+            field.extend_from_slice(b"\r\n\t");
+            field.extend_from_slice(line);
+        }
+        field.extend_from_slice(b"\r\n");
+        field
+    }
+}
+
+fn tag(canonicalization: Canonicalization) -> &'static str {
+    match canonicalization {
+        Canonicalization::Simple => "simple",
+        Canonicalization::Relaxed => "relaxed",
+    }
+}
+
+fn encode(bytes: &[u8]) -> String {
+    let mut encoded = vec![0; base64::encoded_len(bytes.len())];
+    base64::encode_slice(bytes, &mut encoded).expect("sized to fit");
+    String::from_utf8(encoded).expect("base64 is ASCII")
+}
@@ -0,0 +1,6 @@
+//! Content transfer encodings, for message parts which can't be sent as they are.
+//! <https://datatracker.ietf.org/doc/html/rfc2045#section-6>
+
+pub(crate) mod base64;
+mod quoted_printable;
+pub use quoted_printable::{MIN_OUTPUT_LEN, QuotedPrintable};
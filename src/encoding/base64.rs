@@ -0,0 +1,155 @@
+//! Base64 with the standard, padded alphabet.
+//! <https://datatracker.ietf.org/doc/html/rfc4648#section-4>
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The length of `len` bytes encoded, including the padding.
+pub(crate) const fn encoded_len(len: usize) -> usize {
+    len.div_ceil(3) * 4
+}
+
+/// Encodes `input` into the start of `output`, returning the encoded length, or `None` if
+/// `output` is too short.
+pub(crate) fn encode_slice(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    encode_parts(&[input], output)
+}
+
+/// Encodes the concatenation of `parts`, without copying them together first.
+pub(crate) fn encode_parts(parts: &[&[u8]], output: &mut [u8]) -> Option<usize> {
+    let len = encoded_len(parts.iter().map(|part| part.len()).sum());
+    let mut bytes = parts.iter().flat_map(|part| part.iter().copied());
+    for quad in output.get_mut(..len)?.chunks_exact_mut(4) {
+        let group = [bytes.next(), bytes.next(), bytes.next()];
+        let present = group.iter().flatten().count();
+        let [a, b, c] = group.map(|byte| u32::from(byte.unwrap_or(0)));
+        let triple = (a << 16) | (b << 8) | c;
+        for (i, out) in quad.iter_mut().enumerate() {
+            // n bytes take n + 1 characters, the rest is padding
+            *out = match i <= present {
+                true => ALPHABET[(triple >> (18 - 6 * i)) as usize & 63],
+                false => b'=',
+            };
+        }
+    }
+    Some(len)
+}
+
+/// The input of a [`Decoder`] isn't valid base64.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct InvalidBase64;
+
+/// Decodes one character at a time, so text split over several lines can be decoded in
+/// place: a byte is only written after the characters it is decoded from were read.
+#[derive(Default)]
+pub(crate) struct Decoder {
+    bits: u32,
+    // bits in `bits` which aren't part of a decoded byte yet
+    pending: u8,
+    padded: bool,
+}
+
+impl Decoder {
+    /// Returns the byte the character completes, if any.
+    pub(crate) fn push(&mut self, c: u8) -> Result<Option<u8>, InvalidBase64> {
+        let value = match c {
+            b'=' => {
+                self.padded = true;
+                return Ok(None);
+            }
+            // nothing but padding may follow padding
+            _ if self.padded => return Err(InvalidBase64),
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return Err(InvalidBase64),
+        };
+        self.bits = (self.bits << 6) | u32::from(value);
+        self.pending += 6;
+        if self.pending < 8 {
+            return Ok(None);
+        }
+        self.pending -= 8;
+        let byte = (self.bits >> self.pending) as u8;
+        self.bits &= (1 << self.pending) - 1;
+        Ok(Some(byte))
+    }
+
+    /// Checks that the input didn't end in the middle of a byte.
+    pub(crate) fn finish(self) -> Result<(), InvalidBase64> {
+        // a single character of a group holds only 6 bits
+        match self.pending {
+            6 => Err(InvalidBase64),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(input: &[u8]) -> String {
+        let mut out = vec![0; encoded_len(input.len())];
+        let len = encode_slice(input, &mut out).unwrap();
+        String::from_utf8(out[..len].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn rfc_4648_vectors() {
+        // https://datatracker.ietf.org/doc/html/rfc4648#section-10
+        for (input, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encode(input.as_bytes()), encoded);
+        }
+        assert_eq!(encode(&[0xfb, 0xff, 0xbf]), "+/+/");
+    }
+
+    #[test]
+    fn parts_are_encoded_as_one() {
+        let mut out = [0; 32];
+        let len = encode_parts(&[b"\0", b"user", b"\0", b"pass"], &mut out).unwrap();
+        assert_eq!(&out[..len], b"AHVzZXIAcGFzcw==");
+    }
+
+    fn decode(input: &str) -> Result<Vec<u8>, InvalidBase64> {
+        let mut decoder = Decoder::default();
+        let mut out = Vec::new();
+        for c in input.bytes() {
+            out.extend(decoder.push(c)?);
+        }
+        decoder.finish()?;
+        Ok(out)
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        for input in ["", "f", "fo", "foo", "foob", "fooba", "foobar"] {
+            assert_eq!(decode(&encode(input.as_bytes())).unwrap(), input.as_bytes());
+        }
+        assert_eq!(decode("+/+/").unwrap(), [0xfb, 0xff, 0xbf]);
+        // padding is optional
+        assert_eq!(decode("Zm8").unwrap(), b"fo");
+    }
+
+    #[test]
+    fn invalid_input() {
+        assert_eq!(decode("Zm9v!"), Err(InvalidBase64));
+        assert_eq!(decode("Zg==Zg=="), Err(InvalidBase64));
+        assert_eq!(decode("Zm9vY"), Err(InvalidBase64));
+    }
+
+    #[test]
+    fn output_too_short() {
+        assert_eq!(encode_slice(b"foo", &mut [0; 3]), None);
+        assert_eq!(encode_slice(b"foo", &mut [0; 4]), Some(4));
+    }
+}
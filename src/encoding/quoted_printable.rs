@@ -0,0 +1,228 @@
+// an encoded line may be 76 characters long, including the `=` of a soft line break
+const MAX_LINE_LEN: usize = 75;
+
+const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+/// The longest output for a single input byte: a held back byte, a soft line break and an
+/// escape. Output buffers have to be at least this long.
+pub const MIN_OUTPUT_LEN: usize = 12;
+
+/// Quoted-printable encoding, for text which is mostly ASCII but may contain other
+/// characters or long lines.
+/// <https://datatracker.ietf.org/doc/html/rfc2045#section-6.7>
+///
+/// The input can arrive in chunks of any size. `\r\n` line breaks are kept, and lines are
+/// wrapped with soft line breaks (`=\r\n`) to at most 76 characters.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::encoding::QuotedPrintable;
+///
+/// let mut encoder = QuotedPrintable::new();
+/// let mut out = [0; 64];
+/// let mut encoded = Vec::new();
+/// for mut chunk in [&b"Gr\xc3\xbc\xc3\x9fe "[..], b"\r\n= 1"] {
+///     while !chunk.is_empty() {
+///         let (read, written) = encoder.encode(chunk, &mut out);
+///         encoded.extend_from_slice(&out[..written]);
+///         chunk = &chunk[read..];
+///     }
+/// }
+/// let written = encoder.finish(&mut out);
+/// encoded.extend_from_slice(&out[..written]);
+/// assert_eq!(encoded, b"Gr=C3=BC=C3=9Fe=20\r\n=3D 1");
+/// ```
+#[derive(Debug, Clone)]
+pub struct QuotedPrintable {
+    line_len: usize,
+    // whitespace or `\r`, which is encoded depending on the byte after it
+    held_back: Option<u8>,
+}
+
+// the output for one input byte
+struct Token {
+    buf: [u8; MIN_OUTPUT_LEN],
+    len: usize,
+    line_len: usize,
+}
+
+impl Token {
+    fn new(line_len: usize) -> Self {
+        Token {
+            buf: [0; MIN_OUTPUT_LEN],
+            len: 0,
+            line_len,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+
+    // a byte of a line, wrapped if the line would get too long
+    fn push_byte(&mut self, byte: u8, literal: bool) {
+        let len = if literal { 1 } else { 3 };
+        if self.line_len + len > MAX_LINE_LEN {
+            self.push(b"=\r\n");
+            self.line_len = 0;
+        }
+        if literal {
+            self.push(&[byte]);
+        } else {
+            self.push(&[
+                b'=',
+                HEX[usize::from(byte >> 4)],
+                HEX[usize::from(byte & 0xf)],
+            ]);
+        }
+        self.line_len += len;
+    }
+
+    fn push_line_break(&mut self) {
+        self.push(b"\r\n");
+        self.line_len = 0;
+    }
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    byte == b' ' || byte == b'\t'
+}
+
+impl QuotedPrintable {
+    pub fn new() -> Self {
+        QuotedPrintable {
+            line_len: 0,
+            held_back: None,
+        }
+    }
+
+    /// Encodes as much of `input` as fits into `out`, which has to hold at least
+    /// [`MIN_OUTPUT_LEN`] bytes. Returns the number of bytes read and written.
+    pub fn encode(&mut self, input: &[u8], out: &mut [u8]) -> (usize, usize) {
+        let mut read = 0;
+        let mut written = 0;
+        for &byte in input {
+            let mut token = Token::new(self.line_len);
+            let held_back = match (self.held_back, byte) {
+                (Some(b'\r'), b'\n') => {
+                    token.push_line_break();
+                    None
+                }
+                (held_back, byte) => {
+                    if let Some(held) = held_back {
+                        // whitespace at the end of a line would be removed in transit
+                        token.push_byte(held, is_whitespace(held) && byte != b'\r');
+                    }
+                    if is_whitespace(byte) || byte == b'\r' {
+                        Some(byte)
+                    } else {
+                        token.push_byte(byte, byte.is_ascii_graphic() && byte != b'=');
+                        None
+                    }
+                }
+            };
+            let Some(dst) = out.get_mut(written..written + token.len) else {
+                break;
+            };
+            dst.copy_from_slice(&token.buf[..token.len]);
+            written += token.len;
+            read += 1;
+            self.line_len = token.line_len;
+            self.held_back = held_back;
+        }
+        (read, written)
+    }
+
+    /// Encodes the byte held back at the end of the input, returns the number of bytes
+    /// written to `out`, which has to hold at least [`MIN_OUTPUT_LEN`] bytes.
+    pub fn finish(&mut self, out: &mut [u8]) -> usize {
+        let mut token = Token::new(self.line_len);
+        if let Some(held) = self.held_back.take() {
+            token.push_byte(held, false);
+        }
+        out[..token.len].copy_from_slice(&token.buf[..token.len]);
+        self.line_len = 0;
+        token.len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_chunks(chunks: &[&[u8]], out_len: usize) -> String {
+        let mut encoder = QuotedPrintable::new();
+        let mut out = vec![0; out_len];
+        let mut encoded = Vec::new();
+        for chunk in chunks {
+            let mut chunk = *chunk;
+            while !chunk.is_empty() {
+                let (read, written) = encoder.encode(chunk, &mut out);
+                encoded.extend_from_slice(&out[..written]);
+                chunk = &chunk[read..];
+            }
+        }
+        let written = encoder.finish(&mut out);
+        encoded.extend_from_slice(&out[..written]);
+        String::from_utf8(encoded).unwrap()
+    }
+
+    fn encode(text: &str) -> String {
+        encode_chunks(&[text.as_bytes()], 64)
+    }
+
+    #[test]
+    fn escapes_what_isnt_printable() {
+        assert_eq!(encode("Grüße"), "Gr=C3=BC=C3=9Fe");
+        assert_eq!(encode("a=b"), "a=3Db");
+        assert_eq!(
+            encode("tab\tand space \r\nend "),
+            "tab\tand space=20\r\nend=20"
+        );
+        assert_eq!(encode("bare\nlf and\rcr"), "bare=0Alf and=0Dcr");
+        assert_eq!(encode("trailing\r"), "trailing=0D");
+    }
+
+    #[test]
+    fn wraps_long_lines() {
+        let encoded = encode(&"x".repeat(200));
+        for line in encoded.split("\r\n") {
+            assert!(line.len() <= 76, "{line}");
+        }
+        assert_eq!(encoded.replace("=\r\n", ""), "x".repeat(200));
+        // escapes are never split
+        let encoded = encode(&"ü".repeat(40));
+        assert!(encoded.split("=\r\n").all(|line| line.len() % 3 == 0));
+    }
+
+    // xorshift, to get reproducible inputs without pulling in a property testing crate
+    struct Rng(u64);
+    impl Rng {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+    }
+
+    #[test]
+    fn chunking_doesnt_change_the_output() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..500 {
+            let len = rng.below(200) as usize;
+            let text: Vec<u8> = (0..len)
+                .map(|_| b" \t\r\na=\xff"[rng.below(7) as usize])
+                .collect();
+            let split = rng.below(len as u64 + 1) as usize;
+            let (a, b) = text.split_at(split);
+            assert_eq!(
+                encode_chunks(&[a, b], MIN_OUTPUT_LEN),
+                encode_chunks(&[&text], 1024),
+                "{text:?}"
+            );
+        }
+    }
+}
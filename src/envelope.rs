@@ -0,0 +1,677 @@
+//! SMTP envelope and per-transaction extension parameters.
+//!
+//! The envelope is what the server actually routes on: the reverse-path given in `MAIL FROM`
+//! and the forward-paths given in `RCPT TO`. It is independent from the `From:`/`To:` headers
+//! of the message itself.
+//! <https://datatracker.ietf.org/doc/html/rfc5321#section-2.3.1>
+//!
+//! Besides the addresses, an envelope can carry Delivery Status Notification parameters
+//! as defined in [RFC 3461](https://datatracker.ietf.org/doc/html/rfc3461).
+//! These are only sent if the server advertised the `DSN` extension.
+//!
+//! On an authenticated session, the envelope can also name who submitted the message with
+//! the `AUTH=` parameter of [RFC 4954](https://datatracker.ietf.org/doc/html/rfc4954#section-5).
+//!
+//! The size of the message (`SIZE=`) and its body type (`BODY=`) are declared only if the
+//! server advertised `SIZE` and `8BITMIME`. Parameters of extensions this crate doesn't know
+//! about are passed on as [`Parameter`]s.
+//!
+//! Forwarders can rewrite the reverse-path with the Sender Rewriting Scheme of [`Srs`].
+
+use core::{fmt::Display, ops::BitOr};
+
+use crate::smtp::{Extensions, capabilities::Capabilities};
+
+#[cfg(feature = "alloc")]
+mod srs;
+#[cfg(feature = "alloc")]
+pub use srs::{Srs, SrsError, SrsKey};
+
+/// When the server should send a delivery status notification for a recipient.
+///
+/// Combine conditions with `|`, e.g. `Notify::FAILURE | Notify::DELAY`.
+/// An empty set is sent as `NOTIFY=NEVER`.
+/// <https://datatracker.ietf.org/doc/html/rfc3461#section-4.1>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Notify(u8);
+
+impl Notify {
+    /// Never send a notification, not even on failure.
+    pub const NEVER: Notify = Notify(0);
+    /// Notify on successful delivery.
+    pub const SUCCESS: Notify = Notify(1);
+    /// Notify if delivery failed.
+    pub const FAILURE: Notify = Notify(1 << 1);
+    /// Notify if delivery is delayed.
+    pub const DELAY: Notify = Notify(1 << 2);
+
+    /// Returns true if all conditions in `other` are set in `self`.
+    pub fn contains(self, other: Notify) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns true if this is `NOTIFY=NEVER`.
+    pub fn is_never(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The keywords making up the `NOTIFY=` value, in the order RFC 3461 lists them.
+    pub(crate) fn keywords(self) -> impl Iterator<Item = &'static str> {
+        let flags = [
+            (Notify::SUCCESS, "SUCCESS"),
+            (Notify::FAILURE, "FAILURE"),
+            (Notify::DELAY, "DELAY"),
+        ];
+        self.is_never()
+            .then_some("NEVER")
+            .into_iter()
+            .chain(flags.into_iter().filter_map(move |(flag, name)| {
+                (!self.is_never() && self.contains(flag)).then_some(name)
+            }))
+    }
+}
+
+impl BitOr for Notify {
+    type Output = Notify;
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Notify(self.0 | rhs.0)
+    }
+}
+
+impl Display for Notify {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (idx, keyword) in self.keywords().enumerate() {
+            if idx > 0 {
+                f.write_str(",")?;
+            }
+            f.write_str(keyword)?;
+        }
+        Ok(())
+    }
+}
+
+/// How much of the original message should be returned in a failure notification.
+/// <https://datatracker.ietf.org/doc/html/rfc3461#section-4.3>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ret {
+    /// Return the full message.
+    Full,
+    /// Return only the headers.
+    Headers,
+}
+
+impl Ret {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Ret::Full => "FULL",
+            Ret::Headers => "HDRS",
+        }
+    }
+}
+
+impl Display for Ret {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Who submitted a message, sent as the `AUTH=` parameter of `MAIL FROM`.
+///
+/// Relays which trust the authenticated client pass this on instead of treating the message
+/// as coming from an anonymous source.
+/// <https://datatracker.ietf.org/doc/html/rfc4954#section-5>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Submitter<'a> {
+    /// The identity the message was originally submitted by, usually a mailbox.
+    Identity(&'a str),
+    /// The submitter is unknown or shouldn't be passed on, sent as `AUTH=<>`.
+    Unknown,
+}
+
+/// The body type of a message, sent as the `BODY=` parameter of `MAIL FROM`.
+/// <https://datatracker.ietf.org/doc/html/rfc6152#section-2>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyType {
+    /// Only 7-bit ASCII, the default for any SMTP server.
+    SevenBit,
+    /// Lines may contain octets above 127, which requires `8BITMIME`.
+    EightBitMime,
+}
+
+impl BodyType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BodyType::SevenBit => "7BIT",
+            BodyType::EightBitMime => "8BITMIME",
+        }
+    }
+}
+
+impl Display for BodyType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An extension parameter of `MAIL FROM` or `RCPT TO` which has no dedicated setter, e.g.
+/// `MT-PRIORITY=3` or `REQUIRETLS`.
+///
+/// The parameter is sent as given, so it has to be one the server advertised support for.
+/// A keyword has to consist of letters, digits and `-`, and a value of printable ASCII
+/// other than `=`. Otherwise sending the envelope fails with
+/// [`ProtocolError::InvalidParameter`](crate::ProtocolError::InvalidParameter).
+/// <https://datatracker.ietf.org/doc/html/rfc5321#section-4.1.2>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parameter<'a> {
+    keyword: &'a str,
+    value: Option<&'a str>,
+}
+
+impl<'a> Parameter<'a> {
+    /// A parameter with a value, sent as `keyword=value`.
+    pub fn new(keyword: &'a str, value: &'a str) -> Self {
+        Parameter {
+            keyword,
+            value: Some(value),
+        }
+    }
+
+    /// A parameter without a value, sent as `keyword`.
+    pub fn flag(keyword: &'a str) -> Self {
+        Parameter {
+            keyword,
+            value: None,
+        }
+    }
+
+    pub fn keyword(&self) -> &'a str {
+        self.keyword
+    }
+
+    pub fn value(&self) -> Option<&'a str> {
+        self.value
+    }
+
+    /// Returns true if the keyword and value can be sent as they are.
+    pub fn is_valid(&self) -> bool {
+        let keyword = self.keyword.as_bytes();
+        keyword.first().is_some_and(u8::is_ascii_alphanumeric)
+            && keyword
+                .iter()
+                .all(|&b| b.is_ascii_alphanumeric() || b == b'-')
+            && self.value.is_none_or(|value| {
+                !value.is_empty()
+                    && value
+                        .bytes()
+                        .all(|b| (b'!'..=b'~').contains(&b) && b != b'=')
+            })
+    }
+}
+
+impl Display for Parameter<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.keyword)?;
+        match self.value {
+            Some(value) => write!(f, "={value}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A single forward-path, with its optional DSN and extension parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recipient<'a> {
+    address: &'a str,
+    notify: Option<Notify>,
+    orcpt: Option<&'a str>,
+    parameters: &'a [Parameter<'a>],
+}
+
+impl<'a> Recipient<'a> {
+    pub fn new(address: &'a str) -> Self {
+        Recipient {
+            address,
+            notify: None,
+            orcpt: None,
+            parameters: &[],
+        }
+    }
+
+    /// Request notifications for this recipient (`NOTIFY=`).
+    pub fn with_notify(mut self, notify: Notify) -> Self {
+        self.notify = Some(notify);
+        self
+    }
+
+    /// Record the original recipient address (`ORCPT=rfc822;...`).
+    ///
+    /// Useful when the address was rewritten (e.g. by a forwarder) so the notification
+    /// still mentions the address the sender used.
+    /// <https://datatracker.ietf.org/doc/html/rfc3461#section-4.2>
+    pub fn with_orcpt(mut self, original: &'a str) -> Self {
+        self.orcpt = Some(original);
+        self
+    }
+
+    /// Add parameters of other extensions to `RCPT TO`, sent after the DSN parameters.
+    pub fn with_parameters(mut self, parameters: &'a [Parameter<'a>]) -> Self {
+        self.parameters = parameters;
+        self
+    }
+
+    pub fn address(&self) -> &'a str {
+        self.address
+    }
+
+    pub fn notify(&self) -> Option<Notify> {
+        self.notify
+    }
+
+    pub fn orcpt(&self) -> Option<&'a str> {
+        self.orcpt
+    }
+
+    pub fn parameters(&self) -> &'a [Parameter<'a>] {
+        self.parameters
+    }
+
+    /// Returns true if any DSN parameter is set on this recipient.
+    pub fn has_dsn_params(&self) -> bool {
+        self.notify.is_some() || self.orcpt.is_some()
+    }
+
+    /// Returns false if the address contains a line break or `>`, see
+    /// [`Envelope::has_valid_addresses`].
+    pub fn has_valid_address(&self) -> bool {
+        is_valid_path(self.address)
+    }
+}
+
+impl<'a> From<&'a str> for Recipient<'a> {
+    fn from(address: &'a str) -> Self {
+        Recipient::new(address)
+    }
+}
+
+/// The longest [envelope identifier](Envelope::with_envid), in characters of its xtext
+/// encoding.
+/// <https://datatracker.ietf.org/doc/html/rfc3461#section-4.4>
+pub const MAX_ENVID_LEN: usize = 100;
+
+/// The reverse-path and forward-paths of a mail transaction.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::envelope::{Envelope, Notify, Recipient, Ret};
+///
+/// let recipients = [
+///     Recipient::new("alice@example.com").with_notify(Notify::FAILURE | Notify::DELAY),
+///     Recipient::new("bob@example.com"),
+/// ];
+/// let envelope = Envelope::new("sender@example.com", &recipients)
+///     .with_ret(Ret::Headers)
+///     .with_envid("QQ314159")
+///     .with_size(4096);
+/// assert!(envelope.has_dsn_params());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Envelope<'a> {
+    from: &'a str,
+    recipients: &'a [Recipient<'a>],
+    ret: Option<Ret>,
+    envid: Option<&'a str>,
+    submitter: Option<Submitter<'a>>,
+    size: Option<u64>,
+    body: Option<BodyType>,
+    parameters: &'a [Parameter<'a>],
+}
+
+impl<'a> Envelope<'a> {
+    pub fn new(from: &'a str, recipients: &'a [Recipient<'a>]) -> Self {
+        Envelope {
+            from,
+            recipients,
+            ret: None,
+            envid: None,
+            submitter: None,
+            size: None,
+            body: None,
+            parameters: &[],
+        }
+    }
+
+    /// Request the full message or only its headers in failure notifications (`RET=`).
+    pub fn with_ret(mut self, ret: Ret) -> Self {
+        self.ret = Some(ret);
+        self
+    }
+
+    /// Set an envelope identifier which is included in notifications (`ENVID=`).
+    ///
+    /// RFC 3461 limits this to [`MAX_ENVID_LEN`] characters once encoded as xtext, where `+`,
+    /// `=` and anything but printable ASCII take three each. Sending a longer one fails with
+    /// [`ProtocolError::InvalidParameter`](crate::ProtocolError::InvalidParameter).
+    /// <https://datatracker.ietf.org/doc/html/rfc3461#section-4.4>
+    pub fn with_envid(mut self, envid: &'a str) -> Self {
+        self.envid = Some(envid);
+        self
+    }
+
+    /// Name who submitted the message (`AUTH=`).
+    ///
+    /// Only sent on a session which [authenticated](crate::Smtp::is_authenticated).
+    /// <https://datatracker.ietf.org/doc/html/rfc4954#section-5>
+    pub fn with_submitter(mut self, submitter: Submitter<'a>) -> Self {
+        self.submitter = Some(submitter);
+        self
+    }
+
+    /// Declare the size of the message in octets (`SIZE=`), so the server can refuse it
+    /// before it is transferred.
+    ///
+    /// Only sent if the server advertised `SIZE`, as the declaration is optional. A size
+    /// above the server's limit fails with
+    /// [`ProtocolError::MessageTooLarge`](crate::ProtocolError::MessageTooLarge) before
+    /// anything is sent.
+    /// <https://datatracker.ietf.org/doc/html/rfc1870#section-6>
+    pub fn with_size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Declare the body type of the message (`BODY=`).
+    ///
+    /// Only sent if the server advertised `8BITMIME`, which is required for
+    /// [`BodyType::EightBitMime`].
+    /// <https://datatracker.ietf.org/doc/html/rfc6152#section-3>
+    pub fn with_body(mut self, body: BodyType) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    /// Add parameters of other extensions to `MAIL FROM`, sent after all others.
+    pub fn with_parameters(mut self, parameters: &'a [Parameter<'a>]) -> Self {
+        self.parameters = parameters;
+        self
+    }
+
+    pub fn from(&self) -> &'a str {
+        self.from
+    }
+
+    pub fn recipients(&self) -> &'a [Recipient<'a>] {
+        self.recipients
+    }
+
+    pub fn ret(&self) -> Option<Ret> {
+        self.ret
+    }
+
+    pub fn envid(&self) -> Option<&'a str> {
+        self.envid
+    }
+
+    pub fn submitter(&self) -> Option<Submitter<'a>> {
+        self.submitter
+    }
+
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    pub fn body(&self) -> Option<BodyType> {
+        self.body
+    }
+
+    pub fn parameters(&self) -> &'a [Parameter<'a>] {
+        self.parameters
+    }
+
+    // drops the declarations the server doesn't understand, as they are optional
+    pub(crate) fn declarable(mut self, capabilities: &Capabilities) -> Self {
+        if !capabilities.supports(Extensions::SIZE) {
+            self.size = None;
+        }
+        if !capabilities.supports(Extensions::EIGHTBITMIME) {
+            self.body = None;
+        }
+        self
+    }
+
+    /// Returns true if all extension parameters of the envelope and its recipients are
+    /// [valid](Parameter::is_valid).
+    pub fn has_valid_parameters(&self) -> bool {
+        self.parameters
+            .iter()
+            .chain(self.recipients.iter().flat_map(|r| r.parameters()))
+            .all(Parameter::is_valid)
+    }
+
+    /// Returns true if none of the addresses contains a line break or `>`, which would end
+    /// the `<...>` path in the middle of the address, or the command.
+    pub fn has_valid_addresses(&self) -> bool {
+        is_valid_path(self.from) && self.recipients.iter().all(Recipient::has_valid_address)
+    }
+
+    /// Returns true if an address contains non-ASCII characters, so the server has to support
+    /// `SMTPUTF8` and `MAIL FROM` carries the `SMTPUTF8` parameter.
+    /// <https://datatracker.ietf.org/doc/html/rfc6531#section-3.4>
+    pub fn requires_smtputf8(&self) -> bool {
+        !self.from.is_ascii() || self.recipients.iter().any(|r| !r.address().is_ascii())
+    }
+
+    /// Returns true if any DSN parameter is set on the envelope or any of its recipients.
+    pub fn has_dsn_params(&self) -> bool {
+        self.ret.is_some()
+            || self.envid.is_some()
+            || self.recipients.iter().any(Recipient::has_dsn_params)
+    }
+}
+
+// an address is sent between `<` and `>` on the command line
+fn is_valid_path(address: &str) -> bool {
+    !address.contains(['\r', '\n', '>'])
+}
+
+// the length of the xtext encoding of `s`
+pub(crate) fn xtext_len(s: &str) -> usize {
+    let mut chunks = xtext_chunks(s);
+    let mut len = 0;
+    while let Some(chunk) = chunks.next_chunk() {
+        len += chunk.len();
+    }
+    len
+}
+
+/// Splits `s` into chunks which together form its xtext encoding.
+///
+/// Printable ASCII except `+` and `=` is passed through as-is, everything else
+/// is encoded as `+XX`. Yields borrowed runs of `s` where possible so the
+/// encoding can be streamed without a buffer.
+/// <https://datatracker.ietf.org/doc/html/rfc3461#section-4>
+fn xtext_chunks(s: &str) -> XtextChunks<'_> {
+    XtextChunks {
+        remaining: s.as_bytes(),
+        escape: [0; 3],
+    }
+}
+
+struct XtextChunks<'a> {
+    remaining: &'a [u8],
+    escape: [u8; 3],
+}
+
+impl XtextChunks<'_> {
+    fn is_xchar(b: u8) -> bool {
+        (b'!'..=b'~').contains(&b) && b != b'+' && b != b'='
+    }
+
+    /// Returns the next chunk, either a borrowed run or an escape sequence.
+    /// Not an `Iterator` because escapes borrow from `self`.
+    fn next_chunk(&mut self) -> Option<&[u8]> {
+        const HEX: &[u8; 16] = b"0123456789ABCDEF";
+        let first = *self.remaining.first()?;
+        if Self::is_xchar(first) {
+            let run = self
+                .remaining
+                .iter()
+                .position(|b| !Self::is_xchar(*b))
+                .unwrap_or(self.remaining.len());
+            let (chunk, rest) = self.remaining.split_at(run);
+            self.remaining = rest;
+            Some(chunk)
+        } else {
+            self.remaining = &self.remaining[1..];
+            self.escape = [
+                b'+',
+                HEX[(first >> 4) as usize],
+                HEX[(first & 0xf) as usize],
+            ];
+            Some(&self.escape)
+        }
+    }
+}
+
+/// Displays a string in its xtext encoding.
+pub(crate) struct Xtext<'a>(pub(crate) &'a str);
+
+impl Display for Xtext<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut chunks = xtext_chunks(self.0);
+        while let Some(chunk) = chunks.next_chunk() {
+            // every chunk is ascii by construction
+            f.write_str(core::str::from_utf8(chunk).map_err(|_| core::fmt::Error)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays the extension parameters of a `MAIL FROM` command, including the leading space,
+/// as they are sent and logged.
+pub(crate) struct MailParameters<'a>(pub(crate) &'a Envelope<'a>);
+
+impl Display for MailParameters<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if let Some(ret) = self.0.ret() {
+            write!(f, " RET={ret}")?;
+        }
+        if let Some(envid) = self.0.envid() {
+            write!(f, " ENVID={}", Xtext(envid))?;
+        }
+        if let Some(size) = self.0.size() {
+            write!(f, " SIZE={size}")?;
+        }
+        if let Some(body) = self.0.body() {
+            write!(f, " BODY={body}")?;
+        }
+        if self.0.requires_smtputf8() {
+            f.write_str(" SMTPUTF8")?;
+        }
+        match self.0.submitter() {
+            Some(Submitter::Identity(identity)) => write!(f, " AUTH={}", Xtext(identity))?,
+            Some(Submitter::Unknown) => f.write_str(" AUTH=<>")?,
+            None => {}
+        }
+        for parameter in self.0.parameters() {
+            write!(f, " {parameter}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays the extension parameters of a `RCPT TO` command, including the leading space,
+/// as they are sent and logged.
+pub(crate) struct RcptParameters<'a>(pub(crate) &'a Recipient<'a>);
+
+impl Display for RcptParameters<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if let Some(notify) = self.0.notify() {
+            write!(f, " NOTIFY={notify}")?;
+        }
+        if let Some(orcpt) = self.0.orcpt() {
+            write!(f, " ORCPT=rfc822;{}", Xtext(orcpt))?;
+        }
+        for parameter in self.0.parameters() {
+            write!(f, " {parameter}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xtext(s: &str) -> String {
+        Xtext(s).to_string()
+    }
+
+    #[test]
+    fn parameter_validity() {
+        assert!(Parameter::new("MT-PRIORITY", "3").is_valid());
+        assert!(Parameter::flag("REQUIRETLS").is_valid());
+        assert_eq!(
+            Parameter::new("MT-PRIORITY", "3").to_string(),
+            "MT-PRIORITY=3"
+        );
+        assert!(!Parameter::flag("").is_valid());
+        assert!(!Parameter::flag("-X").is_valid());
+        assert!(!Parameter::flag("X Y").is_valid());
+        assert!(!Parameter::new("X", "").is_valid());
+        assert!(!Parameter::new("X", "a=b").is_valid());
+        assert!(!Parameter::new("X", "a\r\nRSET").is_valid());
+    }
+
+    #[test]
+    fn notify_display() {
+        assert_eq!(Notify::NEVER.to_string(), "NEVER");
+        assert_eq!(Notify::SUCCESS.to_string(), "SUCCESS");
+        assert_eq!(
+            (Notify::DELAY | Notify::FAILURE).to_string(),
+            "FAILURE,DELAY"
+        );
+        assert_eq!(
+            (Notify::SUCCESS | Notify::FAILURE | Notify::DELAY).to_string(),
+            "SUCCESS,FAILURE,DELAY"
+        );
+    }
+
+    #[test]
+    fn notify_never_is_absorbed() {
+        // NEVER must not be combined with other values
+        // <https://datatracker.ietf.org/doc/html/rfc3461#section-4.1>
+        let notify = Notify::NEVER | Notify::FAILURE;
+        assert!(!notify.is_never());
+        assert_eq!(notify.to_string(), "FAILURE");
+    }
+
+    #[test]
+    fn xtext_passthrough() {
+        assert_eq!(xtext("QQ314159"), "QQ314159");
+        assert_eq!(xtext("alice@example.com"), "alice@example.com");
+    }
+
+    #[test]
+    fn xtext_escapes() {
+        // RFC 3461 section 4: "+" and "=" and anything outside of "!".."~" must be encoded
+        assert_eq!(xtext("a+b=c"), "a+2Bb+3Dc");
+        assert_eq!(xtext("a b"), "a+20b");
+        assert_eq!(xtext("é"), "+C3+A9");
+        assert_eq!(xtext(""), "");
+    }
+
+    #[test]
+    fn envelope_dsn_params() {
+        let plain = [Recipient::new("a@example.com")];
+        assert!(!Envelope::new("me@example.com", &plain).has_dsn_params());
+        assert!(
+            Envelope::new("me@example.com", &plain)
+                .with_ret(Ret::Full)
+                .has_dsn_params()
+        );
+
+        let notified = [Recipient::new("a@example.com").with_orcpt("b@example.com")];
+        assert!(Envelope::new("me@example.com", &notified).has_dsn_params());
+    }
+}
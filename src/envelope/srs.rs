@@ -0,0 +1,341 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Display;
+
+use crate::encoding::base64;
+
+/// The secret an [`Srs`] guards its addresses with.
+///
+/// Returns the first 3 bytes of a MAC of `data` keyed with a secret, usually HMAC-SHA1 as in
+/// other implementations. `data` is already lowercased. Any function with the same signature
+/// is a key.
+pub trait SrsKey {
+    fn mac(&self, data: &[u8]) -> [u8; 3];
+}
+
+impl<F: Fn(&[u8]) -> [u8; 3]> SrsKey for F {
+    fn mac(&self, data: &[u8]) -> [u8; 3] {
+        self(data)
+    }
+}
+
+/// The Sender Rewriting Scheme, for forwarding mail without breaking SPF.
+///
+/// A forwarder which keeps the reverse-path of a message fails the SPF check of the next
+/// server, as it isn't allowed to send for the sender's domain. [`forward`](Self::forward)
+/// rewrites the reverse-path into an address of the forwarder's own domain, which encodes
+/// the original one. Bounces to it are turned back into the original address with
+/// [`reverse`](Self::reverse).
+///
+/// Rewritten addresses carry a timestamp and a hash, so the forwarder can't be abused as an
+/// open relay for bounces. Addresses which were already rewritten by another forwarder are
+/// rewritten into the `SRS1` form, which points at the first forwarder instead of growing
+/// with every hop.
+/// <https://www.libsrs2.net/srs/srs.pdf>
+///
+/// Times are given in seconds since the Unix epoch, e.g. the
+/// [`timestamp`](crate::message::DateTime::timestamp) of a [`Clock`](crate::message::Clock).
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::envelope::Srs;
+///
+/// // use a real MAC like HMAC-SHA1 with a secret key
+/// let key = |data: &[u8]| {
+///     let sum = data.iter().fold(0u32, |sum, &b| sum.wrapping_mul(31).wrapping_add(b.into()));
+///     [sum as u8, (sum >> 8) as u8, (sum >> 16) as u8]
+/// };
+/// let srs = Srs::new(key, "forwarder.example");
+/// let now = 1_700_000_000;
+/// let forwarded = srs.forward("alice@example.com", now).unwrap();
+/// assert!(forwarded.starts_with("SRS0="));
+/// assert!(forwarded.ends_with("=example.com=alice@forwarder.example"));
+/// assert_eq!(srs.reverse(&forwarded, now).unwrap(), "alice@example.com");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Srs<'a, K> {
+    key: K,
+    domain: &'a str,
+    max_age: u16,
+}
+
+impl<'a, K: SrsKey> Srs<'a, K> {
+    /// Rewrites addresses into ones at `domain`, which has to route them back to this
+    /// forwarder.
+    pub fn new(key: K, domain: &'a str) -> Self {
+        Srs {
+            key,
+            domain,
+            max_age: 21,
+        }
+    }
+
+    /// The number of days a rewritten address is valid for, 21 by default.
+    ///
+    /// Timestamps wrap around after 1024 days, so longer ages are capped.
+    pub fn with_max_age(mut self, days: u16) -> Self {
+        self.max_age = days.min(TIMESTAMP_PERIOD - 1);
+        self
+    }
+
+    /// Rewrites the reverse-path `address` of a message being forwarded.
+    ///
+    /// Addresses at the forwarder's own domain and the null reverse-path are returned as
+    /// they are.
+    pub fn forward(&self, address: &str, now: u64) -> Result<String, SrsError> {
+        if address.is_empty() {
+            return Ok(String::new());
+        }
+        let (local, host) = address.rsplit_once('@').ok_or(SrsError::InvalidAddress)?;
+        if local.is_empty() || host.is_empty() {
+            return Err(SrsError::InvalidAddress);
+        }
+        if host.eq_ignore_ascii_case(self.domain) {
+            return Ok(address.to_string());
+        }
+        let domain = self.domain;
+        if let Some(rest) = strip_tag(local, "SRS0") {
+            // the separator is kept, hence the `==`
+            let hash = self.hash(&[host, rest]);
+            return Ok(format!("SRS1={hash}={host}={rest}@{domain}"));
+        }
+        if let Some(rest) = strip_tag(local, "SRS1") {
+            let (_, first, rest) = split_srs1(&rest[1..])?;
+            let hash = self.hash(&[first, rest]);
+            return Ok(format!("SRS1={hash}={first}={rest}@{domain}"));
+        }
+        let timestamp = timestamp(now);
+        let timestamp = core::str::from_utf8(&timestamp).expect("base32 is ASCII");
+        let hash = self.hash(&[timestamp, host, local]);
+        Ok(format!("SRS0={hash}={timestamp}={host}={local}@{domain}"))
+    }
+
+    /// Turns an address rewritten by [`forward`](Self::forward) back into the address it
+    /// was rewritten from, e.g. to deliver a bounce.
+    ///
+    /// An `SRS1` address is turned into the `SRS0` address of the first forwarder. Only the
+    /// local part is used, the domain is whatever routed the bounce here.
+    pub fn reverse(&self, address: &str, now: u64) -> Result<String, SrsError> {
+        let local = address.rsplit_once('@').map_or(address, |(local, _)| local);
+        if let Some(rest) = strip_tag(local, "SRS0") {
+            let mut fields = rest[1..].splitn(4, '=');
+            let (Some(hash), Some(timestamp), Some(host), Some(user)) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                return Err(SrsError::InvalidFormat);
+            };
+            if host.is_empty() || user.is_empty() {
+                return Err(SrsError::InvalidFormat);
+            }
+            self.verify(hash, &[timestamp, host, user])?;
+            let then = parse_timestamp(timestamp).ok_or(SrsError::InvalidFormat)?;
+            let age = (day(now) + TIMESTAMP_PERIOD - then) % TIMESTAMP_PERIOD;
+            if age > self.max_age {
+                return Err(SrsError::Expired);
+            }
+            return Ok(format!("{user}@{host}"));
+        }
+        if let Some(rest) = strip_tag(local, "SRS1") {
+            let (hash, first, rest) = split_srs1(&rest[1..])?;
+            self.verify(hash, &[first, rest])?;
+            return Ok(format!("SRS0{rest}@{first}"));
+        }
+        Err(SrsError::NotSrs)
+    }
+
+    fn hash(&self, parts: &[&str]) -> String {
+        let mac = self.key.mac(&hash_input(parts));
+        let mut hash = [0; base64::encoded_len(3)];
+        base64::encode_slice(&mac, &mut hash).expect("the hash fits");
+        hash.iter().copied().map(char::from).collect()
+    }
+
+    fn verify(&self, hash: &str, parts: &[&str]) -> Result<(), SrsError> {
+        // some servers lowercase addresses on the way
+        match hash.eq_ignore_ascii_case(&self.hash(parts)) {
+            true => Ok(()),
+            false => Err(SrsError::InvalidHash),
+        }
+    }
+}
+
+// the days since the epoch a timestamp is counted in, wrapping around
+const TIMESTAMP_PERIOD: u16 = 1024;
+const BASE32: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+fn day(now: u64) -> u16 {
+    (now / 86_400 % u64::from(TIMESTAMP_PERIOD)) as u16
+}
+
+fn timestamp(now: u64) -> [u8; 2] {
+    let day = day(now);
+    [BASE32[usize::from(day >> 5)], BASE32[usize::from(day & 31)]]
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<u16> {
+    let [high, low] = timestamp.as_bytes() else {
+        return None;
+    };
+    let digit = |c: &u8| {
+        let c = c.to_ascii_uppercase();
+        BASE32.iter().position(|&b| b == c).map(|d| d as u16)
+    };
+    Some(digit(high)? << 5 | digit(low)?)
+}
+
+fn hash_input(parts: &[&str]) -> Vec<u8> {
+    parts
+        .iter()
+        .flat_map(|part| part.bytes())
+        .map(|b| b.to_ascii_lowercase())
+        .collect()
+}
+
+// the part after the tag, starting with the separator, which may be any of `=+-`
+fn strip_tag<'s>(local: &'s str, tag: &str) -> Option<&'s str> {
+    let prefix = local.get(..tag.len())?;
+    let rest = &local[tag.len()..];
+    (prefix.eq_ignore_ascii_case(tag) && rest.starts_with(['=', '+', '-'])).then_some(rest)
+}
+
+// `hash=first-forwarder=rest`, where `rest` still starts with its separator
+fn split_srs1(fields: &str) -> Result<(&str, &str, &str), SrsError> {
+    let (hash, fields) = fields.split_once('=').ok_or(SrsError::InvalidFormat)?;
+    let (first, rest) = fields.split_once('=').ok_or(SrsError::InvalidFormat)?;
+    if first.is_empty() || !rest.starts_with(['=', '+', '-']) {
+        return Err(SrsError::InvalidFormat);
+    }
+    Ok((hash, first, rest))
+}
+
+/// Why an address couldn't be rewritten by an [`Srs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SrsError {
+    /// The address to forward has no `@`, or nothing before or after it.
+    InvalidAddress,
+    /// The address to reverse wasn't rewritten by SRS.
+    NotSrs,
+    /// The address to reverse starts like a rewritten one, but is missing fields.
+    InvalidFormat,
+    /// The hash doesn't match, so the address wasn't rewritten with this key.
+    InvalidHash,
+    /// The address was rewritten longer ago than the [maximum age](Srs::with_max_age).
+    Expired,
+}
+
+impl Display for SrsError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let msg = match self {
+            SrsError::InvalidAddress => "invalid address",
+            SrsError::NotSrs => "not an SRS address",
+            SrsError::InvalidFormat => "invalid SRS address",
+            SrsError::InvalidHash => "SRS hash doesn't match",
+            SrsError::Expired => "SRS address expired",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl core::error::Error for SrsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(data: &[u8]) -> [u8; 3] {
+        let sum = data
+            .iter()
+            .fold(7u32, |sum, &b| sum.wrapping_mul(31).wrapping_add(b.into()));
+        [sum as u8, (sum >> 8) as u8, (sum >> 16) as u8]
+    }
+
+    const NOW: u64 = 1_700_000_000;
+    const DAY: u64 = 86_400;
+
+    #[test]
+    fn forwards_and_reverses() {
+        let srs = Srs::new(key, "fwd.example");
+        let forwarded = srs.forward("a=b@example.com", NOW).unwrap();
+        let hash = srs.hash(&["G3", "example.com", "a=b"]);
+        assert_eq!(
+            forwarded,
+            format!("SRS0={hash}=G3=example.com=a=b@fwd.example")
+        );
+        assert_eq!(srs.reverse(&forwarded, NOW).unwrap(), "a=b@example.com");
+        // servers may change the case on the way
+        let lower = forwarded.to_lowercase();
+        assert_eq!(srs.reverse(&lower, NOW).unwrap(), "a=b@example.com");
+
+        assert_eq!(srs.forward("x@FWD.example", NOW).unwrap(), "x@FWD.example");
+        assert_eq!(srs.forward("", NOW).unwrap(), "");
+        assert_eq!(srs.forward("nope", NOW), Err(SrsError::InvalidAddress));
+    }
+
+    #[test]
+    fn forwards_srs0_as_srs1() {
+        let first = Srs::new(key, "first.example");
+        let second = Srs::new(|data: &[u8]| key(&[data, b"2"].concat()), "second.example");
+        let third = Srs::new(key, "third.example");
+        let srs0 = first.forward("a@example.com", NOW).unwrap();
+        let (srs0_local, _) = srs0.split_once('@').unwrap();
+        let srs1 = second.forward(&srs0, NOW).unwrap();
+        let rest = &srs0_local[4..];
+        let hash = second.hash(&["first.example", rest]);
+        assert_eq!(
+            srs1,
+            format!("SRS1={hash}=first.example={rest}@second.example")
+        );
+        // further hops point at the first forwarder as well
+        let hop = third.forward(&srs1, NOW).unwrap();
+        assert!(hop.contains("=first.example=="));
+        assert!(hop.ends_with("@third.example"));
+
+        assert_eq!(second.reverse(&srs1, NOW).unwrap(), srs0);
+        assert_eq!(third.reverse(&hop, NOW).unwrap(), srs0);
+        assert_eq!(first.reverse(&srs1, NOW), Err(SrsError::InvalidHash));
+    }
+
+    #[test]
+    fn rejects_forged_and_expired_addresses() {
+        let srs = Srs::new(key, "fwd.example").with_max_age(3);
+        let forwarded = srs.forward("a@example.com", NOW).unwrap();
+        assert_eq!(
+            srs.reverse(&forwarded, NOW + 3 * DAY).unwrap(),
+            "a@example.com"
+        );
+        assert_eq!(
+            srs.reverse(&forwarded, NOW + 4 * DAY),
+            Err(SrsError::Expired)
+        );
+        let forged = forwarded.replace("a@fwd", "b@fwd");
+        assert_eq!(srs.reverse(&forged, NOW), Err(SrsError::InvalidHash));
+        assert_eq!(srs.reverse("a@example.com", NOW), Err(SrsError::NotSrs));
+        assert_eq!(
+            srs.reverse("SRS0=x=y@fwd", NOW),
+            Err(SrsError::InvalidFormat)
+        );
+        assert_eq!(
+            srs.reverse("SRS1=x=y@fwd", NOW),
+            Err(SrsError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn timestamps_wrap_around() {
+        let day = |days: u64| days * DAY;
+        assert_eq!(&timestamp(day(0)), b"AA");
+        assert_eq!(&timestamp(day(1023)), b"77");
+        assert_eq!(&timestamp(day(1024)), b"AA");
+        assert_eq!(parse_timestamp("77"), Some(1023));
+        assert_eq!(parse_timestamp("a7"), Some(31));
+        assert_eq!(parse_timestamp("A"), None);
+        assert_eq!(parse_timestamp("A1"), None);
+        let srs = Srs::new(key, "fwd.example");
+        let forwarded = srs.forward("a@example.com", day(1023)).unwrap();
+        assert!(srs.reverse(&forwarded, day(1025)).is_ok());
+    }
+}
@@ -1,4 +1,11 @@
-use crate::smtp::Extensions;
+use core::fmt::Display;
+
+use crate::message::Lint;
+use crate::smtp::{
+    Extensions, Reply, ReplyCode,
+    enhanced::EnhancedCode,
+    retry::{RetryAction, RetryPolicy},
+};
 
 //todo: no thiserror so as not to pull in syn and keep embedded build times fast
 /// errors that originated from the SMTP protocol
@@ -12,14 +19,25 @@ pub enum MalformedError {
     InvalidEncoding,
     NoCode,
     UnexpectedCode {
-        expected: &'static [u16],
-        actual: u16,
+        expected: &'static [ReplyCode],
+        actual: ReplyCode,
     },
     CodeChanged {
-        old_code: u16,
-        new_code: u16,
+        old_code: ReplyCode,
+        new_code: ReplyCode,
     },
     UnexpectedEof,
+    /// a reply line is longer than we can represent
+    LineTooLong,
+    /// more data followed the last line of a reply
+    TrailingData,
+    /// A reply of `needed` bytes didn't fit into the buffer: a borrowed buffer can't grow, an
+    /// owned one only up to its [limit](crate::Smtp::set_max_buffer_size). The rest of the
+    /// reply was skipped, so the session is still usable.
+    ReplyTooLarge {
+        needed: usize,
+        capacity: usize,
+    },
 }
 
 impl core::error::Error for MalformedError {
@@ -35,11 +53,14 @@ impl core::fmt::Display for MalformedError {
             MalformedError::InvalidEncoding => write!(f, "Invalid encoding"),
             MalformedError::NoCode => write!(f, "No code"),
             MalformedError::UnexpectedCode { expected, actual } => {
-                write!(
-                    f,
-                    "Received unexpected code {}, expected one of {:?}",
-                    actual, expected
-                )
+                write!(f, "Received unexpected code {actual}, expected one of [")?;
+                for (idx, code) in expected.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{code}")?;
+                }
+                write!(f, "]")
             }
             MalformedError::CodeChanged { old_code, new_code } => {
                 write!(
@@ -49,6 +70,14 @@ impl core::fmt::Display for MalformedError {
                 )
             }
             MalformedError::UnexpectedEof => write!(f, "Unexpected EOF reached"),
+            MalformedError::LineTooLong => write!(f, "Reply line too long"),
+            MalformedError::TrailingData => write!(f, "Trailing data after the last reply line"),
+            MalformedError::ReplyTooLarge { needed, capacity } => {
+                write!(
+                    f,
+                    "Reply of {needed} bytes too large for the {capacity} byte buffer"
+                )
+            }
         }
     }
 }
@@ -59,7 +88,104 @@ pub enum ProtocolError {
     LineTooLong,
     #[cfg(feature = "lettre")]
     NoSender,
-    UnsupportedExtension(Extensions<'static>),
+    /// The server didn't advertise an extension an operation needs, detected before sending
+    /// the command it would have rejected.
+    UnsupportedExtension {
+        extension: Extensions<'static>,
+        needed_for: Operation,
+    },
+    /// The session authenticates with a TLS client certificate only, but the server
+    /// still demanded `AUTH`.
+    ClientCertificateNotAccepted,
+    /// Client certificates are part of the TLS handshake, they can't be used without TLS.
+    ClientCertificateWithoutTls,
+    /// Authentication by client certificate was requested, but no certificate was given.
+    MissingClientCertificate,
+    /// The command needs an EHLO or HELO first, e.g. after connecting or upgrading to TLS.
+    NotGreeted,
+    /// STARTTLS was accepted, the stream has to be upgraded before sending anything else.
+    TlsUpgradePending,
+    /// The command is only allowed before greeting the server.
+    AlreadyGreeted,
+    /// AUTH would send the credentials over a connection without TLS.
+    PlaintextAuth,
+    /// The server sent an AUTH challenge, which has to be answered with
+    /// [`Smtp::auth_respond`](crate::Smtp::auth_respond) or
+    /// [`Smtp::auth_cancel`](crate::Smtp::auth_cancel) before anything else is sent.
+    AuthChallengePending,
+    /// There is no AUTH challenge to answer.
+    NoAuthChallenge,
+    /// The message is larger than the limit the server advertised with SIZE.
+    /// <https://datatracker.ietf.org/doc/html/rfc1870#section-4>
+    MessageTooLarge {
+        size: u64,
+        limit: u64,
+    },
+    /// The command needs a successful AUTH first, e.g. `MAIL FROM` naming a
+    /// [submitter](crate::envelope::Envelope::with_submitter).
+    NotAuthenticated,
+    /// A [message](crate::message::Message) header has a line break where it would end the
+    /// field, a [custom header](crate::message::Message::with_header) an invalid or reserved
+    /// name, there are more custom headers than fit, or the `Sender` is the same as the
+    /// `From` address.
+    InvalidHeader,
+    /// More [attachments](crate::message::Message::with_attachment) were added to a message
+    /// than it can hold, see [`MAX_ATTACHMENTS`](crate::message::MAX_ATTACHMENTS).
+    TooManyAttachments,
+    /// An address of the envelope contains a line break or `>`, see
+    /// [`Envelope::has_valid_addresses`](crate::envelope::Envelope::has_valid_addresses).
+    InvalidAddress,
+    /// An [extension parameter](crate::envelope::Parameter) of the envelope has an invalid
+    /// keyword or value, or its [ENVID](crate::envelope::Envelope::with_envid) is too long.
+    InvalidParameter,
+    /// The argument of a command like `VRFY` is empty or contains a line break.
+    InvalidArgument,
+    /// The session is in [dry-run](crate::Smtp::set_dry_run) mode, so no message data is sent.
+    DryRun,
+    /// An earlier command was interrupted before its reply was read, so the session is out
+    /// of step with the server, see [`Smtp::is_usable`](crate::Smtp::is_usable).
+    Interrupted,
+    /// The message has a deliverability problem, and the session is in
+    /// [strict mode](crate::Smtp::set_strict_messages).
+    MessageLint(Lint),
+}
+
+impl ProtocolError {
+    pub(crate) fn unsupported(extension: Extensions<'static>, needed_for: Operation) -> Self {
+        ProtocolError::UnsupportedExtension {
+            extension,
+            needed_for,
+        }
+    }
+}
+
+/// What needed the extension of a [`ProtocolError::UnsupportedExtension`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    StartTls,
+    /// Authentication with the given mechanism.
+    Auth(&'static str),
+    /// `RET=`, `ENVID=`, `NOTIFY=` or `ORCPT=` in the envelope.
+    DsnParameters,
+    /// The `AUTH=` parameter of `MAIL FROM`.
+    Submitter,
+    /// An envelope address with non-ASCII characters.
+    InternationalAddress,
+    /// `BODY=8BITMIME` in the envelope.
+    EightBitBody,
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Operation::StartTls => write!(f, "STARTTLS"),
+            Operation::Auth(mechanism) => write!(f, "AUTH {mechanism}"),
+            Operation::DsnParameters => write!(f, "delivery status notification parameters"),
+            Operation::Submitter => write!(f, "the AUTH= parameter of MAIL FROM"),
+            Operation::InternationalAddress => write!(f, "a non-ASCII address"),
+            Operation::EightBitBody => write!(f, "an 8-bit message body"),
+        }
+    }
 }
 
 impl core::fmt::Display for ProtocolError {
@@ -69,9 +195,58 @@ impl core::fmt::Display for ProtocolError {
             ProtocolError::LineTooLong => write!(f, "Line too long"),
             #[cfg(feature = "lettre")]
             ProtocolError::NoSender => write!(f, "Missing \"from\" address on lettre envelope"),
-            ProtocolError::UnsupportedExtension(ext) => {
-                write!(f, "Extension {ext} not supported")
+            ProtocolError::UnsupportedExtension {
+                extension,
+                needed_for,
+            } => write!(
+                f,
+                "Extension {extension} not supported, needed for {needed_for}"
+            ),
+            ProtocolError::ClientCertificateNotAccepted => write!(
+                f,
+                "Server demands AUTH, the TLS client certificate was not accepted"
+            ),
+            ProtocolError::ClientCertificateWithoutTls => {
+                write!(f, "A TLS client certificate requires a TLS connection")
+            }
+            ProtocolError::MissingClientCertificate => {
+                write!(f, "Client certificate authentication without a certificate")
+            }
+            ProtocolError::NotGreeted => write!(f, "Server has not been greeted with EHLO"),
+            ProtocolError::TlsUpgradePending => {
+                write!(f, "STARTTLS accepted but the stream was not upgraded")
+            }
+            ProtocolError::AlreadyGreeted => write!(f, "Server has already been greeted"),
+            ProtocolError::PlaintextAuth => {
+                write!(
+                    f,
+                    "Refusing to send credentials over an unencrypted connection"
+                )
             }
+            ProtocolError::AuthChallengePending => {
+                write!(
+                    f,
+                    "The AUTH challenge of the server has to be answered first"
+                )
+            }
+            ProtocolError::NoAuthChallenge => write!(f, "No AUTH challenge to answer"),
+            ProtocolError::MessageTooLarge { size, limit } => write!(
+                f,
+                "Message of {size} bytes exceeds the server limit of {limit} bytes"
+            ),
+            ProtocolError::NotAuthenticated => {
+                write!(f, "The command requires an authenticated session")
+            }
+            ProtocolError::InvalidHeader => write!(f, "Invalid message header"),
+            ProtocolError::TooManyAttachments => write!(f, "Too many message attachments"),
+            ProtocolError::InvalidAddress => write!(f, "Invalid envelope address"),
+            ProtocolError::InvalidParameter => write!(f, "Invalid envelope parameter"),
+            ProtocolError::InvalidArgument => write!(f, "Invalid command argument"),
+            ProtocolError::DryRun => write!(f, "Not sending message data in dry-run mode"),
+            ProtocolError::Interrupted => {
+                write!(f, "An earlier command was interrupted before its reply")
+            }
+            ProtocolError::MessageLint(lint) => write!(f, "Refusing message: {lint}"),
         }
     }
 }
@@ -82,17 +257,191 @@ impl core::error::Error for ProtocolError {
     }
 }
 
+/// An owned copy of the text of a server reply.
+///
+/// Errors outlive the read buffer the reply was parsed from, so the text is copied into
+/// a small fixed-size buffer. This works without `alloc`, at the cost of truncating long
+/// replies (at a character boundary). The buffer is kept small because it is part of every
+/// `Result` returned by the client. Multi-line replies are joined with spaces.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ReplyText {
+    buf: [u8; ReplyText::CAPACITY],
+    len: u8,
+}
+
+impl ReplyText {
+    /// The maximum number of bytes kept.
+    pub const CAPACITY: usize = 64;
+
+    pub fn new(text: &str) -> Self {
+        let mut reply_text = ReplyText {
+            buf: [0; Self::CAPACITY],
+            len: 0,
+        };
+        reply_text.push(text);
+        reply_text
+    }
+
+    /// Copies the text of every line of `reply`, joined with spaces.
+    pub fn from_reply(reply: &Reply<'_>) -> Self {
+        let mut reply_text = ReplyText::new("");
+        for (idx, line) in reply.lines().enumerate() {
+            if idx > 0 {
+                reply_text.push(" ");
+            }
+            reply_text.push(line);
+        }
+        reply_text
+    }
+
+    fn push(&mut self, text: &str) {
+        let available = Self::CAPACITY - self.len as usize;
+        let mut end = text.len().min(available);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        let start = self.len as usize;
+        self.buf[start..start + end].copy_from_slice(&text.as_bytes()[..end]);
+        self.len += end as u8;
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len as usize])
+            .expect("only whole characters are copied")
+    }
+}
+
+impl core::fmt::Debug for ReplyText {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Display for ReplyText {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// any error that can occur in the SMTP transport layer
-/// can be categorized into three categories:
-/// - IO errors
+/// can be categorized into four categories:
+/// - IO errors, including failed TLS handshakes
 /// - Protocol errors
-/// - General SMTP related errors that are expected within the protocol
-///   like "message too long" or "not authenticated"
+/// - Malformed replies from the server
+/// - Negative replies, which are expected within the protocol
+///   like "mailbox full" or "try again later"
 #[derive(Debug)]
 pub enum Error<T: core::error::Error> {
     IoError(T),
     ProtocolError(ProtocolError),
     MalformedError(MalformedError),
+    /// The server answered with a 4xx or 5xx reply.
+    ServerRejected {
+        code: ReplyCode,
+        /// The enhanced status code, if the server sent one
+        /// <https://datatracker.ietf.org/doc/html/rfc3463>
+        enhanced: Option<EnhancedCode>,
+        message: ReplyText,
+    },
+    /// The server didn't reply within the [timeout](crate::Smtp::set_timer) of the command.
+    Timeout,
+    /// Starting TLS failed, e.g. the server's certificate isn't trusted or the handshake was
+    /// cut off. The connection can't be used anymore.
+    Tls(T),
+    /// The [credential provider](crate::smtp::auth::CredentialProvider) of
+    /// [`Smtp::authenticate_with`](crate::Smtp::authenticate_with) failed to fetch or refresh
+    /// the credentials. Nothing was sent for them, so the session is still usable.
+    Credentials(T),
+}
+
+impl<T: core::error::Error> Error<T> {
+    /// Turns a reply with an unexpected code into an error.
+    ///
+    /// Negative replies become [`Error::ServerRejected`], anything else means the server
+    /// doesn't follow the protocol and becomes [`MalformedError::UnexpectedCode`].
+    pub(crate) fn unexpected_reply(reply: &Reply<'_>, expected: &'static [ReplyCode]) -> Self {
+        if reply.code().is_negative() {
+            Error::ServerRejected {
+                code: reply.code(),
+                enhanced: reply.enhanced_code(),
+                message: ReplyText::from_reply(reply),
+            }
+        } else {
+            Error::MalformedError(MalformedError::UnexpectedCode {
+                expected,
+                actual: reply.code(),
+            })
+        }
+    }
+
+    /// Returns true if the server rejected the command with a transient (4xx) reply,
+    /// meaning the same command may succeed if retried later.
+    ///
+    /// IO, timeout, protocol and malformed reply errors are neither transient nor permanent:
+    /// whether to retry those depends on the application.
+    /// <https://datatracker.ietf.org/doc/html/rfc5321#section-4.2.1>
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Error::ServerRejected { code, .. } if code.is_transient())
+    }
+
+    /// Returns true if the server rejected the command with a permanent (5xx) reply.
+    pub fn is_permanent(&self) -> bool {
+        matches!(self, Error::ServerRejected { code, .. } if code.is_permanent())
+    }
+
+    /// Like [`is_transient`](Self::is_transient), but lets `policy` override the basic code
+    /// of the rejection, e.g. to give up on `4.7.1` policy blocks.
+    pub fn should_retry(&self, policy: &RetryPolicy<'_>) -> bool {
+        matches!(self, Error::ServerRejected { code, enhanced, .. }
+            if policy.action(*code, *enhanced) == RetryAction::Retry)
+    }
+
+    /// Returns true if the server refused the command until the connection is secured with
+    /// `STARTTLS`, e.g. `530 5.7.0 Must issue a STARTTLS command first`.
+    ///
+    /// `530` also means authentication is required, the two are told apart by the text.
+    /// <https://datatracker.ietf.org/doc/html/rfc3207#section-4>
+    pub fn is_starttls_required(&self) -> bool {
+        matches!(self, Error::ServerRejected { code, message, .. }
+            if *code == ReplyCode::AUTH_REQUIRED
+                && message
+                    .as_str()
+                    .as_bytes()
+                    .windows(b"STARTTLS".len())
+                    .any(|word| word.eq_ignore_ascii_case(b"STARTTLS")))
+    }
+
+    /// Returns true if the server refused the command because the session isn't
+    /// authenticated (`530`, unless [STARTTLS is required](Self::is_starttls_required)), or
+    /// no longer accepts its credentials (`535`), e.g. after it dropped the authentication of
+    /// a long idle session.
+    /// <https://datatracker.ietf.org/doc/html/rfc4954#section-6>
+    pub fn is_authentication_required(&self) -> bool {
+        matches!(self, Error::ServerRejected { code, .. }
+            if *code == ReplyCode::AUTH_REQUIRED || *code == ReplyCode::AUTH_FAILED)
+            && !self.is_starttls_required()
+    }
+
+    /// Returns false if the connection should be closed instead of sending another message.
+    ///
+    /// That is the case after IO and TLS errors, timeouts, malformed replies and `421 Service not
+    /// available`, which the server sends before closing the connection. The rest of a
+    /// reply may still arrive after a timeout, so the session is out of step with the
+    /// server. A reply too large for the buffer was skipped completely instead. After any
+    /// other rejection the transaction was already aborted with [`RSET`](crate::Smtp::rset),
+    /// and protocol errors are detected before anything is sent. A server which rejected the
+    /// message in the middle of its data leaves the session out of step instead, see
+    /// [`Smtp::is_usable`](crate::Smtp::is_usable).
+    /// <https://datatracker.ietf.org/doc/html/rfc5321#section-3.8>
+    pub fn is_session_usable(&self) -> bool {
+        match self {
+            Error::MalformedError(MalformedError::ReplyTooLarge { .. }) => true,
+            Error::IoError(_) | Error::MalformedError(_) | Error::Timeout | Error::Tls(_) => false,
+            Error::ServerRejected { code, .. } => *code != ReplyCode::SERVICE_NOT_AVAILABLE,
+            Error::ProtocolError(e) => !matches!(e, ProtocolError::Interrupted),
+            Error::Credentials(_) => true,
+        }
+    }
 }
 
 impl<T: core::error::Error> core::fmt::Display for Error<T> {
@@ -101,6 +450,12 @@ impl<T: core::error::Error> core::fmt::Display for Error<T> {
             Error::IoError(e) => write!(f, "IO Error: {e}"),
             Error::ProtocolError(e) => e.fmt(f),
             Error::MalformedError(e) => e.fmt(f),
+            Error::ServerRejected { code, message, .. } => {
+                write!(f, "Server rejected command: {code} {message}")
+            }
+            Error::Timeout => write!(f, "Timed out waiting for a reply"),
+            Error::Tls(e) => write!(f, "TLS Error: {e}"),
+            Error::Credentials(e) => write!(f, "Credentials Error: {e}"),
         }
     }
 }
@@ -108,9 +463,10 @@ impl<T: core::error::Error> core::fmt::Display for Error<T> {
 impl<T: core::error::Error + 'static> core::error::Error for Error<T> {
     fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
         match self {
-            Error::IoError(e) => Some(e),
+            Error::IoError(e) | Error::Tls(e) | Error::Credentials(e) => Some(e),
             Error::ProtocolError(e) => e.source(),
             Error::MalformedError(e) => e.source(),
+            Error::ServerRejected { .. } | Error::Timeout => None,
         }
     }
 }
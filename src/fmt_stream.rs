@@ -0,0 +1,101 @@
+//! Streaming of formatted values without formatting them in memory as a whole.
+
+use core::fmt::{self, Display, Write};
+
+/// Splits the output of a [`Display`] value into chunks of at most `N` bytes, so dates,
+/// sizes and the like can be written to the stream without allocating or a buffer sized
+/// for the longest possible value.
+///
+/// The value is formatted again for every chunk, skipping what was returned before, so it
+/// must produce the same output every time.
+pub(crate) struct FmtToStream<V, const N: usize = 32> {
+    value: V,
+    written: usize,
+    buf: [u8; N],
+}
+
+impl<V: Display, const N: usize> FmtToStream<V, N> {
+    pub(crate) fn new(value: V) -> Self {
+        FmtToStream {
+            value,
+            written: 0,
+            buf: [0; N],
+        }
+    }
+
+    /// Returns the next chunk, or `None` once the whole value was returned.
+    /// Not an `Iterator` because chunks borrow from `self`.
+    pub(crate) fn next_chunk(&mut self) -> Option<&[u8]> {
+        let mut window = Window {
+            skip: self.written,
+            buf: &mut self.buf,
+            len: 0,
+        };
+        // fails once the window is full, which ends formatting early
+        let _ = write!(window, "{}", self.value);
+        let len = window.len;
+        if len == 0 {
+            return None;
+        }
+        self.written += len;
+        Some(&self.buf[..len])
+    }
+}
+
+// collects the part of the output after the first `skip` bytes which fits into `buf`
+struct Window<'b> {
+    skip: usize,
+    buf: &'b mut [u8],
+    len: usize,
+}
+
+impl Write for Window<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let skipped = self.skip.min(s.len());
+        self.skip -= skipped;
+        let rest = &s.as_bytes()[skipped..];
+        let take = rest.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + take].copy_from_slice(&rest[..take]);
+        self.len += take;
+        if take < rest.len() {
+            return Err(fmt::Error);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect<const N: usize>(value: impl Display) -> Vec<u8> {
+        let mut chunks = FmtToStream::<_, N>::new(value);
+        let mut out = Vec::new();
+        while let Some(chunk) = chunks.next_chunk() {
+            assert!(!chunk.is_empty() && chunk.len() <= N);
+            out.extend_from_slice(chunk);
+        }
+        out
+    }
+
+    // formatted in several pieces, like most `Display` implementations
+    struct Sample;
+
+    impl Display for Sample {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("größe")?;
+            write!(f, "-{:>8}-{:x}", 12345, u64::MAX)
+        }
+    }
+
+    #[test]
+    fn chunks_match_the_formatted_value() {
+        let value = &Sample;
+        let expected = value.to_string();
+        assert_eq!(collect::<1>(value), expected.as_bytes());
+        assert_eq!(collect::<7>(value), expected.as_bytes());
+        assert_eq!(collect::<32>(value), expected.as_bytes());
+        assert_eq!(collect::<256>(value), expected.as_bytes());
+        assert_eq!(collect::<4>(""), b"");
+    }
+}
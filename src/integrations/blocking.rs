@@ -0,0 +1,178 @@
+//! A synchronous session over any [`std::io::Read`] + [`Write`] stream, e.g. a
+//! [`TcpStream`](std::net::TcpStream), for tools which don't want an async runtime just to
+//! send an email.
+//!
+//! The async session is driven by a minimal [`block_on`], the stream itself blocks.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::net::TcpStream;
+//!
+//! use simple_smtp::{
+//!     envelope::{Envelope, Recipient},
+//!     integrations::blocking::BlockingSmtp,
+//!     message::Message,
+//! };
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let mut smtp = BlockingSmtp::new(TcpStream::connect("localhost:25")?);
+//! smtp.ready()?;
+//! smtp.ehlo("client.example.com")?;
+//! let recipients = [Recipient::new("bob@example.com")];
+//! let message = Message::new("alice@example.com")
+//!     .with_to(&["bob@example.com"])
+//!     .with_text_body("Hi Bob!");
+//! smtp.send_message(&Envelope::new("alice@example.com", &recipients), &message)?;
+//! smtp.quit()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use core::{
+    pin::pin,
+    task::{Context, Poll, Waker},
+};
+use std::{
+    io::{self, Read, Write},
+    sync::Arc,
+    task::Wake,
+    thread::{self, Thread},
+};
+
+use crate::{
+    Error, ReadWrite, Smtp,
+    envelope::Envelope,
+    message::Message,
+    smtp::{EhloResponse, Ready, Reply, SendReport},
+};
+
+/// A [`ReadWrite`] for a blocking stream.
+///
+/// There is no [`Timer`](crate::Timer) for blocking streams, use the timeouts of the stream
+/// instead, e.g. [`TcpStream::set_read_timeout`](std::net::TcpStream::set_read_timeout).
+pub struct BlockingIo<T: Read + Write>(pub T);
+
+impl<T: Read + Write> ReadWrite for BlockingIo<T> {
+    type Error = io::Error;
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf)
+    }
+
+    async fn write_single(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        self.0.write_all(buf)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.flush()
+    }
+}
+
+/// Runs `future` to completion on the current thread, parking it while the future is
+/// pending.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+/// An [`Smtp`] session whose commands block until the reply arrives.
+///
+/// The common commands have a blocking version here, anything else can be run on the
+/// [`session`](Self::session) with [`block_on`], or with [`run`](Self::run).
+pub struct BlockingSmtp<'a, T: Read + Write> {
+    smtp: Smtp<'a, BlockingIo<T>>,
+}
+
+impl<T: Read + Write> BlockingSmtp<'static, T> {
+    pub fn new(stream: T) -> Self {
+        BlockingSmtp {
+            smtp: Smtp::new(BlockingIo(stream)),
+        }
+    }
+}
+
+impl<'a, T: Read + Write> BlockingSmtp<'a, T> {
+    /// Wraps a session which was set up already, e.g. with a borrowed buffer.
+    pub fn from_session(smtp: Smtp<'a, BlockingIo<T>>) -> Self {
+        BlockingSmtp { smtp }
+    }
+
+    /// The async session, e.g. for its settings.
+    pub fn session(&mut self) -> &mut Smtp<'a, BlockingIo<T>> {
+        &mut self.smtp
+    }
+
+    /// Runs `f` on the session, blocking until it completes.
+    pub fn run<R>(&mut self, f: impl AsyncFnOnce(&mut Smtp<'a, BlockingIo<T>>) -> R) -> R {
+        block_on(f(&mut self.smtp))
+    }
+
+    pub fn ready(&mut self) -> Result<Ready<'_>, Error<io::Error>> {
+        block_on(self.smtp.ready())
+    }
+
+    pub fn ehlo(&mut self, domain: &str) -> Result<EhloResponse<'_>, Error<io::Error>> {
+        block_on(self.smtp.ehlo(domain))
+    }
+
+    pub fn auth(&mut self, username: &str, password: &str) -> Result<Reply<'_>, Error<io::Error>> {
+        block_on(self.smtp.auth(username, password))
+    }
+
+    pub fn noop(&mut self) -> Result<Reply<'_>, Error<io::Error>> {
+        block_on(self.smtp.noop())
+    }
+
+    /// See [`Smtp::send_message`].
+    pub fn send_message(
+        &mut self,
+        envelope: &Envelope<'_>,
+        message: &Message<'_>,
+    ) -> Result<(), Error<io::Error>> {
+        block_on(self.smtp.send_message(envelope, message))
+    }
+
+    /// See [`Smtp::send_mail`].
+    pub fn send_mail(
+        &mut self,
+        from: impl AsRef<str>,
+        to: impl Iterator<Item = impl AsRef<str>>,
+        data: &[u8],
+    ) -> Result<(), Error<io::Error>> {
+        block_on(self.smtp.send_mail(from, to, data))
+    }
+
+    /// See [`Smtp::send_mail_report`].
+    pub fn send_mail_report(
+        &mut self,
+        from: impl AsRef<str>,
+        to: impl Iterator<Item = impl AsRef<str>>,
+        data: &[u8],
+    ) -> Result<SendReport<io::Error>, Error<io::Error>> {
+        block_on(self.smtp.send_mail_report(from, to, data))
+    }
+
+    pub fn quit(&mut self) -> Result<Reply<'_>, Error<io::Error>> {
+        block_on(self.smtp.quit())
+    }
+
+    pub fn into_inner(self) -> T {
+        self.smtp.into_inner().0.0
+    }
+}
@@ -1,6 +1,13 @@
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
 use embassy_net::tcp::TcpSocket;
+use embassy_time::Instant;
 
-use crate::ReadWrite;
+use crate::{ReadWrite, Timer};
 
 impl ReadWrite for TcpSocket<'_> {
     type Error = EmbassyTcpError;
@@ -15,6 +22,10 @@ impl ReadWrite for TcpSocket<'_> {
         }
         Ok(())
     }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        TcpSocket::flush(self).await.map_err(EmbassyTcpError)
+    }
 }
 
 #[derive(Debug)]
@@ -31,3 +42,31 @@ impl core::error::Error for EmbassyTcpError {
         None
     }
 }
+
+/// A [`Timer`] on the embassy time driver, see [`Smtp::set_timer`](crate::Smtp::set_timer).
+#[derive(Debug, Default)]
+pub struct EmbassyTimer(Option<embassy_time::Timer>);
+
+impl EmbassyTimer {
+    pub fn new() -> Self {
+        EmbassyTimer(None)
+    }
+}
+
+impl Timer for EmbassyTimer {
+    fn start(&mut self, timeout: Duration) {
+        // timeouts too long for the tick rate never elapse
+        let deadline = embassy_time::Duration::try_from(timeout)
+            .ok()
+            .and_then(|timeout| Instant::now().checked_add(timeout))
+            .unwrap_or(Instant::MAX);
+        self.0 = Some(embassy_time::Timer::at(deadline));
+    }
+
+    fn poll_elapsed(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        match &mut self.0 {
+            Some(timer) => Pin::new(timer).poll(cx),
+            None => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use core::ops::{Deref, DerefMut};
+
+use embedded_io_async::{ErrorKind, Read, Write};
+
+use crate::ReadWrite;
+
+/// A [`ReadWrite`] for any transport implementing the `embedded-io-async` traits, e.g. a
+/// TLS stream or a UART.
+///
+/// A wrapper rather than a blanket implementation, so it doesn't overlap with the transports
+/// which already implement [`ReadWrite`] themselves.
+pub struct EmbeddedIo<T: Read + Write>(pub T);
+
+impl<T: Read + Write> Deref for EmbeddedIo<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Read + Write> DerefMut for EmbeddedIo<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<T: Read + Write> ReadWrite for EmbeddedIo<T> {
+    type Error = EmbeddedIoError<T::Error>;
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf).await.map_err(EmbeddedIoError)
+    }
+
+    async fn write_single(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        self.0.write_all(buf).await.map_err(EmbeddedIoError)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.flush().await.map_err(EmbeddedIoError)
+    }
+}
+
+/// An error of the wrapped transport, the `embedded-io` errors don't implement
+/// [`core::error::Error`].
+#[derive(Debug)]
+pub struct EmbeddedIoError<E: embedded_io_async::Error>(pub E);
+
+impl<E: embedded_io_async::Error> EmbeddedIoError<E> {
+    pub fn kind(&self) -> ErrorKind {
+        self.0.kind()
+    }
+}
+
+impl<E: embedded_io_async::Error> core::fmt::Display for EmbeddedIoError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "embedded-io error: {:?}", self.0.kind())
+    }
+}
+
+impl<E: embedded_io_async::Error> core::error::Error for EmbeddedIoError<E> {}
@@ -0,0 +1,60 @@
+use core::{
+    future::poll_fn,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+use std::io;
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use crate::ReadWrite;
+
+/// A [`ReadWrite`] for any stream implementing the `futures-io` traits, e.g. an async-std or
+/// smol `TcpStream`, so the crate can be used without tokio.
+pub struct FuturesIo<T: AsyncRead + AsyncWrite + Unpin>(pub T);
+
+impl<T: AsyncRead + AsyncWrite + Unpin> Deref for FuturesIo<T> {
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> DerefMut for FuturesIo<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> ReadWrite for FuturesIo<T> {
+    type Error = io::Error;
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        poll_fn(|cx| Pin::new(&mut self.0).poll_read(cx, buf)).await
+    }
+
+    fn read_available(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        // poll once, a pending read means nothing arrived yet
+        let mut cx = Context::from_waker(Waker::noop());
+        match Pin::new(&mut self.0).poll_read(&mut cx, buf) {
+            Poll::Ready(result) => result,
+            Poll::Pending => Ok(0),
+        }
+    }
+
+    async fn write_single(&mut self, mut buf: &[u8]) -> Result<(), Self::Error> {
+        while !buf.is_empty() {
+            let written = poll_fn(|cx| Pin::new(&mut self.0).poll_write(cx, buf)).await?;
+            if written == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            buf = &buf[written..];
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        poll_fn(|cx| Pin::new(&mut self.0).poll_flush(cx)).await
+    }
+}
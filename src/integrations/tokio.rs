@@ -1,9 +1,20 @@
-use core::ops::{Deref, DerefMut};
+use core::{
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
 use std::{array, io::IoSlice};
 
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+    time::{Instant, Sleep},
+};
 
-use crate::ReadWrite;
+use crate::{AsyncBodySource, ReadWrite, Timer};
+
+mod client;
+pub use client::{ClientSession, ClientStream, SmtpClientBuilder, TlsMode};
 
 pub struct TokioIo<T: AsyncRead + AsyncWrite + Unpin + Send>(pub T);
 impl<T: AsyncRead + AsyncWrite + Unpin + Send> Deref for TokioIo<T> {
@@ -25,6 +36,20 @@ impl<T: AsyncRead + AsyncWrite + Unpin + Send> ReadWrite for TokioIo<T> {
         self.0.read(buf).await
     }
 
+    fn read_available(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        // poll once, a pending read means nothing arrived yet
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut read_buf = ReadBuf::new(buf);
+        match Pin::new(&mut self.0).poll_read(&mut cx, &mut read_buf) {
+            Poll::Ready(result) => result.map(|()| read_buf.filled().len()),
+            Poll::Pending => Ok(0),
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.flush().await
+    }
+
     async fn write_single(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
         if buf.is_empty() {
             return Ok(());
@@ -78,39 +103,318 @@ impl<T: AsyncRead + AsyncWrite + Unpin + Send> ReadWrite for TokioIo<T> {
     }
 }
 
+/// Streams a message body from any [`AsyncRead`], e.g. a file.
+pub struct TokioBody<R: AsyncRead + Unpin> {
+    reader: R,
+    buf: Box<[u8]>,
+}
+
+impl<R: AsyncRead + Unpin> TokioBody<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_capacity(reader, 8 * 1024)
+    }
+
+    /// `capacity` is the largest chunk read at once.
+    pub fn with_capacity(reader: R, capacity: usize) -> Self {
+        TokioBody {
+            reader,
+            buf: vec![0; capacity].into_boxed_slice(),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncBodySource for TokioBody<R> {
+    type Error = tokio::io::Error;
+    async fn next_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        let read = self.reader.read(&mut self.buf).await?;
+        Ok(&self.buf[..read])
+    }
+}
+
+/// A [`Timer`] on the tokio runtime, see [`Smtp::set_timer`](crate::Smtp::set_timer).
+#[derive(Debug, Default)]
+pub struct TokioTimer(Option<Pin<Box<Sleep>>>);
+
+impl TokioTimer {
+    pub fn new() -> Self {
+        TokioTimer(None)
+    }
+}
+
+impl Timer for TokioTimer {
+    fn start(&mut self, timeout: Duration) {
+        // timeouts too long to represent end 30 years from now, which is what tokio does
+        let now = Instant::now();
+        let deadline = now
+            .checked_add(timeout)
+            .unwrap_or(now + Duration::from_secs(60 * 60 * 24 * 365 * 30));
+        match &mut self.0 {
+            Some(sleep) => sleep.as_mut().reset(deadline),
+            None => self.0 = Some(Box::pin(tokio::time::sleep_until(deadline))),
+        }
+    }
+
+    fn poll_elapsed(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        match &mut self.0 {
+            Some(sleep) => sleep.as_mut().poll(cx),
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(feature = "rustls")]
+pub use rustls_support::{
+    ClientCertificate, RootCertificates, connect_tls, connect_tls_with_config,
+};
+
+#[cfg(feature = "chaos")]
+pub mod chaos;
+#[cfg(feature = "dane")]
+pub mod dane;
+#[cfg(feature = "dangerous-tls")]
+pub mod dangerous;
+#[cfg(feature = "delivery")]
+pub mod delivery;
+#[cfg(feature = "mta-sts")]
+pub mod mta_sts;
+#[cfg(feature = "pool")]
+pub mod pool;
+
+#[cfg(feature = "rustls")]
+mod verifier;
+#[cfg(feature = "rustls")]
+pub use verifier::{PinnedCertificate, client_config_with_verifier};
+
+#[cfg(feature = "native-tls")]
+mod native_tls_support;
+#[cfg(feature = "native-tls")]
+pub use native_tls_support::{connect_tls_native, connect_tls_native_with};
+
 #[cfg(feature = "rustls")]
 mod rustls_support {
-    use std::sync::Arc;
+    use std::{io, sync::Arc};
 
+    use rustls::{
+        ClientConfig, RootCertStore,
+        client::danger::ServerCertVerifier,
+        pki_types::{CertificateDer, PrivateKeyDer, ServerName, pem::PemObject},
+    };
     use tokio::io::{AsyncRead, AsyncWrite};
     use tokio_rustls::{TlsConnector, client::TlsStream};
 
-    use super::TokioIo;
-    use crate::{Error, ReadWrite, Smtp};
+    use super::{TokioIo, client_config_with_verifier};
+    use crate::{Error, ReadWrite, Smtp, address::parse_ip_host};
+
+    /// A TLS client certificate, for relays which authenticate clients by mutual TLS.
+    pub struct ClientCertificate {
+        pub chain: Vec<CertificateDer<'static>>,
+        pub key: PrivateKeyDer<'static>,
+    }
+
+    /// Certificates of private CAs, e.g. a corporate CA, which are trusted in addition to the
+    /// [webpki roots](webpki_roots).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn example() -> std::io::Result<()> {
+    /// use simple_smtp::integrations::tokio::{RootCertificates, SmtpClientBuilder};
+    ///
+    /// let roots = RootCertificates::new().add_pem(&std::fs::read("corporate-ca.pem")?)?;
+    /// let builder = SmtpClientBuilder::new("relay.corp.example").with_root_certificates(roots);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[derive(Debug, Clone, Default)]
+    pub struct RootCertificates(Vec<CertificateDer<'static>>);
+
+    impl RootCertificates {
+        pub fn new() -> Self {
+            RootCertificates(Vec::new())
+        }
+
+        /// Adds a DER encoded certificate.
+        pub fn add_der(mut self, der: &[u8]) -> io::Result<Self> {
+            let cert = CertificateDer::from(der.to_vec());
+            // fail here instead of during the handshake
+            RootCertStore::empty()
+                .add(cert.clone())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            self.0.push(cert);
+            Ok(self)
+        }
+
+        /// Adds every certificate in a PEM file, which has to contain at least one.
+        pub fn add_pem(mut self, pem: &[u8]) -> io::Result<Self> {
+            let before = self.0.len();
+            for cert in CertificateDer::pem_slice_iter(pem) {
+                let cert = cert.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                self = self.add_der(&cert)?;
+            }
+            if self.0.len() == before {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "no certificate in the PEM data",
+                ));
+            }
+            Ok(self)
+        }
+
+        /// A rustls config trusting these certificates and the webpki roots, for
+        /// [`connect_tls_with_config`] and [`Smtp::upgrade_to_tls_with_config`].
+        pub fn client_config(
+            &self,
+            client_cert: Option<ClientCertificate>,
+        ) -> io::Result<Arc<ClientConfig>> {
+            let mut root_cert_store =
+                RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            let (_, ignored) = root_cert_store.add_parsable_certificates(self.0.iter().cloned());
+            debug_assert_eq!(ignored, 0, "checked when added");
+            let builder = ClientConfig::builder().with_root_certificates(root_cert_store);
+            let config = match client_cert {
+                Some(cert) => builder
+                    .with_client_auth_cert(cert.chain, cert.key)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+                None => builder.with_no_client_auth(),
+            };
+            Ok(Arc::new(config))
+        }
+    }
+
+    // rustls doesn't send SNI for an IP address, and checks it against the IP addresses in
+    // the certificate instead of the DNS names
+    fn server_name(domain: &str) -> io::Result<ServerName<'static>> {
+        if let Some(ip) = parse_ip_host(domain) {
+            return Ok(ServerName::IpAddress(ip.into()));
+        }
+        ServerName::try_from(domain)
+            .map(|name| name.to_owned())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    /// Starts TLS on a freshly opened connection, for implicit TLS submission (port 465).
+    /// <https://datatracker.ietf.org/doc/html/rfc8314#section-3>
+    ///
+    /// `domain` is the name the certificate is checked against. It may also be an IP address,
+    /// see [`parse_ip_host`] for the accepted forms.
+    pub async fn connect_tls<T: AsyncRead + AsyncWrite + Unpin + Send>(
+        stream: T,
+        domain: &str,
+        client_cert: Option<ClientCertificate>,
+    ) -> io::Result<TokioIo<TlsStream<T>>> {
+        let config = RootCertificates::new().client_config(client_cert)?;
+        connect_tls_with_config(stream, domain, config).await
+    }
+
+    /// Like [`connect_tls`], but with a custom rustls `config`, e.g. to verify the server
+    /// certificate differently.
+    pub async fn connect_tls_with_config<T: AsyncRead + AsyncWrite + Unpin + Send>(
+        stream: T,
+        domain: &str,
+        config: Arc<ClientConfig>,
+    ) -> io::Result<TokioIo<TlsStream<T>>> {
+        handshake(stream, server_name(domain)?, config).await
+    }
+
+    async fn handshake<T: AsyncRead + AsyncWrite + Unpin + Send>(
+        stream: T,
+        server_name: ServerName<'static>,
+        config: Arc<ClientConfig>,
+    ) -> io::Result<TokioIo<TlsStream<T>>> {
+        // no TLS 1.3 early data: the client may not send anything before the server's
+        // greeting, and servers drop clients which talk first as spam bots
+        // https://datatracker.ietf.org/doc/html/rfc5321#section-4.3.1
+        let tls = TlsConnector::from(config)
+            .connect(server_name, stream)
+            .await?;
+        Ok(TokioIo(tls))
+    }
+
     impl<'buffer, T: AsyncRead + AsyncWrite + Unpin + Send> Smtp<'buffer, TokioIo<T>> {
+        /// Starts TLS after a successful `STARTTLS`, see [`connect_tls`] for `domain`.
+        ///
+        /// A failed handshake, or a `domain` which isn't a valid name, is an [`Error::Tls`].
         pub async fn upgrade_to_tls(
             self,
             domain: &str,
         ) -> Result<Smtp<'buffer, TokioIo<TlsStream<T>>>, Error<<TokioIo<T> as ReadWrite>::Error>>
         {
-            let (tcp, buffer) = self.into_inner();
-
-            let root_cert_store =
-                rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
-            let config = rustls::ClientConfig::builder()
-                .with_root_certificates(root_cert_store)
-                .with_no_client_auth(); // i guess this was previously the default?
-            let connector = TlsConnector::from(Arc::new(config));
-            let tls = connector
-                .connect(
-                    rustls::pki_types::ServerName::try_from(domain)
-                        .unwrap()
-                        .to_owned(),
-                    tcp.0,
-                )
+            let config = RootCertificates::new()
+                .client_config(None)
+                .map_err(Error::Tls)?;
+            self.upgrade_to_tls_with_config(domain, config).await
+        }
+
+        /// Like [`upgrade_to_tls`](Self::upgrade_to_tls), but presents `client_cert` to the
+        /// server during the handshake.
+        pub async fn upgrade_to_tls_with_client_cert(
+            self,
+            domain: &str,
+            client_cert: ClientCertificate,
+        ) -> Result<Smtp<'buffer, TokioIo<TlsStream<T>>>, Error<<TokioIo<T> as ReadWrite>::Error>>
+        {
+            let config = RootCertificates::new()
+                .client_config(Some(client_cert))
+                .map_err(Error::Tls)?;
+            self.upgrade_to_tls_with_config(domain, config).await
+        }
+
+        /// Like [`upgrade_to_tls`](Self::upgrade_to_tls), but with a custom rustls `config`,
+        /// see [`connect_tls_with_config`].
+        pub async fn upgrade_to_tls_with_config(
+            self,
+            domain: &str,
+            config: Arc<ClientConfig>,
+        ) -> Result<Smtp<'buffer, TokioIo<TlsStream<T>>>, Error<<TokioIo<T> as ReadWrite>::Error>>
+        {
+            let server_name = server_name(domain).map_err(Error::Tls)?;
+            self.upgrade_to_tls_with(config, server_name).await
+        }
+
+        /// Like [`upgrade_to_tls`](Self::upgrade_to_tls), but checks the server certificate
+        /// with `verifier` instead of the webpki roots, e.g. a [`PinnedCertificate`](super::PinnedCertificate).
+        pub async fn upgrade_to_tls_with_verifier(
+            self,
+            domain: &str,
+            verifier: Arc<dyn ServerCertVerifier>,
+        ) -> Result<Smtp<'buffer, TokioIo<TlsStream<T>>>, Error<<TokioIo<T> as ReadWrite>::Error>>
+        {
+            let config = client_config_with_verifier(verifier, None).map_err(Error::Tls)?;
+            self.upgrade_to_tls_with_config(domain, config).await
+        }
+
+        /// Starts TLS with a custom rustls `config`, e.g. with private roots, a client
+        /// certificate or the `dangerous` verifier for a test server, and
+        /// the `server_name` the certificate is checked against.
+        ///
+        /// # Example
+        ///
+        /// ```no_run
+        /// # async fn example(
+        /// #     smtp: simple_smtp::Smtp<'static, simple_smtp::integrations::tokio::TokioIo<tokio::net::TcpStream>>,
+        /// # ) -> anyhow::Result<()> {
+        /// use rustls::pki_types::ServerName;
+        /// use simple_smtp::integrations::tokio::RootCertificates;
+        ///
+        /// let config = RootCertificates::new()
+        ///     .add_pem(&std::fs::read("corporate-ca.pem")?)?
+        ///     .client_config(None)?;
+        /// let name = ServerName::try_from("relay.corp.example")?;
+        /// let smtp = smtp.upgrade_to_tls_with(config, name).await?;
+        /// # Ok(())
+        /// # }
+        /// ```
+        pub async fn upgrade_to_tls_with(
+            self,
+            config: Arc<ClientConfig>,
+            server_name: ServerName<'static>,
+        ) -> Result<Smtp<'buffer, TokioIo<TlsStream<T>>>, Error<<TokioIo<T> as ReadWrite>::Error>>
+        {
+            let (tcp, upgrade) = self.into_upgrade();
+            let tls = handshake(tcp.0, server_name, config)
                 .await
-                .expect("failed to connect");
-            Ok(Smtp::new_with_buffer(TokioIo(tls), buffer))
+                .map_err(Error::Tls)?;
+            Ok(upgrade.finish(tls))
         }
     }
 }
@@ -0,0 +1,370 @@
+//! Failure injection, to check how an application copes with a misbehaving server, e.g.
+//! whether it retries and alerts as it should, in a staging environment.
+//!
+//! A [`ChaosStream`] sits between the session and the connection, and with the configured
+//! probabilities
+//! - drops the connection after `RCPT TO`, so its reply never arrives
+//! - delays the reply to the end of the message data
+//! - answers `MAIL FROM` with a `451` itself, without sending it to the server
+//!
+//! [`SmtpClientBuilder::with_chaos`](super::SmtpClientBuilder::with_chaos) puts one in front
+//! of the connections it opens. Not meant for production.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! use simple_smtp::integrations::tokio::{SmtpClientBuilder, chaos::ChaosConfig};
+//!
+//! // e.g. read from an environment variable of the staging deployment
+//! let chaos: ChaosConfig = "drop_after_rcpt=0.05,force_451=0.1,delay_data_ack=0.2,delay_ms=30000"
+//!     .parse()?;
+//! let mut smtp = SmtpClientBuilder::new("relay.staging.example.com")
+//!     .with_chaos(chaos)
+//!     .connect()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use core::{
+    fmt,
+    pin::Pin,
+    str::FromStr,
+    task::{Context, Poll, ready},
+    time::Duration,
+};
+use std::{
+    io,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::Sleep,
+};
+
+const FORCED_REPLY: &[u8] = b"451 4.3.0 Failure injected for chaos testing\r\n";
+const END_OF_DATA: &[u8] = b"\r\n.\r\n";
+
+/// How often a [`ChaosStream`] injects each failure, as probabilities from 0 to 1. Nothing is
+/// injected by default.
+///
+/// Parses from comma separated `key=value` pairs, e.g. from a configuration file:
+/// `drop_after_rcpt`, `delay_data_ack` and `force_451` with a probability, `delay_ms` with
+/// the delay of the reply in milliseconds, and `seed`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChaosConfig {
+    drop_after_rcpt: f64,
+    delay_data_ack: f64,
+    data_ack_delay: Duration,
+    force_451: f64,
+    seed: Option<u64>,
+}
+
+impl ChaosConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drops the connection after a `RCPT TO` reached the server, before its reply.
+    pub fn with_drop_after_rcpt(mut self, probability: f64) -> Self {
+        self.drop_after_rcpt = probability;
+        self
+    }
+
+    /// Holds back the reply to the end of the message data for `delay`, e.g. longer than the
+    /// [timeout](crate::smtp::Timeouts) of the session.
+    pub fn with_delayed_data_ack(mut self, probability: f64, delay: Duration) -> Self {
+        self.delay_data_ack = probability;
+        self.data_ack_delay = delay;
+        self
+    }
+
+    /// Answers `MAIL FROM` with `451 4.3.0`, a temporary failure. The command isn't sent, so
+    /// the session stays in step with the server.
+    pub fn with_forced_451(mut self, probability: f64) -> Self {
+        self.force_451 = probability;
+        self
+    }
+
+    /// Makes the injected failures reproducible. The current time is used otherwise.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+}
+
+/// A [`ChaosConfig`] which couldn't be parsed, with the offending pair.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidChaosConfig(pub String);
+
+impl fmt::Display for InvalidChaosConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid chaos configuration: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidChaosConfig {}
+
+impl FromStr for ChaosConfig {
+    type Err = InvalidChaosConfig;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = ChaosConfig::new();
+        for pair in s.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let invalid = || InvalidChaosConfig(pair.to_owned());
+            let (key, value) = pair.split_once('=').ok_or_else(invalid)?;
+            let probability = || match value.trim().parse::<f64>() {
+                Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+                _ => Err(invalid()),
+            };
+            match key.trim() {
+                "drop_after_rcpt" => config.drop_after_rcpt = probability()?,
+                "delay_data_ack" => config.delay_data_ack = probability()?,
+                "force_451" => config.force_451 = probability()?,
+                "delay_ms" => {
+                    let ms = value.trim().parse().map_err(|_| invalid())?;
+                    config.data_ack_delay = Duration::from_millis(ms);
+                }
+                "seed" => config.seed = Some(value.trim().parse().map_err(|_| invalid())?),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Injects failures into the SMTP exchange on `inner`, see the [module](self) docs.
+///
+/// Commands are held back until their line is complete, to decide what to do with them.
+pub struct ChaosStream<S> {
+    inner: S,
+    state: ChaosState,
+}
+
+/// What a [`ChaosStream`] knows about the exchange, kept when its stream is replaced by a
+/// TLS upgrade.
+pub(super) struct ChaosState {
+    config: ChaosConfig,
+    rng: u64,
+    // the command line being written
+    line: Vec<u8>,
+    // complete command lines which weren't written to the stream yet
+    outgoing: Vec<u8>,
+    // DATA was sent, a 354 reply starts the message data
+    data_requested: bool,
+    in_data: bool,
+    // the end of the message data written so far, to find the end marker across writes
+    tail: Vec<u8>,
+    // the rest of a reply answered in place of the server
+    fake_reply: &'static [u8],
+    drop_pending: bool,
+    dropped: bool,
+    delay_pending: bool,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> ChaosStream<S> {
+    pub fn new(inner: S, config: ChaosConfig) -> Self {
+        let seed = config.seed.unwrap_or_else(|| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            now.as_nanos() as u64
+        });
+        let state = ChaosState {
+            config,
+            // xorshift never leaves 0
+            rng: seed.max(1),
+            line: Vec::new(),
+            outgoing: Vec::new(),
+            data_requested: false,
+            in_data: false,
+            tail: Vec::new(),
+            fake_reply: &[],
+            drop_pending: false,
+            dropped: false,
+            delay_pending: false,
+            delay: None,
+        };
+        ChaosStream { inner, state }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    // the TLS upgrade of the tokio client
+    #[cfg(feature = "rustls")]
+    pub(super) fn into_parts(self) -> (S, ChaosState) {
+        (self.inner, self.state)
+    }
+
+    #[cfg(feature = "rustls")]
+    pub(super) fn from_parts(inner: S, state: ChaosState) -> Self {
+        ChaosStream { inner, state }
+    }
+}
+
+impl ChaosState {
+    // xorshift64, plenty for picking failures
+    fn chance(&mut self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        ((self.rng >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+
+    fn command(&mut self) {
+        let line = core::mem::take(&mut self.line);
+        let is = |command: &[u8]| {
+            line.get(..command.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(command))
+        };
+        if is(b"MAIL FROM") && self.chance(self.config.force_451) {
+            #[cfg(feature = "log-04")]
+            log::warn!("chaos: answering MAIL FROM with 451");
+            self.fake_reply = FORCED_REPLY;
+            return;
+        }
+        if is(b"RCPT TO") && self.chance(self.config.drop_after_rcpt) {
+            #[cfg(feature = "log-04")]
+            log::warn!("chaos: dropping the connection after RCPT TO");
+            self.drop_pending = true;
+        }
+        self.data_requested = line.eq_ignore_ascii_case(b"DATA\r\n");
+        self.outgoing.extend_from_slice(&line);
+    }
+
+    fn data(&mut self, data: &[u8]) {
+        let keep = END_OF_DATA.len() - 1;
+        let mut joint = core::mem::take(&mut self.tail);
+        joint.extend_from_slice(&data[..data.len().min(keep)]);
+        let contains = |bytes: &[u8]| bytes.windows(END_OF_DATA.len()).any(|w| w == END_OF_DATA);
+        let ended = contains(&joint) || contains(data);
+        self.tail = match data.len() >= keep {
+            true => data[data.len() - keep..].to_vec(),
+            false => joint[joint.len().saturating_sub(keep)..].to_vec(),
+        };
+        if ended {
+            self.in_data = false;
+            if self.chance(self.config.delay_data_ack) {
+                #[cfg(feature = "log-04")]
+                log::warn!("chaos: delaying the reply to the message data");
+                self.delay_pending = true;
+            }
+        }
+    }
+}
+
+fn dropped() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::ConnectionReset,
+        "connection dropped for chaos testing",
+    )
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> ChaosStream<S> {
+    // writes the command lines held back
+    fn poll_outgoing(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.state.outgoing.is_empty() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.state.outgoing))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.state.outgoing.drain(..n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for ChaosStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let state = &mut this.state;
+        if state.drop_pending {
+            // the server still gets to see the connection close
+            let _ = ready!(Pin::new(&mut this.inner).poll_shutdown(cx));
+            state.drop_pending = false;
+            state.dropped = true;
+        }
+        if state.dropped {
+            return Poll::Ready(Err(dropped()));
+        }
+        if !state.fake_reply.is_empty() {
+            let n = state.fake_reply.len().min(buf.remaining());
+            buf.put_slice(&state.fake_reply[..n]);
+            state.fake_reply = &state.fake_reply[n..];
+            return Poll::Ready(Ok(()));
+        }
+        if state.delay_pending {
+            state.delay_pending = false;
+            state.delay = Some(Box::pin(tokio::time::sleep(state.config.data_ack_delay)));
+        }
+        if let Some(delay) = &mut state.delay {
+            ready!(delay.as_mut().poll(cx));
+            state.delay = None;
+        }
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        let read = &buf.filled()[before..];
+        if state.data_requested && !read.is_empty() {
+            state.data_requested = false;
+            state.in_data = read.starts_with(b"354");
+            // the data starts on a new line
+            state.tail = b"\r\n".to_vec();
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for ChaosStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.state.dropped {
+            return Poll::Ready(Err(dropped()));
+        }
+        ready!(this.poll_outgoing(cx))?;
+        if this.state.in_data {
+            let n = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+            this.state.data(&buf[..n]);
+            return Poll::Ready(Ok(n));
+        }
+        let Some(end) = buf.iter().position(|&b| b == b'\n') else {
+            this.state.line.extend_from_slice(buf);
+            return Poll::Ready(Ok(buf.len()));
+        };
+        this.state.line.extend_from_slice(&buf[..=end]);
+        this.state.command();
+        Poll::Ready(Ok(end + 1))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.state.dropped {
+            return Poll::Ready(Err(dropped()));
+        }
+        ready!(this.poll_outgoing(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.state.dropped {
+            ready!(this.poll_outgoing(cx))?;
+        }
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
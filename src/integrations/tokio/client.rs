@@ -0,0 +1,617 @@
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use std::io::{self, IoSlice};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+};
+#[cfg(feature = "rustls")]
+use tokio_rustls::client::TlsStream;
+
+#[cfg(feature = "chaos")]
+use super::chaos::{ChaosConfig, ChaosStream};
+#[cfg(feature = "rustls")]
+use super::{ClientCertificate, RootCertificates};
+use super::{TokioIo, TokioTimer};
+use crate::{
+    Error, ProtocolError, Smtp,
+    address::{AddressLiteral, parse_ip_host},
+    smtp::{
+        ReplyCode, Timeouts,
+        auth::{AuthMode, CredentialProvider, Credentials},
+        negotiation::DesiredFeatures,
+    },
+};
+
+/// How the connection is secured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsMode {
+    /// TLS from the first byte, usually on port 465.
+    /// <https://datatracker.ietf.org/doc/html/rfc8314#section-3>
+    #[cfg(feature = "rustls")]
+    Implicit,
+    /// Upgrade a plaintext connection with `STARTTLS`, usually on port 587.
+    /// Fails if the server doesn't offer `STARTTLS`.
+    /// <https://datatracker.ietf.org/doc/html/rfc3207>
+    #[cfg(feature = "rustls")]
+    StartTls,
+    /// Plaintext, usually on port 25, unless the server requires `STARTTLS` to authenticate,
+    /// see [`SmtpClientBuilder::allow_starttls_on_demand`].
+    None,
+}
+
+impl TlsMode {
+    /// The port conventionally used with this mode.
+    pub fn default_port(&self) -> u16 {
+        match self {
+            #[cfg(feature = "rustls")]
+            TlsMode::Implicit => 465,
+            #[cfg(feature = "rustls")]
+            TlsMode::StartTls => 587,
+            TlsMode::None => 25,
+        }
+    }
+}
+
+/// The connection produced by [`SmtpClientBuilder`], either plaintext or TLS.
+pub enum ClientStream {
+    Plain(TcpStream),
+    #[cfg(feature = "rustls")]
+    Tls(Box<TlsStream<TcpStream>>),
+    /// Either of the others, with failures injected, see [`SmtpClientBuilder::with_chaos`].
+    #[cfg(feature = "chaos")]
+    Chaos(Box<ChaosStream<ClientStream>>),
+}
+
+impl ClientStream {
+    pub fn is_tls(&self) -> bool {
+        match self {
+            ClientStream::Plain(_) => false,
+            #[cfg(feature = "rustls")]
+            ClientStream::Tls(_) => true,
+            #[cfg(feature = "chaos")]
+            ClientStream::Chaos(s) => s.get_ref().is_tls(),
+        }
+    }
+}
+
+impl AsyncRead for ClientStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ClientStream::Plain(s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(feature = "rustls")]
+            ClientStream::Tls(s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(feature = "chaos")]
+            ClientStream::Chaos(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ClientStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            ClientStream::Plain(s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(feature = "rustls")]
+            ClientStream::Tls(s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(feature = "chaos")]
+            ClientStream::Chaos(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            ClientStream::Plain(s) => Pin::new(s).poll_write_vectored(cx, bufs),
+            #[cfg(feature = "rustls")]
+            ClientStream::Tls(s) => Pin::new(s).poll_write_vectored(cx, bufs),
+            #[cfg(feature = "chaos")]
+            ClientStream::Chaos(s) => Pin::new(s).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            ClientStream::Plain(s) => s.is_write_vectored(),
+            #[cfg(feature = "rustls")]
+            ClientStream::Tls(s) => s.is_write_vectored(),
+            #[cfg(feature = "chaos")]
+            ClientStream::Chaos(s) => s.is_write_vectored(),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ClientStream::Plain(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(feature = "rustls")]
+            ClientStream::Tls(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(feature = "chaos")]
+            ClientStream::Chaos(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            ClientStream::Plain(s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(feature = "rustls")]
+            ClientStream::Tls(s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(feature = "chaos")]
+            ClientStream::Chaos(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+}
+
+pub type ClientSession = Smtp<'static, TokioIo<ClientStream>>;
+
+/// Opens a ready to use session in one call.
+///
+/// Takes care of the greeting, `EHLO`, `STARTTLS` followed by a second `EHLO`, and
+/// authentication, in the order the RFCs require.
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn example() -> Result<(), simple_smtp::Error<std::io::Error>> {
+/// use simple_smtp::{integrations::tokio::{SmtpClientBuilder, TlsMode}, smtp::auth::AuthMode};
+///
+/// let mut smtp = SmtpClientBuilder::new("smtp.example.com")
+///     .with_tls(TlsMode::StartTls)
+///     .with_auth(AuthMode::Plain { username: "me", password: "secret" })
+///     .with_ehlo_domain("client.example.com")
+///     .connect()
+///     .await?;
+/// smtp.send_mail("me@example.com", ["you@example.com"].iter(), b"hi").await?;
+/// smtp.quit().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct SmtpClientBuilder<'a> {
+    host: &'a str,
+    port: Option<u16>,
+    tls: TlsMode,
+    auth: AuthMode<'a>,
+    ehlo_domain: Option<&'a str>,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    reply_timeouts: Timeouts,
+    dry_run: bool,
+    ehlo_after_auth: bool,
+    helo_fallback: bool,
+    plaintext_auth: bool,
+    desired: Option<DesiredFeatures<'static>>,
+    #[cfg(feature = "rustls")]
+    client_cert: Option<ClientCertificate>,
+    #[cfg(feature = "rustls")]
+    tls_server_name: Option<&'a str>,
+    #[cfg(feature = "rustls")]
+    roots: RootCertificates,
+    #[cfg(feature = "rustls")]
+    tls_config: Option<std::sync::Arc<rustls::ClientConfig>>,
+    #[cfg(feature = "rustls")]
+    starttls_on_demand: bool,
+    #[cfg(feature = "chaos")]
+    chaos: Option<ChaosConfig>,
+}
+
+impl<'a> SmtpClientBuilder<'a> {
+    /// Defaults to `STARTTLS` when TLS support is enabled, without authentication.
+    ///
+    /// `host` is a domain name or an IP address, see [`parse_ip_host`] for the accepted forms.
+    pub fn new(host: &'a str) -> Self {
+        SmtpClientBuilder {
+            host,
+            port: None,
+            #[cfg(feature = "rustls")]
+            tls: TlsMode::StartTls,
+            #[cfg(not(feature = "rustls"))]
+            tls: TlsMode::None,
+            auth: AuthMode::None,
+            ehlo_domain: None,
+            connect_timeout: None,
+            timeout: None,
+            reply_timeouts: Timeouts::default(),
+            dry_run: false,
+            ehlo_after_auth: false,
+            helo_fallback: false,
+            plaintext_auth: false,
+            desired: None,
+            #[cfg(feature = "rustls")]
+            client_cert: None,
+            #[cfg(feature = "rustls")]
+            tls_server_name: None,
+            #[cfg(feature = "rustls")]
+            roots: RootCertificates::new(),
+            #[cfg(feature = "rustls")]
+            tls_config: None,
+            #[cfg(feature = "rustls")]
+            starttls_on_demand: true,
+            #[cfg(feature = "chaos")]
+            chaos: None,
+        }
+    }
+
+    /// Defaults to the [conventional port](TlsMode::default_port) of the TLS mode.
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    pub fn with_tls(mut self, tls: TlsMode) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    pub fn with_auth(mut self, auth: AuthMode<'a>) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// The domain the client identifies itself with in `EHLO`.
+    ///
+    /// Defaults to the address literal of the local end of the connection, as the client
+    /// has no way to know its own domain name.
+    /// <https://datatracker.ietf.org/doc/html/rfc5321#section-4.1.4>
+    pub fn with_ehlo_domain(mut self, domain: &'a str) -> Self {
+        self.ehlo_domain = Some(domain);
+        self
+    }
+
+    /// Limits the time spent on the TCP connection and the TLS handshake.
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Limits the time spent from the greeting until the session is authenticated.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// The time to wait for each reply, for the whole session. Defaults to the minimum
+    /// timeouts of RFC 5321, see [`Smtp::set_timeouts`].
+    pub fn with_reply_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.reply_timeouts = timeouts;
+        self
+    }
+
+    /// Never send messages, see [`Smtp::set_dry_run`].
+    pub fn with_dry_run(mut self) -> Self {
+        self.dry_run = true;
+        self
+    }
+
+    /// Greet the server again after AUTH, for servers which advertise more to authenticated
+    /// clients, e.g. a larger SIZE limit. Both sets are kept, see
+    /// [`Smtp::pre_auth_capabilities`].
+    pub fn with_ehlo_after_auth(mut self) -> Self {
+        self.ehlo_after_auth = true;
+        self
+    }
+
+    /// Greet with `HELO` if the server doesn't understand `EHLO`, see [`Smtp::helo`].
+    ///
+    /// Off by default, as a legacy session has no extensions: no STARTTLS, so the session
+    /// stays in plaintext, and no AUTH. Only enable this for old equipment on a trusted
+    /// network, with [`TlsMode::None`].
+    pub fn allow_helo_fallback(mut self, allow: bool) -> Self {
+        self.helo_fallback = allow;
+        self
+    }
+
+    /// Send credentials even if the connection isn't encrypted, see
+    /// [`Smtp::set_allow_plaintext_auth`].
+    pub fn allow_plaintext_auth(mut self, allow: bool) -> Self {
+        self.plaintext_auth = allow;
+        self
+    }
+
+    /// Checks the server's extensions against `desired` after every EHLO, see
+    /// [`Smtp::set_desired_features`].
+    pub fn with_desired_features(mut self, desired: DesiredFeatures<'static>) -> Self {
+        self.desired = Some(desired);
+        self
+    }
+
+    /// Presents `cert` during the TLS handshake, see [`AuthMode::ClientCertOnly`].
+    #[cfg(feature = "rustls")]
+    pub fn with_client_certificate(mut self, cert: ClientCertificate) -> Self {
+        self.client_cert = Some(cert);
+        self
+    }
+
+    /// The name the server's certificate is checked against, for connecting to an IP address
+    /// of a server with a certificate for its domain name. Defaults to the host.
+    #[cfg(feature = "rustls")]
+    pub fn with_tls_server_name(mut self, name: &'a str) -> Self {
+        self.tls_server_name = Some(name);
+        self
+    }
+
+    /// Trusts `roots` in addition to the webpki roots, for servers with a certificate issued
+    /// by a private CA.
+    #[cfg(feature = "rustls")]
+    pub fn with_root_certificates(mut self, roots: RootCertificates) -> Self {
+        self.roots = roots;
+        self
+    }
+
+    /// Secures the connection with `config` instead of one trusting the
+    /// [root certificates](Self::with_root_certificates), e.g. one checking the certificate
+    /// with a custom verifier, see [`client_config_with_verifier`](super::client_config_with_verifier).
+    /// A [client certificate](Self::with_client_certificate) has to be part of `config` then.
+    #[cfg(feature = "rustls")]
+    pub fn with_tls_config(mut self, config: std::sync::Arc<rustls::ClientConfig>) -> Self {
+        self.tls_config = Some(config);
+        self
+    }
+
+    /// Upgrade a [`TlsMode::None`] session with `STARTTLS` and authenticate again if the
+    /// server refuses `AUTH` until the connection is secured, see
+    /// [`Error::is_starttls_required`]. On by default.
+    ///
+    /// Only applies while connecting: without authentication, the refusal of a later
+    /// command such as `MAIL FROM` is returned as an error. Use [`TlsMode::StartTls`] for
+    /// servers which require it.
+    #[cfg(feature = "rustls")]
+    pub fn allow_starttls_on_demand(mut self, allow: bool) -> Self {
+        self.starttls_on_demand = allow;
+        self
+    }
+
+    /// Injects failures into the session with the probabilities of `config`, to test how
+    /// the application handles them, see the [`chaos`](super::chaos) module.
+    #[cfg(feature = "chaos")]
+    pub fn with_chaos(mut self, config: ChaosConfig) -> Self {
+        self.chaos = Some(config);
+        self
+    }
+
+    pub async fn connect(self) -> Result<ClientSession, Error<io::Error>> {
+        self.open(None::<&NoCredentials>).await
+    }
+
+    /// Connects like [`connect`](Self::connect), but authenticates with credentials fetched
+    /// from `provider` when the session is ready for them, instead of the
+    /// [`with_auth`](Self::with_auth) mode. See [`Smtp::authenticate_with`].
+    pub async fn connect_with_credentials<P: CredentialProvider>(
+        self,
+        provider: &P,
+    ) -> Result<ClientSession, Error<io::Error>>
+    where
+        P::Error: Into<io::Error>,
+    {
+        self.open(Some(provider)).await
+    }
+
+    async fn open<P: CredentialProvider>(
+        self,
+        provider: Option<&P>,
+    ) -> Result<ClientSession, Error<io::Error>>
+    where
+        P::Error: Into<io::Error>,
+    {
+        let SmtpClientBuilder {
+            host,
+            port,
+            tls,
+            auth,
+            ehlo_domain,
+            connect_timeout,
+            timeout,
+            reply_timeouts,
+            dry_run,
+            ehlo_after_auth,
+            helo_fallback,
+            plaintext_auth,
+            desired,
+            #[cfg(feature = "rustls")]
+            client_cert,
+            #[cfg(feature = "rustls")]
+            tls_server_name,
+            #[cfg(feature = "rustls")]
+            roots,
+            #[cfg(feature = "rustls")]
+            tls_config,
+            #[cfg(feature = "rustls")]
+            starttls_on_demand,
+            #[cfg(feature = "chaos")]
+            chaos,
+        } = self;
+        // configuration errors, reported before connecting
+        #[cfg(feature = "rustls")]
+        let has_client_cert = client_cert.is_some();
+        #[cfg(not(feature = "rustls"))]
+        let has_client_cert = false;
+        if has_client_cert && tls == TlsMode::None {
+            return Err(ProtocolError::ClientCertificateWithoutTls.into());
+        }
+        if matches!(auth, AuthMode::ClientCertOnly) && !has_client_cert {
+            return Err(ProtocolError::MissingClientCertificate.into());
+        }
+        #[cfg(feature = "rustls")]
+        let server_name = tls_server_name.unwrap_or(host);
+        #[cfg(feature = "rustls")]
+        let tls_config = match (tls, tls_config) {
+            (TlsMode::None, _) if !starttls_on_demand => None,
+            (_, Some(config)) => Some(config),
+            _ => Some(roots.client_config(client_cert).map_err(Error::Tls)?),
+        };
+        let port = port.unwrap_or(tls.default_port());
+        let (stream, local_ip) = with_timeout(connect_timeout, async {
+            let tcp = match parse_ip_host(host) {
+                Some(ip) => TcpStream::connect((ip, port)).await,
+                None => TcpStream::connect((host, port)).await,
+            }
+            .map_err(Error::IoError)?;
+            let local_ip = tcp.local_addr().map_err(Error::IoError)?.ip();
+            let stream = match tls {
+                #[cfg(feature = "rustls")]
+                TlsMode::Implicit => {
+                    let config = tls_config.clone().expect("configured for TLS");
+                    let tls = super::connect_tls_with_config(tcp, server_name, config)
+                        .await
+                        .map_err(Error::Tls)?;
+                    ClientStream::Tls(Box::new(tls.0))
+                }
+                _ => ClientStream::Plain(tcp),
+            };
+            Ok((stream, local_ip))
+        })
+        .await?;
+        #[cfg(feature = "chaos")]
+        let stream = match chaos {
+            Some(config) => ClientStream::Chaos(Box::new(ChaosStream::new(stream, config))),
+            None => stream,
+        };
+        let local_literal = AddressLiteral(local_ip).to_string();
+        let ehlo_domain = ehlo_domain.unwrap_or(&local_literal);
+        let secure = stream.is_tls();
+        let mut smtp = Smtp::new(TokioIo(stream));
+        smtp.set_secure(secure);
+        smtp.set_allow_plaintext_auth(plaintext_auth);
+        smtp.set_dry_run(dry_run);
+        smtp.set_owned_timer(TokioTimer::new());
+        smtp.set_timeouts(reply_timeouts);
+        if let Some(desired) = desired {
+            smtp.set_desired_features(desired);
+        }
+
+        with_timeout(timeout, async move {
+            smtp.ready().await?;
+            match smtp.ehlo(ehlo_domain).await {
+                Ok(_) => {}
+                // "command not recognized", the server predates ESMTP
+                // https://datatracker.ietf.org/doc/html/rfc5321#section-3.2
+                Err(Error::ServerRejected { code, .. })
+                    if helo_fallback
+                        && (code == ReplyCode::SYNTAX_ERROR
+                            || code == ReplyCode::COMMAND_NOT_IMPLEMENTED) =>
+                {
+                    #[cfg(feature = "log-04")]
+                    log::warn!("EHLO rejected with {code}, falling back to HELO");
+                    smtp.helo(ehlo_domain).await?;
+                }
+                Err(e) => return Err(e),
+            }
+            #[cfg(feature = "rustls")]
+            if tls == TlsMode::StartTls {
+                let config = tls_config.clone().expect("configured for TLS");
+                smtp = starttls(smtp, server_name, config, ehlo_domain, connect_timeout).await?;
+            }
+            match authenticate(&mut smtp, &auth, provider).await {
+                Ok(()) => {}
+                // e.g. `530 5.7.0 Must issue a STARTTLS command first`
+                #[cfg(feature = "rustls")]
+                Err(e) if e.is_starttls_required() && !smtp.is_secure() => {
+                    let Some(config) = tls_config else {
+                        return Err(e);
+                    };
+                    #[cfg(feature = "log-04")]
+                    log::warn!("{e}, upgrading the connection");
+                    smtp =
+                        starttls(smtp, server_name, config, ehlo_domain, connect_timeout).await?;
+                    authenticate(&mut smtp, &auth, provider).await?;
+                }
+                Err(e) => return Err(e),
+            }
+            if ehlo_after_auth && smtp.pre_auth_capabilities().is_some() {
+                smtp.ehlo(ehlo_domain).await?;
+            }
+            Ok(smtp)
+        })
+        .await
+    }
+}
+
+// with the credentials of the provider if there is one, the configured mode otherwise
+async fn authenticate<P: CredentialProvider>(
+    smtp: &mut ClientSession,
+    auth: &AuthMode<'_>,
+    provider: Option<&P>,
+) -> Result<(), Error<io::Error>>
+where
+    P::Error: Into<io::Error>,
+{
+    match provider {
+        Some(provider) => smtp.authenticate_with(provider).await,
+        None => smtp.authenticate(auth).await,
+    }
+}
+
+// the provider of a plain `connect`, never asked for credentials
+struct NoCredentials;
+
+impl CredentialProvider for NoCredentials {
+    type Error = io::Error;
+
+    async fn credentials(&self) -> Result<Credentials, io::Error> {
+        unreachable!("only passed as None")
+    }
+}
+
+// upgrades a plaintext session and greets the server again
+#[cfg(feature = "rustls")]
+async fn starttls(
+    mut smtp: ClientSession,
+    server_name: &str,
+    config: std::sync::Arc<rustls::ClientConfig>,
+    ehlo_domain: &str,
+    connect_timeout: Option<Duration>,
+) -> Result<ClientSession, Error<io::Error>> {
+    smtp.starttls().await?;
+    let (stream, upgrade) = smtp.into_upgrade();
+    let upgrade_tcp = |stream: ClientStream| async move {
+        let ClientStream::Plain(tcp) = stream else {
+            unreachable!("STARTTLS is only used on plaintext connections");
+        };
+        let tls = with_timeout(connect_timeout, async {
+            super::connect_tls_with_config(tcp, server_name, config)
+                .await
+                .map_err(Error::Tls)
+        })
+        .await?;
+        Ok::<_, Error<io::Error>>(ClientStream::Tls(Box::new(tls.0)))
+    };
+    let stream = match stream.0 {
+        // failures are still injected after the upgrade
+        #[cfg(feature = "chaos")]
+        ClientStream::Chaos(chaos) => {
+            let (inner, state) = chaos.into_parts();
+            let tls = upgrade_tcp(inner).await?;
+            ClientStream::Chaos(Box::new(ChaosStream::from_parts(tls, state)))
+        }
+        stream => upgrade_tcp(stream).await?,
+    };
+    let mut smtp = upgrade.finish(TokioIo(stream));
+    // the capabilities may differ once the connection is secured
+    // https://datatracker.ietf.org/doc/html/rfc3207#section-4.2
+    smtp.ehlo(ehlo_domain).await?;
+    Ok(smtp)
+}
+
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    fut: impl Future<Output = Result<T, Error<io::Error>>>,
+) -> Result<T, Error<io::Error>> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, fut)
+            .await
+            .map_err(|_| Error::IoError(io::ErrorKind::TimedOut.into()))?,
+        None => fut.await,
+    }
+}
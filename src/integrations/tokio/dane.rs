@@ -0,0 +1,343 @@
+//! DANE: checking the certificate of an MX host against its TLSA records, for delivery from
+//! server to server.
+//! <https://datatracker.ietf.org/doc/html/rfc7672>
+//!
+//! Only the usages SMTP relies on are supported: a trust anchor (`DANE-TA`) or the
+//! server's own certificate (`DANE-EE`). The PKIX usages are unusable for SMTP, as there is
+//! no agreement on which CAs to trust.
+//! <https://datatracker.ietf.org/doc/html/rfc7672#section-3.1.3>
+//!
+//! # Example
+//!
+//! ```no_run
+//! # async fn example(
+//! #     smtp: simple_smtp::Smtp<'static, simple_smtp::integrations::tokio::TokioIo<tokio::net::TcpStream>>,
+//! # ) -> anyhow::Result<()> {
+//! use simple_smtp::integrations::tokio::dane::{DanePolicy, TlsaRecord, TlsaResolver};
+//!
+//! struct Resolver;
+//!
+//! impl TlsaResolver for Resolver {
+//!     async fn resolve(&self, name: &str) -> std::io::Result<Vec<TlsaRecord>> {
+//!         // query a DNSSEC validating resolver for the TLSA records of `name`
+//!         # let _ = name;
+//!         Ok(Vec::new())
+//!     }
+//! }
+//!
+//! let smtp = smtp
+//!     .upgrade_to_tls_dane("mx.example.com", 25, DanePolicy::Mandatory, &Resolver)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{io, sync::Arc};
+
+use rustls::{
+    CertificateError, DigitallySignedStruct, RootCertStore, SignatureScheme,
+    client::{
+        WebPkiServerVerifier,
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    },
+    crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+};
+use sha2::{Digest, Sha256, Sha512};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::client::TlsStream;
+
+use super::{TokioIo, client_config_with_verifier, verifier::default_provider};
+use crate::{Error, ReadWrite, Smtp};
+
+/// A TLSA record.
+/// <https://datatracker.ietf.org/doc/html/rfc6698#section-2.1>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsaRecord {
+    pub usage: u8,
+    pub selector: u8,
+    pub matching_type: u8,
+    pub data: Vec<u8>,
+}
+
+impl TlsaRecord {
+    /// `DANE-TA`: the record matches a trust anchor in the chain the server presents.
+    pub const USAGE_DANE_TA: u8 = 2;
+    /// `DANE-EE`: the record matches the server's own certificate.
+    pub const USAGE_DANE_EE: u8 = 3;
+    /// The record matches the whole certificate.
+    pub const SELECTOR_CERT: u8 = 0;
+    /// The record matches the `SubjectPublicKeyInfo` of the certificate.
+    pub const SELECTOR_SPKI: u8 = 1;
+    /// The data is the selected content itself.
+    pub const MATCHING_FULL: u8 = 0;
+    pub const MATCHING_SHA256: u8 = 1;
+    pub const MATCHING_SHA512: u8 = 2;
+
+    pub fn new(usage: u8, selector: u8, matching_type: u8, data: impl Into<Vec<u8>>) -> Self {
+        TlsaRecord {
+            usage,
+            selector,
+            matching_type,
+            data: data.into(),
+        }
+    }
+
+    /// Returns true if the record can be used for SMTP, others have to be ignored.
+    /// <https://datatracker.ietf.org/doc/html/rfc7672#section-2.2>
+    pub fn is_usable(&self) -> bool {
+        matches!(self.usage, Self::USAGE_DANE_TA | Self::USAGE_DANE_EE)
+            && matches!(self.selector, Self::SELECTOR_CERT | Self::SELECTOR_SPKI)
+            && matches!(
+                self.matching_type,
+                Self::MATCHING_FULL | Self::MATCHING_SHA256 | Self::MATCHING_SHA512
+            )
+    }
+
+    fn matches(&self, cert: &[u8]) -> bool {
+        let selected = match self.selector {
+            Self::SELECTOR_CERT => cert,
+            Self::SELECTOR_SPKI => match subject_public_key_info(cert) {
+                Some(spki) => spki,
+                None => return false,
+            },
+            _ => return false,
+        };
+        match self.matching_type {
+            Self::MATCHING_FULL => self.data == selected,
+            Self::MATCHING_SHA256 => self.data[..] == Sha256::digest(selected)[..],
+            Self::MATCHING_SHA512 => self.data[..] == Sha512::digest(selected)[..],
+            _ => false,
+        }
+    }
+}
+
+/// The name the TLSA records of an SMTP server are published at, e.g.
+/// `_25._tcp.mx.example.com`.
+/// <https://datatracker.ietf.org/doc/html/rfc7672#section-2.2.3>
+pub fn tlsa_name(host: &str, port: u16) -> String {
+    format!("_{port}._tcp.{}", host.trim_end_matches('.'))
+}
+
+/// Looks up TLSA records, e.g. with a DNS library.
+pub trait TlsaResolver {
+    /// The TLSA records of `name`, see [`tlsa_name`].
+    ///
+    /// Only records of an answer DNSSEC validated as secure may be returned, an insecure
+    /// answer counts as no records. A failed lookup, including a bogus answer, has to be an
+    /// error, as delivery must be deferred then.
+    /// <https://datatracker.ietf.org/doc/html/rfc7672#section-2.1.1>
+    fn resolve(&self, name: &str) -> impl Future<Output = io::Result<Vec<TlsaRecord>>>;
+}
+
+/// What to do if the server has no usable TLSA records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DanePolicy {
+    /// Check the certificate against the webpki roots instead.
+    Opportunistic,
+    /// Fail the upgrade.
+    Mandatory,
+}
+
+/// Checks the server certificate against TLSA records.
+///
+/// With a `DANE-EE` record only the server's certificate has to match, its names and
+/// validity period aren't checked. With a `DANE-TA` record the chain has to lead to a
+/// certificate in it which matches, and is checked like one issued by a CA.
+/// <https://datatracker.ietf.org/doc/html/rfc7672#section-3.1>
+#[derive(Debug)]
+pub struct DaneVerifier {
+    records: Vec<TlsaRecord>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl DaneVerifier {
+    /// Unusable records are ignored.
+    pub fn new(records: impl IntoIterator<Item = TlsaRecord>) -> Self {
+        DaneVerifier {
+            records: records.into_iter().filter(TlsaRecord::is_usable).collect(),
+            provider: default_provider(),
+        }
+    }
+
+    /// Returns true if there is a usable record to check against.
+    pub fn has_records(&self) -> bool {
+        !self.records.is_empty()
+    }
+
+    fn records(&self, usage: u8) -> impl Iterator<Item = &TlsaRecord> {
+        self.records
+            .iter()
+            .filter(move |record| record.usage == usage)
+    }
+
+    // the chain has to be valid up to the matching trust anchor
+    fn verify_with_anchor(
+        &self,
+        anchor: &CertificateDer<'_>,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let mut roots = RootCertStore::empty();
+        roots.add(anchor.clone().into_owned())?;
+        let verifier =
+            WebPkiServerVerifier::builder_with_provider(Arc::new(roots), self.provider.clone())
+                .build()
+                .map_err(|e| rustls::Error::General(e.to_string()))?;
+        verifier.verify_server_cert(end_entity, intermediates, server_name, &[], now)
+    }
+}
+
+impl ServerCertVerifier for DaneVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if self
+            .records(TlsaRecord::USAGE_DANE_EE)
+            .any(|record| record.matches(end_entity))
+        {
+            return Ok(ServerCertVerified::assertion());
+        }
+        // the server has to include the trust anchor in its chain
+        // https://datatracker.ietf.org/doc/html/rfc7672#section-3.1.2
+        let mut last_error = None;
+        for anchor in intermediates {
+            if !self
+                .records(TlsaRecord::USAGE_DANE_TA)
+                .any(|record| record.matches(anchor))
+            {
+                continue;
+            }
+            match self.verify_with_anchor(anchor, end_entity, intermediates, server_name, now) {
+                Ok(verified) => return Ok(verified),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or(rustls::Error::InvalidCertificate(
+            CertificateError::ApplicationVerificationFailure,
+        )))
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+impl<'buffer, T: AsyncRead + AsyncWrite + Unpin + Send> Smtp<'buffer, TokioIo<T>> {
+    /// Starts TLS after a successful `STARTTLS` with the MX host `mx_host`, checking its
+    /// certificate against the TLSA records `resolver` finds for it.
+    ///
+    /// Without usable records, the certificate is checked against the webpki roots with
+    /// [`DanePolicy::Opportunistic`], and the upgrade fails with [`DanePolicy::Mandatory`].
+    /// A failed lookup fails the upgrade either way. These failures, like a failed
+    /// handshake, are an [`Error::Tls`].
+    pub async fn upgrade_to_tls_dane(
+        self,
+        mx_host: &str,
+        port: u16,
+        policy: DanePolicy,
+        resolver: &impl TlsaResolver,
+    ) -> Result<Smtp<'buffer, TokioIo<TlsStream<T>>>, Error<<TokioIo<T> as ReadWrite>::Error>> {
+        let records = resolver
+            .resolve(&tlsa_name(mx_host, port))
+            .await
+            .map_err(Error::Tls)?;
+        let verifier = DaneVerifier::new(records);
+        if verifier.has_records() {
+            let config =
+                client_config_with_verifier(Arc::new(verifier), None).map_err(Error::Tls)?;
+            return self.upgrade_to_tls_with_config(mx_host, config).await;
+        }
+        match policy {
+            DanePolicy::Opportunistic => self.upgrade_to_tls(mx_host).await,
+            DanePolicy::Mandatory => Err(Error::Tls(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("no usable TLSA records for {mx_host}"),
+            ))),
+        }
+    }
+}
+
+// the `SubjectPublicKeyInfo` of a DER encoded certificate, including its tag and length
+// https://datatracker.ietf.org/doc/html/rfc5280#section-4.1
+fn subject_public_key_info(cert: &[u8]) -> Option<&[u8]> {
+    let (certificate, _) = der_element(cert)?;
+    let (tbs_certificate, _) = der_element(contents(certificate)?)?;
+    let mut fields = contents(tbs_certificate)?;
+    // the explicitly tagged version is optional
+    if fields.first() == Some(&0xa0) {
+        fields = der_element(fields)?.1;
+    }
+    // serialNumber, signature, issuer, validity and subject
+    for _ in 0..5 {
+        fields = der_element(fields)?.1;
+    }
+    der_element(fields).map(|(spki, _)| spki)
+}
+
+// splits the first element off `der`
+fn der_element(der: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (header_len, len) = der_header(der)?;
+    let end = header_len.checked_add(len)?;
+    (end <= der.len()).then(|| der.split_at(end))
+}
+
+// the contents of a single element
+fn contents(element: &[u8]) -> Option<&[u8]> {
+    let (header_len, _) = der_header(element)?;
+    element.get(header_len..)
+}
+
+// the length of the tag and length bytes, and the length of the contents
+fn der_header(der: &[u8]) -> Option<(usize, usize)> {
+    let &first = der.get(1)?;
+    if first < 0x80 {
+        return Some((2, usize::from(first)));
+    }
+    let len_bytes = usize::from(first & 0x7f);
+    if len_bytes == 0 || len_bytes > 4 {
+        return None;
+    }
+    let len = der
+        .get(2..2 + len_bytes)?
+        .iter()
+        .fold(0, |len, byte| len << 8 | usize::from(*byte));
+    Some((2 + len_bytes, len))
+}
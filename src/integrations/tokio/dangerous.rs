@@ -0,0 +1,84 @@
+//! TLS without certificate verification, for lab relays with self-signed certificates.
+//!
+//! **Never use this in production.** Anyone on the path can impersonate the server and read
+//! everything sent over the connection, including credentials.
+
+use std::sync::Arc;
+
+use rustls::{
+    ClientConfig, DigitallySignedStruct, SignatureScheme,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+};
+
+/// A rustls config which accepts any server certificate, to pass to
+/// [`connect_tls_with_config`](super::connect_tls_with_config) or
+/// [`Smtp::upgrade_to_tls_with_config`](crate::Smtp::upgrade_to_tls_with_config).
+///
+/// The handshake signatures are still checked, so the server has to own the key of the
+/// certificate it presents, but anyone can present a certificate for any name.
+pub fn insecure_client_config() -> Arc<ClientConfig> {
+    #[cfg(feature = "log-04")]
+    log::warn!("TLS certificate verification is DISABLED, the server can't be trusted");
+    let provider = super::verifier::default_provider();
+    let config = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .expect("the default provider supports the default versions")
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(NoVerification(provider)))
+        .with_no_client_auth();
+    Arc::new(config)
+}
+
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        #[cfg(feature = "log-04")]
+        log::warn!("NOT verifying the TLS certificate of {server_name:?}");
+        #[cfg(not(feature = "log-04"))]
+        let _ = server_name;
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
@@ -0,0 +1,498 @@
+//! Delivering mail straight to the MX hosts of the recipients' domains, without a relay.
+//!
+//! Recipients are grouped by domain, and each domain's MX hosts are tried in order of
+//! preference on port 25 until one accepts the connection. The connection has to be
+//! upgraded with STARTTLS, with the certificate checked against the MX host's name.
+//!
+//! Domains can ask for more than that:
+//! - an [MTA-STS](super::mta_sts) policy lists the MX hosts which may receive their mail,
+//!   see [`with_mta_sts`](DirectDelivery::with_mta_sts)
+//! - [DANE](super::dane) TLSA records name the certificates their MX hosts present, see
+//!   [`with_dane`](DirectDelivery::with_dane)
+//!
+//! Receiving servers are strict about mail from unknown hosts, so using this properly takes
+//! some set-up:
+//! - outbound port 25 has to be open, which many hosting providers block
+//! - an SPF record of the sending domain has to list the sending IP address
+//! - messages should be DKIM signed, see [`Signer`](crate::message::Signer)
+//! - ideally, the sending domain has a DMARC record and the sending IP address a PTR record
+//!
+//! Providers also block senders opening many connections at once, so a [`DirectDelivery`]
+//! opens at most two connections to one MX host at a time, across all its deliveries. Share
+//! it between the deliveries of a bulk send, and see
+//! [`with_max_connections_per_host`](DirectDelivery::with_max_connections_per_host) and
+//! [`with_message_delay`](DirectDelivery::with_message_delay) to tune this.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # async fn example(message: &[u8]) -> std::io::Result<()> {
+//! use simple_smtp::{integrations::tokio::delivery::DirectDelivery, resolver::HickoryResolver};
+//!
+//! let delivery = DirectDelivery::new(HickoryResolver::from_system_conf()?, "mail.example.org");
+//! let report = delivery
+//!     .deliver("me@example.org", &["you@example.com", "them@example.net"], message)
+//!     .await;
+//! for (recipient, outcome) in &report.recipients {
+//!     println!("{recipient}: {outcome:?}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use core::time::Duration;
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::Instant,
+};
+
+use super::{
+    RootCertificates, SmtpClientBuilder, TlsMode, client_config_with_verifier,
+    dane::{DaneVerifier, TlsaRecord, TlsaResolver, tlsa_name},
+    mta_sts::{Mode, PolicyCache},
+};
+use crate::{
+    Error,
+    address::group_by_domain,
+    envelope::{Envelope, Recipient},
+    resolver::{Resolver, lookup_mx},
+    smtp::{
+        ReplyCode,
+        retry::{RetryAction, RetryPolicy},
+    },
+};
+
+/// What happened to the message for one recipient.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecipientOutcome {
+    /// The recipient's mail server accepted the message.
+    Delivered,
+    /// Delivery failed, but may succeed later. The code is `None` for failures without a
+    /// reply from the server, e.g. a failed DNS lookup or connection, or a server without
+    /// STARTTLS.
+    TempFail(Option<ReplyCode>),
+    /// The recipient's mail server rejected the message, and the
+    /// [retry policy](DirectDelivery::with_retry_policy) gives up on its reply. The code is
+    /// `None` for domains which accept no mail at all.
+    PermFail(Option<ReplyCode>),
+}
+
+impl RecipientOutcome {
+    fn from_error(error: &Error<io::Error>, policy: &RetryPolicy<'_>) -> Self {
+        match error {
+            Error::ServerRejected { code, enhanced, .. } => match policy.action(*code, *enhanced) {
+                RetryAction::Retry => RecipientOutcome::TempFail(Some(*code)),
+                RetryAction::Never => RecipientOutcome::PermFail(Some(*code)),
+            },
+            _ => RecipientOutcome::TempFail(None),
+        }
+    }
+}
+
+/// The outcome for every recipient of a message, in the order they were given.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeliveryReport {
+    pub recipients: Vec<(String, RecipientOutcome)>,
+}
+
+impl DeliveryReport {
+    /// Returns true if the message was delivered to every recipient.
+    pub fn is_delivered(&self) -> bool {
+        self.recipients
+            .iter()
+            .all(|(_, outcome)| *outcome == RecipientOutcome::Delivered)
+    }
+
+    /// The recipients delivery may be retried for later.
+    pub fn temporary_failures(&self) -> impl Iterator<Item = &str> {
+        self.recipients
+            .iter()
+            .filter(|(_, outcome)| matches!(outcome, RecipientOutcome::TempFail(_)))
+            .map(|(recipient, _)| recipient.as_str())
+    }
+}
+
+/// Delivers messages to the MX hosts of their recipients, see the [module](self) docs.
+///
+/// `D` looks up the TLSA records of the MX hosts, once set with
+/// [`with_dane`](Self::with_dane).
+pub struct DirectDelivery<'a, R, D = NoDane> {
+    resolver: R,
+    ehlo_domain: &'a str,
+    port: u16,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    roots: RootCertificates,
+    retry_policy: RetryPolicy<'a>,
+    max_connections_per_host: usize,
+    message_delay: Duration,
+    hosts: Mutex<HashMap<String, HostLimit>>,
+    mta_sts: Option<&'a PolicyCache>,
+    dane: Option<D>,
+}
+
+/// The TLSA resolver of a [`DirectDelivery`] without [DANE](DirectDelivery::with_dane).
+pub enum NoDane {}
+
+impl TlsaResolver for NoDane {
+    async fn resolve(&self, _name: &str) -> io::Result<Vec<TlsaRecord>> {
+        match *self {}
+    }
+}
+
+// the connections to one MX host, shared by all deliveries. Dropped once the host is idle,
+// so a long-lived delivery only keeps the hosts it is talking to.
+struct HostLimit {
+    connections: Arc<Semaphore>,
+    next_message: Instant,
+}
+
+impl<'a, R: Resolver> DirectDelivery<'a, R> {
+    /// Greets the MX hosts with `ehlo_domain`, the name of the sending host, which should
+    /// match the PTR record of its IP address.
+    pub fn new(resolver: R, ehlo_domain: &'a str) -> Self {
+        DirectDelivery {
+            resolver,
+            ehlo_domain,
+            port: 25,
+            connect_timeout: None,
+            timeout: None,
+            roots: RootCertificates::new(),
+            retry_policy: RetryPolicy::new(),
+            max_connections_per_host: 2,
+            message_delay: Duration::ZERO,
+            hosts: Mutex::new(HashMap::new()),
+            mta_sts: None,
+            dane: None,
+        }
+    }
+}
+
+impl<'a, R: Resolver, D: TlsaResolver> DirectDelivery<'a, R, D> {
+    /// Defaults to 25, the port MX hosts accept mail on.
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Bounds connecting to one MX host, see [`SmtpClientBuilder::with_connect_timeout`].
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Bounds setting up the session with one MX host, see
+    /// [`SmtpClientBuilder::with_timeout`].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Trusts `roots` in addition to the webpki roots, for MX hosts on a private network.
+    pub fn with_root_certificates(mut self, roots: RootCertificates) -> Self {
+        self.roots = roots;
+        self
+    }
+
+    /// Decides which rejections are [temporary](RecipientOutcome::TempFail), by default the
+    /// ones with a transient basic code.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy<'a>) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Defaults to 2, at least 1. Deliveries to a host with this many open connections wait
+    /// for one of them to close, hosts are told apart by name, so domains sharing MX hosts
+    /// share the limit too.
+    pub fn with_max_connections_per_host(mut self, max: usize) -> Self {
+        self.max_connections_per_host = max.max(1);
+        self
+    }
+
+    /// Starts the connections to one MX host at least `delay` apart, each of them sends one
+    /// message. Defaults to no delay.
+    pub fn with_message_delay(mut self, delay: Duration) -> Self {
+        self.message_delay = delay;
+        self
+    }
+
+    /// Looks up the [MTA-STS](super::mta_sts) policy of every recipient domain in `cache`,
+    /// fetching it if it isn't cached.
+    ///
+    /// Under an enforced policy, only the MX hosts it lists are tried, a domain without any
+    /// is a [temporary failure](RecipientOutcome::TempFail). Domains without a policy, or
+    /// whose policy can't be fetched, are delivered to as usual.
+    /// <https://datatracker.ietf.org/doc/html/rfc8461#section-5>
+    pub fn with_mta_sts(mut self, cache: &'a PolicyCache) -> Self {
+        self.mta_sts = Some(cache);
+        self
+    }
+
+    /// Checks the certificate of MX hosts with usable TLSA records against them instead of
+    /// the webpki roots, see [`DaneVerifier`]. The records are looked up with `resolver`, an
+    /// MX host whose lookup fails is skipped like one which can't be reached.
+    ///
+    /// A DANE-secured host is only checked against its records, whatever the
+    /// [MTA-STS](Self::with_mta_sts) policy of the domain.
+    /// <https://datatracker.ietf.org/doc/html/rfc7672#section-2.2>
+    pub fn with_dane<T: TlsaResolver>(self, resolver: T) -> DirectDelivery<'a, R, T> {
+        DirectDelivery {
+            resolver: self.resolver,
+            ehlo_domain: self.ehlo_domain,
+            port: self.port,
+            connect_timeout: self.connect_timeout,
+            timeout: self.timeout,
+            roots: self.roots,
+            retry_policy: self.retry_policy,
+            max_connections_per_host: self.max_connections_per_host,
+            message_delay: self.message_delay,
+            hosts: self.hosts,
+            mta_sts: self.mta_sts,
+            dane: Some(resolver),
+        }
+    }
+
+    /// Sends `data` from `from` to every recipient, one connection per domain.
+    ///
+    /// Failures are reported per recipient instead of stopping the delivery: a rejected
+    /// recipient doesn't keep the message from the others, and an unreachable domain
+    /// doesn't keep it from the other domains.
+    pub async fn deliver(&self, from: &str, recipients: &[&str], data: &[u8]) -> DeliveryReport {
+        let mut report = DeliveryReport {
+            recipients: recipients
+                .iter()
+                .map(|recipient| ((*recipient).to_owned(), RecipientOutcome::TempFail(None)))
+                .collect(),
+        };
+        let indexed = recipients.iter().copied().enumerate();
+        for group in group_by_domain(indexed, |(_, recipient)| *recipient) {
+            let outcomes = self.deliver_to_domain(&group.domain, from, &group.recipients, data);
+            for ((index, _), outcome) in group.recipients.iter().zip(outcomes.await) {
+                report.recipients[*index].1 = outcome;
+            }
+        }
+        report
+    }
+
+    async fn deliver_to_domain(
+        &self,
+        domain: &str,
+        from: &str,
+        recipients: &[(usize, &str)],
+        data: &[u8],
+    ) -> Vec<RecipientOutcome> {
+        let all = |outcome| vec![outcome; recipients.len()];
+        let mut hosts = match lookup_mx(&self.resolver, domain).await {
+            Ok(hosts) => hosts,
+            Err(_) => return all(RecipientOutcome::TempFail(None)),
+        };
+        if hosts.is_empty() {
+            return all(RecipientOutcome::PermFail(None));
+        }
+        if let Some(cache) = self.mta_sts
+            && let Ok(policy) = cache.get(domain).await
+        {
+            match policy.mode {
+                Mode::Enforce => hosts.retain(|(_, host)| policy.matches_mx(host)),
+                #[cfg(feature = "log-04")]
+                Mode::Testing => {
+                    for (_, host) in hosts.iter().filter(|(_, host)| !policy.matches_mx(host)) {
+                        log::warn!("{host} is not an MX host of the MTA-STS policy of {domain}");
+                    }
+                }
+                _ => {}
+            }
+            if hosts.is_empty() {
+                #[cfg(feature = "log-04")]
+                log::warn!("no MX host of {domain} is listed in its MTA-STS policy");
+                return all(RecipientOutcome::TempFail(None));
+            }
+        }
+
+        let mut last_error = None;
+        for (_, host) in &hosts {
+            let tls_config = match self.dane_config(host).await {
+                Ok(config) => config,
+                // delivery to the host has to be deferred
+                Err(e) => {
+                    #[cfg(feature = "log-04")]
+                    log::warn!("TLSA lookup for {host} failed: {e}");
+                    last_error = Some(Error::Tls(e));
+                    continue;
+                }
+            };
+            let _permit = self.wait_for_host(host).await;
+            let mut builder = SmtpClientBuilder::new(host)
+                .with_port(self.port)
+                .with_tls(TlsMode::StartTls)
+                .with_ehlo_domain(self.ehlo_domain)
+                .with_root_certificates(self.roots.clone());
+            if let Some(timeout) = self.connect_timeout {
+                builder = builder.with_connect_timeout(timeout);
+            }
+            if let Some(timeout) = self.timeout {
+                builder = builder.with_timeout(timeout);
+            }
+            if let Some(config) = tls_config {
+                builder = builder.with_tls_config(config);
+            }
+            let mut smtp = match builder.connect().await {
+                Ok(smtp) => smtp,
+                // try the next host
+                Err(e) => {
+                    #[cfg(feature = "log-04")]
+                    log::warn!("delivery to {host} for {domain} failed: {e}");
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+
+            let addresses: Vec<Recipient<'_>> = recipients
+                .iter()
+                .map(|(_, address)| Recipient::new(address))
+                .collect();
+            let mut outcomes = vec![None; recipients.len()];
+            let result = smtp
+                .send_envelope_partial(&Envelope::new(from, &addresses), data, |index, e| {
+                    outcomes[index] = Some(RecipientOutcome::from_error(&e, &self.retry_policy));
+                })
+                .await;
+            let _ = smtp.quit().await;
+            let rest = match result {
+                Ok(_) => RecipientOutcome::Delivered,
+                Err(e) => RecipientOutcome::from_error(&e, &self.retry_policy),
+            };
+            return outcomes
+                .into_iter()
+                .map(|outcome| outcome.unwrap_or(rest))
+                .collect();
+        }
+        all(last_error.map_or(RecipientOutcome::TempFail(None), |e| {
+            RecipientOutcome::from_error(&e, &self.retry_policy)
+        }))
+    }
+
+    // the config checking the certificate of `host` against its TLSA records, if it has
+    // usable ones
+    async fn dane_config(&self, host: &str) -> io::Result<Option<Arc<rustls::ClientConfig>>> {
+        let Some(resolver) = &self.dane else {
+            return Ok(None);
+        };
+        let records = resolver.resolve(&tlsa_name(host, self.port)).await?;
+        let verifier = DaneVerifier::new(records);
+        if !verifier.has_records() {
+            return Ok(None);
+        }
+        client_config_with_verifier(Arc::new(verifier), None).map(Some)
+    }
+
+    // waits for a free connection to `host`, and for its turn after the last message
+    async fn wait_for_host(&self, host: &str) -> OwnedSemaphorePermit {
+        let host = host.to_ascii_lowercase();
+        let connections = {
+            let mut hosts = self.hosts.lock().unwrap();
+            let now = Instant::now();
+            // every open connection and every waiting delivery holds on to the semaphore
+            hosts.retain(|_, limit| {
+                Arc::strong_count(&limit.connections) > 1 || limit.next_message > now
+            });
+            let limit = hosts.entry(host.clone()).or_insert_with(|| HostLimit {
+                connections: Arc::new(Semaphore::new(self.max_connections_per_host)),
+                next_message: now,
+            });
+            limit.connections.clone()
+        };
+        let permit = connections
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed");
+        let start = {
+            let mut hosts = self.hosts.lock().unwrap();
+            let limit = hosts.get_mut(&host).expect("added before waiting");
+            let start = limit.next_message.max(Instant::now());
+            limit.next_message = start + self.message_delay;
+            start
+        };
+        tokio::time::sleep_until(start).await;
+        permit
+    }
+
+    /// Sends a lettre message to the recipients of its envelope.
+    #[cfg(feature = "lettre")]
+    pub async fn send_lettre(
+        &self,
+        email: &lettre::Message,
+    ) -> Result<DeliveryReport, crate::ProtocolError> {
+        let envelope = email.envelope();
+        let from = envelope.from().ok_or(crate::ProtocolError::NoSender)?;
+        let to: Vec<&str> = envelope
+            .to()
+            .iter()
+            .map(|address| address.as_ref())
+            .collect();
+        Ok(self.deliver(from.as_ref(), &to, &email.formatted()).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoResolver;
+
+    impl Resolver for NoResolver {
+        type Error = io::Error;
+
+        async fn mx(&self, _domain: &str) -> io::Result<Vec<(u16, String)>> {
+            Ok(Vec::new())
+        }
+
+        async fn has_address(&self, _domain: &str) -> io::Result<bool> {
+            Ok(false)
+        }
+    }
+
+    fn hosts<R>(delivery: &DirectDelivery<'_, R>) -> Vec<String> {
+        let mut hosts: Vec<_> = delivery.hosts.lock().unwrap().keys().cloned().collect();
+        hosts.sort();
+        hosts
+    }
+
+    #[tokio::test]
+    async fn idle_hosts_are_dropped() {
+        let delivery = DirectDelivery::new(NoResolver, "client.example.org");
+        let permit = delivery.wait_for_host("MX1.example.com").await;
+        drop(delivery.wait_for_host("mx2.example.com").await);
+        assert_eq!(hosts(&delivery), ["mx1.example.com", "mx2.example.com"]);
+
+        // the connection to mx1 is still open, mx2 is idle
+        drop(delivery.wait_for_host("mx3.example.com").await);
+        assert_eq!(hosts(&delivery), ["mx1.example.com", "mx3.example.com"]);
+
+        drop(permit);
+        drop(delivery.wait_for_host("mx3.example.com").await);
+        assert_eq!(hosts(&delivery), ["mx3.example.com"]);
+    }
+
+    #[tokio::test]
+    async fn hosts_are_kept_until_their_next_message() {
+        let delivery = DirectDelivery::new(NoResolver, "client.example.org")
+            .with_message_delay(Duration::from_secs(60));
+        drop(delivery.wait_for_host("mx1.example.com").await);
+        drop(delivery.wait_for_host("mx2.example.com").await);
+        assert_eq!(hosts(&delivery), ["mx1.example.com", "mx2.example.com"]);
+    }
+
+    #[test]
+    fn at_least_one_connection_per_host() {
+        let delivery =
+            DirectDelivery::new(NoResolver, "client.example.org").with_max_connections_per_host(0);
+        assert_eq!(delivery.max_connections_per_host, 1);
+    }
+}
@@ -0,0 +1,335 @@
+//! MTA-STS: the policy a domain publishes over HTTPS to require TLS for mail to its MX hosts,
+//! for delivery from server to server.
+//! <https://datatracker.ietf.org/doc/html/rfc8461>
+//!
+//! The `_mta-sts` TXT record announcing a policy isn't looked up here. Without it, a cached
+//! policy is used until it expires, [`PolicyCache::remove`] it when the record's id changes.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # async fn example(
+//! #     mut smtp: simple_smtp::Smtp<'static, simple_smtp::integrations::tokio::TokioIo<tokio::net::TcpStream>>,
+//! # ) -> anyhow::Result<()> {
+//! use simple_smtp::integrations::tokio::mta_sts::PolicyCache;
+//!
+//! let cache = PolicyCache::new();
+//! // no policy, deliver as usual
+//! let policy = cache.get("example.com").await.ok();
+//! smtp.ehlo("client.example.org").await?;
+//! if let Some(policy) = &policy {
+//!     policy.enforce("mx.example.com", smtp.capabilities())?;
+//! }
+//! smtp.starttls().await?;
+//! // the certificate has to be valid for the MX host
+//! let smtp = smtp.upgrade_to_tls("mx.example.com").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::HashMap,
+    fmt, io,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use super::connect_tls;
+use crate::smtp::{Extensions, capabilities::Capabilities};
+
+/// The longest a policy may be cached, a year.
+pub const MAX_AGE_LIMIT: u64 = 31_557_600;
+/// The largest policy accepted.
+/// <https://datatracker.ietf.org/doc/html/rfc8461#section-3.3>
+const MAX_POLICY_SIZE: usize = 64 * 1024;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What a domain asks senders to do when TLS to its MX hosts fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Don't deliver without TLS to a listed MX host.
+    Enforce,
+    /// Deliver anyway, but report the failure.
+    Testing,
+    /// The domain no longer has a policy.
+    None,
+}
+
+/// A parsed `mta-sts.txt`.
+/// <https://datatracker.ietf.org/doc/html/rfc8461#section-3.2>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    pub mode: Mode,
+    /// Host names or `*.` patterns matching the leftmost label.
+    pub mx: Vec<String>,
+    /// How long the policy may be cached, in seconds.
+    pub max_age: u64,
+}
+
+impl Policy {
+    pub fn parse(text: &str) -> io::Result<Policy> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let (mut version, mut mode, mut max_age) = (None, None, None);
+        let mut mx = Vec::new();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let Some((key, value)) = line.split_once(':') else {
+                return Err(invalid(format!("invalid policy line {line:?}")));
+            };
+            let value = value.trim();
+            match key.trim() {
+                "version" => version = version.or(Some(value)),
+                "mode" => mode = mode.or(Some(value)),
+                "max_age" => max_age = max_age.or(Some(value)),
+                "mx" => mx.push(value.to_ascii_lowercase()),
+                // future extensions
+                _ => {}
+            }
+        }
+        if version != Some("STSv1") {
+            return Err(invalid(format!("unsupported policy version {version:?}")));
+        }
+        let mode = match mode {
+            Some("enforce") => Mode::Enforce,
+            Some("testing") => Mode::Testing,
+            Some("none") => Mode::None,
+            other => return Err(invalid(format!("invalid policy mode {other:?}"))),
+        };
+        let max_age = max_age
+            .filter(|age| {
+                !age.is_empty() && age.len() <= 10 && age.bytes().all(|b| b.is_ascii_digit())
+            })
+            .and_then(|age| age.parse::<u64>().ok())
+            .filter(|&age| age <= MAX_AGE_LIMIT)
+            .ok_or_else(|| invalid(format!("invalid policy max_age {max_age:?}")))?;
+        if mx.is_empty() && mode != Mode::None {
+            return Err(invalid("policy without mx".into()));
+        }
+        Ok(Policy { mode, mx, max_age })
+    }
+
+    /// Returns true if `host` is one of the policy's MX hosts.
+    /// <https://datatracker.ietf.org/doc/html/rfc8461#section-4.1>
+    pub fn matches_mx(&self, host: &str) -> bool {
+        let host = host.strip_suffix('.').unwrap_or(host);
+        self.mx
+            .iter()
+            .any(|pattern| match pattern.strip_prefix("*.") {
+                Some(parent) => host.split_once('.').is_some_and(|(label, rest)| {
+                    !label.is_empty() && rest.eq_ignore_ascii_case(parent)
+                }),
+                None => host.eq_ignore_ascii_case(pattern),
+            })
+    }
+
+    /// Checks the MX host `mx_host`, after EHLO on a plaintext connection, before STARTTLS.
+    ///
+    /// Only an [`Mode::Enforce`] policy fails, with [`Mode::Testing`] violations are logged.
+    /// The certificate still has to be checked against the webpki roots for `mx_host`, i.e.
+    /// with [`Smtp::upgrade_to_tls`](crate::Smtp::upgrade_to_tls).
+    pub fn enforce(
+        &self,
+        mx_host: &str,
+        capabilities: &Capabilities,
+    ) -> Result<(), PolicyViolation> {
+        let violation = if !self.matches_mx(mx_host) {
+            PolicyViolation::MxMismatch(mx_host.to_owned())
+        } else if !capabilities.supports(Extensions::StartTls) {
+            PolicyViolation::StartTlsUnavailable(mx_host.to_owned())
+        } else {
+            return Ok(());
+        };
+        match self.mode {
+            Mode::Enforce => Err(violation),
+            Mode::Testing => {
+                #[cfg(feature = "log-04")]
+                log::warn!("MTA-STS policy in testing mode not met: {violation}");
+                Ok(())
+            }
+            Mode::None => Ok(()),
+        }
+    }
+}
+
+/// Delivering to an MX host would break an enforced [`Policy`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyViolation {
+    /// The MX host isn't listed in the policy.
+    MxMismatch(String),
+    /// The MX host doesn't offer STARTTLS.
+    StartTlsUnavailable(String),
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyViolation::MxMismatch(host) => {
+                write!(f, "{host} is not an MX host of the MTA-STS policy")
+            }
+            PolicyViolation::StartTlsUnavailable(host) => {
+                write!(
+                    f,
+                    "{host} doesn't offer STARTTLS, which the MTA-STS policy requires"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+/// The host serving the policy of `domain`.
+pub fn policy_host(domain: &str) -> String {
+    format!("mta-sts.{}", domain.strip_suffix('.').unwrap_or(domain))
+}
+
+/// Fetches the policy of `domain` from its [policy host](policy_host), checking the
+/// certificate against the webpki roots.
+///
+/// Redirects aren't followed, and the whole fetch times out after a minute.
+pub async fn fetch_policy(domain: &str) -> io::Result<Policy> {
+    let host = policy_host(domain);
+    let fetch = async {
+        let tcp = TcpStream::connect((host.as_str(), 443)).await?;
+        let tls = connect_tls(tcp, &host, None).await?;
+        request_policy(tls.0, domain).await
+    };
+    tokio::time::timeout(FETCH_TIMEOUT, fetch)
+        .await
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::TimedOut,
+                "fetching the MTA-STS policy timed out",
+            )
+        })?
+}
+
+/// Requests the policy of `domain` over an established HTTPS connection to its
+/// [policy host](policy_host).
+pub async fn request_policy<T: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: T,
+    domain: &str,
+) -> io::Result<Policy> {
+    // HTTP/1.0, so the body isn't chunked
+    let request = format!(
+        "GET /.well-known/mta-sts.txt HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        policy_host(domain)
+    );
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+    let mut response = Vec::new();
+    // the headers may come on top of the largest policy
+    (&mut stream)
+        .take(2 * MAX_POLICY_SIZE as u64)
+        .read_to_end(&mut response)
+        .await?;
+    let body = response_body(&response)?;
+    let text =
+        std::str::from_utf8(body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Policy::parse(text)
+}
+
+fn response_body(response: &[u8]) -> io::Result<&[u8]> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_owned());
+    let end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| invalid("incomplete HTTP response"))?;
+    let head =
+        std::str::from_utf8(&response[..end]).map_err(|_| invalid("invalid HTTP headers"))?;
+    let mut body = &response[end + 4..];
+    let mut lines = head.split("\r\n");
+    let status = lines.next().unwrap_or_default();
+    match status.split(' ').nth(1) {
+        Some("200") => {}
+        // including redirects, which mustn't be followed
+        _ => return Err(invalid(&format!("unexpected HTTP status {status:?}"))),
+    }
+    let mut plain_text = false;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-type") {
+            let media_type = value.split(';').next().unwrap_or_default().trim();
+            plain_text = media_type.eq_ignore_ascii_case("text/plain");
+        } else if name.eq_ignore_ascii_case("content-length") {
+            let length = value
+                .parse()
+                .map_err(|_| invalid("invalid Content-Length"))?;
+            body = body
+                .get(..length)
+                .ok_or_else(|| invalid("truncated HTTP response"))?;
+        }
+    }
+    if !plain_text {
+        return Err(invalid("the MTA-STS policy is not text/plain"));
+    }
+    if body.len() > MAX_POLICY_SIZE {
+        return Err(invalid("the MTA-STS policy is too large"));
+    }
+    Ok(body)
+}
+
+/// Policies by domain, kept for their `max_age`.
+#[derive(Debug, Default)]
+pub struct PolicyCache {
+    policies: Mutex<HashMap<String, (Policy, Instant)>>,
+}
+
+impl PolicyCache {
+    pub fn new() -> Self {
+        PolicyCache::default()
+    }
+
+    /// The cached policy of `domain`, or the one [fetched](fetch_policy) if there's none or it
+    /// expired.
+    ///
+    /// A failed fetch means the domain has no usable policy, and delivery continues as if it
+    /// didn't have one.
+    /// <https://datatracker.ietf.org/doc/html/rfc8461#section-5.1>
+    pub async fn get(&self, domain: &str) -> io::Result<Policy> {
+        let domain = domain.to_ascii_lowercase();
+        if let Some(policy) = self.cached(&domain) {
+            return Ok(policy);
+        }
+        let policy = fetch_policy(&domain).await?;
+        self.insert(&domain, policy.clone());
+        Ok(policy)
+    }
+
+    /// The cached, unexpired policy of `domain`.
+    pub fn cached(&self, domain: &str) -> Option<Policy> {
+        let mut policies = self.policies.lock().unwrap_or_else(|e| e.into_inner());
+        let domain = domain.to_ascii_lowercase();
+        match policies.get(&domain) {
+            Some((policy, expires)) if Instant::now() < *expires => Some(policy.clone()),
+            Some(_) => {
+                policies.remove(&domain);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, domain: &str, policy: Policy) {
+        let expires = Instant::now() + Duration::from_secs(policy.max_age);
+        self.policies
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(domain.to_ascii_lowercase(), (policy, expires));
+    }
+
+    /// Forgets the policy of `domain`, e.g. after the id in its `_mta-sts` record changed.
+    pub fn remove(&self, domain: &str) {
+        self.policies
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&domain.to_ascii_lowercase());
+    }
+}
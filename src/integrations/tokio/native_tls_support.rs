@@ -0,0 +1,81 @@
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_native_tls::{TlsConnector, TlsStream, native_tls};
+
+use super::TokioIo;
+use crate::{Error, ReadWrite, Smtp};
+
+/// Starts TLS on a freshly opened connection with the TLS library of the platform (SChannel,
+/// Security.framework or OpenSSL), which trusts the certificates of the platform's trust
+/// store.
+///
+/// This can be enabled next to rustls, which one is used is chosen at the call site.
+pub async fn connect_tls_native<T: AsyncRead + AsyncWrite + Unpin + Send>(
+    stream: T,
+    domain: &str,
+) -> io::Result<TokioIo<TlsStream<T>>> {
+    let connector = native_tls::TlsConnector::new().map_err(io::Error::other)?;
+    connect_tls_native_with(stream, domain, connector.into()).await
+}
+
+/// Like [`connect_tls_native`], but with a custom `connector`, e.g. one with a client
+/// certificate or additional roots.
+pub async fn connect_tls_native_with<T: AsyncRead + AsyncWrite + Unpin + Send>(
+    stream: T,
+    domain: &str,
+    connector: TlsConnector,
+) -> io::Result<TokioIo<TlsStream<T>>> {
+    let tls = connector
+        .connect(domain, stream)
+        .await
+        .map_err(io::Error::other)?;
+    Ok(TokioIo(tls))
+}
+
+impl<'buffer, T: AsyncRead + AsyncWrite + Unpin + Send> Smtp<'buffer, TokioIo<T>> {
+    /// Starts TLS after a successful `STARTTLS` with the TLS library of the platform, see
+    /// [`connect_tls_native`].
+    ///
+    /// A failed handshake is an [`Error::Tls`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use simple_smtp::{Smtp, integrations::tokio::TokioIo};
+    ///
+    /// # async fn example() -> anyhow::Result<()> {
+    /// let tcp = tokio::net::TcpStream::connect("smtp.example.com:587").await?;
+    /// let mut smtp = Smtp::new(TokioIo(tcp));
+    /// smtp.ready().await?;
+    /// smtp.ehlo("client.example.com").await?;
+    /// smtp.starttls().await?;
+    /// let mut smtp = smtp.upgrade_to_tls_native("smtp.example.com").await?;
+    /// smtp.ehlo("client.example.com").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn upgrade_to_tls_native(
+        self,
+        domain: &str,
+    ) -> Result<Smtp<'buffer, TokioIo<TlsStream<T>>>, Error<<TokioIo<T> as ReadWrite>::Error>> {
+        let connector =
+            native_tls::TlsConnector::new().map_err(|e| Error::Tls(io::Error::other(e)))?;
+        self.upgrade_to_tls_native_with(domain, connector.into())
+            .await
+    }
+
+    /// Like [`upgrade_to_tls_native`](Self::upgrade_to_tls_native), but with a custom
+    /// `connector`.
+    pub async fn upgrade_to_tls_native_with(
+        self,
+        domain: &str,
+        connector: TlsConnector,
+    ) -> Result<Smtp<'buffer, TokioIo<TlsStream<T>>>, Error<<TokioIo<T> as ReadWrite>::Error>> {
+        let (tcp, upgrade) = self.into_upgrade();
+        let tls = connect_tls_native_with(tcp.0, domain, connector)
+            .await
+            .map_err(Error::Tls)?;
+        Ok(upgrade.finish(tls))
+    }
+}
@@ -0,0 +1,502 @@
+//! A pool of authenticated sessions to one relay, for services which send in bursts.
+//!
+//! A [`SmtpPool`] opens sessions on demand, up to its maximum, and keeps them open after use
+//! instead of quitting. Sessions left idle for longer than the idle timeout are closed by
+//! [`maintain`](SmtpPool::maintain), which also opens sessions until the minimum is
+//! reached, so the first messages of a burst don't wait for the connection, TLS handshake and
+//! authentication.
+//!
+//! Servers close sessions which are idle for too long themselves, often after 5 minutes, so
+//! an idle session is checked with `NOOP` before it is handed out, and replaced if the server
+//! is gone.
+//! <https://datatracker.ietf.org/doc/html/rfc5321#section-4.5.3.2.7>
+//!
+//! How long sessions were idle, and when tokens expire, is measured with tokio's clock,
+//! [`Instant`], so tests of the expiry don't have to wait for it: `tokio::time::pause` and
+//! `tokio::time::advance` of tokio's `test-util` feature move the clock forward on a current
+//! thread runtime.
+//!
+//! Sessions authenticated with an OAuth 2.0 token shouldn't outlive it, and a session can't
+//! authenticate again once it is authenticated. With a [`TokenCache`] passed to
+//! [`with_expiry`](SmtpPool::with_expiry), sessions are replaced by ones authenticated with
+//! a fresh token shortly before their token expires, see the second example.
+//! <https://datatracker.ietf.org/doc/html/rfc4954#section-4>
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), simple_smtp::Error<std::io::Error>> {
+//! use std::time::Duration;
+//!
+//! use simple_smtp::{integrations::tokio::{SmtpClientBuilder, pool::SmtpPool}, smtp::auth::AuthMode};
+//!
+//! let pool = SmtpPool::new(|| {
+//!     SmtpClientBuilder::new("smtp.example.com")
+//!         .with_auth(AuthMode::Plain { username: "me", password: "secret" })
+//!         .connect()
+//! })
+//! .with_max_connections(8)
+//! .with_min_connections(2)
+//! .with_idle_timeout(Duration::from_secs(60));
+//!
+//! // opens the two sessions kept warm, call again e.g. every few seconds
+//! pool.maintain().await?;
+//!
+//! let mut smtp = pool.get().await?;
+//! smtp.send_mail("me@example.com", ["you@example.com"].iter(), b"hi").await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! With tokens from an authorization server:
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), simple_smtp::Error<std::io::Error>> {
+//! use std::{io, sync::Arc, time::Duration};
+//!
+//! use simple_smtp::integrations::tokio::{
+//!     SmtpClientBuilder,
+//!     pool::{AccessToken, SmtpPool, TokenCache, TokenSource},
+//! };
+//! use tokio::time::Instant;
+//!
+//! struct AuthorizationServer;
+//!
+//! impl TokenSource for AuthorizationServer {
+//!     type Error = io::Error;
+//!
+//!     async fn fetch(&self) -> Result<AccessToken, io::Error> {
+//!         // e.g. a refresh token grant
+//!         Ok(AccessToken {
+//!             username: "me@example.com".to_string(),
+//!             token: "ya29.a0Af".to_string(),
+//!             expires_at: Instant::now() + Duration::from_secs(3600),
+//!         })
+//!     }
+//! }
+//!
+//! let tokens = Arc::new(TokenCache::new(AuthorizationServer));
+//! let pool = SmtpPool::new({
+//!     let tokens = tokens.clone();
+//!     move || {
+//!         let tokens = tokens.clone();
+//!         async move {
+//!             SmtpClientBuilder::new("smtp.example.com")
+//!                 .connect_with_credentials(&*tokens)
+//!                 .await
+//!         }
+//!     }
+//! })
+//! .with_expiry(move || tokens.refresh_at());
+//! # Ok(())
+//! # }
+//! ```
+
+use core::{
+    fmt,
+    ops::{Deref, DerefMut},
+    time::Duration,
+};
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Mutex, MutexGuard},
+};
+
+use tokio::{
+    sync::{Semaphore, SemaphorePermit},
+    time::Instant,
+};
+
+use super::ClientSession;
+use crate::{
+    Error,
+    smtp::{
+        SessionState,
+        auth::{CredentialProvider, Credentials},
+    },
+};
+
+/// The number of sessions of a [`SmtpPool`], and what happened to them so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolMetrics {
+    /// Sessions handed out and not returned yet.
+    pub in_use: usize,
+    /// Open sessions waiting in the pool.
+    pub idle: usize,
+    /// Sessions opened since the pool was created.
+    pub created: u64,
+    /// Sessions closed by the pool: idle for too long, gone when checked, or returned
+    /// unusable.
+    pub evicted: u64,
+}
+
+/// Sessions opened by `connect`, kept for reuse, see the [module](self) docs.
+pub struct SmtpPool<F> {
+    connect: F,
+    min: usize,
+    max: usize,
+    idle_timeout: Duration,
+    expiry: Option<Box<dyn Fn() -> Option<Instant> + Send + Sync>>,
+    // one permit per session which may be handed out
+    permits: Semaphore,
+    state: Mutex<PoolState>,
+}
+
+#[derive(Default)]
+struct PoolState {
+    // the most recently returned last
+    idle: VecDeque<Idle>,
+    in_use: usize,
+    created: u64,
+    evicted: u64,
+}
+
+struct Idle {
+    session: ClientSession,
+    since: Instant,
+    replace_at: Option<Instant>,
+}
+
+// whether a session must be replaced before its credentials expire
+fn is_expiring(replace_at: Option<Instant>) -> bool {
+    replace_at.is_some_and(|at| at <= Instant::now())
+}
+
+impl<F, Fut> SmtpPool<F>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<ClientSession, Error<io::Error>>>,
+{
+    /// Opens sessions with `connect`, e.g. [`SmtpClientBuilder::connect`] of a builder set
+    /// up with authentication, so the sessions in the pool are ready to send. With
+    /// [`SmtpClientBuilder::connect_with_credentials`], every new session fetches the current
+    /// credentials, so rotated ones are picked up as sessions are replaced.
+    ///
+    /// Defaults to at most 4 sessions, none of them kept open for longer than 60 seconds
+    /// without being used.
+    ///
+    /// [`SmtpClientBuilder::connect`]: super::SmtpClientBuilder::connect
+    /// [`SmtpClientBuilder::connect_with_credentials`]: super::SmtpClientBuilder::connect_with_credentials
+    pub fn new(connect: F) -> Self {
+        let max = 4;
+        SmtpPool {
+            connect,
+            min: 0,
+            max,
+            idle_timeout: Duration::from_secs(60),
+            expiry: None,
+            permits: Semaphore::new(max),
+            state: Mutex::new(PoolState::default()),
+        }
+    }
+
+    /// The most sessions handed out at once, further [`get`](Self::get) calls wait for one
+    /// to be returned. At least 1.
+    pub fn with_max_connections(mut self, max: usize) -> Self {
+        self.max = max.max(1);
+        self.min = self.min.min(self.max);
+        self.permits = Semaphore::new(self.max);
+        self
+    }
+
+    /// The sessions kept open even while idle, and opened ahead of time by
+    /// [`maintain`](Self::maintain). At most the maximum.
+    pub fn with_min_connections(mut self, min: usize) -> Self {
+        self.min = min.min(self.max);
+        self
+    }
+
+    /// How long a session may stay idle before [`maintain`](Self::maintain) closes it, unless
+    /// it is needed for the minimum.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// When the credentials a session was just opened with should no longer be used, e.g.
+    /// [`TokenCache::refresh_at`]. Idle sessions past that time are replaced instead of
+    /// handed out, and closed by [`maintain`](Self::maintain), which opens fresh ones.
+    pub fn with_expiry(
+        mut self,
+        expiry: impl Fn() -> Option<Instant> + Send + Sync + 'static,
+    ) -> Self {
+        self.expiry = Some(Box::new(expiry));
+        self
+    }
+
+    pub fn metrics(&self) -> PoolMetrics {
+        let state = self.lock();
+        PoolMetrics {
+            in_use: state.in_use,
+            idle: state.idle.len(),
+            created: state.created,
+            evicted: state.evicted,
+        }
+    }
+
+    /// Hands out an idle session, or opens a new one if there is none. Waits while the
+    /// maximum is in use.
+    ///
+    /// The session goes back to the pool when the [`PooledSession`] is dropped.
+    pub async fn get(&self) -> Result<PooledSession<'_, F>, Error<io::Error>> {
+        let permit = self.permits.acquire().await.expect("never closed");
+        loop {
+            let Some(mut idle) = self.lock().idle.pop_back() else {
+                break;
+            };
+            if is_expiring(idle.replace_at) {
+                #[cfg(feature = "log-04")]
+                log::debug!("credentials of pooled session expire, replacing it");
+                let _ = idle.session.quit().await;
+            } else if idle.session.noop().await.is_ok() {
+                return Ok(self.hand_out(idle.session, idle.replace_at, permit));
+            } else {
+                #[cfg(feature = "log-04")]
+                log::debug!("pooled session is gone, evicting it");
+            }
+            self.lock().evicted += 1;
+        }
+        let (session, replace_at) = self.open().await?;
+        Ok(self.hand_out(session, replace_at, permit))
+    }
+
+    /// Closes the sessions which were idle for longer than the idle timeout, keeping the
+    /// minimum, and opens sessions until the minimum is open. Meant to be called
+    /// periodically, e.g. on every tick of a [`tokio::time::interval`].
+    ///
+    /// Returns the error of the first session which couldn't be opened.
+    pub async fn maintain(&self) -> Result<(), Error<io::Error>> {
+        let expired = {
+            let mut state = self.lock();
+            let open = state.idle.len() + state.in_use;
+            let idle_timeout = self.idle_timeout;
+            let timed_out = state
+                .idle
+                .iter()
+                .take(open.saturating_sub(self.min))
+                .take_while(|idle| idle.since.elapsed() > idle_timeout)
+                .count();
+            // sessions whose credentials expire go regardless of the minimum, and are
+            // replaced below
+            let (mut expired, kept): (Vec<_>, _) = state
+                .idle
+                .drain(timed_out..)
+                .partition(|idle| is_expiring(idle.replace_at));
+            state.idle.extend(kept);
+            expired.extend(state.idle.drain(..timed_out));
+            state.evicted += expired.len() as u64;
+            expired
+        };
+        for mut idle in expired {
+            let _ = idle.session.quit().await;
+        }
+
+        loop {
+            // a permit keeps the session being opened from exceeding the maximum
+            let Ok(_permit) = self.permits.try_acquire() else {
+                return Ok(());
+            };
+            let open = {
+                let state = self.lock();
+                state.idle.len() + state.in_use
+            };
+            if open >= self.min {
+                return Ok(());
+            }
+            let (session, replace_at) = self.open().await?;
+            self.lock().idle.push_back(Idle {
+                session,
+                since: Instant::now(),
+                replace_at,
+            });
+        }
+    }
+
+    // a new session, and when its credentials expire
+    async fn open(&self) -> Result<(ClientSession, Option<Instant>), Error<io::Error>> {
+        let session = (self.connect)().await?;
+        self.lock().created += 1;
+        let replace_at = self.expiry.as_ref().and_then(|expiry| expiry());
+        Ok((session, replace_at))
+    }
+
+    fn hand_out<'p>(
+        &'p self,
+        session: ClientSession,
+        replace_at: Option<Instant>,
+        permit: SemaphorePermit<'p>,
+    ) -> PooledSession<'p, F> {
+        self.lock().in_use += 1;
+        PooledSession {
+            pool: self,
+            session: Some(session),
+            replace_at,
+            _permit: permit,
+        }
+    }
+}
+
+impl<F> SmtpPool<F> {
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        // the state stays consistent even if a thread panicked while holding the lock
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // takes back a session when its PooledSession is dropped
+    fn put_back(&self, session: ClientSession, replace_at: Option<Instant>) {
+        let mut state = self.lock();
+        state.in_use -= 1;
+        // a session interrupted mid-command, or left mid-upgrade, can't be reused
+        if session.is_usable() && session.state() == SessionState::Greeted {
+            state.idle.push_back(Idle {
+                session,
+                since: Instant::now(),
+                replace_at,
+            });
+        } else {
+            state.evicted += 1;
+        }
+    }
+}
+
+/// A session of a [`SmtpPool`], which goes back to the pool when dropped.
+pub struct PooledSession<'p, F> {
+    pool: &'p SmtpPool<F>,
+    session: Option<ClientSession>,
+    replace_at: Option<Instant>,
+    _permit: SemaphorePermit<'p>,
+}
+
+impl<F> PooledSession<'_, F> {
+    /// Takes the session out of the pool, so it isn't returned when it is done with.
+    pub fn detach(mut self) -> ClientSession {
+        self.pool.lock().in_use -= 1;
+        self.session.take().expect("taken only once")
+    }
+}
+
+impl<F> Deref for PooledSession<'_, F> {
+    type Target = ClientSession;
+
+    fn deref(&self) -> &Self::Target {
+        self.session.as_ref().expect("taken only once")
+    }
+}
+
+impl<F> DerefMut for PooledSession<'_, F> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.session.as_mut().expect("taken only once")
+    }
+}
+
+impl<F> Drop for PooledSession<'_, F> {
+    fn drop(&mut self) {
+        if let Some(session) = self.session.take() {
+            self.pool.put_back(session, self.replace_at);
+        }
+    }
+}
+
+/// An OAuth 2.0 access token, see [`TokenSource`].
+#[derive(Clone, PartialEq, Eq)]
+pub struct AccessToken {
+    /// The account the token was issued for.
+    pub username: String,
+    pub token: String,
+    /// On tokio's clock, see the [module](self) docs.
+    pub expires_at: Instant,
+}
+
+// the token stays out of logs
+impl fmt::Debug for AccessToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessToken")
+            .field("username", &self.username)
+            .field("expires_at", &self.expires_at)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Where access tokens come from, e.g. a refresh token grant with the authorization server.
+/// <https://datatracker.ietf.org/doc/html/rfc6749#section-6>
+pub trait TokenSource {
+    type Error;
+
+    /// A new token, called by [`TokenCache`] when its token is about to expire.
+    fn fetch(&self) -> impl Future<Output = Result<AccessToken, Self::Error>>;
+}
+
+/// Keeps the token of a [`TokenSource`] until shortly before it expires, and provides it
+/// as [`Credentials::OAuth2`] for `AUTH XOAUTH2`.
+///
+/// Passed to [`SmtpClientBuilder::connect_with_credentials`], and with
+/// [`refresh_at`](Self::refresh_at) to [`SmtpPool::with_expiry`], see the [module](self)
+/// docs.
+///
+/// [`SmtpClientBuilder::connect_with_credentials`]: super::SmtpClientBuilder::connect_with_credentials
+pub struct TokenCache<S> {
+    source: S,
+    margin: Duration,
+    token: Mutex<Option<AccessToken>>,
+}
+
+impl<S: TokenSource> TokenCache<S> {
+    /// Defaults to replacing tokens 5 minutes before they expire.
+    pub fn new(source: S) -> Self {
+        TokenCache {
+            source,
+            margin: Duration::from_secs(5 * 60),
+            token: Mutex::new(None),
+        }
+    }
+
+    /// How long before it expires a token is replaced, which should cover the time a
+    /// session takes to send a message.
+    pub fn with_refresh_margin(mut self, margin: Duration) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// When the current token will be replaced, `None` before the first one was fetched.
+    pub fn refresh_at(&self) -> Option<Instant> {
+        Some(self.refresh_time(self.lock().as_ref()?))
+    }
+
+    fn refresh_time(&self, token: &AccessToken) -> Instant {
+        let expires_at = token.expires_at;
+        expires_at.checked_sub(self.margin).unwrap_or(expires_at)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<AccessToken>> {
+        self.token.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<S: TokenSource> CredentialProvider for TokenCache<S> {
+    type Error = S::Error;
+
+    async fn credentials(&self) -> Result<Credentials, S::Error> {
+        let cached = self.lock().clone();
+        let token = match cached.filter(|token| self.refresh_time(token) > Instant::now()) {
+            Some(token) => token,
+            None => {
+                // concurrent sessions may fetch at the same time, the last token is kept
+                let token = self.source.fetch().await?;
+                *self.lock() = Some(token.clone());
+                token
+            }
+        };
+        Ok(Credentials::OAuth2 {
+            username: token.username,
+            token: token.token,
+        })
+    }
+
+    /// Forgets the token, so the next session fetches a new one. Called when the server
+    /// rejected it, e.g. because it was revoked.
+    async fn refresh(&self) -> Result<(), S::Error> {
+        *self.lock() = None;
+        Ok(())
+    }
+}
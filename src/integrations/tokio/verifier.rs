@@ -0,0 +1,143 @@
+use std::{io, sync::Arc};
+
+use rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, SignatureScheme,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature},
+    pki_types::{CertificateDer, ServerName, UnixTime, pem::PemObject},
+};
+
+use super::ClientCertificate;
+
+// the process wide provider if one was installed, like `ClientConfig::builder`
+pub(super) fn default_provider() -> Arc<CryptoProvider> {
+    CryptoProvider::get_default()
+        .cloned()
+        .unwrap_or_else(|| Arc::new(rustls::crypto::aws_lc_rs::default_provider()))
+}
+
+/// A rustls config which checks the server certificate with `verifier` instead of the
+/// webpki roots, for [`connect_tls_with_config`](super::connect_tls_with_config) and
+/// [`Smtp::upgrade_to_tls_with_config`](crate::Smtp::upgrade_to_tls_with_config).
+pub fn client_config_with_verifier(
+    verifier: Arc<dyn ServerCertVerifier>,
+    client_cert: Option<ClientCertificate>,
+) -> io::Result<Arc<ClientConfig>> {
+    let builder = ClientConfig::builder_with_provider(default_provider())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier);
+    let config = match client_cert {
+        Some(cert) => builder
+            .with_client_auth_cert(cert.chain, cert.key)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(config))
+}
+
+/// Trusts exactly one server certificate, for devices which pin the certificate of their
+/// relay instead of shipping a root store.
+///
+/// Only the pinned certificate is accepted, whoever issued it. Its names and validity period
+/// aren't checked, so this also works without a clock. The server still has to prove it owns
+/// the key of the certificate during the handshake.
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn example(
+/// #     smtp: simple_smtp::Smtp<'static, simple_smtp::integrations::tokio::TokioIo<tokio::net::TcpStream>>,
+/// # ) -> anyhow::Result<()> {
+/// use std::sync::Arc;
+///
+/// use simple_smtp::integrations::tokio::PinnedCertificate;
+///
+/// let pinned = PinnedCertificate::from_pem(&std::fs::read("relay.pem")?)?;
+/// let smtp = smtp
+///     .upgrade_to_tls_with_verifier("relay.example.com", Arc::new(pinned))
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct PinnedCertificate {
+    cert: CertificateDer<'static>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl PinnedCertificate {
+    /// Pins a DER encoded certificate.
+    pub fn new(der: &[u8]) -> Self {
+        PinnedCertificate {
+            cert: CertificateDer::from(der.to_vec()),
+            provider: default_provider(),
+        }
+    }
+
+    /// Pins the only certificate in a PEM file.
+    pub fn from_pem(pem: &[u8]) -> io::Result<Self> {
+        let mut certs = CertificateDer::pem_slice_iter(pem);
+        let (Some(cert), None) = (certs.next(), certs.next()) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected exactly one certificate in the PEM data",
+            ));
+        };
+        let cert = cert.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(PinnedCertificate::new(&cert))
+    }
+}
+
+impl ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if end_entity.as_ref() != self.cert.as_ref() {
+            return Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ));
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
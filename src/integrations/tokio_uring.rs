@@ -0,0 +1,78 @@
+use core::ops::Range;
+use std::io;
+
+use tokio_uring::{
+    buf::{IoBuf, IoBufMut},
+    net::TcpStream,
+};
+
+use crate::{Buffer, OwnedIo, OwnedReadWrite};
+
+/// A [`ReadWrite`](crate::ReadWrite) for a `tokio-uring` socket, for high-volume senders on
+/// Linux.
+///
+/// The socket reads into and writes from the buffer of the [`OwnedIo`], as io_uring owns the
+/// buffer of an operation until it completes. Data is copied between that buffer and the
+/// session's, see [`OwnedReadWrite`].
+///
+/// # Example
+///
+/// ```no_run
+/// use simple_smtp::{Smtp, integrations::tokio_uring::UringIo};
+///
+/// tokio_uring::start(async {
+///     let stream = tokio_uring::net::TcpStream::connect("127.0.0.1:25".parse().unwrap())
+///         .await
+///         .unwrap();
+///     let mut smtp = Smtp::new(UringIo::new(stream));
+///     smtp.ready().await.unwrap();
+/// });
+/// ```
+pub type UringIo = OwnedIo<TcpStream>;
+
+impl OwnedReadWrite for TcpStream {
+    type Error = io::Error;
+
+    async fn read_into(
+        &mut self,
+        buf: Buffer<'static>,
+        range: Range<usize>,
+    ) -> (Buffer<'static>, Result<usize, Self::Error>) {
+        let (result, slice) = self.read(buf.slice(range)).await;
+        (slice.into_inner(), result)
+    }
+
+    async fn write_from(
+        &mut self,
+        buf: Buffer<'static>,
+        range: Range<usize>,
+    ) -> (Buffer<'static>, Result<(), Self::Error>) {
+        let (result, slice) = self.write_all(buf.slice(range)).await;
+        (slice.into_inner(), result)
+    }
+}
+
+// SAFETY: both the boxed and the `'static` borrowed slice stay at the same address when the
+// buffer is moved, and every byte of them is initialized.
+unsafe impl IoBuf for Buffer<'static> {
+    fn stable_ptr(&self) -> *const u8 {
+        self.as_ptr()
+    }
+
+    fn bytes_init(&self) -> usize {
+        self.len()
+    }
+
+    fn bytes_total(&self) -> usize {
+        self.len()
+    }
+}
+
+// SAFETY: see `IoBuf`, the buffer is always fully initialized
+unsafe impl IoBufMut for Buffer<'static> {
+    fn stable_mut_ptr(&mut self) -> *mut u8 {
+        self.as_mut_ptr()
+    }
+
+    unsafe fn set_init(&mut self, _pos: usize) {}
+}
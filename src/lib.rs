@@ -1,35 +1,99 @@
-// no_std unless the std flag is set
-#![cfg_attr(not(feature = "std"), no_std)]
+// no_std unless the std flag is set, the unit tests use the std prelude
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
 #[cfg(feature = "alloc")]
 extern crate alloc;
 
+use core::{
+    task::{Context, Poll},
+    time::Duration,
+};
+
 mod error;
 pub use error::*;
 
 mod buffer;
 pub use buffer::Buffer;
 
+mod write_all;
+pub use write_all::{PartialWrite, WriteAll, WriteAllError};
+
+mod owned_io;
+pub use owned_io::{OwnedIo, OwnedIoError, OwnedReadWrite};
+
+pub mod address;
+
+pub mod encoding;
+
+pub mod envelope;
+
+mod fmt_stream;
+
+#[cfg(feature = "alloc")]
+pub mod bounce;
+
+#[cfg(feature = "alloc")]
+pub mod canonicalization;
+
+#[cfg(feature = "dkim")]
+pub mod dkim;
+
+#[cfg(feature = "arc")]
+pub mod arc;
+
 pub mod smtp;
 pub use smtp::Smtp;
 
 pub mod message;
 
+pub mod transparency;
+
+#[cfg(feature = "alloc")]
+pub mod vectors;
+
+#[cfg(feature = "std")]
+pub mod transport;
+
+#[cfg(feature = "dns")]
+pub mod resolver;
+
 pub mod integrations {
+    #[cfg(feature = "std")]
+    pub mod blocking;
     #[cfg(feature = "embassy")]
     mod embassy;
     #[cfg(feature = "embassy")]
-    pub use embassy::EmbassyTcpError;
+    pub use embassy::{EmbassyTcpError, EmbassyTimer};
+    #[cfg(feature = "embedded-io")]
+    mod embedded_io;
+    #[cfg(feature = "embedded-io")]
+    pub use embedded_io::{EmbeddedIo, EmbeddedIoError};
+    #[cfg(feature = "futures-io")]
+    mod futures_io;
+    #[cfg(feature = "futures-io")]
+    pub use futures_io::FuturesIo;
     #[cfg(feature = "lettre")]
     mod lettre;
     #[cfg(feature = "tokio")]
     pub mod tokio;
+    #[cfg(all(feature = "tokio-uring", target_os = "linux"))]
+    pub mod tokio_uring;
 }
 
+/// The connection to the server.
+///
+/// Writes are all-or-error: `write_single` and `write_multi` only return `Ok` once every
+/// byte was handed to the stream, a stream which may write less per call has to write the
+/// rest itself, e.g. by wrapping it in [`WriteAll`]. After an error the stream is in an
+/// unknown state, as part of the data may have been written.
 pub trait ReadWrite {
     type Error: core::error::Error;
+    /// Reads at least one byte, returning 0 only once the connection is closed.
     fn read(&mut self, buf: &mut [u8]) -> impl Future<Output = Result<usize, Self::Error>>;
+    /// Writes all of `buf`.
     fn write_single(&mut self, buf: &[u8]) -> impl Future<Output = Result<(), Self::Error>>;
+    /// Writes all of the slices in order, e.g. with a single vectored write. Writes them one
+    /// by one by default.
     fn write_multi(&mut self, buf: &[&[u8]]) -> impl Future<Output = Result<(), Self::Error>> {
         async move {
             for b in buf {
@@ -38,4 +102,43 @@ pub trait ReadWrite {
             Ok(())
         }
     }
+    /// Sends whatever the stream buffered so far, e.g. pending TLS records.
+    ///
+    /// Called before the session waits for a reply, so a buffering stream doesn't hold back
+    /// the command the server is supposed to answer. The default does nothing, for streams
+    /// which write right away.
+    fn flush(&mut self) -> impl Future<Output = Result<(), Self::Error>> {
+        async { Ok(()) }
+    }
+    /// Reads what the server already sent without waiting for more, returning 0 if nothing
+    /// arrived yet.
+    ///
+    /// Checked while sending message data, so a server which rejects the message early,
+    /// e.g. with `552` or `421`, is noticed before the whole message is written. The default
+    /// never finds anything, so the reply is only read after the end of data marker.
+    fn read_available(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let _ = buf;
+        Ok(0)
+    }
+}
+
+/// Bounds how long [`Smtp`] waits for a reply, see [`Smtp::set_timer`].
+///
+/// Implemented by [`TokioTimer`](integrations::tokio::TokioTimer) and
+/// [`EmbassyTimer`](integrations::EmbassyTimer). The session starts the timer before reading
+/// each reply, with the timeout of the command from its [`Timeouts`](smtp::Timeouts).
+pub trait Timer {
+    /// Starts counting down from `timeout`, replacing the previous countdown.
+    fn start(&mut self, timeout: Duration);
+    /// Returns `Ready` once the countdown elapsed, otherwise `cx` is woken when it does.
+    fn poll_elapsed(&mut self, cx: &mut Context<'_>) -> Poll<()>;
+}
+
+/// A message body which is produced piece by piece, e.g. read from flash or a file.
+///
+/// Used by [`Smtp::send_envelope_stream`], so a message never has to be in memory as a whole.
+pub trait AsyncBodySource {
+    type Error: core::error::Error;
+    /// Returns the next part of the message, or an empty slice once the message is complete.
+    fn next_chunk(&mut self) -> impl Future<Output = Result<&[u8], Self::Error>>;
 }
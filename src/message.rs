@@ -1,6 +1,31 @@
 //! Message formatting utilities.
 //!
-//! This module provides utilities for formatting email messages according to RFC 5322.
+//! This module provides utilities for formatting email messages according to RFC 5322, and
+//! [`MessageRef`] for reading them.
 
+mod builder;
+pub use builder::{
+    HeaderLineBreaks, MAX_ATTACHMENTS, MAX_HEADERS, MailboxList, Message, Overrides, Resent,
+};
 pub mod datetime;
-pub use datetime::{DateTime, TimeZone};
+#[cfg(target_has_atomic = "32")]
+pub use datetime::MockClock;
+#[cfg(feature = "std")]
+pub use datetime::SystemClock;
+pub use datetime::{Clock, DateTime, TimeZone};
+pub mod encoded_word;
+mod header_value;
+mod lint;
+mod parse;
+mod preview;
+#[cfg(feature = "alloc")]
+mod signing;
+pub(crate) use header_value::HeaderLine;
+pub use header_value::HeaderValue;
+pub use lint::{Lint, Lints};
+pub use parse::{Field, Fields, MessageRef, ParseError, Unfolded};
+pub use preview::Preview;
+#[cfg(feature = "alloc")]
+pub use signing::Signer;
+#[cfg(feature = "alloc")]
+pub(crate) use signing::sign;
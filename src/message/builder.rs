@@ -0,0 +1,977 @@
+use core::fmt::{self, Write};
+
+use super::{
+    DateTime, HeaderLine, HeaderValue, Lint, Lints, Preview, encoded_word,
+    lint::{is_aligned, is_bulk, is_line_too_long},
+};
+use crate::{
+    ProtocolError, ReadWrite,
+    address::{Address, AddressList, DisplayName, ListEntry, Mailbox},
+    encoding::{QuotedPrintable, base64},
+    envelope::Envelope,
+    transparency::DataWriter,
+};
+
+/// A message to send with [`Smtp::send_message`](crate::Smtp::send_message).
+///
+/// Everything is borrowed, and the message is written to the server part by part, so the
+/// complete message never has to be in memory.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::message::{DateTime, Message};
+///
+/// let message = Message::new("Alice <alice@example.com>")
+///     .with_to(&["bob@example.com"])
+///     .with_subject("Lunch")
+///     .with_date(DateTime::from_utc(2025, 12, 7, 12, 0, 0).unwrap())
+///     .with_html_body("See you at noon!", "<p>See you at <b>noon</b>!</p>");
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Message<'a> {
+    from: &'a str,
+    sender: Option<&'a str>,
+    to: MailboxList<'a>,
+    cc: MailboxList<'a>,
+    subject: Option<&'a str>,
+    date: Option<DateTime>,
+    message_id: Option<&'a str>,
+    resent: Option<Resent<'a>>,
+    body: Body<'a>,
+    headers: [Option<(&'a str, &'a str)>; MAX_HEADERS],
+    // a custom header didn't fit, which fails sending
+    too_many_headers: bool,
+    line_breaks: HeaderLineBreaks,
+    attachments: [Option<Attachment<'a>>; MAX_ATTACHMENTS],
+    // an attachment didn't fit, which fails sending
+    too_many_attachments: bool,
+}
+
+/// Which line breaks the values of [custom headers](Message::with_header) may contain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeaderLineBreaks {
+    /// No line breaks at all, long values are folded when sending.
+    #[default]
+    Reject,
+    /// Values which are already folded: a line break followed by a space or tab is sent
+    /// as-is, any other line break is rejected. Folded values must be ASCII, as they can't
+    /// be encoded without undoing the folding.
+    /// <https://datatracker.ietf.org/doc/html/rfc5322#section-2.2.3>
+    AllowFolded,
+}
+
+/// The number of [custom headers](Message::with_header) a [`Message`] can hold.
+pub const MAX_HEADERS: usize = 16;
+
+/// The number of attachments a [`Message`] can hold.
+pub const MAX_ATTACHMENTS: usize = 8;
+
+// written by `Message` itself, so they can't be added as custom headers. `Return-Path` is
+// added by the server delivering the message.
+// https://datatracker.ietf.org/doc/html/rfc5321#section-4.4
+const RESERVED_HEADERS: [&str; 11] = [
+    "Date",
+    "From",
+    "Sender",
+    "To",
+    "Cc",
+    "Subject",
+    "Message-ID",
+    "MIME-Version",
+    "Content-Type",
+    "Content-Transfer-Encoding",
+    "Return-Path",
+];
+
+/// The `Resent-*` fields added when a message is sent on again as-is, e.g. forwarded to a
+/// new recipient, written as a block at the top of the header.
+/// <https://datatracker.ietf.org/doc/html/rfc5322#section-3.6.6>
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::message::{DateTime, Message, Resent};
+///
+/// let now = DateTime::from_utc(2025, 12, 8, 9, 30, 0).unwrap();
+/// let message = Message::new("alice@example.com")
+///     .with_to(&["bob@example.com"])
+///     .with_resent(Resent::new(now, "bob@example.com").with_to(&["carol@example.com"]));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Resent<'a> {
+    date: DateTime,
+    from: &'a str,
+    to: &'a [&'a str],
+    message_id: Option<&'a str>,
+}
+
+impl<'a> Resent<'a> {
+    /// `from` is who resends the message, an address optionally with a display name.
+    pub fn new(date: DateTime, from: &'a str) -> Self {
+        Resent {
+            date,
+            from,
+            to: &[],
+            message_id: None,
+        }
+    }
+
+    pub fn with_to(mut self, to: &'a [&'a str]) -> Self {
+        self.to = to;
+        self
+    }
+
+    /// `id` includes the angle brackets, e.g. `<5678@example.com>`.
+    pub fn with_message_id(mut self, id: &'a str) -> Self {
+        self.message_id = Some(id);
+        self
+    }
+
+    async fn write_to<T: ReadWrite>(&self, writer: &mut DataWriter<'_, T>) -> Result<(), T::Error> {
+        let mut line = HeaderLine::start(writer, "Resent-Date").await?;
+        line.display(self.date).await?;
+        line.finish().await?;
+        header(writer, "Resent-From", HeaderValue::mailbox(self.from)).await?;
+        address_list(writer, "Resent-To", MailboxList::Text(self.to)).await?;
+        if let Some(id) = self.message_id {
+            header(writer, "Resent-Message-ID", HeaderValue::text(id)).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Headers which replace those of a [`Message`] for a single send, see
+/// [`Smtp::send_message_with`](crate::Smtp::send_message_with).
+///
+/// The message itself isn't changed, so one message can be shared by a whole bulk run with a
+/// subject or recipients of its own for every batch.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::message::{Message, Overrides};
+///
+/// let message = Message::new("news@example.com").with_subject("Newsletter");
+/// let batch = ["alice@example.com", "bob@example.com"];
+/// let overrides = Overrides::new()
+///     .with_subject("Newsletter for our early subscribers")
+///     .with_to(&batch);
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Overrides<'a> {
+    to: Option<MailboxList<'a>>,
+    cc: Option<MailboxList<'a>>,
+    subject: Option<&'a str>,
+}
+
+impl<'a> Overrides<'a> {
+    /// Overrides nothing.
+    pub fn new() -> Self {
+        Overrides::default()
+    }
+
+    /// Replaces the `To:` header, see [`Message::with_to`].
+    pub fn with_to(mut self, to: impl Into<MailboxList<'a>>) -> Self {
+        self.to = Some(to.into());
+        self
+    }
+
+    /// Replaces the `Cc:` header, an empty list leaves it out.
+    pub fn with_cc(mut self, cc: impl Into<MailboxList<'a>>) -> Self {
+        self.cc = Some(cc.into());
+        self
+    }
+
+    pub fn with_subject(mut self, subject: &'a str) -> Self {
+        self.subject = Some(subject);
+        self
+    }
+}
+
+/// The addresses of a `To` or `Cc` header field.
+#[derive(Debug, Clone, Copy)]
+pub enum MailboxList<'a> {
+    /// Addresses optionally with a display name, which are checked for line breaks only.
+    Text(&'a [&'a str]),
+    /// Parsed, and written with the display name quoted or encoded as needed.
+    Mailboxes(&'a [Mailbox<'a>]),
+    /// Parsed from a single text, written like `Mailboxes`.
+    List(AddressList<'a>),
+}
+
+impl<'a> MailboxList<'a> {
+    fn is_empty(&self) -> bool {
+        match self {
+            MailboxList::Text(addresses) => addresses.is_empty(),
+            MailboxList::Mailboxes(mailboxes) => mailboxes.is_empty(),
+            MailboxList::List(list) => list.entries().next().is_none(),
+        }
+    }
+
+    // the addresses which weren't parsed
+    fn text(&self) -> &'a [&'a str] {
+        match self {
+            MailboxList::Text(addresses) => addresses,
+            MailboxList::Mailboxes(_) | MailboxList::List(_) => &[],
+        }
+    }
+}
+
+impl<'a> From<&'a [&'a str]> for MailboxList<'a> {
+    fn from(addresses: &'a [&'a str]) -> Self {
+        MailboxList::Text(addresses)
+    }
+}
+
+impl<'a, const N: usize> From<&'a [&'a str; N]> for MailboxList<'a> {
+    fn from(addresses: &'a [&'a str; N]) -> Self {
+        MailboxList::Text(addresses)
+    }
+}
+
+impl<'a> From<&'a [Mailbox<'a>]> for MailboxList<'a> {
+    fn from(mailboxes: &'a [Mailbox<'a>]) -> Self {
+        MailboxList::Mailboxes(mailboxes)
+    }
+}
+
+impl<'a, const N: usize> From<&'a [Mailbox<'a>; N]> for MailboxList<'a> {
+    fn from(mailboxes: &'a [Mailbox<'a>; N]) -> Self {
+        MailboxList::Mailboxes(mailboxes)
+    }
+}
+
+impl<'a> From<AddressList<'a>> for MailboxList<'a> {
+    fn from(list: AddressList<'a>) -> Self {
+        MailboxList::List(list)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Body<'a> {
+    Text(&'a str),
+    Alternative { text: &'a str, html: &'a str },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Attachment<'a> {
+    filename: &'a str,
+    content_type: &'a str,
+    content: &'a [u8],
+}
+
+impl<'a> Message<'a> {
+    /// `from` is the `From:` header, an address optionally with a display name.
+    ///
+    /// Display names and the subject may contain any text, they are sent as
+    /// [`HeaderValue`]s.
+    pub fn new(from: &'a str) -> Self {
+        Message {
+            from,
+            sender: None,
+            to: MailboxList::Text(&[]),
+            cc: MailboxList::Text(&[]),
+            subject: None,
+            date: None,
+            message_id: None,
+            resent: None,
+            body: Body::Text(""),
+            headers: [None; MAX_HEADERS],
+            too_many_headers: false,
+            line_breaks: HeaderLineBreaks::Reject,
+            attachments: [None; MAX_ATTACHMENTS],
+            too_many_attachments: false,
+        }
+    }
+
+    /// The mailbox which actually sent the message, if it isn't the author in `From:`, e.g. a
+    /// secretary sending on someone's behalf.
+    /// <https://datatracker.ietf.org/doc/html/rfc5322#section-3.6.2>
+    pub fn with_sender(mut self, sender: &'a str) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
+    /// Either text like `["Bob <bob@example.com>"]`, [`Mailbox`]es, or an [`AddressList`].
+    pub fn with_to(mut self, to: impl Into<MailboxList<'a>>) -> Self {
+        self.to = to.into();
+        self
+    }
+
+    pub fn with_cc(mut self, cc: impl Into<MailboxList<'a>>) -> Self {
+        self.cc = cc.into();
+        self
+    }
+
+    pub fn with_subject(mut self, subject: &'a str) -> Self {
+        self.subject = Some(subject);
+        self
+    }
+
+    /// Defaults to the current time with the `std` feature, otherwise the header is left out
+    /// and most servers add one.
+    pub fn with_date(mut self, date: DateTime) -> Self {
+        self.date = Some(date);
+        self
+    }
+
+    /// `id` includes the angle brackets, e.g. `<1234@example.com>`.
+    pub fn with_message_id(mut self, id: &'a str) -> Self {
+        self.message_id = Some(id);
+        self
+    }
+
+    /// Adds a header field the message doesn't have a method for, e.g. `List-Unsubscribe` or
+    /// `Auto-Submitted`. Non-ASCII values are sent as encoded words.
+    ///
+    /// The header is checked when sending: [`Smtp::send_message`](crate::Smtp::send_message)
+    /// fails with [`ProtocolError::InvalidHeader`] if the name isn't a valid field name, is one
+    /// of the headers this message writes itself or `Return-Path`, which only the delivering
+    /// server adds, or if the value contains a line break, which
+    /// would allow injecting other headers. Values folded by the caller can be
+    /// [allowed](Self::with_header_line_breaks). It also fails if more than [`MAX_HEADERS`]
+    /// custom headers were added.
+    pub fn with_header(mut self, name: &'a str, value: &'a str) -> Self {
+        match self.headers.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => *slot = Some((name, value)),
+            None => self.too_many_headers = true,
+        }
+        self
+    }
+
+    /// Which line breaks custom header values may contain, [`HeaderLineBreaks::Reject`] by
+    /// default.
+    pub fn with_header_line_breaks(mut self, line_breaks: HeaderLineBreaks) -> Self {
+        self.line_breaks = line_breaks;
+        self
+    }
+
+    /// Marks the message as resent, replacing any earlier `Resent-*` fields.
+    pub fn with_resent(mut self, resent: Resent<'a>) -> Self {
+        self.resent = Some(resent);
+        self
+    }
+
+    /// A plain text body, replacing any earlier body.
+    pub fn with_text_body(mut self, text: &'a str) -> Self {
+        self.body = Body::Text(text);
+        self
+    }
+
+    /// A `multipart/alternative` body with both a plain text and an HTML version, so mail
+    /// clients show whichever they prefer. Replaces any earlier body.
+    /// <https://datatracker.ietf.org/doc/html/rfc2046#section-5.1.4>
+    pub fn with_html_body(mut self, text: &'a str, html: &'a str) -> Self {
+        self.body = Body::Alternative { text, html };
+        self
+    }
+
+    /// Attaches a file, which turns the message into `multipart/mixed` with the body as the
+    /// first part. `content` is base64 encoded on the fly while sending.
+    /// <https://datatracker.ietf.org/doc/html/rfc2183>
+    ///
+    /// A `filename` which isn't ASCII is sent percent-encoded as UTF-8.
+    /// <https://datatracker.ietf.org/doc/html/rfc2231#section-4>
+    ///
+    /// Sending fails with [`ProtocolError::TooManyAttachments`] if more than
+    /// [`MAX_ATTACHMENTS`] were added, and with [`ProtocolError::InvalidHeader`] if the
+    /// filename or content type contains a line break.
+    pub fn with_attachment(
+        mut self,
+        filename: &'a str,
+        content_type: &'a str,
+        content: &'a [u8],
+    ) -> Self {
+        let attachment = Attachment {
+            filename,
+            content_type,
+            content,
+        };
+        match self.attachments.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => *slot = Some(attachment),
+            None => self.too_many_attachments = true,
+        }
+        self
+    }
+
+    /// The key headers and the first `max_chars` characters of the plain text body, e.g. to
+    /// list queued messages or to log one without its whole content.
+    pub fn preview(&self, max_chars: usize) -> Preview<'a> {
+        let text = match self.body {
+            Body::Text(text) | Body::Alternative { text, .. } => text,
+        };
+        Preview::new(
+            self.from,
+            self.to,
+            self.subject,
+            self.date,
+            text,
+            max_chars,
+            self.attachments().count(),
+        )
+    }
+
+    /// Checks the message for common deliverability problems, e.g. before queueing it, see
+    /// [`Lint`]. The `From` address is only compared with the sender of `envelope` if one is
+    /// given.
+    ///
+    /// Sessions in [strict mode](crate::Smtp::set_strict_messages) check every message before
+    /// sending it.
+    pub fn lint(&self, envelope: Option<&Envelope<'_>>) -> Lints {
+        self.lints(envelope, &Overrides::new())
+    }
+
+    pub(crate) fn lints(
+        &self,
+        envelope: Option<&Envelope<'_>>,
+        overrides: &Overrides<'_>,
+    ) -> Lints {
+        let mut lints = Lints::default();
+        if self.date.or_else(default_date).is_none() {
+            lints.insert(Lint::MissingDate);
+        }
+        if self.message_id.is_none() {
+            lints.insert(Lint::MissingMessageId);
+        }
+        if envelope.is_some_and(|envelope| !is_aligned(self.from, envelope.from())) {
+            lints.insert(Lint::FromMismatch);
+        }
+        if let Body::Alternative { text, html } = self.body
+            && text.trim().is_empty()
+            && !html.trim().is_empty()
+        {
+            lints.insert(Lint::HtmlWithoutText);
+        }
+        // encoded values are split into short words, so only values sent as-is can be too long
+        let subject = overrides.subject.or(self.subject);
+        let mut unencoded = subject
+            .map(|subject| ("Subject", subject))
+            .into_iter()
+            .chain(self.headers().copied())
+            .filter(|(_, value)| value.contains('\n') || !encoded_word::needs_encoding(value));
+        if unencoded.any(|(name, value)| is_line_too_long(name, value)) {
+            lints.insert(Lint::LineTooLong);
+        }
+        let has_unsubscribe = self
+            .headers()
+            .any(|(name, _)| name.eq_ignore_ascii_case("List-Unsubscribe"));
+        if !has_unsubscribe && self.headers().any(|(name, value)| is_bulk(name, value)) {
+            lints.insert(Lint::MissingUnsubscribe);
+        }
+        lints
+    }
+
+    fn attachments(&self) -> impl Iterator<Item = &Attachment<'a>> {
+        self.attachments.iter().flatten()
+    }
+
+    fn headers(&self) -> impl Iterator<Item = &(&'a str, &'a str)> {
+        self.headers.iter().flatten()
+    }
+
+    /// The message with the date it will be sent with, so writing it twice gives the same
+    /// result.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn with_fixed_date(mut self) -> Self {
+        self.date = self.date.or_else(default_date);
+        self
+    }
+
+    /// The message with `date` unless it has one already.
+    pub(crate) fn or_date(mut self, date: DateTime) -> Self {
+        self.date = self.date.or(Some(date));
+        self
+    }
+
+    /// Checks the headers, with `overrides` applied, before anything is sent.
+    pub(crate) fn validate(&self, overrides: &Overrides<'_>) -> Result<(), ProtocolError> {
+        // addresses and identifiers are written as-is, so they must not end the field
+        let resent = self.resent.as_ref();
+        let to = overrides.to.unwrap_or(self.to);
+        let cc = overrides.cc.unwrap_or(self.cc);
+        let mut raw = [self.from]
+            .into_iter()
+            .chain(self.sender)
+            .chain(to.text().iter().chain(cc.text()).copied())
+            .chain(self.message_id)
+            .chain(resent.map(|resent| resent.from))
+            .chain(
+                resent
+                    .into_iter()
+                    .flat_map(|resent| resent.to.iter().copied()),
+            )
+            .chain(resent.and_then(|resent| resent.message_id));
+        if raw.any(|value| value.contains(['\r', '\n'])) {
+            return Err(ProtocolError::InvalidHeader);
+        }
+        if self.sender == Some(self.from) {
+            // https://datatracker.ietf.org/doc/html/rfc5322#section-3.6.2
+            return Err(ProtocolError::InvalidHeader);
+        }
+        if self.too_many_headers {
+            return Err(ProtocolError::InvalidHeader);
+        }
+        if self.too_many_attachments {
+            return Err(ProtocolError::TooManyAttachments);
+        }
+        if self.attachments().any(|attachment| {
+            [attachment.filename, attachment.content_type]
+                .iter()
+                .any(|value| value.contains(['\r', '\n']))
+        }) {
+            return Err(ProtocolError::InvalidHeader);
+        }
+        for (name, value) in self.headers() {
+            // printable ASCII except the colon
+            // https://datatracker.ietf.org/doc/html/rfc5322#section-3.6.8
+            let valid_name = !name.is_empty()
+                && name.bytes().all(|b| b.is_ascii_graphic() && b != b':')
+                && !RESERVED_HEADERS
+                    .iter()
+                    .any(|reserved| reserved.eq_ignore_ascii_case(name))
+                && !name
+                    .get(..7)
+                    .is_some_and(|prefix| prefix.eq_ignore_ascii_case("Resent-"));
+            let valid_value = match self.line_breaks {
+                HeaderLineBreaks::Reject => !value.contains(['\r', '\n']),
+                HeaderLineBreaks::AllowFolded => is_folded_correctly(value),
+            };
+            if !valid_name || !valid_value {
+                return Err(ProtocolError::InvalidHeader);
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn write_to<T: ReadWrite>(
+        &self,
+        writer: &mut DataWriter<'_, T>,
+        overrides: &Overrides<'_>,
+    ) -> Result<(), T::Error> {
+        // the most recent resent block comes first
+        if let Some(resent) = &self.resent {
+            resent.write_to(writer).await?;
+        }
+        let date = self.date.or_else(default_date);
+        if let Some(date) = date {
+            let mut line = HeaderLine::start(writer, "Date").await?;
+            line.display(date).await?;
+            line.finish().await?;
+        }
+        header(writer, "From", HeaderValue::mailbox(self.from)).await?;
+        if let Some(sender) = self.sender {
+            header(writer, "Sender", HeaderValue::mailbox(sender)).await?;
+        }
+        address_list(writer, "To", overrides.to.unwrap_or(self.to)).await?;
+        address_list(writer, "Cc", overrides.cc.unwrap_or(self.cc)).await?;
+        if let Some(subject) = overrides.subject.or(self.subject) {
+            header(writer, "Subject", HeaderValue::text(subject)).await?;
+        }
+        if let Some(id) = self.message_id {
+            header(writer, "Message-ID", HeaderValue::text(id)).await?;
+        }
+        for (name, value) in self.headers() {
+            if value.contains('\n') {
+                // already folded by the caller
+                let mut line = HeaderLine::start(writer, name).await?;
+                line.folded(value).await?;
+                line.finish().await?;
+                continue;
+            }
+            header(writer, name, HeaderValue::text(value)).await?;
+        }
+        header(writer, "MIME-Version", HeaderValue::text("1.0")).await?;
+        if self.attachments().next().is_none() {
+            return write_body(writer, self.body).await;
+        }
+        let mut hash = match self.body {
+            Body::Text(text) => fnv1a(FNV_OFFSET, &[text.as_bytes()]),
+            Body::Alternative { text, html } => {
+                fnv1a(FNV_OFFSET, &[text.as_bytes(), html.as_bytes()])
+            }
+        };
+        for attachment in self.attachments() {
+            let names = [
+                attachment.filename.as_bytes(),
+                attachment.content_type.as_bytes(),
+            ];
+            hash = fnv1a(hash, &names);
+        }
+        let boundary = Boundary::new("mixed", hash);
+        let boundary = boundary.as_str().as_bytes();
+        writer
+            .write_multi(&[
+                b"Content-Type: multipart/mixed; boundary=\"",
+                boundary,
+                b"\"\r\n\r\n--",
+                boundary,
+                b"\r\n",
+            ])
+            .await?;
+        write_body(writer, self.body).await?;
+        for attachment in self.attachments() {
+            writer.write_multi(&[b"\r\n--", boundary, b"\r\n"]).await?;
+            write_attachment(writer, attachment).await?;
+        }
+        writer.write_multi(&[b"\r\n--", boundary, b"--\r\n"]).await
+    }
+}
+
+// every line break starts a continuation line, and none of those consist of whitespace only
+fn is_folded_correctly(value: &str) -> bool {
+    if !value.contains('\n') {
+        return !value.contains('\r');
+    }
+    let mut lines = value.split("\r\n");
+    let first = lines.next().unwrap_or_default();
+    value.is_ascii()
+        && !first.contains(['\r', '\n'])
+        && lines.all(|line| {
+            line.starts_with([' ', '\t'])
+                && !line.contains(['\r', '\n'])
+                && !line.trim_start_matches([' ', '\t']).is_empty()
+        })
+}
+
+// the content headers, empty line and content of the body, without a final line break
+async fn write_body<T: ReadWrite>(
+    writer: &mut DataWriter<'_, T>,
+    body: Body<'_>,
+) -> Result<(), T::Error> {
+    match body {
+        Body::Text(text) => {
+            text_part_headers(writer, "plain").await?;
+            writer.write(b"\r\n").await?;
+            quoted_printable(writer, text).await
+        }
+        Body::Alternative { text, html } => {
+            let hash = fnv1a(FNV_OFFSET, &[text.as_bytes(), html.as_bytes()]);
+            let boundary = Boundary::new("alt", hash);
+            let boundary = boundary.as_str().as_bytes();
+            writer
+                .write_multi(&[
+                    b"Content-Type: multipart/alternative; boundary=\"",
+                    boundary,
+                    b"\"\r\n\r\n",
+                ])
+                .await?;
+            for (subtype, content) in [("plain", text), ("html", html)] {
+                writer.write_multi(&[b"--", boundary, b"\r\n"]).await?;
+                text_part_headers(writer, subtype).await?;
+                writer.write(b"\r\n").await?;
+                quoted_printable(writer, content).await?;
+                writer.write(b"\r\n").await?;
+            }
+            writer.write_multi(&[b"--", boundary, b"--"]).await
+        }
+    }
+}
+
+async fn write_attachment<T: ReadWrite>(
+    writer: &mut DataWriter<'_, T>,
+    attachment: &Attachment<'_>,
+) -> Result<(), T::Error> {
+    writer
+        .write_multi(&[
+            b"Content-Type: ",
+            attachment.content_type.as_bytes(),
+            b"\r\nContent-Disposition: attachment; ",
+        ])
+        .await?;
+    if attachment.filename.is_ascii() {
+        writer.write(b"filename=\"").await?;
+        write_quoted(writer, attachment.filename).await?;
+        writer.write(b"\"").await?;
+    } else {
+        writer.write(b"filename*=UTF-8''").await?;
+        write_percent_encoded(writer, attachment.filename).await?;
+    }
+    writer
+        .write(b"\r\nContent-Transfer-Encoding: base64\r\n\r\n")
+        .await?;
+    base64_lines(writer, attachment.content).await
+}
+
+// the inside of a quoted-string, with quotes and backslashes escaped
+// https://datatracker.ietf.org/doc/html/rfc5322#section-3.2.4
+async fn write_quoted<T: ReadWrite>(
+    writer: &mut DataWriter<'_, T>,
+    value: &str,
+) -> Result<(), T::Error> {
+    let mut rest = value;
+    while let Some(idx) = rest.find(['"', '\\']) {
+        writer
+            .write_multi(&[&rest.as_bytes()[..idx], b"\\", &rest.as_bytes()[idx..=idx]])
+            .await?;
+        rest = &rest[idx + 1..];
+    }
+    writer.write(rest.as_bytes()).await
+}
+
+// a parameter value with everything but the attribute-chars encoded as `%XX`
+// https://datatracker.ietf.org/doc/html/rfc2231#section-7
+async fn write_percent_encoded<T: ReadWrite>(
+    writer: &mut DataWriter<'_, T>,
+    value: &str,
+) -> Result<(), T::Error> {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    let is_attribute_char = |b: u8| b.is_ascii_graphic() && !b"*'%()<>@,;:\\\"/[]?=".contains(&b);
+    let mut rest = value.as_bytes();
+    while let Some(idx) = rest.iter().position(|&b| !is_attribute_char(b)) {
+        let b = rest[idx];
+        let escape = [b'%', HEX[(b >> 4) as usize], HEX[(b & 0xf) as usize]];
+        writer.write_multi(&[&rest[..idx], &escape]).await?;
+        rest = &rest[idx + 1..];
+    }
+    writer.write(rest).await
+}
+
+// 57 bytes make a line of 76 characters
+// https://datatracker.ietf.org/doc/html/rfc2045#section-6.8
+const BASE64_LINE: usize = 57;
+
+// encodes a few lines at a time, so the encoded content is never in memory as a whole
+async fn base64_lines<T: ReadWrite>(
+    writer: &mut DataWriter<'_, T>,
+    content: &[u8],
+) -> Result<(), T::Error> {
+    const LINES: usize = 8;
+    let mut buf = [0; LINES * 78];
+    let mut lines = content.chunks(BASE64_LINE).peekable();
+    while lines.peek().is_some() {
+        let mut len = 0;
+        for line in lines.by_ref().take(LINES) {
+            if len > 0 {
+                buf[len..len + 2].copy_from_slice(b"\r\n");
+                len += 2;
+            }
+            len += base64::encode_slice(line, &mut buf[len..]).expect("a line fits");
+        }
+        if lines.peek().is_some() {
+            buf[len..len + 2].copy_from_slice(b"\r\n");
+            len += 2;
+        }
+        writer.write(&buf[..len]).await?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+fn default_date() -> Option<DateTime> {
+    Some(DateTime::now_utc())
+}
+
+#[cfg(not(feature = "std"))]
+fn default_date() -> Option<DateTime> {
+    None
+}
+
+async fn header<T: ReadWrite>(
+    writer: &mut DataWriter<'_, T>,
+    name: &str,
+    value: HeaderValue<'_>,
+) -> Result<(), T::Error> {
+    let mut line = HeaderLine::start(writer, name).await?;
+    value.write_to(&mut line).await?;
+    line.finish().await
+}
+
+async fn address_list<T: ReadWrite>(
+    writer: &mut DataWriter<'_, T>,
+    name: &str,
+    addresses: MailboxList<'_>,
+) -> Result<(), T::Error> {
+    if addresses.is_empty() {
+        return Ok(());
+    }
+    let mut line = HeaderLine::start(writer, name).await?;
+    match addresses {
+        MailboxList::Text(addresses) => {
+            for (idx, address) in addresses.iter().enumerate() {
+                if idx > 0 {
+                    line.separate(b", ");
+                }
+                HeaderValue::mailbox(address).write_to(&mut line).await?;
+            }
+        }
+        MailboxList::Mailboxes(mailboxes) => {
+            write_mailboxes(&mut line, mailboxes.iter().copied()).await?
+        }
+        MailboxList::List(list) => write_entries(&mut line, list).await?,
+    }
+    line.finish().await
+}
+
+async fn write_mailboxes<'a, T: ReadWrite>(
+    line: &mut HeaderLine<'_, '_, T>,
+    mailboxes: impl Iterator<Item = Mailbox<'a>>,
+) -> Result<(), T::Error> {
+    for (idx, mailbox) in mailboxes.enumerate() {
+        if idx > 0 {
+            line.separate(b", ");
+        }
+        write_mailbox(line, &mailbox).await?;
+    }
+    Ok(())
+}
+
+// writes groups as such, so the header shows who a message was sent to the way it was drafted
+async fn write_entries<T: ReadWrite>(
+    line: &mut HeaderLine<'_, '_, T>,
+    list: AddressList<'_>,
+) -> Result<(), T::Error> {
+    for (idx, entry) in list.entries().enumerate() {
+        if idx > 0 {
+            line.separate(b", ");
+        }
+        match entry {
+            ListEntry::Mailbox(mailbox) => write_mailbox(line, &mailbox).await?,
+            ListEntry::Group(group) => {
+                write_display_name(line, group.display_name()).await?;
+                line.separate(b": ");
+                for (idx, mailbox) in group.members().iter().enumerate() {
+                    if idx > 0 {
+                        line.separate(b", ");
+                    }
+                    write_mailbox(line, &mailbox).await?;
+                }
+                // `;` directly follows the colon of an empty group, or the last member
+                let empty = group.members().iter().next().is_none();
+                line.separate(if empty { b":" } else { b"" });
+                line.display(';').await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn write_mailbox<T: ReadWrite>(
+    line: &mut HeaderLine<'_, '_, T>,
+    mailbox: &Mailbox<'_>,
+) -> Result<(), T::Error> {
+    let address = mailbox.address();
+    let Some(name) = mailbox.display_name() else {
+        return line.word(address.as_str().as_bytes()).await;
+    };
+    write_display_name(line, name).await?;
+    line.display(AngleAddr(address)).await
+}
+
+async fn write_display_name<T: ReadWrite>(
+    line: &mut HeaderLine<'_, '_, T>,
+    name: DisplayName<'_>,
+) -> Result<(), T::Error> {
+    if name.chars().all(|c| c.is_ascii()) {
+        line.display(name.quoted()).await?;
+    } else {
+        // https://datatracker.ietf.org/doc/html/rfc2047#section-5
+        let mut chunks = encoded_word::CharChunks::new(name.chars());
+        while let Some(chunk) = chunks.next_chunk() {
+            let mut buf = [0; encoded_word::MAX_WORD_LEN];
+            line.word(encoded_word::word(chunk, &mut buf).as_bytes())
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+struct AngleAddr<'a>(Address<'a>);
+
+impl fmt::Display for AngleAddr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{}>", self.0)
+    }
+}
+
+async fn text_part_headers<T: ReadWrite>(
+    writer: &mut DataWriter<'_, T>,
+    subtype: &str,
+) -> Result<(), T::Error> {
+    writer
+        .write_multi(&[
+            b"Content-Type: text/",
+            subtype.as_bytes(),
+            b"; charset=utf-8\r\n",
+        ])
+        .await?;
+    let encoding = HeaderValue::text("quoted-printable");
+    header(writer, "Content-Transfer-Encoding", encoding).await
+}
+
+async fn quoted_printable<T: ReadWrite>(
+    writer: &mut DataWriter<'_, T>,
+    text: &str,
+) -> Result<(), T::Error> {
+    let mut encoder = QuotedPrintable::new();
+    let mut buf = [0; 256];
+    let mut rest = text.as_bytes();
+    while !rest.is_empty() {
+        let (read, written) = encoder.encode(rest, &mut buf);
+        writer.write(&buf[..written]).await?;
+        rest = &rest[read..];
+    }
+    let written = encoder.finish(&mut buf);
+    writer.write(&buf[..written]).await
+}
+
+// `=_` never occurs in quoted-printable or base64 encoded content, so a boundary starting
+// with it can't be mistaken for a line of a part.
+// https://datatracker.ietf.org/doc/html/rfc2046#section-5.1.1
+struct Boundary(ArrayString<40>);
+
+impl Boundary {
+    // `kind` keeps nested boundaries apart, the hash of the content tells messages apart
+    fn new(kind: &str, hash: u64) -> Self {
+        let mut boundary = ArrayString::new();
+        let _ = write!(boundary, "=_{kind}_{hash:016x}");
+        Boundary(boundary)
+    }
+
+    fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+// FNV-1a, continuing from `hash`
+fn fnv1a(hash: u64, content: &[&[u8]]) -> u64 {
+    content
+        .iter()
+        .flat_map(|part| part.iter())
+        .fold(hash, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+// formats short values without allocating
+struct ArrayString<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> ArrayString<N> {
+    fn new() -> Self {
+        ArrayString {
+            buf: [0; N],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).expect("only whole strings are written")
+    }
+}
+
+impl<const N: usize> Write for ArrayString<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
@@ -6,6 +6,8 @@
 //! without allocation, so chrono's `alloc` feature is not required.
 
 use core::fmt;
+#[cfg(target_has_atomic = "32")]
+use core::sync::atomic::{AtomicI32, AtomicU32, Ordering, fence};
 
 use chrono::{DateTime as ChronoDateTime, FixedOffset, TimeZone as ChronoTimeZone, Utc};
 
@@ -211,6 +213,56 @@ impl DateTime {
         })
     }
 
+    /// Parses the value of a `Date` field, as written by the [`Display`](fmt::Display)
+    /// implementation, e.g. `Sun, 07 Dec 2025 12:30:00 +0100`.
+    ///
+    /// The obsolete forms older mailers write are accepted too: two and three digit years,
+    /// times without seconds, and zone names like `GMT` or `EST`, with unknown names read
+    /// as an undefined zone. Comments after the zone, like `(CET)`, are ignored.
+    /// <https://datatracker.ietf.org/doc/html/rfc5322#section-4.3>
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use simple_smtp::message::DateTime;
+    ///
+    /// let date = DateTime::parse("Sun, 7 Dec 2025 12:30:00 +0100").unwrap();
+    /// assert_eq!(date.timestamp(), 1765107000);
+    /// assert_eq!(DateTime::parse(&date.to_string()), Some(date));
+    /// ```
+    #[must_use]
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut tokens = value
+            .split(|c: char| c.is_ascii_whitespace() || c == ',')
+            .filter(|token| !token.is_empty());
+        let mut day = tokens.next()?;
+        // the day of the week is optional, and redundant
+        if day.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            day = tokens.next()?;
+        }
+        let day = number(day, 2)?;
+        let month = tokens.next()?;
+        let month = MONTHS
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(month))? as u32
+            + 1;
+        let year = tokens.next()?;
+        let year = match (year.len(), number(year, 4)? as i32) {
+            (2, year) if year < 50 => year + 2000,
+            (2 | 3, year) => year + 1900,
+            (_, year) => year,
+        };
+        let mut time = tokens.next()?.split(':');
+        let hour = number(time.next()?, 2)?;
+        let minute = number(time.next()?, 2)?;
+        let second = time.next().map_or(Some(0), |second| number(second, 2))?;
+        if time.next().is_some() {
+            return None;
+        }
+        let zone = parse_zone(tokens.next()?)?;
+        DateTime::from_local(year, month, day, hour, minute, second, zone)
+    }
+
     /// Convert to a different timezone while keeping the same point in time.
     ///
     /// This converts the actual time value to the new timezone.
@@ -235,6 +287,12 @@ impl DateTime {
         })
     }
 
+    /// The Unix timestamp (seconds since 1970-01-01 00:00:00 UTC) of this point in time.
+    #[must_use]
+    pub fn timestamp(&self) -> i64 {
+        self.utc.timestamp()
+    }
+
     /// Get the current UTC time as a DateTime.
     #[cfg(feature = "std")]
     #[must_use]
@@ -264,6 +322,216 @@ impl DateTime {
     }
 }
 
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// a number of at most `max_digits` digits
+fn number(text: &str, max_digits: usize) -> Option<u32> {
+    if text.is_empty() || text.len() > max_digits || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+// `+HHMM`, `-HHMM`, or one of the obsolete zone names
+fn parse_zone(zone: &str) -> Option<TimeZone> {
+    if let Some(offset) = zone.strip_prefix(['+', '-']) {
+        if offset.len() != 4 {
+            return None;
+        }
+        let hours = number(&offset[..2], 2)?;
+        let minutes = number(&offset[2..], 2)?;
+        if minutes > 59 {
+            return None;
+        }
+        let minutes = (hours * 60 + minutes) as i32;
+        // -0000 is an undefined zone, like TimeZone::minus(0, 0)
+        let offset_minutes = match zone.starts_with('-') {
+            true => Some(-minutes).filter(|&minutes| minutes != 0),
+            false => Some(minutes),
+        };
+        return Some(TimeZone { offset_minutes });
+    }
+    if !zone.bytes().all(|b| b.is_ascii_alphabetic()) {
+        return None;
+    }
+    let zones = [
+        ("UT", 0),
+        ("GMT", 0),
+        ("EDT", -4),
+        ("EST", -5),
+        ("CDT", -5),
+        ("CST", -6),
+        ("MDT", -6),
+        ("MST", -7),
+        ("PDT", -7),
+        ("PST", -8),
+    ];
+    // the military zones and unknown names are undefined, their meaning was never agreed on
+    let offset_minutes = zones
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(zone))
+        .map(|(_, hours)| hours * 60);
+    Some(TimeZone { offset_minutes })
+}
+
+/// Where the current time comes from, e.g. for the `Date` header of a message without a
+/// date, see [`Smtp::set_clock`](crate::Smtp::set_clock).
+///
+/// Passing a [`MockClock`] instead of the system time makes time-dependent behavior
+/// testable, and gives devices without a system clock a way to provide the time.
+pub trait Clock {
+    fn now(&self) -> DateTime;
+}
+
+/// The system time, in UTC.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime {
+        DateTime::now_utc()
+    }
+}
+
+/// A clock which only moves when it is told to, for tests.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::message::{Clock, DateTime, MockClock};
+///
+/// let clock = MockClock::new(DateTime::from_utc(2025, 12, 7, 12, 0, 0).unwrap());
+/// clock.advance(90);
+/// assert_eq!(clock.now(), DateTime::from_utc(2025, 12, 7, 12, 1, 30).unwrap());
+/// ```
+// atomics instead of a Cell, so a session holding the clock can still be sent to another task,
+// with the time split into halves for targets without 64-bit atomics
+#[cfg(target_has_atomic = "32")]
+pub struct MockClock {
+    // odd while the time is written, readers retry until they saw the same even value twice
+    sequence: AtomicU32,
+    millis_high: AtomicU32,
+    millis_low: AtomicU32,
+    // the offset in minutes, `i32::MIN` for an undefined zone
+    zone: AtomicI32,
+}
+
+#[cfg(target_has_atomic = "32")]
+impl MockClock {
+    pub fn new(now: DateTime) -> Self {
+        let clock = MockClock {
+            sequence: AtomicU32::new(0),
+            millis_high: AtomicU32::new(0),
+            millis_low: AtomicU32::new(0),
+            zone: AtomicI32::new(0),
+        };
+        clock.set(now);
+        clock
+    }
+
+    pub fn set(&self, now: DateTime) {
+        self.update(|_, _| {
+            (
+                now.utc.timestamp_millis(),
+                now.zone.offset_minutes().unwrap_or(i32::MIN),
+            )
+        });
+    }
+
+    /// Moves the clock forward by `seconds`, or backward if negative. The clock stops at
+    /// the earliest and latest date it can represent.
+    pub fn advance(&self, seconds: i64) {
+        let min = ChronoDateTime::<Utc>::MIN_UTC.timestamp_millis();
+        let max = ChronoDateTime::<Utc>::MAX_UTC.timestamp_millis();
+        self.update(|millis, zone| {
+            let millis = millis
+                .saturating_add(seconds.saturating_mul(1000))
+                .clamp(min, max);
+            (millis, zone)
+        });
+    }
+
+    // replaces the time with `f` of the current one, one writer at a time
+    fn update(&self, f: impl FnOnce(i64, i32) -> (i64, i32)) {
+        let sequence = loop {
+            let sequence = self.sequence.load(Ordering::Relaxed);
+            if sequence.is_multiple_of(2)
+                && self
+                    .sequence
+                    .compare_exchange_weak(
+                        sequence,
+                        sequence.wrapping_add(1),
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+            {
+                break sequence;
+            }
+            core::hint::spin_loop();
+        };
+        // readers see the odd sequence before any half of the new time
+        fence(Ordering::Release);
+        let (millis, zone) = f(self.millis(), self.zone.load(Ordering::Relaxed));
+        self.millis_high
+            .store((millis >> 32) as u32, Ordering::Relaxed);
+        self.millis_low.store(millis as u32, Ordering::Relaxed);
+        self.zone.store(zone, Ordering::Relaxed);
+        self.sequence
+            .store(sequence.wrapping_add(2), Ordering::Release);
+    }
+
+    fn millis(&self) -> i64 {
+        let high = self.millis_high.load(Ordering::Relaxed);
+        let low = self.millis_low.load(Ordering::Relaxed);
+        (u64::from(high) << 32 | u64::from(low)) as i64
+    }
+}
+
+#[cfg(target_has_atomic = "32")]
+impl Clock for MockClock {
+    fn now(&self) -> DateTime {
+        let (millis, zone) = loop {
+            let before = self.sequence.load(Ordering::Acquire);
+            let millis = self.millis();
+            let zone = self.zone.load(Ordering::Relaxed);
+            fence(Ordering::Acquire);
+            if before.is_multiple_of(2) && self.sequence.load(Ordering::Relaxed) == before {
+                break (millis, zone);
+            }
+            core::hint::spin_loop();
+        };
+        let offset_minutes = match zone {
+            i32::MIN => None,
+            offset => Some(offset),
+        };
+        DateTime {
+            utc: ChronoDateTime::<Utc>::from_timestamp_millis(millis)
+                .expect("kept in range by `set` and `advance`"),
+            zone: TimeZone { offset_minutes },
+        }
+    }
+}
+
+/// A clock set to the time this one shows now, moving independently of it.
+#[cfg(target_has_atomic = "32")]
+impl Clone for MockClock {
+    fn clone(&self) -> Self {
+        MockClock::new(self.now())
+    }
+}
+
+#[cfg(target_has_atomic = "32")]
+impl fmt::Debug for MockClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MockClock").field(&self.now()).finish()
+    }
+}
+
 impl fmt::Display for DateTime {
     /// Formats the date-time according to RFC 5322 §3.3.
     ///
@@ -377,6 +645,45 @@ mod tests {
         assert!(utc.to_string().contains("+0000"));
     }
 
+    #[cfg(target_has_atomic = "32")]
+    #[test]
+    fn mock_clock_saturates() {
+        let start = DateTime::from_utc(2025, 12, 7, 12, 0, 0).unwrap();
+        let clock = MockClock::new(start);
+        clock.advance(i64::MAX);
+        let max = ChronoDateTime::<Utc>::MAX_UTC;
+        assert_eq!(clock.now().utc.timestamp_millis(), max.timestamp_millis());
+        clock.advance(1);
+        clock.advance(i64::MIN);
+        clock.advance(i64::MIN);
+        assert_eq!(clock.now().utc, ChronoDateTime::<Utc>::MIN_UTC);
+
+        clock.set(start);
+        clock.advance(-60);
+        assert_eq!(
+            clock.now(),
+            DateTime::from_utc(2025, 12, 7, 11, 59, 0).unwrap()
+        );
+    }
+
+    #[cfg(target_has_atomic = "32")]
+    #[test]
+    fn mock_clock_clone_is_a_snapshot() {
+        let zone = TimeZone::minus(5, 30).unwrap();
+        let clock = MockClock::new(DateTime::from_local(1969, 7, 20, 20, 17, 40, zone).unwrap());
+        clock.advance(-1);
+        let copy = clock.clone();
+        clock.advance(10);
+        assert_eq!(
+            copy.now(),
+            DateTime::from_local(1969, 7, 20, 20, 17, 39, zone).unwrap()
+        );
+        assert_eq!(
+            clock.now(),
+            DateTime::from_local(1969, 7, 20, 20, 17, 49, zone).unwrap()
+        );
+    }
+
     #[test]
     fn date_invalid_returns_none() {
         assert!(DateTime::from_utc(2025, 13, 1, 0, 0, 0).is_none());
@@ -584,4 +891,53 @@ mod tests {
             "to_zone with None timezone should display as -0000"
         );
     }
+
+    #[test]
+    fn date_parsing() {
+        let date = DateTime::parse("Sun, 07 Dec 2025 12:30:00 +0100").unwrap();
+        assert_eq!(
+            date,
+            DateTime::from_local(2025, 12, 7, 12, 30, 0, TimeZone::plus(1, 0).unwrap()).unwrap()
+        );
+        assert_eq!(DateTime::parse(&date.to_string()), Some(date));
+
+        // obsolete forms
+        let cases = [
+            ("7 Dec 25 12:30 GMT", "Sun, 07 Dec 2025 12:30:00 +0000"),
+            (
+                "Fri, 21 Nov 97 09:55:06 EST",
+                "Fri, 21 Nov 1997 09:55:06 -0500",
+            ),
+            (
+                "Thu,\r\n 13 Feb 1969 23:32:54 -0330 (Newfoundland)",
+                "Thu, 13 Feb 1969 23:32:54 -0330",
+            ),
+            (
+                "1 jan 2025 00:00:00 -0000",
+                "Wed, 01 Jan 2025 00:00:00 -0000",
+            ),
+            ("1 Jan 2025 00:00:00 Z", "Wed, 01 Jan 2025 00:00:00 -0000"),
+            (
+                "1 Jan 2025 00:00:00 +1400",
+                "Wed, 01 Jan 2025 00:00:00 +1400",
+            ),
+        ];
+        for (value, expected) in cases {
+            let date = DateTime::parse(value).unwrap_or_else(|| panic!("{value}"));
+            assert_eq!(date.to_string(), expected);
+        }
+
+        for invalid in [
+            "",
+            "Sun, 07 Dec 2025",
+            "07 Dec 2025 12:30:00",
+            "07 Foo 2025 12:30:00 +0000",
+            "32 Dec 2025 12:30:00 +0000",
+            "07 Dec 2025 12:30:00:00 +0000",
+            "07 Dec 2025 12:30:00 +100",
+            "07 Dec 2025 12:30:00 +0160",
+        ] {
+            assert_eq!(DateTime::parse(invalid), None, "{invalid}");
+        }
+    }
 }
@@ -0,0 +1,154 @@
+//! Encoded words, for non-ASCII text in header fields such as `Subject`.
+//! <https://datatracker.ietf.org/doc/html/rfc2047>
+//!
+//! Text is encoded as UTF-8 in base64 (`=?UTF-8?B?...?=`), split into words of at most 75
+//! octets without breaking up characters.
+
+use core::fmt::{self, Write};
+
+use crate::encoding::base64;
+
+// https://datatracker.ietf.org/doc/html/rfc2047#section-2
+pub(crate) const MAX_WORD_LEN: usize = 75;
+const PREFIX: &str = "=?UTF-8?B?";
+const SUFFIX: &str = "?=";
+// every 3 bytes of text take 4 bytes of base64
+const MAX_CHUNK_LEN: usize = (MAX_WORD_LEN - PREFIX.len() - SUFFIX.len()) / 4 * 3;
+
+/// Returns true if `text` can't be sent as-is in an unstructured header field: it contains
+/// non-ASCII or control characters, or something that would be mistaken for an encoded word.
+pub fn needs_encoding(text: &str) -> bool {
+    text.bytes()
+        .any(|b| !(b.is_ascii_graphic() || b == b' ' || b == b'\t'))
+        || text.contains("=?")
+}
+
+/// Splits `text` into the pieces which are encoded as one word each.
+pub fn chunks(text: &str) -> Chunks<'_> {
+    Chunks { remaining: text }
+}
+
+/// The pieces of a text, created by [`chunks`].
+pub struct Chunks<'a> {
+    remaining: &'a str,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining.is_empty() {
+            return None;
+        }
+        let mut end = self.remaining.len().min(MAX_CHUNK_LEN);
+        while !self.remaining.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (chunk, rest) = self.remaining.split_at(end);
+        self.remaining = rest;
+        Some(chunk)
+    }
+}
+
+/// Like [`Chunks`], for text which is only available one character at a time.
+/// Not an `Iterator` because chunks borrow from `self`.
+pub(crate) struct CharChunks<I: Iterator<Item = char>> {
+    chars: core::iter::Peekable<I>,
+    buf: [u8; MAX_CHUNK_LEN],
+}
+
+impl<I: Iterator<Item = char>> CharChunks<I> {
+    pub(crate) fn new(chars: I) -> Self {
+        CharChunks {
+            chars: chars.peekable(),
+            buf: [0; MAX_CHUNK_LEN],
+        }
+    }
+
+    pub(crate) fn next_chunk(&mut self) -> Option<&str> {
+        let mut len = 0;
+        while let Some(c) = self.chars.next_if(|c| len + c.len_utf8() <= MAX_CHUNK_LEN) {
+            len += c.encode_utf8(&mut self.buf[len..]).len();
+        }
+        match len {
+            0 => None,
+            len => Some(core::str::from_utf8(&self.buf[..len]).expect("whole characters")),
+        }
+    }
+}
+
+/// Writes `text` as encoded words, separated by a folding line break so every line of the
+/// header field stays short.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::message::encoded_word;
+///
+/// let mut subject = String::from("Subject: ");
+/// encoded_word::encode("Grüße", &mut subject).unwrap();
+/// assert_eq!(subject, "Subject: =?UTF-8?B?R3LDvMOfZQ==?=");
+/// ```
+pub fn encode(text: &str, out: &mut impl Write) -> fmt::Result {
+    for (idx, chunk) in chunks(text).enumerate() {
+        if idx > 0 {
+            out.write_str("\r\n ")?;
+        }
+        out.write_str(word(chunk, &mut [0; MAX_WORD_LEN]))?;
+    }
+    Ok(())
+}
+
+/// Encodes one of the [`chunks`] as a complete word in `buf`.
+pub(crate) fn word<'b>(chunk: &str, buf: &'b mut [u8; MAX_WORD_LEN]) -> &'b str {
+    let payload_end = buf.len() - SUFFIX.len();
+    buf[..PREFIX.len()].copy_from_slice(PREFIX.as_bytes());
+    let len = base64::encode_slice(chunk.as_bytes(), &mut buf[PREFIX.len()..payload_end])
+        .expect("chunks fit in a word");
+    let end = PREFIX.len() + len + SUFFIX.len();
+    buf[PREFIX.len() + len..end].copy_from_slice(SUFFIX.as_bytes());
+    core::str::from_utf8(&buf[..end]).expect("base64 is ASCII")
+}
+
+#[cfg(test)]
+mod tests {
+    use ::base64::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn words_fit_the_limit() {
+        let text = "ä".repeat(100);
+        let mut out = String::new();
+        encode(&text, &mut out).unwrap();
+        for word in out.split("\r\n ") {
+            assert!(word.len() <= MAX_WORD_LEN, "{word}");
+        }
+        let decoded: Vec<u8> = out
+            .split("\r\n ")
+            .flat_map(|word| {
+                let payload = word.strip_prefix(PREFIX).unwrap().strip_suffix(SUFFIX);
+                BASE64_STANDARD.decode(payload.unwrap()).unwrap()
+            })
+            .collect();
+        assert_eq!(decoded, text.as_bytes());
+    }
+
+    #[test]
+    fn char_chunks_match_chunks() {
+        let text = "ää€€€€€€€€€€€€€€ and some more text";
+        let mut char_chunks = CharChunks::new(text.chars());
+        for chunk in chunks(text) {
+            assert_eq!(char_chunks.next_chunk(), Some(chunk));
+        }
+        assert_eq!(char_chunks.next_chunk(), None);
+    }
+
+    #[test]
+    fn detects_what_needs_encoding() {
+        assert!(!needs_encoding("Hello, world"));
+        assert!(needs_encoding("Grüße"));
+        assert!(needs_encoding("line\r\nbreak"));
+        assert!(needs_encoding("=?looks encoded?="));
+    }
+}
@@ -0,0 +1,222 @@
+use core::fmt::{self, Display};
+
+use super::encoded_word::{self, MAX_WORD_LEN};
+use crate::{ReadWrite, transparency::DataWriter};
+
+/// A header field value, written as [encoded words](encoded_word) where it can't be sent
+/// as-is, e.g. a subject with non-ASCII characters.
+///
+/// [`Message`](super::Message) wraps its headers in this, so only values sent some other way
+/// need it explicitly. Encoding doesn't allocate, the words are written one at a time, and
+/// long values are folded between words when sent.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::message::HeaderValue;
+///
+/// assert_eq!(HeaderValue::text("Lunch").to_string(), "Lunch");
+/// assert_eq!(
+///     HeaderValue::mailbox("Jürgen <j@example.com>").to_string(),
+///     "=?UTF-8?B?SsO8cmdlbg==?= <j@example.com>"
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderValue<'a> {
+    value: &'a str,
+    mailbox: bool,
+}
+
+impl<'a> HeaderValue<'a> {
+    /// Unstructured text such as a `Subject`, which is encoded as a whole if needed.
+    pub fn text(value: &'a str) -> Self {
+        HeaderValue {
+            value,
+            mailbox: false,
+        }
+    }
+
+    /// An address with an optional display name, `Name <address>`. Only the display name is
+    /// encoded, as the address must stay readable for the mail servers.
+    /// <https://datatracker.ietf.org/doc/html/rfc2047#section-5>
+    pub fn mailbox(value: &'a str) -> Self {
+        HeaderValue {
+            value,
+            mailbox: true,
+        }
+    }
+
+    pub fn as_str(&self) -> &'a str {
+        self.value
+    }
+
+    // the text to encode, which may be empty, and the rest to write as-is
+    fn parts(&self) -> (&'a str, &'a str) {
+        let value = self.value;
+        if !self.mailbox {
+            return match encoded_word::needs_encoding(value) {
+                true => (value, ""),
+                false => ("", value),
+            };
+        }
+        let Some(start) = value.rfind('<').filter(|_| value.ends_with('>')) else {
+            return ("", value);
+        };
+        let name = value[..start].trim_end();
+        if !encoded_word::needs_encoding(name) {
+            return ("", value);
+        }
+        let unquoted = name
+            .strip_prefix('"')
+            .and_then(|name| name.strip_suffix('"'))
+            .unwrap_or(name);
+        (unquoted, &value[name.len()..])
+    }
+
+    pub(crate) async fn write_to<T: ReadWrite>(
+        &self,
+        line: &mut HeaderLine<'_, '_, T>,
+    ) -> Result<(), T::Error> {
+        let (encoded, rest) = self.parts();
+        if encoded.is_empty() {
+            return line.text(rest).await;
+        }
+        for chunk in encoded_word::chunks(encoded) {
+            let mut buf = [0; MAX_WORD_LEN];
+            line.word(encoded_word::word(chunk, &mut buf).as_bytes())
+                .await?;
+        }
+        if rest.is_empty() {
+            return Ok(());
+        }
+        // the rest of a mailbox starts with the space before the address
+        line.text(rest.strip_prefix(' ').unwrap_or(rest)).await
+    }
+}
+
+// RFC 5322 recommends lines of at most 78 characters, excluding the line break
+// https://datatracker.ietf.org/doc/html/rfc5322#section-2.1.1
+const MAX_LINE_LEN: usize = 78;
+
+/// Writes a header field, folding it between words so lines stay short. A single word
+/// longer than a line is written as-is.
+/// <https://datatracker.ietf.org/doc/html/rfc5322#section-2.2.3>
+pub(crate) struct HeaderLine<'w, 'a, T: ReadWrite> {
+    writer: &'w mut DataWriter<'a, T>,
+    column: usize,
+    // written before the next word, or replaced by the fold
+    separator: &'static [u8],
+}
+
+impl<'w, 'a, T: ReadWrite> HeaderLine<'w, 'a, T> {
+    pub(crate) async fn start(
+        writer: &'w mut DataWriter<'a, T>,
+        name: &str,
+    ) -> Result<Self, T::Error> {
+        writer.write_multi(&[name.as_bytes(), b":"]).await?;
+        Ok(HeaderLine {
+            writer,
+            column: name.len() + 1,
+            separator: b" ",
+        })
+    }
+
+    /// Separates the next word with `separator` instead of a space, e.g. `, ` in a list.
+    pub(crate) fn separate(&mut self, separator: &'static [u8]) {
+        self.separator = separator;
+    }
+
+    pub(crate) async fn word(&mut self, word: &[u8]) -> Result<(), T::Error> {
+        let separator = core::mem::replace(&mut self.separator, b" ");
+        let too_long = self.column + separator.len() + word.len() > MAX_LINE_LEN;
+        if too_long && !word.is_empty() && self.column > 1 {
+            // the whitespace of the separator is where the line is folded
+            self.writer
+                .write_multi(&[separator.trim_ascii_end(), b"\r\n "])
+                .await?;
+            self.column = 1;
+        } else {
+            self.writer.write(separator).await?;
+            self.column += separator.len();
+        }
+        self.writer.write(word).await?;
+        self.column += word.len();
+        Ok(())
+    }
+
+    /// Writes the words of `text`, which may be folded at every space.
+    pub(crate) async fn text(&mut self, text: &str) -> Result<(), T::Error> {
+        for word in text.split(' ') {
+            self.word(word.as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes a formatted value as a single word.
+    pub(crate) async fn display(&mut self, value: impl Display) -> Result<(), T::Error> {
+        let separator = core::mem::replace(&mut self.separator, b" ");
+        self.writer.write(separator).await?;
+        self.column += separator.len() + self.writer.write_display(value).await?;
+        Ok(())
+    }
+
+    /// Writes a value which already contains its folds, tracking only the length of its last
+    /// line.
+    pub(crate) async fn folded(&mut self, value: &str) -> Result<(), T::Error> {
+        let separator = core::mem::replace(&mut self.separator, b" ");
+        self.writer
+            .write_multi(&[separator, value.as_bytes()])
+            .await?;
+        self.column = match value.rfind('\n') {
+            Some(idx) => value.len() - idx - 1,
+            None => self.column + separator.len() + value.len(),
+        };
+        Ok(())
+    }
+
+    pub(crate) async fn finish(self) -> Result<(), T::Error> {
+        self.writer.write(b"\r\n").await
+    }
+}
+
+impl Display for HeaderValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (encoded, rest) = self.parts();
+        encoded_word::encode(encoded, f)?;
+        f.write_str(rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_is_left_alone() {
+        assert_eq!(
+            HeaderValue::text("Hello, world").to_string(),
+            "Hello, world"
+        );
+        let mailbox = "Bob <bob@example.com>";
+        assert_eq!(HeaderValue::mailbox(mailbox).to_string(), mailbox);
+        assert_eq!(
+            HeaderValue::mailbox("bob@example.com").as_str(),
+            "bob@example.com"
+        );
+    }
+
+    #[test]
+    fn encodes_only_display_names() {
+        assert_eq!(
+            HeaderValue::text("Grüße").to_string(),
+            "=?UTF-8?B?R3LDvMOfZQ==?="
+        );
+        assert_eq!(
+            HeaderValue::mailbox("\"Grüße\"  <a@example.com>").to_string(),
+            "=?UTF-8?B?R3LDvMOfZQ==?=  <a@example.com>"
+        );
+        // a non-ASCII address can't be helped by encoding, SMTPUTF8 is needed for that
+        let address = "jürgen@example.com";
+        assert_eq!(HeaderValue::mailbox(address).to_string(), address);
+    }
+}
@@ -0,0 +1,178 @@
+//! Checks for common deliverability problems of a message, see [`Message::lint`].
+//!
+//! None of these make a message invalid, but spam filters and receiving servers often treat
+//! them as signs of a misconfigured or bulk sender.
+//!
+//! [`Message::lint`]: super::Message::lint
+
+use core::fmt::{self, Display};
+
+use crate::address::{Address, Mailbox};
+
+// header lines longer than this, excluding the line break, may be rejected or broken up
+// https://datatracker.ietf.org/doc/html/rfc5322#section-2.1.1
+const MAX_LINE_LEN: usize = 998;
+
+/// A deliverability problem of a [`Message`](super::Message).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lint {
+    /// The message has no date and none is added when sending, e.g. without `std` or a
+    /// [clock](crate::Smtp::set_clock).
+    MissingDate,
+    /// No [`Message-ID`](super::Message::with_message_id), which many receivers expect.
+    MissingMessageId,
+    /// The domain of the `From` address isn't the domain of the envelope sender or one of
+    /// its subdomains, so the message fails DMARC alignment with SPF.
+    /// <https://datatracker.ietf.org/doc/html/rfc7489#section-3.1.2>
+    FromMismatch,
+    /// An HTML body with an empty plain text part.
+    HtmlWithoutText,
+    /// A header value with a word too long to fold into lines of at most 998 characters.
+    LineTooLong,
+    /// A bulk message, one with a `Precedence: bulk` or a `List-Id` header, without a
+    /// `List-Unsubscribe` header.
+    /// <https://datatracker.ietf.org/doc/html/rfc2369#section-3.2>
+    MissingUnsubscribe,
+}
+
+impl Lint {
+    const ALL: [Lint; 6] = [
+        Lint::MissingDate,
+        Lint::MissingMessageId,
+        Lint::FromMismatch,
+        Lint::HtmlWithoutText,
+        Lint::LineTooLong,
+        Lint::MissingUnsubscribe,
+    ];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lint::MissingDate => write!(f, "Message has no Date header"),
+            Lint::MissingMessageId => write!(f, "Message has no Message-ID header"),
+            Lint::FromMismatch => {
+                write!(f, "From domain doesn't match the envelope sender")
+            }
+            Lint::HtmlWithoutText => write!(f, "HTML body without a plain text part"),
+            Lint::LineTooLong => write!(f, "Header line longer than 998 characters"),
+            Lint::MissingUnsubscribe => {
+                write!(f, "Bulk message without a List-Unsubscribe header")
+            }
+        }
+    }
+}
+
+/// The [`Lint`]s found in a message, in the order they are declared in.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::message::{Lint, Message};
+///
+/// let message = Message::new("alice@example.com")
+///     .with_message_id("<1234@example.com>")
+///     .with_html_body("", "<p>Hi!</p>");
+/// let lints = message.lint(None);
+/// assert!(lints.contains(Lint::HtmlWithoutText));
+/// assert!(!lints.contains(Lint::MissingMessageId));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Lints(u8);
+
+impl Lints {
+    pub(crate) fn insert(&mut self, lint: Lint) {
+        self.0 |= lint.bit();
+    }
+
+    pub fn contains(&self, lint: Lint) -> bool {
+        self.0 & lint.bit() != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Lint> {
+        let lints = *self;
+        Lint::ALL
+            .into_iter()
+            .filter(move |lint| lints.contains(*lint))
+    }
+}
+
+// relaxed alignment: the same domain, or one a subdomain of the other
+pub(crate) fn is_aligned(from: &str, envelope_from: &str) -> bool {
+    let (Ok(from), Ok(envelope_from)) = (Mailbox::parse(from), Address::parse(envelope_from))
+    else {
+        // nothing to compare, e.g. the null reverse path of a bounce
+        return true;
+    };
+    let (a, b) = (from.address().domain(), envelope_from.domain());
+    is_subdomain(a, b) || is_subdomain(b, a)
+}
+
+fn is_subdomain(domain: &str, parent: &str) -> bool {
+    let Some(prefix_len) = domain.len().checked_sub(parent.len()) else {
+        return false;
+    };
+    let (prefix, suffix) = domain.split_at_checked(prefix_len).unwrap_or(("", ""));
+    suffix.eq_ignore_ascii_case(parent) && (prefix.is_empty() || prefix.ends_with('.'))
+}
+
+pub(crate) fn is_bulk(name: &str, value: &str) -> bool {
+    name.eq_ignore_ascii_case("List-Id")
+        || (name.eq_ignore_ascii_case("Precedence")
+            && ["bulk", "list"]
+                .iter()
+                .any(|precedence| value.trim().eq_ignore_ascii_case(precedence)))
+}
+
+/// Returns true if a header can't be written in lines of at most 998 characters, as its
+/// value is only folded between words, or at the folds already in it.
+pub(crate) fn is_line_too_long(name: &str, value: &str) -> bool {
+    let mut lines = value.split("\r\n");
+    let first = lines.next().unwrap_or_default();
+    // the first line starts after `name: `
+    let first_too_long = first
+        .split(' ')
+        .any(|word| name.len() + 2 + word.len() > MAX_LINE_LEN);
+    first_too_long || lines.any(|line| line.len() > MAX_LINE_LEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_alignment_and_line_lengths() {
+        assert!(is_aligned(
+            "Alice <alice@example.com>",
+            "bounces@mail.EXAMPLE.com"
+        ));
+        assert!(is_aligned("alice@mail.example.com", "alice@example.com"));
+        assert!(!is_aligned("alice@example.com", "alice@notexample.com"));
+        assert!(!is_aligned("alice@example.com", "alice@example.org"));
+        assert!(is_aligned("alice@example.com", ""));
+
+        assert!(is_bulk("precedence", " Bulk"));
+        assert!(!is_bulk("Precedence", "first-class"));
+
+        let word = "x".repeat(985);
+        assert!(!is_line_too_long("X-Token", &word));
+        assert!(is_line_too_long("X-Much-Longer-Token", &word));
+        let folded = format!("short\r\n {word}{word}");
+        assert!(is_line_too_long("X-Token", &folded));
+
+        let mut lints = Lints::default();
+        assert!(lints.is_empty());
+        lints.insert(Lint::MissingUnsubscribe);
+        lints.insert(Lint::MissingDate);
+        let found: Vec<Lint> = lints.iter().collect();
+        assert_eq!(found, [Lint::MissingDate, Lint::MissingUnsubscribe]);
+    }
+}
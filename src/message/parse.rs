@@ -0,0 +1,357 @@
+use core::fmt::{self, Display};
+
+use super::DateTime;
+use crate::address::{AddressList, Mailbox};
+
+/// A received message, split into its header fields and body, e.g. to relay it, inspect what
+/// a test sent, or read a bounce.
+/// <https://datatracker.ietf.org/doc/html/rfc5322#section-2.1>
+///
+/// Nothing is copied: the fields borrow from the message, and folded values are unfolded
+/// only when they are displayed. The message itself is kept as-is, so
+/// [`as_bytes`](Self::as_bytes) sends it on unchanged.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::message::MessageRef;
+///
+/// let message = MessageRef::parse(
+///     b"From: Alice <alice@example.com>\r\n\
+///       Subject: Lunch\r\n\
+///       \ttomorrow?\r\n\
+///       \r\n\
+///       See you at noon!\r\n",
+/// )
+/// .unwrap();
+/// let from = message.from().unwrap().iter().next().unwrap();
+/// assert_eq!(from.address().as_str(), "alice@example.com");
+/// assert_eq!(message.subject().unwrap().to_string(), "Lunch\ttomorrow?");
+/// assert_eq!(message.body(), b"See you at noon!\r\n");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageRef<'a> {
+    message: &'a [u8],
+    header: &'a str,
+    body: &'a [u8],
+}
+
+/// Why a message couldn't be [parsed](MessageRef::parse).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// The header isn't UTF-8, which internationalized messages are limited to.
+    /// <https://datatracker.ietf.org/doc/html/rfc6532#section-3.2>
+    NotUtf8,
+    /// A header line is neither a field nor the continuation of one.
+    InvalidField,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            ParseError::NotUtf8 => "message header isn't UTF-8",
+            ParseError::InvalidField => "invalid header field",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl core::error::Error for ParseError {}
+
+impl<'a> MessageRef<'a> {
+    /// Splits `message` at the first empty line, with CRLF or LF line endings, and checks the
+    /// fields of the header. A message without an empty line has no body.
+    ///
+    /// The body isn't checked, it may be in any encoding the header declares.
+    pub fn parse(message: &'a [u8]) -> Result<Self, ParseError> {
+        let mut header_len = message.len();
+        let mut body_start = message.len();
+        let mut start = 0;
+        for line in message.split_inclusive(|&b| b == b'\n') {
+            if line == b"\r\n" || line == b"\n" {
+                header_len = start;
+                body_start = start + line.len();
+                break;
+            }
+            start += line.len();
+        }
+        let header =
+            core::str::from_utf8(&message[..header_len]).map_err(|_| ParseError::NotUtf8)?;
+        for (i, line) in header.lines().enumerate() {
+            let valid = match line.starts_with([' ', '\t']) {
+                true => i > 0,
+                false => line
+                    .split_once(':')
+                    .is_some_and(|(name, _)| is_field_name(name)),
+            };
+            if !valid {
+                return Err(ParseError::InvalidField);
+            }
+        }
+        Ok(MessageRef {
+            message,
+            header,
+            body: &message[body_start..],
+        })
+    }
+
+    /// The whole message, as parsed.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.message
+    }
+
+    /// The header fields, without the empty line ending them.
+    pub fn header(&self) -> &'a str {
+        self.header
+    }
+
+    /// Everything after the empty line ending the header.
+    pub fn body(&self) -> &'a [u8] {
+        self.body
+    }
+
+    /// The fields of the header, in order.
+    pub fn fields(&self) -> Fields<'a> {
+        Fields { rest: self.header }
+    }
+
+    /// The first field called `name`, ignoring case.
+    pub fn field(&self, name: &str) -> Option<Field<'a>> {
+        self.fields_named(name).next()
+    }
+
+    /// Every field called `name`, ignoring case, e.g. all `Received` fields.
+    pub fn fields_named<'n>(&self, name: &'n str) -> impl Iterator<Item = Field<'a>> + 'n
+    where
+        'a: 'n,
+    {
+        self.fields()
+            .filter(move |field| field.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The authors from `From`, usually a single mailbox.
+    pub fn from(&self) -> Option<AddressList<'a>> {
+        self.address_list("From")
+    }
+
+    /// The mailbox from `Sender`, set when someone other than the author sent the message.
+    pub fn sender(&self) -> Option<Mailbox<'a>> {
+        Mailbox::parse(self.field("Sender")?.raw_value().trim()).ok()
+    }
+
+    pub fn to(&self) -> Option<AddressList<'a>> {
+        self.address_list("To")
+    }
+
+    pub fn cc(&self) -> Option<AddressList<'a>> {
+        self.address_list("Cc")
+    }
+
+    pub fn reply_to(&self) -> Option<AddressList<'a>> {
+        self.address_list("Reply-To")
+    }
+
+    /// The `Subject`, with encoded words left as they are.
+    pub fn subject(&self) -> Option<Unfolded<'a>> {
+        Some(self.field("Subject")?.value())
+    }
+
+    /// The `Date`, see [`DateTime::parse`] for the accepted forms.
+    pub fn date(&self) -> Option<DateTime> {
+        DateTime::parse(self.field("Date")?.raw_value())
+    }
+
+    /// The `Message-ID`, including the angle brackets.
+    pub fn message_id(&self) -> Option<&'a str> {
+        Some(self.field("Message-ID")?.raw_value().trim())
+    }
+
+    // folding whitespace is whitespace to the address parser, so values aren't unfolded
+    fn address_list(&self, name: &str) -> Option<AddressList<'a>> {
+        AddressList::parse(self.field(name)?.raw_value().trim()).ok()
+    }
+}
+
+// printable ASCII except the colon, optionally followed by whitespace in the obsolete syntax
+// https://datatracker.ietf.org/doc/html/rfc5322#section-3.6.8
+fn is_field_name(name: &str) -> bool {
+    let name = name.trim_end_matches([' ', '\t']);
+    !name.is_empty() && name.bytes().all(|b| (33..=126).contains(&b))
+}
+
+/// A header field of a [`MessageRef`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field<'a> {
+    name: &'a str,
+    value: &'a str,
+    raw: &'a str,
+}
+
+impl<'a> Field<'a> {
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// The whole field as written, from its name to the line break ending it, e.g. to
+    /// canonicalize it for a signature.
+    pub fn as_raw(&self) -> &'a str {
+        self.raw
+    }
+
+    /// The value as written after the colon, including the line breaks of folded lines but
+    /// not the last one.
+    pub fn raw_value(&self) -> &'a str {
+        self.value
+    }
+
+    /// The value with folded lines joined and surrounding whitespace trimmed.
+    pub fn value(&self) -> Unfolded<'a> {
+        Unfolded(self.value.trim())
+    }
+}
+
+/// An iterator over the [fields](MessageRef::fields) of a message.
+#[derive(Debug, Clone)]
+pub struct Fields<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Field<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.rest;
+        let (name, after) = start.split_once(':')?;
+        // the value continues on lines starting with whitespace
+        let mut end = 0;
+        for line in after.split_inclusive('\n') {
+            if end > 0 && !line.starts_with([' ', '\t']) {
+                break;
+            }
+            end += line.len();
+        }
+        let value = &after[..end];
+        self.rest = &after[end..];
+        let value = value.strip_suffix('\n').unwrap_or(value);
+        Some(Field {
+            name: name.trim_end_matches([' ', '\t']),
+            value: value.strip_suffix('\r').unwrap_or(value),
+            raw: &start[..start.len() - self.rest.len()],
+        })
+    }
+}
+
+/// A field value with its folding undone when displayed, by removing the line breaks.
+/// <https://datatracker.ietf.org/doc/html/rfc5322#section-2.2.3>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unfolded<'a>(&'a str);
+
+impl<'a> Unfolded<'a> {
+    /// The value as written, with the line breaks of folded lines.
+    pub fn as_raw(&self) -> &'a str {
+        self.0
+    }
+}
+
+impl Display for Unfolded<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in self.0.split('\n') {
+            f.write_str(line.strip_suffix('\r').unwrap_or(line))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_header_and_body() {
+        let message = MessageRef::parse(b"Subject: hi\n\nbody\n\nmore").unwrap();
+        assert_eq!(message.header(), "Subject: hi\n");
+        assert_eq!(message.body(), b"body\n\nmore");
+
+        let message = MessageRef::parse(b"Subject: hi\r\n").unwrap();
+        assert_eq!(message.body(), b"");
+        let message = MessageRef::parse(b"\r\nbody").unwrap();
+        assert_eq!(message.fields().count(), 0);
+        assert_eq!(message.body(), b"body");
+    }
+
+    #[test]
+    fn fields_are_unfolded() {
+        let message = MessageRef::parse(
+            b"Received: from a.example.com\r\n\tby b.example.com;\r\n \
+              Sun, 07 Dec 2025 12:30:00 +0100\r\n\
+              X-Empty:\r\n\
+              Subject : obsolete\r\n\
+              received: from c.example.com\r\n\r\n",
+        )
+        .unwrap();
+        let fields: Vec<_> = message.fields().collect();
+        assert_eq!(fields.len(), 4);
+        assert_eq!(
+            fields[0].raw_value(),
+            " from a.example.com\r\n\tby b.example.com;\r\n Sun, 07 Dec 2025 12:30:00 +0100"
+        );
+        assert_eq!(
+            fields[0].value().to_string(),
+            "from a.example.com\tby b.example.com; Sun, 07 Dec 2025 12:30:00 +0100"
+        );
+        assert_eq!(fields[1].value().to_string(), "");
+        assert_eq!(fields[2].as_raw(), "Subject : obsolete\r\n");
+        assert_eq!(message.subject().unwrap().to_string(), "obsolete");
+        assert_eq!(message.fields_named("RECEIVED").count(), 2);
+    }
+
+    #[test]
+    fn typed_accessors() {
+        let message = MessageRef::parse(
+            b"From: Alice <alice@example.com>\r\n\
+              Sender: list@example.org\r\n\
+              To: bob@example.com,\r\n Team: carol@example.com;\r\n\
+              Date: Sun, 07 Dec 2025\r\n 12:30:00 +0100\r\n\
+              Message-ID: <1234@example.com>\r\n\
+              Cc: not an address\r\n\r\n",
+        )
+        .unwrap();
+        let to: Vec<_> = message.to().unwrap().iter().collect();
+        assert_eq!(to.len(), 2);
+        assert_eq!(to[1].address().as_str(), "carol@example.com");
+        assert_eq!(
+            message.sender().unwrap().address().as_str(),
+            "list@example.org"
+        );
+        assert_eq!(
+            message.date().unwrap().to_string(),
+            "Sun, 07 Dec 2025 12:30:00 +0100"
+        );
+        assert_eq!(message.message_id(), Some("<1234@example.com>"));
+        assert_eq!(message.cc(), None);
+        assert_eq!(message.reply_to(), None);
+        assert_eq!(message.subject(), None);
+    }
+
+    #[test]
+    fn invalid_headers() {
+        assert_eq!(
+            MessageRef::parse(b" Subject: hi\r\n"),
+            Err(ParseError::InvalidField)
+        );
+        assert_eq!(
+            MessageRef::parse(b"From joe@example.com\r\n"),
+            Err(ParseError::InvalidField)
+        );
+        assert_eq!(
+            MessageRef::parse(b"Sub ject: hi\r\n"),
+            Err(ParseError::InvalidField)
+        );
+        assert_eq!(
+            MessageRef::parse(b"Subject: \xff\r\n"),
+            Err(ParseError::NotUtf8)
+        );
+        // only the header has to be UTF-8
+        assert!(MessageRef::parse(b"Subject: hi\r\n\r\n\xff").is_ok());
+    }
+}
@@ -0,0 +1,155 @@
+//! A short summary of a message, e.g. for a queue listing or a log line, without the whole
+//! body.
+
+use core::fmt::{self, Display, Write};
+
+use super::{DateTime, MailboxList};
+
+/// The key headers of a [`Message`](super::Message) and the start of its plain text, see
+/// [`Message::preview`](super::Message::preview).
+///
+/// Formats as a single line, with the line breaks and runs of whitespace in the subject and
+/// text collapsed to single spaces.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::message::Message;
+///
+/// let message = Message::new("Alice <alice@example.com>")
+///     .with_to(&["bob@example.com"])
+///     .with_subject("Lunch")
+///     .with_text_body("Hungry?\r\n\r\nLet's meet at noon.\r\n");
+/// let preview = message.preview(16);
+/// assert_eq!(preview.text(), "Hungry?\r\n\r\nLet's");
+/// assert!(preview.is_truncated());
+/// assert_eq!(
+///     preview.to_string(),
+///     "Alice <alice@example.com> \"Lunch\": Hungry? Let's…"
+/// );
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Preview<'a> {
+    from: &'a str,
+    to: MailboxList<'a>,
+    subject: Option<&'a str>,
+    date: Option<DateTime>,
+    text: &'a str,
+    truncated: bool,
+    attachments: usize,
+}
+
+impl<'a> Preview<'a> {
+    pub(super) fn new(
+        from: &'a str,
+        to: MailboxList<'a>,
+        subject: Option<&'a str>,
+        date: Option<DateTime>,
+        text: &'a str,
+        max_chars: usize,
+        attachments: usize,
+    ) -> Self {
+        let end = text.char_indices().nth(max_chars).map(|(idx, _)| idx);
+        Preview {
+            from,
+            to,
+            subject,
+            date,
+            text: &text[..end.unwrap_or(text.len())],
+            truncated: end.is_some(),
+            attachments,
+        }
+    }
+
+    pub fn from(&self) -> &'a str {
+        self.from
+    }
+
+    pub fn to(&self) -> MailboxList<'a> {
+        self.to
+    }
+
+    pub fn subject(&self) -> Option<&'a str> {
+        self.subject
+    }
+
+    /// The date the message was given, the one it is sent with may only be set when sending.
+    pub fn date(&self) -> Option<DateTime> {
+        self.date
+    }
+
+    /// The start of the plain text body as written, at most the number of characters the
+    /// preview was made with.
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+
+    /// Returns true if the text was cut short.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn attachments(&self) -> usize {
+        self.attachments
+    }
+}
+
+impl Display for Preview<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.from)?;
+        if let Some(subject) = self.subject {
+            f.write_str(" \"")?;
+            collapse_whitespace(subject, f)?;
+            f.write_char('"')?;
+        }
+        if self.attachments > 0 {
+            write!(f, " [attachments: {}]", self.attachments)?;
+        }
+        f.write_str(": ")?;
+        collapse_whitespace(self.text, f)?;
+        if self.truncated {
+            f.write_char('…')?;
+        }
+        Ok(())
+    }
+}
+
+// writes `text` without leading and trailing whitespace, and every other run of whitespace as
+// a single space
+fn collapse_whitespace(text: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (idx, word) in text.split_whitespace().enumerate() {
+        if idx > 0 {
+            f.write_char(' ')?;
+        }
+        f.write_str(word)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message::{DateTime, Message};
+
+    #[test]
+    fn previews_messages() {
+        let date = DateTime::from_utc(2025, 12, 7, 12, 0, 0).unwrap();
+        let message = Message::new("alice@example.com")
+            .with_subject("  Quarterly\r\n report ")
+            .with_date(date)
+            .with_html_body("Zoë's numbers are in.", "<p>Zoë's numbers are in.</p>")
+            .with_attachment("q4.csv", "text/csv", b"1,2,3\r\n");
+        let preview = message.preview(5);
+        assert_eq!(preview.text(), "Zoë's");
+        assert_eq!(preview.date(), Some(date));
+        assert_eq!(preview.attachments(), 1);
+        assert_eq!(
+            preview.to_string(),
+            "alice@example.com \"Quarterly report\" [attachments: 1]: Zoë's…"
+        );
+
+        let preview = Message::new("alice@example.com").preview(100);
+        assert!(!preview.is_truncated());
+        assert_eq!(preview.subject(), None);
+        assert_eq!(preview.to_string(), "alice@example.com: ");
+    }
+}
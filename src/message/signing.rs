@@ -0,0 +1,127 @@
+use alloc::vec::Vec;
+use core::convert::Infallible;
+
+use super::{Message, Overrides};
+use crate::{
+    ReadWrite,
+    canonicalization::{BodyCanonicalizer, Canonicalization},
+    transparency::DataWriter,
+};
+
+/// Signs a message sent with [`Smtp::send_signed_message`](crate::Smtp::send_signed_message),
+/// e.g. with DKIM.
+///
+/// A DKIM signature covers the body hash, but is sent in front of the message, so the
+/// message is written twice: once to the signer, canonicalized the way it asks for, and then
+/// to the server after the field returned by [`signature`](Self::signature). Neither pass
+/// keeps the message in memory.
+/// <https://datatracker.ietf.org/doc/html/rfc6376#section-5>
+pub trait Signer {
+    fn header_canonicalization(&self) -> Canonicalization;
+
+    fn body_canonicalization(&self) -> Canonicalization;
+
+    /// A canonicalized header field including its line break, in the order they are sent.
+    /// `name` is the field name as written, to pick the fields to sign.
+    fn header(&mut self, name: &str, field: &[u8]);
+
+    /// The next piece of the canonicalized body.
+    fn body(&mut self, chunk: &[u8]);
+
+    /// The complete field to send in front of the message, e.g. `DKIM-Signature: ...`,
+    /// including the final line break. Called once everything else was passed on.
+    fn signature(&mut self) -> Vec<u8>;
+}
+
+/// Writes `message` to `signer` and returns its signature.
+pub(crate) async fn sign(
+    message: &Message<'_>,
+    overrides: &Overrides<'_>,
+    signer: &mut impl Signer,
+) -> Vec<u8> {
+    let mut sink = SignerSink::new(signer);
+    let mut writer = DataWriter::unstuffed(&mut sink);
+    let Ok(()) = message.write_to(&mut writer, overrides).await;
+    let Ok(_) = writer.finish().await;
+    sink.finish()
+}
+
+// splits the written message into header fields and the body
+struct SignerSink<'s, S: Signer> {
+    signer: &'s mut S,
+    // the line being written, while in the header
+    line: Vec<u8>,
+    // the header field being written, complete once a line starts without whitespace
+    field: Vec<u8>,
+    // set once the empty line ending the header was written
+    body: Option<BodyCanonicalizer>,
+}
+
+impl<'s, S: Signer> SignerSink<'s, S> {
+    fn new(signer: &'s mut S) -> Self {
+        SignerSink {
+            signer,
+            line: Vec::new(),
+            field: Vec::new(),
+            body: None,
+        }
+    }
+
+    fn feed(&mut self, mut data: &[u8]) {
+        while self.body.is_none() {
+            let Some(end) = data.iter().position(|&b| b == b'\n') else {
+                self.line.extend_from_slice(data);
+                return;
+            };
+            self.line.extend_from_slice(&data[..=end]);
+            data = &data[end + 1..];
+            let line = core::mem::take(&mut self.line);
+            if line.starts_with(b" ") || line.starts_with(b"\t") {
+                self.field.extend_from_slice(&line);
+                continue;
+            }
+            self.flush_field();
+            if line == b"\r\n" {
+                self.body = Some(BodyCanonicalizer::new(self.signer.body_canonicalization()));
+            } else {
+                self.field = line;
+            }
+        }
+        if let Some(body) = &mut self.body {
+            body.feed(data, |chunk| self.signer.body(chunk));
+        }
+    }
+
+    fn flush_field(&mut self) {
+        if self.field.is_empty() {
+            return;
+        }
+        let field = core::mem::take(&mut self.field);
+        let colon = field.iter().position(|&b| b == b':').unwrap_or(field.len());
+        let name = core::str::from_utf8(&field[..colon]).unwrap_or_default();
+        let canonical = self.signer.header_canonicalization().header(&field);
+        self.signer.header(name.trim_end(), &canonical);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let body = self.body.take().unwrap_or_else(|| {
+            self.flush_field();
+            BodyCanonicalizer::new(self.signer.body_canonicalization())
+        });
+        body.finish(|chunk| self.signer.body(chunk));
+        self.signer.signature()
+    }
+}
+
+impl<S: Signer> ReadWrite for SignerSink<'_, S> {
+    type Error = Infallible;
+
+    async fn read(&mut self, _buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Ok(0)
+    }
+
+    async fn write_single(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        self.feed(buf);
+        Ok(())
+    }
+}
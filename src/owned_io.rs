@@ -0,0 +1,183 @@
+use core::{
+    fmt::{self, Display},
+    ops::Range,
+};
+
+use crate::{Buffer, ReadWrite};
+
+/// A connection which takes ownership of the buffers it reads into and writes from, e.g. a
+/// completion based runtime like io_uring, or a DMA driven peripheral.
+///
+/// Such IO may still use the buffer after the future of an operation was dropped, so it
+/// can't borrow one like [`ReadWrite`] does. The buffer is handed back with the result of
+/// every operation, including failed ones. Every [`ReadWrite`] is also an
+/// `OwnedReadWrite`, and [`OwnedIo`] turns one into a [`ReadWrite`] for [`Smtp`](crate::Smtp).
+///
+/// This only adapts the transport, it doesn't save any copies: [`Smtp`](crate::Smtp) still
+/// reads and writes through its own buffer, which is copied to and from the one of the
+/// [`OwnedIo`]. Lending the session's buffer to the transport on the `DATA` path isn't
+/// supported yet.
+pub trait OwnedReadWrite {
+    type Error: core::error::Error + 'static;
+    /// Reads at least one byte into `buf[range]`, returning 0 only once the connection is
+    /// closed.
+    fn read_into(
+        &mut self,
+        buf: Buffer<'static>,
+        range: Range<usize>,
+    ) -> impl Future<Output = (Buffer<'static>, Result<usize, Self::Error>)>;
+    /// Writes all of `buf[range]`.
+    fn write_from(
+        &mut self,
+        buf: Buffer<'static>,
+        range: Range<usize>,
+    ) -> impl Future<Output = (Buffer<'static>, Result<(), Self::Error>)>;
+    fn flush(&mut self) -> impl Future<Output = Result<(), Self::Error>> {
+        async { Ok(()) }
+    }
+}
+
+impl<T: ReadWrite<Error: 'static>> OwnedReadWrite for T {
+    type Error = T::Error;
+
+    async fn read_into(
+        &mut self,
+        mut buf: Buffer<'static>,
+        range: Range<usize>,
+    ) -> (Buffer<'static>, Result<usize, Self::Error>) {
+        let result = self.read(&mut buf[range]).await;
+        (buf, result)
+    }
+
+    async fn write_from(
+        &mut self,
+        buf: Buffer<'static>,
+        range: Range<usize>,
+    ) -> (Buffer<'static>, Result<(), Self::Error>) {
+        let result = self.write_single(&buf[range]).await;
+        (buf, result)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        ReadWrite::flush(self).await
+    }
+}
+
+/// A [`ReadWrite`] for an [`OwnedReadWrite`], which lends it a buffer of its own.
+///
+/// Every byte read or written is copied between the session and that buffer, one copy more
+/// than a [`ReadWrite`] transport needs. The slices of
+/// [`write_multi`](ReadWrite::write_multi) are gathered into it, so they are sent with as
+/// few writes as the buffer allows.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::{OwnedIo, Smtp, transport::MemoryTransport};
+///
+/// // e.g. a buffer set aside for a DMA peripheral
+/// let buffer: &'static mut [u8] = Box::leak(Box::new([0; 512]));
+/// let smtp = Smtp::new(OwnedIo::new_with_buffer(MemoryTransport::new(), buffer));
+/// ```
+pub struct OwnedIo<T: OwnedReadWrite> {
+    io: T,
+    // `None` while an operation owns it, and after its future was dropped
+    buf: Option<Buffer<'static>>,
+}
+
+#[cfg(feature = "alloc")]
+impl<T: OwnedReadWrite> OwnedIo<T> {
+    pub fn new(io: T) -> Self {
+        Self::new_with_buffer(io, alloc::vec![0; 4096])
+    }
+}
+
+impl<T: OwnedReadWrite> OwnedIo<T> {
+    /// # Panics
+    ///
+    /// If `buf` is empty.
+    pub fn new_with_buffer(io: T, buf: impl Into<Buffer<'static>>) -> Self {
+        let buf = buf.into();
+        assert!(!buf.is_empty(), "empty IO buffer");
+        OwnedIo { io, buf: Some(buf) }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+
+    fn take_buf(&mut self) -> Result<Buffer<'static>, OwnedIoError<T::Error>> {
+        self.buf.take().ok_or(OwnedIoError::BufferLost)
+    }
+}
+
+impl<T: OwnedReadWrite> ReadWrite for OwnedIo<T> {
+    type Error = OwnedIoError<T::Error>;
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let owned = self.take_buf()?;
+        let len = buf.len().min(owned.len());
+        let (owned, result) = self.io.read_into(owned, 0..len).await;
+        let read = self.buf.insert(owned);
+        let n = result.map_err(OwnedIoError::Io)?;
+        buf[..n].copy_from_slice(&read[..n]);
+        Ok(n)
+    }
+
+    async fn write_single(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        self.write_multi(&[buf]).await
+    }
+
+    async fn write_multi(&mut self, buf: &[&[u8]]) -> Result<(), Self::Error> {
+        let mut owned = self.take_buf()?;
+        let mut filled = 0;
+        let mut result = Ok(());
+        for mut part in buf.iter().copied() {
+            while !part.is_empty() && result.is_ok() {
+                if filled == owned.len() {
+                    (owned, result) = self.io.write_from(owned, 0..filled).await;
+                    filled = 0;
+                }
+                let len = part.len().min(owned.len() - filled);
+                owned[filled..filled + len].copy_from_slice(&part[..len]);
+                filled += len;
+                part = &part[len..];
+            }
+        }
+        if filled > 0 && result.is_ok() {
+            (owned, result) = self.io.write_from(owned, 0..filled).await;
+        }
+        self.buf = Some(owned);
+        result.map_err(OwnedIoError::Io)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.io.flush().await.map_err(OwnedIoError::Io)
+    }
+}
+
+#[derive(Debug)]
+pub enum OwnedIoError<E> {
+    Io(E),
+    /// An earlier operation was dropped while the connection owned the buffer, so there is
+    /// none left to read or write with.
+    BufferLost,
+}
+
+impl<E: Display> Display for OwnedIoError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OwnedIoError::Io(e) => e.fmt(f),
+            OwnedIoError::BufferLost => write!(f, "IO buffer lost to a dropped operation"),
+        }
+    }
+}
+
+impl<E: core::error::Error + 'static> core::error::Error for OwnedIoError<E> {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            OwnedIoError::Io(e) => Some(e),
+            OwnedIoError::BufferLost => None,
+        }
+    }
+}
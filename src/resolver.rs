@@ -0,0 +1,73 @@
+//! Finding the hosts which accept mail for a domain, for delivery from server to server.
+//! <https://datatracker.ietf.org/doc/html/rfc5321#section-5.1>
+//!
+//! The DNS queries are left to a [`Resolver`], so embedded targets can use their own DNS
+//! client. With the `dns-hickory` feature, [`HickoryResolver`] queries the system's resolvers
+//! with tokio.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # #[cfg(feature = "dns-hickory")]
+//! # async fn example() -> anyhow::Result<()> {
+//! use simple_smtp::resolver::{HickoryResolver, lookup_mx};
+//!
+//! let resolver = HickoryResolver::from_system_conf()?;
+//! for (preference, host) in lookup_mx(&resolver, "example.com").await? {
+//!     println!("{preference} {host}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use alloc::{borrow::ToOwned, string::String, vec, vec::Vec};
+
+#[cfg(feature = "dns-hickory")]
+mod hickory;
+#[cfg(feature = "dns-hickory")]
+pub use hickory::HickoryResolver;
+
+/// The DNS queries [`lookup_mx`] needs.
+pub trait Resolver {
+    type Error;
+
+    /// The preference and host of every MX record of `domain`, in any order.
+    ///
+    /// No records, including for a domain that doesn't exist, is an empty list and not an
+    /// error.
+    fn mx(&self, domain: &str) -> impl Future<Output = Result<Vec<(u16, String)>, Self::Error>>;
+
+    /// Returns true if `domain` has an A or AAAA record.
+    fn has_address(&self, domain: &str) -> impl Future<Output = Result<bool, Self::Error>>;
+}
+
+/// The hosts accepting mail for `domain` with their preference, most preferred first.
+///
+/// A domain without MX records but with an address is its own mail host, with preference 0.
+/// Hosts with the same preference keep the order the resolver returned them in. An empty list
+/// means the domain accepts no mail: it has no MX records and no address, or a null MX.
+/// <https://datatracker.ietf.org/doc/html/rfc7505>
+pub async fn lookup_mx<R: Resolver>(
+    resolver: &R,
+    domain: &str,
+) -> Result<Vec<(u16, String)>, R::Error> {
+    let mut records = resolver.mx(domain).await?;
+    if records.is_empty() {
+        return Ok(if resolver.has_address(domain).await? {
+            vec![(0, domain.strip_suffix('.').unwrap_or(domain).to_owned())]
+        } else {
+            Vec::new()
+        });
+    }
+    for (_, host) in &mut records {
+        if host.ends_with('.') {
+            host.pop();
+        }
+    }
+    // a null MX is the only record, its host is the root "."
+    if records.iter().any(|(_, host)| host.is_empty()) {
+        return Ok(Vec::new());
+    }
+    records.sort_by_key(|&(preference, _)| preference);
+    Ok(records)
+}
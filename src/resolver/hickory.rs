@@ -0,0 +1,47 @@
+use std::io;
+
+use hickory_resolver::{ResolveError, TokioResolver};
+
+use super::Resolver;
+
+/// A [`Resolver`] querying DNS servers with hickory on tokio.
+#[derive(Debug, Clone)]
+pub struct HickoryResolver(pub TokioResolver);
+
+impl HickoryResolver {
+    /// Queries the DNS servers of the system, from `/etc/resolv.conf` on Unix or the registry
+    /// on Windows.
+    pub fn from_system_conf() -> io::Result<Self> {
+        let builder = TokioResolver::builder_tokio().map_err(io::Error::other)?;
+        Ok(HickoryResolver(builder.build()))
+    }
+}
+
+impl From<TokioResolver> for HickoryResolver {
+    fn from(resolver: TokioResolver) -> Self {
+        HickoryResolver(resolver)
+    }
+}
+
+impl Resolver for HickoryResolver {
+    type Error = ResolveError;
+
+    async fn mx(&self, domain: &str) -> Result<Vec<(u16, String)>, ResolveError> {
+        match self.0.mx_lookup(domain).await {
+            Ok(lookup) => Ok(lookup
+                .iter()
+                .map(|mx| (mx.preference(), mx.exchange().to_ascii()))
+                .collect()),
+            Err(e) if e.is_no_records_found() => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn has_address(&self, domain: &str) -> Result<bool, ResolveError> {
+        match self.0.lookup_ip(domain).await {
+            Ok(lookup) => Ok(lookup.iter().next().is_some()),
+            Err(e) if e.is_no_records_found() => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
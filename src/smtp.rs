@@ -1,39 +1,202 @@
 use core::{
     fmt::Display,
+    future::poll_fn,
     ops::{Deref, Range},
+    pin::pin,
+    task::Poll,
+    time::Duration,
 };
 
-use super::{Error, MalformedError};
-use crate::{Buffer, ReadWrite};
+use super::{Error, MalformedError, Operation, ProtocolError};
+#[cfg(feature = "alloc")]
+use crate::message::Signer;
+use crate::{
+    AsyncBodySource, Buffer, ReadWrite, Timer,
+    address::Mailbox,
+    encoding::base64,
+    envelope::{
+        BodyType, Envelope, MAX_ENVID_LEN, MailParameters, RcptParameters, Recipient, xtext_len,
+    },
+    fmt_stream::FmtToStream,
+    message::{Clock, Message, Overrides},
+    transparency::DataWriter,
+};
+
+pub mod auth;
+use auth::{AuthMode, AuthStep};
+#[cfg(feature = "alloc")]
+use auth::{CredentialProvider, Credentials};
+pub mod capabilities;
+use capabilities::{AuthMechanism, Capabilities};
+pub mod code;
+pub use code::ReplyCode;
+pub mod enhanced;
+use enhanced::EnhancedCode;
+pub mod negotiation;
+use negotiation::{DesiredFeatures, NegotiationReport};
+pub mod retry;
+pub mod timeouts;
+pub use timeouts::Timeouts;
+pub mod history;
+use history::ErrorReport;
+pub use history::ReplyHistory;
 
 #[derive(Debug)]
 pub struct ReplyLine<'a> {
-    code: u16,
+    code: ReplyCode,
     is_last: bool,
-    message: &'a str,
+    // checked for UTF-8 when accessed, not when parsed
+    message: &'a [u8],
 }
 
 impl<'a> ReplyLine<'a> {
-    pub fn code(&self) -> u16 {
+    pub fn code(&self) -> ReplyCode {
         self.code
     }
     pub fn is_last(&self) -> bool {
         self.is_last
     }
-    pub fn message(&self) -> &'a str {
+    /// The text of the line, or [`MalformedError::InvalidEncoding`] if it isn't valid UTF-8.
+    pub fn message(&self) -> Result<&'a str, MalformedError> {
+        core::str::from_utf8(self.message).map_err(|_| MalformedError::InvalidEncoding)
+    }
+
+    /// The text of the line as sent by the server, which may not be valid UTF-8.
+    pub fn message_bytes(&self) -> &'a [u8] {
         self.message
     }
+
+    /// The enhanced status code at the start of the message, if any.
+    ///
+    /// Servers only send these after advertising [`Extensions::EnhancedStatusCodes`],
+    /// but it is safe to call on any reply.
+    /// <https://datatracker.ietf.org/doc/html/rfc2034#section-4>
+    pub fn enhanced_code(&self) -> Option<EnhancedCode> {
+        EnhancedCode::parse_prefix(valid_prefix(self.message)).map(|(code, _)| code)
+    }
+
+    /// Parses a single reply line, e.g. `250-SIZE 10485760`.
+    ///
+    /// A trailing `\r\n` is optional, but any other CR or LF in the line is rejected.
+    /// This uses the same grammar [`Smtp`] uses for replies on a live session, so it can be
+    /// used to parse logs or replies relayed through a proxy.
+    /// <https://datatracker.ietf.org/doc/html/rfc5321#section-4.2>
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use simple_smtp::smtp::ReplyLine;
+    ///
+    /// let line = ReplyLine::parse("250-mail.example.com\r\n").unwrap();
+    /// assert_eq!(line.code(), 250);
+    /// assert!(!line.is_last());
+    /// assert_eq!(line.message().unwrap(), "mail.example.com");
+    /// ```
+    pub fn parse(line: &'a str) -> Result<ReplyLine<'a>, MalformedError> {
+        let line = line.strip_suffix("\r\n").unwrap_or(line);
+        let (header, message) = line
+            .as_bytes()
+            .split_first_chunk::<4>()
+            .ok_or(MalformedError::NoCode)?;
+        let (code, is_last) = parse_line_header(header)?;
+        if find_line_terminator(message)?.is_some() || message.ends_with(b"\r") {
+            return Err(MalformedError::InvalidLineTermination);
+        }
+        Ok(ReplyLine {
+            code,
+            is_last,
+            message,
+        })
+    }
+}
+
+/// Parses the reply code and continuation marker which start every reply line.
+/// <https://datatracker.ietf.org/doc/html/rfc5321#section-4.2>
+fn parse_line_header(header: &[u8; 4]) -> Result<(ReplyCode, bool), MalformedError> {
+    let [code @ .., separator] = header;
+    if !code.iter().all(u8::is_ascii_digit) {
+        return Err(MalformedError::NoCode);
+    }
+    let code = code
+        .iter()
+        .fold(0u16, |acc, digit| acc * 10 + u16::from(digit - b'0'));
+    let is_last = match separator {
+        b' ' => true,
+        b'-' => false,
+        //todo: wrong error message
+        _ => return Err(MalformedError::InvalidEncoding),
+    };
+    Ok((ReplyCode::new(code), is_last))
+}
+
+/// Finds the end of a reply in the bytes skipped by `Smtp::skip_reply`.
+#[derive(Default)]
+struct ReplyEnd {
+    // the position within the current line
+    column: usize,
+    is_last: bool,
+    after_cr: bool,
+}
+
+impl ReplyEnd {
+    // returns the number of bytes up to and including the end of the reply, if it's in `bytes`
+    fn find(&mut self, bytes: &[u8]) -> Option<usize> {
+        for (idx, &byte) in bytes.iter().enumerate() {
+            if self.after_cr && byte == b'\n' {
+                if self.is_last {
+                    return Some(idx + 1);
+                }
+                self.column = 0;
+                self.after_cr = false;
+                continue;
+            }
+            if self.column == 3 {
+                self.is_last = byte == b' ';
+            }
+            self.after_cr = byte == b'\r';
+            self.column += 1;
+        }
+        None
+    }
+}
+
+/// Returns the length of the line at the start of `buf`, excluding its `\r\n` terminator,
+/// or `None` if the line isn't complete yet.
+///
+/// Bare CR or LF are rejected.
+/// <https://datatracker.ietf.org/doc/html/rfc5321#section-2.3.8>
+fn find_line_terminator(buf: &[u8]) -> Result<Option<usize>, MalformedError> {
+    let mut iter = buf.iter().enumerate();
+    while let Some((idx, char)) = iter.next() {
+        if *char == b'\r' {
+            return match iter.next() {
+                Some((_, b'\n')) => Ok(Some(idx)),
+                Some(_) => Err(MalformedError::InvalidLineTermination),
+                None => Ok(None),
+            };
+        }
+        if *char == b'\n' {
+            return Err(MalformedError::InvalidLineTermination);
+        }
+    }
+    Ok(None)
+}
+
+// the longest prefix of `bytes` which is valid UTF-8
+fn valid_prefix(bytes: &[u8]) -> &str {
+    bytes.utf8_chunks().next().map_or("", |chunk| chunk.valid())
 }
 
 impl Display for ReplyLine<'_> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(
-            f,
-            "{}{}{}",
-            self.code,
-            if self.is_last { ' ' } else { '-' },
-            self.message
-        )
+        write!(f, "{}{}", self.code, if self.is_last { ' ' } else { '-' })?;
+        for chunk in self.message.utf8_chunks() {
+            f.write_str(chunk.valid())?;
+            if !chunk.invalid().is_empty() {
+                write!(f, "{}", char::REPLACEMENT_CHARACTER)?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -45,9 +208,12 @@ impl Display for ReplyLine<'_> {
 // and replace the \r\n with the size of the next line
 // that way we don't have to reparse the codes and we can just use the size of the line to iterate instead of
 // finding the \r\n. And we could use the last 16 bits to store the total line count
+//
+// the text of a line is only checked for UTF-8 when it's accessed, so a reply which is
+// only matched on its code is never checked at all
 #[derive(Copy, Clone)]
 pub struct Reply<'a> {
-    code: u16,
+    code: ReplyCode,
     message_len: u16,
     remaining_buffer: &'a [u8],
 }
@@ -59,67 +225,206 @@ impl<'a> Iterator for Reply<'a> {
         if self.remaining_buffer.is_empty() {
             return None;
         }
-        let (this, next) = self.remaining_buffer.split_at(self.message_len as usize);
-        if next.len() < 6 {
-            self.remaining_buffer = &[];
-            self.message_len = 0;
-        } else {
-            // after our message, we first have the required \r\n line terminator
-            // then we have 4 bytes for the next code and continuation marker, then the real message starts
-            self.remaining_buffer = &next[6..];
-            // but we sneakily stored the length of the next message in the two bytes
-            // directly preceding it
-            self.message_len = u16::from_ne_bytes([next[4], next[5]]);
+        // lines which aren't valid UTF-8 are skipped, the ones after them may still be useful
+        while !self.remaining_buffer.is_empty() {
+            let this = self.current_bytes().ok()?;
+            self.skip_line(this.len());
+            if let Ok(line) = core::str::from_utf8(this) {
+                return Some(line);
+            }
         }
-        Some(core::str::from_utf8(this).expect("should already be validated as utf-8"))
+        None
     }
 }
 
 impl<'a> Reply<'a> {
-    pub fn code(&self) -> u16 {
+    pub fn code(&self) -> ReplyCode {
         self.code
     }
 
+    /// The text of the lines, skipping the ones which aren't valid UTF-8, see
+    /// [`is_utf8`](Self::is_utf8) and [`replies`](Self::replies).
     pub fn lines(&self) -> impl Iterator<Item = &str> {
         *self
     }
 
+    /// Returns true if the text of every line is valid UTF-8.
+    ///
+    /// Replies aren't checked when they are read, but when their text is accessed.
+    pub fn is_utf8(&self) -> bool {
+        self.replies().all(|line| line.message().is_ok())
+    }
+
+    /// The enhanced status code of the reply, taken from its first line.
+    /// <https://datatracker.ietf.org/doc/html/rfc2034#section-4>
+    pub fn enhanced_code(&self) -> Option<EnhancedCode> {
+        let line = self.current_bytes().unwrap_or_default();
+        EnhancedCode::parse_prefix(valid_prefix(line)).map(|(code, _)| code)
+    }
+
+    /// The lines of the reply, including those which aren't valid UTF-8.
     pub fn replies(&self) -> impl Iterator<Item = ReplyLine<'_>> {
-        // let n_lines = self.lines().count();
-        let end = self.remaining_buffer.as_ptr_range().end as usize;
-        self.map(move |line| {
-            let my_end = line.as_bytes().as_ptr_range().end as usize;
-            ReplyLine {
-                code: self.code,
-                is_last: end - my_end < 2,
-                message: line,
+        let mut rest = *self;
+        core::iter::from_fn(move || {
+            if rest.remaining_buffer.is_empty() {
+                return None;
             }
+            let message = rest.current_bytes().ok()?;
+            rest.skip_line(message.len());
+            Some(ReplyLine {
+                code: rest.code,
+                is_last: rest.remaining_buffer.is_empty(),
+                message,
+            })
         })
     }
 
-    fn from_buffer(buffer: &[u8]) -> Reply<'_> {
-        if buffer.len() < 4 {
-            panic!("Buffer too small");
+    /// Parses a complete, possibly multi-line, reply.
+    ///
+    /// `buffer` must contain exactly one reply, with every line terminated by `\r\n`.
+    /// The reply is parsed in place: the line terminators and continuation markers in `buffer`
+    /// are overwritten with bookkeeping data, which is why this takes a mutable slice.
+    /// <https://datatracker.ietf.org/doc/html/rfc5321#section-4.2>
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use simple_smtp::smtp::Reply;
+    ///
+    /// let mut raw = *b"250-mail.example.com\r\n250 STARTTLS\r\n";
+    /// let reply = Reply::parse(&mut raw).unwrap();
+    /// assert_eq!(reply.code(), 250);
+    /// assert_eq!(
+    ///     reply.lines().collect::<Vec<_>>(),
+    ///     ["mail.example.com", "STARTTLS"]
+    /// );
+    /// ```
+    pub fn parse(buffer: &mut [u8]) -> Result<Reply<'_>, MalformedError> {
+        let mut code = None;
+        let mut line_start = 0;
+        loop {
+            let header = buffer
+                .get(line_start..line_start + 4)
+                .and_then(|h| h.first_chunk::<4>())
+                .ok_or(MalformedError::UnexpectedEof)?;
+            let (line_code, is_last) = parse_line_header(header)?;
+            match code {
+                None => code = Some(line_code),
+                Some(old_code) if old_code != line_code => {
+                    return Err(MalformedError::CodeChanged {
+                        old_code,
+                        new_code: line_code,
+                    });
+                }
+                Some(_) => {}
+            }
+            let message_start = line_start + 4;
+            let message_len = find_line_terminator(&buffer[message_start..])?
+                .ok_or(MalformedError::UnexpectedEof)?;
+            let message_len =
+                u16::try_from(message_len).map_err(|_| MalformedError::LineTooLong)?;
+            // same layout as `Smtp::read_line`, see the comment on `Reply`
+            buffer[message_start - 2..message_start].copy_from_slice(&message_len.to_ne_bytes());
+            line_start = message_start + message_len as usize + 2;
+            if is_last {
+                break;
+            }
         }
-        let code = u16::from_ne_bytes([buffer[0], buffer[1]]);
-        let message_len = u16::from_ne_bytes([buffer[2], buffer[3]]);
-        let remaining_buffer = &buffer[4..];
-        if remaining_buffer.len() < message_len as usize {
-            panic!("Buffer too small");
+        if line_start != buffer.len() {
+            return Err(MalformedError::TrailingData);
         }
-        Reply {
-            code,
-            message_len,
+        let code = code.ok_or(MalformedError::NoCode)?;
+        buffer[0..2].copy_from_slice(&code.as_u16().to_ne_bytes());
+        // leave out the final \r\n, like `Smtp::read_multiline_reply`
+        Reply::from_buffer(&buffer[..line_start - 2])
+    }
+
+    // `buffer` is laid out as described on `Reply`, which the server can't be trusted to
+    // have sent, so this checks the first line instead of panicking on a broken one
+    fn from_buffer(buffer: &[u8]) -> Result<Reply<'_>, MalformedError> {
+        let Some(([code_0, code_1, len_0, len_1], remaining_buffer)) = buffer.split_first_chunk()
+        else {
+            return Err(MalformedError::UnexpectedEof);
+        };
+        let reply = Reply {
+            code: ReplyCode::new(u16::from_ne_bytes([*code_0, *code_1])),
+            message_len: u16::from_ne_bytes([*len_0, *len_1]),
             remaining_buffer,
-        }
+        };
+        reply.current_bytes()?;
+        Ok(reply)
+    }
+
+    /// The line the reply is at, the first one unless it has been iterated over. Empty if it
+    /// isn't valid UTF-8.
+    pub fn current_line(&self) -> &'a str {
+        self.try_current_line().unwrap_or_default()
+    }
+
+    fn current_bytes(&self) -> Result<&'a [u8], MalformedError> {
+        self.remaining_buffer
+            .get(..self.message_len as usize)
+            .ok_or(MalformedError::UnexpectedEof)
+    }
+
+    fn try_current_line(&self) -> Result<&'a str, MalformedError> {
+        core::str::from_utf8(self.current_bytes()?).map_err(|_| MalformedError::InvalidEncoding)
     }
 
-    pub fn current_line(self) -> &'a str {
-        core::str::from_utf8(&self.remaining_buffer[..self.message_len as usize])
-            .expect("should already be validated as utf-8")
+    fn skip_line(&mut self, len: usize) {
+        let next = &self.remaining_buffer[len..];
+        if next.len() < 6 {
+            self.remaining_buffer = &[];
+            self.message_len = 0;
+        } else {
+            // after our message, we first have the required \r\n line terminator
+            // then we have 4 bytes for the next code and continuation marker, then the real message starts
+            self.remaining_buffer = &next[6..];
+            // but we sneakily stored the length of the next message in the two bytes
+            // directly preceding it
+            self.message_len = u16::from_ne_bytes([next[4], next[5]]);
+        }
     }
 }
 
+/// How a message was sent by [`Smtp::send_envelope_reauthenticating`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendOutcome {
+    Sent,
+    /// Sent after the server dropped the authentication of the session, and the client
+    /// authenticated again.
+    Reauthenticated,
+}
+
+/// The protocol a session speaks, see [`Smtp::set_protocol`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protocol {
+    #[default]
+    Smtp,
+    /// LMTP, for delivering into a mail store like Dovecot or Cyrus, usually over a local
+    /// socket. The server is greeted with `LHLO`, and reports the delivery to each accepted
+    /// recipient separately after the data.
+    /// <https://datatracker.ietf.org/doc/html/rfc2033>
+    Lmtp,
+}
+
+/// Where a session is in the command sequence.
+///
+/// Commands sent out of order are rejected locally with a [`ProtocolError`], instead of
+/// leaking credentials or mail before the session is set up properly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    /// The server hasn't been greeted with EHLO or HELO since the connection, or the TLS
+    /// upgrade, was established.
+    NotGreeted,
+    /// The server was greeted, so AUTH and mail transactions are possible.
+    Greeted,
+    /// The server accepted STARTTLS. Nothing may be sent until the stream is upgraded, after
+    /// which the client must forget what it learned before and greet the server again.
+    /// <https://datatracker.ietf.org/doc/html/rfc3207#section-4.2>
+    TlsPending,
+}
+
 pub struct Smtp<'a, T: ReadWrite> {
     // the underlying stream, e.g. TcpStream or TlsStream
     stream: T,
@@ -129,29 +434,179 @@ pub struct Smtp<'a, T: ReadWrite> {
     // filled: usize,
     // the range of the buffer which has not been processed yet
     buf_unprocessed: Range<usize>,
+    // the server was greeted with HELO instead of EHLO, so no extensions are available
+    legacy: bool,
+    // the session relies on the TLS client certificate instead of AUTH
+    client_cert_auth: bool,
+    // AUTH succeeded, or the client certificate is relied on instead
+    authenticated: bool,
+    // the server challenged the client during AUTH and waits for the response
+    auth_challenged: bool,
+    state: SessionState,
+    // what the server advertised in the last EHLO response of this session
+    capabilities: Capabilities,
+    // the capabilities when AUTH succeeded, and those of the first EHLO after it
+    pre_auth_capabilities: Option<Capabilities>,
+    post_auth_capabilities: Option<Capabilities>,
+    // stop mail transactions before DATA
+    dry_run: bool,
+    // refuse messages with lints
+    strict_messages: bool,
+    protocol: Protocol,
+    // the recipients accepted in the current transaction, each of which gets a reply to the
+    // data in LMTP
+    accepted_recipients: usize,
+    // the stream is encrypted, so credentials can be sent
+    secure: bool,
+    // send credentials even if the stream isn't encrypted
+    plaintext_auth: bool,
+    // compared with the capabilities after every EHLO
+    desired: Option<DesiredFeatures<'static>>,
+    negotiation: Option<NegotiationReport>,
+    // the time of messages without a date, the system time if not set
+    clock: Option<&'a (dyn Clock + Sync)>,
+    // bounds the wait for every reply, which is unbounded without it
+    timer: Option<SessionTimer<'a>>,
+    timeouts: Timeouts,
+    // the timeout of the next reply, if not `Timeouts::command`
+    reply_timeout: Option<Duration>,
+    history: Option<ReplyHistory<'a>>,
+    // a command was written but its reply wasn't read completely, e.g. because the future
+    // sending it was dropped, so the next reply read would be a stale one
+    reply_pending: bool,
+    // the size an owned buffer may grow to for long replies
+    max_buffer_size: usize,
+}
+
+// owned by sessions with an owned buffer, which can't borrow anything for `'static`
+enum SessionTimer<'a> {
+    #[cfg(feature = "alloc")]
+    Owned(alloc::boxed::Box<dyn Timer + Send + 'a>),
+    // a `'static` timer type, as a shorter lifetime would make the session invariant
+    Borrowed(&'a mut (dyn Timer + Send + 'static)),
+}
+
+impl SessionTimer<'_> {
+    fn get(&mut self) -> &mut (dyn Timer + Send) {
+        match self {
+            #[cfg(feature = "alloc")]
+            SessionTimer::Owned(timer) => timer.as_mut(),
+            SessionTimer::Borrowed(timer) => *timer,
+        }
+    }
+}
+
+// polls `future` until it completes, or returns `None` once `timer` elapsed
+async fn within<F: Future>(timer: &mut (dyn Timer + Send), future: F) -> Option<F::Output> {
+    let mut future = pin!(future);
+    poll_fn(|cx| match future.as_mut().poll(cx) {
+        Poll::Ready(output) => Poll::Ready(Some(output)),
+        Poll::Pending => timer.poll_elapsed(cx).map(|()| None),
+    })
+    .await
+}
+
+/// A session between STARTTLS and the TLS handshake, see [`Smtp::into_upgrade`].
+pub struct TlsUpgrade<'a> {
+    buf: Buffer<'a>,
+    legacy: bool,
+    dry_run: bool,
+    strict_messages: bool,
+    protocol: Protocol,
+    plaintext_auth: bool,
+    desired: Option<DesiredFeatures<'static>>,
+    clock: Option<&'a (dyn Clock + Sync)>,
+    timer: Option<SessionTimer<'a>>,
+    timeouts: Timeouts,
+    history: Option<ReplyHistory<'a>>,
+    max_buffer_size: usize,
+}
+
+impl<'a> TlsUpgrade<'a> {
+    /// Continues the session on the encrypted `stream`, which is marked as
+    /// [secure](Smtp::is_secure). The server has to be greeted again, as it forgets what was
+    /// negotiated before the upgrade.
+    pub fn finish<T: ReadWrite<Error = impl core::error::Error>>(self, stream: T) -> Smtp<'a, T> {
+        let mut smtp = Smtp::new_with_buffer(stream, self.buf);
+        smtp.legacy = self.legacy;
+        smtp.dry_run = self.dry_run;
+        smtp.strict_messages = self.strict_messages;
+        smtp.protocol = self.protocol;
+        smtp.plaintext_auth = self.plaintext_auth;
+        smtp.desired = self.desired;
+        smtp.clock = self.clock;
+        smtp.timer = self.timer;
+        smtp.timeouts = self.timeouts;
+        smtp.history = self.history;
+        smtp.max_buffer_size = self.max_buffer_size;
+        smtp.secure = true;
+        smtp
+    }
 }
 
+// enough for any reasonable EHLO response, while a broken server can't exhaust the memory
+const DEFAULT_MAX_BUFFER_SIZE: usize = 64 * 1024;
+
 #[cfg(feature = "alloc")]
 impl<T: ReadWrite<Error = impl core::error::Error>> Smtp<'static, T> {
     pub fn new(stream: T) -> Self {
-        Self::new_with_buffer(stream, vec![0; 1024])
+        Self::new_with_buffer(stream, alloc::vec![0; 1024])
     }
 }
 
 impl<'buffer, T: ReadWrite<Error = impl core::error::Error>> Smtp<'buffer, T> {
     async fn fill_buffer(&mut self) -> Result<(), Error<T::Error>> {
         let start_from = self.buf_unprocessed.end;
-        let n_bytes = self
-            .stream
-            .read(&mut self.buf[start_from..])
-            .await
-            .map_err(Error::IoError)?;
+        if start_from == self.buf.len() && !self.buf.grow(self.max_buffer_size) {
+            return Err(self.skip_reply().await);
+        }
+        self.buf_unprocessed.end += self.read_into(start_from).await?;
+        Ok(())
+    }
+
+    // reads whatever the server sent into the buffer from `start`, within the timeout
+    async fn read_into(&mut self, start: usize) -> Result<usize, Error<T::Error>> {
+        let read = self.stream.read(&mut self.buf[start..]);
+        let n_bytes = match &mut self.timer {
+            Some(timer) => within(timer.get(), read).await.ok_or(Error::Timeout)?,
+            None => read.await,
+        }
+        .map_err(Error::IoError)?;
 
         if n_bytes == 0 {
             return Err(MalformedError::UnexpectedEof.into());
         }
-        self.buf_unprocessed.end += n_bytes;
-        Ok(())
+        Ok(n_bytes)
+    }
+
+    // reads and drops the rest of a reply which doesn't fit into the full buffer, so the
+    // session stays in step with the server and only this reply is lost
+    async fn skip_reply(&mut self) -> Error<T::Error> {
+        let capacity = self.buf.len();
+        // the buffer ends within a line, whose header `read_line` may already have consumed
+        let start = self.buf_unprocessed.start;
+        let line_start = if start == 0 || self.buf[start - 2..start] == *b"\r\n" {
+            start
+        } else {
+            start - 4
+        };
+        let mut end = ReplyEnd::default();
+        end.find(&self.buf[line_start..]);
+        let mut needed = capacity;
+        loop {
+            let n_bytes = match self.read_into(0).await {
+                Ok(n_bytes) => n_bytes,
+                Err(e) => return e,
+            };
+            if let Some(n_bytes) = end.find(&self.buf[..n_bytes]) {
+                needed += n_bytes;
+                break;
+            }
+            needed += n_bytes;
+        }
+        self.buf_unprocessed = 0..0;
+        self.reply_pending = false;
+        MalformedError::ReplyTooLarge { needed, capacity }.into()
     }
 
     async fn fill_to_atleast(&mut self, n: usize) -> Result<(), Error<T::Error>> {
@@ -171,32 +626,12 @@ impl<'buffer, T: ReadWrite<Error = impl core::error::Error>> Smtp<'buffer, T> {
     // returns a range instead of a slice because of lifetime issues
     fn buffer_contains_terminator(&mut self) -> Result<Option<Range<usize>>, Error<T::Error>> {
         let buf = &self.buf[self.buf_unprocessed.clone()];
-        let mut iter = buf.iter().enumerate();
-        while let Some((idx, char)) = iter.next() {
-            if *char == b'\r' {
-                match iter.next() {
-                    Some((_, b'\n')) => {
-                        let range = self.buf_unprocessed.start..self.buf_unprocessed.start + idx;
-                        self.buf_unprocessed.start += idx + 2;
-                        return Ok(Some(range));
-                    }
-                    Some(_) => {
-                        return Err(Error::MalformedError(
-                            MalformedError::InvalidLineTermination,
-                        ));
-                    }
-                    None => {
-                        return Ok(None);
-                    }
-                }
-            }
-            if *char == b'\n' {
-                return Err(Error::MalformedError(
-                    MalformedError::InvalidLineTermination,
-                ));
-            }
-        }
-        Ok(None)
+        let Some(idx) = find_line_terminator(buf)? else {
+            return Ok(None);
+        };
+        let range = self.buf_unprocessed.start..self.buf_unprocessed.start + idx;
+        self.buf_unprocessed.start += idx + 2;
+        Ok(Some(range))
     }
 
     async fn consume_until_newline_and_write_len_header(
@@ -205,13 +640,11 @@ impl<'buffer, T: ReadWrite<Error = impl core::error::Error>> Smtp<'buffer, T> {
         loop {
             match self.buffer_contains_terminator()? {
                 Some(msg) => {
-                    let len = msg.len();
-                    // todo: error here
+                    let len = u16::try_from(msg.len()).map_err(|_| MalformedError::LineTooLong)?;
                     // we need to copy the message length into the buffer
                     // we only call this _after_ we have found the code, so we can safely
                     // widen the range and include some extra bytes
-                    self.buf[msg.start - 2..msg.start]
-                        .copy_from_slice(&u16::to_ne_bytes(len as u16));
+                    self.buf[msg.start - 2..msg.start].copy_from_slice(&u16::to_ne_bytes(len));
                     return Ok(&self.buf[msg]);
                 }
                 None => self.fill_buffer().await?,
@@ -221,22 +654,10 @@ impl<'buffer, T: ReadWrite<Error = impl core::error::Error>> Smtp<'buffer, T> {
 
     /// reads a single line from the server.
     pub async fn read_line(&mut self) -> Result<ReplyLine<'_>, Error<T::Error>> {
-        let Ok(Ok(code)) = core::str::from_utf8(self.consume(3).await?).map(|s| s.parse::<u16>())
-        else {
-            return Err(Error::MalformedError(MalformedError::NoCode));
-        };
-        let is_last = match self.consume(1).await?[0] {
-            b' ' => true,
-            b'-' => false,
-            _ => {
-                //todo: wrong error message
-                return Err(Error::MalformedError(MalformedError::InvalidEncoding));
-            }
-        };
+        let header = self.consume(4).await?;
+        let (code, is_last) = parse_line_header(header.first_chunk().expect("consumed 4 bytes"))?;
         // now we need to find the line terminator
-        let message_bytes = self.consume_until_newline_and_write_len_header().await?;
-        let message = core::str::from_utf8(message_bytes)
-            .map_err(|_| Error::MalformedError(MalformedError::InvalidEncoding))?;
+        let message = self.consume_until_newline_and_write_len_header().await?;
         let reply = ReplyLine {
             code,
             is_last,
@@ -247,8 +668,21 @@ impl<'buffer, T: ReadWrite<Error = impl core::error::Error>> Smtp<'buffer, T> {
         Ok(reply)
     }
 
+    /// Reads a complete reply, within the [timeout](Self::set_timer) of the command it
+    /// answers.
     pub async fn read_multiline_reply(&mut self) -> Result<Reply<'_>, Error<T::Error>> {
         self.buf_unprocessed = 0..0;
+        self.read_buffered_reply().await
+    }
+
+    // reads a reply of which the first bytes may already be in the buffer
+    async fn read_buffered_reply(&mut self) -> Result<Reply<'_>, Error<T::Error>> {
+        // everything written so far has to reach the server before it can answer
+        self.stream.flush().await.map_err(Error::IoError)?;
+        let timeout = self.reply_timeout.take().unwrap_or(self.timeouts.command());
+        if let Some(timer) = &mut self.timer {
+            timer.get().start(timeout);
+        }
         let reply = self.read_line().await?;
         let expected_code = reply.code();
         let mut is_last = reply.is_last();
@@ -263,9 +697,21 @@ impl<'buffer, T: ReadWrite<Error = impl core::error::Error>> Smtp<'buffer, T> {
             }
             is_last = reply.is_last();
         }
-        self.buf[0..2].copy_from_slice(&u16::to_ne_bytes(expected_code));
-        let all_replies = &self.buf[..self.buf_unprocessed.start];
-        Ok(Reply::from_buffer(all_replies))
+        self.buf[0..2].copy_from_slice(&u16::to_ne_bytes(expected_code.as_u16()));
+        self.reply_pending = false;
+        let reply = Reply::from_buffer(&self.buf[..self.buf_unprocessed.start - 2])?;
+        if let Some(history) = &mut self.history {
+            history.push(&reply);
+        }
+        self.last_reply()
+    }
+
+    // the reply most recently read by `read_multiline_reply`, so callers can update the
+    // session after inspecting the code without holding on to the borrow
+    fn last_reply(&self) -> Result<Reply<'_>, Error<T::Error>> {
+        // leave out the final \r\n so the last line is recognized as such by `Reply::replies`
+        let end = self.buf_unprocessed.start.saturating_sub(2);
+        Ok(Reply::from_buffer(&self.buf[..end])?)
     }
 
     pub fn new_with_buffer(stream: T, buffer: impl Into<Buffer<'buffer>>) -> Self {
@@ -273,199 +719,1713 @@ impl<'buffer, T: ReadWrite<Error = impl core::error::Error>> Smtp<'buffer, T> {
             buf: buffer.into(),
             stream,
             buf_unprocessed: 0..0,
+            legacy: false,
+            client_cert_auth: false,
+            authenticated: false,
+            auth_challenged: false,
+            state: SessionState::NotGreeted,
+            capabilities: Capabilities::none(),
+            pre_auth_capabilities: None,
+            post_auth_capabilities: None,
+            dry_run: false,
+            strict_messages: false,
+            protocol: Protocol::Smtp,
+            accepted_recipients: 0,
+            secure: false,
+            plaintext_auth: false,
+            desired: None,
+            negotiation: None,
+            clock: None,
+            timer: None,
+            timeouts: Timeouts::default(),
+            reply_timeout: None,
+            history: None,
+            reply_pending: false,
+            max_buffer_size: DEFAULT_MAX_BUFFER_SIZE,
         }
     }
 
+    /// Sends the message after a `DATA` command, followed by the end of data marker.
+    ///
+    /// Lines starting with a `.` are dot-stuffed, so `data` can be any message. A bare `\r` or
+    /// `\n` is sent as `\r\n`, so servers which also end lines at a bare `\n` can't be made
+    /// to end the data early. A line break is added before the final dot if `data` doesn't
+    /// end with one.
+    ///
+    /// If the stream [notices](ReadWrite::read_available) a reply while the data is written,
+    /// e.g. a `552` from a server which doesn't wait for the end, the rest of the data is
+    /// dropped and that reply is returned. The session is out of step with the server then:
+    /// it is no longer [usable](Self::is_usable), and can only be closed.
     pub async fn send_data<'s>(&'s mut self, data: &[u8]) -> Result<Reply<'s>, Error<T::Error>> {
+        self.refuse_dry_run()?;
         #[cfg(feature = "log-04")]
         log::debug!("c>[{} bytes of data]<CR><LF>.<CR><LF>", data.len());
-        // send the data
+        self.begin_exchange()?;
+        let mut writer = DataWriter::new(&mut self.stream, &mut self.buf[..]);
+        writer.write(data).await.map_err(Error::IoError)?;
+        let early = writer.finish().await.map_err(Error::IoError)?;
+        self.read_data_reply(early).await
+    }
+
+    // the reply to the data, `early` bytes of which arrived while writing it
+    async fn read_data_reply(&mut self, early: usize) -> Result<Reply<'_>, Error<T::Error>> {
+        self.reply_timeout = Some(self.timeouts.data_end());
+        if early == 0 {
+            return self.read_multiline_reply().await;
+        }
+        #[cfg(feature = "log-04")]
+        log::warn!("server replied before the end of data, the rest of the data was dropped");
+        self.buf_unprocessed = 0..early;
+        let read = self.read_buffered_reply().await.map(|_| ());
+        // the server takes the data it already received for commands, and may still answer
+        // them, even if its reply was skipped
+        self.reply_pending = true;
+        read?;
+        self.last_reply()
+    }
+
+    /// Like [`send_data`](Self::send_data), but for data which is already dot-stuffed.
+    ///
+    /// `data` is written as-is, a line starting with a single `.` ends the message early.
+    pub async fn send_data_raw<'s>(
+        &'s mut self,
+        data: &[u8],
+    ) -> Result<Reply<'s>, Error<T::Error>> {
+        self.refuse_dry_run()?;
+        #[cfg(feature = "log-04")]
+        log::debug!("c>[{} bytes of raw data]<CR><LF>.<CR><LF>", data.len());
+        let terminator: &[u8] = if data.is_empty() || data.ends_with(b"\r\n") {
+            b".\r\n"
+        } else {
+            b"\r\n.\r\n"
+        };
+        self.begin_exchange()?;
         self.stream
-            .write_multi(&[data, b"\r\n.\r\n"])
+            .write_multi(&[data, terminator])
             .await
             .map_err(Error::IoError)?;
-        // read the reply
+        self.reply_timeout = Some(self.timeouts.data_end());
         self.read_multiline_reply().await
     }
 
-    pub fn into_inner(self) -> (T, Buffer<'buffer>) {
-        (self.stream, self.buf)
+    /// Like [`send_data`](Self::send_data), but takes the message from `source` one chunk at
+    /// a time, and dot-stuffs it on the way.
+    ///
+    /// If `source` fails the message can't be aborted, the server is still waiting for the
+    /// end of the data, so the connection has to be closed.
+    pub async fn send_data_stream<'s, S: AsyncBodySource>(
+        &'s mut self,
+        mut source: S,
+    ) -> Result<Reply<'s>, Error<T::Error>>
+    where
+        S::Error: Into<T::Error>,
+    {
+        self.refuse_dry_run()?;
+        #[cfg(feature = "log-04")]
+        log::debug!("c>[streamed data]<CR><LF>.<CR><LF>");
+        self.begin_exchange()?;
+        let mut writer = DataWriter::new(&mut self.stream, &mut self.buf[..]);
+        while !writer.interrupted() {
+            let chunk = source
+                .next_chunk()
+                .await
+                .map_err(|e| Error::IoError(e.into()))?;
+            if chunk.is_empty() {
+                break;
+            }
+            writer.write(chunk).await.map_err(Error::IoError)?;
+        }
+        let early = writer.finish().await.map_err(Error::IoError)?;
+        self.read_data_reply(early).await
+    }
+
+    pub fn into_inner(self) -> (T, Buffer<'buffer>) {
+        (self.stream, self.buf)
+    }
+
+    /// Like [`into_inner`](Self::into_inner), but keeps the session settings, such as
+    /// [dry-run](Self::set_dry_run), so they carry over to the session on the encrypted
+    /// stream.
+    pub fn into_upgrade(self) -> (T, TlsUpgrade<'buffer>) {
+        let upgrade = TlsUpgrade {
+            buf: self.buf,
+            legacy: self.legacy,
+            dry_run: self.dry_run,
+            strict_messages: self.strict_messages,
+            protocol: self.protocol,
+            plaintext_auth: self.plaintext_auth,
+            desired: self.desired,
+            clock: self.clock,
+            timer: self.timer,
+            timeouts: self.timeouts,
+            history: self.history,
+            max_buffer_size: self.max_buffer_size,
+        };
+        (self.stream, upgrade)
+    }
+
+    pub async fn ready(&mut self) -> Result<Ready<'_>, Error<T::Error>> {
+        // wait for the server to be ready
+        self.begin_exchange()?;
+        self.reply_timeout = Some(self.timeouts.greeting());
+        let reply = self.read_multiline_reply().await?;
+        // 220 or 554 are expected
+        if reply.code != ReplyCode::SERVICE_READY {
+            return Err(Error::unexpected_reply(&reply, &[ReplyCode::SERVICE_READY]));
+        }
+        Ok(Ready::new(reply))
+    }
+
+    /// Greets the server with `EHLO`, or `LHLO` in an [LMTP](Protocol::Lmtp) session, and
+    /// returns what it advertised.
+    pub async fn ehlo(&mut self, domain: &str) -> Result<EhloResponse<'_>, Error<T::Error>> {
+        self.expect_state(&[SessionState::NotGreeted, SessionState::Greeted])?;
+        let command: &[u8] = match self.protocol {
+            Protocol::Smtp => b"EHLO ",
+            Protocol::Lmtp => b"LHLO ",
+        };
+        #[cfg(feature = "log-04")]
+        log::debug!(
+            "c>{}{}",
+            core::str::from_utf8(command).unwrap_or_default(),
+            domain
+        );
+        self.begin_exchange()?;
+        self.stream
+            .write_multi(&[command, domain.as_bytes(), b"\r\n"])
+            .await
+            .map_err(Error::IoError)?;
+        self.capabilities = Capabilities::none();
+        self.negotiation = None;
+        let code = self.read_multiline_reply().await?.code();
+        // or 504, 550, 502
+        if code != ReplyCode::OK {
+            self.state = SessionState::NotGreeted;
+            return Err(Error::unexpected_reply(
+                &self.last_reply()?,
+                &[ReplyCode::OK],
+            ));
+        }
+        self.state = SessionState::Greeted;
+        self.legacy = false;
+        self.capabilities = Capabilities::from_ehlo(&EhloResponse::new(self.last_reply()?));
+        if self.authenticated && self.pre_auth_capabilities.is_some() {
+            self.post_auth_capabilities.get_or_insert(self.capabilities);
+        }
+        self.negotiate();
+        Ok(EhloResponse::new(self.last_reply()?))
+    }
+
+    /// Greets the server with [`EHLO`](Self::ehlo) unless it was already greeted since the
+    /// connection or the TLS upgrade was established, and returns what it advertised.
+    ///
+    /// Helpers can call this before commands which need a greeting without costing a round
+    /// trip when the caller already sent EHLO. A [legacy](Self::is_legacy) session isn't
+    /// greeted again, so its capabilities stay empty.
+    pub async fn ensure_ehlo(&mut self, domain: &str) -> Result<&Capabilities, Error<T::Error>> {
+        if self.state == SessionState::NotGreeted {
+            self.ehlo(domain).await?;
+        }
+        Ok(&self.capabilities)
+    }
+
+    /// Greets the server with [`EHLO`](Self::ehlo), falling back to [`HELO`](Self::helo) if
+    /// the server doesn't know the command, and returns what it advertised.
+    ///
+    /// Some appliances only implement the original SMTP and answer EHLO with `500` or `502`.
+    /// In that case the session is [legacy](Self::is_legacy) and the capabilities are empty.
+    /// <https://datatracker.ietf.org/doc/html/rfc5321#section-3.2>
+    ///
+    /// LMTP has no HELO, so an [LMTP](Protocol::Lmtp) session doesn't fall back.
+    pub async fn hello(&mut self, domain: &str) -> Result<&Capabilities, Error<T::Error>> {
+        match self.ehlo(domain).await.map(|_| ()) {
+            Ok(()) => {}
+            Err(Error::ServerRejected { code, .. })
+                if self.protocol == Protocol::Smtp
+                    && (code == ReplyCode::SYNTAX_ERROR
+                        || code == ReplyCode::COMMAND_NOT_IMPLEMENTED) =>
+            {
+                #[cfg(feature = "log-04")]
+                log::info!("server doesn't support EHLO ({code}), falling back to HELO");
+                self.helo(domain).await?;
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(&self.capabilities)
+    }
+
+    /// Greets the server with the pre-ESMTP `HELO` command.
+    ///
+    /// Only use this for servers which don't understand EHLO. The session is marked as
+    /// [legacy](Self::is_legacy): no service extensions are available, so extension
+    /// parameters like the DSN ones on [`Envelope`] are left out of later commands.
+    /// <https://datatracker.ietf.org/doc/html/rfc5321#section-4.1.1.1>
+    pub async fn helo(&mut self, domain: &str) -> Result<Reply<'_>, Error<T::Error>> {
+        self.expect_state(&[SessionState::NotGreeted, SessionState::Greeted])?;
+        #[cfg(feature = "log-04")]
+        log::debug!("c>HELO {}", domain);
+        self.begin_exchange()?;
+        self.stream
+            .write_multi(&[b"HELO ", domain.as_bytes(), b"\r\n"])
+            .await
+            .map_err(Error::IoError)?;
+        self.capabilities = Capabilities::none();
+        self.negotiation = None;
+        let code = self.read_multiline_reply().await?.code();
+        // or 504, 550, 502
+        if code != ReplyCode::OK {
+            self.state = SessionState::NotGreeted;
+            return Err(Error::unexpected_reply(
+                &self.last_reply()?,
+                &[ReplyCode::OK],
+            ));
+        }
+        self.state = SessionState::Greeted;
+        self.legacy = true;
+        self.negotiate();
+        self.last_reply()
+    }
+
+    /// Returns true if the server was greeted with [`HELO`](Self::helo) rather than EHLO.
+    ///
+    /// Legacy sessions don't support any extensions: no STARTTLS, no AUTH, no pipelining,
+    /// no SIZE or DSN parameters.
+    pub fn is_legacy(&self) -> bool {
+        self.legacy
+    }
+
+    /// What the server advertised in response to the last [`EHLO`](Self::ehlo).
+    ///
+    /// Empty before EHLO, on a [legacy](Self::is_legacy) session and after STARTTLS, as the
+    /// client must forget the capabilities it learned over plaintext.
+    /// <https://datatracker.ietf.org/doc/html/rfc3207#section-4.2>
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// What the server advertised before [`auth`](Self::auth) succeeded, `None` if it didn't.
+    ///
+    /// Some servers advertise more once the client authenticated, e.g. a larger SIZE limit,
+    /// which only an EHLO after AUTH reveals, see
+    /// [`post_auth_capabilities`](Self::post_auth_capabilities).
+    pub fn pre_auth_capabilities(&self) -> Option<&Capabilities> {
+        self.pre_auth_capabilities.as_ref()
+    }
+
+    /// What the server advertised in response to the first EHLO after [`auth`](Self::auth)
+    /// succeeded, `None` if the server wasn't greeted again.
+    pub fn post_auth_capabilities(&self) -> Option<&Capabilities> {
+        self.post_auth_capabilities.as_ref()
+    }
+
+    /// The extensions to check the server's capabilities against after every EHLO.
+    ///
+    /// The outcome is logged, with unsatisfied features as warnings, and available from
+    /// [`negotiation_report`](Self::negotiation_report).
+    pub fn set_desired_features(&mut self, desired: DesiredFeatures<'static>) {
+        self.desired = Some(desired);
+    }
+
+    /// How the [desired features](Self::set_desired_features) compare to what the server
+    /// advertised in the last greeting.
+    ///
+    /// `None` if no features were desired, or the server hasn't been greeted since the
+    /// session was created or upgraded with STARTTLS.
+    pub fn negotiation_report(&self) -> Option<NegotiationReport> {
+        self.negotiation
+    }
+
+    fn negotiate(&mut self) {
+        self.negotiation = self
+            .desired
+            .map(|desired| self.capabilities.negotiate(&desired));
+    }
+
+    /// In dry-run mode, [`send_mail`](Self::send_mail) and
+    /// [`send_envelope`](Self::send_envelope) go through `MAIL FROM` and `RCPT TO` as usual,
+    /// but abort the transaction with `RSET` instead of sending the message.
+    ///
+    /// Rejections of the sender or recipients are reported exactly like they would be for
+    /// a real delivery, which makes this useful for testing configurations and in staging
+    /// environments that must not send real mail.
+    ///
+    /// The [`send_data`](Self::send_data) family fails with [`ProtocolError::DryRun`] before
+    /// writing anything.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Switches the session to LMTP, or back to SMTP, before greeting the server.
+    ///
+    /// An LMTP server reports the delivery to each accepted recipient separately after the
+    /// data, in the order they were accepted. [`send_envelope_per_recipient`] returns those
+    /// results, while the other sending methods fail with the first failed delivery, even
+    /// though the message may have been delivered to the other recipients.
+    /// <https://datatracker.ietf.org/doc/html/rfc2033#section-4.2>
+    ///
+    /// [`send_envelope_per_recipient`]: Self::send_envelope_per_recipient
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    // the send_data family is public, so dry-run can't rely on the transaction methods alone
+    fn refuse_dry_run(&self) -> Result<(), ProtocolError> {
+        if self.dry_run {
+            return Err(ProtocolError::DryRun);
+        }
+        Ok(())
+    }
+
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    /// Returns false once a command was interrupted before its reply was read completely,
+    /// e.g. because the future sending it was dropped or [timed out](Self::set_timer), or
+    /// the stream failed, and after the server replied in the middle of the
+    /// [data](Self::send_data).
+    ///
+    /// The server may still send the reply, which would be taken for the reply to the next
+    /// command, so such a session refuses any command but [`fast_quit`](Self::fast_quit)
+    /// with [`ProtocolError::Interrupted`]. Every future of this session is cancel safe in
+    /// that sense: dropping it either leaves the session as it was, or marks it unusable.
+    pub fn is_usable(&self) -> bool {
+        !self.reply_pending
+    }
+
+    // called before writing a command, until its reply was read
+    fn begin_exchange(&mut self) -> Result<(), ProtocolError> {
+        if self.reply_pending {
+            return Err(ProtocolError::Interrupted);
+        }
+        self.reply_pending = true;
+        Ok(())
+    }
+
+    fn expect_state(&self, allowed: &[SessionState]) -> Result<(), ProtocolError> {
+        // anything sent now would be taken as the response to the challenge
+        if self.auth_challenged {
+            return Err(ProtocolError::AuthChallengePending);
+        }
+        if allowed.contains(&self.state) {
+            return Ok(());
+        }
+        Err(match self.state {
+            SessionState::NotGreeted => ProtocolError::NotGreeted,
+            SessionState::TlsPending => ProtocolError::TlsUpgradePending,
+            SessionState::Greeted => ProtocolError::AlreadyGreeted,
+        })
+    }
+
+    /// Asks the server to start a TLS handshake.
+    ///
+    /// On success the session moves to [`SessionState::TlsPending`], and refuses any other
+    /// command: the stream has to be upgraded into a new session, which then has to be
+    /// greeted again.
+    ///
+    /// Fails with [`ProtocolError::UnsupportedExtension`] before sending anything if the last
+    /// EHLO response didn't advertise STARTTLS, e.g. on a [legacy](Self::is_legacy) session.
+    pub async fn starttls(&mut self) -> Result<Reply<'_>, Error<T::Error>> {
+        self.expect_state(&[SessionState::Greeted])?;
+        if !self.capabilities.supports(Extensions::StartTls) {
+            return Err(
+                ProtocolError::unsupported(Extensions::StartTls, Operation::StartTls).into(),
+            );
+        }
+        #[cfg(feature = "log-04")]
+        log::debug!("c>STARTTLS");
+        self.begin_exchange()?;
+        self.stream
+            .write_single(b"STARTTLS\r\n")
+            .await
+            .map_err(Error::IoError)?;
+        let code = self.read_multiline_reply().await?.code();
+        // 220 or 554 are expected
+        if code != ReplyCode::SERVICE_READY {
+            return Err(Error::unexpected_reply(
+                &self.last_reply()?,
+                &[ReplyCode::SERVICE_READY],
+            ));
+        }
+        self.state = SessionState::TlsPending;
+        self.capabilities = Capabilities::none();
+        self.negotiation = None;
+        self.last_reply()
+    }
+
+    /// Marks whether the stream is encrypted, for implicit TLS or after upgrading a session
+    /// with STARTTLS. The tokio helpers take care of this themselves.
+    pub fn set_secure(&mut self, secure: bool) {
+        self.secure = secure;
+    }
+
+    /// Returns true if the stream was [marked](Self::set_secure) as encrypted.
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    /// Returns true if [`auth`](Self::auth) succeeded, or the session
+    /// [relies](AuthMode::ClientCertOnly) on the TLS client certificate.
+    ///
+    /// Upgrading to TLS starts a new, unauthenticated session.
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticated
+    }
+
+    /// Allows [`auth`](Self::auth) on a stream that isn't [secure](Self::is_secure).
+    ///
+    /// Off by default, as the credentials would be readable by anyone on the path.
+    /// <https://datatracker.ietf.org/doc/html/rfc4954#section-4>
+    pub fn set_allow_plaintext_auth(&mut self, allow: bool) {
+        self.plaintext_auth = allow;
+    }
+
+    /// Authenticates with `AUTH PLAIN`.
+    ///
+    /// Fails with [`ProtocolError::PlaintextAuth`] before sending anything if the stream isn't
+    /// [secure](Self::is_secure), unless [allowed](Self::set_allow_plaintext_auth), and with
+    /// [`ProtocolError::UnsupportedExtension`] if the last EHLO response didn't advertise
+    /// `AUTH PLAIN`.
+    pub async fn auth(
+        &mut self,
+        username: &str,
+        password: &str,
+    ) -> Result<Reply<'_>, Error<T::Error>> {
+        self.expect_state(&[SessionState::Greeted])?;
+        if !self.secure && !self.plaintext_auth {
+            return Err(ProtocolError::PlaintextAuth.into());
+        }
+        if !self.capabilities.supports_auth(AuthMechanism::Plain) {
+            let needed_for = Operation::Auth(AuthMechanism::Plain.as_str());
+            return Err(ProtocolError::unsupported(Extensions::Auth("PLAIN"), needed_for).into());
+        }
+        #[cfg(feature = "log-04")]
+        log::debug!("c>AUTH PLAIN [censored]");
+
+        // the read buffer holds the encoded credentials until the reply overwrites them,
+        // so no allocation is needed
+        let credentials: [&[u8]; 4] = [b"\0", username.as_bytes(), b"\0", password.as_bytes()];
+        let len =
+            base64::encode_parts(&credentials, &mut self.buf).ok_or(ProtocolError::LineTooLong)?;
+        // nothing was sent if the credentials don't fit
+        self.begin_exchange()?;
+        self.stream
+            .write_multi(&[b"AUTH PLAIN ", &self.buf[..len], b"\r\n"])
+            .await
+            .map_err(Error::IoError)?;
+        let code = self.read_multiline_reply().await?.code();
+        // 235 or 554 are expected
+        if code != ReplyCode::AUTH_SUCCESSFUL {
+            return Err(Error::unexpected_reply(
+                &self.last_reply()?,
+                &[ReplyCode::AUTH_SUCCESSFUL],
+            ));
+        }
+        self.set_authenticated();
+        self.last_reply()
+    }
+
+    fn set_authenticated(&mut self) {
+        self.authenticated = true;
+        self.pre_auth_capabilities = Some(self.capabilities);
+        self.post_auth_capabilities = None;
+    }
+
+    /// Starts authenticating with `mechanism`, for the SASL mechanisms [`auth`](Self::auth)
+    /// doesn't implement, e.g. `LOGIN` or `XOAUTH2`. `initial_response` is sent along with
+    /// the command, encoded as base64. It is written in parts, so it may be longer than the
+    /// buffer, like the access token of `XOAUTH2`.
+    ///
+    /// The server either accepts the client right away, or sends a challenge, decoded from
+    /// base64, which is answered with [`auth_respond`](Self::auth_respond) or
+    /// [`auth_cancel`](Self::auth_cancel). No other command can be sent until then.
+    /// Challenges can be long, e.g. the error details of a failed `XOAUTH2`, and may span
+    /// several lines, which are joined. An owned buffer grows up to its
+    /// [limit](Self::set_max_buffer_size) to hold them.
+    ///
+    /// Fails like [`auth`](Self::auth) before sending anything.
+    /// <https://datatracker.ietf.org/doc/html/rfc4954#section-4>
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # async fn example(
+    /// #     smtp: &mut simple_smtp::Smtp<'_, impl simple_smtp::ReadWrite<Error = std::io::Error>>,
+    /// # ) -> Result<(), simple_smtp::Error<std::io::Error>> {
+    /// use simple_smtp::smtp::{auth::AuthStep, capabilities::AuthMechanism};
+    ///
+    /// let mut step = smtp.auth_begin(AuthMechanism::Login, None).await?;
+    /// while let AuthStep::Challenge(challenge) = step {
+    ///     let response = match challenge {
+    ///         b"Username:" => "me@example.com",
+    ///         _ => "hunter2",
+    ///     };
+    ///     step = smtp.auth_respond(response.as_bytes()).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn auth_begin(
+        &mut self,
+        mechanism: AuthMechanism,
+        initial_response: Option<&[u8]>,
+    ) -> Result<AuthStep<'_>, Error<T::Error>> {
+        self.expect_state(&[SessionState::Greeted])?;
+        if !self.secure && !self.plaintext_auth {
+            return Err(ProtocolError::PlaintextAuth.into());
+        }
+        if !self.capabilities.supports_auth(mechanism) {
+            let needed_for = Operation::Auth(mechanism.as_str());
+            let extension = Extensions::Auth(mechanism.as_str());
+            return Err(ProtocolError::unsupported(extension, needed_for).into());
+        }
+        #[cfg(feature = "log-04")]
+        log::debug!("c>AUTH {} [censored]", mechanism.as_str());
+
+        self.begin_exchange()?;
+        let separator: &[u8] = match initial_response {
+            None => b"",
+            // an empty response is sent as a single `=`
+            Some([]) => b" =",
+            Some(_) => b" ",
+        };
+        let mechanism = mechanism.as_str().as_bytes();
+        let response = initial_response.unwrap_or_default();
+        self.write_base64_line(&[b"AUTH ", mechanism, separator], response)
+            .await?;
+        self.auth_step().await
+    }
+
+    /// Answers the challenge the server sent during [`auth_begin`](Self::auth_begin),
+    /// encoding `response` as base64. Like the initial response, it may be longer than the
+    /// buffer.
+    pub async fn auth_respond(&mut self, response: &[u8]) -> Result<AuthStep<'_>, Error<T::Error>> {
+        if !self.auth_challenged {
+            return Err(ProtocolError::NoAuthChallenge.into());
+        }
+        #[cfg(feature = "log-04")]
+        log::debug!("c>[censored]");
+        self.begin_exchange()?;
+        self.write_base64_line(&[], response).await?;
+        self.auth_step().await
+    }
+
+    // writes up to 3 `prefix` parts, `data` encoded as base64 and the line break, in one go
+    // if the encoded data fits in the buffer; otherwise it's encoded a few bytes at a time on
+    // the stack, so long SASL responses like OAuth2 access tokens don't need a larger buffer
+    async fn write_base64_line(
+        &mut self,
+        prefix: &[&[u8]],
+        data: &[u8],
+    ) -> Result<(), Error<T::Error>> {
+        if let Some(len) = base64::encode_slice(data, &mut self.buf) {
+            let mut parts: [&[u8]; 5] = [b""; 5];
+            parts[..prefix.len()].copy_from_slice(prefix);
+            parts[prefix.len()] = &self.buf[..len];
+            parts[prefix.len() + 1] = b"\r\n";
+            return self
+                .stream
+                .write_multi(&parts[..prefix.len() + 2])
+                .await
+                .map_err(Error::IoError);
+        }
+        const CHUNK: usize = 192;
+        let mut encoded = [0; base64::encoded_len(CHUNK)];
+        self.stream
+            .write_multi(prefix)
+            .await
+            .map_err(Error::IoError)?;
+        for chunk in data.chunks(CHUNK) {
+            let len = base64::encode_slice(chunk, &mut encoded).expect("a chunk fits");
+            self.stream
+                .write_single(&encoded[..len])
+                .await
+                .map_err(Error::IoError)?;
+        }
+        self.stream
+            .write_single(b"\r\n")
+            .await
+            .map_err(Error::IoError)
+    }
+
+    /// Gives up on the authentication started with [`auth_begin`](Self::auth_begin) instead
+    /// of answering the challenge. The server confirms with a `501` reply, which is returned.
+    pub async fn auth_cancel(&mut self) -> Result<Reply<'_>, Error<T::Error>> {
+        if !self.auth_challenged {
+            return Err(ProtocolError::NoAuthChallenge.into());
+        }
+        #[cfg(feature = "log-04")]
+        log::debug!("c>*");
+        self.begin_exchange()?;
+        self.stream
+            .write_single(b"*\r\n")
+            .await
+            .map_err(Error::IoError)?;
+        self.auth_challenged = false;
+        self.read_multiline_reply().await
+    }
+
+    // reads the reply to a step of `auth_begin`
+    async fn auth_step(&mut self) -> Result<AuthStep<'_>, Error<T::Error>> {
+        let code = self.read_multiline_reply().await?.code();
+        self.auth_challenged = code == ReplyCode::AUTH_CONTINUE;
+        if code == ReplyCode::AUTH_CONTINUE {
+            let len = self.decode_challenge()?;
+            return Ok(AuthStep::Challenge(&self.buf[..len]));
+        }
+        if code != ReplyCode::AUTH_SUCCESSFUL {
+            return Err(Error::unexpected_reply(
+                &self.last_reply()?,
+                &[ReplyCode::AUTH_CONTINUE, ReplyCode::AUTH_SUCCESSFUL],
+            ));
+        }
+        self.set_authenticated();
+        Ok(AuthStep::Success(self.last_reply()?))
+    }
+
+    // decodes the text of the `334` reply in the buffer into its start, returning the
+    // decoded length. A line is a 4 byte header, the code with the length of the line
+    // written over its last 2 bytes, followed by the text and \r\n. The decoded bytes never
+    // catch up with the line being decoded, as base64 takes 4 characters for 3 bytes.
+    fn decode_challenge(&mut self) -> Result<usize, MalformedError> {
+        let end = self.buf_unprocessed.start - 2;
+        let mut decoder = base64::Decoder::default();
+        let mut decoded = 0;
+        let mut start = 4;
+        while start <= end {
+            let len = usize::from(u16::from_ne_bytes([
+                self.buf[start - 2],
+                self.buf[start - 1],
+            ]));
+            for i in start..start + len {
+                let byte = decoder
+                    .push(self.buf[i])
+                    .map_err(|_| MalformedError::InvalidEncoding)?;
+                if let Some(byte) = byte {
+                    self.buf[decoded] = byte;
+                    decoded += 1;
+                }
+            }
+            start += len + 6;
+        }
+        decoder
+            .finish()
+            .map_err(|_| MalformedError::InvalidEncoding)?;
+        Ok(decoded)
+    }
+
+    /// Authenticates according to `mode`.
+    ///
+    /// With [`AuthMode::ClientCertOnly`] nothing is sent, the server is expected to have
+    /// authenticated the client during the TLS handshake. If it rejects `MAIL FROM` with
+    /// `530 Authentication required` the error is reported as
+    /// [`ProtocolError::ClientCertificateNotAccepted`]. On a session that isn't
+    /// [secure](Self::is_secure) it fails with [`ProtocolError::ClientCertificateWithoutTls`].
+    pub async fn authenticate(&mut self, mode: &AuthMode<'_>) -> Result<(), Error<T::Error>> {
+        self.expect_state(&[SessionState::Greeted])?;
+        if matches!(mode, AuthMode::ClientCertOnly) && !self.secure {
+            return Err(ProtocolError::ClientCertificateWithoutTls.into());
+        }
+        self.client_cert_auth = matches!(mode, AuthMode::ClientCertOnly);
+        self.authenticated |= self.client_cert_auth;
+        match mode {
+            AuthMode::None | AuthMode::ClientCertOnly => {}
+            AuthMode::Plain { username, password } => {
+                self.auth(username, password).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Authenticates with credentials fetched from `provider` right before they are sent,
+    /// with `AUTH PLAIN` for a password and `AUTH XOAUTH2` for a token.
+    ///
+    /// If the server rejects them as invalid, the provider is
+    /// [refreshed](CredentialProvider::refresh) and the fresh credentials are tried once.
+    /// Errors of the provider are returned as [`Error::Credentials`].
+    #[cfg(feature = "alloc")]
+    pub async fn authenticate_with<P: CredentialProvider>(
+        &mut self,
+        provider: &P,
+    ) -> Result<(), Error<T::Error>>
+    where
+        P::Error: Into<T::Error>,
+    {
+        let provider_error = |e: P::Error| Error::Credentials(e.into());
+        let credentials = provider.credentials().await.map_err(provider_error)?;
+        match self.authenticate_credentials(&credentials).await {
+            Err(Error::ServerRejected { code, .. }) if code == ReplyCode::AUTH_FAILED => {
+                #[cfg(feature = "log-04")]
+                log::warn!("credentials rejected, refreshing them");
+            }
+            result => return result,
+        }
+        drop(credentials);
+        provider.refresh().await.map_err(provider_error)?;
+        let credentials = provider.credentials().await.map_err(provider_error)?;
+        self.authenticate_credentials(&credentials).await
+    }
+
+    #[cfg(feature = "alloc")]
+    async fn authenticate_credentials(
+        &mut self,
+        credentials: &Credentials,
+    ) -> Result<(), Error<T::Error>> {
+        match credentials {
+            Credentials::Password { username, password } => {
+                self.auth(username, password).await?;
+            }
+            Credentials::OAuth2 { username, token } => {
+                let response = alloc::format!("user={username}\x01auth=Bearer {token}\x01\x01");
+                let step = self
+                    .auth_begin(AuthMechanism::XOAuth2, Some(response.as_bytes()))
+                    .await?;
+                if matches!(step, AuthStep::Challenge(_)) {
+                    // the challenge holds the details of the failure, an empty response
+                    // gets the final error reply
+                    let step = self.auth_respond(b"").await?;
+                    if matches!(step, AuthStep::Challenge(_)) {
+                        // a server challenging again would take the next command as the
+                        // answer, so the exchange is cancelled before reporting it
+                        self.auth_cancel().await?;
+                        return Err(MalformedError::UnexpectedCode {
+                            expected: &[ReplyCode::AUTH_SUCCESSFUL],
+                            actual: ReplyCode::AUTH_CONTINUE,
+                        }
+                        .into());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Aborts the current mail transaction, if any, so the next message starts from a clean
+    /// state.
+    ///
+    /// The `send_*` methods already do this when the server rejects a recipient or `DATA`,
+    /// see [`Error::is_session_usable`].
+    /// <https://datatracker.ietf.org/doc/html/rfc5321#section-4.1.1.5>
+    pub async fn rset(&mut self) -> Result<Reply<'_>, Error<T::Error>> {
+        self.expect_state(&[SessionState::Greeted])?;
+        #[cfg(feature = "log-04")]
+        log::debug!("c>RSET");
+        self.begin_exchange()?;
+        self.stream
+            .write_single(b"RSET\r\n")
+            .await
+            .map_err(Error::IoError)?;
+        self.accepted_recipients = 0;
+        let reply = self.read_multiline_reply().await?;
+        if reply.code != ReplyCode::OK {
+            return Err(Error::unexpected_reply(&reply, &[ReplyCode::OK]));
+        }
+        Ok(reply)
+    }
+
+    pub async fn quit(&mut self) -> Result<Reply<'_>, Error<T::Error>> {
+        self.begin_exchange()?;
+        self.fast_quit().await?;
+        let reply = self.read_multiline_reply().await?;
+        // 221 or 554 are expected
+        if reply.code != ReplyCode::SERVICE_CLOSING {
+            return Err(Error::unexpected_reply(
+                &reply,
+                &[ReplyCode::SERVICE_CLOSING],
+            ));
+        }
+        Ok(reply)
+    }
+
+    pub async fn fast_quit(&mut self) -> Result<(), Error<T::Error>> {
+        #[cfg(feature = "log-04")]
+        log::debug!("c>QUIT");
+        // the reply is never read, but QUIT is always allowed to end the session
+        self.reply_pending = true;
+        self.stream
+            .write_single(b"QUIT\r\n")
+            .await
+            .map_err(Error::IoError)?;
+        self.stream.flush().await.map_err(Error::IoError)
+    }
+
+    /// Does nothing, but checks that the server is still there, e.g. before reusing a
+    /// pooled connection.
+    /// <https://datatracker.ietf.org/doc/html/rfc5321#section-4.1.1.9>
+    pub async fn noop(&mut self) -> Result<Reply<'_>, Error<T::Error>> {
+        self.expect_state(&[SessionState::NotGreeted, SessionState::Greeted])?;
+        #[cfg(feature = "log-04")]
+        log::debug!("c>NOOP");
+        self.begin_exchange()?;
+        self.stream
+            .write_single(b"NOOP\r\n")
+            .await
+            .map_err(Error::IoError)?;
+        let reply = self.read_multiline_reply().await?;
+        if reply.code != ReplyCode::OK {
+            return Err(Error::unexpected_reply(&reply, &[ReplyCode::OK]));
+        }
+        Ok(reply)
+    }
+
+    /// Asks the server whether `user` is a mailbox, usually given as an address.
+    ///
+    /// Many servers disable this to keep spammers from harvesting addresses, and answer
+    /// with `252`: the address can't be verified, but mail for it will be accepted. In that
+    /// case the reply has no mailboxes. A `251` reply, the user isn't local but mail for it
+    /// will be forwarded, is verified as well.
+    /// <https://datatracker.ietf.org/doc/html/rfc5321#section-3.5.1>
+    pub async fn vrfy(&mut self, user: &str) -> Result<MailboxReply<'_>, Error<T::Error>> {
+        let reply = self.mailbox_query(b"VRFY", user).await?;
+        match reply.code {
+            ReplyCode::OK | ReplyCode::USER_NOT_LOCAL_WILL_FORWARD => Ok(MailboxReply::new(reply)),
+            ReplyCode::CANNOT_VERIFY => Ok(MailboxReply::unverified(reply)),
+            _ => Err(Error::unexpected_reply(
+                &reply,
+                &[
+                    ReplyCode::OK,
+                    ReplyCode::USER_NOT_LOCAL_WILL_FORWARD,
+                    ReplyCode::CANNOT_VERIFY,
+                ],
+            )),
+        }
+    }
+
+    /// Asks the server for the members of the mailing list `list`.
+    /// <https://datatracker.ietf.org/doc/html/rfc5321#section-3.5.2>
+    pub async fn expn(&mut self, list: &str) -> Result<MailboxReply<'_>, Error<T::Error>> {
+        let reply = self.mailbox_query(b"EXPN", list).await?;
+        if reply.code != ReplyCode::OK {
+            return Err(Error::unexpected_reply(&reply, &[ReplyCode::OK]));
+        }
+        Ok(MailboxReply::new(reply))
+    }
+
+    /// Asks the server for help, on `topic` if given, usually a command. The text is in the
+    /// lines of the reply.
+    /// <https://datatracker.ietf.org/doc/html/rfc5321#section-4.1.1.8>
+    pub async fn help(&mut self, topic: Option<&str>) -> Result<Reply<'_>, Error<T::Error>> {
+        self.expect_state(&[SessionState::NotGreeted, SessionState::Greeted])?;
+        let topic = topic.unwrap_or_default();
+        check_argument(topic)?;
+        #[cfg(feature = "log-04")]
+        log::debug!("c>HELP {topic}");
+        let separator: &[u8] = if topic.is_empty() { b"" } else { b" " };
+        self.begin_exchange()?;
+        self.stream
+            .write_multi(&[b"HELP", separator, topic.as_bytes(), b"\r\n"])
+            .await
+            .map_err(Error::IoError)?;
+        let reply = self.read_multiline_reply().await?;
+        if reply.code != ReplyCode::HELP_MESSAGE && reply.code != ReplyCode::SYSTEM_STATUS {
+            return Err(Error::unexpected_reply(
+                &reply,
+                &[ReplyCode::HELP_MESSAGE, ReplyCode::SYSTEM_STATUS],
+            ));
+        }
+        Ok(reply)
+    }
+
+    // VRFY or EXPN, which both take a string
+    async fn mailbox_query(
+        &mut self,
+        command: &'static [u8],
+        argument: &str,
+    ) -> Result<Reply<'_>, Error<T::Error>> {
+        self.expect_state(&[SessionState::NotGreeted, SessionState::Greeted])?;
+        if argument.is_empty() {
+            return Err(ProtocolError::InvalidArgument.into());
+        }
+        check_argument(argument)?;
+        #[cfg(feature = "log-04")]
+        log::debug!(
+            "c>{} {argument}",
+            core::str::from_utf8(command).unwrap_or_default()
+        );
+        self.begin_exchange()?;
+        self.stream
+            .write_multi(&[command, b" ", argument.as_bytes(), b"\r\n"])
+            .await
+            .map_err(Error::IoError)?;
+        self.read_multiline_reply().await
+    }
+
+    pub async fn send_mail(
+        &mut self,
+        from: impl AsRef<str>,
+        to: impl Iterator<Item = impl AsRef<str>>,
+        data: &[u8], //nice to have: streaming data for memory constrained devices
+    ) -> Result<(), Error<T::Error>> {
+        self.check_size(data)?;
+        let from = from.as_ref();
+        let envelope = Envelope::new(from, &[]);
+        self.check_envelope(&envelope)?;
+        self.mail_from(&envelope).await?;
+        // now we need to send the recipients, checked one at a time as they come
+        for recipient in to {
+            let recipient = [Recipient::new(recipient.as_ref())];
+            let result = match self.check_envelope(&Envelope::new(from, &recipient)) {
+                Ok(()) => self.rcpt_to(&recipient[0]).await,
+                Err(error) => Err(error.into()),
+            };
+            if let Err(error) = result {
+                return Err(self.abort_transaction(error).await);
+            }
+        }
+        self.data_or_dry_run(data).await
+    }
+
+    /// Like [`send_mail`](Self::send_mail), but goes on with the other recipients when the
+    /// server rejects one, reporting which addresses were accepted and which rejected.
+    ///
+    /// The message is only sent if at least one recipient was accepted, otherwise the
+    /// transaction is [reset](Self::rset) and every address is in
+    /// [`rejected`](SendReport::rejected). See
+    /// [`send_envelope_partial`](Self::send_envelope_partial) for the errors returned.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// use simple_smtp::{Smtp, transport::MemoryTransport};
+    ///
+    /// let mut smtp = Smtp::new(MemoryTransport::new());
+    /// smtp.ready().await?;
+    /// smtp.ehlo("localhost").await?;
+    ///
+    /// let to = ["you@example.com", "them@example.com"];
+    /// let report = smtp.send_mail_report("me@example.com", to.iter(), b"hi\r\n").await?;
+    /// assert_eq!(report.accepted, to);
+    /// assert!(report.rejected.is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub async fn send_mail_report(
+        &mut self,
+        from: impl AsRef<str>,
+        to: impl Iterator<Item = impl AsRef<str>>,
+        data: &[u8],
+    ) -> Result<SendReport<T::Error>, Error<T::Error>> {
+        use alloc::{borrow::ToOwned, vec::Vec};
+
+        let to: Vec<_> = to.map(|address| address.as_ref().to_owned()).collect();
+        let recipients: Vec<_> = to.iter().map(|address| Recipient::new(address)).collect();
+        let mut rejected = Vec::new();
+        let envelope = Envelope::new(from.as_ref(), &recipients);
+        self.send_envelope_partial(&envelope, data, |index, error| {
+            rejected.push((index, error))
+        })
+        .await?;
+
+        let mut report = SendReport {
+            accepted: Vec::new(),
+            rejected: Vec::new(),
+        };
+        // rejections are reported in the order of the recipients
+        let mut rejected = rejected.into_iter().peekable();
+        for (index, address) in to.into_iter().enumerate() {
+            match rejected.next_if(|(rejected, _)| *rejected == index) {
+                Some((_, error)) => report.rejected.push((address, error)),
+                None => report.accepted.push(address),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Sends a message using the addresses and parameters of `envelope`.
+    ///
+    /// DSN parameters require the server to have advertised [`Extensions::Dsn`] in its EHLO
+    /// response, as it would reject the unknown parameters. Otherwise, e.g. on a
+    /// [legacy](Self::is_legacy) session, this fails with
+    /// [`ProtocolError::UnsupportedExtension`] before sending anything. The same goes for
+    /// addresses with non-ASCII characters, which need [`Extensions::SMTPUTF8`], and an
+    /// 8-bit [body](Envelope::with_body), which needs [`Extensions::EIGHTBITMIME`].
+    /// <https://datatracker.ietf.org/doc/html/rfc3461#section-4>
+    pub async fn send_envelope(
+        &mut self,
+        envelope: &Envelope<'_>,
+        data: &[u8],
+    ) -> Result<(), Error<T::Error>> {
+        self.check_size(data)?;
+        self.start_envelope(envelope).await?;
+        self.data_or_dry_run(data).await
+    }
+
+    /// Sends several messages over this session, one transaction after the other, e.g. to
+    /// empty a queue without opening a connection per message.
+    ///
+    /// A message the server rejects is passed to `rejected` with its index, and the next
+    /// one is sent, as the transaction was [reset](Self::rset). Once an error leaves the
+    /// session unusable, see [`Error::is_session_usable`], the remaining messages aren't
+    /// sent and the error is returned. Otherwise this returns the number of messages the
+    /// server accepted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// use simple_smtp::{Smtp, envelope::Envelope, transport::MemoryTransport};
+    ///
+    /// let transport = MemoryTransport::new();
+    /// let outbox = transport.outbox();
+    /// let mut smtp = Smtp::new(transport);
+    /// smtp.ready().await?;
+    /// smtp.ehlo("localhost").await?;
+    ///
+    /// let to = ["you@example.com".into()];
+    /// let envelope = Envelope::new("me@example.com", &to);
+    /// let queue = [(&envelope, &b"first\r\n"[..]), (&envelope, &b"second\r\n"[..])];
+    /// let sent = smtp
+    ///     .send_many(queue, |index, error| eprintln!("message {index} rejected: {error}"))
+    ///     .await?;
+    /// assert_eq!(sent, 2);
+    /// assert_eq!(outbox.messages().len(), 2);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_many<'e>(
+        &mut self,
+        messages: impl IntoIterator<Item = (&'e Envelope<'e>, &'e [u8])>,
+        mut rejected: impl FnMut(usize, Error<T::Error>),
+    ) -> Result<usize, Error<T::Error>> {
+        let mut sent = 0;
+        for (index, (envelope, data)) in messages.into_iter().enumerate() {
+            match self.send_envelope(envelope, data).await {
+                Ok(()) => sent += 1,
+                // e.g. after a reply in the middle of the data
+                Err(error) if !error.is_session_usable() || !self.is_usable() => {
+                    return Err(error);
+                }
+                Err(error) => rejected(index, error),
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Like [`send_envelope`](Self::send_envelope), but sends the message to the recipients
+    /// the server accepted instead of giving up on the first one it rejects.
+    ///
+    /// A rejected recipient is passed to `rejected` with its index in the envelope. If the
+    /// server rejects all of them, the transaction is [reset](Self::rset) and nothing is sent.
+    /// Otherwise this returns the number of recipients the message was sent to. An error
+    /// after `RCPT TO`, e.g. a rejected `DATA`, applies to all accepted recipients. In an
+    /// [LMTP](Protocol::Lmtp) session, see
+    /// [`send_envelope_per_recipient`](Self::send_envelope_per_recipient) instead.
+    pub async fn send_envelope_partial(
+        &mut self,
+        envelope: &Envelope<'_>,
+        data: &[u8],
+        mut rejected: impl FnMut(usize, Error<T::Error>),
+    ) -> Result<usize, Error<T::Error>> {
+        self.check_size(data)?;
+        self.check_envelope(envelope)?;
+        let envelope = envelope.declarable(&self.capabilities);
+        self.mail_from(&envelope).await?;
+        let mut accepted = 0;
+        for (index, recipient) in envelope.recipients().iter().enumerate() {
+            match self.rcpt_to(recipient).await {
+                Ok(()) => accepted += 1,
+                Err(error) if !error.is_session_usable() => return Err(error),
+                Err(error) => rejected(index, error),
+            }
+        }
+        if accepted == 0 {
+            self.rset().await?;
+            return Ok(0);
+        }
+        self.data_or_dry_run(data).await?;
+        Ok(accepted)
+    }
+
+    /// Like [`send_envelope_partial`](Self::send_envelope_partial), but returns the outcome
+    /// for every recipient of the envelope, in order: its rejection, or in an
+    /// [LMTP](Protocol::Lmtp) session the server's reply on the delivery to it.
+    ///
+    /// An error for the whole transaction, e.g. a rejected `MAIL FROM` or `DATA`, or in an
+    /// SMTP session a rejection of the message, is returned instead.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # async fn example(
+    /// #     stream: impl simple_smtp::ReadWrite<Error = std::io::Error>,
+    /// # ) -> Result<(), Box<dyn std::error::Error>> {
+    /// use simple_smtp::{Smtp, envelope::Envelope, smtp::Protocol};
+    ///
+    /// // e.g. the local socket of Dovecot or Cyrus
+    /// let mut lmtp = Smtp::new(stream);
+    /// lmtp.set_protocol(Protocol::Lmtp);
+    /// lmtp.ready().await?;
+    /// lmtp.ehlo("localhost").await?;
+    ///
+    /// let to = ["alice@example.com".into(), "bob@example.com".into()];
+    /// let envelope = Envelope::new("me@example.com", &to);
+    /// let results = lmtp
+    ///     .send_envelope_per_recipient(&envelope, b"Subject: hi\r\n\r\nhello\r\n")
+    ///     .await?;
+    /// for (recipient, result) in to.iter().zip(results) {
+    ///     if let Err(error) = result {
+    ///         eprintln!("not delivered to {}: {error}", recipient.address());
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub async fn send_envelope_per_recipient(
+        &mut self,
+        envelope: &Envelope<'_>,
+        data: &[u8],
+    ) -> Result<alloc::vec::Vec<Result<(), Error<T::Error>>>, Error<T::Error>> {
+        use alloc::vec::Vec;
+
+        self.check_size(data)?;
+        self.check_envelope(envelope)?;
+        let envelope = envelope.declarable(&self.capabilities);
+        self.mail_from(&envelope).await?;
+        let mut results = Vec::with_capacity(envelope.recipients().len());
+        for recipient in envelope.recipients() {
+            match self.rcpt_to(recipient).await {
+                Err(error) if !error.is_session_usable() => return Err(error),
+                result => results.push(result),
+            }
+        }
+        // the indices of the accepted recipients, in the order of their replies to the data
+        let accepted: Vec<_> = (0..results.len()).filter(|&i| results[i].is_ok()).collect();
+        if accepted.is_empty() {
+            self.rset().await?;
+            return Ok(results);
+        }
+        if self.dry_run {
+            self.data_or_dry_run(data).await?;
+            return Ok(results);
+        }
+        self.data_command().await?;
+        let reply = self.send_data(data).await?;
+        let first = data_result(&reply);
+        if self.protocol == Protocol::Smtp {
+            first?;
+            return Ok(results);
+        }
+        self.delivery_replies(first, |position, error| {
+            results[accepted[position]] = Err(error)
+        })
+        .await?;
+        Ok(results)
+    }
+
+    /// Like [`send_envelope`](Self::send_envelope), but greets the server with `domain` and
+    /// authenticates with `auth` again if it rejects the transaction because it dropped the
+    /// authentication of the session, see [`Error::is_authentication_required`]. Some relays
+    /// do so after long idle periods.
+    ///
+    /// The message is retried once. Nothing is retried on a session which wasn't
+    /// [authenticated](Self::is_authenticated) to begin with.
+    pub async fn send_envelope_reauthenticating(
+        &mut self,
+        envelope: &Envelope<'_>,
+        data: &[u8],
+        domain: &str,
+        auth: &AuthMode<'_>,
+    ) -> Result<SendOutcome, Error<T::Error>> {
+        match self.send_envelope(envelope, data).await {
+            Ok(()) => return Ok(SendOutcome::Sent),
+            Err(error)
+                if error.is_authentication_required() && self.authenticated && self.is_usable() =>
+            {
+                #[cfg(feature = "log-04")]
+                log::warn!("{error}, authenticating again");
+                #[cfg(not(feature = "log-04"))]
+                let _ = error;
+            }
+            Err(error) => return Err(error),
+        }
+        self.authenticated = false;
+        self.ehlo(domain).await?;
+        self.authenticate(auth).await?;
+        self.send_envelope(envelope, data).await?;
+        Ok(SendOutcome::Reauthenticated)
+    }
+
+    /// Like [`send_envelope`](Self::send_envelope), but takes the message from `source` one
+    /// chunk at a time, see [`send_data_stream`](Self::send_data_stream).
+    ///
+    /// As the size isn't known up front, it isn't checked against the server's SIZE limit.
+    pub async fn send_envelope_stream<S: AsyncBodySource>(
+        &mut self,
+        envelope: &Envelope<'_>,
+        source: S,
+    ) -> Result<(), Error<T::Error>>
+    where
+        S::Error: Into<T::Error>,
+    {
+        self.start_envelope(envelope).await?;
+        if self.dry_run {
+            #[cfg(feature = "log-04")]
+            log::info!("dry run: accepted by the server, not sending the data");
+            self.rset().await?;
+            return Ok(());
+        }
+        self.data_command().await?;
+        let reply = self.send_data_stream(source).await?;
+        let first = data_result(&reply);
+        self.check_delivery(first).await
+    }
+
+    /// Sends `message` to the addresses of `envelope`, writing it to the server part by part
+    /// instead of formatting it in memory first.
+    ///
+    /// The `To:` and `Cc:` headers of the message are not used for routing, so every
+    /// recipient has to be in `envelope`.
+    ///
+    /// Invalid headers, e.g. addresses containing line breaks or [custom
+    /// headers](Message::with_header) with reserved names, fail with
+    /// [`ProtocolError::InvalidHeader`] before anything is sent. A reply before the end of the
+    /// message is handled as in [`send_data`](Self::send_data).
+    pub async fn send_message(
+        &mut self,
+        envelope: &Envelope<'_>,
+        message: &Message<'_>,
+    ) -> Result<(), Error<T::Error>> {
+        self.send_message_with(envelope, message, &Overrides::new())
+            .await
+    }
+
+    /// Like [`send_message`](Self::send_message), with some headers of `message` replaced
+    /// by `overrides`, e.g. a subject or `To:` header per batch of a bulk run.
+    pub async fn send_message_with(
+        &mut self,
+        envelope: &Envelope<'_>,
+        message: &Message<'_>,
+        overrides: &Overrides<'_>,
+    ) -> Result<(), Error<T::Error>> {
+        message.validate(overrides)?;
+        let message = self.dated(message);
+        self.check_lints(envelope, &message, overrides)?;
+        self.send_validated_message(envelope, &message, overrides, &[])
+            .await
+    }
+
+    /// Sets the clock messages without a [date](Message::with_date) are dated with, instead
+    /// of the system time. Without `std` there is no system time, so such messages are sent
+    /// without a `Date` header unless a clock is set.
+    pub fn set_clock(&mut self, clock: &'buffer (dyn Clock + Sync)) {
+        self.clock = Some(clock);
+    }
+
+    /// Bounds the wait for every reply with `timer`, so a server which stops responding
+    /// fails the command with [`Error::Timeout`] instead of blocking the session forever.
+    /// Each reply gets the timeout of its command, see [`set_timeouts`](Self::set_timeouts).
+    ///
+    /// Without a timer the session waits as long as the stream does.
+    pub fn set_timer(&mut self, timer: &'buffer mut (dyn Timer + Send + 'static)) {
+        self.timer = Some(SessionTimer::Borrowed(timer));
+    }
+
+    /// Like [`set_timer`](Self::set_timer), but the session owns `timer`.
+    #[cfg(feature = "alloc")]
+    pub fn set_owned_timer(&mut self, timer: impl Timer + Send + 'buffer) {
+        self.timer = Some(SessionTimer::Owned(alloc::boxed::Box::new(timer)));
+    }
+
+    /// Defaults to the minimum timeouts of RFC 5321, which only apply once a
+    /// [timer](Self::set_timer) is set.
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    pub fn timeouts(&self) -> Timeouts {
+        self.timeouts
+    }
+
+    /// Lets an owned buffer grow up to `size` bytes when a reply doesn't fit, e.g. a long
+    /// EHLO response, 64 KiB by default. A borrowed buffer never grows, a reply longer than
+    /// either fails with [`MalformedError::ReplyTooLarge`] but leaves the session usable.
+    pub fn set_max_buffer_size(&mut self, size: usize) {
+        self.max_buffer_size = size;
+    }
+
+    /// Keeps the last replies in `history`, to tell what led up to an error with
+    /// [`report`](Self::report). Replies are kept from the next one on, also across a
+    /// [TLS upgrade](Self::into_upgrade).
+    pub fn set_reply_history(&mut self, history: ReplyHistory<'buffer>) {
+        self.history = Some(history);
+    }
+
+    pub fn reply_history(&self) -> Option<&ReplyHistory<'buffer>> {
+        self.history.as_ref()
+    }
+
+    /// Pairs `error` with the [reply history](Self::set_reply_history) for display, e.g. to
+    /// log why a transaction failed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # async fn example() {
+    /// use simple_smtp::{Smtp, smtp::ReplyHistory, transport::MemoryTransport};
+    ///
+    /// let mut smtp = Smtp::new(MemoryTransport::new());
+    /// smtp.set_reply_history(ReplyHistory::with_capacity(8));
+    /// if let Err(error) = smtp.ready().await {
+    ///     eprintln!("{}", smtp.report(&error));
+    /// }
+    /// # }
+    /// ```
+    pub fn report<'s>(&'s self, error: &'s Error<T::Error>) -> ErrorReport<'s, T::Error> {
+        ErrorReport {
+            error,
+            history: self.history.as_ref(),
+        }
+    }
+
+    /// Refuses messages with [lints](Message::lint) in [`send_message`](Self::send_message)
+    /// and its variants, failing with [`ProtocolError::MessageLint`] before anything is sent.
+    pub fn set_strict_messages(&mut self, strict: bool) {
+        self.strict_messages = strict;
+    }
+
+    pub fn is_strict_messages(&self) -> bool {
+        self.strict_messages
+    }
+
+    fn check_lints(
+        &self,
+        envelope: &Envelope<'_>,
+        message: &Message<'_>,
+        overrides: &Overrides<'_>,
+    ) -> Result<(), ProtocolError> {
+        if !self.strict_messages {
+            return Ok(());
+        }
+        match message.lints(Some(envelope), overrides).iter().next() {
+            Some(lint) => Err(ProtocolError::MessageLint(lint)),
+            None => Ok(()),
+        }
     }
 
-    pub async fn ready(&mut self) -> Result<Ready<'_>, Error<T::Error>> {
-        // wait for the server to be ready
-        let reply = self.read_multiline_reply().await?;
-        // 220 or 554 are expected
-        if reply.code != 220 {
-            return Err(Error::MalformedError(MalformedError::UnexpectedCode {
-                expected: &[220],
-                actual: reply.code(),
-            }));
+    fn dated<'m>(&self, message: &Message<'m>) -> Message<'m> {
+        match self.clock {
+            Some(clock) => message.or_date(clock.now()),
+            None => *message,
         }
-        Ok(Ready::new(reply))
     }
 
-    pub async fn ehlo(&mut self, domain: &str) -> Result<EhloResponse<'_>, Error<T::Error>> {
-        #[cfg(feature = "log-04")]
-        log::debug!("c>EHLO {}", domain);
-        self.stream
-            .write_multi(&[b"EHLO ", domain.as_bytes(), b"\r\n"])
+    /// Like [`send_message`](Self::send_message), with a signature, e.g. DKIM, in front of
+    /// the message.
+    ///
+    /// The message is written to `signer` before anything is sent, and then to the server.
+    /// Without a date set, both use the current time.
+    #[cfg(feature = "alloc")]
+    pub async fn send_signed_message(
+        &mut self,
+        envelope: &Envelope<'_>,
+        message: &Message<'_>,
+        signer: &mut impl Signer,
+    ) -> Result<(), Error<T::Error>> {
+        let overrides = Overrides::new();
+        message.validate(&overrides)?;
+        let message = self.dated(message).with_fixed_date();
+        self.check_lints(envelope, &message, &overrides)?;
+        let signature = crate::message::sign(&message, &overrides, signer).await;
+        self.send_validated_message(envelope, &message, &overrides, &signature)
             .await
-            .map_err(Error::IoError)?;
-        let reply = self.read_multiline_reply().await?;
-        // or 504, 550, 502
-        if reply.code != 250 {
-            return Err(Error::MalformedError(MalformedError::UnexpectedCode {
-                expected: &[250],
-                actual: reply.code(),
-            }));
-        }
-        Ok(EhloResponse::new(reply))
     }
 
-    pub async fn starttls(&mut self) -> Result<Reply<'_>, Error<T::Error>> {
+    // `prefix` is written in front of the message
+    async fn send_validated_message(
+        &mut self,
+        envelope: &Envelope<'_>,
+        message: &Message<'_>,
+        overrides: &Overrides<'_>,
+        prefix: &[u8],
+    ) -> Result<(), Error<T::Error>> {
+        self.start_envelope(envelope).await?;
+        if self.dry_run {
+            #[cfg(feature = "log-04")]
+            log::info!("dry run: accepted by the server, not sending the message");
+            self.rset().await?;
+            return Ok(());
+        }
+        self.data_command().await?;
         #[cfg(feature = "log-04")]
-        log::debug!("c>STARTTLS");
-        self.stream
-            .write_single(b"STARTTLS\r\n")
+        log::debug!("c>[message]<CR><LF>.<CR><LF>");
+        self.begin_exchange()?;
+        let mut writer = DataWriter::new(&mut self.stream, &mut self.buf[..]);
+        writer.write(prefix).await.map_err(Error::IoError)?;
+        message
+            .write_to(&mut writer, overrides)
             .await
             .map_err(Error::IoError)?;
-        let reply = self.read_multiline_reply().await?;
-        // 220 or 554 are expected
-        if reply.code != 220 {
-            return Err(Error::MalformedError(MalformedError::UnexpectedCode {
-                expected: &[220],
-                actual: reply.code(),
-            }));
+        let early = writer.finish().await.map_err(Error::IoError)?;
+        let reply = self.read_data_reply(early).await?;
+        let first = data_result(&reply);
+        self.check_delivery(first).await
+    }
+
+    // MAIL FROM and RCPT TO for every recipient
+    async fn start_envelope(&mut self, envelope: &Envelope<'_>) -> Result<(), Error<T::Error>> {
+        self.check_envelope(envelope)?;
+        let envelope = envelope.declarable(&self.capabilities);
+        self.mail_from(&envelope).await?;
+        for recipient in envelope.recipients() {
+            if let Err(error) = self.rcpt_to(recipient).await {
+                return Err(self.abort_transaction(error).await);
+            }
         }
-        Ok(reply)
+        Ok(())
     }
 
-    pub async fn auth(
-        &mut self,
-        username: &str,
-        password: &str,
-    ) -> Result<Reply<'_>, Error<T::Error>> {
-        use base64::prelude::*;
+    // what the server has to support for `envelope`, checked before sending anything
+    pub(crate) fn check_envelope(&self, envelope: &Envelope<'_>) -> Result<(), ProtocolError> {
+        if !envelope.has_valid_addresses() {
+            return Err(ProtocolError::InvalidAddress);
+        }
+        if envelope.has_dsn_params() && !self.capabilities.supports(Extensions::Dsn) {
+            let needed_for = Operation::DsnParameters;
+            return Err(ProtocolError::unsupported(Extensions::Dsn, needed_for));
+        }
+        if envelope.requires_smtputf8() && !self.capabilities.supports(Extensions::SMTPUTF8) {
+            let needed_for = Operation::InternationalAddress;
+            return Err(ProtocolError::unsupported(Extensions::SMTPUTF8, needed_for));
+        }
+        if envelope.submitter().is_some() {
+            if !self.authenticated {
+                return Err(ProtocolError::NotAuthenticated);
+            }
+            if !self.capabilities.supports(Extensions::Auth("")) {
+                let needed_for = Operation::Submitter;
+                return Err(ProtocolError::unsupported(Extensions::Auth(""), needed_for));
+            }
+        }
+        if envelope.body() == Some(BodyType::EightBitMime)
+            && !self.capabilities.supports(Extensions::EIGHTBITMIME)
+        {
+            let needed_for = Operation::EightBitBody;
+            return Err(ProtocolError::unsupported(
+                Extensions::EIGHTBITMIME,
+                needed_for,
+            ));
+        }
+        if let (Some(size), Some(limit)) = (envelope.size(), self.capabilities.max_size())
+            && size > limit
+        {
+            return Err(ProtocolError::MessageTooLarge { size, limit });
+        }
+        if !envelope.has_valid_parameters()
+            || envelope
+                .envid()
+                .is_some_and(|envid| xtext_len(envid) > MAX_ENVID_LEN)
+        {
+            return Err(ProtocolError::InvalidParameter);
+        }
+        Ok(())
+    }
+
+    // RSET after a command of the transaction failed, so the session can be reused. If that
+    // fails as well, its error is the one telling whether the session is still usable.
+    async fn abort_transaction(&mut self, error: Error<T::Error>) -> Error<T::Error> {
+        if !error.is_session_usable() {
+            return error;
+        }
+        match self.rset().await {
+            Ok(_) => error,
+            Err(rset_error) => {
+                #[cfg(feature = "log-04")]
+                log::warn!("RSET after a failed transaction failed, dropping error: {error}");
+                rset_error
+            }
+        }
+    }
+
+    async fn data_or_dry_run(&mut self, data: &[u8]) -> Result<(), Error<T::Error>> {
+        if !self.dry_run {
+            return self.data(data).await;
+        }
         #[cfg(feature = "log-04")]
-        log::debug!("c>AUTH PLAIN [censored]");
+        log::info!(
+            "dry run: accepted by the server, not sending {} bytes of data",
+            data.len()
+        );
+        self.rset().await?;
+        Ok(())
+    }
 
-        // since we have to base64 encode w/o allocating
-        // we will use the read buffer to store the base64 encoded data.
-        // but there's no api for "encode this slice append x, append y, append z"
-        // so we first have to make the data contiguous...
-        // let's use the same buffer again for now. Ideally we should write some kind of streaming
-        // base64 encoder which we can call with a slice of slices
-        let payload = {
-            self.buf[0] = 0;
-            self.buf[1..1 + username.len()].copy_from_slice(username.as_bytes());
-            self.buf[1 + username.len()] = 0;
-            self.buf[username.len() + 2..username.len() + 2 + password.len()]
-                .copy_from_slice(password.as_bytes());
-            let (read, write) = self.buf.split_at_mut(username.len() + 2 + password.len());
-            let bytes = BASE64_STANDARD.encode_slice(read, write).unwrap();
-            &write[..bytes]
-        };
-        //if we can allocate, use just do it.
-        // let payload = BASE64_STANDARD.encode(format!("\0{}\0{}", username, password));
-        self.stream
-            .write_multi(&[b"AUTH PLAIN ", payload, b"\r\n"])
-            .await
-            .map_err(Error::IoError)?;
-        let reply = self.read_multiline_reply().await?;
-        // 235 or 554 are expected
-        if reply.code != 235 {
-            return Err(Error::MalformedError(MalformedError::UnexpectedCode {
-                expected: &[235],
-                actual: reply.code(),
-            }));
+    // fail early instead of transferring a message the server is going to reject
+    // https://datatracker.ietf.org/doc/html/rfc1870#section-6.1
+    fn check_size(&self, data: &[u8]) -> Result<(), ProtocolError> {
+        match self.capabilities.max_size() {
+            Some(limit) if data.len() as u64 > limit => Err(ProtocolError::MessageTooLarge {
+                size: data.len() as u64,
+                limit,
+            }),
+            _ => Ok(()),
         }
-        Ok(reply)
     }
 
-    pub async fn quit(&mut self) -> Result<Reply<'_>, Error<T::Error>> {
-        self.fast_quit().await?;
-        let reply = self.read_multiline_reply().await?;
-        // 221 or 554 are expected
-        if reply.code != 221 {
-            return Err(Error::MalformedError(MalformedError::UnexpectedCode {
-                expected: &[221],
-                actual: reply.code(),
-            }));
+    // writes a formatted value chunk by chunk
+    async fn write_display(&mut self, value: impl Display) -> Result<(), Error<T::Error>> {
+        let mut chunks = FmtToStream::<_>::new(value);
+        while let Some(chunk) = chunks.next_chunk() {
+            self.stream
+                .write_single(chunk)
+                .await
+                .map_err(Error::IoError)?;
         }
-        Ok(reply)
+        Ok(())
     }
 
-    pub async fn fast_quit(&mut self) -> Result<(), Error<T::Error>> {
+    pub(crate) async fn mail_from(
+        &mut self,
+        envelope: &Envelope<'_>,
+    ) -> Result<(), Error<T::Error>> {
+        self.expect_state(&[SessionState::Greeted])?;
+        self.accepted_recipients = 0;
         #[cfg(feature = "log-04")]
-        log::debug!("c>QUIT");
+        log::debug!(
+            "c>MAIL FROM: <{}>{}",
+            envelope.from(),
+            MailParameters(envelope)
+        );
+        self.begin_exchange()?;
         self.stream
-            .write_single(b"QUIT\r\n")
+            .write_multi(&[b"MAIL FROM:<", envelope.from().as_bytes(), b">"])
+            .await
+            .map_err(Error::IoError)?;
+        self.write_display(MailParameters(envelope)).await?;
+        self.stream
+            .write_single(b"\r\n")
             .await
             .map_err(Error::IoError)?;
+        let client_cert_auth = self.client_cert_auth;
+        self.reply_timeout = Some(self.timeouts.mail());
+        let reply = self.read_multiline_reply().await?;
+        if client_cert_auth && reply.code == ReplyCode::AUTH_REQUIRED {
+            #[cfg(feature = "log-04")]
+            log::warn!("server demands AUTH despite the TLS client certificate");
+            return Err(ProtocolError::ClientCertificateNotAccepted.into());
+        }
+        // 250 or 554 are expected
+        if reply.code != ReplyCode::OK {
+            return Err(Error::unexpected_reply(&reply, &[ReplyCode::OK]));
+        }
         Ok(())
     }
 
-    pub async fn send_mail(
+    pub(crate) async fn rcpt_to(
         &mut self,
-        from: impl AsRef<str>,
-        to: impl Iterator<Item = impl AsRef<str>>,
-        data: &[u8], //nice to have: streaming data for memory constrained devices
+        recipient: &Recipient<'_>,
     ) -> Result<(), Error<T::Error>> {
         #[cfg(feature = "log-04")]
-        log::debug!("c>MAIL FROM: <{}>", from.as_ref());
+        log::debug!(
+            "c>RCPT TO: <{}>{}",
+            recipient.address(),
+            RcptParameters(recipient)
+        );
+        self.begin_exchange()?;
+        self.stream
+            .write_multi(&[b"RCPT TO:<", recipient.address().as_bytes(), b">"])
+            .await
+            .map_err(Error::IoError)?;
+        self.write_display(RcptParameters(recipient)).await?;
         self.stream
-            .write_multi(&[b"MAIL FROM:<", from.as_ref().as_bytes(), b">\r\n"])
+            .write_single(b"\r\n")
             .await
             .map_err(Error::IoError)?;
+        self.reply_timeout = Some(self.timeouts.rcpt());
         let reply = self.read_multiline_reply().await?;
+
         // 250 or 554 are expected
-        if reply.code != 250 {
-            return Err(Error::MalformedError(MalformedError::UnexpectedCode {
-                expected: &[250],
-                actual: reply.code(),
-            }));
+        if reply.code != ReplyCode::OK {
+            return Err(Error::unexpected_reply(&reply, &[ReplyCode::OK]));
         }
+        self.accepted_recipients += 1;
+        Ok(())
+    }
 
-        // now we need to send the recipients
-        for recipient in to {
-            #[cfg(feature = "log-04")]
-            log::debug!("c>RCPT TO: <{}>", recipient.as_ref());
-            self.stream
-                .write_multi(&[b"RCPT TO:<", recipient.as_ref().as_bytes(), b">\r\n"])
-                .await
-                .map_err(Error::IoError)?;
-            let reply = self.read_multiline_reply().await?;
+    async fn data(&mut self, data: &[u8]) -> Result<(), Error<T::Error>> {
+        self.data_command().await?;
+        let reply = self.send_data(data).await?;
+        let first = data_result(&reply);
+        self.check_delivery(first).await
+    }
 
-            // 250 or 554 are expected
-            if reply.code != 250 {
-                return Err(Error::MalformedError(MalformedError::UnexpectedCode {
-                    expected: &[250],
-                    actual: reply.code(),
-                }));
+    // fails with the first failed delivery, after reading the replies of all recipients
+    async fn check_delivery(
+        &mut self,
+        first: Result<(), Error<T::Error>>,
+    ) -> Result<(), Error<T::Error>> {
+        let mut result = Ok(());
+        self.delivery_replies(first, |_, error| {
+            if result.is_ok() {
+                result = Err(error);
+            }
+        })
+        .await?;
+        result
+    }
+
+    // passes the failures among the replies to the end of the data to `failed`, with the
+    // position of the recipient among the accepted ones: in LMTP every accepted recipient
+    // gets a reply, `first` being the one of the first recipient
+    // https://datatracker.ietf.org/doc/html/rfc2033#section-4.2
+    async fn delivery_replies(
+        &mut self,
+        first: Result<(), Error<T::Error>>,
+        mut failed: impl FnMut(usize, Error<T::Error>),
+    ) -> Result<(), Error<T::Error>> {
+        // after a reply in the middle of the data, the server isn't going to send more
+        let replies = match self.protocol {
+            Protocol::Lmtp if self.is_usable() => self.accepted_recipients,
+            _ => 1,
+        };
+        if let Err(error) = first {
+            failed(0, error);
+        }
+        for position in 1..replies {
+            self.reply_pending = true;
+            self.reply_timeout = Some(self.timeouts.data_end());
+            let reply = self.read_next_reply().await?;
+            if let Err(error) = data_result(&reply) {
+                failed(position, error);
             }
         }
+        Ok(())
+    }
+
+    /// Reads another reply to the last command, which may have arrived together with the
+    /// previous one, e.g. the reply for each further recipient after
+    /// [`send_data`](Self::send_data) in an [LMTP](Protocol::Lmtp) session.
+    pub async fn read_next_reply(&mut self) -> Result<Reply<'_>, Error<T::Error>> {
+        let pending = self.buf_unprocessed.clone();
+        self.buf[..].copy_within(pending.clone(), 0);
+        self.buf_unprocessed = 0..pending.len();
+        self.read_buffered_reply().await
+    }
+
+    async fn data_command(&mut self) -> Result<(), Error<T::Error>> {
         #[cfg(feature = "log-04")]
         log::debug!("c>DATA");
+        self.begin_exchange()?;
         self.stream
             .write_single(b"DATA\r\n")
             .await
             .map_err(Error::IoError)?;
+        self.reply_timeout = Some(self.timeouts.data());
         let reply = self.read_multiline_reply().await?;
         // 354 or 554 are expected
-        if reply.code != 354 {
-            return Err(Error::MalformedError(MalformedError::UnexpectedCode {
-                expected: &[354],
-                actual: reply.code(),
-            }));
-        }
-        let reply = self.send_data(data).await?;
-        // 250 or 554 are expected
-        if reply.code != 250 {
-            return Err(Error::MalformedError(MalformedError::UnexpectedCode {
-                expected: &[250],
-                actual: reply.code(),
-            }));
+        if reply.code != ReplyCode::START_MAIL_INPUT {
+            let error = Error::unexpected_reply(&reply, &[ReplyCode::START_MAIL_INPUT]);
+            return Err(self.abort_transaction(error).await);
         }
         Ok(())
     }
@@ -508,6 +2468,12 @@ pub enum Extensions<'a> {
     StartTls,
     /// AUTH extension with supported mechanisms (e.g., "PLAIN LOGIN")
     Auth(&'a str),
+    /// Delivery Status Notifications, enables the parameters on [`Envelope`]
+    /// <https://datatracker.ietf.org/doc/html/rfc3461#section-4>
+    Dsn,
+    /// Replies carry an [`EnhancedCode`] before their text
+    /// <https://datatracker.ietf.org/doc/html/rfc2034#section-3>
+    EnhancedStatusCodes,
     Other(&'a str, &'a str),
 }
 
@@ -515,6 +2481,8 @@ impl Display for Extensions<'_> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Extensions::StartTls => write!(f, "STARTTLS"),
+            Extensions::Dsn => write!(f, "DSN"),
+            Extensions::EnhancedStatusCodes => write!(f, "ENHANCEDSTATUSCODES"),
             Extensions::Auth(mechanisms) => {
                 if mechanisms.is_empty() {
                     write!(f, "AUTH")
@@ -532,6 +2500,29 @@ impl Display for Extensions<'_> {
         }
     }
 }
+
+/// Well known extensions which don't have a dedicated variant.
+///
+/// These match regardless of the arguments the server advertised, so
+/// `ehlo.supports(Extensions::SIZE)` is true for `SIZE 10485760` too.
+impl Extensions<'static> {
+    /// Command pipelining
+    /// <https://datatracker.ietf.org/doc/html/rfc2920#section-3>
+    pub const PIPELINING: Extensions<'static> = Extensions::Other("PIPELINING", "");
+    /// Message size declaration, the argument is the maximum size in octets
+    /// <https://datatracker.ietf.org/doc/html/rfc1870#section-4>
+    pub const SIZE: Extensions<'static> = Extensions::Other("SIZE", "");
+    /// 8bit-MIME transport
+    /// <https://datatracker.ietf.org/doc/html/rfc6152#section-2>
+    pub const EIGHTBITMIME: Extensions<'static> = Extensions::Other("8BITMIME", "");
+    /// Internationalized email addresses and headers
+    /// <https://datatracker.ietf.org/doc/html/rfc6531#section-3.1>
+    pub const SMTPUTF8: Extensions<'static> = Extensions::Other("SMTPUTF8", "");
+    /// Sending the message in chunks with BDAT
+    /// <https://datatracker.ietf.org/doc/html/rfc3030#section-2>
+    pub const CHUNKING: Extensions<'static> = Extensions::Other("CHUNKING", "");
+}
+
 impl Extensions<'_> {
     #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Extensions<'_> {
@@ -556,6 +2547,14 @@ impl Extensions<'_> {
                 log::warn!("AUTH extension with no mechanisms advertised");
             }
             Extensions::Auth(args)
+        } else if keyword.eq_ignore_ascii_case("DSN") {
+            // RFC 3461 Section 4 defines DSN with no parameters.
+            // <https://datatracker.ietf.org/doc/html/rfc3461#section-4>
+            Extensions::Dsn
+        } else if keyword.eq_ignore_ascii_case("ENHANCEDSTATUSCODES") {
+            // RFC 2034 Section 3 defines ENHANCEDSTATUSCODES with no parameters.
+            // <https://datatracker.ietf.org/doc/html/rfc2034#section-3>
+            Extensions::EnhancedStatusCodes
         } else {
             Extensions::Other(keyword, args)
         }
@@ -598,20 +2597,143 @@ impl<'a> EhloResponse<'a> {
                         .any(|m| m.eq_ignore_ascii_case(wanted))
                 }
             }
+            // Keywords are case insensitive, and if no args are given we only
+            // care about the keyword. This is what makes `Extensions::SIZE` work.
+            // <https://datatracker.ietf.org/doc/html/rfc5321#section-2.4>
+            (Extensions::Other(keyword, args), Extensions::Other(wanted, wanted_args)) => {
+                keyword.eq_ignore_ascii_case(wanted)
+                    && (wanted_args.is_empty() || args == wanted_args)
+            }
             // Everything else uses structural equality
             _ => e == ext,
         })
     }
 
+    /// The maximum message size the server accepts, as advertised by the SIZE extension.
+    ///
+    /// Returns `None` if SIZE wasn't advertised or if the server declared no fixed limit
+    /// (either by leaving out the argument or by advertising `SIZE 0`).
+    /// <https://datatracker.ietf.org/doc/html/rfc1870#section-4>
+    pub fn max_size(&self) -> Option<u64> {
+        self.extensions().find_map(|e| match e {
+            Extensions::Other(keyword, args) if keyword.eq_ignore_ascii_case("SIZE") => {
+                args.trim().parse().ok().filter(|size| *size > 0)
+            }
+            _ => None,
+        })
+    }
+
+    /// Compares what the server advertised with what the client would like to use.
+    ///
+    /// The resulting report is logged, with unsatisfied features as warnings, so it's easy
+    /// to see why e.g. pipelining isn't being used.
+    pub fn negotiate(&self, desired: &DesiredFeatures<'_>) -> NegotiationReport {
+        Capabilities::from_ehlo(self).negotiate(desired)
+    }
+
     pub fn extensions<'b: 'a>(&'b self) -> impl Iterator<Item = Extensions<'a>> {
-        // Pass the full line to from_str - it handles keyword/args splitting
-        self.reply.lines().skip(1).map(Extensions::from_str)
+        // Pass the full line to from_str - it handles keyword/args splitting. The first line
+        // is the greeting, even if it isn't valid UTF-8
+        self.reply
+            .replies()
+            .skip(1)
+            .filter_map(|line| line.message().ok())
+            .map(Extensions::from_str)
+    }
+}
+
+/// The reply to [`Smtp::vrfy`] or [`Smtp::expn`], with a mailbox on every line.
+pub struct MailboxReply<'a> {
+    reply: Reply<'a>,
+    verified: bool,
+}
+
+impl<'a> Deref for MailboxReply<'a> {
+    type Target = Reply<'a>;
+    fn deref(&self) -> &Self::Target {
+        &self.reply
+    }
+}
+
+impl<'a> MailboxReply<'a> {
+    pub fn new(reply: Reply<'a>) -> Self {
+        MailboxReply {
+            reply,
+            verified: true,
+        }
+    }
+
+    fn unverified(reply: Reply<'a>) -> Self {
+        MailboxReply {
+            reply,
+            verified: false,
+        }
+    }
+
+    /// Returns false if the server answered `252`, refusing to verify the address.
+    pub fn is_verified(&self) -> bool {
+        self.verified
+    }
+
+    /// The mailboxes on the lines of the reply, usually `Name <address>`. Lines which aren't
+    /// a mailbox are skipped, as are all lines of an unverified reply.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use simple_smtp::smtp::{MailboxReply, Reply};
+    ///
+    /// let mut raw = *b"250-Jane Doe <jane@example.com>\r\n250 <john@example.com>\r\n";
+    /// let reply = MailboxReply::new(Reply::parse(&mut raw).unwrap());
+    /// let addresses: Vec<_> = reply.mailboxes().map(|m| m.address().as_str()).collect();
+    /// assert_eq!(addresses, ["jane@example.com", "john@example.com"]);
+    /// ```
+    pub fn mailboxes<'b: 'a>(&'b self) -> impl Iterator<Item = Mailbox<'a>> {
+        let lines = self.verified.then(|| self.reply.lines());
+        lines
+            .into_iter()
+            .flatten()
+            .filter_map(|line| Mailbox::parse(line).ok())
+    }
+}
+
+/// The recipients [`Smtp::send_mail_report`] sent the message to, and the ones the server
+/// rejected, each in the order they were given.
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub struct SendReport<E: core::error::Error> {
+    pub accepted: alloc::vec::Vec<alloc::string::String>,
+    pub rejected: alloc::vec::Vec<(alloc::string::String, Error<E>)>,
+}
+
+#[cfg(feature = "alloc")]
+impl<E: core::error::Error> SendReport<E> {
+    /// Returns true if the message was sent to at least one recipient.
+    pub fn is_sent(&self) -> bool {
+        !self.accepted.is_empty()
+    }
+}
+
+// the outcome of a reply to the end of the data, 250 or 554 are expected
+fn data_result<E: core::error::Error>(reply: &Reply<'_>) -> Result<(), Error<E>> {
+    match reply.code {
+        ReplyCode::OK => Ok(()),
+        _ => Err(Error::unexpected_reply(reply, &[ReplyCode::OK])),
+    }
+}
+
+// a command argument can't contain the line break ending the command
+fn check_argument(argument: &str) -> Result<(), ProtocolError> {
+    match argument.contains(['\r', '\n']) {
+        true => Err(ProtocolError::InvalidArgument),
+        false => Ok(()),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ReplyText;
 
     // Helper to build a buffer in the format Reply::from_buffer expects.
     // Format: [code: u16][msg_len: u16][message bytes...]
@@ -652,7 +2774,7 @@ mod tests {
     #[test]
     fn reply_from_buffer_single_line() {
         let buf = build_single_line_buffer(250, "OK");
-        let reply = Reply::from_buffer(&buf);
+        let reply = Reply::from_buffer(&buf).unwrap();
 
         assert_eq!(reply.code(), 250);
         assert_eq!(reply.current_line(), "OK");
@@ -661,7 +2783,7 @@ mod tests {
     #[test]
     fn reply_from_buffer_empty_message() {
         let buf = build_single_line_buffer(220, "");
-        let reply = Reply::from_buffer(&buf);
+        let reply = Reply::from_buffer(&buf).unwrap();
 
         assert_eq!(reply.code(), 220);
         assert_eq!(reply.current_line(), "");
@@ -671,27 +2793,63 @@ mod tests {
     fn reply_from_buffer_long_message() {
         let long_msg = "a".repeat(200);
         let buf = build_single_line_buffer(354, &long_msg);
-        let reply = Reply::from_buffer(&buf);
+        let reply = Reply::from_buffer(&buf).unwrap();
 
         assert_eq!(reply.code(), 354);
         assert_eq!(reply.current_line(), long_msg);
     }
 
     #[test]
-    #[should_panic(expected = "Buffer too small")]
     fn reply_from_buffer_too_small_header() {
         let buf = vec![0, 0, 0];
-        let _ = Reply::from_buffer(&buf);
+        assert!(matches!(
+            Reply::from_buffer(&buf),
+            Err(MalformedError::UnexpectedEof)
+        ));
     }
 
     #[test]
-    #[should_panic(expected = "Buffer too small")]
     fn reply_from_buffer_message_len_exceeds_buffer() {
         let mut buf = Vec::new();
         buf.extend_from_slice(&250u16.to_ne_bytes());
         buf.extend_from_slice(&10u16.to_ne_bytes()); // claims 10 bytes
         buf.extend_from_slice(b"hi"); // only 2 bytes
-        let _ = Reply::from_buffer(&buf);
+        assert!(matches!(
+            Reply::from_buffer(&buf),
+            Err(MalformedError::UnexpectedEof)
+        ));
+    }
+
+    #[test]
+    fn reply_from_buffer_invalid_utf8() {
+        // the text is only checked once it's accessed
+        let mut buf = build_single_line_buffer(250, "OK");
+        buf[4] = 0xff;
+        let reply = Reply::from_buffer(&buf).unwrap();
+        assert_eq!(reply.code(), 250);
+        assert!(!reply.is_utf8());
+        assert_eq!(reply.current_line(), "");
+        let line = reply.replies().next().unwrap();
+        assert_eq!(line.message_bytes(), b"\xffK");
+        assert!(matches!(
+            line.message(),
+            Err(MalformedError::InvalidEncoding)
+        ));
+        assert_eq!(line.to_string(), "250 \u{fffd}K");
+
+        // a broken later line is skipped, not the ones after it
+        let mut buf = build_multiline_buffer(250, &["host", "X-BROKEN", "SIZE 1000"]);
+        let broken = buf.windows(8).position(|w| w == b"X-BROKEN").unwrap();
+        buf[broken] = 0xff;
+        let reply = Reply::from_buffer(&buf).unwrap();
+        assert_eq!(reply.lines().collect::<Vec<_>>(), ["host", "SIZE 1000"]);
+        assert_eq!(reply.replies().count(), 3);
+        assert!(!reply.is_utf8());
+
+        let buf = build_multiline_buffer(250, &["host", "SIZE 1000"]);
+        let reply = Reply::from_buffer(&buf).unwrap();
+        assert!(reply.is_utf8());
+        assert_eq!(reply.lines().count(), 2);
     }
 
     // ══════════════════════════════════════════════════════════════════════════
@@ -701,7 +2859,7 @@ mod tests {
     #[test]
     fn reply_iterator_single_line() {
         let buf = build_single_line_buffer(250, "mail.example.com");
-        let reply = Reply::from_buffer(&buf);
+        let reply = Reply::from_buffer(&buf).unwrap();
 
         let lines: Vec<_> = reply.lines().collect();
         assert_eq!(lines, vec!["mail.example.com"]);
@@ -711,7 +2869,7 @@ mod tests {
     fn reply_iterator_multiline() {
         let buf =
             build_multiline_buffer(250, &["mail.example.com", "STARTTLS", "AUTH PLAIN LOGIN"]);
-        let reply = Reply::from_buffer(&buf);
+        let reply = Reply::from_buffer(&buf).unwrap();
 
         let lines: Vec<_> = reply.lines().collect();
         assert_eq!(
@@ -726,7 +2884,7 @@ mod tests {
     #[test]
     fn reply_iterator_empty_lines() {
         let buf = build_multiline_buffer(250, &["host", "", "SIZE 1000"]);
-        let reply = Reply::from_buffer(&buf);
+        let reply = Reply::from_buffer(&buf).unwrap();
 
         let lines: Vec<_> = reply.lines().collect();
         assert_eq!(lines, vec!["host", "", "SIZE 1000"]);
@@ -735,7 +2893,7 @@ mod tests {
     #[test]
     fn reply_code_accessor() {
         let buf = build_single_line_buffer(421, "Service not available");
-        let reply = Reply::from_buffer(&buf);
+        let reply = Reply::from_buffer(&buf).unwrap();
         assert_eq!(reply.code(), 421);
     }
 
@@ -798,6 +2956,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn extensions_dsn() {
+        assert_eq!(Extensions::from_str("DSN"), Extensions::Dsn);
+        assert_eq!(Extensions::from_str("dsn"), Extensions::Dsn);
+        assert_eq!(format!("{}", Extensions::Dsn), "DSN");
+    }
+
+    #[test]
+    fn extensions_enhanced_status_codes() {
+        assert_eq!(
+            Extensions::from_str("ENHANCEDSTATUSCODES"),
+            Extensions::EnhancedStatusCodes
+        );
+        assert_eq!(
+            format!("{}", Extensions::EnhancedStatusCodes),
+            "ENHANCEDSTATUSCODES"
+        );
+    }
+
     #[test]
     fn extensions_empty_string() {
         assert_eq!(Extensions::from_str(""), Extensions::Other("", ""));
@@ -810,22 +2987,22 @@ mod tests {
     #[test]
     fn replyline_accessors() {
         let line = ReplyLine {
-            code: 250,
+            code: ReplyCode::new(250),
             is_last: false,
-            message: "STARTTLS",
+            message: b"STARTTLS",
         };
 
         assert_eq!(line.code(), 250);
         assert!(!line.is_last());
-        assert_eq!(line.message(), "STARTTLS");
+        assert_eq!(line.message().unwrap(), "STARTTLS");
     }
 
     #[test]
     fn replyline_display_continuation() {
         let line = ReplyLine {
-            code: 250,
+            code: ReplyCode::new(250),
             is_last: false,
-            message: "mail.example.com",
+            message: b"mail.example.com",
         };
         assert_eq!(format!("{}", line), "250-mail.example.com");
     }
@@ -833,9 +3010,9 @@ mod tests {
     #[test]
     fn replyline_display_final() {
         let line = ReplyLine {
-            code: 250,
+            code: ReplyCode::new(250),
             is_last: true,
-            message: "OK",
+            message: b"OK",
         };
         assert_eq!(format!("{}", line), "250 OK");
     }
@@ -843,9 +3020,9 @@ mod tests {
     #[test]
     fn replyline_display_empty_message() {
         let line = ReplyLine {
-            code: 220,
+            code: ReplyCode::new(220),
             is_last: true,
-            message: "",
+            message: b"",
         };
         assert_eq!(format!("{}", line), "220 ");
     }
@@ -862,14 +3039,35 @@ mod tests {
 
         for (code, msg) in cases {
             let line = ReplyLine {
-                code,
+                code: ReplyCode::new(code),
                 is_last: true,
-                message: msg,
+                message: msg.as_bytes(),
             };
             assert_eq!(format!("{}", line), format!("{} {}", code, msg));
         }
     }
 
+    #[test]
+    fn replyline_enhanced_code() {
+        use enhanced::{Class, Subject};
+
+        let line = ReplyLine::parse("550 5.1.1 User unknown").unwrap();
+        let code = line.enhanced_code().unwrap();
+        assert_eq!(code.class(), Class::PermanentFailure);
+        assert_eq!(code.subject_kind(), Subject::Addressing);
+        assert_eq!(code.detail(), 1);
+
+        let line = ReplyLine::parse("250 OK").unwrap();
+        assert_eq!(line.enhanced_code(), None);
+    }
+
+    #[test]
+    fn reply_enhanced_code_from_first_line() {
+        let buf = build_multiline_buffer(451, &["4.7.1 Greylisted", "try again later"]);
+        let reply = Reply::from_buffer(&buf).unwrap();
+        assert_eq!(reply.enhanced_code().unwrap().to_string(), "4.7.1");
+    }
+
     // ══════════════════════════════════════════════════════════════════════════
     // Extensions Display tests
     // ══════════════════════════════════════════════════════════════════════════
@@ -929,8 +3127,8 @@ mod tests {
     #[test]
     fn malformed_error_display_unexpected_code() {
         let err = MalformedError::UnexpectedCode {
-            expected: &[250, 251],
-            actual: 550,
+            expected: &[ReplyCode::OK, ReplyCode::USER_NOT_LOCAL_WILL_FORWARD],
+            actual: ReplyCode::MAILBOX_UNAVAILABLE,
         };
         let msg = format!("{}", err);
         assert!(msg.contains("550"));
@@ -941,8 +3139,8 @@ mod tests {
     #[test]
     fn malformed_error_display_code_changed() {
         let err = MalformedError::CodeChanged {
-            old_code: 250,
-            new_code: 354,
+            old_code: ReplyCode::OK,
+            new_code: ReplyCode::START_MAIL_INPUT,
         };
         let msg = format!("{}", err);
         assert!(msg.contains("250"));
@@ -955,6 +3153,63 @@ mod tests {
         assert_eq!(format!("{}", err), "Unexpected EOF reached");
     }
 
+    #[test]
+    fn unexpected_reply_classifies_negative_codes() {
+        let buf = build_single_line_buffer(550, "5.1.1 User unknown");
+        let reply = Reply::from_buffer(&buf).unwrap();
+        let err = Error::<core::fmt::Error>::unexpected_reply(&reply, &[ReplyCode::OK]);
+        let Error::ServerRejected {
+            code,
+            enhanced,
+            message,
+        } = &err
+        else {
+            panic!("expected ServerRejected, got {err:?}");
+        };
+        assert_eq!(*code, ReplyCode::MAILBOX_UNAVAILABLE);
+        assert_eq!(enhanced.unwrap().to_string(), "5.1.1");
+        assert_eq!(message.as_str(), "5.1.1 User unknown");
+        assert!(err.is_permanent());
+        assert!(!err.is_transient());
+
+        let buf = build_single_line_buffer(421, "Try again later");
+        let reply = Reply::from_buffer(&buf).unwrap();
+        let err = Error::<core::fmt::Error>::unexpected_reply(&reply, &[ReplyCode::OK]);
+        assert!(err.is_transient());
+        assert_eq!(
+            err.to_string(),
+            "Server rejected command: 421 Try again later"
+        );
+
+        // a positive code where another was expected is a protocol violation, not a rejection
+        let buf = build_single_line_buffer(354, "Go ahead");
+        let reply = Reply::from_buffer(&buf).unwrap();
+        let err = Error::<core::fmt::Error>::unexpected_reply(&reply, &[ReplyCode::OK]);
+        assert!(matches!(
+            err,
+            Error::MalformedError(MalformedError::UnexpectedCode { .. })
+        ));
+        assert!(!err.is_transient() && !err.is_permanent());
+    }
+
+    #[test]
+    fn reply_text_joins_and_truncates() {
+        let buf = build_multiline_buffer(452, &["Too many", "recipients"]);
+        let reply = Reply::from_buffer(&buf).unwrap();
+        assert_eq!(
+            ReplyText::from_reply(&reply).as_str(),
+            "Too many recipients"
+        );
+
+        let long = "é".repeat(ReplyText::CAPACITY);
+        let text = ReplyText::new(&long);
+        // truncated at a character boundary, never splitting the two byte 'é'
+        assert_eq!(text.as_str().len(), ReplyText::CAPACITY);
+        assert!(text.as_str().chars().all(|c| c == 'é'));
+        let odd = format!("x{long}");
+        assert_eq!(ReplyText::new(&odd).as_str().len(), ReplyText::CAPACITY - 1);
+    }
+
     // ══════════════════════════════════════════════════════════════════════════
     // Reply::replies() tests (ReplyLine iterator)
     // ══════════════════════════════════════════════════════════════════════════
@@ -962,19 +3217,19 @@ mod tests {
     #[test]
     fn reply_replies_single_line() {
         let buf = build_single_line_buffer(250, "OK");
-        let reply = Reply::from_buffer(&buf);
+        let reply = Reply::from_buffer(&buf).unwrap();
 
         let lines: Vec<_> = reply.replies().collect();
         assert_eq!(lines.len(), 1);
         assert_eq!(lines[0].code(), 250);
-        assert_eq!(lines[0].message(), "OK");
+        assert_eq!(lines[0].message().unwrap(), "OK");
         assert!(lines[0].is_last());
     }
 
     #[test]
     fn reply_replies_multiline_is_last_flags() {
         let buf = build_multiline_buffer(250, &["host.example.com", "STARTTLS", "SIZE 1000"]);
-        let reply = Reply::from_buffer(&buf);
+        let reply = Reply::from_buffer(&buf).unwrap();
 
         let lines: Vec<_> = reply.replies().collect();
         assert_eq!(lines.len(), 3);
@@ -991,6 +3246,88 @@ mod tests {
         }
     }
 
+    // ══════════════════════════════════════════════════════════════════════════
+    // ReplyLine::parse / Reply::parse tests
+    // ══════════════════════════════════════════════════════════════════════════
+
+    #[test]
+    fn replyline_parse() {
+        let line = ReplyLine::parse("250 OK").unwrap();
+        assert_eq!(line.code(), 250);
+        assert!(line.is_last());
+        assert_eq!(line.message().unwrap(), "OK");
+
+        let line = ReplyLine::parse("250-PIPELINING\r\n").unwrap();
+        assert!(!line.is_last());
+        assert_eq!(line.message().unwrap(), "PIPELINING");
+    }
+
+    #[test]
+    fn replyline_parse_errors() {
+        assert!(matches!(
+            ReplyLine::parse("25"),
+            Err(MalformedError::NoCode)
+        ));
+        assert!(matches!(
+            ReplyLine::parse("+25 OK"),
+            Err(MalformedError::NoCode)
+        ));
+        assert!(matches!(
+            ReplyLine::parse("250_OK"),
+            Err(MalformedError::InvalidEncoding)
+        ));
+        assert!(matches!(
+            ReplyLine::parse("250 OK\nmore"),
+            Err(MalformedError::InvalidLineTermination)
+        ));
+        assert!(matches!(
+            ReplyLine::parse("250 OK\r"),
+            Err(MalformedError::InvalidLineTermination)
+        ));
+    }
+
+    #[test]
+    fn reply_parse_multiline() {
+        let mut raw = b"250-mail.example.com\r\n250-STARTTLS\r\n250 SIZE 1000\r\n".to_vec();
+        let reply = Reply::parse(&mut raw).unwrap();
+        assert_eq!(reply.code(), 250);
+        let lines: Vec<_> = reply.replies().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1].message().unwrap(), "STARTTLS");
+        assert!(!lines[1].is_last());
+        assert!(lines[2].is_last());
+    }
+
+    #[test]
+    fn reply_parse_errors() {
+        let mut incomplete = b"250-mail.example.com\r\n".to_vec();
+        assert!(matches!(
+            Reply::parse(&mut incomplete),
+            Err(MalformedError::UnexpectedEof)
+        ));
+
+        let mut changed = b"250-mail.example.com\r\n251 OK\r\n".to_vec();
+        assert!(matches!(
+            Reply::parse(&mut changed),
+            Err(MalformedError::CodeChanged {
+                old_code: ReplyCode::OK,
+                new_code: ReplyCode::USER_NOT_LOCAL_WILL_FORWARD
+            })
+        ));
+
+        let mut trailing = b"250 OK\r\n354 Go ahead\r\n".to_vec();
+        assert!(matches!(
+            Reply::parse(&mut trailing),
+            Err(MalformedError::TrailingData)
+        ));
+
+        // only the text is broken, which is checked when it's accessed
+        let mut invalid_utf8 = b"250 \xff\r\n".to_vec();
+        let reply = Reply::parse(&mut invalid_utf8).unwrap();
+        assert_eq!(reply.code(), 250);
+        assert!(!reply.is_utf8());
+    }
+
     // ══════════════════════════════════════════════════════════════════════════
     // EhloResponse::supports() tests
     // ══════════════════════════════════════════════════════════════════════════
@@ -998,29 +3335,83 @@ mod tests {
     #[test]
     fn ehlo_supports_starttls() {
         let buf = build_multiline_buffer(250, &["mail.example.com", "STARTTLS", "SIZE 1000"]);
-        let reply = Reply::from_buffer(&buf);
+        let reply = Reply::from_buffer(&buf).unwrap();
         let ehlo = EhloResponse::new(reply);
 
         assert!(ehlo.supports(Extensions::StartTls));
         assert!(!ehlo.supports(Extensions::Auth("")));
     }
 
+    #[test]
+    fn ehlo_skips_lines_with_invalid_utf8() {
+        let mut buf = build_multiline_buffer(
+            250,
+            &["mail.example.com", "X-BROKEN", "STARTTLS", "SIZE 1000"],
+        );
+        let broken = buf.windows(8).position(|w| w == b"X-BROKEN").unwrap();
+        buf[broken + 1] = 0xff;
+        let ehlo = EhloResponse::new(Reply::from_buffer(&buf).unwrap());
+
+        assert!(ehlo.supports(Extensions::StartTls));
+        assert_eq!(ehlo.max_size(), Some(1000));
+        assert_eq!(ehlo.extensions().count(), 2);
+
+        // nor is the first line taken for an extension if the greeting is broken
+        let mut buf = build_multiline_buffer(250, &["mail.example.com", "STARTTLS"]);
+        buf[4] = 0xff;
+        let ehlo = EhloResponse::new(Reply::from_buffer(&buf).unwrap());
+        assert_eq!(ehlo.extensions().count(), 1);
+        assert!(ehlo.supports(Extensions::StartTls));
+    }
+
     #[test]
     fn ehlo_supports_auth_any() {
         // When checking Auth(""), we're asking "does the server support AUTH at all?"
         let buf = build_multiline_buffer(250, &["mail.example.com", "AUTH PLAIN LOGIN"]);
-        let reply = Reply::from_buffer(&buf);
+        let reply = Reply::from_buffer(&buf).unwrap();
         let ehlo = EhloResponse::new(reply);
 
         // Should return true for Auth("") meaning "any AUTH"
         assert!(ehlo.supports(Extensions::Auth("")));
     }
 
+    #[test]
+    fn ehlo_supports_known_constants() {
+        let buf = build_multiline_buffer(
+            250,
+            &[
+                "mail.example.com",
+                "pipelining",
+                "SIZE 10485760",
+                "8BITMIME",
+            ],
+        );
+        let ehlo = EhloResponse::new(Reply::from_buffer(&buf).unwrap());
+
+        assert!(ehlo.supports(Extensions::PIPELINING));
+        assert!(ehlo.supports(Extensions::SIZE));
+        assert!(ehlo.supports(Extensions::EIGHTBITMIME));
+        assert!(!ehlo.supports(Extensions::SMTPUTF8));
+        assert!(ehlo.supports(Extensions::Other("SIZE", "10485760")));
+        assert!(!ehlo.supports(Extensions::Other("SIZE", "1000")));
+        assert_eq!(ehlo.max_size(), Some(10485760));
+    }
+
+    #[test]
+    fn ehlo_max_size_without_limit() {
+        for size in ["SIZE", "SIZE 0"] {
+            let buf = build_multiline_buffer(250, &["mail.example.com", size]);
+            let ehlo = EhloResponse::new(Reply::from_buffer(&buf).unwrap());
+            assert!(ehlo.supports(Extensions::SIZE));
+            assert_eq!(ehlo.max_size(), None);
+        }
+    }
+
     #[test]
     fn ehlo_supports_auth_specific_mechanism() {
         // Server advertises AUTH PLAIN LOGIN
         let buf = build_multiline_buffer(250, &["mail.example.com", "AUTH PLAIN LOGIN"]);
-        let reply = Reply::from_buffer(&buf);
+        let reply = Reply::from_buffer(&buf).unwrap();
         let ehlo = EhloResponse::new(reply);
 
         // Should be able to check for specific mechanisms
@@ -0,0 +1,82 @@
+//! How a session authenticates with the server.
+
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+
+use super::Reply;
+
+/// The way a client proves its identity to the server.
+///
+/// Passed to [`Smtp::authenticate`](crate::Smtp::authenticate).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMode<'a> {
+    /// Don't authenticate, e.g. for relays that accept mail from trusted networks.
+    None,
+    /// `AUTH PLAIN` with a username and password.
+    /// <https://datatracker.ietf.org/doc/html/rfc4954>
+    Plain {
+        username: &'a str,
+        password: &'a str,
+    },
+    /// The TLS client certificate is the only credential; no `AUTH` command is sent.
+    ///
+    /// Some relays authenticate solely by mutual TLS and accept `MAIL FROM` right after
+    /// `EHLO`. If the server still demands `AUTH` it means the certificate wasn't accepted,
+    /// which is reported as [`ProtocolError::ClientCertificateNotAccepted`](crate::ProtocolError::ClientCertificateNotAccepted)
+    /// instead of a bare `530` rejection.
+    ClientCertOnly,
+}
+
+/// How the server answered a step of [`Smtp::auth_begin`](crate::Smtp::auth_begin).
+pub enum AuthStep<'a> {
+    /// `334`, the server waits for the response to this challenge, decoded from base64.
+    Challenge(&'a [u8]),
+    /// `235`, the client is authenticated.
+    Success(Reply<'a>),
+}
+
+/// Credentials fetched from a [`CredentialProvider`] when the session authenticates.
+#[cfg(feature = "alloc")]
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credentials {
+    /// For `AUTH PLAIN`.
+    Password { username: String, password: String },
+    /// An OAuth 2.0 access token, for `AUTH XOAUTH2`.
+    /// <https://developers.google.com/workspace/gmail/imap/xoauth2-protocol>
+    OAuth2 { username: String, token: String },
+}
+
+// the secrets stay out of logs
+#[cfg(feature = "alloc")]
+impl core::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let (kind, username) = match self {
+            Credentials::Password { username, .. } => ("Password", username),
+            Credentials::OAuth2 { username, .. } => ("OAuth2", username),
+        };
+        f.debug_struct(kind)
+            .field("username", username)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Fetches credentials right before they are needed, from a vault, a KMS or the system's
+/// keyring, instead of keeping them in memory for the lifetime of the client.
+///
+/// Passed to [`Smtp::authenticate_with`](crate::Smtp::authenticate_with), which drops the
+/// credentials once they were sent.
+#[cfg(feature = "alloc")]
+pub trait CredentialProvider {
+    type Error;
+
+    /// The current credentials. Called every time a session authenticates, so an
+    /// implementation may cache them, e.g. until a token expires.
+    fn credentials(&self) -> impl Future<Output = Result<Credentials, Self::Error>>;
+
+    /// Called when the server rejected the last credentials as invalid (`535`), e.g. after a
+    /// password was rotated or a token revoked, before they are fetched again. Does nothing
+    /// by default.
+    fn refresh(&self) -> impl Future<Output = Result<(), Self::Error>> {
+        async { Ok(()) }
+    }
+}
@@ -0,0 +1,245 @@
+//! The server's capabilities, kept on the session after the reply they came from is gone.
+//!
+//! An [`EhloResponse`] borrows the read buffer, so it is invalidated by the next command.
+//! [`Capabilities`] stores the parts the client acts on in a few bytes, so later commands
+//! can check what the server supports.
+
+use core::fmt::Display;
+
+use super::{
+    EhloResponse, Extensions,
+    negotiation::{DesiredFeatures, FeatureStatus, NegotiationReport},
+};
+
+/// SASL mechanisms the client knows about.
+/// <https://www.iana.org/assignments/sasl-mechanisms/sasl-mechanisms.xhtml>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMechanism {
+    Plain,
+    Login,
+    CramMd5,
+    XOAuth2,
+    OAuthBearer,
+    ScramSha1,
+    ScramSha256,
+}
+
+impl AuthMechanism {
+    const ALL: [AuthMechanism; 7] = [
+        AuthMechanism::Plain,
+        AuthMechanism::Login,
+        AuthMechanism::CramMd5,
+        AuthMechanism::XOAuth2,
+        AuthMechanism::OAuthBearer,
+        AuthMechanism::ScramSha1,
+        AuthMechanism::ScramSha256,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthMechanism::Plain => "PLAIN",
+            AuthMechanism::Login => "LOGIN",
+            AuthMechanism::CramMd5 => "CRAM-MD5",
+            AuthMechanism::XOAuth2 => "XOAUTH2",
+            AuthMechanism::OAuthBearer => "OAUTHBEARER",
+            AuthMechanism::ScramSha1 => "SCRAM-SHA-1",
+            AuthMechanism::ScramSha256 => "SCRAM-SHA-256",
+        }
+    }
+
+    /// Mechanism names are case insensitive.
+    /// <https://datatracker.ietf.org/doc/html/rfc4422#section-3.1>
+    pub fn from_name(name: &str) -> Option<AuthMechanism> {
+        Self::ALL
+            .into_iter()
+            .find(|m| m.as_str().eq_ignore_ascii_case(name))
+    }
+
+    fn bit(&self) -> u8 {
+        1 << *self as u8
+    }
+}
+
+impl Display for AuthMechanism {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+const STARTTLS: u16 = 1;
+const AUTH: u16 = 1 << 1;
+const DSN: u16 = 1 << 2;
+const ENHANCED_STATUS_CODES: u16 = 1 << 3;
+const PIPELINING: u16 = 1 << 4;
+const SIZE: u16 = 1 << 5;
+const EIGHTBITMIME: u16 = 1 << 6;
+const SMTPUTF8: u16 = 1 << 7;
+const CHUNKING: u16 = 1 << 8;
+
+/// The extensions a server advertised in its last EHLO response.
+///
+/// Only the extensions with a constant on [`Extensions`] and the known [`AuthMechanism`]s
+/// are kept, anything else is reported as unsupported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    extensions: u16,
+    auth_mechanisms: u8,
+    max_size: Option<u64>,
+}
+
+impl Capabilities {
+    /// No extensions at all, as for a server that hasn't been greeted with EHLO.
+    pub const fn none() -> Self {
+        Capabilities {
+            extensions: 0,
+            auth_mechanisms: 0,
+            max_size: None,
+        }
+    }
+
+    pub fn from_ehlo(ehlo: &EhloResponse<'_>) -> Self {
+        let mut capabilities = Capabilities::none();
+        for extension in ehlo.extensions() {
+            match extension {
+                Extensions::Auth(mechanisms) => {
+                    capabilities.extensions |= AUTH;
+                    for mechanism in mechanisms
+                        .split_whitespace()
+                        .filter_map(AuthMechanism::from_name)
+                    {
+                        capabilities.auth_mechanisms |= mechanism.bit();
+                    }
+                }
+                other => capabilities.extensions |= Self::flag(&other).unwrap_or(0),
+            }
+        }
+        capabilities.max_size = ehlo.max_size();
+        capabilities
+    }
+
+    fn flag(extension: &Extensions<'_>) -> Option<u16> {
+        Some(match extension {
+            Extensions::StartTls => STARTTLS,
+            Extensions::Auth(_) => AUTH,
+            Extensions::Dsn => DSN,
+            Extensions::EnhancedStatusCodes => ENHANCED_STATUS_CODES,
+            Extensions::Other(keyword, _) => [
+                (Extensions::PIPELINING, PIPELINING),
+                (Extensions::SIZE, SIZE),
+                (Extensions::EIGHTBITMIME, EIGHTBITMIME),
+                (Extensions::SMTPUTF8, SMTPUTF8),
+                (Extensions::CHUNKING, CHUNKING),
+            ]
+            .into_iter()
+            .find_map(|(known, flag)| match known {
+                Extensions::Other(name, _) if name.eq_ignore_ascii_case(keyword) => Some(flag),
+                _ => None,
+            })?,
+        })
+    }
+
+    /// Like [`EhloResponse::supports`], but arguments other than AUTH mechanisms are ignored.
+    pub fn supports(&self, extension: Extensions<'_>) -> bool {
+        match extension {
+            Extensions::Auth(mechanism) if !mechanism.is_empty() => {
+                AuthMechanism::from_name(mechanism).is_some_and(|m| self.supports_auth(m))
+            }
+            other => Self::flag(&other).is_some_and(|flag| self.extensions & flag != 0),
+        }
+    }
+
+    pub fn supports_auth(&self, mechanism: AuthMechanism) -> bool {
+        self.auth_mechanisms & mechanism.bit() != 0
+    }
+
+    /// The SASL mechanisms the server offered, in the order of [`AuthMechanism`].
+    pub fn auth_mechanisms(&self) -> impl Iterator<Item = AuthMechanism> {
+        let capabilities = *self;
+        AuthMechanism::ALL
+            .into_iter()
+            .filter(move |m| capabilities.supports_auth(*m))
+    }
+
+    /// See [`EhloResponse::max_size`].
+    pub fn max_size(&self) -> Option<u64> {
+        self.max_size
+    }
+
+    /// Compares `desired` with these capabilities, see [`EhloResponse::negotiate`].
+    ///
+    /// The resulting report is logged, with unsatisfied features as warnings, so it's easy
+    /// to see why e.g. pipelining isn't being used.
+    pub fn negotiate(&self, desired: &DesiredFeatures<'_>) -> NegotiationReport {
+        let status = |requested: bool, ext: Extensions| match (requested, self.supports(ext)) {
+            (false, _) => FeatureStatus::NotRequested,
+            (true, true) => FeatureStatus::Satisfied,
+            (true, false) => FeatureStatus::Unsatisfied,
+        };
+        let size = match desired.message_size {
+            None => FeatureStatus::NotRequested,
+            Some(_) if !self.supports(Extensions::SIZE) => FeatureStatus::Unsatisfied,
+            Some(size) => match self.max_size() {
+                Some(limit) if limit < size => FeatureStatus::Unsatisfied,
+                _ => FeatureStatus::Satisfied,
+            },
+        };
+        let report = NegotiationReport {
+            pipelining: status(desired.pipelining, Extensions::PIPELINING),
+            dsn: status(desired.dsn, Extensions::Dsn),
+            smtputf8: status(desired.smtputf8, Extensions::SMTPUTF8),
+            size,
+            auth: match desired.auth_mechanism {
+                None => FeatureStatus::NotRequested,
+                Some(mechanism) => status(true, Extensions::Auth(mechanism)),
+            },
+        };
+        #[cfg(feature = "log-04")]
+        if report.all_satisfied() {
+            log::info!("negotiated extensions: {report}");
+        } else {
+            log::warn!("not all desired extensions are available: {report}");
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smtp::Reply;
+
+    fn capabilities(lines: &str) -> Capabilities {
+        let mut raw = lines.as_bytes().to_vec();
+        let reply = Reply::parse(&mut raw).unwrap();
+        Capabilities::from_ehlo(&EhloResponse::new(reply))
+    }
+
+    #[test]
+    fn from_ehlo() {
+        let caps = capabilities(
+            "250-mail.example.com\r\n250-pipelining\r\n250-SIZE 1000\r\n250-DSN\r\n250-X-UNKNOWN\r\n250 AUTH LOGIN plain X-CUSTOM\r\n",
+        );
+        assert!(caps.supports(Extensions::PIPELINING));
+        assert!(caps.supports(Extensions::SIZE));
+        assert!(caps.supports(Extensions::Dsn));
+        assert!(caps.supports(Extensions::Auth("")));
+        assert!(caps.supports(Extensions::Auth("PLAIN")));
+        assert!(!caps.supports(Extensions::Auth("CRAM-MD5")));
+        assert!(!caps.supports(Extensions::Auth("X-CUSTOM")));
+        assert!(!caps.supports(Extensions::StartTls));
+        assert!(!caps.supports(Extensions::Other("X-UNKNOWN", "")));
+        assert_eq!(caps.max_size(), Some(1000));
+        assert_eq!(
+            caps.auth_mechanisms().collect::<Vec<_>>(),
+            [AuthMechanism::Plain, AuthMechanism::Login]
+        );
+    }
+
+    #[test]
+    fn none() {
+        let caps = Capabilities::none();
+        assert!(!caps.supports(Extensions::Auth("")));
+        assert_eq!(caps.auth_mechanisms().count(), 0);
+        assert_eq!(caps, capabilities("250 mail.example.com\r\n"));
+    }
+}
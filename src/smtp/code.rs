@@ -0,0 +1,197 @@
+//! Typed SMTP reply codes.
+//!
+//! Every reply starts with a three digit code. The first digit says whether the command
+//! succeeded, failed temporarily or failed permanently, which is usually all the client
+//! needs to decide what to do next.
+//! <https://datatracker.ietf.org/doc/html/rfc5321#section-4.2.1>
+
+use core::fmt::Display;
+
+/// A three digit SMTP reply code.
+///
+/// Compares equal to its raw `u16` value, and the well known codes are available as
+/// constants so they can be used in match arms:
+///
+/// ```
+/// use simple_smtp::smtp::ReplyCode;
+///
+/// let code = ReplyCode::new(451);
+/// assert!(code.is_transient());
+/// assert_eq!(code, 451);
+/// match code {
+///     ReplyCode::OK => println!("accepted"),
+///     ReplyCode::LOCAL_ERROR => println!("try again later"),
+///     other => println!("unexpected {other}"),
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ReplyCode(u16);
+
+impl ReplyCode {
+    /// `211` System status, or system help reply
+    pub const SYSTEM_STATUS: ReplyCode = ReplyCode(211);
+    /// `214` Help message
+    pub const HELP_MESSAGE: ReplyCode = ReplyCode(214);
+    /// `220` Service ready
+    pub const SERVICE_READY: ReplyCode = ReplyCode(220);
+    /// `221` Service closing transmission channel
+    pub const SERVICE_CLOSING: ReplyCode = ReplyCode(221);
+    /// `235` Authentication successful
+    /// <https://datatracker.ietf.org/doc/html/rfc4954#section-6>
+    pub const AUTH_SUCCESSFUL: ReplyCode = ReplyCode(235);
+    /// `250` Requested mail action okay, completed
+    pub const OK: ReplyCode = ReplyCode(250);
+    /// `251` User not local; will forward
+    pub const USER_NOT_LOCAL_WILL_FORWARD: ReplyCode = ReplyCode(251);
+    /// `252` Cannot VRFY user, but will accept message and attempt delivery
+    pub const CANNOT_VERIFY: ReplyCode = ReplyCode(252);
+    /// `334` Server challenge during authentication
+    /// <https://datatracker.ietf.org/doc/html/rfc4954#section-4>
+    pub const AUTH_CONTINUE: ReplyCode = ReplyCode(334);
+    /// `354` Start mail input; end with `<CRLF>.<CRLF>`
+    pub const START_MAIL_INPUT: ReplyCode = ReplyCode(354);
+    /// `421` Service not available, closing transmission channel
+    pub const SERVICE_NOT_AVAILABLE: ReplyCode = ReplyCode(421);
+    /// `450` Requested mail action not taken: mailbox unavailable
+    pub const MAILBOX_BUSY: ReplyCode = ReplyCode(450);
+    /// `451` Requested action aborted: local error in processing
+    pub const LOCAL_ERROR: ReplyCode = ReplyCode(451);
+    /// `452` Requested action not taken: insufficient system storage
+    pub const INSUFFICIENT_STORAGE: ReplyCode = ReplyCode(452);
+    /// `455` Server unable to accommodate parameters
+    pub const UNABLE_TO_ACCOMMODATE_PARAMETERS: ReplyCode = ReplyCode(455);
+    /// `500` Syntax error, command unrecognized
+    pub const SYNTAX_ERROR: ReplyCode = ReplyCode(500);
+    /// `501` Syntax error in parameters or arguments
+    pub const PARAMETER_SYNTAX_ERROR: ReplyCode = ReplyCode(501);
+    /// `502` Command not implemented
+    pub const COMMAND_NOT_IMPLEMENTED: ReplyCode = ReplyCode(502);
+    /// `503` Bad sequence of commands
+    pub const BAD_SEQUENCE: ReplyCode = ReplyCode(503);
+    /// `504` Command parameter not implemented
+    pub const PARAMETER_NOT_IMPLEMENTED: ReplyCode = ReplyCode(504);
+    /// `530` Authentication required, or STARTTLS required first
+    /// <https://datatracker.ietf.org/doc/html/rfc4954#section-6>
+    pub const AUTH_REQUIRED: ReplyCode = ReplyCode(530);
+    /// `535` Authentication credentials invalid
+    /// <https://datatracker.ietf.org/doc/html/rfc4954#section-6>
+    pub const AUTH_FAILED: ReplyCode = ReplyCode(535);
+    /// `550` Requested action not taken: mailbox unavailable
+    pub const MAILBOX_UNAVAILABLE: ReplyCode = ReplyCode(550);
+    /// `551` User not local
+    pub const USER_NOT_LOCAL: ReplyCode = ReplyCode(551);
+    /// `552` Requested mail action aborted: exceeded storage allocation
+    pub const EXCEEDED_STORAGE: ReplyCode = ReplyCode(552);
+    /// `553` Requested action not taken: mailbox name not allowed
+    pub const MAILBOX_NAME_NOT_ALLOWED: ReplyCode = ReplyCode(553);
+    /// `554` Transaction failed
+    pub const TRANSACTION_FAILED: ReplyCode = ReplyCode(554);
+    /// `555` MAIL FROM/RCPT TO parameters not recognized or not implemented
+    pub const PARAMETERS_NOT_RECOGNIZED: ReplyCode = ReplyCode(555);
+
+    pub const fn new(code: u16) -> Self {
+        ReplyCode(code)
+    }
+
+    /// The raw numeric value.
+    pub const fn as_u16(&self) -> u16 {
+        self.0
+    }
+
+    /// The first digit of the code.
+    pub const fn class(&self) -> u8 {
+        (self.0 / 100) as u8
+    }
+
+    /// `1yz`: the command was accepted, but the server waits for confirmation.
+    /// SMTP doesn't use these, but the class is defined for completeness.
+    pub const fn is_positive_preliminary(&self) -> bool {
+        self.class() == 1
+    }
+
+    /// `2yz`: the command succeeded.
+    pub const fn is_positive_completion(&self) -> bool {
+        self.class() == 2
+    }
+
+    /// `3yz`: the command was accepted, but the server needs more information.
+    pub const fn is_positive_intermediate(&self) -> bool {
+        self.class() == 3
+    }
+
+    /// `4yz`: the command failed, but the same command may succeed later.
+    /// These are the codes worth retrying.
+    pub const fn is_transient(&self) -> bool {
+        self.class() == 4
+    }
+
+    /// `5yz`: the command failed and retrying it unchanged won't help.
+    pub const fn is_permanent(&self) -> bool {
+        self.class() == 5
+    }
+
+    /// Returns true for either transient or permanent failures.
+    pub const fn is_negative(&self) -> bool {
+        self.is_transient() || self.is_permanent()
+    }
+}
+
+impl From<u16> for ReplyCode {
+    fn from(code: u16) -> Self {
+        ReplyCode(code)
+    }
+}
+
+impl From<ReplyCode> for u16 {
+    fn from(code: ReplyCode) -> Self {
+        code.0
+    }
+}
+
+impl PartialEq<u16> for ReplyCode {
+    fn eq(&self, other: &u16) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<ReplyCode> for u16 {
+    fn eq(&self, other: &ReplyCode) -> bool {
+        *self == other.0
+    }
+}
+
+impl Display for ReplyCode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:03}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classes() {
+        assert!(ReplyCode::OK.is_positive_completion());
+        assert!(ReplyCode::START_MAIL_INPUT.is_positive_intermediate());
+        assert!(ReplyCode::SERVICE_NOT_AVAILABLE.is_transient());
+        assert!(!ReplyCode::SERVICE_NOT_AVAILABLE.is_permanent());
+        assert!(ReplyCode::MAILBOX_UNAVAILABLE.is_permanent());
+        assert!(ReplyCode::MAILBOX_UNAVAILABLE.is_negative());
+        assert!(!ReplyCode::OK.is_negative());
+    }
+
+    #[test]
+    fn raw_value_comparisons() {
+        assert_eq!(ReplyCode::OK, 250);
+        assert_eq!(250, ReplyCode::OK);
+        assert_eq!(ReplyCode::from(354), ReplyCode::START_MAIL_INPUT);
+        assert_eq!(u16::from(ReplyCode::AUTH_FAILED), 535);
+        assert_eq!(ReplyCode::new(421).as_u16(), 421);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(ReplyCode::OK.to_string(), "250");
+    }
+}
@@ -0,0 +1,443 @@
+//! Enhanced mail system status codes.
+//!
+//! Servers advertising `ENHANCEDSTATUSCODES` prefix the text of their replies with a
+//! structured `class.subject.detail` code, e.g. `550 5.1.1 User unknown`.
+//! The basic reply code only tells you *whether* something failed, the enhanced code tells
+//! you *what* failed, e.g. a bad mailbox (`5.1.1`) versus a policy rejection (`5.7.1`).
+//!
+//! [`EnhancedCode::explanation`] turns a code into a short text for users, like "Mailbox
+//! full", with a stable key to look up a translation.
+//!
+//! **References:**
+//! - [RFC 2034 - SMTP Service Extension for Returning Enhanced Error Codes](https://datatracker.ietf.org/doc/html/rfc2034)
+//! - [RFC 3463 - Enhanced Mail System Status Codes](https://datatracker.ietf.org/doc/html/rfc3463)
+
+use core::fmt::Display;
+
+/// The class of an enhanced status code, i.e. its first digit.
+/// <https://datatracker.ietf.org/doc/html/rfc3463#section-3.1>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    /// `2.X.X`: the operation succeeded.
+    Success,
+    /// `4.X.X`: the operation failed, but may succeed if retried later.
+    PersistentTransientFailure,
+    /// `5.X.X`: the operation failed and will not succeed if retried as-is.
+    PermanentFailure,
+}
+
+impl Class {
+    pub fn as_digit(&self) -> u8 {
+        match self {
+            Class::Success => 2,
+            Class::PersistentTransientFailure => 4,
+            Class::PermanentFailure => 5,
+        }
+    }
+}
+
+/// The subject of an enhanced status code, i.e. its second number.
+/// <https://datatracker.ietf.org/doc/html/rfc3463#section-3.2>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subject {
+    /// `X.0.X`
+    Other,
+    /// `X.1.X`: problems with the sender or recipient address.
+    Addressing,
+    /// `X.2.X`: problems with the recipient's mailbox, e.g. it is full.
+    Mailbox,
+    /// `X.3.X`: problems with the destination mail system.
+    MailSystem,
+    /// `X.4.X`: network and routing problems.
+    Network,
+    /// `X.5.X`: problems with the mail delivery protocol.
+    Protocol,
+    /// `X.6.X`: problems with the message content or media.
+    Content,
+    /// `X.7.X`: security or policy problems.
+    SecurityOrPolicy,
+    /// A subject not defined by RFC 3463.
+    Unknown(u16),
+}
+
+/// A parsed enhanced status code like `5.1.1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnhancedCode {
+    class: Class,
+    subject: u16,
+    detail: u16,
+}
+
+impl EnhancedCode {
+    pub const fn new(class: Class, subject: u16, detail: u16) -> Self {
+        EnhancedCode {
+            class,
+            subject,
+            detail,
+        }
+    }
+
+    pub fn class(&self) -> Class {
+        self.class
+    }
+
+    /// The raw subject number.
+    pub fn subject(&self) -> u16 {
+        self.subject
+    }
+
+    /// The subject as one of the categories defined by RFC 3463.
+    pub fn subject_kind(&self) -> Subject {
+        match self.subject {
+            0 => Subject::Other,
+            1 => Subject::Addressing,
+            2 => Subject::Mailbox,
+            3 => Subject::MailSystem,
+            4 => Subject::Network,
+            5 => Subject::Protocol,
+            6 => Subject::Content,
+            7 => Subject::SecurityOrPolicy,
+            other => Subject::Unknown(other),
+        }
+    }
+
+    pub fn detail(&self) -> u16 {
+        self.detail
+    }
+
+    /// A short explanation of the code, for showing to users.
+    ///
+    /// Codes which aren't in [`EXPLANATIONS`] get the explanation of their subject, i.e.
+    /// `5.2.9` is explained like `5.2.0`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use simple_smtp::smtp::enhanced::EnhancedCode;
+    ///
+    /// let (code, _) = EnhancedCode::parse_prefix("4.2.2 Over quota").unwrap();
+    /// assert_eq!(code.explanation().key, "mailbox.full");
+    /// assert_eq!(code.explanation().english, "Mailbox full");
+    /// ```
+    pub fn explanation(&self) -> Explanation {
+        let find = |detail| {
+            EXPLANATIONS
+                .iter()
+                .find(|(subject, d, _)| *subject == self.subject && *d == detail)
+                .map(|(_, _, explanation)| *explanation)
+        };
+        find(self.detail)
+            .or_else(|| find(0))
+            .unwrap_or(EXPLANATIONS[0].2)
+    }
+
+    /// Parses an enhanced status code from the start of a reply's text.
+    ///
+    /// Returns the code and the remaining text, with the separating space removed.
+    /// Returns `None` if the text doesn't start with a valid code.
+    /// <https://datatracker.ietf.org/doc/html/rfc3463#section-2>
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use simple_smtp::smtp::enhanced::{Class, EnhancedCode};
+    ///
+    /// let (code, rest) = EnhancedCode::parse_prefix("5.1.1 User unknown").unwrap();
+    /// assert_eq!(code, EnhancedCode::new(Class::PermanentFailure, 1, 1));
+    /// assert_eq!(rest, "User unknown");
+    /// ```
+    pub fn parse_prefix(text: &str) -> Option<(EnhancedCode, &str)> {
+        let (code, rest) = text.split_once(' ').unwrap_or((text, ""));
+        let mut parts = code.split('.');
+        let class = match parts.next()? {
+            "2" => Class::Success,
+            "4" => Class::PersistentTransientFailure,
+            "5" => Class::PermanentFailure,
+            _ => return None,
+        };
+        let subject = parse_number(parts.next()?)?;
+        let detail = parse_number(parts.next()?)?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some((EnhancedCode::new(class, subject, detail), rest))
+    }
+}
+
+/// A short explanation of an [`EnhancedCode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Explanation {
+    /// A stable identifier like `mailbox.full`, to look up a translation with.
+    pub key: &'static str,
+    /// The default text, e.g. "Mailbox full".
+    pub english: &'static str,
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.english)
+    }
+}
+
+const fn explain(
+    subject: u16,
+    detail: u16,
+    key: &'static str,
+    english: &'static str,
+) -> (u16, u16, Explanation) {
+    (subject, detail, Explanation { key, english })
+}
+
+/// The explanations by subject and detail, the same for every class. Every subject has an
+/// entry for detail `0`, which is used for details not listed here.
+/// <https://datatracker.ietf.org/doc/html/rfc3463#section-3>
+pub const EXPLANATIONS: &[(u16, u16, Explanation)] = &[
+    explain(0, 0, "other.undefined", "Undefined error"),
+    explain(1, 0, "address.other", "Address problem"),
+    explain(1, 1, "address.bad_mailbox", "Mailbox does not exist"),
+    explain(
+        1,
+        2,
+        "address.bad_system",
+        "Destination system does not exist",
+    ),
+    explain(1, 3, "address.bad_syntax", "Invalid mailbox address"),
+    explain(1, 4, "address.ambiguous", "Ambiguous mailbox address"),
+    explain(1, 5, "address.valid", "Destination address valid"),
+    explain(1, 6, "address.moved", "Mailbox has moved"),
+    explain(1, 7, "address.bad_sender_syntax", "Invalid sender address"),
+    explain(
+        1,
+        8,
+        "address.bad_sender_system",
+        "Sender's system does not exist",
+    ),
+    explain(2, 0, "mailbox.other", "Mailbox problem"),
+    explain(2, 1, "mailbox.disabled", "Mailbox disabled"),
+    explain(2, 2, "mailbox.full", "Mailbox full"),
+    explain(
+        2,
+        3,
+        "mailbox.message_too_long",
+        "Message too large for the mailbox",
+    ),
+    explain(
+        2,
+        4,
+        "mailbox.list_expansion",
+        "Mailing list expansion problem",
+    ),
+    explain(3, 0, "system.other", "Mail system problem"),
+    explain(3, 1, "system.full", "Mail system full"),
+    explain(
+        3,
+        2,
+        "system.not_accepting",
+        "System not accepting messages",
+    ),
+    explain(
+        3,
+        3,
+        "system.unsupported_feature",
+        "Message feature not supported",
+    ),
+    explain(3, 4, "system.message_too_big", "Message too large"),
+    explain(3, 5, "system.misconfigured", "Mail system misconfigured"),
+    explain(4, 0, "network.other", "Network problem"),
+    explain(4, 1, "network.no_answer", "No answer from the server"),
+    explain(4, 2, "network.bad_connection", "Bad connection"),
+    explain(4, 3, "network.directory_server", "Directory server failure"),
+    explain(4, 4, "network.unable_to_route", "Unable to route"),
+    explain(4, 5, "network.congestion", "Mail system congestion"),
+    explain(4, 6, "network.routing_loop", "Routing loop detected"),
+    explain(4, 7, "network.expired", "Delivery time expired"),
+    explain(5, 0, "protocol.other", "Protocol problem"),
+    explain(5, 1, "protocol.invalid_command", "Invalid command"),
+    explain(5, 2, "protocol.syntax_error", "Syntax error"),
+    explain(5, 3, "protocol.too_many_recipients", "Too many recipients"),
+    explain(
+        5,
+        4,
+        "protocol.invalid_arguments",
+        "Invalid command arguments",
+    ),
+    explain(5, 5, "protocol.wrong_version", "Wrong protocol version"),
+    // https://datatracker.ietf.org/doc/html/rfc4954#section-6
+    explain(
+        5,
+        6,
+        "protocol.auth_line_too_long",
+        "Authentication data too long",
+    ),
+    explain(6, 0, "content.other", "Message content problem"),
+    explain(6, 1, "content.media_unsupported", "Media not supported"),
+    explain(
+        6,
+        2,
+        "content.conversion_prohibited",
+        "Conversion required and prohibited",
+    ),
+    explain(
+        6,
+        3,
+        "content.conversion_unsupported",
+        "Conversion required but not supported",
+    ),
+    explain(
+        6,
+        4,
+        "content.lossy_conversion",
+        "Conversion with loss performed",
+    ),
+    explain(6, 5, "content.conversion_failed", "Conversion failed"),
+    explain(7, 0, "security.other", "Security or policy problem"),
+    explain(7, 1, "security.not_authorized", "Delivery not authorized"),
+    explain(
+        7,
+        2,
+        "security.list_expansion_prohibited",
+        "Mailing list expansion prohibited",
+    ),
+    explain(
+        7,
+        3,
+        "security.conversion_prohibited",
+        "Security conversion not possible",
+    ),
+    explain(
+        7,
+        4,
+        "security.unsupported",
+        "Security features not supported",
+    ),
+    explain(7, 5, "security.crypto_failure", "Cryptographic failure"),
+    explain(
+        7,
+        6,
+        "security.algorithm_unsupported",
+        "Cryptographic algorithm not supported",
+    ),
+    explain(
+        7,
+        7,
+        "security.integrity_failure",
+        "Message integrity failure",
+    ),
+    // https://datatracker.ietf.org/doc/html/rfc4954#section-6
+    explain(
+        7,
+        8,
+        "security.bad_credentials",
+        "Invalid username or password",
+    ),
+    explain(
+        7,
+        9,
+        "security.weak_mechanism",
+        "Authentication mechanism too weak",
+    ),
+    explain(7, 11, "security.encryption_required", "Encryption required"),
+    // https://datatracker.ietf.org/doc/html/rfc7372#section-3
+    explain(
+        7,
+        26,
+        "security.sender_checks_failed",
+        "Sender authentication failed",
+    ),
+];
+
+/// subject and detail are 1 to 3 digits.
+fn parse_number(s: &str) -> Option<u16> {
+    if s.is_empty() || s.len() > 3 || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+impl Display for EnhancedCode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}",
+            self.class.as_digit(),
+            self.subject,
+            self.detail
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_prefix_valid() {
+        let (code, rest) = EnhancedCode::parse_prefix("2.1.5 Recipient OK").unwrap();
+        assert_eq!(code.class(), Class::Success);
+        assert_eq!(code.subject_kind(), Subject::Addressing);
+        assert_eq!(code.detail(), 5);
+        assert_eq!(rest, "Recipient OK");
+
+        let (code, rest) = EnhancedCode::parse_prefix("4.7.1").unwrap();
+        assert_eq!(code.class(), Class::PersistentTransientFailure);
+        assert_eq!(code.subject_kind(), Subject::SecurityOrPolicy);
+        assert_eq!(rest, "");
+
+        // subject and detail may have up to 3 digits
+        let (code, _) = EnhancedCode::parse_prefix("5.123.456 text").unwrap();
+        assert_eq!(code.subject(), 123);
+        assert_eq!(code.subject_kind(), Subject::Unknown(123));
+        assert_eq!(code.detail(), 456);
+    }
+
+    #[test]
+    fn parse_prefix_invalid() {
+        for text in [
+            "OK",
+            "",
+            "3.1.1 no such class",
+            "5.1 too short",
+            "5.1.1.1 too long",
+            "5..1 empty subject",
+            "5.1.1234 detail too long",
+            "5.a.1 not a number",
+            "mail.example.com ESMTP",
+        ] {
+            assert!(
+                EnhancedCode::parse_prefix(text).is_none(),
+                "{text:?} should not parse"
+            );
+        }
+    }
+
+    #[test]
+    fn explanations() {
+        let code = EnhancedCode::new(Class::PersistentTransientFailure, 2, 2);
+        assert_eq!(code.explanation().key, "mailbox.full");
+        assert_eq!(code.explanation().to_string(), "Mailbox full");
+        // unlisted details fall back to the subject, unlisted subjects to the undefined error
+        let code = EnhancedCode::new(Class::PermanentFailure, 2, 99);
+        assert_eq!(code.explanation().key, "mailbox.other");
+        let code = EnhancedCode::new(Class::PermanentFailure, 9, 1);
+        assert_eq!(code.explanation().key, "other.undefined");
+
+        for (idx, (subject, detail, explanation)) in EXPLANATIONS.iter().enumerate() {
+            assert!(EXPLANATIONS.iter().any(|(s, d, _)| s == subject && *d == 0));
+            assert!(
+                EXPLANATIONS[idx + 1..]
+                    .iter()
+                    .all(|(s, d, e)| (s, d) != (subject, detail) && e.key != explanation.key)
+            );
+        }
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let code = EnhancedCode::new(Class::PermanentFailure, 7, 26);
+        assert_eq!(code.to_string(), "5.7.26");
+        assert_eq!(
+            EnhancedCode::parse_prefix(&code.to_string()).unwrap().0,
+            code
+        );
+    }
+}
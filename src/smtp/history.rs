@@ -0,0 +1,202 @@
+//! The last replies of a session, for telling what led up to an error.
+//!
+//! Logging every reply is often too much for a production system, but an error deep in a
+//! transaction rarely makes sense without the replies before it. A [`ReplyHistory`] keeps
+//! just the last few, see [`Smtp::set_reply_history`](crate::Smtp::set_reply_history).
+
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, vec};
+use core::fmt::{self, Debug, Display};
+
+use super::{Reply, ReplyCode};
+use crate::{Error, ReplyText};
+
+/// The code and the (truncated) text of a reply in a [`ReplyHistory`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PastReply {
+    code: ReplyCode,
+    text: ReplyText,
+}
+
+impl PastReply {
+    pub fn new(reply: &Reply<'_>) -> Self {
+        PastReply {
+            code: reply.code(),
+            text: ReplyText::from_reply(reply),
+        }
+    }
+
+    pub fn code(&self) -> ReplyCode {
+        self.code
+    }
+
+    /// The lines of the reply joined with spaces, see [`ReplyText`].
+    pub fn text(&self) -> &str {
+        self.text.as_str()
+    }
+}
+
+impl Display for PastReply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code, self.text)
+    }
+}
+
+enum Entries<'a> {
+    #[cfg(feature = "alloc")]
+    Owned(Box<[Option<PastReply>]>),
+    Borrowed(&'a mut [Option<PastReply>]),
+}
+
+/// A ring buffer of the last replies of a session, the oldest is dropped once it is full.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::smtp::{ReplyHistory, history::PastReply};
+///
+/// let mut storage = [None::<PastReply>; 4];
+/// let history = ReplyHistory::new(&mut storage);
+/// assert_eq!(history.capacity(), 4);
+/// assert_eq!(history.iter().count(), 0);
+/// ```
+pub struct ReplyHistory<'a> {
+    entries: Entries<'a>,
+    // where the next reply goes, the oldest reply once the buffer is full
+    next: usize,
+}
+
+impl<'a> ReplyHistory<'a> {
+    /// Keeps as many replies as `storage` has room for.
+    pub fn new(storage: &'a mut [Option<PastReply>]) -> Self {
+        storage.fill(None);
+        ReplyHistory {
+            entries: Entries::Borrowed(storage),
+            next: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.entries().len()
+    }
+
+    /// The replies from the oldest to the newest.
+    pub fn iter(&self) -> impl Iterator<Item = &PastReply> {
+        let (newer, older) = self.entries().split_at(self.next);
+        older.iter().chain(newer).flatten()
+    }
+
+    pub(crate) fn push(&mut self, reply: &Reply<'_>) {
+        let capacity = self.capacity();
+        if capacity == 0 {
+            return;
+        }
+        let next = self.next;
+        self.entries_mut()[next] = Some(PastReply::new(reply));
+        self.next = (next + 1) % capacity;
+    }
+
+    fn entries(&self) -> &[Option<PastReply>] {
+        match &self.entries {
+            #[cfg(feature = "alloc")]
+            Entries::Owned(entries) => entries,
+            Entries::Borrowed(entries) => entries,
+        }
+    }
+
+    fn entries_mut(&mut self) -> &mut [Option<PastReply>] {
+        match &mut self.entries {
+            #[cfg(feature = "alloc")]
+            Entries::Owned(entries) => entries,
+            Entries::Borrowed(entries) => entries,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl ReplyHistory<'static> {
+    /// Keeps the last `capacity` replies.
+    pub fn with_capacity(capacity: usize) -> Self {
+        ReplyHistory {
+            entries: Entries::Owned(vec![None; capacity].into_boxed_slice()),
+            next: 0,
+        }
+    }
+}
+
+impl Debug for ReplyHistory<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// One reply per line, from the oldest to the newest.
+impl Display for ReplyHistory<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, reply) in self.iter().enumerate() {
+            if idx > 0 {
+                f.write_str("\n")?;
+            }
+            write!(f, "{reply}")?;
+        }
+        Ok(())
+    }
+}
+
+/// An error together with the replies which led up to it, see
+/// [`Smtp::report`](crate::Smtp::report).
+pub struct ErrorReport<'s, T: core::error::Error> {
+    pub(crate) error: &'s Error<T>,
+    pub(crate) history: Option<&'s ReplyHistory<'s>>,
+}
+
+impl<T: core::error::Error> Display for ErrorReport<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if let Some(history) = self.history
+            && history.iter().next().is_some()
+        {
+            write!(f, "\nrecent replies:")?;
+            for reply in history.iter() {
+                write!(f, "\n  {reply}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: core::error::Error> Debug for ErrorReport<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorReport")
+            .field("error", self.error)
+            .field("history", &self.history)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_last_replies() {
+        let mut storage = [None; 3];
+        let mut history = ReplyHistory::new(&mut storage);
+        for raw in [
+            "220 ready\r\n",
+            "250 OK\r\n",
+            "250-Accepted\r\n250 2.1.5\r\n",
+            "354 Go\r\n",
+        ] {
+            let mut raw = raw.as_bytes().to_vec();
+            history.push(&Reply::parse(&mut raw).unwrap());
+        }
+        let codes: Vec<u16> = history.iter().map(|r| r.code().as_u16()).collect();
+        assert_eq!(codes, [250, 250, 354]);
+        assert_eq!(history.to_string(), "250 OK\n250 Accepted 2.1.5\n354 Go");
+
+        let mut empty = ReplyHistory::new(&mut []);
+        empty.push(&Reply::parse(&mut b"250 OK\r\n".to_vec()).unwrap());
+        assert_eq!(empty.iter().count(), 0);
+    }
+}
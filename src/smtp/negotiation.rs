@@ -0,0 +1,226 @@
+//! Comparing the extensions a client wants to use with what the server advertised.
+//!
+//! Most extensions degrade silently when the server doesn't support them: mail is still
+//! delivered, just without pipelining or delivery notifications. A [`NegotiationReport`]
+//! makes those downgrades visible.
+
+use core::fmt::Display;
+
+/// The extensions a client would like to use for a session.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::smtp::negotiation::DesiredFeatures;
+///
+/// let desired = DesiredFeatures::new()
+///     .with_pipelining()
+///     .with_message_size(25 * 1024 * 1024)
+///     .with_auth_mechanism("PLAIN");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DesiredFeatures<'a> {
+    pub(crate) pipelining: bool,
+    pub(crate) dsn: bool,
+    pub(crate) smtputf8: bool,
+    pub(crate) message_size: Option<u64>,
+    pub(crate) auth_mechanism: Option<&'a str>,
+}
+
+impl<'a> DesiredFeatures<'a> {
+    pub fn new() -> Self {
+        DesiredFeatures {
+            pipelining: false,
+            dsn: false,
+            smtputf8: false,
+            message_size: None,
+            auth_mechanism: None,
+        }
+    }
+
+    pub fn with_pipelining(mut self) -> Self {
+        self.pipelining = true;
+        self
+    }
+
+    pub fn with_dsn(mut self) -> Self {
+        self.dsn = true;
+        self
+    }
+
+    pub fn with_smtputf8(mut self) -> Self {
+        self.smtputf8 = true;
+        self
+    }
+
+    /// The size in octets of the largest message the client intends to send.
+    pub fn with_message_size(mut self, size: u64) -> Self {
+        self.message_size = Some(size);
+        self
+    }
+
+    /// The SASL mechanism the client intends to authenticate with, e.g. `PLAIN`.
+    pub fn with_auth_mechanism(mut self, mechanism: &'a str) -> Self {
+        self.auth_mechanism = Some(mechanism);
+        self
+    }
+}
+
+/// Whether a desired feature is available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureStatus {
+    /// The client didn't ask for this feature.
+    NotRequested,
+    /// The client asked for this feature and the server supports it.
+    Satisfied,
+    /// The client asked for this feature but the server doesn't support it.
+    Unsatisfied,
+}
+
+impl FeatureStatus {
+    pub fn is_unsatisfied(&self) -> bool {
+        matches!(self, FeatureStatus::Unsatisfied)
+    }
+}
+
+/// The outcome of comparing [`DesiredFeatures`] with an EHLO response.
+///
+/// Created by [`EhloResponse::negotiate`](crate::smtp::EhloResponse::negotiate), or after
+/// every greeting by a session with
+/// [desired features](crate::Smtp::set_desired_features).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NegotiationReport {
+    pub(crate) pipelining: FeatureStatus,
+    pub(crate) dsn: FeatureStatus,
+    pub(crate) smtputf8: FeatureStatus,
+    pub(crate) size: FeatureStatus,
+    pub(crate) auth: FeatureStatus,
+}
+
+impl NegotiationReport {
+    pub fn pipelining(&self) -> FeatureStatus {
+        self.pipelining
+    }
+
+    pub fn dsn(&self) -> FeatureStatus {
+        self.dsn
+    }
+
+    pub fn smtputf8(&self) -> FeatureStatus {
+        self.smtputf8
+    }
+
+    /// Unsatisfied if the server doesn't advertise SIZE, or advertises a limit
+    /// smaller than the desired message size.
+    pub fn size(&self) -> FeatureStatus {
+        self.size
+    }
+
+    /// Unsatisfied if the server doesn't offer the desired SASL mechanism.
+    pub fn auth(&self) -> FeatureStatus {
+        self.auth
+    }
+
+    /// Returns true if every requested feature is available.
+    pub fn all_satisfied(&self) -> bool {
+        self.features().all(|(_, status)| !status.is_unsatisfied())
+    }
+
+    /// Iterates over every feature by name, including the ones that weren't requested.
+    pub fn features(&self) -> impl Iterator<Item = (&'static str, FeatureStatus)> {
+        [
+            ("pipelining", self.pipelining),
+            ("dsn", self.dsn),
+            ("smtputf8", self.smtputf8),
+            ("size", self.size),
+            ("auth", self.auth),
+        ]
+        .into_iter()
+    }
+}
+
+/// Lists the requested features, e.g. `pipelining: yes, size: no`.
+impl Display for NegotiationReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut separator = "";
+        for (name, status) in self.features() {
+            let answer = match status {
+                FeatureStatus::NotRequested => continue,
+                FeatureStatus::Satisfied => "yes",
+                FeatureStatus::Unsatisfied => "no",
+            };
+            write!(f, "{separator}{name}: {answer}")?;
+            separator = ", ";
+        }
+        if separator.is_empty() {
+            write!(f, "nothing requested")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smtp::{EhloResponse, Reply};
+
+    fn negotiate(lines: &str, desired: &DesiredFeatures) -> NegotiationReport {
+        let mut raw = lines.as_bytes().to_vec();
+        let reply = Reply::parse(&mut raw).unwrap();
+        EhloResponse::new(reply).negotiate(desired)
+    }
+
+    #[test]
+    fn everything_satisfied() {
+        let report = negotiate(
+            "250-mail.example.com\r\n250-PIPELINING\r\n250-DSN\r\n250-SIZE 1000\r\n250 AUTH PLAIN\r\n",
+            &DesiredFeatures::new()
+                .with_pipelining()
+                .with_dsn()
+                .with_message_size(1000)
+                .with_auth_mechanism("plain"),
+        );
+        assert!(report.all_satisfied());
+        assert_eq!(report.smtputf8(), FeatureStatus::NotRequested);
+        assert_eq!(
+            report.to_string(),
+            "pipelining: yes, dsn: yes, size: yes, auth: yes"
+        );
+    }
+
+    #[test]
+    fn unsatisfied_features() {
+        let report = negotiate(
+            "250-mail.example.com\r\n250-SIZE 1000\r\n250 AUTH LOGIN\r\n",
+            &DesiredFeatures::new()
+                .with_pipelining()
+                .with_smtputf8()
+                .with_message_size(1001)
+                .with_auth_mechanism("PLAIN"),
+        );
+        assert!(!report.all_satisfied());
+        assert_eq!(report.pipelining(), FeatureStatus::Unsatisfied);
+        assert_eq!(report.smtputf8(), FeatureStatus::Unsatisfied);
+        assert_eq!(report.size(), FeatureStatus::Unsatisfied);
+        assert_eq!(report.auth(), FeatureStatus::Unsatisfied);
+        assert_eq!(report.dsn(), FeatureStatus::NotRequested);
+    }
+
+    #[test]
+    fn size_without_limit() {
+        let desired = DesiredFeatures::new().with_message_size(u64::MAX);
+        let report = negotiate("250-mail.example.com\r\n250 SIZE\r\n", &desired);
+        assert_eq!(report.size(), FeatureStatus::Satisfied);
+
+        // if SIZE isn't advertised we can't know whether the message will be accepted
+        let report = negotiate("250 mail.example.com\r\n", &desired);
+        assert_eq!(report.size(), FeatureStatus::Unsatisfied);
+    }
+
+    #[test]
+    fn nothing_requested() {
+        let report = negotiate("250 mail.example.com\r\n", &DesiredFeatures::new());
+        assert!(report.all_satisfied());
+        assert_eq!(report.to_string(), "nothing requested");
+    }
+}
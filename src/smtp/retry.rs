@@ -0,0 +1,172 @@
+//! Deciding whether a rejected command is worth retrying.
+//!
+//! By default a rejection is retried if its basic code is transient (`4yz`), see
+//! [`Error::is_transient`](crate::Error::is_transient). Some providers don't follow that for
+//! every rejection, e.g. they reply `451 4.7.1` to mail blocked by their policy, which
+//! retrying won't get through. A [`RetryPolicy`] overrides the basic code with a table of
+//! codes and what to do about them, see [`Error::should_retry`](crate::Error::should_retry).
+//!
+//! # Example
+//!
+//! ```
+//! use simple_smtp::smtp::{
+//!     ReplyCode,
+//!     enhanced::{Class, EnhancedCode},
+//!     retry::{CodePattern, RetryAction, RetryPolicy},
+//! };
+//!
+//! const RULES: &[(CodePattern, RetryAction)] = &[
+//!     // blocked by policy, rather than greylisted
+//!     (
+//!         CodePattern::Enhanced(EnhancedCode::new(Class::PersistentTransientFailure, 7, 1)),
+//!         RetryAction::Never,
+//!     ),
+//! ];
+//!
+//! let policy = RetryPolicy::new().with_rules(RULES);
+//! let blocked = EnhancedCode::new(Class::PersistentTransientFailure, 7, 1);
+//! assert_eq!(
+//!     policy.action(ReplyCode::new(451), Some(blocked)),
+//!     RetryAction::Never
+//! );
+//! assert_eq!(policy.action(ReplyCode::new(451), None), RetryAction::Retry);
+//! ```
+
+use super::{
+    ReplyCode,
+    enhanced::{Class, EnhancedCode},
+};
+
+/// What to do about a rejected command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryAction {
+    /// Try again later, the command may succeed then.
+    Retry,
+    /// Give up, the command won't succeed however often it is retried.
+    Never,
+}
+
+/// The replies a rule of a [`RetryPolicy`] applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodePattern {
+    /// Replies with this basic code, whatever their enhanced code.
+    Reply(ReplyCode),
+    /// Replies with exactly this enhanced code.
+    Enhanced(EnhancedCode),
+    /// Replies with an enhanced code of this class and subject, e.g. `4.7.X` for all
+    /// transient policy rejections.
+    Subject(Class, u16),
+}
+
+impl CodePattern {
+    pub fn matches(&self, code: ReplyCode, enhanced: Option<EnhancedCode>) -> bool {
+        match (self, enhanced) {
+            (CodePattern::Reply(reply), _) => *reply == code,
+            (CodePattern::Enhanced(pattern), Some(enhanced)) => *pattern == enhanced,
+            (CodePattern::Subject(class, subject), Some(enhanced)) => {
+                *class == enhanced.class() && *subject == enhanced.subject()
+            }
+            (_, None) => false,
+        }
+    }
+}
+
+/// Decides whether to retry rejections by their codes, see the [module](self) docs.
+///
+/// The first rule matching a reply decides, replies without a matching rule are retried if
+/// their basic code is transient.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy<'a> {
+    rules: &'a [(CodePattern, RetryAction)],
+}
+
+impl<'a> RetryPolicy<'a> {
+    /// Retries exactly the transient rejections.
+    pub const fn new() -> Self {
+        RetryPolicy { rules: &[] }
+    }
+
+    /// Checks `rules` in order before falling back to the basic code.
+    ///
+    /// Greylisting servers commonly reply `451 4.7.1` too, so only suppress retries of a
+    /// code for the servers known to use it for permanent blocks.
+    pub const fn with_rules(mut self, rules: &'a [(CodePattern, RetryAction)]) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    pub fn rules(&self) -> &'a [(CodePattern, RetryAction)] {
+        self.rules
+    }
+
+    /// What to do about a reply with `code` and `enhanced`.
+    pub fn action(&self, code: ReplyCode, enhanced: Option<EnhancedCode>) -> RetryAction {
+        let rule = self
+            .rules
+            .iter()
+            .find(|(pattern, _)| pattern.matches(code, enhanced));
+        match rule {
+            Some((_, action)) => *action,
+            None if code.is_transient() => RetryAction::Retry,
+            None => RetryAction::Never,
+        }
+    }
+}
+
+impl Default for RetryPolicy<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY_BLOCK: EnhancedCode = EnhancedCode::new(Class::PersistentTransientFailure, 7, 1);
+
+    #[test]
+    fn first_matching_rule_decides() {
+        let rules = [
+            (CodePattern::Enhanced(POLICY_BLOCK), RetryAction::Never),
+            (
+                CodePattern::Subject(Class::PersistentTransientFailure, 7),
+                RetryAction::Retry,
+            ),
+            (
+                CodePattern::Subject(Class::PermanentFailure, 7),
+                RetryAction::Retry,
+            ),
+            (CodePattern::Reply(ReplyCode::new(452)), RetryAction::Never),
+        ];
+        let policy = RetryPolicy::new().with_rules(&rules);
+        let code = ReplyCode::new(451);
+        assert_eq!(policy.action(code, Some(POLICY_BLOCK)), RetryAction::Never);
+        let other = EnhancedCode::new(Class::PersistentTransientFailure, 7, 0);
+        assert_eq!(policy.action(code, Some(other)), RetryAction::Retry);
+        let permanent = EnhancedCode::new(Class::PermanentFailure, 7, 1);
+        assert_eq!(
+            policy.action(ReplyCode::new(550), Some(permanent)),
+            RetryAction::Retry
+        );
+        // the basic code matches with any enhanced code
+        let full = EnhancedCode::new(Class::PersistentTransientFailure, 2, 2);
+        assert_eq!(
+            policy.action(ReplyCode::new(452), Some(full)),
+            RetryAction::Never
+        );
+    }
+
+    #[test]
+    fn falls_back_to_basic_code() {
+        let rules = [(CodePattern::Enhanced(POLICY_BLOCK), RetryAction::Never)];
+        let policy = RetryPolicy::new().with_rules(&rules);
+        // enhanced patterns don't match replies without an enhanced code
+        assert_eq!(policy.action(ReplyCode::new(451), None), RetryAction::Retry);
+        assert_eq!(policy.action(ReplyCode::new(550), None), RetryAction::Never);
+        assert_eq!(
+            RetryPolicy::default().action(ReplyCode::new(451), Some(POLICY_BLOCK)),
+            RetryAction::Retry
+        );
+    }
+}
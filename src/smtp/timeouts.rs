@@ -0,0 +1,121 @@
+//! How long a session waits for the server to reply.
+//!
+//! Servers may take minutes before replying, e.g. to look up a recipient or to scan a
+//! message, so RFC 5321 asks clients not to give up too early. The defaults are the minimum
+//! timeouts it recommends, a session only needs a [`Timer`](crate::Timer) to enforce them.
+//! <https://datatracker.ietf.org/doc/html/rfc5321#section-4.5.3.2>
+
+use core::time::Duration;
+
+const MINUTE: u64 = 60;
+
+/// The time to wait for the reply to each command, see
+/// [`Smtp::set_timeouts`](crate::Smtp::set_timeouts).
+///
+/// # Example
+///
+/// ```
+/// use core::time::Duration;
+/// use simple_smtp::smtp::Timeouts;
+///
+/// let timeouts = Timeouts::default().with_command(Duration::from_secs(30));
+/// assert_eq!(timeouts.command(), Duration::from_secs(30));
+/// assert_eq!(timeouts.data_end(), Duration::from_secs(10 * 60));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    greeting: Duration,
+    mail: Duration,
+    rcpt: Duration,
+    data: Duration,
+    data_end: Duration,
+    command: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            greeting: Duration::from_secs(5 * MINUTE),
+            mail: Duration::from_secs(5 * MINUTE),
+            rcpt: Duration::from_secs(5 * MINUTE),
+            data: Duration::from_secs(2 * MINUTE),
+            data_end: Duration::from_secs(10 * MINUTE),
+            command: Duration::from_secs(5 * MINUTE),
+        }
+    }
+}
+
+impl Timeouts {
+    /// The same timeout for every reply, e.g. for tests.
+    pub fn uniform(timeout: Duration) -> Self {
+        Timeouts {
+            greeting: timeout,
+            mail: timeout,
+            rcpt: timeout,
+            data: timeout,
+            data_end: timeout,
+            command: timeout,
+        }
+    }
+
+    /// The `220` greeting after connecting, 5 minutes by default.
+    pub fn with_greeting(mut self, timeout: Duration) -> Self {
+        self.greeting = timeout;
+        self
+    }
+
+    /// `MAIL FROM`, 5 minutes by default.
+    pub fn with_mail(mut self, timeout: Duration) -> Self {
+        self.mail = timeout;
+        self
+    }
+
+    /// Each `RCPT TO`, 5 minutes by default.
+    pub fn with_rcpt(mut self, timeout: Duration) -> Self {
+        self.rcpt = timeout;
+        self
+    }
+
+    /// The `354` reply to `DATA`, 2 minutes by default.
+    pub fn with_data(mut self, timeout: Duration) -> Self {
+        self.data = timeout;
+        self
+    }
+
+    /// The reply after the end of the message data, 10 minutes by default, as the server may
+    /// process the whole message before replying.
+    pub fn with_data_end(mut self, timeout: Duration) -> Self {
+        self.data_end = timeout;
+        self
+    }
+
+    /// Any other command, e.g. `EHLO` or `AUTH`, 5 minutes by default.
+    pub fn with_command(mut self, timeout: Duration) -> Self {
+        self.command = timeout;
+        self
+    }
+
+    pub fn greeting(&self) -> Duration {
+        self.greeting
+    }
+
+    pub fn mail(&self) -> Duration {
+        self.mail
+    }
+
+    pub fn rcpt(&self) -> Duration {
+        self.rcpt
+    }
+
+    pub fn data(&self) -> Duration {
+        self.data
+    }
+
+    pub fn data_end(&self) -> Duration {
+        self.data_end
+    }
+
+    pub fn command(&self) -> Duration {
+        self.command
+    }
+}
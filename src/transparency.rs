@@ -0,0 +1,417 @@
+//! Data transparency, better known as dot-stuffing.
+//!
+//! A line consisting of a single `.` ends the message, so every line of the message starting
+//! with a `.` gets an extra one, which the server removes again.
+//! <https://datatracker.ietf.org/doc/html/rfc5321#section-4.5.2>
+//!
+//! Some servers end a line at a bare `\n` too, so a bare `\r` or `\n` is turned into
+//! `\r\n` on the way. Otherwise a `\n.\n` in the message would end it there for them, and
+//! whatever follows would be taken for commands.
+//! <https://datatracker.ietf.org/doc/html/rfc5321#section-4.1.1.4>
+//!
+//! [`DotStuffer`] does this for a message that arrives in chunks of any size, without
+//! copying or allocating: it yields slices of the input with the extra dots in between.
+
+use core::fmt::Display;
+
+use crate::{ReadWrite, fmt_stream::FmtToStream};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Position {
+    LineStart,
+    AfterCr,
+    InLine,
+}
+
+/// Remembers where in a line the previous chunk ended, so a `\r\n` and `.` split over two
+/// chunks is still stuffed.
+///
+/// A bare `\r` or `\n` is completed to `\r\n`, and ends the line just the same.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::transparency::DotStuffer;
+///
+/// let mut stuffer = DotStuffer::new();
+/// let mut out = Vec::new();
+/// for chunk in [&b"one\r"[..], b"\n.two"] {
+///     for piece in stuffer.feed(chunk) {
+///         out.extend_from_slice(piece);
+///     }
+/// }
+/// out.extend_from_slice(stuffer.terminator());
+/// assert_eq!(out, b"one\r\n..two\r\n.\r\n");
+///
+/// let mut out = Vec::new();
+/// for piece in stuffer.feed(b"\n.\nQUIT\n") {
+///     out.extend_from_slice(piece);
+/// }
+/// assert_eq!(out, b"\r\n..\r\nQUIT\r\n");
+/// ```
+#[derive(Debug, Clone)]
+pub struct DotStuffer {
+    position: Position,
+}
+
+impl DotStuffer {
+    pub fn new() -> Self {
+        DotStuffer {
+            position: Position::LineStart,
+        }
+    }
+
+    /// Returns true if everything fed so far ends with a line break, or nothing was fed yet.
+    ///
+    /// A trailing `\r` isn't a line break yet, the next chunk may start with its `\n`.
+    pub fn at_line_start(&self) -> bool {
+        self.position == Position::LineStart
+    }
+
+    /// The end of data marker to send after the last chunk.
+    ///
+    /// The final dot has to be on a line of its own, so a line break is prepended if the
+    /// message doesn't end with one.
+    pub fn terminator(&self) -> &'static [u8] {
+        match self.position {
+            Position::LineStart => b".\r\n",
+            // completes the bare CR the message ended with
+            Position::AfterCr => b"\n.\r\n",
+            Position::InLine => b"\r\n.\r\n",
+        }
+    }
+
+    /// Splits `chunk` into the slices to write, with the stuffed dots and completed line
+    /// breaks in between.
+    pub fn feed<'a>(&'a mut self, chunk: &'a [u8]) -> Stuffed<'a> {
+        Stuffed {
+            stuffer: self,
+            remaining: chunk,
+            pending: None,
+        }
+    }
+}
+
+/// The pieces of one stuffed chunk, created by [`DotStuffer::feed`].
+///
+/// Has to be consumed completely, otherwise the stuffer loses track of the line position.
+pub struct Stuffed<'a> {
+    stuffer: &'a mut DotStuffer,
+    remaining: &'a [u8],
+    // inserted after the slice returned last
+    pending: Option<&'static [u8]>,
+}
+
+impl<'a> Iterator for Stuffed<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(inserted) = self.pending.take() {
+            return Some(inserted);
+        }
+        if self.remaining.is_empty() {
+            return None;
+        }
+        for (idx, &byte) in self.remaining.iter().enumerate() {
+            // what to insert before `byte`, and whether `byte` is replaced by it
+            let (inserted, replaced): (&'static [u8], bool) = match (self.stuffer.position, byte) {
+                (Position::AfterCr, b'\n') => {
+                    self.stuffer.position = Position::LineStart;
+                    continue;
+                }
+                // a bare CR, `byte` starts the next line
+                (Position::AfterCr, _) => {
+                    self.stuffer.position = Position::LineStart;
+                    (b"\n", false)
+                }
+                // the leading dot is replaced by two
+                (Position::LineStart, b'.') => {
+                    self.stuffer.position = Position::InLine;
+                    (b"..", true)
+                }
+                // a bare LF
+                (_, b'\n') => {
+                    self.stuffer.position = Position::LineStart;
+                    (b"\r\n", true)
+                }
+                (_, b'\r') => {
+                    self.stuffer.position = Position::AfterCr;
+                    continue;
+                }
+                _ => {
+                    self.stuffer.position = Position::InLine;
+                    continue;
+                }
+            };
+            let before = &self.remaining[..idx];
+            self.remaining = &self.remaining[idx + usize::from(replaced)..];
+            if before.is_empty() {
+                return Some(inserted);
+            }
+            self.pending = Some(inserted);
+            return Some(before);
+        }
+        Some(core::mem::take(&mut self.remaining))
+    }
+}
+
+// writes a message to the stream after DATA, dot-stuffing it on the way
+pub(crate) struct DataWriter<'a, T: ReadWrite> {
+    stream: &'a mut T,
+    // `None` when writing somewhere other than the server
+    stuffer: Option<DotStuffer>,
+    out: Corked<'a>,
+}
+
+impl<'a, T: ReadWrite> DataWriter<'a, T> {
+    // uses the first half of `buf` for a reply the server sends before the end of data, and
+    // the second one to collect what is written. Checks for a reply before every write to
+    // the stream, and stops writing once one arrived.
+    pub(crate) fn new(stream: &'a mut T, buf: &'a mut [u8]) -> Self {
+        let (early_reply, pending) = buf.split_at_mut(buf.len() / 2);
+        DataWriter {
+            stream,
+            stuffer: Some(DotStuffer::new()),
+            out: Corked {
+                early_reply,
+                early_len: 0,
+                pending,
+                pending_len: 0,
+            },
+        }
+    }
+
+    // writes the message as it is, without dot-stuffing or the end of data marker
+    #[cfg(feature = "alloc")]
+    pub(crate) fn unstuffed(stream: &'a mut T) -> Self {
+        DataWriter {
+            stream,
+            stuffer: None,
+            out: Corked {
+                early_reply: &mut [],
+                early_len: 0,
+                pending: &mut [],
+                pending_len: 0,
+            },
+        }
+    }
+
+    // the server replied early, the rest of the data is dropped
+    pub(crate) fn interrupted(&self) -> bool {
+        self.out.early_len > 0
+    }
+
+    pub(crate) async fn write(&mut self, data: &[u8]) -> Result<(), T::Error> {
+        let Some(stuffer) = &mut self.stuffer else {
+            return self.out.push(self.stream, data).await;
+        };
+        for piece in stuffer.feed(data) {
+            self.out.push(self.stream, piece).await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn write_multi(&mut self, parts: &[&[u8]]) -> Result<(), T::Error> {
+        for part in parts {
+            self.write(part).await?;
+        }
+        Ok(())
+    }
+
+    // writes a formatted value chunk by chunk, returning its length
+    pub(crate) async fn write_display(&mut self, value: impl Display) -> Result<usize, T::Error> {
+        let mut chunks = FmtToStream::<_>::new(value);
+        let mut written = 0;
+        while let Some(chunk) = chunks.next_chunk() {
+            written += chunk.len();
+            self.write(chunk).await?;
+        }
+        Ok(written)
+    }
+
+    // writes the end of data marker, unless the server replied early. Returns the length of
+    // the early reply.
+    pub(crate) async fn finish(mut self) -> Result<usize, T::Error> {
+        if let Some(stuffer) = &self.stuffer {
+            self.out.push(self.stream, stuffer.terminator()).await?;
+        }
+        self.out.flush(self.stream).await?;
+        Ok(self.out.early_len)
+    }
+}
+
+// collects the many small pieces of a message, e.g. the words of its headers, and writes
+// them together, so they don't each become a TCP segment or TLS record of their own
+struct Corked<'a> {
+    early_reply: &'a mut [u8],
+    early_len: usize,
+    pending: &'a mut [u8],
+    pending_len: usize,
+}
+
+impl Corked<'_> {
+    async fn push<T: ReadWrite>(&mut self, stream: &mut T, data: &[u8]) -> Result<(), T::Error> {
+        if self.early_len > 0 {
+            return Ok(());
+        }
+        if self.pending_len + data.len() > self.pending.len() {
+            self.flush(stream).await?;
+            if data.len() >= self.pending.len() {
+                if self.replied(stream)? {
+                    return Ok(());
+                }
+                return stream.write_single(data).await;
+            }
+        }
+        self.pending[self.pending_len..][..data.len()].copy_from_slice(data);
+        self.pending_len += data.len();
+        Ok(())
+    }
+
+    async fn flush<T: ReadWrite>(&mut self, stream: &mut T) -> Result<(), T::Error> {
+        let len = core::mem::take(&mut self.pending_len);
+        if len == 0 || self.replied(stream)? {
+            return Ok(());
+        }
+        stream.write_single(&self.pending[..len]).await
+    }
+
+    fn replied<T: ReadWrite>(&mut self, stream: &mut T) -> Result<bool, T::Error> {
+        if self.early_len == 0 && !self.early_reply.is_empty() {
+            self.early_len = stream.read_available(self.early_reply)?;
+        }
+        Ok(self.early_len > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stuff(chunks: &[&[u8]]) -> Vec<u8> {
+        let mut stuffer = DotStuffer::new();
+        let mut out = Vec::new();
+        for chunk in chunks {
+            for piece in stuffer.feed(chunk) {
+                out.extend_from_slice(piece);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn stuffs_leading_dots() {
+        assert_eq!(stuff(&[b".hidden\r\n"]), b"..hidden\r\n");
+        assert_eq!(stuff(&[b"a\r\n.\r\nb"]), b"a\r\n..\r\nb");
+        assert_eq!(stuff(&[b"a.b\r\n..c"]), b"a.b\r\n...c");
+    }
+
+    #[test]
+    fn completes_bare_line_breaks() {
+        assert_eq!(stuff(&[b"a\nb\rc"]), b"a\r\nb\r\nc");
+        assert_eq!(stuff(&[b"\r\r\n\n"]), b"\r\n\r\n\r\n");
+        // a dot after a bare line break starts a line too, and is stuffed
+        assert_eq!(stuff(&[b"a\n.b\r.c"]), b"a\r\n..b\r\n..c");
+        // which a server ending lines at LF would otherwise take for the end of data
+        assert_eq!(stuff(&[b"a\n.\nRSET\n"]), b"a\r\n..\r\nRSET\r\n");
+    }
+
+    #[test]
+    fn across_chunk_boundaries() {
+        assert_eq!(stuff(&[b"a\r", b"\n", b".", b"b"]), b"a\r\n..b");
+        assert_eq!(stuff(&[b"a\r\n", b".\r\n"]), b"a\r\n..\r\n");
+        assert_eq!(stuff(&[b".", b".", b"\r\n", b""]), b"...\r\n");
+        assert_eq!(stuff(&[b"a\r", b".", b"b"]), b"a\r\n..b");
+        assert_eq!(stuff(&[b"a\r", b"\r", b"\n"]), b"a\r\n\r\n");
+    }
+
+    // the straightforward implementation: complete the line breaks of the whole message,
+    // then stuff it line by line
+    fn reference(message: &[u8]) -> Vec<u8> {
+        let mut lines = Vec::new();
+        for (idx, &byte) in message.iter().enumerate() {
+            match byte {
+                b'\r' if message.get(idx + 1) != Some(&b'\n') => lines.extend_from_slice(b"\r\n"),
+                b'\n' if idx == 0 || message[idx - 1] != b'\r' => lines.extend_from_slice(b"\r\n"),
+                _ => lines.push(byte),
+            }
+        }
+        let mut out = Vec::new();
+        let mut line_start = true;
+        for &byte in &lines {
+            if line_start && byte == b'.' {
+                out.push(b'.');
+            }
+            out.push(byte);
+            line_start = byte == b'\n';
+        }
+        out
+    }
+
+    // xorshift, to get reproducible inputs without pulling in a property testing crate
+    struct Rng(u64);
+    impl Rng {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+    }
+
+    #[test]
+    fn matches_reference_for_any_chunking() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..2000 {
+            // a small alphabet makes dots at line starts and split line breaks common
+            let len = rng.below(40) as usize;
+            let message: Vec<u8> = (0..len).map(|_| b".\r\na"[rng.below(4) as usize]).collect();
+            let mut chunks = Vec::new();
+            let mut rest = &message[..];
+            while !rest.is_empty() {
+                let (chunk, tail) = rest.split_at(rng.below(rest.len() as u64 + 1) as usize);
+                chunks.push(chunk);
+                rest = tail;
+            }
+            // a trailing CR is only completed by the terminator
+            let mut stuffed = stuff(&chunks);
+            if stuffed.ends_with(b"\r") {
+                stuffed.push(b'\n');
+            }
+            assert_eq!(stuffed, reference(&message), "{chunks:?}");
+
+            let mut stuffer = DotStuffer::new();
+            stuffer.feed(&message).for_each(drop);
+            assert_eq!(
+                stuffer.at_line_start(),
+                message.is_empty() || message.ends_with(b"\n")
+            );
+        }
+    }
+
+    #[test]
+    fn terminator() {
+        let mut stuffer = DotStuffer::new();
+        assert_eq!(stuffer.terminator(), b".\r\n");
+        stuffer.feed(b"text").for_each(drop);
+        assert_eq!(stuffer.terminator(), b"\r\n.\r\n");
+        stuffer.feed(b"\r").for_each(drop);
+        assert_eq!(stuffer.terminator(), b"\n.\r\n");
+        stuffer.feed(b"\n").for_each(drop);
+        assert_eq!(stuffer.terminator(), b".\r\n");
+    }
+
+    #[test]
+    fn line_start_tracking() {
+        let mut stuffer = DotStuffer::new();
+        assert!(stuffer.at_line_start());
+        stuffer.feed(b"text").for_each(drop);
+        assert!(!stuffer.at_line_start());
+        stuffer.feed(b"\r").for_each(drop);
+        assert!(!stuffer.at_line_start());
+        stuffer.feed(b"\n").for_each(drop);
+        assert!(stuffer.at_line_start());
+        stuffer.feed(b"text\n").for_each(drop);
+        assert!(stuffer.at_line_start());
+    }
+}
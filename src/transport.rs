@@ -0,0 +1,16 @@
+//! Transports that capture mail instead of delivering it, for development and tests.
+//!
+//! They implement [`ReadWrite`](crate::ReadWrite) by playing the part of a permissive SMTP
+//! server, so an [`Smtp`](crate::Smtp) session works on top of them exactly like it would
+//! on a real connection.
+
+mod capture;
+pub use capture::{CaptureStream, CapturedEnvelope, Deliver};
+mod file;
+pub use file::{EmlDirectory, FileTransport};
+mod memory;
+pub use memory::{MemoryTransport, Outbox, SentMessage};
+mod stored;
+pub use stored::{InvalidSubmission, StoredSubmission};
+mod trace;
+pub use trace::{MismatchReason, Sequence, TraceMismatch};
@@ -0,0 +1,160 @@
+use std::{collections::VecDeque, io};
+
+use crate::ReadWrite;
+
+/// The addresses of a captured message, as given in `MAIL FROM` and `RCPT TO`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CapturedEnvelope {
+    pub from: String,
+    pub recipients: Vec<String>,
+}
+
+/// Receives every message accepted by a [`CaptureStream`].
+pub trait Deliver {
+    /// `message` is the content as sent after `DATA`, with the dot-stuffing removed.
+    ///
+    /// An error is reported to the client as a transient failure.
+    fn deliver(&mut self, envelope: &CapturedEnvelope, message: &[u8]) -> io::Result<()>;
+}
+
+/// An in-process SMTP server which hands every message to a [`Deliver`] implementation.
+///
+/// It accepts any sender, recipient and credentials. `STARTTLS` is not offered.
+pub struct CaptureStream<D: Deliver> {
+    deliver: D,
+    // bytes written by the client which don't form a complete line yet
+    input: Vec<u8>,
+    // replies waiting to be read by the client
+    output: VecDeque<u8>,
+    envelope: Option<CapturedEnvelope>,
+    // the message being received, `Some` between DATA and the final dot
+    data: Option<Vec<u8>>,
+    commands: Vec<String>,
+}
+
+impl<D: Deliver> CaptureStream<D> {
+    pub fn new(deliver: D) -> Self {
+        let mut stream = CaptureStream {
+            deliver,
+            input: Vec::new(),
+            output: VecDeque::new(),
+            envelope: None,
+            data: None,
+            commands: Vec::new(),
+        };
+        stream.reply("220 localhost ESMTP capture");
+        stream
+    }
+
+    pub fn get_ref(&self) -> &D {
+        &self.deliver
+    }
+
+    pub fn get_mut(&mut self) -> &mut D {
+        &mut self.deliver
+    }
+
+    pub fn into_inner(self) -> D {
+        self.deliver
+    }
+
+    /// Every command received so far, without the message data, e.g. to check them with
+    /// [`expect_sequence!`](crate::expect_sequence).
+    pub fn commands(&self) -> &[String] {
+        &self.commands
+    }
+
+    fn reply(&mut self, reply: &str) {
+        self.output.extend(reply.as_bytes());
+        self.output.extend(b"\r\n");
+    }
+
+    fn process_line(&mut self, line: &[u8]) {
+        if let Some(data) = &mut self.data {
+            if line == b"." {
+                let message = self.data.take().unwrap_or_default();
+                let envelope = self.envelope.take().unwrap_or_default();
+                match self.deliver.deliver(&envelope, &message) {
+                    Ok(()) => self.reply("250 2.0.0 Captured"),
+                    Err(e) => self.reply(&format!("451 4.3.0 Capture failed: {e}")),
+                }
+            } else {
+                // https://datatracker.ietf.org/doc/html/rfc5321#section-4.5.2
+                let line = line.strip_prefix(b".").unwrap_or(line);
+                data.extend_from_slice(line);
+                data.extend_from_slice(b"\r\n");
+            }
+            return;
+        }
+        let line = String::from_utf8_lossy(line).into_owned();
+        self.commands.push(line.clone());
+        let (verb, args) = line.split_once(' ').unwrap_or((&line, ""));
+        match verb.to_ascii_uppercase().as_str() {
+            "EHLO" => self.reply("250-localhost\r\n250-8BITMIME\r\n250-SMTPUTF8\r\n250 AUTH PLAIN"),
+            "HELO" | "NOOP" => self.reply("250 OK"),
+            "AUTH" => self.reply("235 2.7.0 Accepted"),
+            "MAIL" => match path(args, "FROM:") {
+                Some(from) => {
+                    self.envelope = Some(CapturedEnvelope {
+                        from: from.to_owned(),
+                        recipients: Vec::new(),
+                    });
+                    self.reply("250 OK");
+                }
+                None => self.reply("501 5.5.4 Syntax: MAIL FROM:<address>"),
+            },
+            "RCPT" => match (path(args, "TO:"), &mut self.envelope) {
+                (Some(to), Some(envelope)) => {
+                    envelope.recipients.push(to.to_owned());
+                    self.reply("250 OK");
+                }
+                (None, _) => self.reply("501 5.5.4 Syntax: RCPT TO:<address>"),
+                (_, None) => self.reply("503 5.5.1 MAIL first"),
+            },
+            "DATA" => match &self.envelope {
+                Some(envelope) if !envelope.recipients.is_empty() => {
+                    self.data = Some(Vec::new());
+                    self.reply("354 End data with <CR><LF>.<CR><LF>");
+                }
+                _ => self.reply("503 5.5.1 RCPT first"),
+            },
+            "RSET" => {
+                self.envelope = None;
+                self.reply("250 OK");
+            }
+            "QUIT" => self.reply("221 Bye"),
+            _ => self.reply("502 5.5.2 Command not implemented"),
+        }
+    }
+}
+
+// the address between the angle brackets following `prefix`, ignoring any parameters
+fn path<'a>(args: &'a str, prefix: &str) -> Option<&'a str> {
+    let rest = args
+        .get(..prefix.len())
+        .filter(|p| p.eq_ignore_ascii_case(prefix))
+        .map(|_| args[prefix.len()..].trim_start())?;
+    let rest = rest.strip_prefix('<')?;
+    rest.split_once('>').map(|(address, _)| address)
+}
+
+impl<D: Deliver> ReadWrite for CaptureStream<D> {
+    type Error = io::Error;
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let len = buf.len().min(self.output.len());
+        for (dst, src) in buf.iter_mut().zip(self.output.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+
+    async fn write_single(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        self.input.extend_from_slice(buf);
+        while let Some(end) = self.input.windows(2).position(|w| w == b"\r\n") {
+            let line: Vec<u8> = self.input.drain(..end + 2).take(end).collect();
+            self.process_line(&line);
+        }
+        Ok(())
+    }
+}
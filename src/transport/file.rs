@@ -0,0 +1,129 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{CaptureStream, CapturedEnvelope, Deliver, StoredSubmission};
+use crate::ReadWrite;
+
+/// Writes every message to a directory, for local development.
+///
+/// Each message is stored as `<id>.eml`, which mail clients can open directly, next to
+/// `<id>.envelope` holding the `MAIL FROM` and `RCPT TO` lines it was sent with, in the
+/// format of [`StoredSubmission`](super::StoredSubmission).
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// use simple_smtp::{Smtp, transport::FileTransport};
+///
+/// let mut smtp = Smtp::new(FileTransport::new("outbox")?);
+/// smtp.ready().await?;
+/// smtp.ehlo("localhost").await?;
+/// smtp.send_mail("me@example.com", ["you@example.com"].iter(), b"Subject: hi\r\n\r\nhello\r\n")
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct FileTransport(CaptureStream<EmlDirectory>);
+
+impl FileTransport {
+    /// Creates `dir` if it doesn't exist yet.
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        Ok(FileTransport(CaptureStream::new(EmlDirectory::new(dir)?)))
+    }
+
+    pub fn dir(&self) -> &Path {
+        self.0.get_ref().dir()
+    }
+}
+
+impl ReadWrite for FileTransport {
+    type Error = io::Error;
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf).await
+    }
+
+    async fn write_single(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        self.0.write_single(buf).await
+    }
+}
+
+// shared by every directory, as two transports may write to the same one
+static WRITTEN: AtomicU64 = AtomicU64::new(0);
+
+/// The [`Deliver`] implementation behind [`FileTransport`].
+pub struct EmlDirectory {
+    dir: PathBuf,
+}
+
+impl EmlDirectory {
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(EmlDirectory { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Deliver for EmlDirectory {
+    fn deliver(&mut self, envelope: &CapturedEnvelope, message: &[u8]) -> io::Result<()> {
+        // sortable by time, and unique within a process
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        let written = WRITTEN.fetch_add(1, Ordering::Relaxed);
+        let id = format!("{timestamp}-{}-{written}", std::process::id());
+
+        let sidecar = StoredSubmission::encode_envelope(envelope)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        fs::write(self.dir.join(format!("{id}.envelope")), sidecar)?;
+        fs::write(self.dir.join(format!("{id}.eml")), message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_are_unique_across_directories() {
+        let dir = std::env::temp_dir().join(format!("simple-smtp-eml-{}", std::process::id()));
+        let mut first = EmlDirectory::new(&dir).unwrap();
+        let mut second = EmlDirectory::new(&dir).unwrap();
+        let envelope = CapturedEnvelope::default();
+        for _ in 0..3 {
+            first.deliver(&envelope, b"Subject: a\r\n\r\n").unwrap();
+            second.deliver(&envelope, b"Subject: b\r\n\r\n").unwrap();
+        }
+        let written = fs::read_dir(&dir)
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().extension() == Some("eml".as_ref()))
+            .count();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(written, 6);
+    }
+
+    #[test]
+    fn test_unstorable_address_is_an_error() {
+        let dir = std::env::temp_dir().join(format!("simple-smtp-bad-{}", std::process::id()));
+        let mut eml = EmlDirectory::new(&dir).unwrap();
+        let envelope = CapturedEnvelope {
+            from: "a\nb@example.com".into(),
+            recipients: vec![],
+        };
+        let error = eml.deliver(&envelope, b"Subject: a\r\n\r\n").unwrap_err();
+        let written = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(written, 0);
+    }
+}
@@ -0,0 +1,240 @@
+use std::{
+    io,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use super::{CaptureStream, CapturedEnvelope, Deliver};
+use crate::ReadWrite;
+
+/// Keeps every message in memory, so tests can assert on what was sent.
+///
+/// The messages end up in an [`Outbox`], which can be cloned before the transport is handed
+/// to the session and inspected after the code under test is done with it.
+///
+/// # Example
+///
+/// ```
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// use simple_smtp::{Smtp, transport::MemoryTransport};
+///
+/// let transport = MemoryTransport::new();
+/// let outbox = transport.outbox();
+///
+/// let mut smtp = Smtp::new(transport);
+/// smtp.ready().await?;
+/// smtp.ehlo("localhost").await?;
+/// smtp.send_mail("me@example.com", ["you@example.com"].iter(), b"Subject: hi\r\n\r\nhello\r\n")
+///     .await?;
+///
+/// assert_eq!(outbox.sent_to("you@example.com").len(), 1);
+/// assert_eq!(outbox.with_subject("hi")[0].envelope.from, "me@example.com");
+/// # Ok(())
+/// # }
+/// ```
+pub struct MemoryTransport(CaptureStream<Outbox>);
+
+impl MemoryTransport {
+    pub fn new() -> Self {
+        MemoryTransport(CaptureStream::new(Outbox::default()))
+    }
+
+    /// A handle to the messages sent through this transport.
+    pub fn outbox(&self) -> Outbox {
+        self.0.get_ref().clone()
+    }
+
+    /// See [`CaptureStream::commands`].
+    pub fn commands(&self) -> &[String] {
+        self.0.commands()
+    }
+}
+
+impl Default for MemoryTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReadWrite for MemoryTransport {
+    type Error = io::Error;
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf).await
+    }
+
+    async fn write_single(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        self.0.write_single(buf).await
+    }
+}
+
+/// A message captured by a [`MemoryTransport`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentMessage {
+    pub envelope: CapturedEnvelope,
+    /// The content as sent after `DATA`, with the dot-stuffing removed.
+    pub message: Vec<u8>,
+}
+
+impl SentMessage {
+    /// Whether `address` was one of the envelope recipients.
+    ///
+    /// The domain is compared case insensitively, the local part exactly.
+    pub fn is_sent_to(&self, address: &str) -> bool {
+        self.envelope
+            .recipients
+            .iter()
+            .any(|recipient| same_address(recipient, address))
+    }
+
+    /// The value of the first header field called `name`, with folded lines joined.
+    ///
+    /// Encoded words are returned as-is.
+    pub fn header(&self, name: &str) -> Option<String> {
+        let text = String::from_utf8_lossy(&self.message);
+        let mut value: Option<String> = None;
+        for line in text.split("\r\n") {
+            if line.is_empty() {
+                // end of the header section
+                break;
+            }
+            if line.starts_with([' ', '\t']) {
+                // https://datatracker.ietf.org/doc/html/rfc5322#section-2.2.3
+                if let Some(value) = &mut value {
+                    value.push_str(line);
+                }
+                continue;
+            }
+            if value.is_some() {
+                break;
+            }
+            if let Some((field, rest)) = line.split_once(':')
+                && field.trim_end().eq_ignore_ascii_case(name)
+            {
+                value = Some(rest.to_owned());
+            }
+        }
+        value.map(|value| value.trim().to_owned())
+    }
+
+    pub fn subject(&self) -> Option<String> {
+        self.header("Subject")
+    }
+}
+
+fn same_address(a: &str, b: &str) -> bool {
+    match (a.rsplit_once('@'), b.rsplit_once('@')) {
+        (Some((local_a, domain_a)), Some((local_b, domain_b))) => {
+            local_a == local_b && domain_a.eq_ignore_ascii_case(domain_b)
+        }
+        _ => a == b,
+    }
+}
+
+/// The messages captured by a [`MemoryTransport`], shared between all its clones.
+#[derive(Debug, Clone, Default)]
+pub struct Outbox(Arc<Mutex<Vec<SentMessage>>>);
+
+impl Outbox {
+    fn lock(&self) -> MutexGuard<'_, Vec<SentMessage>> {
+        // a panicking test shouldn't hide the messages from the others
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// All messages, in the order they were sent.
+    pub fn messages(&self) -> Vec<SentMessage> {
+        self.lock().clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// The messages for which `predicate` returns true.
+    pub fn filter(&self, mut predicate: impl FnMut(&SentMessage) -> bool) -> Vec<SentMessage> {
+        self.lock()
+            .iter()
+            .filter(|m| predicate(m))
+            .cloned()
+            .collect()
+    }
+
+    /// The messages with `address` as one of the envelope recipients.
+    pub fn sent_to(&self, address: &str) -> Vec<SentMessage> {
+        self.filter(|m| m.is_sent_to(address))
+    }
+
+    /// The messages whose `Subject` header is exactly `subject`.
+    pub fn with_subject(&self, subject: &str) -> Vec<SentMessage> {
+        self.filter(|m| m.subject().as_deref() == Some(subject))
+    }
+
+    /// Removes all messages, returning them.
+    pub fn take(&self) -> Vec<SentMessage> {
+        core::mem::take(&mut *self.lock())
+    }
+}
+
+impl Deliver for Outbox {
+    fn deliver(&mut self, envelope: &CapturedEnvelope, message: &[u8]) -> io::Result<()> {
+        self.lock().push(SentMessage {
+            envelope: envelope.clone(),
+            message: message.to_vec(),
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sent(recipients: &[&str], message: &str) -> SentMessage {
+        SentMessage {
+            envelope: CapturedEnvelope {
+                from: "me@example.com".into(),
+                recipients: recipients.iter().map(|r| r.to_string()).collect(),
+            },
+            message: message.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn header() {
+        let message = sent(
+            &[],
+            "From: me@example.com\r\nsubject : Hello\r\n  world\r\nTo: you\r\n\r\nSubject: body\r\n",
+        );
+        assert_eq!(message.subject().as_deref(), Some("Hello  world"));
+        assert_eq!(message.header("to").as_deref(), Some("you"));
+        assert_eq!(message.header("Cc"), None);
+        assert_eq!(sent(&[], "\r\nSubject: body\r\n").subject(), None);
+    }
+
+    #[test]
+    fn sent_to() {
+        let message = sent(&["Alice@Example.com"], "\r\n");
+        assert!(message.is_sent_to("Alice@example.COM"));
+        assert!(!message.is_sent_to("alice@example.com"));
+        assert!(!message.is_sent_to("bob@example.com"));
+    }
+
+    #[test]
+    fn outbox_is_shared() {
+        let outbox = Outbox::default();
+        let mut deliver = outbox.clone();
+        deliver
+            .deliver(&sent(&["a@b"], "").envelope, b"Subject: x\r\n\r\n")
+            .unwrap();
+        assert_eq!(outbox.len(), 1);
+        assert_eq!(outbox.with_subject("x").len(), 1);
+        assert_eq!(outbox.sent_to("a@b").len(), 1);
+        assert_eq!(outbox.take().len(), 1);
+        assert!(outbox.is_empty());
+    }
+}
@@ -0,0 +1,239 @@
+use std::fmt::{self, Display};
+
+use super::CapturedEnvelope;
+
+/// A message together with its envelope, in a plain format meant for storage.
+///
+/// The format doesn't depend on any other crate and is versioned, so whatever is stored
+/// by one version of this crate can be read by later ones:
+///
+/// ```text
+/// simple-smtp-submission v1
+/// MAIL FROM:<me@example.com>
+/// RCPT TO:<you@example.com>
+///
+/// Subject: hi
+/// ...
+/// ```
+///
+/// Every line ends with `\r\n`. The envelope is followed by an empty line and the message
+/// exactly as given. Anything after the `>` of a command is ignored, so later versions
+/// can add parameters without breaking older readers.
+///
+/// [`EmlDirectory`](super::EmlDirectory) writes the envelope part of this format, so an
+/// `.envelope` file followed by its `.eml` file is a stored submission.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::transport::{CapturedEnvelope, StoredSubmission};
+///
+/// let stored = StoredSubmission {
+///     envelope: CapturedEnvelope {
+///         from: "me@example.com".into(),
+///         recipients: vec!["you@example.com".into()],
+///     },
+///     message: b"Subject: hi\r\n\r\nhello\r\n".to_vec(),
+/// };
+/// let bytes = stored.encode().unwrap();
+/// assert_eq!(StoredSubmission::decode(&bytes).unwrap(), stored);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StoredSubmission {
+    pub envelope: CapturedEnvelope,
+    pub message: Vec<u8>,
+}
+
+impl StoredSubmission {
+    /// The version written by [`encode`](Self::encode), and the latest one
+    /// [`decode`](Self::decode) understands.
+    pub const VERSION: u32 = 1;
+
+    /// Fails like [`encode_envelope`](Self::encode_envelope).
+    pub fn encode(&self) -> Result<Vec<u8>, InvalidSubmission> {
+        let mut out = Self::encode_envelope(&self.envelope)?.into_bytes();
+        out.extend_from_slice(&self.message);
+        Ok(out)
+    }
+
+    /// Only the envelope, including the empty line the message follows.
+    ///
+    /// Fails if an address contains a line break or `>`, which can't be stored. The error
+    /// names the line the address would have been on.
+    pub fn encode_envelope(envelope: &CapturedEnvelope) -> Result<String, InvalidSubmission> {
+        let mut out = format!("{MAGIC} v{}\r\n", Self::VERSION);
+        for (line, (command, address)) in core::iter::once(("MAIL FROM", &envelope.from))
+            .chain(envelope.recipients.iter().map(|rcpt| ("RCPT TO", rcpt)))
+            .enumerate()
+        {
+            if address.contains(['\r', '\n', '>']) {
+                return Err(InvalidSubmission {
+                    line: line + 2,
+                    reason: "address with a line break or `>`",
+                });
+            }
+            out.push_str(&format!("{command}:<{address}>\r\n"));
+        }
+        out.push_str("\r\n");
+        Ok(out)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, InvalidSubmission> {
+        let (envelope, len) = Self::decode_envelope(bytes)?;
+        Ok(StoredSubmission {
+            envelope,
+            message: bytes[len..].to_vec(),
+        })
+    }
+
+    /// Reads the envelope at the start of `bytes`, returning it with the number of bytes it
+    /// took. The message is what follows.
+    pub fn decode_envelope(bytes: &[u8]) -> Result<(CapturedEnvelope, usize), InvalidSubmission> {
+        let mut envelope = CapturedEnvelope::default();
+        let mut has_from = false;
+        let mut read = 0;
+        for line_no in 1.. {
+            let error = |reason| InvalidSubmission {
+                line: line_no,
+                reason,
+            };
+            let rest = &bytes[read..];
+            let len = rest
+                .windows(2)
+                .position(|pair| pair == b"\r\n")
+                .ok_or(error("missing the end of the envelope"))?;
+            read += len + 2;
+            let line = core::str::from_utf8(&rest[..len]).map_err(|_| error("not UTF-8"))?;
+            if line_no == 1 {
+                let version = line
+                    .strip_prefix(MAGIC)
+                    .and_then(|version| version.strip_prefix(" v"))
+                    .and_then(|version| version.parse::<u32>().ok())
+                    .ok_or(error("not a stored submission"))?;
+                if version > Self::VERSION {
+                    return Err(error("written by a newer version"));
+                }
+                continue;
+            }
+            if line.is_empty() {
+                break;
+            }
+            let (command, address) = line
+                .split_once(":<")
+                .and_then(|(command, rest)| Some((command, rest.split_once('>')?.0)))
+                .ok_or(error("expected `COMMAND:<address>`"))?;
+            match command {
+                "MAIL FROM" if !has_from => {
+                    envelope.from = address.to_owned();
+                    has_from = true;
+                }
+                "RCPT TO" if has_from => envelope.recipients.push(address.to_owned()),
+                _ => return Err(error("unexpected command")),
+            }
+        }
+        if !has_from {
+            return Err(InvalidSubmission {
+                line: 2,
+                reason: "missing MAIL FROM",
+            });
+        }
+        Ok((envelope, read))
+    }
+}
+
+const MAGIC: &str = "simple-smtp-submission";
+
+/// Why [`StoredSubmission::decode`] or [`StoredSubmission::encode`] failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidSubmission {
+    /// Counted from 1.
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+impl Display for InvalidSubmission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid stored submission on line {}: {}",
+            self.line, self.reason
+        )
+    }
+}
+
+impl std::error::Error for InvalidSubmission {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> StoredSubmission {
+        StoredSubmission {
+            envelope: CapturedEnvelope {
+                from: String::new(),
+                recipients: vec!["a@example.com".into(), "b@example.com".into()],
+            },
+            // the message may contain anything, including what looks like an envelope
+            message: b"MAIL FROM:<x@example.com>\r\n\r\n.\r\n".to_vec(),
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let stored = sample();
+        let bytes = stored.encode().unwrap();
+        assert!(bytes.starts_with(
+            b"simple-smtp-submission v1\r\nMAIL FROM:<>\r\nRCPT TO:<a@example.com>\r\n"
+        ));
+        assert_eq!(StoredSubmission::decode(&bytes).unwrap(), stored);
+    }
+
+    #[test]
+    fn refuses_addresses_it_cant_store() {
+        let mut stored = sample();
+        stored.envelope.recipients[1] = "b@example.com>\r\nRCPT TO:<c@example.com".into();
+        assert_eq!(
+            stored.encode(),
+            Err(InvalidSubmission {
+                line: 4,
+                reason: "address with a line break or `>`"
+            })
+        );
+    }
+
+    #[test]
+    fn ignores_later_parameters() {
+        let bytes = b"simple-smtp-submission v1\r\n\
+            MAIL FROM:<me@example.com> SIZE=5\r\n\
+            RCPT TO:<you@example.com> NOTIFY=NEVER\r\n\r\nhello";
+        let stored = StoredSubmission::decode(bytes).unwrap();
+        assert_eq!(stored.envelope.from, "me@example.com");
+        assert_eq!(stored.envelope.recipients, ["you@example.com"]);
+        assert_eq!(stored.message, b"hello");
+    }
+
+    #[test]
+    fn reports_invalid_submissions() {
+        let reason = |bytes: &[u8]| StoredSubmission::decode(bytes).unwrap_err().reason;
+        assert_eq!(reason(b"MAIL FROM:<a>\r\n\r\n"), "not a stored submission");
+        assert_eq!(
+            reason(b"simple-smtp-submission v2\r\n\r\n"),
+            "written by a newer version"
+        );
+        assert_eq!(
+            reason(b"simple-smtp-submission v1\r\nMAIL FROM:<a>\r\n"),
+            "missing the end of the envelope"
+        );
+        assert_eq!(
+            reason(b"simple-smtp-submission v1\r\nRCPT TO:<a>\r\n\r\n"),
+            "unexpected command"
+        );
+        assert_eq!(
+            StoredSubmission::decode(b"simple-smtp-submission v1\r\n\r\n"),
+            Err(InvalidSubmission {
+                line: 2,
+                reason: "missing MAIL FROM"
+            })
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use std::fmt::{self, Display};
+
+/// The commands a test expects a client to send, in order. Usually written with
+/// [`expect_sequence!`](crate::expect_sequence).
+///
+/// Every step is a pattern for a whole command line, where `*` matches any text, and the
+/// number of consecutive lines it has to match, one unless given with `=>`. Other commands
+/// may be sent before, between and after the steps, unless the sequence is
+/// [strict](Self::strict).
+///
+/// The commands come from [`CaptureStream::commands`](super::CaptureStream::commands), or
+/// from any other record of what was written, e.g. the lines of a mock stream.
+///
+/// # Example
+///
+/// ```
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// use simple_smtp::{Smtp, expect_sequence, transport::MemoryTransport};
+///
+/// let mut smtp = Smtp::new(MemoryTransport::new());
+/// smtp.ready().await?;
+/// smtp.ehlo("localhost").await?;
+/// let recipients = ["a@example.com", "b@example.com"];
+/// smtp.send_mail("me@example.com", recipients.iter(), b"hello\r\n").await?;
+///
+/// let (transport, _) = smtp.into_inner();
+/// expect_sequence!["EHLO *", "MAIL FROM:<me@example.com>", "RCPT TO:*" => 2, "DATA"]
+///     .strict()
+///     .assert(transport.commands());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sequence<'a> {
+    steps: Vec<(&'a str, usize)>,
+    strict: bool,
+}
+
+impl<'a> Sequence<'a> {
+    /// A sequence of `(pattern, count)` steps.
+    pub fn new(steps: &[(&'a str, usize)]) -> Self {
+        Sequence {
+            steps: steps.to_vec(),
+            strict: false,
+        }
+    }
+
+    /// Don't allow any other commands, so the steps have to match every command.
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    /// Checks `commands` against the steps, without their line breaks.
+    pub fn check<S: AsRef<str>>(&self, commands: &[S]) -> Result<(), TraceMismatch> {
+        let mut commands = commands.iter().map(AsRef::as_ref).enumerate().peekable();
+        for (step, &(pattern, count)) in self.steps.iter().enumerate() {
+            let mismatch = |line: Option<usize>, reason| TraceMismatch {
+                step,
+                pattern: pattern.to_owned(),
+                line,
+                reason,
+            };
+            // skip to the first match, unless strict
+            while let Some((line, command)) = commands.peek() {
+                if glob(pattern, command) {
+                    break;
+                }
+                if self.strict {
+                    return Err(mismatch(Some(*line), MismatchReason::Unexpected));
+                }
+                commands.next();
+            }
+            for matched in 0..count {
+                match commands.next_if(|(_, command)| glob(pattern, command)) {
+                    Some(_) => {}
+                    None if matched == 0 => return Err(mismatch(None, MismatchReason::Missing)),
+                    None => {
+                        let line = commands.peek().map(|(line, _)| *line);
+                        return Err(mismatch(line, MismatchReason::TooFew(matched)));
+                    }
+                }
+            }
+            if let Some((line, _)) = commands.next_if(|(_, command)| glob(pattern, command)) {
+                return Err(mismatch(Some(line), MismatchReason::TooMany));
+            }
+        }
+        match commands.next() {
+            Some((line, _)) if self.strict => Err(TraceMismatch {
+                step: self.steps.len(),
+                pattern: String::new(),
+                line: Some(line),
+                reason: MismatchReason::Unexpected,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Like [`check`](Self::check), but panics with the commands and what didn't match.
+    #[track_caller]
+    pub fn assert<S: AsRef<str>>(&self, commands: &[S]) {
+        if let Err(mismatch) = self.check(commands) {
+            let mut trace = String::new();
+            for (line, command) in commands.iter().enumerate() {
+                trace.push_str(&format!("\n{line:>4}: {}", command.as_ref()));
+            }
+            panic!("{mismatch}, commands sent:{trace}");
+        }
+    }
+}
+
+/// Builds a [`Sequence`] from command patterns, each optionally followed by `=> count`.
+///
+/// ```
+/// use simple_smtp::expect_sequence;
+///
+/// let commands = ["EHLO client", "MAIL FROM:<a@b>", "RCPT TO:<c@d>", "RCPT TO:<e@f>"];
+/// expect_sequence!["EHLO *", "RCPT TO:*" => 2].assert(&commands);
+/// assert!(expect_sequence!["RCPT TO:*", "MAIL FROM:*"].check(&commands).is_err());
+/// ```
+#[macro_export]
+macro_rules! expect_sequence {
+    (@count) => { 1 };
+    (@count $count:expr) => { $count };
+    ($($pattern:expr $(=> $count:expr)?),* $(,)?) => {
+        $crate::transport::Sequence::new(&[
+            $(($pattern, $crate::expect_sequence!(@count $($count)?))),*
+        ])
+    };
+}
+
+/// Why a [`Sequence`] didn't match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceMismatch {
+    /// The index of the step which didn't match, the number of steps for a command after
+    /// the last one.
+    pub step: usize,
+    pub pattern: String,
+    /// The index of the command which didn't match, if any.
+    pub line: Option<usize>,
+    pub reason: MismatchReason,
+}
+
+/// See [`TraceMismatch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MismatchReason {
+    /// No command matched the step.
+    Missing,
+    /// Only this many consecutive commands matched the step.
+    TooFew(usize),
+    /// More consecutive commands matched the step than its count.
+    TooMany,
+    /// A command matched no step of a strict sequence.
+    Unexpected,
+}
+
+impl Display for TraceMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line = |f: &mut fmt::Formatter<'_>| match self.line {
+            Some(line) => write!(f, " at command {line}"),
+            None => Ok(()),
+        };
+        match self.reason {
+            MismatchReason::Missing => write!(f, "no command matches {:?}", self.pattern)?,
+            MismatchReason::TooFew(matched) => write!(
+                f,
+                "only {matched} consecutive commands match {:?}",
+                self.pattern
+            )?,
+            MismatchReason::TooMany => write!(f, "too many commands match {:?}", self.pattern)?,
+            MismatchReason::Unexpected if self.pattern.is_empty() => {
+                f.write_str("unexpected command after the last step")?
+            }
+            MismatchReason::Unexpected => {
+                write!(f, "unexpected command before {:?}", self.pattern)?
+            }
+        }
+        line(f)
+    }
+}
+
+impl std::error::Error for TraceMismatch {}
+
+// whether `text` matches `pattern` as a whole, where `*` matches any text
+fn glob(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // no `*` at all
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        assert!(glob("EHLO *", "EHLO client"));
+        assert!(glob("EHLO *", "EHLO "));
+        assert!(!glob("EHLO *", "EHLO"));
+        assert!(glob("MAIL FROM:<*> *=*", "MAIL FROM:<a@b> SIZE=5"));
+        assert!(glob("DATA", "DATA"));
+        assert!(!glob("DATA", "DATA2"));
+        assert!(glob("*a*a", "aa"));
+        assert!(!glob("*a*a", "a"));
+    }
+
+    #[test]
+    fn reports_mismatches() {
+        let commands = ["EHLO c", "NOOP", "RCPT TO:<a>", "RCPT TO:<b>", "DATA"];
+        let reason = |sequence: Sequence| sequence.check(&commands).unwrap_err().reason;
+        assert!(
+            expect_sequence!["EHLO *", "RCPT TO:*" => 2, "DATA"]
+                .check(&commands)
+                .is_ok()
+        );
+        assert_eq!(reason(expect_sequence!["QUIT"]), MismatchReason::Missing);
+        assert_eq!(
+            reason(expect_sequence!["DATA", "EHLO *"]),
+            MismatchReason::Missing
+        );
+        assert_eq!(
+            reason(expect_sequence!["RCPT TO:*" => 3]),
+            MismatchReason::TooFew(2)
+        );
+        assert_eq!(
+            reason(expect_sequence!["RCPT TO:*"]),
+            MismatchReason::TooMany
+        );
+        assert_eq!(
+            expect_sequence!["EHLO *", "RCPT TO:*" => 2]
+                .strict()
+                .check(&commands),
+            Err(TraceMismatch {
+                step: 1,
+                pattern: "RCPT TO:*".into(),
+                line: Some(1),
+                reason: MismatchReason::Unexpected
+            })
+        );
+        let all = expect_sequence!["EHLO *", "NOOP", "RCPT TO:*" => 2, "DATA"].strict();
+        assert!(all.check(&commands).is_ok());
+        let error = expect_sequence!["EHLO *"]
+            .strict()
+            .check(&commands)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unexpected command after the last step at command 1"
+        );
+    }
+}
@@ -0,0 +1,375 @@
+//! Versioned test vectors for the byte-exact transformations of this crate, so other
+//! implementations can check they produce the same output.
+//!
+//! The vectors are plain text files in the `vectors/v<VERSION>` directory of the
+//! repository and the published crate, one per [`Suite`]. Every vector is a block of
+//! `name`, `operation`, `input` and `expected` lines, separated by empty lines. Lines starting
+//! with `#` are comments. Values are escaped like Rust byte strings: `\r`, `\n`, `\t`, `\\`
+//! and `\xHH`, all other characters stand for their UTF-8 encoding.
+//!
+//! A version, once published, never changes. Fixes and new cases go into the next version.
+//!
+//! Every version directory has a `SHA256SUMS` manifest of its files, in the format of
+//! `sha256sum`, which the tests of this crate check the files against. Check a copy with
+//! `sha256sum -c SHA256SUMS` from within the directory. When a version is released, its
+//! manifest is signed with the maintainer's release key, and the detached signature is
+//! attached to the release as `vectors-v<VERSION>-SHA256SUMS.asc`:
+//!
+//! ```text
+//! gpg --armor --detach-sign --output vectors-v1-SHA256SUMS.asc vectors/v1/SHA256SUMS
+//! gpg --verify vectors-v1-SHA256SUMS.asc vectors/v1/SHA256SUMS
+//! ```
+//!
+//! # Example
+//!
+//! ```
+//! use simple_smtp::vectors::{self, Suite};
+//!
+//! for suite in Suite::ALL {
+//!     for vector in vectors::load(suite) {
+//!         assert_eq!(vector.operation.apply(&vector.input), vector.expected, "{}", vector.name);
+//!     }
+//! }
+//! ```
+
+use alloc::{
+    borrow::ToOwned,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::{self, Display, Write};
+
+use crate::{canonicalization::Canonicalization, message::encoded_word, transparency::DotStuffer};
+
+/// The version of the bundled vectors.
+pub const VERSION: u32 = 1;
+
+/// A group of vectors, stored in a file of its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Suite {
+    DotStuffing,
+    Canonicalization,
+    EncodedWord,
+}
+
+impl Suite {
+    pub const ALL: [Suite; 3] = [
+        Suite::DotStuffing,
+        Suite::Canonicalization,
+        Suite::EncodedWord,
+    ];
+
+    /// The file name within the version directory.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Suite::DotStuffing => "dot-stuffing.txt",
+            Suite::Canonicalization => "canonicalization.txt",
+            Suite::EncodedWord => "encoded-word.txt",
+        }
+    }
+
+    /// The content of the bundled file.
+    pub fn data(self) -> &'static str {
+        match self {
+            Suite::DotStuffing => include_str!("../vectors/v1/dot-stuffing.txt"),
+            Suite::Canonicalization => include_str!("../vectors/v1/canonicalization.txt"),
+            Suite::EncodedWord => include_str!("../vectors/v1/encoded-word.txt"),
+        }
+    }
+}
+
+/// A transformation covered by the vectors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// [`DotStuffer`] followed by the end of data marker.
+    DotStuff,
+    /// [`Canonicalization::header`] of a single field.
+    Header(Canonicalization),
+    /// [`Canonicalization::body`].
+    Body(Canonicalization),
+    /// [`encoded_word::encode`], the input must be UTF-8.
+    EncodedWord,
+}
+
+impl Operation {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "dot-stuff" => Operation::DotStuff,
+            "header-simple" => Operation::Header(Canonicalization::Simple),
+            "header-relaxed" => Operation::Header(Canonicalization::Relaxed),
+            "body-simple" => Operation::Body(Canonicalization::Simple),
+            "body-relaxed" => Operation::Body(Canonicalization::Relaxed),
+            "encoded-word" => Operation::EncodedWord,
+            _ => return None,
+        })
+    }
+
+    /// The name used in the vector files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Operation::DotStuff => "dot-stuff",
+            Operation::Header(Canonicalization::Simple) => "header-simple",
+            Operation::Header(Canonicalization::Relaxed) => "header-relaxed",
+            Operation::Body(Canonicalization::Simple) => "body-simple",
+            Operation::Body(Canonicalization::Relaxed) => "body-relaxed",
+            Operation::EncodedWord => "encoded-word",
+        }
+    }
+
+    /// The output of this crate for `input`, which is what `expected` was generated from.
+    pub fn apply(self, input: &[u8]) -> Vec<u8> {
+        match self {
+            Operation::DotStuff => {
+                let mut stuffer = DotStuffer::new();
+                let mut out: Vec<u8> = stuffer.feed(input).flatten().copied().collect();
+                out.extend_from_slice(stuffer.terminator());
+                out
+            }
+            Operation::Header(canonicalization) => canonicalization.header(input),
+            Operation::Body(canonicalization) => canonicalization.body(input),
+            Operation::EncodedWord => {
+                let mut out = String::new();
+                encoded_word::encode(&String::from_utf8_lossy(input), &mut out)
+                    .expect("writing to a String can't fail");
+                out.into_bytes()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vector {
+    pub name: String,
+    pub operation: Operation,
+    pub input: Vec<u8>,
+    pub expected: Vec<u8>,
+}
+
+impl Vector {
+    /// Creates a vector for `input`, with the output of this crate as `expected`.
+    pub fn generate(name: &str, operation: Operation, input: &[u8]) -> Self {
+        Vector {
+            name: name.to_owned(),
+            operation,
+            input: input.to_vec(),
+            expected: operation.apply(input),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Counted from 1.
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid test vector on line {}: {}",
+            self.line, self.reason
+        )
+    }
+}
+
+impl core::error::Error for ParseError {}
+
+/// The bundled vectors of `suite`.
+pub fn load(suite: Suite) -> Vec<Vector> {
+    parse(suite.data()).expect("the bundled vectors are valid")
+}
+
+/// Parses the content of a vector file.
+pub fn parse(text: &str) -> Result<Vec<Vector>, ParseError> {
+    let mut vectors = Vec::new();
+    let mut fields: [Option<&str>; 4] = [None; 4];
+    let mut block_start = 0;
+    // an empty line at the end completes the last block
+    for (idx, line) in text.lines().chain([""]).enumerate() {
+        let line_no = idx + 1;
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.is_empty() {
+            if fields.iter().any(Option::is_some) {
+                vectors.push(vector_from_fields(fields, block_start)?);
+                fields = [None; 4];
+            }
+            continue;
+        }
+        if fields.iter().all(Option::is_none) {
+            block_start = line_no;
+        }
+        let error = |reason| ParseError {
+            line: line_no,
+            reason,
+        };
+        let (key, value) = line
+            .split_once(": ")
+            .ok_or(error("expected `key: value`"))?;
+        let slot = match key {
+            "name" => 0,
+            "operation" => 1,
+            "input" => 2,
+            "expected" => 3,
+            _ => return Err(error("unknown key")),
+        };
+        if fields[slot].replace(value).is_some() {
+            return Err(error("duplicate key"));
+        }
+    }
+    Ok(vectors)
+}
+
+fn vector_from_fields(fields: [Option<&str>; 4], line: usize) -> Result<Vector, ParseError> {
+    let error = |reason| ParseError { line, reason };
+    let [Some(name), Some(operation), Some(input), Some(expected)] = fields else {
+        return Err(error("incomplete vector"));
+    };
+    let operation = Operation::parse(operation).ok_or(error("unknown operation"))?;
+    let input = unescape(input).ok_or(error("invalid escape in input"))?;
+    if operation == Operation::EncodedWord && core::str::from_utf8(&input).is_err() {
+        return Err(error("input is not UTF-8"));
+    }
+    Ok(Vector {
+        name: name.to_string(),
+        operation,
+        input,
+        expected: unescape(expected).ok_or(error("invalid escape in expected"))?,
+    })
+}
+
+/// Formats vectors the way [`parse`] reads them, below a comment naming the suite.
+pub fn format(suite: Suite, vectors: &[Vector]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# simple-smtp test vectors v{VERSION}: {}",
+        suite.file_name()
+    );
+    for vector in vectors {
+        out.push('\n');
+        let _ = writeln!(out, "name: {}", vector.name);
+        let _ = writeln!(out, "operation: {}", vector.operation.name());
+        out.push_str("input: ");
+        escape(&vector.input, &mut out);
+        out.push_str("\nexpected: ");
+        escape(&vector.expected, &mut out);
+        out.push('\n');
+    }
+    out
+}
+
+fn escape(bytes: &[u8], out: &mut String) {
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\r' => out.push_str("\\r"),
+                '\n' => out.push_str("\\n"),
+                '\t' => out.push_str("\\t"),
+                '\\' => out.push_str("\\\\"),
+                c if c.is_ascii_control() => {
+                    let _ = write!(out, "\\x{:02x}", c as u32);
+                }
+                c => out.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            let _ = write!(out, "\\x{byte:02x}");
+        }
+    }
+}
+
+fn unescape(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(idx) = rest.find('\\') {
+        out.extend_from_slice(&rest.as_bytes()[..idx]);
+        let escaped = &rest[idx + 1..];
+        let (byte, len) = match escaped.as_bytes().first()? {
+            b'r' => (b'\r', 1),
+            b'n' => (b'\n', 1),
+            b't' => (b'\t', 1),
+            b'\\' => (b'\\', 1),
+            b'x' => (u8::from_str_radix(escaped.get(1..3)?, 16).ok()?, 3),
+            _ => return None,
+        };
+        out.push(byte);
+        rest = &escaped[len..];
+    }
+    out.extend_from_slice(rest.as_bytes());
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_vectors_match_the_implementation() {
+        for suite in Suite::ALL {
+            let vectors = load(suite);
+            assert!(!vectors.is_empty());
+            for vector in &vectors {
+                assert_eq!(
+                    vector.operation.apply(&vector.input),
+                    vector.expected,
+                    "{} ({})",
+                    vector.name,
+                    vector.operation.name()
+                );
+            }
+            // the files are exactly what `format` produces, so regenerating them is lossless
+            assert_eq!(format(suite, &vectors), suite.data());
+        }
+    }
+
+    #[test]
+    fn bundled_vectors_match_their_checksums() {
+        use sha2::{Digest, Sha256};
+
+        let manifest = include_str!("../vectors/v1/SHA256SUMS");
+        let mut listed = Vec::new();
+        for line in manifest.lines() {
+            let (digest, file_name) = line.split_once("  ").expect("`<digest>  <file>`");
+            let suite = Suite::ALL
+                .into_iter()
+                .find(|suite| suite.file_name() == file_name)
+                .expect("every file is a suite");
+            let mut hex = String::new();
+            for byte in Sha256::digest(suite.data()) {
+                let _ = write!(hex, "{byte:02x}");
+            }
+            assert_eq!(hex, digest, "{file_name} changed after it was published");
+            listed.push(suite);
+        }
+        assert_eq!(listed.len(), Suite::ALL.len());
+    }
+
+    #[test]
+    fn escapes_round_trip() {
+        let bytes = b"a\\b\r\n\t\x00\x7f\xff\xc3\xbc.";
+        let mut escaped = String::new();
+        escape(bytes, &mut escaped);
+        assert_eq!(escaped, "a\\\\b\\r\\n\\t\\x00\\x7f\\xffü.");
+        assert_eq!(unescape(&escaped).unwrap(), bytes);
+    }
+
+    #[test]
+    fn reports_invalid_vectors() {
+        let missing = "name: x\noperation: dot-stuff\ninput: a\n";
+        assert_eq!(
+            parse(missing),
+            Err(ParseError {
+                line: 1,
+                reason: "incomplete vector"
+            })
+        );
+        let unknown = "# comment\n\nname: x\noperation: rot13\ninput: a\nexpected: n\n";
+        assert_eq!(parse(unknown).unwrap_err().reason, "unknown operation");
+        assert_eq!(parse("input a").unwrap_err().line, 1);
+        assert_eq!(parse("input: \\q").unwrap_err().line, 1);
+    }
+}
@@ -0,0 +1,107 @@
+use core::fmt::{self, Display};
+
+use crate::ReadWrite;
+
+/// A stream whose writes may be short, like most socket APIs, see [`WriteAll`].
+pub trait PartialWrite {
+    type Error: core::error::Error + 'static;
+    fn read(&mut self, buf: &mut [u8]) -> impl Future<Output = Result<usize, Self::Error>>;
+    /// Writes a part of `buf`, returning how many bytes were written. Returns 0 only if the
+    /// stream can't accept any more data.
+    fn write(&mut self, buf: &[u8]) -> impl Future<Output = Result<usize, Self::Error>>;
+    fn flush(&mut self) -> impl Future<Output = Result<(), Self::Error>> {
+        async { Ok(()) }
+    }
+}
+
+/// A [`ReadWrite`] for a [`PartialWrite`] stream, which writes the rest of every short
+/// write.
+///
+/// [`ReadWrite`] writes must be complete, a stream which returns after writing only a part
+/// would silently truncate commands and messages. Wrapping it in this instead of looping in
+/// every implementation rules that out.
+///
+/// # Panics
+///
+/// In debug builds, if the stream claims to have written more bytes than it was given.
+///
+/// # Example
+///
+/// ```
+/// use simple_smtp::{PartialWrite, ReadWrite, WriteAll};
+///
+/// // accepts at most 4 bytes per write
+/// struct Narrow(Vec<u8>);
+///
+/// impl PartialWrite for Narrow {
+///     type Error = core::convert::Infallible;
+///     async fn read(&mut self, _buf: &mut [u8]) -> Result<usize, Self::Error> {
+///         Ok(0)
+///     }
+///     async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+///         let len = buf.len().min(4);
+///         self.0.extend_from_slice(&buf[..len]);
+///         Ok(len)
+///     }
+/// }
+///
+/// # async fn example() {
+/// let mut stream = WriteAll(Narrow(Vec::new()));
+/// stream.write_single(b"NOOP\r\n").await.unwrap();
+/// assert_eq!(stream.0.0, b"NOOP\r\n");
+/// # }
+/// ```
+pub struct WriteAll<T: PartialWrite>(pub T);
+
+impl<T: PartialWrite> ReadWrite for WriteAll<T> {
+    type Error = WriteAllError<T::Error>;
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf).await.map_err(WriteAllError::Io)
+    }
+
+    async fn write_single(&mut self, mut buf: &[u8]) -> Result<(), Self::Error> {
+        while !buf.is_empty() {
+            let written = self.0.write(buf).await.map_err(WriteAllError::Io)?;
+            debug_assert!(
+                written <= buf.len(),
+                "PartialWrite::write wrote {written} of {} bytes",
+                buf.len()
+            );
+            if written == 0 {
+                return Err(WriteAllError::WriteZero);
+            }
+            buf = buf.get(written..).unwrap_or_default();
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.flush().await.map_err(WriteAllError::Io)
+    }
+}
+
+#[derive(Debug)]
+pub enum WriteAllError<E> {
+    Io(E),
+    /// The stream accepted no bytes at all, so the rest could never be written.
+    WriteZero,
+}
+
+impl<E: Display> Display for WriteAllError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteAllError::Io(e) => e.fmt(f),
+            WriteAllError::WriteZero => write!(f, "Stream accepted no more data"),
+        }
+    }
+}
+
+impl<E: core::error::Error + 'static> core::error::Error for WriteAllError<E> {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            WriteAllError::Io(e) => Some(e),
+            WriteAllError::WriteZero => None,
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Tests for sealing and validating ARC chains.
+#![cfg(all(feature = "arc", feature = "std"))]
+
+use simple_smtp::{
+    arc::{ArcError, ArcSealer, ChainStatus, Verifier, validate_chain},
+    dkim::{Algorithm, SigningKey},
+    message::ParseError,
+};
+
+const MESSAGE: &[u8] = b"From: joe@example.com\r\n\
+    To: list@example.org\r\n\
+    Subject: the game\r\n\
+    \r\n\
+    We lost the game.  Are you hungry yet?\r\n";
+
+// "signs" with the digest itself, and records what it signed
+#[derive(Default)]
+struct DigestKey {
+    data: Vec<Vec<u8>>,
+}
+
+impl SigningKey for DigestKey {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::Ed25519Sha256
+    }
+
+    fn sign(&mut self, data: &[u8], digest: &[u8; 32]) -> Vec<u8> {
+        self.data.push(data.to_vec());
+        digest.to_vec()
+    }
+}
+
+// accepts the signatures of a DigestKey published for example.org and example.net
+struct DigestKeys;
+
+impl Verifier for DigestKeys {
+    async fn verify(
+        &self,
+        domain: &str,
+        _selector: &str,
+        algorithm: Algorithm,
+        _data: &[u8],
+        digest: &[u8; 32],
+        signature: &[u8],
+    ) -> bool {
+        ["example.org", "example.net"].contains(&domain)
+            && algorithm == Algorithm::Ed25519Sha256
+            && signature == digest
+    }
+}
+
+fn forward(
+    key: &mut DigestKey,
+    domain: &str,
+    message: &[u8],
+    status: ChainStatus,
+) -> Result<Vec<u8>, ArcError> {
+    let results = format!("{domain}; arc={}", status.as_str());
+    let mut sealer = ArcSealer::new(key, domain, "sel").with_timestamp(1528637909);
+    let mut forwarded = sealer.seal(message, status, &results)?;
+    forwarded.extend_from_slice(message);
+    Ok(forwarded)
+}
+
+#[test]
+fn test_sealed_fields() {
+    let mut key = DigestKey::default();
+    let forwarded = forward(&mut key, "example.org", MESSAGE, ChainStatus::None).unwrap();
+    let forwarded = String::from_utf8(forwarded).unwrap();
+    let lines: Vec<&str> = forwarded.split("\r\n").collect();
+    assert_eq!(
+        lines[0],
+        "ARC-Seal: i=1; a=ed25519-sha256; cv=none; d=example.org; s=sel; t=1528637909;"
+    );
+    assert!(lines[1].starts_with("\tb="));
+    assert_eq!(
+        lines[2],
+        "ARC-Message-Signature: i=1; a=ed25519-sha256; c=relaxed/relaxed; d=example.org; \
+         s=sel; t=1528637909;"
+    );
+    assert_eq!(lines[3], "\th=Subject:To:From;");
+    assert!(lines[4].starts_with("\tbh="));
+    assert!(lines[5].starts_with("\tb="));
+    assert_eq!(
+        lines[6],
+        "ARC-Authentication-Results: i=1; example.org; arc=none"
+    );
+    assert_eq!(lines[7], "From: joe@example.com");
+
+    // the seal covers the other fields of its set, and itself without the signature
+    let sealed = String::from_utf8(key.data.pop().unwrap()).unwrap();
+    assert!(sealed.starts_with(
+        "arc-authentication-results:i=1; example.org; arc=none\r\n\
+         arc-message-signature:i=1; a=ed25519-sha256;"
+    ));
+    assert!(sealed.ends_with(
+        "\r\narc-seal:i=1; a=ed25519-sha256; cv=none; d=example.org; s=sel; t=1528637909; b="
+    ));
+}
+
+#[tokio::test]
+async fn test_chain_of_forwarders() {
+    assert_eq!(
+        validate_chain(MESSAGE, &DigestKeys).await,
+        ChainStatus::None
+    );
+
+    let mut key = DigestKey::default();
+    let first = forward(&mut key, "example.org", MESSAGE, ChainStatus::None).unwrap();
+    let status = validate_chain(&first, &DigestKeys).await;
+    assert_eq!(status, ChainStatus::Pass);
+
+    // the second forwarder changes the subject, which only its own signature covers
+    let changed = String::from_utf8(first)
+        .unwrap()
+        .replace("Subject: the game", "Subject: [list] the game");
+    let second = forward(&mut key, "example.net", changed.as_bytes(), status).unwrap();
+    assert!(second.starts_with(b"ARC-Seal: i=2; a=ed25519-sha256; cv=pass;"));
+    assert_eq!(
+        validate_chain(&second, &DigestKeys).await,
+        ChainStatus::Pass
+    );
+
+    // changing the body breaks the latest message signature
+    let mut modified = second.clone();
+    modified.extend_from_slice(b"--\r\nfooter\r\n");
+    assert_eq!(
+        validate_chain(&modified, &DigestKeys).await,
+        ChainStatus::Fail
+    );
+    // changing an earlier set breaks the seals
+    let modified = String::from_utf8(second)
+        .unwrap()
+        .replace("i=1; example.org; arc=none", "i=1; example.org; arc=pass");
+    assert_eq!(
+        validate_chain(modified.as_bytes(), &DigestKeys).await,
+        ChainStatus::Fail
+    );
+}
+
+#[tokio::test]
+async fn test_unknown_key() {
+    let mut key = DigestKey::default();
+    let forwarded = forward(&mut key, "example.com", MESSAGE, ChainStatus::None).unwrap();
+    assert_eq!(
+        validate_chain(&forwarded, &DigestKeys).await,
+        ChainStatus::Fail
+    );
+}
+
+#[tokio::test]
+async fn test_broken_chains() {
+    let mut key = DigestKey::default();
+    let first = forward(&mut key, "example.org", MESSAGE, ChainStatus::None).unwrap();
+    let first = String::from_utf8(first).unwrap();
+
+    // a set without its message signature
+    let start = first.find("ARC-Message-Signature").unwrap();
+    let end = first.find("ARC-Authentication-Results").unwrap();
+    let incomplete = format!("{}{}", &first[..start], &first[end..]);
+    assert_eq!(
+        validate_chain(incomplete.as_bytes(), &DigestKeys).await,
+        ChainStatus::Fail
+    );
+    assert_eq!(
+        forward(
+            &mut key,
+            "example.net",
+            incomplete.as_bytes(),
+            ChainStatus::Fail
+        ),
+        Err(ArcError::InvalidChain)
+    );
+
+    // nothing is added after a failed chain
+    let failed = forward(&mut key, "example.net", first.as_bytes(), ChainStatus::Fail).unwrap();
+    assert_eq!(
+        validate_chain(&failed, &DigestKeys).await,
+        ChainStatus::Fail
+    );
+    assert_eq!(
+        forward(&mut key, "example.com", &failed, ChainStatus::Fail),
+        Err(ArcError::ChainFailed)
+    );
+
+    assert_eq!(
+        forward(&mut key, "example.net", first.as_bytes(), ChainStatus::None),
+        Err(ArcError::StatusMismatch)
+    );
+    assert_eq!(
+        forward(&mut key, "example.net", MESSAGE, ChainStatus::Pass),
+        Err(ArcError::StatusMismatch)
+    );
+
+    // a header line which isn't a field
+    let malformed = [b"not a field\r\n".as_slice(), MESSAGE].concat();
+    assert_eq!(
+        validate_chain(&malformed, &DigestKeys).await,
+        ChainStatus::Fail
+    );
+    assert_eq!(
+        forward(&mut key, "example.net", &malformed, ChainStatus::None),
+        Err(ArcError::Malformed(ParseError::InvalidField))
+    );
+}
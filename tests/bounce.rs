@@ -0,0 +1,136 @@
+//! Tests for parsing delivery status notifications.
+#![cfg(feature = "alloc")]
+
+use simple_smtp::{
+    bounce::{self, Action, BounceError, Dsn, RecipientStatus},
+    message::ParseError,
+    smtp::enhanced::{Class, EnhancedCode},
+};
+
+// as sent by Postfix, with the original message returned
+const BOUNCE: &str = "Return-Path: <>\r\n\
+    From: MAILER-DAEMON@mx.example.org (Mail Delivery System)\r\n\
+    Subject: Undelivered Mail Returned to Sender\r\n\
+    To: joe@example.org\r\n\
+    MIME-Version: 1.0\r\n\
+    Content-Type: multipart/report; report-type=delivery-status;\r\n\
+    \tboundary=\"4B2F21A0C.1718000000/mx.example.org\"\r\n\
+    \r\n\
+    This is a MIME-encapsulated message.\r\n\
+    \r\n\
+    --4B2F21A0C.1718000000/mx.example.org\r\n\
+    Content-Description: Notification\r\n\
+    Content-Type: text/plain; charset=us-ascii\r\n\
+    \r\n\
+    I'm sorry to have to inform you that your message could not\r\n\
+    be delivered to one or more recipients.\r\n\
+    \r\n\
+    --4B2F21A0C.1718000000/mx.example.org\r\n\
+    Content-Description: Delivery report\r\n\
+    Content-Type: message/delivery-status\r\n\
+    \r\n\
+    Reporting-MTA: dns; mx.example.org\r\n\
+    X-Postfix-Queue-ID: 4B2F21A0C\r\n\
+    Original-Envelope-Id: QQ314159\r\n\
+    Arrival-Date: Mon, 10 Jun 2024 08:00:00 +0000 (UTC)\r\n\
+    \r\n\
+    Final-Recipient: rfc822; bob@example.com\r\n\
+    Original-Recipient: rfc822;Bob@Example.com\r\n\
+    Action: failed\r\n\
+    Status: 5.1.1\r\n\
+    Remote-MTA: dns; mx.example.com\r\n\
+    Diagnostic-Code: smtp; 550 5.1.1 <bob@example.com>: Recipient address\r\n\
+    \x20   rejected: User unknown\r\n\
+    \r\n\
+    Final-Recipient: rfc822; carol@example.net\r\n\
+    Action: delayed (will retry)\r\n\
+    Status: 4.4.1 (connection timed out)\r\n\
+    \r\n\
+    --4B2F21A0C.1718000000/mx.example.org\r\n\
+    Content-Description: Undelivered Message Headers\r\n\
+    Content-Type: text/rfc822-headers\r\n\
+    \r\n\
+    From: joe@example.org\r\n\
+    Action: failed\r\n\
+    \r\n\
+    --4B2F21A0C.1718000000/mx.example.org--\r\n";
+
+#[test]
+fn test_parse_report() {
+    let dsn = bounce::parse(BOUNCE.as_bytes()).unwrap();
+    assert_eq!(
+        dsn,
+        Dsn {
+            reporting_mta: Some("mx.example.org".into()),
+            envelope_id: Some("QQ314159".into()),
+            recipients: vec![
+                RecipientStatus {
+                    original_recipient: Some("Bob@Example.com".into()),
+                    final_recipient: "bob@example.com".into(),
+                    action: Action::Failed,
+                    status: EnhancedCode::new(Class::PermanentFailure, 1, 1),
+                    remote_mta: Some("mx.example.com".into()),
+                    diagnostic_code: Some(
+                        "550 5.1.1 <bob@example.com>: Recipient address    rejected: User \
+                         unknown"
+                            .into()
+                    ),
+                },
+                RecipientStatus {
+                    original_recipient: None,
+                    final_recipient: "carol@example.net".into(),
+                    action: Action::Delayed,
+                    status: EnhancedCode::new(Class::PersistentTransientFailure, 4, 1),
+                    remote_mta: None,
+                    diagnostic_code: None,
+                },
+            ],
+        }
+    );
+    let failed: Vec<_> = dsn.failed().map(|r| r.final_recipient.as_str()).collect();
+    assert_eq!(failed, ["bob@example.com"]);
+}
+
+#[test]
+fn test_lf_line_endings() {
+    let dsn = bounce::parse(BOUNCE.replace("\r\n", "\n").as_bytes()).unwrap();
+    assert_eq!(dsn, bounce::parse(BOUNCE.as_bytes()).unwrap());
+}
+
+#[test]
+fn test_not_a_bounce() {
+    assert_eq!(
+        bounce::parse(b"Subject: hi\r\n\r\nhello\r\n"),
+        Err(BounceError::NotAReport)
+    );
+    // a report of another type, e.g. a read receipt
+    let receipt = BOUNCE.replace("delivery-status;", "disposition-notification;");
+    assert_eq!(
+        bounce::parse(receipt.as_bytes()),
+        Err(BounceError::NotAReport)
+    );
+    let without_status = BOUNCE.replace("message/delivery-status", "text/plain");
+    assert_eq!(
+        bounce::parse(without_status.as_bytes()),
+        Err(BounceError::NoDeliveryStatus)
+    );
+    let garbled = BOUNCE.replace("Action: delayed", "Action delayed");
+    assert_eq!(
+        bounce::parse(garbled.as_bytes()),
+        Err(BounceError::Malformed(ParseError::InvalidField))
+    );
+}
+
+#[test]
+fn test_invalid_recipient() {
+    let missing = BOUNCE.replace("Status: 4.4.1", "X-Status: 4.4.1");
+    assert_eq!(
+        bounce::parse(missing.as_bytes()),
+        Err(BounceError::MissingField("Status"))
+    );
+    let invalid = BOUNCE.replace("Action: delayed", "Action: lost");
+    assert_eq!(
+        bounce::parse(invalid.as_bytes()),
+        Err(BounceError::InvalidField("Action"))
+    );
+}
@@ -0,0 +1,140 @@
+//! Tests for injecting failures into the sessions of the tokio client.
+#![cfg(feature = "chaos")]
+
+mod common;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use simple_smtp::{
+    Error,
+    integrations::tokio::{ClientSession, SmtpClientBuilder, TlsMode, chaos::ChaosConfig},
+    smtp::{ReplyCode, Timeouts},
+};
+use tokio::task::JoinHandle;
+
+// accepts everything, and records the commands it received until the connection is closed
+async fn recording_server() -> (u16, Arc<Mutex<Vec<String>>>, JoinHandle<()>) {
+    let (listener, port) = common::listen().await;
+    let commands = Arc::new(Mutex::new(Vec::new()));
+    let received = commands.clone();
+    let server = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        common::accept_mail(socket, |command| {
+            received.lock().unwrap().push(command.to_owned());
+            true
+        })
+        .await;
+    });
+    (port, commands, server)
+}
+
+async fn connect(port: u16, chaos: ChaosConfig) -> ClientSession {
+    SmtpClientBuilder::new("127.0.0.1")
+        .with_port(port)
+        .with_tls(TlsMode::None)
+        .with_ehlo_domain("client.example.org")
+        .with_reply_timeouts(Timeouts::default().with_data_end(Duration::from_millis(100)))
+        .with_chaos(chaos)
+        .connect()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_nothing_injected_by_default() {
+    let (port, commands, _) = recording_server().await;
+    let mut smtp = connect(port, ChaosConfig::new()).await;
+    smtp.send_mail("me@example.org", ["you@example.com"].iter(), b"hi\r\n")
+        .await
+        .unwrap();
+    smtp.quit().await.unwrap();
+    assert_eq!(
+        *commands.lock().unwrap(),
+        [
+            "EHLO client.example.org",
+            "MAIL FROM:<me@example.org>",
+            "RCPT TO:<you@example.com>",
+            "DATA",
+            ".",
+            "QUIT"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_forced_451() {
+    let (port, commands, _) = recording_server().await;
+    let mut smtp = connect(port, ChaosConfig::new().with_forced_451(1.0)).await;
+    let err = smtp
+        .send_mail("me@example.org", ["you@example.com"].iter(), b"hi\r\n")
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err,
+        Error::ServerRejected { code, .. } if code == ReplyCode::new(451)
+    ));
+    // the server never saw the transaction, so the session goes on
+    smtp.noop().await.unwrap();
+    assert_eq!(
+        *commands.lock().unwrap(),
+        ["EHLO client.example.org", "NOOP"]
+    );
+}
+
+#[tokio::test]
+async fn test_drop_after_rcpt() {
+    let (port, commands, server) = recording_server().await;
+    let mut smtp = connect(port, ChaosConfig::new().with_drop_after_rcpt(1.0)).await;
+    let err = smtp
+        .send_mail("me@example.org", ["you@example.com"].iter(), b"hi\r\n")
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err,
+        Error::IoError(ref e) if e.kind() == std::io::ErrorKind::ConnectionReset
+    ));
+    assert!(!err.is_session_usable());
+    // the server sees the connection close
+    server.await.unwrap();
+    assert_eq!(
+        commands.lock().unwrap().last().unwrap(),
+        "RCPT TO:<you@example.com>"
+    );
+}
+
+#[tokio::test]
+async fn test_delayed_data_ack() {
+    let (port, _, _) = recording_server().await;
+    let chaos = ChaosConfig::new().with_delayed_data_ack(1.0, Duration::from_secs(10));
+    let mut smtp = connect(port, chaos).await;
+    let err = smtp
+        .send_mail("me@example.org", ["you@example.com"].iter(), b"hi\r\n")
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(err, Error::Timeout), "{err:?}");
+}
+
+#[test]
+fn test_parse_config() {
+    let parsed: ChaosConfig =
+        "drop_after_rcpt=0.5, force_451=0.1,delay_data_ack=1,delay_ms=1500,seed=7"
+            .parse()
+            .unwrap();
+    let expected = ChaosConfig::new()
+        .with_drop_after_rcpt(0.5)
+        .with_forced_451(0.1)
+        .with_delayed_data_ack(1.0, Duration::from_millis(1500))
+        .with_seed(7);
+    assert_eq!(parsed, expected);
+    assert_eq!("".parse::<ChaosConfig>().unwrap(), ChaosConfig::new());
+    for invalid in ["force_451=2", "force_451", "unknown=1", "delay_ms=soon"] {
+        let err = invalid.parse::<ChaosConfig>().unwrap_err();
+        assert_eq!(err.0, invalid);
+    }
+}
@@ -0,0 +1,331 @@
+//! Tests for `SmtpClientBuilder` against a scripted server on a local socket.
+
+mod common;
+
+use std::time::Duration;
+
+use common::scripted_server;
+
+use simple_smtp::{
+    Error, ProtocolError,
+    integrations::tokio::{ClientSession, SmtpClientBuilder, TlsMode, TokioIo},
+    smtp::{
+        auth::AuthMode,
+        negotiation::{DesiredFeatures, FeatureStatus},
+    },
+};
+
+#[tokio::test]
+async fn test_plaintext_session_with_auth() {
+    let (port, server) = scripted_server(&[
+        "220 mail.example.com ESMTP\r\n",
+        "250-mail.example.com\r\n250 AUTH PLAIN\r\n",
+        "235 Authentication successful\r\n",
+    ])
+    .await;
+
+    let smtp = SmtpClientBuilder::new("127.0.0.1")
+        .with_port(port)
+        .with_tls(TlsMode::None)
+        .with_auth(AuthMode::Plain {
+            username: "user",
+            password: "pass",
+        })
+        .allow_plaintext_auth(true)
+        .with_ehlo_domain("client.example.com")
+        .with_timeout(Duration::from_secs(5))
+        .with_desired_features(
+            DesiredFeatures::new()
+                .with_pipelining()
+                .with_auth_mechanism("PLAIN"),
+        )
+        .connect()
+        .await
+        .unwrap();
+    let report = smtp.negotiation_report().unwrap();
+    assert_eq!(report.auth(), FeatureStatus::Satisfied);
+    assert_eq!(report.pipelining(), FeatureStatus::Unsatisfied);
+    let (stream, _) = smtp.into_inner();
+    assert!(!stream.is_tls());
+    drop(stream);
+
+    let received = server.await.unwrap();
+    assert_eq!(
+        received,
+        "EHLO client.example.com\r\nAUTH PLAIN AHVzZXIAcGFzcw==\r\n"
+    );
+}
+
+#[tokio::test]
+async fn test_plaintext_auth_is_opt_in() {
+    let (port, server) = scripted_server(&[
+        "220 mail.example.com ESMTP\r\n",
+        "250-mail.example.com\r\n250 AUTH PLAIN\r\n",
+    ])
+    .await;
+
+    let result = SmtpClientBuilder::new("127.0.0.1")
+        .with_port(port)
+        .with_tls(TlsMode::None)
+        .with_auth(AuthMode::Plain {
+            username: "user",
+            password: "pass",
+        })
+        .with_ehlo_domain("client.example.com")
+        .connect()
+        .await;
+    assert!(matches!(
+        result,
+        Err(Error::ProtocolError(ProtocolError::PlaintextAuth))
+    ));
+    // the credentials never left the client
+    assert_eq!(server.await.unwrap(), "EHLO client.example.com\r\n");
+}
+
+#[tokio::test]
+async fn test_ehlo_after_auth() {
+    const REPLIES: &[&str] = &[
+        "220 mail.example.com ESMTP\r\n",
+        "250-mail.example.com\r\n250-SIZE 1000\r\n250 AUTH PLAIN\r\n",
+        "235 Authentication successful\r\n",
+        "250-mail.example.com\r\n250 SIZE 2000\r\n",
+    ];
+    let connect = |port, again| {
+        let builder = SmtpClientBuilder::new("127.0.0.1")
+            .with_port(port)
+            .with_tls(TlsMode::None)
+            .with_auth(AuthMode::Plain {
+                username: "user",
+                password: "pass",
+            })
+            .allow_plaintext_auth(true)
+            .with_ehlo_domain("client.example.com");
+        if again {
+            builder.with_ehlo_after_auth()
+        } else {
+            builder
+        }
+        .connect()
+    };
+
+    let (port, server) = scripted_server(REPLIES).await;
+    let smtp = connect(port, true).await.unwrap();
+    assert_eq!(smtp.capabilities().max_size(), Some(2000));
+    let before = smtp.pre_auth_capabilities().unwrap();
+    assert_eq!(before.max_size(), Some(1000));
+    drop(smtp);
+    assert_eq!(
+        server.await.unwrap(),
+        "EHLO client.example.com\r\nAUTH PLAIN AHVzZXIAcGFzcw==\r\nEHLO client.example.com\r\n"
+    );
+
+    let (port, server) = scripted_server(REPLIES).await;
+    let smtp = connect(port, false).await.unwrap();
+    // only what the server advertised before AUTH is known
+    assert_eq!(smtp.capabilities().max_size(), Some(1000));
+    drop(smtp);
+    assert_eq!(
+        server.await.unwrap(),
+        "EHLO client.example.com\r\nAUTH PLAIN AHVzZXIAcGFzcw==\r\n"
+    );
+}
+
+#[tokio::test]
+async fn test_address_literal_target() {
+    let (port, server) = scripted_server(&[
+        "220 mail.example.com ESMTP\r\n",
+        "250 mail.example.com\r\n",
+        "221 Bye\r\n",
+    ])
+    .await;
+
+    let mut smtp = SmtpClientBuilder::new("[127.0.0.1]")
+        .with_port(port)
+        .with_tls(TlsMode::None)
+        .connect()
+        .await
+        .unwrap();
+    smtp.quit().await.unwrap();
+    drop(smtp);
+
+    // without an EHLO domain the client greets with its own address
+    assert_eq!(server.await.unwrap(), "EHLO [127.0.0.1]\r\nQUIT\r\n");
+}
+
+#[tokio::test]
+async fn test_helo_fallback() {
+    let (port, server) = scripted_server(&[
+        "220 plc.example.com SMTP\r\n",
+        "502 Command not implemented\r\n",
+        "250 plc.example.com\r\n",
+    ])
+    .await;
+
+    let smtp = SmtpClientBuilder::new("127.0.0.1")
+        .with_port(port)
+        .with_tls(TlsMode::None)
+        .with_ehlo_domain("client.example.com")
+        .allow_helo_fallback(true)
+        .connect()
+        .await
+        .unwrap();
+    assert!(smtp.is_legacy());
+    drop(smtp);
+    assert_eq!(
+        server.await.unwrap(),
+        "EHLO client.example.com\r\nHELO client.example.com\r\n"
+    );
+}
+
+#[tokio::test]
+async fn test_helo_fallback_is_opt_in() {
+    let (port, _server) = scripted_server(&[
+        "220 plc.example.com SMTP\r\n",
+        "502 Command not implemented\r\n",
+    ])
+    .await;
+
+    let result = SmtpClientBuilder::new("127.0.0.1")
+        .with_port(port)
+        .with_tls(TlsMode::None)
+        .connect()
+        .await;
+    assert!(matches!(result, Err(Error::ServerRejected { .. })));
+}
+
+#[tokio::test]
+async fn test_starttls_required() {
+    let (port, _server) =
+        scripted_server(&["220 mail.example.com ESMTP\r\n", "250 mail.example.com\r\n"]).await;
+
+    let result = SmtpClientBuilder::new("127.0.0.1")
+        .with_port(port)
+        .with_tls(TlsMode::StartTls)
+        .connect()
+        .await;
+    assert!(matches!(result, Err(Error::ProtocolError(_))));
+}
+
+#[tokio::test]
+async fn test_failed_starttls_handshake() {
+    // the server closes the connection instead of starting the handshake
+    let (port, server) = scripted_server(&[
+        "220 mail.example.com ESMTP\r\n",
+        "250-mail.example.com\r\n250 STARTTLS\r\n",
+        "220 Ready to start TLS\r\n",
+    ])
+    .await;
+
+    let result = SmtpClientBuilder::new("127.0.0.1")
+        .with_port(port)
+        .with_tls(TlsMode::StartTls)
+        .with_timeout(Duration::from_secs(5))
+        .connect()
+        .await;
+    match result {
+        Err(e @ Error::Tls(_)) => assert!(!e.is_session_usable()),
+        _ => panic!("expected a TLS error"),
+    }
+    assert!(server.await.unwrap().ends_with("STARTTLS\r\n"));
+}
+
+#[cfg(feature = "native-tls")]
+#[tokio::test]
+async fn test_failed_native_tls_handshake() {
+    use simple_smtp::Smtp;
+
+    let (port, server) = scripted_server(&[
+        "220 mail.example.com ESMTP\r\n",
+        "250-mail.example.com\r\n250 STARTTLS\r\n",
+        "220 Ready to start TLS\r\n",
+    ])
+    .await;
+
+    let tcp = tokio::net::TcpStream::connect(("127.0.0.1", port))
+        .await
+        .unwrap();
+    let mut smtp = Smtp::new(TokioIo(tcp));
+    smtp.ready().await.unwrap();
+    smtp.ehlo("client.example.com").await.unwrap();
+    smtp.starttls().await.unwrap();
+    match smtp.upgrade_to_tls_native("localhost").await {
+        Err(Error::Tls(_)) => {}
+        _ => panic!("expected a TLS error"),
+    }
+    assert!(server.await.unwrap().ends_with("STARTTLS\r\n"));
+}
+
+#[tokio::test]
+async fn test_starttls_on_demand() {
+    const SCRIPT: &[&str] = &[
+        "220 mail.example.com ESMTP\r\n",
+        "250-mail.example.com\r\n250-STARTTLS\r\n250 AUTH PLAIN\r\n",
+        "530 5.7.0 Must issue a STARTTLS command first\r\n",
+        "220 Ready to start TLS\r\n",
+    ];
+    let builder = |port| {
+        SmtpClientBuilder::new("127.0.0.1")
+            .with_port(port)
+            .with_tls(TlsMode::None)
+            .with_auth(AuthMode::Plain {
+                username: "user",
+                password: "pass",
+            })
+            .allow_plaintext_auth(true)
+            .with_timeout(Duration::from_secs(5))
+    };
+
+    // the handshake is attempted, but the server closes the connection instead
+    let (port, server) = scripted_server(SCRIPT).await;
+    let result = builder(port).connect().await;
+    assert!(matches!(result, Err(Error::Tls(_))));
+    let received = server.await.unwrap();
+    assert!(received.contains("AUTH PLAIN"));
+    assert!(received.ends_with("STARTTLS\r\n"));
+
+    let (port, server) = scripted_server(SCRIPT).await;
+    let result = builder(port)
+        .allow_starttls_on_demand(false)
+        .connect()
+        .await;
+    match result {
+        Err(e) => assert!(e.is_starttls_required() && e.is_permanent()),
+        Ok(_) => panic!("expected the server to require STARTTLS"),
+    }
+    assert!(!server.await.unwrap().contains("STARTTLS"));
+}
+
+#[tokio::test]
+async fn test_timeout() {
+    // the server accepts the connection but never greets
+    let (listener, port) = common::listen().await;
+    let _server = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        drop(socket);
+    });
+
+    let result = SmtpClientBuilder::new("127.0.0.1")
+        .with_port(port)
+        .with_tls(TlsMode::None)
+        .with_timeout(Duration::from_millis(50))
+        .connect()
+        .await;
+    match result {
+        Err(Error::IoError(e)) => assert_eq!(e.kind(), std::io::ErrorKind::TimedOut),
+        _ => panic!("expected a timeout"),
+    }
+}
+
+// never called, it only has to compile: reading a reply holds the session's timer across an
+// await, which must not keep the futures of sessions from being spawned
+#[allow(dead_code)]
+fn session_futures_are_send(
+    smtp: &mut simple_smtp::Smtp<'static, TokioIo<tokio::net::TcpStream>>,
+    session: &mut ClientSession,
+) {
+    fn assert_send<T: Send>(_: T) {}
+
+    assert_send(smtp.ehlo("client.example.com"));
+    assert_send(session.send_mail("alice@example.com", ["bob@example.com"].iter(), b""));
+}
@@ -0,0 +1,82 @@
+//! Tests for TLS client certificates, which replace AUTH on relays that use mutual TLS.
+#![cfg(all(feature = "tokio", feature = "rustls"))]
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use simple_smtp::{
+    Error, ProtocolError,
+    integrations::tokio::{ClientCertificate, SmtpClientBuilder, TlsMode, connect_tls},
+    smtp::auth::AuthMode,
+};
+use tokio::io::AsyncReadExt;
+
+fn certificate(key: &'static [u8]) -> ClientCertificate {
+    ClientCertificate {
+        chain: vec![CertificateDer::from(
+            &include_bytes!("data/client-cert.der")[..],
+        )],
+        key: PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key)),
+    }
+}
+
+#[tokio::test]
+async fn test_connect_tls_rejects_mismatched_key() {
+    let (client, mut server) = tokio::io::duplex(4096);
+    let cert = certificate(include_bytes!("data/other-key.der"));
+    let Err(err) = connect_tls(client, "mail.example.com", Some(cert)).await else {
+        panic!("the key doesn't belong to the certificate");
+    };
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    // the handshake never started
+    let mut buf = [0; 1];
+    assert_eq!(server.read(&mut buf).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_connect_tls_with_client_certificate_starts_handshake() {
+    let (client, mut server) = tokio::io::duplex(4096);
+    let cert = certificate(include_bytes!("data/client-key.der"));
+    let handshake = tokio::spawn(connect_tls(client, "mail.example.com", Some(cert)));
+
+    // a TLS handshake record carrying the ClientHello
+    let mut header = [0; 1];
+    server.read_exact(&mut header).await.unwrap();
+    assert_eq!(header, [0x16]);
+    drop(server);
+    let Err(err) = handshake.await.unwrap() else {
+        panic!("the server went away");
+    };
+    assert_ne!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[tokio::test]
+async fn test_builder_rejects_client_certificate_without_tls() {
+    let result = SmtpClientBuilder::new("127.0.0.1")
+        .with_port(1)
+        .with_tls(TlsMode::None)
+        .with_client_certificate(certificate(include_bytes!("data/client-key.der")))
+        .with_auth(AuthMode::ClientCertOnly)
+        .connect()
+        .await;
+    assert!(matches!(
+        result,
+        Err(Error::ProtocolError(
+            ProtocolError::ClientCertificateWithoutTls
+        ))
+    ));
+}
+
+#[tokio::test]
+async fn test_builder_rejects_client_cert_auth_without_certificate() {
+    let result = SmtpClientBuilder::new("127.0.0.1")
+        .with_port(1)
+        .with_tls(TlsMode::Implicit)
+        .with_auth(AuthMode::ClientCertOnly)
+        .connect()
+        .await;
+    assert!(matches!(
+        result,
+        Err(Error::ProtocolError(
+            ProtocolError::MissingClientCertificate
+        ))
+    ));
+}
@@ -0,0 +1,167 @@
+//! Servers shared by the integration tests, on a local socket or an in-memory stream.
+// every test uses only some of them
+#![allow(dead_code, unused_imports)]
+
+#[cfg(feature = "tokio")]
+pub use local::*;
+#[cfg(all(feature = "tokio", feature = "rustls"))]
+pub use tls::*;
+
+#[cfg(feature = "tokio")]
+mod local {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+        task::JoinHandle,
+    };
+
+    /// Listens on a free port of the loopback interface.
+    pub async fn listen() -> (TcpListener, u16) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        (listener, port)
+    }
+
+    /// Greets the client with the first of `replies`, then answers every line it sends with
+    /// the next one. Returns what the client sent, until the replies or the connection ran out.
+    pub async fn serve_script(socket: TcpStream, replies: &[&str]) -> String {
+        let (read, mut write) = socket.into_split();
+        let mut read = BufReader::new(read);
+        let mut received = String::new();
+        let (greeting, replies) = replies.split_first().unwrap();
+        write.write_all(greeting.as_bytes()).await.unwrap();
+        for reply in replies {
+            if read.read_line(&mut received).await.unwrap() == 0 {
+                break;
+            }
+            write.write_all(reply.as_bytes()).await.unwrap();
+        }
+        received
+    }
+
+    /// Serves `replies` on one connection, see [`serve_script`], and returns what the client
+    /// sent.
+    pub async fn scripted_server(replies: &'static [&'static str]) -> (u16, JoinHandle<String>) {
+        let (listener, port) = listen().await;
+        let handle = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            serve_script(socket, replies).await
+        });
+        (port, handle)
+    }
+
+    /// Greets the client and accepts every command and message, advertising `AUTH XOAUTH2`.
+    ///
+    /// `on_command` sees every line but the message data, before it is answered. The
+    /// connection is closed after `QUIT`, or once `on_command` returns false.
+    pub async fn accept_mail(mut socket: TcpStream, on_command: impl FnMut(&str) -> bool) {
+        let _ = socket.write_all(b"220 mx.example.com ESMTP\r\n").await;
+        answer_mail(socket, on_command).await
+    }
+
+    /// Like [`accept_mail`], for a client which was greeted already, e.g. over TLS after
+    /// `STARTTLS`.
+    pub async fn answer_mail(
+        stream: impl AsyncRead + AsyncWrite,
+        mut on_command: impl FnMut(&str) -> bool,
+    ) {
+        let (read, mut write) = tokio::io::split(stream);
+        let mut lines = BufReader::new(read).lines();
+        let mut in_data = false;
+        while let Ok(Some(line)) = lines.next_line().await {
+            let reply: &[u8] = match line.as_str() {
+                "." if in_data => {
+                    in_data = false;
+                    b"250 Queued\r\n"
+                }
+                _ if in_data => continue,
+                "DATA" => {
+                    in_data = true;
+                    b"354 Go ahead\r\n"
+                }
+                "QUIT" => b"221 Bye\r\n",
+                _ if line.starts_with("EHLO") => b"250-mx.example.com\r\n250 AUTH XOAUTH2\r\n",
+                _ if line.starts_with("AUTH") => b"235 Accepted\r\n",
+                _ => b"250 OK\r\n",
+            };
+            let keep_open = on_command(&line);
+            if write.write_all(reply).await.is_err() || !keep_open || line == "QUIT" {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "tokio", feature = "rustls"))]
+mod tls {
+    use std::sync::Arc;
+
+    use rustls::{
+        ServerConfig,
+        pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+    };
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream},
+        net::TcpStream,
+    };
+    use tokio_rustls::TlsAcceptor;
+
+    use super::answer_mail;
+
+    fn acceptor(chain: Vec<CertificateDer<'static>>, key: &'static [u8]) -> TlsAcceptor {
+        let config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(chain, PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key)))
+            .unwrap();
+        TlsAcceptor::from(Arc::new(config))
+    }
+
+    /// Presents `chain` with the PKCS #8 `key` in the TLS handshake, and answers with
+    /// `greeting` once it is done.
+    pub async fn tls_server(
+        stream: DuplexStream,
+        chain: Vec<CertificateDer<'static>>,
+        key: &'static [u8],
+        greeting: &'static str,
+    ) -> std::io::Result<()> {
+        let mut tls = acceptor(chain, key).accept(stream).await?;
+        tls.write_all(greeting.as_bytes()).await?;
+        tls.shutdown().await
+    }
+
+    /// Greets the client and offers `STARTTLS`, presenting `chain` with the PKCS #8 `key` in
+    /// the handshake. Over TLS, every command and message is accepted, see
+    /// [`answer_mail`](super::answer_mail).
+    pub async fn starttls_mail_server(
+        socket: TcpStream,
+        chain: Vec<CertificateDer<'static>>,
+        key: &'static [u8],
+    ) {
+        let mut socket = BufReader::new(socket);
+        let mut line = String::new();
+        let _ = socket.write_all(b"220 mx.example.com ESMTP\r\n").await;
+        loop {
+            line.clear();
+            if !matches!(socket.read_line(&mut line).await, Ok(1..)) {
+                return;
+            }
+            let reply: &[u8] = match line.trim_end() {
+                "STARTTLS" => b"220 Go ahead\r\n",
+                "QUIT" => b"221 Bye\r\n",
+                ehlo if ehlo.starts_with("EHLO") => b"250-mx.example.com\r\n250 STARTTLS\r\n",
+                _ => b"530 Must issue a STARTTLS command first\r\n",
+            };
+            if socket.write_all(reply).await.is_err() || line.trim_end() == "QUIT" {
+                return;
+            }
+            if line.trim_end() == "STARTTLS" {
+                break;
+            }
+        }
+        // the client waits for the reply to STARTTLS, nothing else is buffered
+        let Ok(tls) = acceptor(chain, key).accept(socket.into_inner()).await else {
+            return;
+        };
+        answer_mail(tls, |_| true).await
+    }
+}
@@ -0,0 +1,96 @@
+//! Tests for checking server certificates against TLSA records.
+#![cfg(feature = "dane")]
+
+mod common;
+
+use std::sync::Arc;
+
+use rustls::pki_types::{CertificateDer, pem::PemObject};
+use sha2::{Digest, Sha256};
+use simple_smtp::integrations::tokio::{
+    client_config_with_verifier, connect_tls_with_config,
+    dane::{DaneVerifier, TlsaRecord, tlsa_name},
+};
+use tokio::io::AsyncReadExt;
+
+const SELF_SIGNED: &[u8] = include_bytes!("data/client-cert.der");
+
+fn ca_cert() -> CertificateDer<'static> {
+    CertificateDer::from_pem_slice(include_bytes!("data/ca-cert.pem")).unwrap()
+}
+
+async fn handshake(
+    records: Vec<TlsaRecord>,
+    chain: Vec<CertificateDer<'static>>,
+    key: &'static [u8],
+) -> std::io::Result<String> {
+    let (client, server_stream) = tokio::io::duplex(16 * 1024);
+    let server = tokio::spawn(common::tls_server(
+        server_stream,
+        chain,
+        key,
+        "220 mx ESMTP\r\n",
+    ));
+    let config = client_config_with_verifier(Arc::new(DaneVerifier::new(records)), None)?;
+    let result = connect_tls_with_config(client, "mail.example.com", config).await;
+    let mut greeting = String::new();
+    match result {
+        Ok(mut tls) => {
+            tls.0.read_to_string(&mut greeting).await?;
+            server.await.unwrap()?;
+            Ok(greeting)
+        }
+        Err(e) => {
+            assert!(server.await.unwrap().is_err());
+            Err(e)
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_dane_ee_matches_certificate_and_key() {
+    let chain = || vec![CertificateDer::from(SELF_SIGNED)];
+    let key = include_bytes!("data/client-key.der");
+    // the self-signed certificate is for another name, which DANE-EE doesn't check
+    let digest = Sha256::digest(SELF_SIGNED).to_vec();
+    let record = TlsaRecord::new(3, 0, 1, digest);
+    assert_eq!(
+        handshake(vec![record], chain(), key).await.unwrap(),
+        "220 mx ESMTP\r\n"
+    );
+
+    let spki = include_bytes!("data/client-spki.der");
+    let record = TlsaRecord::new(3, 1, 0, &spki[..]);
+    assert!(handshake(vec![record], chain(), key).await.is_ok());
+
+    let record = TlsaRecord::new(3, 1, 1, Sha256::digest(b"another key").to_vec());
+    let err = handshake(vec![record], chain(), key).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[tokio::test]
+async fn test_dane_ta_matches_anchor_in_chain() {
+    let server_cert = CertificateDer::from(&include_bytes!("data/server-cert.der")[..]);
+    let key = include_bytes!("data/server-key.der");
+    let record = TlsaRecord::new(2, 0, 0, ca_cert().to_vec());
+    let chain = vec![server_cert.clone(), ca_cert()];
+    assert!(handshake(vec![record.clone()], chain, key).await.is_ok());
+
+    // the anchor has to be in the chain
+    let err = handshake(vec![record], vec![server_cert], key)
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_unusable_records_are_ignored() {
+    // PKIX-EE, and an unknown matching type
+    let verifier = DaneVerifier::new([
+        TlsaRecord::new(1, 0, 0, SELF_SIGNED),
+        TlsaRecord::new(3, 0, 9, SELF_SIGNED),
+    ]);
+    assert!(!verifier.has_records());
+    assert!(DaneVerifier::new([TlsaRecord::new(3, 0, 0, SELF_SIGNED)]).has_records());
+    assert_eq!(tlsa_name("mx.example.com.", 25), "_25._tcp.mx.example.com");
+}
@@ -0,0 +1,41 @@
+//! Tests for TLS without certificate verification, against a server with a self-signed
+//! certificate.
+#![cfg(feature = "dangerous-tls")]
+
+mod common;
+
+use rustls::pki_types::CertificateDer;
+use simple_smtp::integrations::tokio::{
+    connect_tls, connect_tls_with_config, dangerous::insecure_client_config,
+};
+use tokio::io::{AsyncReadExt, DuplexStream};
+
+async fn self_signed_server(stream: DuplexStream) -> std::io::Result<()> {
+    let cert = CertificateDer::from(&include_bytes!("data/client-cert.der")[..]);
+    let key = include_bytes!("data/client-key.der");
+    common::tls_server(stream, vec![cert], key, "220 lab ESMTP\r\n").await
+}
+
+#[tokio::test]
+async fn test_insecure_config_accepts_self_signed_certificate() {
+    let (client, server) = tokio::io::duplex(16 * 1024);
+    let server = tokio::spawn(self_signed_server(server));
+    let mut tls = connect_tls_with_config(client, "lab.example.com", insecure_client_config())
+        .await
+        .unwrap();
+    let mut greeting = String::new();
+    tls.0.read_to_string(&mut greeting).await.unwrap();
+    assert_eq!(greeting, "220 lab ESMTP\r\n");
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_default_config_rejects_self_signed_certificate() {
+    let (client, server) = tokio::io::duplex(16 * 1024);
+    let server = tokio::spawn(self_signed_server(server));
+    let Err(err) = connect_tls(client, "lab.example.com", None).await else {
+        panic!("the certificate isn't signed by a trusted root");
+    };
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(server.await.unwrap().is_err());
+}
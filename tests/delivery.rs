@@ -0,0 +1,367 @@
+//! Tests for delivering straight to the MX hosts of the recipients.
+#![cfg(feature = "delivery")]
+
+mod common;
+
+use rustls::pki_types::CertificateDer;
+use sha2::{Digest, Sha256};
+use simple_smtp::{
+    integrations::tokio::{
+        dane::{TlsaRecord, TlsaResolver},
+        delivery::{DirectDelivery, RecipientOutcome},
+        mta_sts::{Mode, Policy, PolicyCache},
+    },
+    resolver::Resolver,
+    smtp::ReplyCode,
+};
+use std::{
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    task::LocalSet,
+    time::Instant,
+};
+
+// every domain but `null.example` has 127.0.0.1 as its MX host
+struct LocalResolver;
+
+impl Resolver for LocalResolver {
+    type Error = std::io::Error;
+
+    async fn mx(&self, domain: &str) -> std::io::Result<Vec<(u16, String)>> {
+        Ok(match domain {
+            "null.example" => vec![(0, ".".to_owned())],
+            "down.example" => Err(std::io::Error::other("SERVFAIL"))?,
+            _ => vec![(10, "127.0.0.1".to_owned())],
+        })
+    }
+
+    async fn has_address(&self, _domain: &str) -> std::io::Result<bool> {
+        Ok(true)
+    }
+}
+
+// answers every connection with `replies`, see `common::serve_script`
+async fn scripted_server(replies: &'static [&'static str]) -> u16 {
+    let (listener, port) = common::listen().await;
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            common::serve_script(socket, replies).await;
+        }
+    });
+    port
+}
+
+#[tokio::test]
+async fn test_outcomes_per_domain() {
+    // no STARTTLS, which delivery requires
+    let port = scripted_server(&[
+        "220 mx.example.com ESMTP\r\n",
+        "250 mx.example.com\r\n",
+        "221 Bye\r\n",
+    ])
+    .await;
+
+    let report = DirectDelivery::new(LocalResolver, "client.example.org")
+        .with_port(port)
+        .deliver(
+            "me@example.org",
+            &[
+                "a@example.com",
+                "b@null.example",
+                "c@down.example",
+                "d@EXAMPLE.com",
+            ],
+            b"Subject: hi\r\n\r\nhello\r\n",
+        )
+        .await;
+    let outcomes: Vec<_> = report
+        .recipients
+        .iter()
+        .map(|(recipient, outcome)| (recipient.as_str(), *outcome))
+        .collect();
+    assert_eq!(
+        outcomes,
+        [
+            ("a@example.com", RecipientOutcome::TempFail(None)),
+            ("b@null.example", RecipientOutcome::PermFail(None)),
+            ("c@down.example", RecipientOutcome::TempFail(None)),
+            ("d@EXAMPLE.com", RecipientOutcome::TempFail(None)),
+        ]
+    );
+    assert!(!report.is_delivered());
+    assert_eq!(report.temporary_failures().count(), 3);
+}
+
+#[tokio::test]
+async fn test_rejected_greeting() {
+    let port = scripted_server(&["554 No mail from you\r\n", "221 Bye\r\n"]).await;
+
+    let report = DirectDelivery::new(LocalResolver, "client.example.org")
+        .with_port(port)
+        .deliver("me@example.org", &["a@example.com"], b"hello\r\n")
+        .await;
+    assert_eq!(
+        report.recipients[0].1,
+        RecipientOutcome::PermFail(Some(ReplyCode::TRANSACTION_FAILED))
+    );
+}
+
+#[tokio::test]
+async fn test_retry_policy_suppresses_policy_blocks() {
+    use simple_smtp::smtp::{
+        enhanced::{Class, EnhancedCode},
+        retry::{CodePattern, RetryAction, RetryPolicy},
+    };
+
+    let port = scripted_server(&["421 4.7.1 Blocked by policy\r\n", "221 Bye\r\n"]).await;
+    let deliver = |policy| {
+        DirectDelivery::new(LocalResolver, "client.example.org")
+            .with_port(port)
+            .with_retry_policy(policy)
+    };
+    let code = ReplyCode::SERVICE_NOT_AVAILABLE;
+
+    let report = deliver(RetryPolicy::new())
+        .deliver("me@example.org", &["a@example.com"], b"hello\r\n")
+        .await;
+    assert_eq!(
+        report.recipients[0].1,
+        RecipientOutcome::TempFail(Some(code))
+    );
+
+    let blocked = EnhancedCode::new(Class::PersistentTransientFailure, 7, 1);
+    let rules = [(CodePattern::Enhanced(blocked), RetryAction::Never)];
+    let report = deliver(RetryPolicy::new().with_rules(&rules))
+        .deliver("me@example.org", &["a@example.com"], b"hello\r\n")
+        .await;
+    assert_eq!(
+        report.recipients[0].1,
+        RecipientOutcome::PermFail(Some(code))
+    );
+    assert_eq!(report.temporary_failures().count(), 0);
+}
+
+#[derive(Default)]
+struct Connections {
+    open: usize,
+    most_open: usize,
+    started: Vec<Instant>,
+}
+
+// holds every connection open for a while before greeting, and refuses STARTTLS
+async fn counting_server() -> (u16, Arc<Mutex<Connections>>) {
+    let (listener, port) = common::listen().await;
+    let connections = Arc::new(Mutex::new(Connections::default()));
+    let counts = connections.clone();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            {
+                let mut counts = counts.lock().unwrap();
+                counts.open += 1;
+                counts.most_open = counts.most_open.max(counts.open);
+                counts.started.push(Instant::now());
+            }
+            let counts = counts.clone();
+            tokio::spawn(async move {
+                let (read, mut write) = socket.into_split();
+                let mut read = BufReader::new(read);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                write.write_all(b"220 mx.example.com\r\n").await.unwrap();
+                let mut line = String::new();
+                while read.read_line(&mut line).await.unwrap_or(0) > 0 {
+                    let reply = if line.starts_with("QUIT") {
+                        "221 Bye\r\n"
+                    } else {
+                        "250 mx.example.com\r\n"
+                    };
+                    let _ = write.write_all(reply.as_bytes()).await;
+                    line.clear();
+                }
+                counts.lock().unwrap().open -= 1;
+            });
+        }
+    });
+    (port, connections)
+}
+
+async fn deliver_concurrently(delivery: DirectDelivery<'static, LocalResolver>, count: usize) {
+    let delivery = Rc::new(delivery);
+    let tasks = LocalSet::new();
+    for number in 0..count {
+        let delivery = delivery.clone();
+        tasks.spawn_local(async move {
+            // different domains with the same MX host share the limit
+            let recipient = format!("a@domain{number}.example");
+            delivery
+                .deliver("me@example.org", &[recipient.as_str()], b"hello\r\n")
+                .await
+        });
+    }
+    tasks.await;
+}
+
+#[tokio::test]
+async fn test_connections_per_host_are_limited() {
+    // the number of connections, and the most open at once
+    async fn deliver_with(max: Option<usize>, count: usize) -> (usize, usize) {
+        let (port, connections) = counting_server().await;
+        let mut delivery = DirectDelivery::new(LocalResolver, "client.example.org").with_port(port);
+        if let Some(max) = max {
+            delivery = delivery.with_max_connections_per_host(max);
+        }
+        deliver_concurrently(delivery, count).await;
+        let counts = connections.lock().unwrap();
+        (counts.started.len(), counts.most_open)
+    }
+
+    assert_eq!(deliver_with(None, 5).await, (5, 2));
+    assert_eq!(deliver_with(Some(1), 3).await, (3, 1));
+}
+
+#[tokio::test]
+async fn test_messages_to_a_host_are_spaced() {
+    let (port, connections) = counting_server().await;
+    let delay = Duration::from_millis(100);
+    let delivery = DirectDelivery::new(LocalResolver, "client.example.org")
+        .with_port(port)
+        .with_message_delay(delay);
+    let before = Instant::now();
+    deliver_concurrently(delivery, 3).await;
+    let mut started = connections.lock().unwrap().started.clone();
+    started.sort();
+    assert_eq!(started.len(), 3);
+    // the nth connection is started no earlier than n delays in, and accepted after it is
+    // started, however long connecting takes
+    for (number, accepted) in (0u32..).zip(started) {
+        assert!(accepted - before >= delay * number);
+    }
+}
+
+// answers every TLSA lookup with the same records, fails them without any
+struct StaticTlsa(Option<Vec<TlsaRecord>>);
+
+impl TlsaResolver for StaticTlsa {
+    async fn resolve(&self, name: &str) -> std::io::Result<Vec<TlsaRecord>> {
+        assert!(name.ends_with("._tcp.127.0.0.1"), "{name}");
+        self.0
+            .clone()
+            .ok_or_else(|| std::io::Error::other("SERVFAIL"))
+    }
+}
+
+const SELF_SIGNED: &[u8] = include_bytes!("data/client-cert.der");
+
+// offers STARTTLS with a self-signed certificate, and accepts every message
+async fn self_signed_server() -> u16 {
+    let (listener, port) = common::listen().await;
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            let chain = vec![CertificateDer::from(SELF_SIGNED)];
+            let key = include_bytes!("data/client-key.der");
+            tokio::spawn(common::starttls_mail_server(socket, chain, key));
+        }
+    });
+    port
+}
+
+#[tokio::test]
+async fn test_dane_secured_hosts() {
+    let port = self_signed_server().await;
+    let deliver = |dane: Option<Vec<TlsaRecord>>| async move {
+        let delivery = DirectDelivery::new(LocalResolver, "client.example.org").with_port(port);
+        let report = match dane {
+            Some(records) => {
+                delivery
+                    .with_dane(StaticTlsa(Some(records)))
+                    .deliver("me@example.org", &["a@example.com"], b"hello\r\n")
+                    .await
+            }
+            None => {
+                delivery
+                    .deliver("me@example.org", &["a@example.com"], b"hello\r\n")
+                    .await
+            }
+        };
+        report.recipients[0].1
+    };
+
+    // the certificate isn't trusted by the webpki roots
+    assert_eq!(deliver(None).await, RecipientOutcome::TempFail(None));
+    assert_eq!(
+        deliver(Some(Vec::new())).await,
+        RecipientOutcome::TempFail(None)
+    );
+    // but matches the DANE-EE record
+    let record = TlsaRecord::new(3, 0, 1, Sha256::digest(SELF_SIGNED).to_vec());
+    assert_eq!(
+        deliver(Some(vec![record])).await,
+        RecipientOutcome::Delivered
+    );
+    let record = TlsaRecord::new(3, 0, 1, Sha256::digest(b"another certificate").to_vec());
+    assert_eq!(
+        deliver(Some(vec![record])).await,
+        RecipientOutcome::TempFail(None)
+    );
+}
+
+#[tokio::test]
+async fn test_failed_tlsa_lookup_defers_delivery() {
+    let port = scripted_server(&["554 No mail from you\r\n", "221 Bye\r\n"]).await;
+
+    // the host isn't even connected to
+    let report = DirectDelivery::new(LocalResolver, "client.example.org")
+        .with_port(port)
+        .with_dane(StaticTlsa(None))
+        .deliver("me@example.org", &["a@example.com"], b"hello\r\n")
+        .await;
+    assert_eq!(report.recipients[0].1, RecipientOutcome::TempFail(None));
+}
+
+#[tokio::test]
+async fn test_mta_sts_policies_restrict_mx_hosts() {
+    let port = scripted_server(&["554 No mail from you\r\n", "221 Bye\r\n"]).await;
+    let policy = |mode, mx: &str| Policy {
+        mode,
+        mx: vec![mx.to_owned()],
+        max_age: 86400,
+    };
+    let cache = PolicyCache::new();
+    cache.insert(
+        "enforced.example",
+        policy(Mode::Enforce, "mx.enforced.example"),
+    );
+    cache.insert("listed.example", policy(Mode::Enforce, "127.0.0.1"));
+    cache.insert(
+        "testing.example",
+        policy(Mode::Testing, "mx.testing.example"),
+    );
+
+    let report = DirectDelivery::new(LocalResolver, "client.example.org")
+        .with_port(port)
+        .with_mta_sts(&cache)
+        .deliver(
+            "me@example.org",
+            &[
+                "a@enforced.example",
+                "b@listed.example",
+                "c@testing.example",
+            ],
+            b"hello\r\n",
+        )
+        .await;
+    let rejected = RecipientOutcome::PermFail(Some(ReplyCode::TRANSACTION_FAILED));
+    let outcomes: Vec<_> = report
+        .recipients
+        .iter()
+        .map(|(_, outcome)| *outcome)
+        .collect();
+    // the only MX host isn't listed in the enforced policy, so it isn't connected to
+    assert_eq!(
+        outcomes,
+        [RecipientOutcome::TempFail(None), rejected, rejected]
+    );
+}
@@ -0,0 +1,134 @@
+//! Tests for signing messages with DKIM.
+#![cfg(all(feature = "dkim", feature = "std"))]
+
+use simple_smtp::{
+    Smtp,
+    canonicalization::Canonicalization,
+    dkim::{Algorithm, DkimSigner, SigningKey},
+    envelope::{Envelope, Recipient},
+    message::{DateTime, Message, Signer},
+    transport::MemoryTransport,
+};
+
+// the relaxed body hash of the example in RFC 8463, appendix A
+const BODY: &[u8] = b"Hi.\r\n\r\nWe lost the game.  Are you hungry yet?\r\n\r\nJoe.\r\n";
+const BODY_HASH: &str = "2jUSOH9NhtVGCQWNr9BrIAPreKQjO6Sn7XIkfJVOzv8=";
+
+// records what it signs, and returns `signature`
+struct Recorder {
+    algorithm: Algorithm,
+    signature: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl Recorder {
+    fn new(algorithm: Algorithm, signature: &[u8]) -> Self {
+        Recorder {
+            algorithm,
+            signature: signature.to_vec(),
+            data: Vec::new(),
+        }
+    }
+}
+
+impl SigningKey for Recorder {
+    fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    fn sign(&mut self, data: &[u8], _digest: &[u8; 32]) -> Vec<u8> {
+        self.data = data.to_vec();
+        self.signature.clone()
+    }
+}
+
+#[test]
+fn test_signed_data() {
+    let mut key = Recorder::new(Algorithm::Ed25519Sha256, &[0xff; 3]);
+    let mut signer = DkimSigner::new(&mut key, "example.org", "brisbane")
+        .with_headers(&["From", "Received"])
+        .with_timestamp(1528637909);
+    signer.header("Received", b"received:by a\r\n");
+    signer.header("From", b"from:joe@example.org\r\n");
+    signer.header("X-Mailer", b"x-mailer:test\r\n");
+    signer.header("Received", b"received:by b\r\n");
+    signer.body(&Canonicalization::Relaxed.body(BODY));
+    let signature = signer.signature();
+
+    assert_eq!(
+        String::from_utf8(signature).unwrap(),
+        format!(
+            "DKIM-Signature: v=1; a=ed25519-sha256; c=relaxed/relaxed; d=example.org; \
+             s=brisbane; t=1528637909;\r\n\th=Received:From:Received;\r\n\tbh={BODY_HASH};\
+             \r\n\tb=////\r\n"
+        )
+    );
+    // repeated fields from the bottom up, then the signature field without `b=`
+    assert_eq!(
+        String::from_utf8(key.data).unwrap(),
+        format!(
+            "received:by b\r\nfrom:joe@example.org\r\nreceived:by a\r\n\
+             dkim-signature:v=1; a=ed25519-sha256; c=relaxed/relaxed; d=example.org; \
+             s=brisbane; t=1528637909; h=Received:From:Received; bh={BODY_HASH}; b="
+        )
+    );
+}
+
+#[test]
+fn test_signer_is_reusable() {
+    let mut key = Recorder::new(Algorithm::RsaSha256, &[0x42; 256]);
+    let mut signer = DkimSigner::new(&mut key, "example.org", "sel")
+        .with_canonicalization(Canonicalization::Simple, Canonicalization::Simple);
+    for subject in ["first", "second"] {
+        signer.header("From", b"From: joe@example.org\r\n");
+        signer.header("Subject", format!("Subject: {subject}\r\n").as_bytes());
+        signer.body(b"hello\r\n");
+        let signature = String::from_utf8(signer.signature()).unwrap();
+        assert!(signature.starts_with(
+            "DKIM-Signature: v=1; a=rsa-sha256; c=simple/simple; d=example.org; s=sel;\r\n\
+             \th=Subject:From;\r\n"
+        ));
+        // the long signature is folded
+        assert!(signature.lines().all(|line| line.len() <= 78));
+        assert!(signature.ends_with("\r\n"));
+    }
+    // simple canonicalization signs the field as it is sent
+    let data = String::from_utf8(key.data).unwrap();
+    assert!(data.starts_with("Subject: second\r\nFrom: joe@example.org\r\nDKIM-Signature: v=1;"));
+    assert!(data.ends_with(";\r\n\tb="));
+}
+
+#[tokio::test]
+async fn test_send_signed_message() {
+    let transport = MemoryTransport::new();
+    let outbox = transport.outbox();
+    let mut smtp = Smtp::new(transport);
+    smtp.ready().await.unwrap();
+    smtp.ehlo("localhost").await.unwrap();
+
+    let recipients = [Recipient::new("bob@example.com")];
+    let message = Message::new("joe@example.org")
+        .with_to(&["bob@example.com"])
+        .with_subject("the game")
+        .with_date(DateTime::from_utc(2018, 6, 10, 13, 38, 29).unwrap())
+        .with_text_body(core::str::from_utf8(BODY).unwrap());
+    let mut key = Recorder::new(Algorithm::Ed25519Sha256, &[0; 64]);
+    let mut signer = DkimSigner::new(&mut key, "example.org", "brisbane");
+    smtp.send_signed_message(
+        &Envelope::new("joe@example.org", &recipients),
+        &message,
+        &mut signer,
+    )
+    .await
+    .unwrap();
+
+    let sent = &outbox.messages()[0];
+    assert!(
+        sent.message
+            .starts_with(b"DKIM-Signature: v=1; a=ed25519-sha256;")
+    );
+    let signature = sent.header("DKIM-Signature").unwrap();
+    assert!(signature.contains(&format!("bh={BODY_HASH};")));
+    assert!(signature.contains(":From:"));
+    assert!(signature.contains("Subject:"));
+}
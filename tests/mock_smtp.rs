@@ -5,7 +5,13 @@
 
 use std::{collections::VecDeque, fmt};
 
-use simple_smtp::{ReadWrite, Smtp};
+use simple_smtp::{
+    Error, MalformedError, ReadWrite, Smtp,
+    smtp::{
+        ReplyCode,
+        negotiation::{DesiredFeatures, FeatureStatus},
+    },
+};
 
 // ══════════════════════════════════════════════════════════════════════════════
 // Mock Error Type
@@ -41,8 +47,14 @@ pub struct MockStream {
     responses: VecDeque<Vec<u8>>,
     /// Everything the client has written
     written: Vec<u8>,
+    /// How many bytes each write_single() call wrote
+    write_lens: Vec<usize>,
+    /// How many bytes were written at each flush()
+    flushes: Vec<usize>,
     /// If set, the next read/write will return this error
     inject_error: Option<MockError>,
+    /// A reply sent while the client is still writing, once it wrote the given text
+    early_reply: Option<(String, Vec<u8>)>,
 }
 
 impl MockStream {
@@ -50,7 +62,10 @@ impl MockStream {
         MockStream {
             responses: VecDeque::new(),
             written: Vec::new(),
+            write_lens: Vec::new(),
+            flushes: Vec::new(),
             inject_error: None,
+            early_reply: None,
         }
     }
 
@@ -77,6 +92,13 @@ impl MockStream {
         self.queue_response(response)
     }
 
+    /// Queue a reply which read_available() returns once the client has written `after`,
+    /// like a server rejecting the message before the end of data.
+    pub fn queue_early_line(&mut self, after: &str, line: &str) -> &mut Self {
+        self.early_reply = Some((after.to_string(), format!("{}\r\n", line).into_bytes()));
+        self
+    }
+
     /// Make the next read() return an error.
     pub fn inject_read_error(&mut self, err: MockError) -> &mut Self {
         self.inject_error = Some(err);
@@ -88,6 +110,20 @@ impl MockStream {
         &self.written
     }
 
+    /// What each write_single() call wrote, in order.
+    pub fn writes(&self) -> impl Iterator<Item = &[u8]> {
+        self.write_lens.iter().scan(0, |start, &len| {
+            let write = &self.written[*start..*start + len];
+            *start += len;
+            Some(write)
+        })
+    }
+
+    /// How many bytes were written at each flush(), in order.
+    pub fn flushes(&self) -> &[usize] {
+        &self.flushes
+    }
+
     /// Get written data as a string (panics if not valid UTF-8).
     pub fn written_str(&self) -> &str {
         std::str::from_utf8(&self.written).expect("written data should be valid UTF-8")
@@ -138,6 +174,19 @@ impl ReadWrite for MockStream {
         }
     }
 
+    fn read_available(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let written = String::from_utf8_lossy(&self.written);
+        match &self.early_reply {
+            Some((after, reply)) if written.contains(after.as_str()) => {
+                let len = reply.len().min(buf.len());
+                buf[..len].copy_from_slice(&reply[..len]);
+                self.early_reply = None;
+                Ok(len)
+            }
+            _ => Ok(0),
+        }
+    }
+
     async fn write_single(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
         // Check for injected error
         if let Some(err) = self.inject_error.take() {
@@ -145,8 +194,100 @@ impl ReadWrite for MockStream {
         }
 
         self.written.extend_from_slice(buf);
+        self.write_lens.push(buf.len());
         Ok(())
     }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flushes.push(self.written.len());
+        Ok(())
+    }
+}
+
+// The same mock through the futures-io traits, for the FuturesIo adapter
+#[cfg(feature = "futures-io")]
+mod futures_io {
+    use std::{
+        io,
+        pin::{Pin, pin},
+        task::{Context, Poll},
+    };
+
+    use futures_io::{AsyncRead, AsyncWrite};
+    use simple_smtp::ReadWrite;
+
+    use super::MockStream;
+
+    impl AsyncRead for MockStream {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            let read = pin!(ReadWrite::read(self.get_mut(), buf));
+            read.poll(cx).map_err(io::Error::other)
+        }
+    }
+
+    impl AsyncWrite for MockStream {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            // short writes, so the adapter has to write the rest itself
+            let len = buf.len().min(8);
+            let write = pin!(ReadWrite::write_single(self.get_mut(), &buf[..len]));
+            write.poll(cx).map_ok(|()| len).map_err(io::Error::other)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            let flush = pin!(ReadWrite::flush(self.get_mut()));
+            flush.poll(cx).map_err(io::Error::other)
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+// The same mock through the embedded-io-async traits, for the EmbeddedIo adapter
+#[cfg(feature = "embedded-io")]
+mod embedded_io {
+    use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+    use simple_smtp::ReadWrite;
+
+    use super::{MockError, MockStream};
+
+    impl embedded_io_async::Error for MockError {
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    impl ErrorType for MockStream {
+        type Error = MockError;
+    }
+
+    impl Read for MockStream {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            ReadWrite::read(self, buf).await
+        }
+    }
+
+    impl Write for MockStream {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            // short writes, so the adapter has to write the rest itself
+            let len = buf.len().min(8);
+            ReadWrite::write_single(self, &buf[..len]).await?;
+            Ok(len)
+        }
+
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            ReadWrite::flush(self).await
+        }
+    }
 }
 
 // ══════════════════════════════════════════════════════════════════════════════
@@ -211,239 +352,3341 @@ async fn test_ehlo_parses_extensions() {
 }
 
 #[tokio::test]
-async fn test_starttls_command() {
+async fn test_capabilities_outlive_ehlo_response() {
+    use simple_smtp::smtp::{Extensions, capabilities::AuthMechanism};
+
     let mut mock = mock_with_ehlo();
-    // Queue STARTTLS response
-    mock.queue_line("220 Ready to start TLS");
+    mock.queue_line("235 Authentication successful");
 
     let mut smtp = Smtp::new(mock);
+    smtp.set_allow_plaintext_auth(true);
     let _ = smtp.ready().await.unwrap();
+    assert!(!smtp.capabilities().supports(Extensions::StartTls));
     let _ = smtp.ehlo("client.example.com").await.unwrap();
+    smtp.auth("user", "pass").await.unwrap();
 
-    let reply = smtp.starttls().await.expect("starttls() should succeed");
-    assert_eq!(reply.code(), 220);
+    let caps = smtp.capabilities();
+    assert!(caps.supports(Extensions::StartTls));
+    assert!(caps.supports_auth(AuthMechanism::Login));
+    assert_eq!(caps.max_size(), Some(10485760));
+}
+
+#[tokio::test]
+async fn test_message_exceeding_size_not_sent() {
+    use simple_smtp::ProtocolError;
+
+    let mut mock = mock_with_greeting();
+    mock.queue_multiline(250, &["mail.example.com", "SIZE 10"]);
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let result = smtp
+        .send_mail(
+            "me@local",
+            ["you@example.com"].iter(),
+            b"more than ten bytes",
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(Error::ProtocolError(ProtocolError::MessageTooLarge {
+            size: 19,
+            limit: 10
+        }))
+    ));
 
     let (stream, _) = smtp.into_inner();
-    assert!(stream.contains_command("STARTTLS\r\n"));
+    assert!(!stream.written_str().contains("MAIL FROM"));
 }
 
 #[tokio::test]
-async fn test_auth_plain() {
-    let mut mock = mock_with_ehlo();
-    // Queue successful AUTH response
-    mock.queue_line("235 Authentication successful");
+async fn test_auth_without_plain_mechanism() {
+    let mut mock = mock_with_greeting();
+    mock.queue_multiline(250, &["mail.example.com", "AUTH LOGIN"]);
 
     let mut smtp = Smtp::new(mock);
+    smtp.set_allow_plaintext_auth(true);
     let _ = smtp.ready().await.unwrap();
     let _ = smtp.ehlo("client.example.com").await.unwrap();
+    assert!(matches!(
+        smtp.auth("user", "pass").await,
+        Err(Error::ProtocolError(_))
+    ));
+}
 
-    let reply = smtp
-        .auth("user@example.com", "hunter2")
-        .await
-        .expect("auth() should succeed");
-    assert_eq!(reply.code(), 235);
+#[tokio::test]
+async fn test_negotiation_report_after_greeting() {
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("220 Ready to start TLS");
+    let mut smtp = Smtp::new(mock);
+    smtp.set_desired_features(DesiredFeatures::new().with_message_size(1024).with_dsn());
+
+    smtp.ready().await.unwrap();
+    assert!(smtp.negotiation_report().is_none());
+    smtp.ehlo("client.example.com").await.unwrap();
+    let report = smtp.negotiation_report().unwrap();
+    assert_eq!(report.size(), FeatureStatus::Satisfied);
+    assert_eq!(report.dsn(), FeatureStatus::Unsatisfied);
+
+    // the pre-TLS capabilities are forgotten, and so is the report
+    smtp.starttls().await.unwrap();
+    assert!(smtp.negotiation_report().is_none());
+}
 
-    let (stream, _) = smtp.into_inner();
-    // Should have sent AUTH PLAIN with base64 payload
-    assert!(stream.contains_command("AUTH PLAIN "));
+#[tokio::test]
+async fn test_reply_last_line_flag() {
+    let mock = mock_with_ehlo();
+    let mut smtp = Smtp::new(mock);
+
+    let _ = smtp.ready().await.unwrap();
+    let ehlo = smtp.ehlo("client.example.com").await.unwrap();
+    let lines: Vec<_> = ehlo.replies().map(|line| line.is_last()).collect();
+    assert_eq!(lines, [false, false, false, true]);
 }
 
 #[tokio::test]
-async fn test_send_mail_flow() {
-    let mut mock = mock_with_ehlo();
-    // MAIL FROM response
-    mock.queue_line("250 OK");
-    // RCPT TO response
-    mock.queue_line("250 OK");
-    // DATA response
-    mock.queue_line("354 Start mail input");
-    // End of data response
-    mock.queue_line("250 OK: queued as 12345");
+async fn test_auth_requires_tls() {
+    use simple_smtp::ProtocolError;
 
+    let mock = mock_with_ehlo();
     let mut smtp = Smtp::new(mock);
     let _ = smtp.ready().await.unwrap();
     let _ = smtp.ehlo("client.example.com").await.unwrap();
-
-    smtp.send_mail(
-        "sender@example.com",
-        ["recipient@example.com"].iter(),
-        b"Subject: Test\r\n\r\nHello!",
-    )
-    .await
-    .expect("send_mail() should succeed");
+    assert!(matches!(
+        smtp.auth("user", "pass").await,
+        Err(Error::ProtocolError(ProtocolError::PlaintextAuth))
+    ));
 
     let (stream, _) = smtp.into_inner();
-    let written = stream.written_str();
-
-    assert!(written.contains("MAIL FROM:<sender@example.com>\r\n"));
-    assert!(written.contains("RCPT TO:<recipient@example.com>\r\n"));
-    assert!(written.contains("DATA\r\n"));
-    assert!(written.contains("Subject: Test\r\n\r\nHello!"));
-    assert!(written.contains("\r\n.\r\n")); // End of data marker
+    assert!(!stream.written_str().contains("AUTH"));
 }
 
 #[tokio::test]
-async fn test_quit() {
+async fn test_starttls_command() {
     let mut mock = mock_with_ehlo();
-    mock.queue_line("221 Bye");
+    // Queue STARTTLS response
+    mock.queue_line("220 Ready to start TLS");
 
     let mut smtp = Smtp::new(mock);
     let _ = smtp.ready().await.unwrap();
     let _ = smtp.ehlo("client.example.com").await.unwrap();
 
-    let reply = smtp.quit().await.expect("quit() should succeed");
-    assert_eq!(reply.code(), 221);
+    let reply = smtp.starttls().await.expect("starttls() should succeed");
+    assert_eq!(reply.code(), 220);
 
     let (stream, _) = smtp.into_inner();
-    assert!(stream.contains_command("QUIT\r\n"));
+    assert!(stream.contains_command("STARTTLS\r\n"));
 }
 
 #[tokio::test]
-async fn test_full_happy_path() {
-    // The whole enchilada: greeting -> EHLO -> AUTH -> MAIL -> QUIT
-    let mut mock = MockStream::new();
-    mock.queue_line("220 mail.example.com ESMTP");
+async fn test_starttls_requires_upgrade_and_ehlo() {
+    use simple_smtp::{ProtocolError, smtp::SessionState};
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("220 Ready to start TLS");
     mock.queue_multiline(250, &["mail.example.com", "AUTH PLAIN"]);
-    mock.queue_line("235 Authenticated");
-    mock.queue_line("250 OK"); // MAIL FROM
-    mock.queue_line("250 OK"); // RCPT TO
-    mock.queue_line("354 Go ahead");
-    mock.queue_line("250 Queued");
-    mock.queue_line("221 Bye");
+    mock.queue_line("235 Authentication successful");
 
     let mut smtp = Smtp::new(mock);
-
-    // Full flow
     let _ = smtp.ready().await.unwrap();
-    let _ = smtp.ehlo("client.local").await.unwrap();
-    let _ = smtp.auth("me", "secret").await.unwrap();
-    smtp.send_mail("me@local", ["you@remote"].iter(), b"hi")
-        .await
-        .unwrap();
-    let _ = smtp.quit().await.unwrap();
+    assert_eq!(smtp.state(), SessionState::NotGreeted);
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let _ = smtp.starttls().await.unwrap();
+    assert_eq!(smtp.state(), SessionState::TlsPending);
+    assert!(matches!(
+        smtp.auth("user", "pass").await,
+        Err(Error::ProtocolError(ProtocolError::TlsUpgradePending))
+    ));
+
+    // a real client would wrap the stream in TLS here
+    let (stream, buffer) = smtp.into_inner();
+    let mut smtp = Smtp::new_with_buffer(stream, buffer);
+    smtp.set_secure(true);
+    assert!(matches!(
+        smtp.auth("user", "pass").await,
+        Err(Error::ProtocolError(ProtocolError::NotGreeted))
+    ));
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    smtp.auth("user", "pass").await.unwrap();
 
     let (stream, _) = smtp.into_inner();
-    let written = stream.written_str();
+    // nothing was sent while the session was in the wrong state
+    assert_eq!(stream.written_str().matches("AUTH PLAIN").count(), 1);
+}
 
-    // Verify the conversation order
-    let ehlo_pos = written.find("EHLO").unwrap();
-    let auth_pos = written.find("AUTH").unwrap();
-    let mail_pos = written.find("MAIL FROM").unwrap();
-    let quit_pos = written.find("QUIT").unwrap();
+#[tokio::test]
+async fn test_mail_before_ehlo_rejected() {
+    use simple_smtp::ProtocolError;
 
-    assert!(ehlo_pos < auth_pos, "EHLO should come before AUTH");
-    assert!(auth_pos < mail_pos, "AUTH should come before MAIL FROM");
-    assert!(mail_pos < quit_pos, "MAIL FROM should come before QUIT");
+    let mut smtp = Smtp::new(mock_with_greeting());
+    let _ = smtp.ready().await.unwrap();
+    let result = smtp
+        .send_mail("me@local", ["you@example.com"].iter(), b"hi")
+        .await;
+    assert!(matches!(
+        result,
+        Err(Error::ProtocolError(ProtocolError::NotGreeted))
+    ));
 }
 
-// ══════════════════════════════════════════════════════════════════════════════
-// Tests: Error Recovery
-// ══════════════════════════════════════════════════════════════════════════════
+#[tokio::test]
+async fn test_ensure_ehlo_greets_once() {
+    use simple_smtp::smtp::Extensions;
+
+    let mut smtp = Smtp::new(mock_with_ehlo());
+    let _ = smtp.ready().await.unwrap();
+    assert!(
+        smtp.ensure_ehlo("client.example.com")
+            .await
+            .unwrap()
+            .supports(Extensions::StartTls)
+    );
+    // already greeted, so nothing is sent
+    assert!(
+        smtp.ensure_ehlo("client.example.com")
+            .await
+            .unwrap()
+            .supports(Extensions::StartTls)
+    );
+
+    let (stream, _) = smtp.into_inner();
+    assert_eq!(stream.written_str().matches("EHLO").count(), 1);
+}
 
 #[tokio::test]
-async fn test_auth_rejected() {
+async fn test_auth_plain() {
     let mut mock = mock_with_ehlo();
-    // Server says no 😤
-    mock.queue_line("535 Authentication failed");
+    // Queue successful AUTH response
+    mock.queue_line("235 Authentication successful");
 
     let mut smtp = Smtp::new(mock);
+    smtp.set_allow_plaintext_auth(true);
     let _ = smtp.ready().await.unwrap();
     let _ = smtp.ehlo("client.example.com").await.unwrap();
 
-    let result = smtp.auth("baduser", "wrongpass").await;
-    assert!(result.is_err(), "auth() should fail on 535");
+    let reply = smtp
+        .auth("user@example.com", "hunter2")
+        .await
+        .expect("auth() should succeed");
+    assert_eq!(reply.code(), 235);
+
+    let (stream, _) = smtp.into_inner();
+    // Should have sent AUTH PLAIN with base64 payload
+    assert!(stream.contains_command("AUTH PLAIN "));
 }
 
 #[tokio::test]
-async fn test_mail_from_rejected() {
+async fn test_auth_credentials_too_long() {
+    use simple_smtp::ProtocolError;
+
     let mut mock = mock_with_ehlo();
-    // Sender not allowed
-    mock.queue_line("550 Sender rejected");
+    mock.queue_line("250 OK");
 
     let mut smtp = Smtp::new(mock);
+    smtp.set_allow_plaintext_auth(true);
     let _ = smtp.ready().await.unwrap();
     let _ = smtp.ehlo("client.example.com").await.unwrap();
 
-    let result = smtp
-        .send_mail("spam@bad.com", ["victim@example.com"].iter(), b"spam")
-        .await;
-    assert!(result.is_err(), "send_mail() should fail on 550");
+    // the encoded credentials don't fit in the buffer
+    let password = "x".repeat(2000);
+    let err = smtp
+        .auth("user@example.com", &password)
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err,
+        Error::ProtocolError(ProtocolError::LineTooLong)
+    ));
+    // nothing was sent, so the session is still in step with the server
+    assert!(smtp.is_usable());
+    assert_eq!(smtp.noop().await.unwrap().code().as_u16(), 250);
+
+    let (stream, _) = smtp.into_inner();
+    assert!(!stream.contains_command("AUTH"));
 }
 
 #[tokio::test]
-async fn test_rcpt_to_rejected() {
+async fn test_auth_challenges() {
+    use simple_smtp::{
+        ProtocolError,
+        smtp::{auth::AuthStep, capabilities::AuthMechanism},
+    };
+
     let mut mock = mock_with_ehlo();
-    mock.queue_line("250 OK"); // MAIL FROM succeeds
-    mock.queue_line("550 User unknown"); // RCPT TO fails
+    mock.queue_line("334 VXNlcm5hbWU6");
+    mock.queue_line("334 UGFzc3dvcmQ6");
+    mock.queue_line("235 Authentication successful");
 
     let mut smtp = Smtp::new(mock);
-    let _ = smtp.ready().await.unwrap();
-    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    smtp.ready().await.unwrap();
+    smtp.ehlo("client.local").await.unwrap();
+    smtp.set_allow_plaintext_auth(true);
+
+    let step = smtp.auth_begin(AuthMechanism::Login, None).await.unwrap();
+    assert!(matches!(step, AuthStep::Challenge(b"Username:")));
+    // the server waits for the response
+    let err = smtp.noop().await.err().unwrap();
+    assert!(matches!(
+        err,
+        Error::ProtocolError(ProtocolError::AuthChallengePending)
+    ));
+    let step = smtp.auth_respond(b"user").await.unwrap();
+    assert!(matches!(step, AuthStep::Challenge(b"Password:")));
+    let step = smtp.auth_respond(b"pass").await.unwrap();
+    assert!(matches!(step, AuthStep::Success(_)));
+    assert!(smtp.is_authenticated());
 
-    let result = smtp
-        .send_mail("sender@ok.com", ["nonexistent@example.com"].iter(), b"hi")
-        .await;
+    let (stream, _) = smtp.into_inner();
     assert!(
-        result.is_err(),
-        "send_mail() should fail if RCPT TO rejected"
+        stream
+            .written_str()
+            .ends_with("AUTH LOGIN\r\ndXNlcg==\r\ncGFzcw==\r\n")
     );
 }
 
 #[tokio::test]
-async fn test_unexpected_eof() {
-    // Server just... disappears 💀
-    let mut mock = MockStream::new();
-    mock.queue_line("220 mail.example.com ESMTP");
-    // No EHLO response queued — EOF will happen
+async fn test_auth_long_multiline_challenge() {
+    use base64::prelude::*;
+    use simple_smtp::smtp::{auth::AuthStep, capabilities::AuthMechanism};
+
+    let mut mock = mock_with_greeting();
+    mock.queue_multiline(250, &["mail.example.com", "AUTH XOAUTH2"]);
+    // an error report, split over lines longer than the initial buffer
+    let details = format!(r#"{{"status":"400","details":"{}"}}"#, "x".repeat(3000));
+    let encoded = BASE64_STANDARD.encode(&details);
+    let (first, second) = encoded.split_at(1001);
+    mock.queue_multiline(334, &[first, second]);
+    mock.queue_line("535 5.7.8 Username and Password not accepted");
 
     let mut smtp = Smtp::new(mock);
-    let _ = smtp.ready().await.unwrap();
+    smtp.ready().await.unwrap();
+    smtp.ehlo("client.local").await.unwrap();
+    smtp.set_allow_plaintext_auth(true);
+
+    let step = smtp
+        .auth_begin(
+            AuthMechanism::XOAuth2,
+            Some(&b"user=me\x01auth=Bearer token\x01\x01"[..]),
+        )
+        .await
+        .unwrap();
+    let AuthStep::Challenge(challenge) = step else {
+        panic!("expected a challenge");
+    };
+    assert_eq!(challenge, details.as_bytes());
+    // XOAUTH2 expects an empty response to a failure
+    let err = smtp.auth_respond(b"").await.err().unwrap();
+    assert!(err.is_permanent());
+    assert!(!smtp.is_authenticated());
 
-    let result = smtp.ehlo("client.local").await;
-    assert!(result.is_err(), "should error on unexpected EOF");
+    let (stream, _) = smtp.into_inner();
+    assert!(
+        stream
+            .written_str()
+            .contains("AUTH XOAUTH2 dXNlcj1tZQFhdXRoPUJlYXJlciB0b2tlbgEB\r\n")
+    );
+    assert!(stream.written_str().ends_with("\r\n\r\n"));
 }
 
 #[tokio::test]
-async fn test_malformed_no_code() {
-    // Server sends garbage without a status code
-    let mut mock = MockStream::new();
-    mock.queue_response(b"Hello I am a broken server\r\n".to_vec());
+async fn test_auth_responses_longer_than_buffer() {
+    use base64::prelude::*;
+    use simple_smtp::smtp::{auth::AuthStep, capabilities::AuthMechanism};
+
+    let mut mock = mock_with_greeting();
+    mock.queue_multiline(250, &["mail.example.com", "AUTH XOAUTH2 LOGIN"]);
+    mock.queue_line("235 2.7.0 Accepted");
+    mock.queue_line("250 OK");
 
     let mut smtp = Smtp::new(mock);
-    let result = smtp.ready().await;
-    assert!(result.is_err(), "should error on malformed response");
+    smtp.ready().await.unwrap();
+    smtp.ehlo("client.local").await.unwrap();
+    smtp.set_allow_plaintext_auth(true);
+
+    // an access token makes the encoded response longer than the 1 KiB buffer
+    let response = format!("user=me\x01auth=Bearer {}\x01\x01", "t".repeat(1500));
+    let step = smtp
+        .auth_begin(AuthMechanism::XOAuth2, Some(response.as_bytes()))
+        .await
+        .unwrap();
+    assert!(matches!(step, AuthStep::Success(_)));
+    assert_eq!(smtp.noop().await.unwrap().code().as_u16(), 250);
+
+    let (stream, _) = smtp.into_inner();
+    let expected = format!("AUTH XOAUTH2 {}\r\n", BASE64_STANDARD.encode(&response));
+    assert!(stream.written_str().contains(&expected));
 }
 
 #[tokio::test]
-async fn test_malformed_bad_line_terminator() {
-    // Server uses bare LF instead of CRLF 🙄
-    let mut mock = MockStream::new();
-    mock.queue_response(b"220 mail.example.com ESMTP\n".to_vec());
+async fn test_auth_cancel() {
+    use simple_smtp::{ProtocolError, smtp::capabilities::AuthMechanism};
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("334 not base64!");
+    mock.queue_line("501 Authentication cancelled");
+    mock.queue_line("250 OK");
 
     let mut smtp = Smtp::new(mock);
-    let result = smtp.ready().await;
-    assert!(result.is_err(), "should error on bare LF");
+    smtp.ready().await.unwrap();
+    smtp.ehlo("client.local").await.unwrap();
+    smtp.set_allow_plaintext_auth(true);
+
+    let err = smtp
+        .auth_begin(AuthMechanism::Login, None)
+        .await
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err,
+        Error::MalformedError(MalformedError::InvalidEncoding)
+    ));
+    let reply = smtp.auth_cancel().await.unwrap();
+    assert_eq!(reply.code(), ReplyCode::new(501));
+    smtp.noop().await.unwrap();
+    assert!(matches!(
+        smtp.auth_respond(b"late").await.err().unwrap(),
+        Error::ProtocolError(ProtocolError::NoAuthChallenge)
+    ));
+}
+
+#[tokio::test]
+async fn test_capabilities_after_auth() {
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("235 Authentication successful");
+    mock.queue_multiline(250, &["mail.example.com", "AUTH PLAIN", "SIZE 52428800"]);
+
+    let mut smtp = Smtp::new(mock);
+    smtp.set_allow_plaintext_auth(true);
+    smtp.ready().await.unwrap();
+    smtp.ehlo("client.example.com").await.unwrap();
+    assert!(smtp.pre_auth_capabilities().is_none());
+
+    smtp.auth("user@example.com", "hunter2").await.unwrap();
+    assert_eq!(
+        smtp.pre_auth_capabilities().unwrap().max_size(),
+        Some(10485760)
+    );
+    assert!(smtp.post_auth_capabilities().is_none());
+
+    smtp.ehlo("client.example.com").await.unwrap();
+    assert!(smtp.is_authenticated());
+    assert_eq!(smtp.capabilities().max_size(), Some(52428800));
+    assert_eq!(
+        smtp.post_auth_capabilities().unwrap().max_size(),
+        Some(52428800)
+    );
+    assert_eq!(
+        smtp.pre_auth_capabilities().unwrap().max_size(),
+        Some(10485760)
+    );
+}
+
+#[tokio::test]
+async fn test_reauthenticate_after_expired_auth() {
+    use simple_smtp::{
+        envelope::Envelope,
+        smtp::{SendOutcome, auth::AuthMode},
+    };
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("235 Authentication successful");
+    mock.queue_line("530 5.7.0 Authentication required");
+    mock.queue_multiline(250, &["mail.example.com", "AUTH PLAIN"]);
+    mock.queue_line("235 Authentication successful");
+    mock.queue_line("250 OK");
+    mock.queue_line("250 OK");
+    mock.queue_line("354 Start mail input");
+    mock.queue_line("250 OK queued");
+    // the second message needs no recovery
+    mock.queue_line("250 OK");
+    mock.queue_line("250 OK");
+    mock.queue_line("354 Start mail input");
+    mock.queue_line("250 OK queued");
+
+    let mut smtp = Smtp::new(mock);
+    smtp.set_allow_plaintext_auth(true);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let auth = AuthMode::Plain {
+        username: "user",
+        password: "pass",
+    };
+    smtp.authenticate(&auth).await.unwrap();
+
+    let to = ["you@example.com".into()];
+    let envelope = Envelope::new("me@example.com", &to);
+    let outcome = smtp
+        .send_envelope_reauthenticating(&envelope, b"hi\r\n", "client.example.com", &auth)
+        .await
+        .unwrap();
+    assert_eq!(outcome, SendOutcome::Reauthenticated);
+    assert!(smtp.is_authenticated());
+    let outcome = smtp
+        .send_envelope_reauthenticating(&envelope, b"hi\r\n", "client.example.com", &auth)
+        .await
+        .unwrap();
+    assert_eq!(outcome, SendOutcome::Sent);
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert_eq!(written.matches("AUTH PLAIN").count(), 2);
+    assert_eq!(written.matches("EHLO").count(), 2);
+    assert_eq!(written.matches("MAIL FROM").count(), 3);
+}
+
+#[tokio::test]
+async fn test_reauthenticate_only_authenticated_sessions() {
+    use simple_smtp::{envelope::Envelope, smtp::auth::AuthMode};
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("530 5.7.0 Authentication required");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let to = ["you@example.com".into()];
+    let envelope = Envelope::new("me@example.com", &to);
+    let result = smtp
+        .send_envelope_reauthenticating(&envelope, b"hi\r\n", "client.example.com", &AuthMode::None)
+        .await;
+    match result {
+        Err(error) => assert!(error.is_authentication_required()),
+        Ok(_) => panic!("the session was never authenticated"),
+    }
+    let (stream, _) = smtp.into_inner();
+    assert_eq!(stream.written_str().matches("EHLO").count(), 1);
+}
+
+// hands out the credentials in turn, moving on to the next when refreshed
+struct RotatingCredentials {
+    credentials: Vec<simple_smtp::smtp::auth::Credentials>,
+    current: std::cell::Cell<usize>,
+}
+
+impl simple_smtp::smtp::auth::CredentialProvider for RotatingCredentials {
+    type Error = MockError;
+
+    async fn credentials(&self) -> Result<simple_smtp::smtp::auth::Credentials, MockError> {
+        self.credentials
+            .get(self.current.get())
+            .cloned()
+            .ok_or_else(|| MockError::new("vault is empty"))
+    }
+
+    async fn refresh(&self) -> Result<(), MockError> {
+        self.current.set(self.current.get() + 1);
+        Ok(())
+    }
+}
+
+fn password(password: &str) -> simple_smtp::smtp::auth::Credentials {
+    simple_smtp::smtp::auth::Credentials::Password {
+        username: "user".to_string(),
+        password: password.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_authenticate_with_provider() {
+    use base64::prelude::*;
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("235 Authentication successful");
+
+    let mut smtp = Smtp::new(mock);
+    smtp.set_allow_plaintext_auth(true);
+    smtp.ready().await.unwrap();
+    smtp.ehlo("client.example.com").await.unwrap();
+    let provider = RotatingCredentials {
+        credentials: vec![password("hunter2")],
+        current: 0.into(),
+    };
+    smtp.authenticate_with(&provider).await.unwrap();
+    assert!(smtp.is_authenticated());
+    assert_eq!(provider.current.get(), 0);
+
+    let (stream, _) = smtp.into_inner();
+    let expected = format!(
+        "AUTH PLAIN {}\r\n",
+        BASE64_STANDARD.encode("\0user\0hunter2")
+    );
+    assert!(stream.written_str().ends_with(&expected));
+}
+
+#[tokio::test]
+async fn test_authenticate_with_rotated_credentials() {
+    use base64::prelude::*;
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("535 5.7.8 Authentication credentials invalid");
+    mock.queue_line("235 Authentication successful");
+
+    let mut smtp = Smtp::new(mock);
+    smtp.set_allow_plaintext_auth(true);
+    smtp.ready().await.unwrap();
+    smtp.ehlo("client.example.com").await.unwrap();
+    let provider = RotatingCredentials {
+        credentials: vec![password("old"), password("new")],
+        current: 0.into(),
+    };
+    smtp.authenticate_with(&provider).await.unwrap();
+    assert_eq!(provider.current.get(), 1);
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert!(written.contains(&BASE64_STANDARD.encode("\0user\0old")));
+    assert!(written.ends_with(&format!("{}\r\n", BASE64_STANDARD.encode("\0user\0new"))));
+}
+
+#[tokio::test]
+async fn test_authenticate_with_failing_provider() {
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("535 5.7.8 Authentication credentials invalid");
+
+    let mut smtp = Smtp::new(mock);
+    smtp.set_allow_plaintext_auth(true);
+    smtp.ready().await.unwrap();
+    smtp.ehlo("client.example.com").await.unwrap();
+    // refreshed only once, the error of the provider is returned
+    let provider = RotatingCredentials {
+        credentials: vec![password("old")],
+        current: 0.into(),
+    };
+    let err = smtp.authenticate_with(&provider).await.err().unwrap();
+    assert!(matches!(&err, Error::Credentials(MockError(message)) if message == "vault is empty"));
+    assert!(err.is_session_usable());
+    assert!(!smtp.is_authenticated());
+}
+
+#[tokio::test]
+async fn test_authenticate_with_oauth2_token() {
+    use base64::prelude::*;
+    use simple_smtp::smtp::auth::Credentials;
+
+    let mut mock = mock_with_greeting();
+    mock.queue_multiline(250, &["mail.example.com", "AUTH XOAUTH2"]);
+    // the token expired
+    mock.queue_line(&format!(
+        "334 {}",
+        BASE64_STANDARD.encode(r#"{"status":"401","schemes":"bearer"}"#)
+    ));
+    mock.queue_line("535 5.7.8 Username and Password not accepted");
+    mock.queue_line("235 2.7.0 Accepted");
+
+    let mut smtp = Smtp::new(mock);
+    smtp.ready().await.unwrap();
+    smtp.ehlo("client.local").await.unwrap();
+    smtp.set_allow_plaintext_auth(true);
+    let token = |token: &str| Credentials::OAuth2 {
+        username: "me".to_string(),
+        token: token.to_string(),
+    };
+    let provider = RotatingCredentials {
+        credentials: vec![token("expired"), token("token")],
+        current: 0.into(),
+    };
+    smtp.authenticate_with(&provider).await.unwrap();
+    assert!(smtp.is_authenticated());
+    // the secret isn't shown
+    assert_eq!(
+        format!("{:?}", token("token")),
+        r#"OAuth2 { username: "me", .. }"#
+    );
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert!(written.contains(&format!(
+        "AUTH XOAUTH2 {}\r\n\r\n",
+        BASE64_STANDARD.encode("user=me\x01auth=Bearer expired\x01\x01")
+    )));
+    assert!(written.ends_with("AUTH XOAUTH2 dXNlcj1tZQFhdXRoPUJlYXJlciB0b2tlbgEB\r\n"));
+}
+
+#[tokio::test]
+async fn test_authenticate_with_oauth2_repeated_challenge() {
+    use base64::prelude::*;
+    use simple_smtp::smtp::auth::Credentials;
+
+    let mut mock = mock_with_greeting();
+    mock.queue_multiline(250, &["mail.example.com", "AUTH XOAUTH2"]);
+    let challenge = BASE64_STANDARD.encode(r#"{"status":"401","schemes":"bearer"}"#);
+    mock.queue_line(&format!("334 {challenge}"));
+    // the empty response is challenged again instead of getting the final reply
+    mock.queue_line(&format!("334 {challenge}"));
+    mock.queue_line("501 5.7.0 Authentication cancelled");
+
+    let mut smtp = Smtp::new(mock);
+    smtp.ready().await.unwrap();
+    smtp.ehlo("client.local").await.unwrap();
+    smtp.set_allow_plaintext_auth(true);
+    let provider = RotatingCredentials {
+        credentials: vec![Credentials::OAuth2 {
+            username: "me".to_string(),
+            token: "token".to_string(),
+        }],
+        current: 0.into(),
+    };
+    let err = smtp.authenticate_with(&provider).await.err().unwrap();
+    assert!(matches!(
+        err,
+        Error::MalformedError(MalformedError::UnexpectedCode { actual, .. })
+            if actual == ReplyCode::AUTH_CONTINUE
+    ));
+    assert!(!smtp.is_authenticated());
+
+    // the second challenge was cancelled, not refreshed and retried
+    let (stream, _) = smtp.into_inner();
+    assert!(stream.written_str().ends_with("\r\n\r\n*\r\n"));
+    assert_eq!(provider.current.get(), 0);
+}
+
+#[tokio::test]
+async fn test_authenticate_with_long_oauth2_token() {
+    use base64::prelude::*;
+    use simple_smtp::smtp::auth::Credentials;
+
+    let mut mock = mock_with_greeting();
+    mock.queue_multiline(250, &["mail.example.com", "AUTH XOAUTH2"]);
+    mock.queue_line("235 2.7.0 Accepted");
+    mock.queue_line("250 OK");
+
+    let mut smtp = Smtp::new(mock);
+    smtp.ready().await.unwrap();
+    smtp.ehlo("client.local").await.unwrap();
+    smtp.set_allow_plaintext_auth(true);
+    // access tokens of some providers are well over the 1 KiB buffer
+    let token = "t".repeat(1500);
+    let provider = RotatingCredentials {
+        credentials: vec![Credentials::OAuth2 {
+            username: "me".to_string(),
+            token: token.clone(),
+        }],
+        current: 0.into(),
+    };
+    smtp.authenticate_with(&provider).await.unwrap();
+    assert!(smtp.is_authenticated());
+    assert_eq!(smtp.noop().await.unwrap().code().as_u16(), 250);
+
+    let (stream, _) = smtp.into_inner();
+    let response = format!("user=me\x01auth=Bearer {token}\x01\x01");
+    assert!(stream.written_str().contains(&format!(
+        "AUTH XOAUTH2 {}\r\n",
+        BASE64_STANDARD.encode(response)
+    )));
+}
+
+#[tokio::test]
+async fn test_send_mail_flow() {
+    let mut mock = mock_with_ehlo();
+    // MAIL FROM response
+    mock.queue_line("250 OK");
+    // RCPT TO response
+    mock.queue_line("250 OK");
+    // DATA response
+    mock.queue_line("354 Start mail input");
+    // End of data response
+    mock.queue_line("250 OK: queued as 12345");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+
+    smtp.send_mail(
+        "sender@example.com",
+        ["recipient@example.com"].iter(),
+        b"Subject: Test\r\n\r\nHello!",
+    )
+    .await
+    .expect("send_mail() should succeed");
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+
+    assert!(written.contains("MAIL FROM:<sender@example.com>\r\n"));
+    assert!(written.contains("RCPT TO:<recipient@example.com>\r\n"));
+    assert!(written.contains("DATA\r\n"));
+    assert!(written.contains("Subject: Test\r\n\r\nHello!"));
+    assert!(written.contains("\r\n.\r\n")); // End of data marker
+}
+
+#[tokio::test]
+async fn test_send_mail_refuses_addresses_ending_the_command() {
+    use simple_smtp::ProtocolError;
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("250 OK"); // RSET
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+
+    let injected = "me@example.com>\r\nRCPT TO:<other@example.com";
+    assert!(matches!(
+        smtp.send_mail(injected, ["you@example.com"].iter(), b"hi")
+            .await,
+        Err(Error::ProtocolError(ProtocolError::InvalidAddress))
+    ));
+    // the recipients are only known while sending, the transaction is reset
+    let to = ["you@example.com", "them@example.com\nDATA"];
+    assert!(matches!(
+        smtp.send_mail("me@example.com", to.iter(), b"hi").await,
+        Err(Error::ProtocolError(ProtocolError::InvalidAddress))
+    ));
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert!(
+        written.ends_with("MAIL FROM:<me@example.com>\r\nRCPT TO:<you@example.com>\r\nRSET\r\n")
+    );
+}
+
+#[tokio::test]
+async fn test_quit() {
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("221 Bye");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+
+    let reply = smtp.quit().await.expect("quit() should succeed");
+    assert_eq!(reply.code(), 221);
+
+    let (stream, _) = smtp.into_inner();
+    assert!(stream.contains_command("QUIT\r\n"));
+}
+
+#[tokio::test]
+async fn test_full_happy_path() {
+    // The whole enchilada: greeting -> EHLO -> AUTH -> MAIL -> QUIT
+    let mut mock = MockStream::new();
+    mock.queue_line("220 mail.example.com ESMTP");
+    mock.queue_multiline(250, &["mail.example.com", "AUTH PLAIN"]);
+    mock.queue_line("235 Authenticated");
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+    mock.queue_line("221 Bye");
+
+    let mut smtp = Smtp::new(mock);
+    smtp.set_allow_plaintext_auth(true);
+
+    // Full flow
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.local").await.unwrap();
+    let _ = smtp.auth("me", "secret").await.unwrap();
+    smtp.send_mail("me@local", ["you@remote"].iter(), b"hi")
+        .await
+        .unwrap();
+    let _ = smtp.quit().await.unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+
+    // Verify the conversation order
+    let ehlo_pos = written.find("EHLO").unwrap();
+    let auth_pos = written.find("AUTH").unwrap();
+    let mail_pos = written.find("MAIL FROM").unwrap();
+    let quit_pos = written.find("QUIT").unwrap();
+
+    assert!(ehlo_pos < auth_pos, "EHLO should come before AUTH");
+    assert!(auth_pos < mail_pos, "AUTH should come before MAIL FROM");
+    assert!(mail_pos < quit_pos, "MAIL FROM should come before QUIT");
+}
+
+#[tokio::test]
+async fn test_dry_run_stops_before_data() {
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("250 OK"); // RSET
+
+    let mut smtp = Smtp::new(mock);
+    smtp.set_dry_run(true);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    smtp.send_mail("me@local", ["you@example.com"].iter(), b"hi")
+        .await
+        .unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert!(written.contains("RCPT TO:<you@example.com>\r\nRSET\r\n"));
+    assert!(!written.contains("DATA"));
+}
+
+#[tokio::test]
+async fn test_dry_run_reports_rejection() {
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("550 5.1.1 User unknown"); // RCPT TO
+    mock.queue_line("250 Flushed"); // RSET
+
+    let mut smtp = Smtp::new(mock);
+    smtp.set_dry_run(true);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let err = smtp
+        .send_mail("me@local", ["nobody@example.com"].iter(), b"hi")
+        .await
+        .unwrap_err();
+    assert!(err.is_permanent());
+}
+
+#[tokio::test]
+async fn test_dry_run_survives_starttls() {
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("220 Ready to start TLS");
+    mock.queue_multiline(250, &["smtp.example.com", "AUTH PLAIN"]);
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("250 OK"); // RSET
+
+    let mut smtp = Smtp::new(mock);
+    smtp.set_dry_run(true);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let _ = smtp.starttls().await.unwrap();
+
+    // a real client would wrap the stream in TLS here
+    let (stream, upgrade) = smtp.into_upgrade();
+    let mut smtp = upgrade.finish(stream);
+    assert!(smtp.is_dry_run());
+    assert!(smtp.is_secure());
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    smtp.send_mail("me@local", ["you@example.com"].iter(), b"hi")
+        .await
+        .unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert!(written.ends_with("RCPT TO:<you@example.com>\r\nRSET\r\n"));
+    assert!(!written.contains("DATA"));
+}
+
+#[tokio::test]
+async fn test_dry_run_refuses_send_data() {
+    let mut smtp = Smtp::new(mock_with_ehlo());
+    smtp.set_dry_run(true);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    for result in [
+        smtp.send_data(b"hi").await.map(|_| ()),
+        smtp.send_data_raw(b"hi").await.map(|_| ()),
+    ] {
+        assert!(matches!(
+            result,
+            Err(Error::ProtocolError(simple_smtp::ProtocolError::DryRun))
+        ));
+    }
+    let body = ChunkedBody {
+        chunks: VecDeque::from([&b"hi"[..]]),
+    };
+    assert!(matches!(
+        smtp.send_data_stream(body).await,
+        Err(Error::ProtocolError(simple_smtp::ProtocolError::DryRun))
+    ));
+
+    let (stream, _) = smtp.into_inner();
+    assert!(
+        stream
+            .written_str()
+            .ends_with("EHLO client.example.com\r\n")
+    );
+}
+
+/// Hands out a message in fixed chunks, like a reader of a flash chip would.
+struct ChunkedBody {
+    chunks: VecDeque<&'static [u8]>,
+}
+
+impl simple_smtp::AsyncBodySource for ChunkedBody {
+    type Error = MockError;
+    async fn next_chunk(&mut self) -> Result<&[u8], Self::Error> {
+        Ok(self.chunks.pop_front().unwrap_or_default())
+    }
+}
+
+#[tokio::test]
+async fn test_send_envelope_stream_dot_stuffs_across_chunks() {
+    use simple_smtp::envelope::{Envelope, Recipient};
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let recipients = [Recipient::new("you@example.com")];
+    let body = ChunkedBody {
+        chunks: VecDeque::from([
+            &b"Subject: log\r\n\r\nline one\r"[..],
+            b"\n",
+            b".hidden line\r\n",
+        ]),
+    };
+    smtp.send_envelope_stream(&Envelope::new("me@local", &recipients), body)
+        .await
+        .unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    assert!(
+        stream
+            .written_str()
+            .ends_with("DATA\r\nSubject: log\r\n\r\nline one\r\n..hidden line\r\n.\r\n")
+    );
+}
+
+#[tokio::test]
+async fn test_send_data_dot_stuffs() {
+    let mut mock = MockStream::new();
+    mock.queue_line("250 Queued");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    smtp.send_data(b".leading\r\nmiddle\r\n.\r\nend")
+        .await
+        .unwrap();
+    smtp.send_data_raw(b"..stuffed\r\n").await.unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    assert_eq!(
+        stream.written_str(),
+        "..leading\r\nmiddle\r\n..\r\nend\r\n.\r\n..stuffed\r\n.\r\n"
+    );
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Tests: Error Recovery
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[tokio::test]
+async fn test_auth_rejected() {
+    let mut mock = mock_with_ehlo();
+    // Server says no 😤
+    mock.queue_line("535 Authentication failed");
+
+    let mut smtp = Smtp::new(mock);
+    smtp.set_allow_plaintext_auth(true);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+
+    let result = smtp.auth("baduser", "wrongpass").await;
+    assert!(result.is_err(), "auth() should fail on 535");
+}
+
+#[tokio::test]
+async fn test_mail_from_rejected() {
+    let mut mock = mock_with_ehlo();
+    // Sender not allowed
+    mock.queue_line("550 Sender rejected");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+
+    let result = smtp
+        .send_mail("spam@bad.com", ["victim@example.com"].iter(), b"spam")
+        .await;
+    assert!(result.is_err(), "send_mail() should fail on 550");
+}
+
+#[tokio::test]
+async fn test_rcpt_to_rejected() {
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM succeeds
+    mock.queue_line("550 User unknown"); // RCPT TO fails
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+
+    let result = smtp
+        .send_mail("sender@ok.com", ["nonexistent@example.com"].iter(), b"hi")
+        .await;
+    assert!(
+        result.is_err(),
+        "send_mail() should fail if RCPT TO rejected"
+    );
+}
+
+#[tokio::test]
+async fn test_unexpected_eof() {
+    // Server just... disappears 💀
+    let mut mock = MockStream::new();
+    mock.queue_line("220 mail.example.com ESMTP");
+    // No EHLO response queued — EOF will happen
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+
+    let result = smtp.ehlo("client.local").await;
+    assert!(result.is_err(), "should error on unexpected EOF");
+}
+
+#[tokio::test]
+async fn test_malformed_no_code() {
+    // Server sends garbage without a status code
+    let mut mock = MockStream::new();
+    mock.queue_response(b"Hello I am a broken server\r\n".to_vec());
+
+    let mut smtp = Smtp::new(mock);
+    let result = smtp.ready().await;
+    assert!(result.is_err(), "should error on malformed response");
+}
+
+#[tokio::test]
+async fn test_malformed_bad_line_terminator() {
+    // Server uses bare LF instead of CRLF 🙄
+    let mut mock = MockStream::new();
+    mock.queue_response(b"220 mail.example.com ESMTP\n".to_vec());
+
+    let mut smtp = Smtp::new(mock);
+    let result = smtp.ready().await;
+    assert!(result.is_err(), "should error on bare LF");
 }
 
 #[tokio::test]
 async fn test_io_error_propagates() {
     let mut mock = MockStream::new();
-    mock.inject_read_error(MockError::new("connection reset by peer"));
+    mock.inject_read_error(MockError::new("connection reset by peer"));
+
+    let mut smtp = Smtp::new(mock);
+    let result = smtp.ready().await;
+
+    assert!(result.is_err(), "IO error should propagate");
+}
+
+#[tokio::test]
+async fn test_wrong_greeting_code() {
+    // Server is temporarily unavailable
+    let mut mock = MockStream::new();
+    mock.queue_line("421 Service not available");
+
+    let mut smtp = Smtp::new(mock);
+    let result = smtp.ready().await;
+
+    assert!(result.is_err(), "ready() should fail on non-220 code");
+}
+
+#[tokio::test]
+async fn test_rcpt_rejection_is_permanent() {
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK");
+    mock.queue_line("550 5.1.1 User unknown");
+    mock.queue_line("250 Flushed"); // RSET
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+
+    let err = smtp
+        .send_mail("sender@ok.com", ["nonexistent@example.com"].iter(), b"hi")
+        .await
+        .unwrap_err();
+    assert!(err.is_permanent());
+    match err {
+        Error::ServerRejected {
+            code,
+            enhanced,
+            message,
+        } => {
+            assert_eq!(code, ReplyCode::MAILBOX_UNAVAILABLE);
+            assert_eq!(enhanced.unwrap().to_string(), "5.1.1");
+            assert_eq!(message.as_str(), "5.1.1 User unknown");
+        }
+        other => panic!("expected ServerRejected, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_greeting_421_is_transient() {
+    let mut mock = MockStream::new();
+    mock.queue_line("421 Service not available");
+
+    let mut smtp = Smtp::new(mock);
+    let Err(err) = smtp.ready().await else {
+        panic!("ready() should fail on 421");
+    };
+    assert!(err.is_transient());
+    assert!(!err.is_permanent());
+}
+
+#[tokio::test]
+async fn test_retry_policy_overrides_basic_code() {
+    use simple_smtp::smtp::{
+        enhanced::Class,
+        retry::{CodePattern, RetryAction, RetryPolicy},
+    };
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("451 4.7.1 Rejected by policy");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let err = smtp
+        .send_mail("sender@ok.com", ["you@example.com"].iter(), b"hi")
+        .await
+        .unwrap_err();
+    assert!(err.is_transient());
+    assert!(err.should_retry(&RetryPolicy::new()));
+
+    let rules = [(
+        CodePattern::Subject(Class::PersistentTransientFailure, 7),
+        RetryAction::Never,
+    )];
+    assert!(!err.should_retry(&RetryPolicy::new().with_rules(&rules)));
+    // only rejections are retried
+    assert!(!Error::<MockError>::Timeout.should_retry(&RetryPolicy::new()));
+}
+
+#[tokio::test]
+async fn test_client_cert_only_skips_auth() {
+    use simple_smtp::smtp::auth::AuthMode;
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    // a real client would have done the TLS handshake with its certificate
+    smtp.set_secure(true);
+    smtp.authenticate(&AuthMode::ClientCertOnly).await.unwrap();
+    smtp.send_mail("me@local", ["you@example.com"].iter(), b"hi")
+        .await
+        .unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    assert!(!stream.written_str().contains("AUTH"));
+}
+
+#[tokio::test]
+async fn test_client_cert_only_auth_still_required() {
+    use simple_smtp::{ProtocolError, smtp::auth::AuthMode};
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("530 5.7.0 Authentication required");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    // a real client would have done the TLS handshake with its certificate
+    smtp.set_secure(true);
+    smtp.authenticate(&AuthMode::ClientCertOnly).await.unwrap();
+    let result = smtp
+        .send_mail("me@local", ["you@example.com"].iter(), b"hi")
+        .await;
+    assert!(matches!(
+        result,
+        Err(Error::ProtocolError(
+            ProtocolError::ClientCertificateNotAccepted
+        ))
+    ));
+}
+
+#[tokio::test]
+async fn test_noop_vrfy_expn_help() {
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // NOOP
+    mock.queue_multiline(250, &["Jane Doe <jane@example.com>", "not a mailbox"]);
+    mock.queue_line("252 Cannot VRFY user, but will accept message");
+    mock.queue_line("251 User not local; will forward to <jim@example.org>");
+    mock.queue_multiline(250, &["<a@example.com>", "Bob <b@example.com>"]);
+    mock.queue_line("550 No such list");
+    mock.queue_multiline(214, &["Commands:", "EHLO MAIL RCPT DATA"]);
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.local").await.unwrap();
+
+    assert_eq!(smtp.noop().await.unwrap().code(), 250);
+    let reply = smtp.vrfy("jane").await.unwrap();
+    assert!(reply.is_verified());
+    let names: Vec<_> = reply
+        .mailboxes()
+        .map(|mailbox| mailbox.display_name().unwrap().to_string())
+        .collect();
+    assert_eq!(names, ["Jane Doe"]);
+    let reply = smtp.vrfy("john@example.com").await.unwrap();
+    assert!(!reply.is_verified());
+    assert_eq!(reply.mailboxes().count(), 0);
+    let reply = smtp.vrfy("jim").await.unwrap();
+    assert_eq!(reply.code(), 251);
+    assert!(reply.is_verified());
+    let members: Vec<_> = smtp
+        .expn("staff")
+        .await
+        .unwrap()
+        .mailboxes()
+        .map(|mailbox| mailbox.address().as_str().to_owned())
+        .collect();
+    assert_eq!(members, ["a@example.com", "b@example.com"]);
+    assert!(matches!(smtp.expn("nobody").await, Err(e) if e.is_permanent()));
+    assert!(matches!(
+        smtp.vrfy("jane\r\nRSET").await,
+        Err(Error::ProtocolError(
+            simple_smtp::ProtocolError::InvalidArgument
+        ))
+    ));
+    let help = smtp.help(None).await.unwrap();
+    assert_eq!(
+        help.lines().collect::<Vec<_>>(),
+        ["Commands:", "EHLO MAIL RCPT DATA"]
+    );
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert!(written.ends_with(
+        "NOOP\r\nVRFY jane\r\nVRFY john@example.com\r\nVRFY jim\r\nEXPN staff\r\nEXPN nobody\r\nHELP\r\n"
+    ));
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Tests: Envelope / DSN
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[tokio::test]
+async fn test_send_envelope_dsn_params() {
+    use simple_smtp::envelope::{Envelope, Notify, Recipient, Ret};
+
+    let mut mock = MockStream::new();
+    mock.queue_line("220 mail.example.com ESMTP");
+    mock.queue_multiline(250, &["mail.example.com", "DSN"]);
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let ehlo = smtp.ehlo("client.local").await.unwrap();
+    assert!(ehlo.supports(simple_smtp::smtp::Extensions::Dsn));
+
+    let recipients = [
+        Recipient::new("alice@example.com")
+            .with_notify(Notify::FAILURE | Notify::DELAY)
+            .with_orcpt("alice+old@example.com"),
+        Recipient::new("bob@example.com").with_notify(Notify::NEVER),
+    ];
+    let envelope = Envelope::new("me@local", &recipients)
+        .with_ret(Ret::Headers)
+        .with_envid("QQ 314159");
+    smtp.send_envelope(&envelope, b"hi").await.unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert!(written.contains("MAIL FROM:<me@local> RET=HDRS ENVID=QQ+20314159\r\n"));
+    assert!(written.contains(
+        "RCPT TO:<alice@example.com> NOTIFY=FAILURE,DELAY ORCPT=rfc822;alice+2Bold@example.com\r\n"
+    ));
+    assert!(written.contains("RCPT TO:<bob@example.com> NOTIFY=NEVER\r\n"));
+}
+
+#[tokio::test]
+async fn test_send_envelope_envid_length() {
+    use simple_smtp::{
+        ProtocolError,
+        envelope::{Envelope, MAX_ENVID_LEN, Recipient},
+    };
+
+    let mut mock = MockStream::new();
+    mock.queue_line("220 mail.example.com ESMTP");
+    mock.queue_multiline(250, &["mail.example.com", "DSN"]);
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.local").await.unwrap();
+
+    let recipients = [Recipient::new("alice@example.com")];
+    let envelope = Envelope::new("me@local", &recipients);
+    // the limit applies to the xtext encoding, where a `+` takes three characters
+    let too_long = [
+        "Q".repeat(MAX_ENVID_LEN + 1),
+        "+".repeat(MAX_ENVID_LEN / 3 + 1),
+    ];
+    for envid in &too_long {
+        assert!(matches!(
+            smtp.send_envelope(&envelope.with_envid(envid), b"hi").await,
+            Err(Error::ProtocolError(ProtocolError::InvalidParameter))
+        ));
+    }
+    let longest = format!("Q{}", "+".repeat(MAX_ENVID_LEN / 3));
+    smtp.send_envelope(&envelope.with_envid(&longest), b"hi")
+        .await
+        .unwrap();
+
+    // only the envelope with the longest ENVID was sent
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    let envid = format!("Q{}", "+2B".repeat(MAX_ENVID_LEN / 3));
+    assert!(written.contains(&format!("MAIL FROM:<me@local> ENVID={envid}\r\n")));
+    assert_eq!(written.matches("MAIL FROM").count(), 1);
+}
+
+#[tokio::test]
+async fn test_send_envelope_refuses_addresses_ending_the_command() {
+    use simple_smtp::{
+        ProtocolError,
+        envelope::{Envelope, Recipient},
+    };
+
+    let mut smtp = Smtp::new(mock_with_ehlo());
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.local").await.unwrap();
+
+    let recipients = [Recipient::new("alice@example.com")];
+    let injected = [Recipient::new("alice@example.com>\r\nRSET")];
+    for envelope in [
+        Envelope::new("me@local\rRSET", &recipients),
+        Envelope::new("me@local>", &recipients),
+        Envelope::new("me@local", &injected),
+    ] {
+        assert!(!envelope.has_valid_addresses());
+        assert!(matches!(
+            smtp.send_envelope(&envelope, b"hi").await,
+            Err(Error::ProtocolError(ProtocolError::InvalidAddress))
+        ));
+    }
+
+    // refused before sending anything
+    let (stream, _) = smtp.into_inner();
+    assert!(!stream.written_str().contains("MAIL FROM"));
+}
+
+#[tokio::test]
+async fn test_send_envelope_submitter() {
+    use simple_smtp::envelope::{Envelope, Recipient, Submitter};
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("235 Authentication successful");
+    for _ in 0..2 {
+        mock.queue_line("250 OK"); // MAIL FROM
+        mock.queue_line("250 OK"); // RCPT TO
+        mock.queue_line("354 Go ahead");
+        mock.queue_line("250 Queued");
+    }
+
+    let mut smtp = Smtp::new(mock);
+    smtp.set_allow_plaintext_auth(true);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.local").await.unwrap();
+
+    let recipients = [Recipient::new("alice@example.com")];
+    let envelope = Envelope::new("me@local", &recipients)
+        .with_submitter(Submitter::Identity("me+relay@local"));
+    // the identity is only trustworthy on an authenticated session
+    assert!(!smtp.is_authenticated());
+    assert!(matches!(
+        smtp.send_envelope(&envelope, b"hi").await,
+        Err(Error::ProtocolError(
+            simple_smtp::ProtocolError::NotAuthenticated
+        ))
+    ));
+
+    smtp.auth("me", "secret").await.unwrap();
+    assert!(smtp.is_authenticated());
+    smtp.send_envelope(&envelope, b"hi").await.unwrap();
+    let envelope = envelope.with_submitter(Submitter::Unknown);
+    smtp.send_envelope(&envelope, b"hi").await.unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert!(written.contains("MAIL FROM:<me@local> AUTH=me+2Brelay@local\r\n"));
+    assert!(written.contains("MAIL FROM:<me@local> AUTH=<>\r\n"));
+    // nothing was sent before authenticating
+    assert_eq!(written.matches("MAIL FROM").count(), 2);
+}
+
+#[tokio::test]
+async fn test_send_envelope_extension_parameters() {
+    use simple_smtp::{
+        ProtocolError,
+        envelope::{BodyType, Envelope, Parameter, Recipient},
+    };
+
+    let mut mock = MockStream::new();
+    mock.queue_line("220 mail.example.com ESMTP");
+    mock.queue_multiline(250, &["mail.example.com", "SIZE 1000", "8BITMIME"]);
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.local").await.unwrap();
+
+    let priority = [Parameter::new("MT-PRIORITY", "3")];
+    let flag = [Parameter::flag("X-FLAG")];
+    let recipients = [Recipient::new("alice@example.com").with_parameters(&flag)];
+    let envelope = Envelope::new("me@local", &recipients)
+        .with_size(2)
+        .with_body(BodyType::EightBitMime)
+        .with_parameters(&priority);
+    // refused up front, without sending anything
+    assert!(matches!(
+        smtp.send_envelope(&envelope.with_size(1001), b"hi").await,
+        Err(Error::ProtocolError(ProtocolError::MessageTooLarge {
+            size: 1001,
+            limit: 1000
+        }))
+    ));
+    let invalid = [Parameter::new("MT-PRIORITY", "3\r\nRSET")];
+    assert!(matches!(
+        smtp.send_envelope(&envelope.with_parameters(&invalid), b"hi")
+            .await,
+        Err(Error::ProtocolError(ProtocolError::InvalidParameter))
+    ));
+    smtp.send_envelope(&envelope, b"hi").await.unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert!(written.contains("MAIL FROM:<me@local> SIZE=2 BODY=8BITMIME MT-PRIORITY=3\r\n"));
+    assert!(written.contains("RCPT TO:<alice@example.com> X-FLAG\r\n"));
+    assert_eq!(written.matches("MAIL FROM").count(), 1);
+}
+
+#[tokio::test]
+async fn test_send_envelope_leaves_out_undeclarable_parameters() {
+    use simple_smtp::{
+        Operation, ProtocolError,
+        envelope::{BodyType, Envelope, Recipient},
+        smtp::Extensions,
+    };
+
+    let mut mock = MockStream::new();
+    mock.queue_line("220 mail.example.com ESMTP");
+    mock.queue_multiline(250, &["mail.example.com", "PIPELINING"]);
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.local").await.unwrap();
+
+    let recipients = [Recipient::new("alice@example.com")];
+    let envelope = Envelope::new("me@local", &recipients).with_size(2);
+    assert!(matches!(
+        smtp.send_envelope(&envelope.with_body(BodyType::EightBitMime), b"hi")
+            .await,
+        Err(Error::ProtocolError(ProtocolError::UnsupportedExtension {
+            extension: Extensions::EIGHTBITMIME,
+            needed_for: Operation::EightBitBody,
+        }))
+    ));
+    // the size and a 7-bit body are only declarations, so they are dropped
+    smtp.send_envelope(&envelope.with_body(BodyType::SevenBit), b"hi")
+        .await
+        .unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert!(written.contains("MAIL FROM:<me@local>\r\n"));
+    assert_eq!(written.matches("MAIL FROM").count(), 1);
+}
+
+#[tokio::test]
+async fn test_rejected_recipient_resets_transaction() {
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("550 5.1.1 No such user");
+    mock.queue_line("250 Flushed"); // RSET
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("554 No valid recipients"); // DATA
+    mock.queue_line("250 Flushed"); // RSET
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("421 Shutting down");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.local").await.unwrap();
+
+    let error = smtp
+        .send_mail("me@local", ["nobody@example.com"].iter(), b"hi")
+        .await
+        .unwrap_err();
+    assert!(error.is_permanent());
+    assert!(error.is_session_usable());
+    let error = smtp
+        .send_mail("me@local", ["you@example.com"].iter(), b"hi")
+        .await
+        .unwrap_err();
+    assert!(error.is_session_usable());
+    // the server is going away, so there's nothing to reset
+    let error = smtp
+        .send_mail("me@local", ["you@example.com"].iter(), b"hi")
+        .await
+        .unwrap_err();
+    assert!(error.is_transient());
+    assert!(!error.is_session_usable());
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    let commands: Vec<_> = written.lines().collect();
+    simple_smtp::expect_sequence![
+        "MAIL FROM:<me@local>",
+        "RCPT TO:<nobody@example.com>",
+        "RSET",
+        "MAIL FROM:<me@local>",
+        "RCPT TO:<you@example.com>",
+        "DATA",
+        "RSET",
+        "MAIL FROM:<me@local>",
+        "RCPT TO:<you@example.com>",
+    ]
+    .assert(&commands);
+    assert!(written.ends_with("RCPT TO:<you@example.com>\r\n"));
+}
+
+#[tokio::test]
+async fn test_send_many() {
+    use simple_smtp::envelope::{Envelope, Recipient};
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("550 No such user");
+    mock.queue_line("250 Flushed"); // RSET
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+    mock.queue_line("421 Too many messages");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.local").await.unwrap();
+
+    let good = [Recipient::new("you@example.com")];
+    let bad = [Recipient::new("nobody@example.com")];
+    let good = Envelope::new("me@local", &good);
+    let bad = Envelope::new("me@local", &bad);
+    let queue = [(&good, &b"1"[..]), (&bad, b"2"), (&good, b"3")];
+    let mut rejected = Vec::new();
+    let sent = smtp
+        .send_many(queue, |index, error| {
+            rejected.push((index, error.is_permanent()))
+        })
+        .await
+        .unwrap();
+    assert_eq!(sent, 2);
+    assert_eq!(rejected, [(1, true)]);
+
+    // the server closes the connection, so the rest of the queue stays unsent
+    let queue = [(&good, &b"4"[..]), (&good, b"5")];
+    let error = smtp.send_many(queue, |_, _| {}).await.unwrap_err();
+    assert!(!error.is_session_usable());
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert_eq!(written.matches("MAIL FROM").count(), 4);
+    assert_eq!(written.matches("DATA").count(), 2);
+}
+
+#[tokio::test]
+async fn test_send_envelope_partial() {
+    use simple_smtp::envelope::{Envelope, Recipient};
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK");
+    mock.queue_line("550 No such user");
+    mock.queue_line("451 Try again later");
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("550 No such user");
+    mock.queue_line("250 Flushed"); // RSET
+
+    let mut smtp = Smtp::new(mock);
+    smtp.ready().await.unwrap();
+    smtp.ehlo("client.local").await.unwrap();
+
+    let recipients = [
+        Recipient::new("you@example.com"),
+        Recipient::new("nobody@example.com"),
+        Recipient::new("busy@example.com"),
+    ];
+    let mut rejected = Vec::new();
+    let sent = smtp
+        .send_envelope_partial(
+            &Envelope::new("me@local", &recipients),
+            b"hi",
+            |index, e| rejected.push((index, e.is_permanent())),
+        )
+        .await
+        .unwrap();
+    assert_eq!(sent, 1);
+    assert_eq!(rejected, [(1, true), (2, false)]);
+
+    // nobody accepted, nothing is sent
+    let sent = smtp
+        .send_envelope_partial(
+            &Envelope::new("me@local", &recipients[1..2]),
+            b"hi",
+            |_, _| {},
+        )
+        .await
+        .unwrap();
+    assert_eq!(sent, 0);
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert_eq!(written.matches("DATA").count(), 1);
+    assert!(written.ends_with("RSET\r\n"));
+}
+
+#[tokio::test]
+async fn test_send_mail_report() {
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("550 No such user");
+    mock.queue_line("250 OK");
+    mock.queue_line("452 Too many recipients");
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    smtp.ready().await.unwrap();
+    smtp.ehlo("client.local").await.unwrap();
+
+    let to = ["nobody@example.com", "you@example.com", "them@example.com"];
+    let report = smtp
+        .send_mail_report("me@local", to.iter(), b"hi")
+        .await
+        .unwrap();
+    assert!(report.is_sent());
+    assert_eq!(report.accepted, ["you@example.com"]);
+    let rejected: Vec<_> = report
+        .rejected
+        .iter()
+        .map(|(address, e)| (address.as_str(), e.is_permanent()))
+        .collect();
+    assert_eq!(
+        rejected,
+        [("nobody@example.com", true), ("them@example.com", false)]
+    );
+
+    let (stream, _) = smtp.into_inner();
+    assert_eq!(stream.written_str().matches("RCPT TO").count(), 3);
+}
+
+#[tokio::test]
+async fn test_failed_reset_reports_unusable_session() {
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("550 No such user");
+    mock.queue_line("garbage");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.local").await.unwrap();
+    let error = smtp
+        .send_mail("me@local", ["nobody@example.com"].iter(), b"hi")
+        .await
+        .unwrap_err();
+    assert!(!error.is_session_usable());
+}
+
+#[tokio::test]
+async fn test_hello_falls_back_to_helo() {
+    let mut mock = MockStream::new();
+    mock.queue_line("220 plc.factory.local SMTP");
+    mock.queue_line("502 Command not implemented");
+    mock.queue_line("250 plc.factory.local");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let capabilities = smtp.hello("client.local").await.unwrap();
+    assert!(!capabilities.supports(simple_smtp::smtp::Extensions::SIZE));
+    assert!(smtp.is_legacy());
+
+    let (stream, _) = smtp.into_inner();
+    assert_eq!(
+        stream.written_str(),
+        "EHLO client.local\r\nHELO client.local\r\n"
+    );
+}
+
+#[tokio::test]
+async fn test_hello_prefers_ehlo() {
+    let mut smtp = Smtp::new(mock_with_ehlo());
+    let _ = smtp.ready().await.unwrap();
+    let capabilities = smtp.hello("client.local").await.unwrap();
+    assert_eq!(capabilities.max_size(), Some(10485760));
+    assert!(!smtp.is_legacy());
+
+    // other rejections aren't about the command, so there's no point in trying HELO
+    let mut mock = MockStream::new();
+    mock.queue_line("220 mail.example.com ESMTP");
+    mock.queue_line("554 Go away");
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    assert!(smtp.hello("client.local").await.is_err());
+    let (stream, _) = smtp.into_inner();
+    assert_eq!(stream.written_str(), "EHLO client.local\r\n");
+}
+
+#[tokio::test]
+async fn test_helo_legacy_session_refuses_extensions() {
+    use simple_smtp::{
+        Operation,
+        envelope::{Envelope, Notify, Recipient, Ret},
+        smtp::Extensions,
+    };
+
+    let mut mock = MockStream::new();
+    mock.queue_line("220 plc.factory.local SMTP");
+    mock.queue_line("250 plc.factory.local");
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    assert!(!smtp.is_legacy());
+    let reply = smtp.helo("client.local").await.unwrap();
+    assert_eq!(reply.code(), 250);
+    assert!(smtp.is_legacy());
+    // HELO servers can't offer extensions, so don't even try
+    assert!(smtp.starttls().await.is_err());
+    assert!(smtp.auth("me", "secret").await.is_err());
+
+    // nor send DSN parameters the server would reject
+    let recipients = [Recipient::new("alice@example.com").with_notify(Notify::FAILURE)];
+    let envelope = Envelope::new("me@local", &recipients).with_ret(Ret::Full);
+    assert!(matches!(
+        smtp.send_envelope(&envelope, b"hi").await,
+        Err(Error::ProtocolError(
+            simple_smtp::ProtocolError::UnsupportedExtension {
+                extension: Extensions::Dsn,
+                needed_for: Operation::DsnParameters,
+            }
+        ))
+    ));
+    let recipients = [Recipient::new("alice@example.com")];
+    smtp.send_envelope(&Envelope::new("me@local", &recipients), b"hi")
+        .await
+        .unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert!(written.contains("HELO client.local\r\n"));
+    assert!(written.contains("MAIL FROM:<me@local>\r\n"));
+    assert!(written.contains("RCPT TO:<alice@example.com>\r\n"));
+    assert_eq!(written.matches("MAIL FROM").count(), 1);
+}
+
+#[tokio::test]
+async fn test_international_address_needs_smtputf8() {
+    use simple_smtp::{
+        Operation, ProtocolError,
+        envelope::{Envelope, Recipient},
+        smtp::Extensions,
+    };
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_multiline(250, &["mail.example.com", "SMTPUTF8"]);
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.local").await.unwrap();
+    let recipients = [Recipient::new("jürgen@example.com")];
+    let envelope = Envelope::new("me@local", &recipients);
+    let error = smtp.send_envelope(&envelope, b"hi").await.unwrap_err();
+    assert!(matches!(
+        error,
+        Error::ProtocolError(ProtocolError::UnsupportedExtension {
+            extension: Extensions::SMTPUTF8,
+            needed_for: Operation::InternationalAddress,
+        })
+    ));
+    assert_eq!(
+        error.to_string(),
+        "Extension SMTPUTF8 not supported, needed for a non-ASCII address"
+    );
+
+    let _ = smtp.ehlo("client.local").await.unwrap();
+    smtp.send_envelope(&envelope, b"hi").await.unwrap();
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert!(written.contains("MAIL FROM:<me@local> SMTPUTF8\r\n"));
+    assert_eq!(written.matches("MAIL FROM").count(), 1);
+}
+
+#[tokio::test]
+async fn test_rejected_helo_is_not_legacy() {
+    let mut mock = MockStream::new();
+    mock.queue_line("220 mail.example.com ESMTP");
+    mock.queue_line("550 Go away");
+
+    let mut smtp = Smtp::new(mock);
+    smtp.ready().await.unwrap();
+    assert!(smtp.helo("client.local").await.is_err());
+    assert!(!smtp.is_legacy());
+}
+
+#[tokio::test]
+async fn test_client_cert_only_requires_tls() {
+    use simple_smtp::{ProtocolError, smtp::auth::AuthMode};
+
+    let mut smtp = Smtp::new(mock_with_ehlo());
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    assert!(matches!(
+        smtp.authenticate(&AuthMode::ClientCertOnly).await,
+        Err(Error::ProtocolError(
+            ProtocolError::ClientCertificateWithoutTls
+        ))
+    ));
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Tests: Address validation
+// ══════════════════════════════════════════════════════════════════════════════
+
+struct StaticResolver(&'static [&'static str]);
+
+impl simple_smtp::address::Resolver for StaticResolver {
+    type Error = MockError;
+    async fn has_mail_host(&mut self, domain: &str) -> Result<bool, Self::Error> {
+        Ok(self.0.contains(&domain))
+    }
+}
+
+#[tokio::test]
+async fn test_validate_levels() {
+    use simple_smtp::address::{Level, SyntaxError, Verdict, validate};
+
+    let mut resolver = StaticResolver(&["example.com"]);
+    let verdict = validate("user@nomail.example", Level::Syntax, &mut resolver).await;
+    assert_eq!(verdict.unwrap(), Verdict::Valid);
+    let verdict = validate("user@nomail.example", Level::MailHost, &mut resolver).await;
+    assert_eq!(verdict.unwrap(), Verdict::NoMailHost);
+    let verdict = validate("user@example.com", Level::MailHost, &mut resolver).await;
+    assert_eq!(verdict.unwrap(), Verdict::Valid);
+    let verdict = validate("user@[192.0.2.1]", Level::MailHost, &mut resolver).await;
+    assert_eq!(verdict.unwrap(), Verdict::Valid);
+    let verdict = validate("user@", Level::MailHost, &mut resolver).await;
+    assert_eq!(
+        verdict.unwrap(),
+        Verdict::InvalidSyntax(SyntaxError::EmptyDomain)
+    );
+}
+
+#[tokio::test]
+async fn test_callout() {
+    use simple_smtp::{
+        address::{Address, Verdict, callout},
+        smtp::ReplyCode,
+    };
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("550 5.1.1 No such user"); // RCPT TO
+    mock.queue_line("250 OK"); // RSET
+    mock.queue_line("221 Bye");
+
+    let mut smtp = Smtp::new(mock);
+    let address = Address::parse("nobody@example.com").unwrap();
+    let verdict = callout(&mut smtp, "client.example.com", "bounces@local", &address)
+        .await
+        .unwrap();
+    assert!(matches!(
+        verdict,
+        Verdict::Rejected {
+            code: ReplyCode::MAILBOX_UNAVAILABLE,
+            enhanced: Some(_)
+        }
+    ));
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert!(written.contains("RCPT TO:<nobody@example.com>\r\nRSET\r\nQUIT\r\n"));
+    assert!(!written.contains("DATA"));
+}
+
+#[tokio::test]
+async fn test_callout_cleans_up_after_rejected_sender() {
+    use simple_smtp::address::{Address, callout};
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("550 5.7.1 Sender blocked"); // MAIL FROM
+    mock.queue_line("250 OK"); // RSET
+    mock.queue_line("221 Bye");
+
+    let mut smtp = Smtp::new(mock);
+    let address = Address::parse("nobody@example.com").unwrap();
+    let err = callout(&mut smtp, "client.example.com", "bounces@local", &address)
+        .await
+        .unwrap_err();
+    assert!(err.is_permanent());
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert!(written.ends_with("MAIL FROM:<bounces@local>\r\nRSET\r\nQUIT\r\n"));
+    assert!(!written.contains("RCPT TO"));
+}
+
+#[tokio::test]
+async fn test_callout_skips_rset_before_greeting() {
+    use simple_smtp::address::{Address, callout};
+
+    // EHLO gets no reply, so the session is never greeted
+    let mut smtp = Smtp::new(mock_with_greeting());
+    let address = Address::parse("nobody@example.com").unwrap();
+    assert!(
+        callout(&mut smtp, "client.example.com", "bounces@local", &address)
+            .await
+            .is_err()
+    );
+
+    let (stream, _) = smtp.into_inner();
+    assert!(
+        stream
+            .written_str()
+            .ends_with("EHLO client.example.com\r\nQUIT\r\n")
+    );
+}
+
+#[tokio::test]
+async fn test_send_message_encodes_headers() {
+    use simple_smtp::{
+        envelope::{Envelope, Recipient},
+        message::{DateTime, Message},
+    };
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let recipients = [Recipient::new("bob@example.com")];
+    let subject = "Ünïcödé subjects longer than one encoded word are split";
+    let message = Message::new("Jürgen <j@example.com>")
+        .with_to(&["Bob <bob@example.com>", "\"Zoë\" <zoe@example.com>"])
+        .with_subject(subject)
+        .with_date(DateTime::from_utc(2025, 12, 7, 12, 0, 0).unwrap());
+    smtp.send_message(&Envelope::new("j@example.com", &recipients), &message)
+        .await
+        .unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    let data = written.split_once("DATA\r\n").unwrap().1;
+    let head = data.split_once("\r\n\r\n").unwrap().0;
+    assert!(head.is_ascii());
+    assert!(head.contains("From: =?UTF-8?B?SsO8cmdlbg==?= <j@example.com>\r\n"));
+    assert!(head.contains("To: Bob <bob@example.com>, =?UTF-8?B?Wm/Dqw==?= <zoe@example.com>\r\n"));
+    let mut encoded_subject = String::new();
+    simple_smtp::message::encoded_word::encode(subject, &mut encoded_subject).unwrap();
+    // both words are too long to share a line with the field name
+    assert!(head.contains(&format!("Subject:\r\n {encoded_subject}\r\n")));
+    assert!(encoded_subject.contains("?=\r\n =?UTF-8?B?"));
+}
+
+#[tokio::test]
+async fn test_send_message_in_one_write() {
+    use simple_smtp::{
+        envelope::{Envelope, Recipient},
+        message::{DateTime, Message},
+    };
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let recipients = [Recipient::new("bob@example.com")];
+    let message = Message::new("Alice <alice@example.com>")
+        .with_to(&["Bob <bob@example.com>"])
+        .with_subject("Lunch")
+        .with_text_body(".hungry?\r\n")
+        .with_date(DateTime::from_utc(2025, 12, 7, 12, 0, 0).unwrap());
+    smtp.send_message(&Envelope::new("alice@example.com", &recipients), &message)
+        .await
+        .unwrap();
+
+    // the headers, the dot-stuffed body and the end of data marker are written together
+    let (stream, _) = smtp.into_inner();
+    let data = stream
+        .writes()
+        .skip_while(|write| *write != b"DATA\r\n")
+        .nth(1)
+        .unwrap();
+    let data = std::str::from_utf8(data).unwrap();
+    assert!(data.starts_with("Date: "), "{data}");
+    assert!(data.contains("Subject: Lunch\r\n"));
+    assert!(data.ends_with("\r\n..hungry?\r\n.\r\n"), "{data}");
+}
+
+#[tokio::test]
+async fn test_flushes_before_reading_replies() {
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // NOOP
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let _ = smtp.noop().await.unwrap();
+    smtp.fast_quit().await.unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    let ehlo = "EHLO client.example.com\r\n".len();
+    let noop = "NOOP\r\n".len();
+    let quit = "QUIT\r\n".len();
+    assert_eq!(stream.flushes(), [0, ehlo, ehlo + noop, ehlo + noop + quit]);
+}
+
+#[tokio::test]
+async fn test_send_message_with_overrides() {
+    use simple_smtp::{
+        ProtocolError,
+        envelope::{Envelope, Recipient},
+        message::{DateTime, Message, Overrides},
+    };
+
+    let mut mock = mock_with_ehlo();
+    for _ in 0..2 {
+        mock.queue_line("250 OK"); // MAIL FROM
+        mock.queue_line("250 OK"); // RCPT TO
+        mock.queue_line("354 Go ahead");
+        mock.queue_line("250 Queued");
+    }
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let message = Message::new("news@example.com")
+        .with_to(&["list@example.com"])
+        .with_subject("Newsletter")
+        .with_date(DateTime::from_utc(2025, 12, 7, 12, 0, 0).unwrap());
+    for (to, subject) in [
+        ("alice@example.com", "For Alice"),
+        ("bob@example.com", "For Bob"),
+    ] {
+        let recipients = [Recipient::new(to)];
+        let to = [to];
+        let overrides = Overrides::new().with_to(&to).with_subject(subject);
+        smtp.send_message_with(
+            &Envelope::new("news@example.com", &recipients),
+            &message,
+            &overrides,
+        )
+        .await
+        .unwrap();
+    }
+    // line breaks in overrides are rejected like those in the message
+    let overrides = Overrides::new().with_to(&["eve@example.com\r\nBcc: x@example.com"]);
+    let recipients = [Recipient::new("eve@example.com")];
+    let envelope = Envelope::new("news@example.com", &recipients);
+    assert!(matches!(
+        smtp.send_message_with(&envelope, &message, &overrides)
+            .await,
+        Err(Error::ProtocolError(ProtocolError::InvalidHeader))
+    ));
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert!(written.contains("To: alice@example.com\r\nSubject: For Alice\r\n"));
+    assert!(written.contains("To: bob@example.com\r\nSubject: For Bob\r\n"));
+    assert!(!written.contains("list@example.com"));
+    assert!(!written.contains("Newsletter"));
+}
+
+#[tokio::test]
+async fn test_send_message_dated_by_clock() {
+    use simple_smtp::{
+        envelope::{Envelope, Recipient},
+        message::{DateTime, Message, MockClock},
+    };
+
+    let mut mock = mock_with_ehlo();
+    for _ in 0..2 {
+        mock.queue_line("250 OK"); // MAIL FROM
+        mock.queue_line("250 OK"); // RCPT TO
+        mock.queue_line("354 Go ahead");
+        mock.queue_line("250 Queued");
+    }
+
+    let clock = MockClock::new(DateTime::from_utc(2025, 12, 7, 12, 0, 0).unwrap());
+    // a session with a clock can still be moved to another task
+    fn assert_sync<T: Sync>(_: &T) {}
+    assert_sync(&clock);
+    let mut smtp = Smtp::new(mock);
+    smtp.set_clock(&clock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let recipients = [Recipient::new("bob@example.com")];
+    let envelope = Envelope::new("alice@example.com", &recipients);
+    let message = Message::new("alice@example.com").with_subject("hi");
+    smtp.send_message(&envelope, &message).await.unwrap();
+    clock.advance(24 * 60 * 60);
+    // a date of its own wins
+    let dated = message.with_date(DateTime::from_utc(2020, 1, 1, 0, 0, 0).unwrap());
+    smtp.send_message(&envelope, &dated).await.unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert!(written.contains("Date: Sun, 07 Dec 2025 12:00:00 +0000\r\n"));
+    assert!(written.contains("Date: Wed, 01 Jan 2020 00:00:00 +0000\r\n"));
+}
+
+#[tokio::test]
+async fn test_send_message_to_mailboxes() {
+    use simple_smtp::{
+        address::{AddressList, Mailbox},
+        envelope::{Envelope, Recipient},
+        message::{DateTime, Message},
+    };
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let recipients = [Recipient::new("bob@example.com")];
+    let to = [
+        Mailbox::parse("Bob (work) <bob@example.com>").unwrap(),
+        Mailbox::parse("\"Doe, John\" <john@example.com>").unwrap(),
+        Mailbox::parse("\"Zoë\" <zoe@example.com>").unwrap(),
+        Mailbox::parse("carol@example.com").unwrap(),
+    ];
+    let message = Message::new("alice@example.com")
+        .with_to(&to)
+        .with_cc(
+            AddressList::parse(
+                "Dave <dave@example.com>, (none) erin@example.com, Team: frank@example.com, \
+                 Grace <grace@example.com>;, undisclosed-recipients:;",
+            )
+            .unwrap(),
+        )
+        .with_date(DateTime::from_utc(2025, 12, 7, 12, 0, 0).unwrap());
+    smtp.send_message(&Envelope::new("alice@example.com", &recipients), &message)
+        .await
+        .unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert!(written.contains(
+        "To: Bob <bob@example.com>, \"Doe, John\" <john@example.com>,\r\n \
+         =?UTF-8?B?Wm/Dqw==?= <zoe@example.com>, carol@example.com\r\n"
+    ));
+    assert!(written.contains(
+        "Cc: Dave <dave@example.com>, erin@example.com, Team: frank@example.com, \
+         Grace <grace@example.com>;, undisclosed-recipients:;\r\n"
+    ));
+}
+
+#[tokio::test]
+async fn test_send_signed_message() {
+    use simple_smtp::{
+        canonicalization::Canonicalization,
+        envelope::{Envelope, Recipient},
+        message::{DateTime, Message, Signer},
+    };
+
+    // records what it is given, and signs with a fixed value
+    #[derive(Default)]
+    struct Recorder {
+        headers: Vec<(String, Vec<u8>)>,
+        body: Vec<u8>,
+    }
+
+    impl Signer for Recorder {
+        fn header_canonicalization(&self) -> Canonicalization {
+            Canonicalization::Relaxed
+        }
+
+        fn body_canonicalization(&self) -> Canonicalization {
+            Canonicalization::Simple
+        }
+
+        fn header(&mut self, name: &str, field: &[u8]) {
+            self.headers.push((name.into(), field.to_vec()));
+        }
+
+        fn body(&mut self, chunk: &[u8]) {
+            self.body.extend_from_slice(chunk);
+        }
+
+        fn signature(&mut self) -> Vec<u8> {
+            b"X-Signature: signed\r\n".to_vec()
+        }
+    }
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let recipients = [Recipient::new("bob@example.com")];
+    let subject = "a subject long enough to be folded onto a second line of the header";
+    let message = Message::new("alice@example.com")
+        .with_to(&["bob@example.com"])
+        .with_subject(subject)
+        .with_date(DateTime::from_utc(2025, 12, 7, 12, 0, 0).unwrap())
+        .with_text_body(".hidden\r\n\r\n\r\n");
+    let mut signer = Recorder::default();
+    smtp.send_signed_message(
+        &Envelope::new("alice@example.com", &recipients),
+        &message,
+        &mut signer,
+    )
+    .await
+    .unwrap();
+
+    let names: Vec<_> = signer
+        .headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(names[..4], ["Date", "From", "To", "Subject"]);
+    // unfolded by the relaxed canonicalization
+    let expected = format!("subject:{subject}\r\n");
+    assert_eq!(signer.headers[3].1, expected.as_bytes());
+    // not dot-stuffed, and without the empty lines at the end
+    assert_eq!(signer.body, b".hidden\r\n");
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    let data = written.split_once("DATA\r\n").unwrap().1;
+    assert!(data.starts_with("X-Signature: signed\r\nDate: "));
+    assert!(data.contains("\r\n\r\n..hidden\r\n"));
+}
+
+#[tokio::test]
+async fn test_send_message_folds_long_headers() {
+    use simple_smtp::{
+        envelope::{Envelope, Recipient},
+        message::{DateTime, Message},
+    };
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let recipients = [Recipient::new("bob@example.com")];
+    let to: Vec<String> = (0..20)
+        .map(|idx| format!("Recipient {idx} <recipient-{idx}@example.com>"))
+        .collect();
+    let to: Vec<&str> = to.iter().map(String::as_str).collect();
+    let subject = "a subject which goes on and on, much longer than the recommended line length";
+    let message = Message::new("alice@example.com")
+        .with_to(to.as_slice())
+        .with_subject(subject)
+        .with_date(DateTime::from_utc(2025, 12, 7, 12, 0, 0).unwrap());
+    smtp.send_message(&Envelope::new("alice@example.com", &recipients), &message)
+        .await
+        .unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    let data = written.split_once("DATA\r\n").unwrap().1;
+    let head = data.split_once("\r\n\r\n").unwrap().0;
+    for line in head.split("\r\n") {
+        assert!(line.len() <= 78, "{line}");
+    }
+    // unfolding restores the original values
+    let unfolded = head.replace("\r\n ", " ");
+    assert!(unfolded.contains(&format!("To: {}\r\n", to.join(", "))));
+    assert!(unfolded.contains(&format!("Subject: {subject}\r\n")));
+    // folds are placed after the commas
+    assert!(head.contains(">,\r\n Recipient"));
+}
+
+#[tokio::test]
+async fn test_send_message_alternative() {
+    use simple_smtp::{
+        envelope::{Envelope, Recipient},
+        message::{DateTime, Message},
+    };
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let recipients = [Recipient::new("bob@example.com")];
+    let message = Message::new("Alice <alice@example.com>")
+        .with_to(&["Bob <bob@example.com>", "carol@example.com"])
+        .with_subject("Lunch")
+        .with_date(DateTime::from_utc(2025, 12, 7, 12, 0, 0).unwrap())
+        .with_html_body("Grüße\r\n.\r\n", "<p>Grüße</p>");
+    smtp.send_message(&Envelope::new("alice@example.com", &recipients), &message)
+        .await
+        .unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    let data = written.split_once("DATA\r\n").unwrap().1;
+    let (head, body) = data.split_once("\r\n\r\n").unwrap();
+    assert_eq!(
+        head.lines().take(5).collect::<Vec<_>>(),
+        [
+            "Date: Sun, 07 Dec 2025 12:00:00 +0000",
+            "From: Alice <alice@example.com>",
+            "To: Bob <bob@example.com>, carol@example.com",
+            "Subject: Lunch",
+            "MIME-Version: 1.0",
+        ]
+    );
+    let boundary = head
+        .split_once("multipart/alternative; boundary=\"")
+        .unwrap()
+        .1
+        .trim_end_matches('"');
+    assert!(boundary.starts_with("=_"));
+    let parts: Vec<&str> = body
+        .strip_prefix(&format!("--{boundary}\r\n"))
+        .unwrap()
+        .strip_suffix(&format!("\r\n--{boundary}--\r\n.\r\n"))
+        .unwrap()
+        .split(&format!("\r\n--{boundary}\r\n"))
+        .collect();
+    assert_eq!(
+        parts,
+        [
+            "Content-Type: text/plain; charset=utf-8\r\n\
+             Content-Transfer-Encoding: quoted-printable\r\n\r\n\
+             Gr=C3=BC=C3=9Fe\r\n..\r\n",
+            "Content-Type: text/html; charset=utf-8\r\n\
+             Content-Transfer-Encoding: quoted-printable\r\n\r\n\
+             <p>Gr=C3=BC=C3=9Fe</p>",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_send_message_custom_headers() {
+    use simple_smtp::{
+        ProtocolError,
+        envelope::{Envelope, Recipient},
+        message::{DateTime, MAX_HEADERS, Message},
+    };
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let recipients = [Recipient::new("bob@example.com")];
+    let envelope = Envelope::new("alice@example.com", &recipients);
+    let message = Message::new("alice@example.com")
+        .with_to(&["bob@example.com"])
+        .with_date(DateTime::from_utc(2025, 12, 7, 12, 0, 0).unwrap())
+        .with_header("Auto-Submitted", "auto-generated")
+        .with_header("List-Unsubscribe", "<mailto:unsubscribe@example.com>");
+
+    for invalid in [
+        message.with_header("X-Evil", "x\r\nBcc: eve@example.com"),
+        message.with_header("X Spaced", "value"),
+        message.with_header("", "value"),
+        message.with_header("content-type", "text/html"),
+        // one more than fits
+        (0..MAX_HEADERS - 1).fold(message, |message, _| message.with_header("X-Tag", "a")),
+    ] {
+        assert!(matches!(
+            smtp.send_message(&envelope, &invalid).await,
+            Err(Error::ProtocolError(ProtocolError::InvalidHeader))
+        ));
+    }
+    smtp.send_message(&envelope, &message).await.unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    // the invalid messages were refused before the transaction started
+    assert_eq!(written.matches("MAIL FROM").count(), 1);
+    let data = written.split_once("DATA\r\n").unwrap().1;
+    let head = data.split_once("\r\n\r\n").unwrap().0;
+    assert!(head.contains(
+        "To: bob@example.com\r\n\
+         Auto-Submitted: auto-generated\r\n\
+         List-Unsubscribe: <mailto:unsubscribe@example.com>\r\n\
+         MIME-Version: 1.0\r\n"
+    ));
+}
+
+#[tokio::test]
+async fn test_send_message_prefolded_headers() {
+    use simple_smtp::{
+        ProtocolError,
+        envelope::{Envelope, Recipient},
+        message::{DateTime, HeaderLineBreaks, Message},
+    };
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
 
     let mut smtp = Smtp::new(mock);
-    let result = smtp.ready().await;
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let recipients = [Recipient::new("bob@example.com")];
+    let envelope = Envelope::new("alice@example.com", &recipients);
+    let references = "<1@example.com>\r\n <2@example.com>\r\n\t<3@example.com>";
+    let message = Message::new("alice@example.com")
+        .with_date(DateTime::from_utc(2025, 12, 7, 12, 0, 0).unwrap())
+        .with_header("References", references);
+    // strict by default
+    assert!(matches!(
+        smtp.send_message(&envelope, &message).await,
+        Err(Error::ProtocolError(ProtocolError::InvalidHeader))
+    ));
+
+    let message = message.with_header_line_breaks(HeaderLineBreaks::AllowFolded);
+    for invalid in [
+        "a\r\nBcc: eve@example.com",
+        "a\nb",
+        "a\r b",
+        "a\r\n \r\n b",
+        "a\r\n ",
+        "\u{fc}\r\n b",
+    ] {
+        assert!(matches!(
+            smtp.send_message(&envelope, &message.with_header("X-Folded", invalid))
+                .await,
+            Err(Error::ProtocolError(ProtocolError::InvalidHeader))
+        ));
+    }
+    smtp.send_message(&envelope, &message).await.unwrap();
 
-    assert!(result.is_err(), "IO error should propagate");
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert_eq!(written.matches("MAIL FROM").count(), 1);
+    assert!(written.contains(&format!("\r\nReferences: {references}\r\nMIME-Version")));
 }
 
 #[tokio::test]
-async fn test_wrong_greeting_code() {
-    // Server is temporarily unavailable
+async fn test_send_message_sender_and_resent() {
+    use simple_smtp::{
+        ProtocolError,
+        envelope::{Envelope, Recipient},
+        message::{DateTime, Message, Resent},
+    };
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let recipients = [Recipient::new("carol@example.com")];
+    let envelope = Envelope::new("bob@example.com", &recipients);
+    let resent = Resent::new(
+        DateTime::from_utc(2025, 12, 8, 9, 30, 0).unwrap(),
+        "Bob <bob@example.com>",
+    )
+    .with_to(&["carol@example.com"])
+    .with_message_id("<5678@example.com>");
+    let message = Message::new("alice@example.com")
+        .with_sender("secretary@example.com")
+        .with_to(&["bob@example.com"])
+        .with_date(DateTime::from_utc(2025, 12, 7, 12, 0, 0).unwrap())
+        .with_resent(resent);
+
+    for invalid in [
+        message.with_sender("alice@example.com"),
+        message.with_to(&["bob@example.com\r\nBcc: eve@example.com"]),
+        message.with_resent(resent.with_to(&["carol@example.com\n"])),
+        message.with_header("Return-Path", "<alice@example.com>"),
+        message.with_header("resent-cc", "eve@example.com"),
+    ] {
+        assert!(matches!(
+            smtp.send_message(&envelope, &invalid).await,
+            Err(Error::ProtocolError(ProtocolError::InvalidHeader))
+        ));
+    }
+    smtp.send_message(&envelope, &message).await.unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    assert_eq!(written.matches("MAIL FROM").count(), 1);
+    let data = written.split_once("DATA\r\n").unwrap().1;
+    let head = data.split_once("\r\n\r\n").unwrap().0;
+    assert_eq!(
+        head.lines().take(8).collect::<Vec<_>>(),
+        [
+            "Resent-Date: Mon, 08 Dec 2025 09:30:00 +0000",
+            "Resent-From: Bob <bob@example.com>",
+            "Resent-To: carol@example.com",
+            "Resent-Message-ID: <5678@example.com>",
+            "Date: Sun, 07 Dec 2025 12:00:00 +0000",
+            "From: alice@example.com",
+            "Sender: secretary@example.com",
+            "To: bob@example.com",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_send_message_early_reply() {
+    use simple_smtp::{
+        ProtocolError,
+        envelope::{Envelope, Recipient},
+        message::Message,
+        smtp::SessionState,
+    };
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_early_line("MIME-Version", "552 5.3.4 Message too big");
+
+    // writes are coalesced in half of the buffer, a small one sends the data in small pieces
+    let mut smtp = Smtp::new_with_buffer(mock, vec![0; 128]);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let recipients = [Recipient::new("bob@example.com")];
+    let content = vec![b'x'; 100_000];
+    let message = Message::new("alice@example.com")
+        .with_to(&["bob@example.com"])
+        .with_attachment("big.bin", "application/octet-stream", &content);
+    let error = smtp
+        .send_message(&Envelope::new("alice@example.com", &recipients), &message)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("552"), "{error}");
+    // the server takes whatever follows for commands, so the session is over
+    assert!(!smtp.is_usable());
+    assert_eq!(smtp.state(), SessionState::Greeted);
+    assert!(matches!(
+        smtp.send_envelope(&Envelope::new("alice@example.com", &recipients), b"hi")
+            .await,
+        Err(Error::ProtocolError(ProtocolError::Interrupted))
+    ));
+    assert!(matches!(
+        smtp.ehlo("client.example.com").await,
+        Err(Error::ProtocolError(ProtocolError::Interrupted))
+    ));
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    // the attachment and the end of data marker were never sent
+    assert!(!written.contains("big.bin"));
+    assert!(written.len() < 10_000);
+    assert!(!written.contains(&"eHh4".repeat(1000)));
+    assert!(!written.ends_with("\r\n.\r\n"));
+}
+
+#[tokio::test]
+async fn test_send_message_with_attachments() {
+    use base64::prelude::*;
+    use simple_smtp::{
+        envelope::{Envelope, Recipient},
+        message::Message,
+    };
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let report: Vec<u8> = (0..=255).cycle().take(1000).collect();
+    let recipients = [Recipient::new("bob@example.com")];
+    let message = Message::new("alice@example.com")
+        .with_to(&["bob@example.com"])
+        .with_text_body("Report attached")
+        .with_attachment("report \"final\".bin", "application/octet-stream", &report)
+        .with_attachment("empty.txt", "text/plain", b"")
+        .with_attachment("Übersicht 100%.pdf", "application/pdf", b"%PDF");
+    smtp.send_message(&Envelope::new("alice@example.com", &recipients), &message)
+        .await
+        .unwrap();
+
+    let (stream, _) = smtp.into_inner();
+    let written = stream.written_str();
+    let data = written.split_once("DATA\r\n").unwrap().1;
+    let boundary = data
+        .split_once("multipart/mixed; boundary=\"")
+        .unwrap()
+        .1
+        .split_once('"')
+        .unwrap()
+        .0;
+    let body = data.split_once("\r\n\r\n").unwrap().1;
+    let parts: Vec<&str> = body
+        .strip_prefix(&format!("--{boundary}\r\n"))
+        .unwrap()
+        .strip_suffix(&format!("\r\n--{boundary}--\r\n.\r\n"))
+        .unwrap()
+        .split(&format!("\r\n--{boundary}\r\n"))
+        .collect();
+    assert_eq!(parts.len(), 4);
+    assert!(parts[0].ends_with("\r\n\r\nReport attached"));
+
+    let (head, encoded) = parts[1].split_once("\r\n\r\n").unwrap();
+    assert_eq!(
+        head,
+        "Content-Type: application/octet-stream\r\n\
+         Content-Disposition: attachment; filename=\"report \\\"final\\\".bin\"\r\n\
+         Content-Transfer-Encoding: base64"
+    );
+    let lines: Vec<&str> = encoded.split("\r\n").collect();
+    assert!(lines[..lines.len() - 1].iter().all(|line| line.len() == 76));
+    assert_eq!(BASE64_STANDARD.decode(lines.concat()).unwrap(), report);
+
+    assert!(parts[2].ends_with("Content-Transfer-Encoding: base64\r\n\r\n"));
+    // RFC 2231 encoding for a filename which isn't ASCII
+    assert!(parts[3].contains(
+        "Content-Disposition: attachment; filename*=UTF-8''%C3%9Cbersicht%20100%25.pdf\r\n"
+    ));
+}
+
+#[tokio::test]
+async fn test_send_message_with_too_many_attachments() {
+    use simple_smtp::{
+        ProtocolError,
+        envelope::{Envelope, Recipient},
+        message::{MAX_ATTACHMENTS, Message},
+    };
+
+    let mut smtp = Smtp::new(mock_with_ehlo());
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let recipients = [Recipient::new("bob@example.com")];
+    let message = (0..=MAX_ATTACHMENTS).fold(
+        Message::new("alice@example.com").with_to(&["bob@example.com"]),
+        |message, _| message.with_attachment("a.txt", "text/plain", b"a"),
+    );
+    assert!(matches!(
+        smtp.send_message(&Envelope::new("alice@example.com", &recipients), &message)
+            .await,
+        Err(Error::ProtocolError(ProtocolError::TooManyAttachments))
+    ));
+    // refused before the transaction started
+    let (stream, _) = smtp.into_inner();
+    assert!(!stream.written_str().contains("MAIL FROM"));
+}
+
+#[tokio::test]
+async fn test_send_message_refuses_attachment_line_breaks() {
+    use simple_smtp::{
+        ProtocolError,
+        envelope::{Envelope, Recipient},
+        message::Message,
+    };
+
+    let mut smtp = Smtp::new(mock_with_ehlo());
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let recipients = [Recipient::new("bob@example.com")];
+    let envelope = Envelope::new("alice@example.com", &recipients);
+    for (filename, content_type) in [
+        ("a.txt\r\nBcc: eve@example.com", "text/plain"),
+        ("a.txt", "text/plain\nX-Injected: yes"),
+    ] {
+        let message = Message::new("alice@example.com")
+            .with_to(&["bob@example.com"])
+            .with_attachment(filename, content_type, b"a");
+        assert!(matches!(
+            smtp.send_message(&envelope, &message).await,
+            Err(Error::ProtocolError(ProtocolError::InvalidHeader))
+        ));
+    }
+    let (stream, _) = smtp.into_inner();
+    assert!(!stream.written_str().contains("MAIL FROM"));
+}
+
+#[tokio::test]
+async fn test_reply_timeout() {
+    use std::time::Duration;
+
+    use simple_smtp::{
+        integrations::tokio::{TokioIo, TokioTimer},
+        smtp::Timeouts,
+    };
+
+    // the server end stays open, but never replies
+    let (client, _server) = tokio::io::duplex(64);
+    let mut smtp = Smtp::new(TokioIo(client));
+    smtp.set_owned_timer(TokioTimer::new());
+    smtp.set_timeouts(
+        Timeouts::uniform(Duration::from_secs(60 * 60)).with_greeting(Duration::from_millis(20)),
+    );
+    let error = match smtp.ready().await {
+        Ok(_) => panic!("a silent server is ready"),
+        Err(error) => error,
+    };
+    assert!(matches!(error, Error::Timeout));
+    assert!(!error.is_session_usable());
+}
+
+#[tokio::test]
+async fn test_report_includes_reply_history() {
+    use simple_smtp::smtp::ReplyHistory;
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // NOOP
+    mock.queue_line("421 Closing connection");
+
+    let mut smtp = Smtp::new(mock);
+    smtp.set_reply_history(ReplyHistory::with_capacity(2));
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let _ = smtp.noop().await.unwrap();
+    let error = match smtp.noop().await {
+        Ok(_) => panic!("NOOP succeeded after 421"),
+        Err(error) => error,
+    };
+
+    assert_eq!(
+        smtp.report(&error).to_string(),
+        "Server rejected command: 421 Closing connection\n\
+         recent replies:\n  250 OK\n  421 Closing connection"
+    );
+}
+
+#[tokio::test]
+async fn test_dropped_send_marks_session_unusable() {
+    use std::time::Duration;
+
+    use simple_smtp::{ProtocolError, integrations::tokio::TokioIo};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let (client, server) = tokio::io::duplex(4096);
+    // replies to every command, but never to the end of the data
+    tokio::spawn(async move {
+        let (read, mut write) = tokio::io::split(server);
+        let mut lines = BufReader::new(read).lines();
+        write.write_all(b"220 ready\r\n").await.unwrap();
+        for reply in ["250 mail.example.com", "250 OK", "250 OK", "354 Go ahead"] {
+            lines.next_line().await.unwrap();
+            write
+                .write_all(format!("{reply}\r\n").as_bytes())
+                .await
+                .unwrap();
+        }
+        while let Ok(Some(_)) = lines.next_line().await {}
+    });
+    let mut smtp = Smtp::new(TokioIo(client));
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    assert!(smtp.is_usable());
+
+    let send = smtp.send_mail("a@example.com", ["b@example.com"].iter(), b"hi\r\n");
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), send)
+            .await
+            .is_err()
+    );
+    assert!(!smtp.is_usable());
+    let error = match smtp.noop().await {
+        Ok(_) => panic!("NOOP on an interrupted session"),
+        Err(error) => error,
+    };
+    assert!(matches!(
+        error,
+        Error::ProtocolError(ProtocolError::Interrupted)
+    ));
+    assert!(!error.is_session_usable());
+    smtp.fast_quit().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_long_ehlo_response_grows_owned_buffer() {
+    let long_lines: Vec<String> = (0..100).map(|i| format!("X-EXTENSION-{i:03}")).collect();
+    let mut lines = vec!["mail.example.com"];
+    lines.extend(long_lines.iter().map(String::as_str));
+    lines.push("SIZE 10485760");
+
+    let mut mock = mock_with_greeting();
+    mock.queue_multiline(250, &lines);
+    let mut smtp = Smtp::new(mock);
+    let _ = smtp.ready().await.unwrap();
+    let ehlo = smtp.ehlo("client.example.com").await.unwrap();
+    assert_eq!(ehlo.lines().count(), 102);
+    assert_eq!(smtp.capabilities().max_size(), Some(10485760));
+
+    // a borrowed buffer can't grow, the rest of the reply is skipped instead
+    let mut mock = mock_with_greeting();
+    mock.queue_multiline(250, &lines);
+    mock.queue_line("250 OK");
+    let mut buffer = [0; 256];
+    let mut smtp = Smtp::new_with_buffer(mock, &mut buffer[..]);
+    let _ = smtp.ready().await.unwrap();
+    let error = match smtp.ehlo("client.example.com").await {
+        Ok(_) => panic!("EHLO response fits into the buffer"),
+        Err(error) => error,
+    };
+    let reply_len: usize = lines.iter().map(|line| line.len() + 6).sum();
+    assert!(matches!(
+        error,
+        Error::MalformedError(MalformedError::ReplyTooLarge { needed, capacity: 256 })
+            if needed == reply_len
+    ));
+    assert!(error.is_session_usable());
+    assert_eq!(smtp.noop().await.unwrap().code().as_u16(), 250);
+}
+
+#[cfg(feature = "futures-io")]
+#[tokio::test]
+async fn test_futures_io_adapter() {
+    use simple_smtp::integrations::FuturesIo;
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK");
+    let mut smtp = Smtp::new(FuturesIo(mock));
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    assert_eq!(smtp.noop().await.unwrap().code().as_u16(), 250);
+
+    let (FuturesIo(mock), _) = smtp.into_inner();
+    assert!(
+        mock.written_str()
+            .ends_with("EHLO client.example.com\r\nNOOP\r\n")
+    );
+    assert!(mock.writes().all(|write| write.len() <= 8));
+    assert!(!mock.flushes().is_empty());
+
     let mut mock = MockStream::new();
-    mock.queue_line("421 Service not available");
+    mock.inject_read_error(MockError::new("connection reset"));
+    let mut smtp = Smtp::new(FuturesIo(mock));
+    let error = match smtp.ready().await {
+        Ok(_) => panic!("read the greeting from a reset connection"),
+        Err(error) => error,
+    };
+    assert!(matches!(error, Error::IoError(_)));
+    assert_eq!(error.to_string(), "IO Error: MockError: connection reset");
+}
+
+#[cfg(feature = "embedded-io")]
+#[tokio::test]
+async fn test_embedded_io_adapter() {
+    use simple_smtp::integrations::{EmbeddedIo, EmbeddedIoError};
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK");
+    let mut smtp = Smtp::new(EmbeddedIo(mock));
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    assert_eq!(smtp.noop().await.unwrap().code().as_u16(), 250);
+    let (EmbeddedIo(mock), _) = smtp.into_inner();
+    assert!(
+        mock.written_str()
+            .ends_with("EHLO client.example.com\r\nNOOP\r\n")
+    );
+    assert!(mock.writes().all(|write| write.len() <= 8));
+    assert!(!mock.flushes().is_empty());
+
+    let mut mock = MockStream::new();
+    mock.inject_read_error(MockError::new("connection reset"));
+    let mut smtp = Smtp::new(EmbeddedIo(mock));
+    let error = match smtp.ready().await {
+        Ok(_) => panic!("read the greeting from a reset connection"),
+        Err(error) => error,
+    };
+    assert!(matches!(error, Error::IoError(EmbeddedIoError(_))));
+    assert_eq!(error.to_string(), "IO Error: embedded-io error: Other");
+}
+
+#[tokio::test]
+async fn test_strict_messages() {
+    use simple_smtp::{
+        ProtocolError,
+        envelope::{Envelope, Recipient},
+        message::{DateTime, Lint, Message},
+    };
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
 
     let mut smtp = Smtp::new(mock);
-    let result = smtp.ready().await;
+    smtp.set_strict_messages(true);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let recipients = [Recipient::new("bob@example.com")];
+    let message = Message::new("Alice <alice@example.com>")
+        .with_to(&["bob@example.com"])
+        .with_date(DateTime::from_utc(2025, 12, 7, 12, 0, 0).unwrap())
+        .with_message_id("<1234@example.com>")
+        .with_header("Precedence", "bulk");
+
+    let envelope = Envelope::new("bounces@mail.example.com", &recipients);
+    let error = match smtp.send_message(&envelope, &message).await {
+        Ok(()) => panic!("sent a bulk message without List-Unsubscribe"),
+        Err(error) => error,
+    };
+    assert!(matches!(
+        error,
+        Error::ProtocolError(ProtocolError::MessageLint(Lint::MissingUnsubscribe))
+    ));
+    assert!(error.is_session_usable());
+
+    let message = message.with_header("List-Unsubscribe", "<mailto:unsubscribe@example.com>");
+    let envelope = Envelope::new("alice@example.org", &recipients);
+    assert!(matches!(
+        smtp.send_message(&envelope, &message).await,
+        Err(Error::ProtocolError(ProtocolError::MessageLint(
+            Lint::FromMismatch
+        )))
+    ));
+    assert!(!smtp.into_inner().0.contains_command("MAIL FROM"));
 
-    assert!(result.is_err(), "ready() should fail on non-220 code");
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK"); // RCPT TO
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 Queued");
+    let mut smtp = Smtp::new(mock);
+    smtp.set_strict_messages(true);
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    let envelope = Envelope::new("bounces@mail.example.com", &recipients);
+    smtp.send_message(&envelope, &message).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_write_all_loops_short_writes() {
+    use simple_smtp::{PartialWrite, WriteAll, WriteAllError};
+
+    // a transport which accepts at most `limit` bytes per write, like a socket with a full
+    // send buffer
+    struct Narrow {
+        mock: MockStream,
+        limit: usize,
+    }
+
+    impl PartialWrite for Narrow {
+        type Error = MockError;
+
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            ReadWrite::read(&mut self.mock, buf).await
+        }
+
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            let len = buf.len().min(self.limit);
+            self.mock.write_single(&buf[..len]).await?;
+            Ok(len)
+        }
+    }
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK");
+    let mut smtp = Smtp::new(WriteAll(Narrow { mock, limit: 3 }));
+    let _ = smtp.ready().await.unwrap();
+    let _ = smtp.ehlo("client.example.com").await.unwrap();
+    assert_eq!(smtp.noop().await.unwrap().code().as_u16(), 250);
+    let (WriteAll(narrow), _) = smtp.into_inner();
+    assert!(
+        narrow
+            .mock
+            .written_str()
+            .ends_with("EHLO client.example.com\r\nNOOP\r\n")
+    );
+    assert!(narrow.mock.writes().all(|write| write.len() <= 3));
+
+    // a transport which stops accepting data fails instead of dropping the rest
+    let mut smtp = Smtp::new(WriteAll(Narrow {
+        mock: mock_with_greeting(),
+        limit: 0,
+    }));
+    let _ = smtp.ready().await.unwrap();
+    let error = match smtp.noop().await {
+        Ok(_) => panic!("NOOP was written to a stream accepting nothing"),
+        Err(error) => error,
+    };
+    assert!(matches!(error, Error::IoError(WriteAllError::WriteZero)));
+    assert!(!error.is_session_usable());
+}
+
+#[test]
+fn test_blocking_session() {
+    use std::io::{self, Read, Write};
+
+    use simple_smtp::{
+        envelope::{Envelope, Recipient},
+        integrations::blocking::BlockingSmtp,
+        message::{DateTime, Message},
+    };
+
+    // replays a script of replies, one per read, and records what was written, like a std
+    // TcpStream would
+    struct Scripted {
+        replies: VecDeque<&'static str>,
+        written: Vec<u8>,
+    }
+
+    impl Read for Scripted {
+        fn read(&mut self, mut buf: &mut [u8]) -> io::Result<usize> {
+            match self.replies.pop_front() {
+                Some(reply) => buf.write(format!("{reply}\r\n").as_bytes()),
+                None => Ok(0),
+            }
+        }
+    }
+
+    impl Write for Scripted {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let replies = [
+        "220 mail.example.com ESMTP ready",
+        "250-mail.example.com\r\n250 8BITMIME",
+        "250 OK",
+        "250 OK",
+        "354 Go ahead",
+        "250 Queued",
+        "221 Bye",
+    ];
+    let mut smtp = BlockingSmtp::new(Scripted {
+        replies: replies.into(),
+        written: Vec::new(),
+    });
+    smtp.ready().unwrap();
+    smtp.ehlo("client.example.com").unwrap();
+    assert!(
+        smtp.session()
+            .capabilities()
+            .supports(simple_smtp::smtp::Extensions::EIGHTBITMIME)
+    );
+    let recipients = [Recipient::new("bob@example.com")];
+    let message = Message::new("alice@example.com")
+        .with_to(&["bob@example.com"])
+        .with_date(DateTime::from_utc(2025, 12, 7, 12, 0, 0).unwrap())
+        .with_text_body("Hi Bob!");
+    smtp.send_message(&Envelope::new("alice@example.com", &recipients), &message)
+        .unwrap();
+    let code = smtp.run(async |smtp| smtp.quit().await.map(|reply| reply.code().as_u16()));
+    assert_eq!(code.unwrap(), 221);
+
+    let written = String::from_utf8(smtp.into_inner().written).unwrap();
+    assert!(written.starts_with("EHLO client.example.com\r\nMAIL FROM:<alice@example.com>"));
+    assert!(written.ends_with("Hi Bob!\r\n.\r\nQUIT\r\n"));
+}
+
+#[cfg(all(feature = "tokio-uring", target_os = "linux"))]
+#[test]
+fn test_tokio_uring_session() {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use simple_smtp::integrations::tokio_uring::UringIo;
+
+    // a real socket, as io_uring can't drive the mock
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        stream.write_all(b"220 ready\r\n").unwrap();
+        let mut commands = Vec::new();
+        for reply in ["250-mail.example.com\r\n250 SIZE 1000\r\n", "221 Bye\r\n"] {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            commands.push(line);
+            stream.write_all(reply.as_bytes()).unwrap();
+        }
+        commands
+    });
+
+    tokio_uring::start(async {
+        let stream = tokio_uring::net::TcpStream::connect(addr).await.unwrap();
+        let mut smtp = Smtp::new(UringIo::new(stream));
+        let _ = smtp.ready().await.unwrap();
+        let _ = smtp.ehlo("client.example.com").await.unwrap();
+        assert_eq!(smtp.capabilities().max_size(), Some(1000));
+        assert_eq!(smtp.quit().await.unwrap().code().as_u16(), 221);
+    });
+    assert_eq!(
+        server.join().unwrap(),
+        ["EHLO client.example.com\r\n", "QUIT\r\n"]
+    );
+}
+
+#[tokio::test]
+async fn test_owned_io_adapter() {
+    use simple_smtp::OwnedIo;
+
+    let mut mock = mock_with_ehlo();
+    mock.queue_line("250 OK");
+    // smaller than the replies and commands, so they take several reads and writes
+    let mut smtp = Smtp::new(OwnedIo::new_with_buffer(mock, vec![0; 16]));
+    let _ = smtp.ready().await.unwrap();
+    let ehlo = smtp.ehlo("client.example.com").await.unwrap();
+    assert!(ehlo.supports(simple_smtp::smtp::Extensions::StartTls));
+    assert_eq!(smtp.noop().await.unwrap().code().as_u16(), 250);
+
+    let mock = smtp.into_inner().0.into_inner();
+    assert!(
+        mock.written_str()
+            .ends_with("EHLO client.example.com\r\nNOOP\r\n")
+    );
+    let lens: Vec<usize> = mock.writes().map(<[u8]>::len).collect();
+    assert_eq!(lens, [16, 9, 6]);
+    assert!(!mock.flushes().is_empty());
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+// Tests: LMTP
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[tokio::test]
+async fn test_lmtp_reply_per_recipient() {
+    use simple_smtp::{envelope::Envelope, smtp::Protocol};
+
+    let mut mock = mock_with_greeting();
+    mock.queue_multiline(250, &["mail.example.com", "PIPELINING"]);
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK");
+    mock.queue_line("250 OK");
+    mock.queue_line("354 Go ahead");
+    // both replies to the data arrive at once
+    mock.queue_response("250 2.0.0 Delivered\r\n452 4.2.2 Mailbox full\r\n");
+    mock.queue_line("250 OK"); // NOOP
+
+    let mut smtp = Smtp::new(mock);
+    smtp.set_protocol(Protocol::Lmtp);
+    smtp.ready().await.unwrap();
+    smtp.ehlo("client.local").await.unwrap();
+
+    let to = ["you@example.com".into(), "full@example.com".into()];
+    let error = smtp
+        .send_envelope(&Envelope::new("me@local", &to), b"hi")
+        .await
+        .unwrap_err();
+    assert!(matches!(error, Error::ServerRejected { code, .. } if code.as_u16() == 452));
+    // every reply was read, the session is in step with the server
+    assert_eq!(smtp.noop().await.unwrap().code().as_u16(), 250);
+
+    let (stream, _) = smtp.into_inner();
+    assert!(stream.written_str().contains("LHLO client.local\r\n"));
+    assert!(!stream.contains_command("EHLO"));
+}
+
+#[tokio::test]
+async fn test_lmtp_send_envelope_per_recipient() {
+    use simple_smtp::{
+        envelope::{Envelope, Recipient},
+        smtp::Protocol,
+    };
+
+    let mut mock = mock_with_greeting();
+    mock.queue_line("250 mail.example.com");
+    mock.queue_line("250 OK"); // MAIL FROM
+    mock.queue_line("250 OK");
+    mock.queue_line("550 5.1.1 No such user");
+    mock.queue_line("250 OK");
+    mock.queue_line("354 Go ahead");
+    mock.queue_line("250 2.0.0 Delivered");
+    mock.queue_line("452 4.2.2 Mailbox full");
+
+    let mut smtp = Smtp::new(mock);
+    smtp.set_protocol(Protocol::Lmtp);
+    smtp.ready().await.unwrap();
+    smtp.ehlo("client.local").await.unwrap();
+
+    let recipients = [
+        Recipient::new("you@example.com"),
+        Recipient::new("nobody@example.com"),
+        Recipient::new("full@example.com"),
+    ];
+    let results = smtp
+        .send_envelope_per_recipient(&Envelope::new("me@local", &recipients), b"hi")
+        .await
+        .unwrap();
+    let codes: Vec<_> = results
+        .iter()
+        .map(|result| match result {
+            Ok(()) => 250,
+            Err(Error::ServerRejected { code, .. }) => code.as_u16(),
+            Err(error) => panic!("unexpected error: {error}"),
+        })
+        .collect();
+    assert_eq!(codes, [250, 550, 452]);
+}
+
+#[tokio::test]
+async fn test_lmtp_has_no_helo_fallback() {
+    use simple_smtp::smtp::Protocol;
+
+    let mut mock = mock_with_greeting();
+    mock.queue_line("500 Unknown command");
+
+    let mut smtp = Smtp::new(mock);
+    smtp.set_protocol(Protocol::Lmtp);
+    smtp.ready().await.unwrap();
+    assert!(smtp.hello("client.local").await.is_err());
+
+    let (stream, _) = smtp.into_inner();
+    assert!(!stream.contains_command("HELO"));
 }
@@ -0,0 +1,131 @@
+//! Tests for parsing, fetching and enforcing MTA-STS policies.
+#![cfg(feature = "mta-sts")]
+
+use simple_smtp::{
+    integrations::tokio::mta_sts::{Mode, Policy, PolicyCache, PolicyViolation, request_policy},
+    smtp::capabilities::Capabilities,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+const POLICY: &str = "version: STSv1\r\nmode: enforce\r\nmx: mail.example.com\r\nmx: *.example.net\r\nmax_age: 604800\r\n";
+
+// answers the policy request with `response`, returning the request
+async fn fetch(response: &'static str) -> (std::io::Result<Policy>, String) {
+    let (client, mut server) = tokio::io::duplex(4096);
+    let server = tokio::spawn(async move {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let n = server.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+        server.write_all(response.as_bytes()).await.unwrap();
+        String::from_utf8(request).unwrap()
+    });
+    let policy = request_policy(client, "example.com").await;
+    (policy, server.await.unwrap())
+}
+
+#[test]
+fn test_parse_policy() {
+    let policy = Policy::parse(POLICY).unwrap();
+    assert_eq!(policy.mode, Mode::Enforce);
+    assert_eq!(policy.mx, ["mail.example.com", "*.example.net"]);
+    assert_eq!(policy.max_age, 604800);
+
+    // LF line endings, whitespace and unknown keys
+    let policy = Policy::parse("version:STSv1\nmode: none\nmax_age:  86400 \nfoo: bar\n").unwrap();
+    assert_eq!(policy.mode, Mode::None);
+    assert!(policy.mx.is_empty());
+
+    for invalid in [
+        "mode: enforce\nmx: mail.example.com\nmax_age: 86400\n",
+        "version: STSv2\nmode: enforce\nmx: mail.example.com\nmax_age: 86400\n",
+        "version: STSv1\nmode: strict\nmx: mail.example.com\nmax_age: 86400\n",
+        "version: STSv1\nmode: enforce\nmx: mail.example.com\nmax_age: -1\n",
+        "version: STSv1\nmode: enforce\nmx: mail.example.com\nmax_age: 31557601\n",
+        "version: STSv1\nmode: enforce\nmax_age: 86400\n",
+        "version: STSv1\nmode enforce\n",
+    ] {
+        assert!(Policy::parse(invalid).is_err(), "{invalid:?}");
+    }
+}
+
+#[test]
+fn test_matches_mx() {
+    let policy = Policy::parse(POLICY).unwrap();
+    assert!(policy.matches_mx("mail.example.com"));
+    assert!(policy.matches_mx("MAIL.example.com."));
+    assert!(policy.matches_mx("mx1.example.net"));
+    // the wildcard matches exactly one label
+    assert!(!policy.matches_mx("example.net"));
+    assert!(!policy.matches_mx("a.b.example.net"));
+    assert!(!policy.matches_mx("mail.example.org"));
+}
+
+#[test]
+fn test_enforce() {
+    let mut policy = Policy::parse(POLICY).unwrap();
+    assert_eq!(
+        policy.enforce("mx.example.org", &Capabilities::none()),
+        Err(PolicyViolation::MxMismatch("mx.example.org".into()))
+    );
+    assert_eq!(
+        policy.enforce("mail.example.com", &Capabilities::none()),
+        Err(PolicyViolation::StartTlsUnavailable(
+            "mail.example.com".into()
+        ))
+    );
+
+    policy.mode = Mode::Testing;
+    assert_eq!(
+        policy.enforce("mx.example.org", &Capabilities::none()),
+        Ok(())
+    );
+}
+
+#[tokio::test]
+async fn test_request_policy() {
+    let (policy, request) = fetch(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: 69\r\n\r\nversion: STSv1\r\nmode: testing\r\nmx: mail.example.com\r\nmax_age: 86400\r\nignored",
+    )
+    .await;
+    assert!(request.starts_with("GET /.well-known/mta-sts.txt HTTP/1.0\r\n"));
+    assert!(request.contains("\r\nHost: mta-sts.example.com\r\n"));
+    let policy = policy.unwrap();
+    assert_eq!(policy.mode, Mode::Testing);
+    assert_eq!(policy.mx, ["mail.example.com"]);
+}
+
+#[tokio::test]
+async fn test_request_policy_rejects_invalid_responses() {
+    for response in [
+        "HTTP/1.1 301 Moved Permanently\r\nLocation: https://example.com/\r\n\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\nversion: STSv1\r\nmode: none\r\nmax_age: 86400\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 1000\r\n\r\nversion: STSv1\r\n",
+    ] {
+        let (policy, _) = fetch(response).await;
+        assert!(policy.is_err(), "{response:?}");
+    }
+}
+
+#[test]
+fn test_policy_cache() {
+    let cache = PolicyCache::new();
+    assert_eq!(cache.cached("example.com"), None);
+    let policy = Policy::parse(POLICY).unwrap();
+    cache.insert("Example.com", policy.clone());
+    assert_eq!(cache.cached("example.COM"), Some(policy.clone()));
+    cache.remove("example.com");
+    assert_eq!(cache.cached("example.com"), None);
+
+    // expired right away
+    cache.insert(
+        "example.com",
+        Policy {
+            max_age: 0,
+            ..policy
+        },
+    );
+    assert_eq!(cache.cached("example.com"), None);
+}
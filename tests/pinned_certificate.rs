@@ -0,0 +1,61 @@
+//! Tests for pinning the server certificate instead of checking it against a root store.
+#![cfg(all(feature = "tokio", feature = "rustls"))]
+
+mod common;
+
+use std::sync::Arc;
+
+use rustls::pki_types::CertificateDer;
+use simple_smtp::integrations::tokio::{
+    PinnedCertificate, client_config_with_verifier, connect_tls_with_config,
+};
+use tokio::io::{AsyncReadExt, DuplexStream};
+
+const CERT: &[u8] = include_bytes!("data/client-cert.der");
+
+async fn self_signed_server(stream: DuplexStream) -> std::io::Result<()> {
+    let key = include_bytes!("data/client-key.der");
+    common::tls_server(
+        stream,
+        vec![CertificateDer::from(CERT)],
+        key,
+        "220 relay ESMTP\r\n",
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_pinned_certificate_is_accepted() {
+    let (client, server) = tokio::io::duplex(16 * 1024);
+    let server = tokio::spawn(self_signed_server(server));
+    let config = client_config_with_verifier(Arc::new(PinnedCertificate::new(CERT)), None).unwrap();
+    let mut tls = connect_tls_with_config(client, "relay.example.com", config)
+        .await
+        .unwrap();
+    let mut greeting = String::new();
+    tls.0.read_to_string(&mut greeting).await.unwrap();
+    assert_eq!(greeting, "220 relay ESMTP\r\n");
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_other_certificate_is_rejected() {
+    let (client, server) = tokio::io::duplex(16 * 1024);
+    let server = tokio::spawn(self_signed_server(server));
+    let pinned = PinnedCertificate::new(include_bytes!("data/server-cert.der"));
+    let config = client_config_with_verifier(Arc::new(pinned), None).unwrap();
+    let Err(err) = connect_tls_with_config(client, "relay.example.com", config).await else {
+        panic!("the server presented a different certificate");
+    };
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(server.await.unwrap().is_err());
+}
+
+#[test]
+fn test_pem_must_hold_one_certificate() {
+    let pem = include_bytes!("data/ca-cert.pem");
+    assert!(PinnedCertificate::from_pem(pem).is_ok());
+    let twice = [&pem[..], &pem[..]].concat();
+    assert!(PinnedCertificate::from_pem(&twice).is_err());
+    assert!(PinnedCertificate::from_pem(b"").is_err());
+}
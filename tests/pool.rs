@@ -0,0 +1,333 @@
+//! Tests for the session pool of the tokio client.
+#![cfg(feature = "pool")]
+
+mod common;
+
+use std::{
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use simple_smtp::{
+    Error,
+    integrations::tokio::{
+        ClientSession, SmtpClientBuilder, TlsMode,
+        pool::{AccessToken, PoolMetrics, SmtpPool, TokenCache, TokenSource},
+    },
+    smtp::auth::CredentialProvider,
+};
+use tokio::time::Instant;
+
+// accepts every connection and everything sent on it, and counts the connections and NOOPs;
+// the first connection is closed after EHLO, if `close_first` is set
+async fn server(close_first: bool) -> (u16, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+    let (listener, port) = common::listen().await;
+    let connections = Arc::new(AtomicUsize::new(0));
+    let noops = Arc::new(AtomicUsize::new(0));
+    let (accepted, received) = (connections.clone(), noops.clone());
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            let close = accepted.fetch_add(1, Ordering::SeqCst) == 0 && close_first;
+            let received = received.clone();
+            tokio::spawn(common::accept_mail(socket, move |command| {
+                if command == "NOOP" {
+                    received.fetch_add(1, Ordering::SeqCst);
+                }
+                !close
+            }));
+        }
+    });
+    (port, connections, noops)
+}
+
+// moves tokio's clock forward, which the pool measures idle times and token expiry with,
+// without waiting
+async fn skip(duration: Duration) {
+    tokio::time::pause();
+    tokio::time::advance(duration).await;
+    tokio::time::resume();
+}
+
+async fn connect(port: u16) -> Result<ClientSession, Error<io::Error>> {
+    SmtpClientBuilder::new("127.0.0.1")
+        .with_port(port)
+        .with_tls(TlsMode::None)
+        .with_ehlo_domain("client.example.org")
+        .connect()
+        .await
+}
+
+#[tokio::test]
+async fn test_sessions_are_reused() {
+    let (port, connections, noops) = server(false).await;
+    let pool = SmtpPool::new(move || connect(port));
+    for _ in 0..3 {
+        let mut smtp = pool.get().await.unwrap();
+        smtp.send_mail("me@example.org", ["you@example.com"].iter(), b"hi\r\n")
+            .await
+            .unwrap();
+        assert_eq!(pool.metrics().in_use, 1);
+    }
+    assert_eq!(
+        pool.metrics(),
+        PoolMetrics {
+            in_use: 0,
+            idle: 1,
+            created: 1,
+            evicted: 0,
+        }
+    );
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+    // checked before every reuse
+    assert_eq!(noops.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_grows_up_to_max() {
+    let (port, connections, _) = server(false).await;
+    let pool = SmtpPool::new(move || connect(port)).with_max_connections(2);
+    let first = pool.get().await.unwrap();
+    let second = pool.get().await.unwrap();
+    assert_eq!(pool.metrics().in_use, 2);
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+
+    // a third waits for one of the others
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), pool.get())
+            .await
+            .is_err()
+    );
+    drop(first);
+    let third = tokio::time::timeout(Duration::from_secs(5), pool.get())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+    drop((second, third));
+    assert_eq!(pool.metrics().idle, 2);
+}
+
+#[tokio::test]
+async fn test_maintain_shrinks_and_warms() {
+    let (port, connections, _) = server(false).await;
+    let pool = SmtpPool::new(move || connect(port))
+        .with_min_connections(1)
+        .with_idle_timeout(Duration::from_secs(60));
+
+    // the minimum is opened ahead of time
+    pool.maintain().await.unwrap();
+    assert_eq!(pool.metrics().idle, 1);
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+    let sessions = [pool.get().await.unwrap(), pool.get().await.unwrap()];
+    drop(sessions);
+    assert_eq!(pool.metrics().idle, 2);
+    // not idle for long enough
+    pool.maintain().await.unwrap();
+    assert_eq!(pool.metrics().idle, 2);
+
+    skip(Duration::from_secs(61)).await;
+    pool.maintain().await.unwrap();
+    assert_eq!(
+        pool.metrics(),
+        PoolMetrics {
+            in_use: 0,
+            idle: 1,
+            created: 2,
+            evicted: 1,
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_closed_session_is_replaced() {
+    let (port, connections, _) = server(true).await;
+    let pool = SmtpPool::new(move || connect(port));
+    drop(pool.get().await.unwrap());
+    assert_eq!(pool.metrics().idle, 1);
+
+    let mut smtp = pool.get().await.unwrap();
+    smtp.noop().await.unwrap();
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+    assert_eq!(pool.metrics().evicted, 1);
+
+    // a detached session doesn't come back
+    let _detached = smtp.detach();
+    assert_eq!(
+        pool.metrics(),
+        PoolMetrics {
+            in_use: 0,
+            idle: 0,
+            created: 2,
+            evicted: 1,
+        }
+    );
+}
+
+// issues numbered tokens valid for `lifetime`, padded to `len` bytes, and counts them
+struct Tokens {
+    lifetime: Duration,
+    len: usize,
+    fetched: Arc<AtomicUsize>,
+}
+
+impl TokenSource for Tokens {
+    type Error = io::Error;
+
+    async fn fetch(&self) -> Result<AccessToken, io::Error> {
+        let number = self.fetched.fetch_add(1, Ordering::SeqCst) + 1;
+        Ok(AccessToken {
+            username: "me@example.org".to_string(),
+            token: format!("{:t<1$}", format!("token{number}"), self.len),
+            expires_at: Instant::now() + self.lifetime,
+        })
+    }
+}
+
+// tokens valid for an hour, replaced after 55 minutes
+fn token_cache() -> (TokenCache<Tokens>, Arc<AtomicUsize>) {
+    token_cache_with(0, Duration::from_secs(60 * 60), Duration::from_secs(5 * 60))
+}
+
+fn token_cache_with(
+    len: usize,
+    lifetime: Duration,
+    margin: Duration,
+) -> (TokenCache<Tokens>, Arc<AtomicUsize>) {
+    let fetched = Arc::new(AtomicUsize::new(0));
+    let tokens = Tokens {
+        lifetime,
+        len,
+        fetched: fetched.clone(),
+    };
+    let cache = TokenCache::new(tokens).with_refresh_margin(margin);
+    (cache, fetched)
+}
+
+async fn connect_oauth(
+    port: u16,
+    tokens: Arc<TokenCache<Tokens>>,
+) -> Result<ClientSession, Error<io::Error>> {
+    SmtpClientBuilder::new("127.0.0.1")
+        .with_port(port)
+        .with_tls(TlsMode::None)
+        .with_ehlo_domain("client.example.org")
+        .allow_plaintext_auth(true)
+        .connect_with_credentials(&*tokens)
+        .await
+}
+
+#[test]
+fn test_pool_is_send_sync() {
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    // shared between tasks as an `Arc<SmtpPool<_>>`, with or without an expiry
+    let pool = SmtpPool::new(move || connect(25));
+    assert_send_sync(&pool);
+    let tokens = Arc::new(token_cache().0);
+    let pool = SmtpPool::new({
+        let tokens = tokens.clone();
+        move || connect_oauth(25, tokens.clone())
+    })
+    .with_expiry(move || tokens.refresh_at());
+    assert_send_sync(&pool);
+}
+
+#[tokio::test]
+async fn test_token_cache() {
+    let (tokens, fetched) = token_cache();
+    assert_eq!(tokens.refresh_at(), None);
+    let first = tokens.credentials().await.unwrap();
+    assert_eq!(tokens.credentials().await.unwrap(), first);
+    assert_eq!(fetched.load(Ordering::SeqCst), 1);
+
+    // replaced ahead of its expiry
+    skip(Duration::from_secs(56 * 60)).await;
+    let second = tokens.credentials().await.unwrap();
+    assert_ne!(second, first);
+    assert_eq!(fetched.load(Ordering::SeqCst), 2);
+
+    // a rejected token is forgotten
+    tokens.refresh().await.unwrap();
+    assert_eq!(tokens.refresh_at(), None);
+    tokens.credentials().await.unwrap();
+    assert_eq!(fetched.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_sessions_replaced_before_tokens_expire() {
+    let (port, connections, _) = server(false).await;
+    let (tokens, fetched) = token_cache();
+    let tokens = Arc::new(tokens);
+    let pool = SmtpPool::new({
+        let tokens = tokens.clone();
+        move || connect_oauth(port, tokens.clone())
+    })
+    .with_min_connections(1)
+    .with_expiry({
+        let tokens = tokens.clone();
+        move || tokens.refresh_at()
+    });
+    pool.maintain().await.unwrap();
+    drop(pool.get().await.unwrap());
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+    // the minimum is replaced too
+    skip(Duration::from_secs(56 * 60)).await;
+    pool.maintain().await.unwrap();
+    assert_eq!(
+        pool.metrics(),
+        PoolMetrics {
+            in_use: 0,
+            idle: 1,
+            created: 2,
+            evicted: 1,
+        }
+    );
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+    assert_eq!(fetched.load(Ordering::SeqCst), 2);
+
+    // an expiring session isn't handed out
+    skip(Duration::from_secs(56 * 60)).await;
+    let mut smtp = pool.get().await.unwrap();
+    smtp.noop().await.unwrap();
+    assert_eq!(connections.load(Ordering::SeqCst), 3);
+    assert_eq!(fetched.load(Ordering::SeqCst), 3);
+    assert_eq!(pool.metrics().evicted, 2);
+}
+
+#[tokio::test]
+async fn test_sessions_replaced_with_long_tokens() {
+    let (port, connections, _) = server(false).await;
+    // longer than the session buffer once encoded
+    let (tokens, fetched) = token_cache_with(
+        1500,
+        Duration::from_secs(60 * 60),
+        Duration::from_secs(5 * 60),
+    );
+    let tokens = Arc::new(tokens);
+    let pool = SmtpPool::new({
+        let tokens = tokens.clone();
+        move || connect_oauth(port, tokens.clone())
+    })
+    .with_min_connections(1)
+    .with_expiry({
+        let tokens = tokens.clone();
+        move || tokens.refresh_at()
+    });
+    pool.maintain().await.unwrap();
+    pool.get().await.unwrap().noop().await.unwrap();
+
+    skip(Duration::from_secs(56 * 60)).await;
+    pool.maintain().await.unwrap();
+    let mut smtp = pool.get().await.unwrap();
+    smtp.noop().await.unwrap();
+    assert!(smtp.is_usable());
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+    assert_eq!(fetched.load(Ordering::SeqCst), 2);
+    assert_eq!(pool.metrics().evicted, 1);
+}
@@ -0,0 +1,69 @@
+//! Tests for MX lookups with a custom resolver.
+#![cfg(feature = "dns")]
+
+use simple_smtp::resolver::{Resolver, lookup_mx};
+
+struct StaticResolver {
+    mx: &'static [(u16, &'static str)],
+    has_address: bool,
+}
+
+impl Resolver for StaticResolver {
+    type Error = std::convert::Infallible;
+
+    async fn mx(&self, _domain: &str) -> Result<Vec<(u16, String)>, Self::Error> {
+        Ok(self
+            .mx
+            .iter()
+            .map(|&(preference, host)| (preference, host.to_owned()))
+            .collect())
+    }
+
+    async fn has_address(&self, _domain: &str) -> Result<bool, Self::Error> {
+        Ok(self.has_address)
+    }
+}
+
+async fn lookup(mx: &'static [(u16, &'static str)], has_address: bool) -> Vec<(u16, String)> {
+    let resolver = StaticResolver { mx, has_address };
+    lookup_mx(&resolver, "example.com.").await.unwrap()
+}
+
+fn owned(records: &[(u16, &str)]) -> Vec<(u16, String)> {
+    records
+        .iter()
+        .map(|&(preference, host)| (preference, host.to_owned()))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_lookup_mx_sorts_by_preference() {
+    let records = lookup(
+        &[
+            (20, "backup.example.com."),
+            (10, "mx1.example.com."),
+            (10, "mx2.example.com"),
+        ],
+        true,
+    )
+    .await;
+    assert_eq!(
+        records,
+        owned(&[
+            (10, "mx1.example.com"),
+            (10, "mx2.example.com"),
+            (20, "backup.example.com"),
+        ])
+    );
+}
+
+#[tokio::test]
+async fn test_lookup_mx_implicit_mx() {
+    assert_eq!(lookup(&[], true).await, owned(&[(0, "example.com")]));
+    assert_eq!(lookup(&[], false).await, owned(&[]));
+}
+
+#[tokio::test]
+async fn test_lookup_mx_null_mx() {
+    assert_eq!(lookup(&[(0, ".")], true).await, owned(&[]));
+}
@@ -0,0 +1,55 @@
+//! Tests for trusting a private CA, against a server with a certificate issued by it.
+#![cfg(all(feature = "tokio", feature = "rustls"))]
+
+mod common;
+
+use rustls::pki_types::CertificateDer;
+use simple_smtp::integrations::tokio::{RootCertificates, connect_tls, connect_tls_with_config};
+use tokio::io::{AsyncReadExt, DuplexStream};
+
+const CA_PEM: &[u8] = include_bytes!("data/ca-cert.pem");
+
+// mail.example.com, issued by the CA in ca-cert.pem
+async fn private_ca_server(stream: DuplexStream) -> std::io::Result<()> {
+    let cert = CertificateDer::from(&include_bytes!("data/server-cert.der")[..]);
+    let key = include_bytes!("data/server-key.der");
+    common::tls_server(stream, vec![cert], key, "220 mail.example.com ESMTP\r\n").await
+}
+
+#[tokio::test]
+async fn test_private_ca_is_trusted() {
+    let roots = RootCertificates::new().add_pem(CA_PEM).unwrap();
+    let (client, server) = tokio::io::duplex(16 * 1024);
+    let server = tokio::spawn(private_ca_server(server));
+    let config = roots.client_config(None).unwrap();
+    let mut tls = connect_tls_with_config(client, "mail.example.com", config)
+        .await
+        .unwrap();
+    let mut greeting = String::new();
+    tls.0.read_to_string(&mut greeting).await.unwrap();
+    assert_eq!(greeting, "220 mail.example.com ESMTP\r\n");
+    server.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_private_ca_is_not_trusted_by_default() {
+    let (client, server) = tokio::io::duplex(16 * 1024);
+    let server = tokio::spawn(private_ca_server(server));
+    let Err(err) = connect_tls(client, "mail.example.com", None).await else {
+        panic!("the CA isn't one of the webpki roots");
+    };
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(server.await.unwrap().is_err());
+}
+
+#[test]
+fn test_invalid_root_certificates() {
+    let err = RootCertificates::new()
+        .add_pem(b"not a certificate")
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    let err = RootCertificates::new()
+        .add_der(b"\x30\x03\x02\x01\x00")
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,94 @@
+//! Tests for the capturing transports, driven through a regular `Smtp` session.
+
+use simple_smtp::{
+    Smtp,
+    envelope::{Envelope, Recipient},
+    transport::{FileTransport, MemoryTransport, StoredSubmission},
+};
+
+#[tokio::test]
+async fn test_file_transport_writes_eml_and_envelope() {
+    let dir =
+        std::env::temp_dir().join(format!("simple-smtp-file-transport-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let mut smtp = Smtp::new(FileTransport::new(&dir).unwrap());
+    // nothing leaves the process
+    smtp.set_allow_plaintext_auth(true);
+    smtp.ready().await.unwrap();
+    smtp.ehlo("localhost").await.unwrap();
+    smtp.auth("user", "pass").await.unwrap();
+    let recipients = [
+        Recipient::new("alice@example.com"),
+        Recipient::new("bob@example.com"),
+    ];
+    smtp.send_envelope(
+        &Envelope::new("me@example.com", &recipients),
+        b"Subject: hi\r\n\r\nhello\r\n",
+    )
+    .await
+    .unwrap();
+    smtp.quit().await.unwrap();
+
+    let mut files: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    files.sort();
+    assert_eq!(files.len(), 2);
+    // "<id>.eml" sorts before "<id>.envelope"
+    let envelope = std::fs::read_to_string(&files[1]).unwrap();
+    assert!(files[1].extension().unwrap() == "envelope");
+    assert_eq!(
+        envelope,
+        "simple-smtp-submission v1\r\nMAIL FROM:<me@example.com>\r\n\
+         RCPT TO:<alice@example.com>\r\nRCPT TO:<bob@example.com>\r\n\r\n"
+    );
+    let message = std::fs::read(&files[0]).unwrap();
+    assert_eq!(message, b"Subject: hi\r\n\r\nhello\r\n");
+    // together they are a stored submission
+    let stored = StoredSubmission::decode(&[envelope.as_bytes(), &message].concat()).unwrap();
+    assert_eq!(
+        stored.envelope.recipients,
+        ["alice@example.com", "bob@example.com"]
+    );
+    assert_eq!(stored.message, message);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_memory_transport_records_messages() {
+    let transport = MemoryTransport::new();
+    let outbox = transport.outbox();
+
+    let mut smtp = Smtp::new(transport);
+    smtp.ready().await.unwrap();
+    smtp.ehlo("localhost").await.unwrap();
+    smtp.send_mail(
+        "me@example.com",
+        ["alice@example.com"].iter(),
+        b"Subject: first\r\n\r\nhello\r\n",
+    )
+    .await
+    .unwrap();
+    smtp.send_mail(
+        "me@example.com",
+        ["alice@example.com", "bob@example.com"].iter(),
+        b"Subject: second\r\n\r\n..dotted\r\n",
+    )
+    .await
+    .unwrap();
+    smtp.quit().await.unwrap();
+
+    assert_eq!(outbox.len(), 2);
+    assert_eq!(outbox.sent_to("alice@example.com").len(), 2);
+    let to_bob = outbox.sent_to("bob@example.com");
+    assert_eq!(to_bob.len(), 1);
+    assert_eq!(to_bob[0].subject().as_deref(), Some("second"));
+    assert_eq!(
+        outbox.with_subject("first")[0].envelope.from,
+        "me@example.com"
+    );
+    assert!(outbox.sent_to("carol@example.com").is_empty());
+}